@@ -1,11 +1,12 @@
 //! CPAL-based audio output backend.
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Device, Stream, StreamConfig};
-use ringbuf::traits::{Consumer, Producer, Split};
+use cpal::{BufferSize, Device, Stream, StreamConfig};
+use ringbuf::traits::{Consumer, Observer, Producer, Split};
 use ringbuf::{HeapCons, HeapProd, HeapRb};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use crate::traits::{AudioError, AudioOutput};
 
@@ -15,6 +16,8 @@ pub struct CpalOutput {
     config: StreamConfig,
     stream: Option<Stream>,
     producer: HeapProd<f32>,
+    /// Ring buffer capacity in frames (interleaved stereo).
+    ring_frames: u32,
     running: Arc<AtomicBool>,
 }
 
@@ -44,6 +47,7 @@ impl CpalOutput {
             config,
             stream: None,
             producer,
+            ring_frames: (buffer_size / 2) as u32,
             running: Arc::new(AtomicBool::new(false)),
         };
 
@@ -93,6 +97,18 @@ impl AudioOutput for CpalOutput {
         self.config.sample_rate.0
     }
 
+    /// Ring buffer depth plus the device buffer (when the host reports one).
+    ///
+    /// The render thread keeps the ring buffer full, so its capacity is the
+    /// steady-state queueing delay.
+    fn latency_frames(&self) -> u32 {
+        let device_frames = match self.config.buffer_size {
+            BufferSize::Fixed(frames) => frames,
+            BufferSize::Default => 0,
+        };
+        self.ring_frames + device_frames
+    }
+
     fn write(&mut self, data: &[f32]) {
         let mut offset = 0;
         while offset < data.len() {
@@ -104,6 +120,22 @@ impl AudioOutput for CpalOutput {
         }
     }
 
+    /// Gives up after twice the ring's length, in case the device stalled.
+    fn drain(&mut self) {
+        if self.stream.is_none() || !self.running.load(Ordering::Relaxed) {
+            return;
+        }
+        let ring_secs = self.ring_frames as f64 / self.config.sample_rate.0 as f64;
+        let deadline = Instant::now() + Duration::from_secs_f64(ring_secs * 2.0);
+        while !self.producer.is_empty() {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            std::thread::park_timeout(deadline - now);
+        }
+    }
+
     fn start(&mut self) -> Result<(), AudioError> {
         self.running.store(true, Ordering::Relaxed);
         if let Some(ref stream) = self.stream {
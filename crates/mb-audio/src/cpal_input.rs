@@ -0,0 +1,105 @@
+//! CPAL-based audio input (capture) backend.
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, FromSample, Sample, SampleFormat, SizedSample, Stream, StreamConfig};
+use ringbuf::traits::{Observer, Producer, Split};
+use ringbuf::{HeapCons, HeapProd, HeapRb};
+
+use crate::traits::{AudioError, AudioInput};
+
+/// Names of the host's input devices, for `CpalInput::open`.
+pub fn input_device_names() -> Vec<String> {
+    let Ok(devices) = cpal::default_host().input_devices() else { return Vec::new() };
+    devices.filter_map(|d| d.name().ok()).collect()
+}
+
+/// CPAL-based audio input.
+pub struct CpalInput {
+    stream: Stream,
+    sample_rate: u32,
+}
+
+impl CpalInput {
+    /// Open an input device by name (`None` = the default device) and start
+    /// capturing.
+    ///
+    /// Captured audio arrives in the returned consumer as interleaved stereo
+    /// f32: mono devices are copied to both sides and wider devices
+    /// contribute their first two channels. Frames arriving while the ring
+    /// buffer is full are dropped.
+    pub fn open(device_name: Option<&str>) -> Result<(Self, HeapCons<f32>), AudioError> {
+        let device = find_device(device_name)?;
+        let supported = device
+            .default_input_config()
+            .map_err(|e| AudioError::DeviceInit(e.to_string()))?;
+        let format = supported.sample_format();
+        let config: StreamConfig = supported.into();
+
+        // About one second of interleaved stereo
+        let rb = HeapRb::<f32>::new(config.sample_rate.0 as usize * 2);
+        let (producer, consumer) = rb.split();
+
+        let stream = match format {
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, producer),
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, producer),
+            SampleFormat::I32 => build_stream::<i32>(&device, &config, producer),
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, producer),
+            other => return Err(AudioError::StreamCreate(format!("unsupported sample format {}", other))),
+        }?;
+        stream.play().map_err(|e| AudioError::Playback(e.to_string()))?;
+
+        Ok((Self { stream, sample_rate: config.sample_rate.0 }, consumer))
+    }
+}
+
+fn find_device(name: Option<&str>) -> Result<Device, AudioError> {
+    let host = cpal::default_host();
+    let Some(name) = name else {
+        return host.default_input_device().ok_or(AudioError::NoDevice);
+    };
+    host.input_devices()
+        .map_err(|e| AudioError::DeviceInit(e.to_string()))?
+        .find(|d| d.name().is_ok_and(|n| n == name))
+        .ok_or(AudioError::NoDevice)
+}
+
+fn build_stream<T>(device: &Device, config: &StreamConfig, mut producer: HeapProd<f32>) -> Result<Stream, AudioError>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let channels = config.channels.max(1) as usize;
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                for frame in data.chunks_exact(channels) {
+                    // Push whole frames only, so the sides never swap
+                    if producer.vacant_len() < 2 {
+                        break;
+                    }
+                    let left = f32::from_sample(frame[0]);
+                    let right = frame.get(1).map_or(left, |&s| f32::from_sample(s));
+                    let _ = producer.try_push(left);
+                    let _ = producer.try_push(right);
+                }
+            },
+            |err| eprintln!("Audio input error: {}", err),
+            None,
+        )
+        .map_err(|e| AudioError::StreamCreate(e.to_string()))
+}
+
+impl AudioInput for CpalInput {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn start(&mut self) -> Result<(), AudioError> {
+        self.stream.play().map_err(|e| AudioError::Playback(e.to_string()))
+    }
+
+    fn stop(&mut self) -> Result<(), AudioError> {
+        self.stream.pause().map_err(|e| AudioError::Playback(e.to_string()))
+    }
+}
@@ -1,7 +1,11 @@
-//! Audio output backends for masterblaster tracker.
+//! Audio output and input backends (and MIDI ports) for masterblaster tracker.
 
 mod cpal_backend;
+mod cpal_input;
+mod midi;
 mod traits;
 
 pub use cpal_backend::CpalOutput;
-pub use traits::{AudioError, AudioOutput};
+pub use cpal_input::{input_device_names, CpalInput};
+pub use midi::{midi_input_names, midi_output_names, MidiIn, MidiMessage, MidiOut, MIDI_MESSAGE_MAX};
+pub use traits::{AudioError, AudioInput, AudioOutput};
@@ -0,0 +1,102 @@
+//! MIDI ports (midir backend).
+
+use std::time::Instant;
+
+use midir::{Ignore, MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
+use ringbuf::traits::{Producer, Split};
+use ringbuf::{HeapCons, HeapRb};
+
+use crate::traits::AudioError;
+
+/// Client name the ports are opened under.
+const CLIENT_NAME: &str = "masterblaster";
+
+/// Received messages buffered until the consumer reads them.
+const INPUT_RING_CAPACITY: usize = 1024;
+
+/// Longest message kept whole (an MTC full-frame SysEx is 10 bytes).
+pub const MIDI_MESSAGE_MAX: usize = 16;
+
+/// Names of the host's MIDI output ports, for `MidiOut::open`.
+pub fn midi_output_names() -> Vec<String> {
+    let Ok(midi) = MidiOutput::new(CLIENT_NAME) else { return Vec::new() };
+    midi.ports().iter().filter_map(|p| midi.port_name(p).ok()).collect()
+}
+
+/// Names of the host's MIDI input ports, for `MidiIn::open`.
+pub fn midi_input_names() -> Vec<String> {
+    let Ok(midi) = MidiInput::new(CLIENT_NAME) else { return Vec::new() };
+    midi.ports().iter().filter_map(|p| midi.port_name(p).ok()).collect()
+}
+
+/// A connected MIDI output port.
+pub struct MidiOut {
+    connection: MidiOutputConnection,
+}
+
+impl MidiOut {
+    /// Connect to the output port called `name`.
+    pub fn open(name: &str) -> Result<Self, AudioError> {
+        let midi = MidiOutput::new(CLIENT_NAME).map_err(|e| AudioError::DeviceInit(e.to_string()))?;
+        let port = midi.ports().into_iter()
+            .find(|p| midi.port_name(p).is_ok_and(|n| n == name))
+            .ok_or(AudioError::NoDevice)?;
+        let connection = midi.connect(&port, CLIENT_NAME).map_err(|e| AudioError::DeviceInit(e.to_string()))?;
+        Ok(Self { connection })
+    }
+
+    /// Send one complete message now.
+    pub fn send(&mut self, message: &[u8]) -> Result<(), AudioError> {
+        self.connection.send(message).map_err(|e| AudioError::Playback(e.to_string()))
+    }
+}
+
+/// A message received on a `MidiIn` port.
+#[derive(Clone, Copy, Debug)]
+pub struct MidiMessage {
+    /// Microseconds since the epoch passed to `MidiIn::open`
+    pub micros: u64,
+    len: u8,
+    data: [u8; MIDI_MESSAGE_MAX],
+}
+
+impl MidiMessage {
+    /// The message bytes.
+    pub fn bytes(&self) -> &[u8] {
+        &self.data[..self.len as usize]
+    }
+}
+
+/// A connected MIDI input port.
+pub struct MidiIn {
+    _connection: MidiInputConnection<()>,
+}
+
+impl MidiIn {
+    /// Connect to the input port called `name`.
+    ///
+    /// Messages (including clock, time code and SysEx up to
+    /// `MIDI_MESSAGE_MAX` bytes) arrive in the returned consumer, stamped
+    /// with microseconds since `epoch` when they were received. Messages
+    /// arriving while the ring buffer is full are dropped.
+    pub fn open(name: &str, epoch: Instant) -> Result<(Self, HeapCons<MidiMessage>), AudioError> {
+        let mut midi = MidiInput::new(CLIENT_NAME).map_err(|e| AudioError::DeviceInit(e.to_string()))?;
+        midi.ignore(Ignore::ActiveSense);
+        let port = midi.ports().into_iter()
+            .find(|p| midi.port_name(p).is_ok_and(|n| n == name))
+            .ok_or(AudioError::NoDevice)?;
+        let (mut producer, consumer) = HeapRb::<MidiMessage>::new(INPUT_RING_CAPACITY).split();
+        let connection = midi
+            .connect(&port, CLIENT_NAME, move |_, bytes, _| {
+                if bytes.is_empty() || bytes.len() > MIDI_MESSAGE_MAX {
+                    return;
+                }
+                let mut data = [0; MIDI_MESSAGE_MAX];
+                data[..bytes.len()].copy_from_slice(bytes);
+                let micros = epoch.elapsed().as_micros() as u64;
+                let _ = producer.try_push(MidiMessage { micros, len: bytes.len() as u8, data });
+            }, ())
+            .map_err(|e| AudioError::DeviceInit(e.to_string()))?;
+        Ok((Self { _connection: connection }, consumer))
+    }
+}
@@ -1,4 +1,4 @@
-//! Audio output trait and error types.
+//! Audio output and input traits and error types.
 
 /// Error type for audio operations.
 #[derive(Debug)]
@@ -11,6 +11,8 @@ pub enum AudioError {
     Playback(String),
     /// No audio device available
     NoDevice,
+    /// The device is already in use (e.g. a recording is in progress)
+    Busy,
 }
 
 impl std::fmt::Display for AudioError {
@@ -20,6 +22,7 @@ impl std::fmt::Display for AudioError {
             AudioError::StreamCreate(msg) => write!(f, "Stream create error: {}", msg),
             AudioError::Playback(msg) => write!(f, "Playback error: {}", msg),
             AudioError::NoDevice => write!(f, "No audio device available"),
+            AudioError::Busy => write!(f, "Audio device busy"),
         }
     }
 }
@@ -31,12 +34,34 @@ pub trait AudioOutput {
     /// Get the sample rate.
     fn sample_rate(&self) -> u32;
 
+    /// Total output latency in frames: time between `write()` and the
+    /// samples reaching the speakers. Backends that can't tell return 0.
+    fn latency_frames(&self) -> u32 {
+        0
+    }
+
     /// Write interleaved stereo f32 samples to the output (blocking).
     fn write(&mut self, data: &[f32]);
 
+    /// Wait until everything written has gone to the device, so stopping
+    /// doesn't cut it off. Backends without a queue return at once.
+    fn drain(&mut self) {}
+
     /// Start playback.
     fn start(&mut self) -> Result<(), AudioError>;
 
     /// Stop playback.
     fn stop(&mut self) -> Result<(), AudioError>;
 }
+
+/// Trait for audio input (capture) backends.
+pub trait AudioInput {
+    /// Get the sample rate of the captured audio.
+    fn sample_rate(&self) -> u32;
+
+    /// Resume capturing.
+    fn start(&mut self) -> Result<(), AudioError>;
+
+    /// Pause capturing.
+    fn stop(&mut self) -> Result<(), AudioError>;
+}
@@ -0,0 +1,635 @@
+//! The CLAP ABI around `Instrument`.
+//!
+//! Exports `clap_entry` with a factory for one plugin, which provides the
+//! params, audio-ports, state and preset-load extensions. Parameter ids
+//! are their indices. Processing takes the instrument lock with `try_lock`,
+//! so a block that races a main-thread state load renders silence rather
+//! than waiting.
+
+use std::ffi::{c_char, c_void, CStr};
+use std::ptr;
+use std::sync::{Mutex, MutexGuard};
+
+use clap_sys::entry::clap_plugin_entry;
+use clap_sys::events::{
+    clap_event_param_value, clap_event_transport, clap_input_events, clap_output_events, CLAP_CORE_EVENT_SPACE_ID,
+    CLAP_EVENT_PARAM_VALUE, CLAP_TRANSPORT_HAS_BEATS_TIMELINE, CLAP_TRANSPORT_HAS_TEMPO, CLAP_TRANSPORT_IS_PLAYING,
+};
+use clap_sys::ext::audio_ports::{
+    clap_audio_port_info, clap_plugin_audio_ports, CLAP_AUDIO_PORT_IS_MAIN, CLAP_EXT_AUDIO_PORTS, CLAP_PORT_STEREO,
+};
+use clap_sys::ext::params::{
+    clap_host_params, clap_param_info, clap_plugin_params, CLAP_EXT_PARAMS, CLAP_PARAM_IS_AUTOMATABLE,
+    CLAP_PARAM_IS_STEPPED, CLAP_PARAM_RESCAN_VALUES,
+};
+use clap_sys::ext::preset_load::{clap_plugin_preset_load, CLAP_EXT_PRESET_LOAD, CLAP_EXT_PRESET_LOAD_COMPAT};
+use clap_sys::ext::state::{clap_plugin_state, CLAP_EXT_STATE};
+use clap_sys::factory::plugin_factory::{clap_plugin_factory, CLAP_PLUGIN_FACTORY_ID};
+use clap_sys::factory::preset_discovery::{clap_preset_discovery_location_kind, CLAP_PRESET_DISCOVERY_LOCATION_FILE};
+use clap_sys::fixedpoint::CLAP_BEATTIME_FACTOR;
+use clap_sys::host::clap_host;
+use clap_sys::id::{clap_id, CLAP_INVALID_ID};
+use clap_sys::plugin::{clap_plugin, clap_plugin_descriptor};
+use clap_sys::plugin_features::{CLAP_PLUGIN_FEATURE_INSTRUMENT, CLAP_PLUGIN_FEATURE_STEREO};
+use clap_sys::process::{clap_process, clap_process_status, CLAP_PROCESS_CONTINUE};
+use clap_sys::stream::{clap_istream, clap_ostream};
+use clap_sys::version::CLAP_VERSION;
+
+use crate::{HostTransport, Instrument, GAIN_MAX_DB, GAIN_MIN_DB, MUTE_CHANNELS, PARAM_MASTER_GAIN, PARAM_MUTE_BASE};
+
+const PLUGIN_ID: &CStr = c"org.masterblaster.player";
+
+/// Sample rate until the host activates the plugin.
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+/// Null-terminated feature list.
+struct Features([*const c_char; 3]);
+
+// SAFETY: pointers to static strings, never written
+unsafe impl Sync for Features {}
+
+static FEATURES: Features =
+    Features([CLAP_PLUGIN_FEATURE_INSTRUMENT.as_ptr(), CLAP_PLUGIN_FEATURE_STEREO.as_ptr(), ptr::null()]);
+
+static DESCRIPTOR: clap_plugin_descriptor = clap_plugin_descriptor {
+    clap_version: CLAP_VERSION,
+    id: PLUGIN_ID.as_ptr(),
+    name: c"masterblaster".as_ptr(),
+    vendor: c"masterblaster".as_ptr(),
+    url: c"".as_ptr(),
+    manual_url: c"".as_ptr(),
+    support_url: c"".as_ptr(),
+    version: c"0.1.0".as_ptr(),
+    description: c"Plays masterblaster, MOD, IT, S3M and BMX songs in sync with the host".as_ptr(),
+    features: FEATURES.0.as_ptr(),
+};
+
+#[allow(non_upper_case_globals)]
+#[no_mangle]
+pub static clap_entry: clap_plugin_entry = clap_plugin_entry {
+    clap_version: CLAP_VERSION,
+    init: Some(entry_init),
+    deinit: Some(entry_deinit),
+    get_factory: Some(entry_get_factory),
+};
+
+static FACTORY: clap_plugin_factory = clap_plugin_factory {
+    get_plugin_count: Some(factory_plugin_count),
+    get_plugin_descriptor: Some(factory_plugin_descriptor),
+    create_plugin: Some(factory_create_plugin),
+};
+
+static PARAMS: clap_plugin_params = clap_plugin_params {
+    count: Some(params_count),
+    get_info: Some(params_get_info),
+    get_value: Some(params_get_value),
+    value_to_text: Some(params_value_to_text),
+    text_to_value: Some(params_text_to_value),
+    flush: Some(params_flush),
+};
+
+static AUDIO_PORTS: clap_plugin_audio_ports =
+    clap_plugin_audio_ports { count: Some(audio_ports_count), get: Some(audio_ports_get) };
+
+static STATE: clap_plugin_state = clap_plugin_state { save: Some(state_save), load: Some(state_load) };
+
+static PRESET_LOAD: clap_plugin_preset_load = clap_plugin_preset_load { from_location: Some(preset_from_location) };
+
+unsafe extern "C" fn entry_init(_path: *const c_char) -> bool {
+    true
+}
+
+unsafe extern "C" fn entry_deinit() {}
+
+unsafe extern "C" fn entry_get_factory(id: *const c_char) -> *const c_void {
+    // SAFETY: the host passes a NUL-terminated id
+    if unsafe { CStr::from_ptr(id) } == CLAP_PLUGIN_FACTORY_ID {
+        (&FACTORY as *const clap_plugin_factory).cast()
+    } else {
+        ptr::null()
+    }
+}
+
+unsafe extern "C" fn factory_plugin_count(_factory: *const clap_plugin_factory) -> u32 {
+    1
+}
+
+unsafe extern "C" fn factory_plugin_descriptor(
+    _factory: *const clap_plugin_factory,
+    index: u32,
+) -> *const clap_plugin_descriptor {
+    if index == 0 { &DESCRIPTOR } else { ptr::null() }
+}
+
+unsafe extern "C" fn factory_create_plugin(
+    _factory: *const clap_plugin_factory,
+    host: *const clap_host,
+    id: *const c_char,
+) -> *const clap_plugin {
+    // SAFETY: the host passes a NUL-terminated id
+    if unsafe { CStr::from_ptr(id) } != PLUGIN_ID {
+        return ptr::null();
+    }
+    let plugin = Box::into_raw(Box::new(Plugin {
+        clap: clap_plugin {
+            desc: &DESCRIPTOR,
+            plugin_data: ptr::null_mut(),
+            init: Some(plugin_init),
+            destroy: Some(plugin_destroy),
+            activate: Some(plugin_activate),
+            deactivate: Some(plugin_deactivate),
+            start_processing: Some(plugin_start_processing),
+            stop_processing: Some(plugin_stop_processing),
+            reset: Some(plugin_reset),
+            process: Some(plugin_process),
+            get_extension: Some(plugin_get_extension),
+            on_main_thread: Some(plugin_on_main_thread),
+        },
+        host,
+        instrument: Mutex::new(Instrument::new(DEFAULT_SAMPLE_RATE)),
+    }));
+    // SAFETY: just allocated; the plugin points back at its own box
+    unsafe {
+        (*plugin).clap.plugin_data = plugin.cast();
+        &(*plugin).clap
+    }
+}
+
+/// One plugin instance, owned through `clap.plugin_data`.
+struct Plugin {
+    clap: clap_plugin,
+    host: *const clap_host,
+    instrument: Mutex<Instrument>,
+}
+
+impl Plugin {
+    /// The instance behind a plugin pointer from the host.
+    ///
+    /// # Safety
+    ///
+    /// `plugin` must come from `factory_create_plugin` and not be destroyed.
+    unsafe fn from_ptr<'a>(plugin: *const clap_plugin) -> &'a Plugin {
+        // SAFETY: per the caller
+        unsafe { &*(*plugin).plugin_data.cast::<Plugin>() }
+    }
+
+    fn instrument(&self) -> MutexGuard<'_, Instrument> {
+        self.instrument.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Tell the host every parameter may have a new value.
+    fn rescan_params(&self) {
+        // SAFETY: the host outlives its plugins; extensions are main-thread
+        // calls, as are the state and preset loads that call this
+        unsafe {
+            let Some(get_extension) = (*self.host).get_extension else { return };
+            let params = get_extension(self.host, CLAP_EXT_PARAMS.as_ptr()).cast::<clap_host_params>();
+            if let Some(rescan) = params.as_ref().and_then(|p| p.rescan) {
+                rescan(self.host, CLAP_PARAM_RESCAN_VALUES);
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn plugin_init(_plugin: *const clap_plugin) -> bool {
+    true
+}
+
+unsafe extern "C" fn plugin_destroy(plugin: *const clap_plugin) {
+    // SAFETY: the host destroys each plugin once, after its last call
+    drop(unsafe { Box::from_raw((*plugin).plugin_data.cast::<Plugin>()) });
+}
+
+unsafe extern "C" fn plugin_activate(plugin: *const clap_plugin, sample_rate: f64, _min: u32, _max: u32) -> bool {
+    // SAFETY: a live plugin from the factory
+    let plugin = unsafe { Plugin::from_ptr(plugin) };
+    plugin.instrument().set_sample_rate(sample_rate.round() as u32);
+    true
+}
+
+unsafe extern "C" fn plugin_deactivate(_plugin: *const clap_plugin) {}
+
+unsafe extern "C" fn plugin_start_processing(_plugin: *const clap_plugin) -> bool {
+    true
+}
+
+unsafe extern "C" fn plugin_stop_processing(_plugin: *const clap_plugin) {}
+
+unsafe extern "C" fn plugin_reset(plugin: *const clap_plugin) {
+    // SAFETY: a live plugin from the factory
+    let plugin = unsafe { Plugin::from_ptr(plugin) };
+    plugin.instrument().sync(&HostTransport::default());
+}
+
+unsafe extern "C" fn plugin_on_main_thread(_plugin: *const clap_plugin) {}
+
+unsafe extern "C" fn plugin_get_extension(_plugin: *const clap_plugin, id: *const c_char) -> *const c_void {
+    // SAFETY: the host passes a NUL-terminated id
+    let id = unsafe { CStr::from_ptr(id) };
+    if id == CLAP_EXT_PARAMS {
+        (&PARAMS as *const clap_plugin_params).cast()
+    } else if id == CLAP_EXT_AUDIO_PORTS {
+        (&AUDIO_PORTS as *const clap_plugin_audio_ports).cast()
+    } else if id == CLAP_EXT_STATE {
+        (&STATE as *const clap_plugin_state).cast()
+    } else if id == CLAP_EXT_PRESET_LOAD || id == CLAP_EXT_PRESET_LOAD_COMPAT {
+        (&PRESET_LOAD as *const clap_plugin_preset_load).cast()
+    } else {
+        ptr::null()
+    }
+}
+
+unsafe extern "C" fn plugin_process(plugin: *const clap_plugin, process: *const clap_process) -> clap_process_status {
+    // SAFETY: a live plugin and a process call whose pointers are valid for its duration
+    let (plugin, process) = unsafe { (Plugin::from_ptr(plugin), &*process) };
+    let frames = process.frames_count as usize;
+    // SAFETY: the host provides `audio_outputs_count` output buffers
+    let output = unsafe { (process.audio_outputs_count > 0).then(|| &*process.audio_outputs) };
+    let channels: &[*mut f32] = match output {
+        // SAFETY: `data32` holds `channel_count` channel pointers
+        Some(o) if !o.data32.is_null() => unsafe { std::slice::from_raw_parts(o.data32, o.channel_count as usize) },
+        _ => &[],
+    };
+    // SAFETY: each channel pointer addresses `frames` samples
+    let mut channels = channels.iter().map(|&ch| unsafe { std::slice::from_raw_parts_mut(ch, frames) });
+
+    let Ok(mut instrument) = plugin.instrument.try_lock() else {
+        channels.for_each(|ch| ch.fill(0.0));
+        return CLAP_PROCESS_CONTINUE;
+    };
+    // SAFETY: the event list is valid for the process call
+    unsafe { apply_param_events(&mut instrument, process.in_events) };
+    // SAFETY: null or valid for the process call
+    if let Some(transport) = unsafe { process.transport.as_ref() } {
+        instrument.sync(&host_transport(transport));
+    }
+    match (channels.next(), channels.next()) {
+        (Some(left), Some(right)) => instrument.render(left, right),
+        (Some(mono), None) => mono.fill(0.0),
+        _ => {}
+    }
+    CLAP_PROCESS_CONTINUE
+}
+
+fn host_transport(transport: &clap_event_transport) -> HostTransport {
+    let flags = transport.flags;
+    HostTransport {
+        playing: flags & CLAP_TRANSPORT_IS_PLAYING != 0,
+        tempo: (flags & CLAP_TRANSPORT_HAS_TEMPO != 0).then_some(transport.tempo),
+        beats: (flags & CLAP_TRANSPORT_HAS_BEATS_TIMELINE != 0)
+            .then(|| transport.song_pos_beats as f64 / CLAP_BEATTIME_FACTOR as f64),
+    }
+}
+
+/// Apply the parameter changes in `events` (at block start).
+///
+/// # Safety
+///
+/// `events` must be null or a valid CLAP input event list.
+unsafe fn apply_param_events(instrument: &mut Instrument, events: *const clap_input_events) {
+    // SAFETY: per the caller
+    let Some(events) = (unsafe { events.as_ref() }) else { return };
+    let (Some(size), Some(get)) = (events.size, events.get) else { return };
+    // SAFETY: list calls on a valid list; events are valid until it's dropped
+    for i in 0..unsafe { size(events) } {
+        let Some(header) = (unsafe { get(events, i).as_ref() }) else { continue };
+        if header.space_id == CLAP_CORE_EVENT_SPACE_ID && header.type_ == CLAP_EVENT_PARAM_VALUE {
+            let event = unsafe { &*(header as *const _ as *const clap_event_param_value) };
+            instrument.set_param(event.param_id, event.value);
+        }
+    }
+}
+
+/// Copy `text` into a C string buffer of `capacity` bytes, truncating.
+///
+/// # Safety
+///
+/// `buf` must be writable for `capacity` bytes.
+unsafe fn write_c_str(text: &str, buf: *mut c_char, capacity: usize) {
+    if capacity == 0 {
+        return;
+    }
+    let len = text.len().min(capacity - 1);
+    // SAFETY: per the caller, `len + 1 <= capacity`
+    unsafe {
+        ptr::copy_nonoverlapping(text.as_ptr().cast::<c_char>(), buf, len);
+        *buf.add(len) = 0;
+    }
+}
+
+unsafe extern "C" fn params_count(_plugin: *const clap_plugin) -> u32 {
+    PARAM_MUTE_BASE + MUTE_CHANNELS
+}
+
+unsafe extern "C" fn params_get_info(_plugin: *const clap_plugin, index: u32, info: *mut clap_param_info) -> bool {
+    if index >= PARAM_MUTE_BASE + MUTE_CHANNELS {
+        return false;
+    }
+    // SAFETY: the host passes a writable info struct
+    let info = unsafe { &mut *info };
+    info.id = index;
+    info.cookie = ptr::null_mut();
+    let (name, module) = if index == PARAM_MASTER_GAIN {
+        info.flags = CLAP_PARAM_IS_AUTOMATABLE;
+        (info.min_value, info.max_value, info.default_value) = (GAIN_MIN_DB, GAIN_MAX_DB, 0.0);
+        ("Master Gain".to_string(), "Master")
+    } else {
+        info.flags = CLAP_PARAM_IS_AUTOMATABLE | CLAP_PARAM_IS_STEPPED;
+        (info.min_value, info.max_value, info.default_value) = (0.0, 1.0, 0.0);
+        (format!("Mute Channel {}", index - PARAM_MUTE_BASE + 1), "Channels")
+    };
+    // SAFETY: fixed-size buffers in `info`
+    unsafe {
+        write_c_str(&name, info.name.as_mut_ptr(), info.name.len());
+        write_c_str(module, info.module.as_mut_ptr(), info.module.len());
+    }
+    true
+}
+
+unsafe extern "C" fn params_get_value(plugin: *const clap_plugin, id: clap_id, value: *mut f64) -> bool {
+    // SAFETY: a live plugin from the factory
+    let plugin = unsafe { Plugin::from_ptr(plugin) };
+    let Some(v) = plugin.instrument().param(id) else { return false };
+    // SAFETY: the host passes a writable value
+    unsafe { *value = v };
+    true
+}
+
+unsafe extern "C" fn params_value_to_text(
+    _plugin: *const clap_plugin,
+    id: clap_id,
+    value: f64,
+    display: *mut c_char,
+    size: u32,
+) -> bool {
+    let text = match id {
+        PARAM_MASTER_GAIN if value <= GAIN_MIN_DB => "-inf dB".to_string(),
+        PARAM_MASTER_GAIN => format!("{:+.1} dB", value),
+        _ if id < PARAM_MUTE_BASE + MUTE_CHANNELS => (if value >= 0.5 { "Muted" } else { "On" }).to_string(),
+        _ => return false,
+    };
+    // SAFETY: the host passes a buffer of `size` bytes
+    unsafe { write_c_str(&text, display, size as usize) };
+    true
+}
+
+unsafe extern "C" fn params_text_to_value(
+    _plugin: *const clap_plugin,
+    id: clap_id,
+    display: *const c_char,
+    value: *mut f64,
+) -> bool {
+    // SAFETY: the host passes a NUL-terminated string
+    let Ok(text) = unsafe { CStr::from_ptr(display) }.to_str() else { return false };
+    let text = text.trim();
+    let parsed = match id {
+        PARAM_MASTER_GAIN if text.starts_with("-inf") => Some(GAIN_MIN_DB),
+        PARAM_MASTER_GAIN => text.trim_end_matches("dB").trim().parse().ok(),
+        _ if id < PARAM_MUTE_BASE + MUTE_CHANNELS => match text {
+            "Muted" | "1" => Some(1.0),
+            "On" | "0" => Some(0.0),
+            _ => None,
+        },
+        _ => None,
+    };
+    let Some(v) = parsed else { return false };
+    // SAFETY: the host passes a writable value
+    unsafe { *value = v };
+    true
+}
+
+unsafe extern "C" fn params_flush(
+    plugin: *const clap_plugin,
+    in_events: *const clap_input_events,
+    _out_events: *const clap_output_events,
+) {
+    // SAFETY: a live plugin from the factory, and a valid event list
+    let plugin = unsafe { Plugin::from_ptr(plugin) };
+    unsafe { apply_param_events(&mut plugin.instrument(), in_events) };
+}
+
+unsafe extern "C" fn audio_ports_count(_plugin: *const clap_plugin, is_input: bool) -> u32 {
+    if is_input { 0 } else { 1 }
+}
+
+unsafe extern "C" fn audio_ports_get(
+    _plugin: *const clap_plugin,
+    index: u32,
+    is_input: bool,
+    info: *mut clap_audio_port_info,
+) -> bool {
+    if is_input || index != 0 {
+        return false;
+    }
+    // SAFETY: the host passes a writable info struct
+    let info = unsafe { &mut *info };
+    info.id = 0;
+    info.flags = CLAP_AUDIO_PORT_IS_MAIN;
+    info.channel_count = 2;
+    info.port_type = CLAP_PORT_STEREO.as_ptr();
+    info.in_place_pair = CLAP_INVALID_ID;
+    // SAFETY: fixed-size buffer in `info`
+    unsafe { write_c_str("Main", info.name.as_mut_ptr(), info.name.len()) };
+    true
+}
+
+unsafe extern "C" fn state_save(plugin: *const clap_plugin, stream: *const clap_ostream) -> bool {
+    // SAFETY: a live plugin from the factory, and a valid stream
+    let (plugin, stream) = unsafe { (Plugin::from_ptr(plugin), &*stream) };
+    let Some(write) = stream.write else { return false };
+    let data = plugin.instrument().save_state();
+    let mut rest = data.as_slice();
+    while !rest.is_empty() {
+        // SAFETY: writes from a live slice of `rest.len()` bytes
+        let written = unsafe { write(stream, rest.as_ptr().cast(), rest.len() as u64) };
+        if written <= 0 {
+            return false;
+        }
+        rest = &rest[written as usize..];
+    }
+    true
+}
+
+unsafe extern "C" fn state_load(plugin: *const clap_plugin, stream: *const clap_istream) -> bool {
+    // SAFETY: a live plugin from the factory, and a valid stream
+    let (plugin, stream) = unsafe { (Plugin::from_ptr(plugin), &*stream) };
+    let Some(read) = stream.read else { return false };
+    let mut data = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        // SAFETY: reads into a live buffer of `chunk.len()` bytes
+        let n = unsafe { read(stream, chunk.as_mut_ptr().cast(), chunk.len() as u64) };
+        match n {
+            0 => break,
+            n if n < 0 => return false,
+            n => data.extend_from_slice(&chunk[..n as usize]),
+        }
+    }
+    let loaded = plugin.instrument().load_state(&data).is_ok();
+    if loaded {
+        plugin.rescan_params();
+    }
+    loaded
+}
+
+unsafe extern "C" fn preset_from_location(
+    plugin: *const clap_plugin,
+    location_kind: clap_preset_discovery_location_kind,
+    location: *const c_char,
+    _load_key: *const c_char,
+) -> bool {
+    if location_kind != CLAP_PRESET_DISCOVERY_LOCATION_FILE || location.is_null() {
+        return false;
+    }
+    // SAFETY: a live plugin from the factory, and a NUL-terminated path
+    let (plugin, path) = unsafe { (Plugin::from_ptr(plugin), CStr::from_ptr(location)) };
+    let Ok(path) = path.to_str() else { return false };
+    let Ok(data) = std::fs::read(path) else { return false };
+    let extension = std::path::Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("");
+    let loaded = plugin.instrument().load(&data, extension).is_ok();
+    if loaded {
+        plugin.rescan_params();
+    }
+    loaded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap_sys::audio_buffer::clap_audio_buffer;
+    use clap_sys::events::clap_event_header;
+
+    static HOST: clap_host = clap_host {
+        clap_version: CLAP_VERSION,
+        host_data: ptr::null_mut(),
+        name: c"test".as_ptr(),
+        vendor: c"test".as_ptr(),
+        url: c"".as_ptr(),
+        version: c"0".as_ptr(),
+        get_extension: Some(host_get_extension),
+        request_restart: Some(host_request),
+        request_process: Some(host_request),
+        request_callback: Some(host_request),
+    };
+
+    unsafe extern "C" fn host_get_extension(_host: *const clap_host, _id: *const c_char) -> *const c_void {
+        ptr::null()
+    }
+
+    unsafe extern "C" fn host_request(_host: *const clap_host) {}
+
+    unsafe extern "C" fn events_size(list: *const clap_input_events) -> u32 {
+        unsafe { (*list).ctx.is_null() as u32 ^ 1 }
+    }
+
+    unsafe extern "C" fn events_get(list: *const clap_input_events, _index: u32) -> *const clap_event_header {
+        unsafe { (*list).ctx.cast() }
+    }
+
+    unsafe extern "C" fn stream_write(stream: *const clap_ostream, buffer: *const c_void, size: u64) -> i64 {
+        let data = unsafe { &mut *(*stream).ctx.cast::<Vec<u8>>() };
+        // Short writes, to exercise the write loop
+        let n = size.min(1000) as usize;
+        data.extend_from_slice(unsafe { std::slice::from_raw_parts(buffer.cast::<u8>(), n) });
+        n as i64
+    }
+
+    unsafe extern "C" fn stream_read(stream: *const clap_istream, buffer: *mut c_void, size: u64) -> i64 {
+        let data = unsafe { &mut *(*stream).ctx.cast::<&[u8]>() };
+        let n = (size as usize).min(data.len());
+        unsafe { ptr::copy_nonoverlapping(data.as_ptr(), buffer.cast::<u8>(), n) };
+        *data = &data[n..];
+        n as i64
+    }
+
+    #[test]
+    fn host_drives_the_plugin_through_the_clap_abi() {
+        unsafe {
+            assert!(clap_entry.init.unwrap()(c"".as_ptr()));
+            let factory = &*clap_entry.get_factory.unwrap()(CLAP_PLUGIN_FACTORY_ID.as_ptr()).cast::<clap_plugin_factory>();
+            assert_eq!(factory.get_plugin_count.unwrap()(factory), 1);
+            let desc = &*factory.get_plugin_descriptor.unwrap()(factory, 0);
+            let plugin = factory.create_plugin.unwrap()(factory, &HOST, desc.id);
+            let p = &*plugin;
+            assert!(p.init.unwrap()(plugin));
+            assert!(p.activate.unwrap()(plugin, 8000.0, 1, 4096));
+
+            let params = &*p.get_extension.unwrap()(plugin, CLAP_EXT_PARAMS.as_ptr()).cast::<clap_plugin_params>();
+            assert_eq!(params.count.unwrap()(plugin), 1 + MUTE_CHANNELS);
+            let mut info: clap_param_info = std::mem::zeroed();
+            assert!(params.get_info.unwrap()(plugin, 2, &mut info));
+            assert_eq!(CStr::from_ptr(info.name.as_ptr()), c"Mute Channel 2");
+
+            // Mute channel 2 through a flush, as a host does while stopped
+            let mute = clap_event_param_value {
+                header: clap_event_header {
+                    size: size_of::<clap_event_param_value>() as u32,
+                    time: 0,
+                    space_id: CLAP_CORE_EVENT_SPACE_ID,
+                    type_: CLAP_EVENT_PARAM_VALUE,
+                    flags: 0,
+                },
+                param_id: 2,
+                cookie: ptr::null_mut(),
+                note_id: -1,
+                port_index: -1,
+                channel: -1,
+                key: -1,
+                value: 1.0,
+            };
+            let events = clap_input_events {
+                ctx: (&mute as *const clap_event_param_value).cast_mut().cast(),
+                size: Some(events_size),
+                get: Some(events_get),
+            };
+            params.flush.unwrap()(plugin, &events, ptr::null());
+            let mut value = 0.0;
+            assert!(params.get_value.unwrap()(plugin, 2, &mut value));
+            assert_eq!(value, 1.0);
+
+            // Process a playing block
+            let (mut left, mut right) = (vec![1.0f32; 256], vec![1.0f32; 256]);
+            let mut channels = [left.as_mut_ptr(), right.as_mut_ptr()];
+            let mut output = clap_audio_buffer {
+                data32: channels.as_mut_ptr(),
+                data64: ptr::null_mut(),
+                channel_count: 2,
+                latency: 0,
+                constant_mask: 0,
+            };
+            let mut transport: clap_event_transport = std::mem::zeroed();
+            transport.flags = CLAP_TRANSPORT_IS_PLAYING | CLAP_TRANSPORT_HAS_TEMPO | CLAP_TRANSPORT_HAS_BEATS_TIMELINE;
+            transport.tempo = 140.0;
+            let no_events = clap_input_events { ctx: ptr::null_mut(), size: Some(events_size), get: Some(events_get) };
+            let process = clap_process {
+                steady_time: 0,
+                frames_count: 256,
+                transport: &transport,
+                audio_inputs: ptr::null(),
+                audio_outputs: &mut output,
+                audio_inputs_count: 0,
+                audio_outputs_count: 1,
+                in_events: &no_events,
+                out_events: ptr::null(),
+            };
+            assert_eq!(p.process.unwrap()(plugin, &process), CLAP_PROCESS_CONTINUE);
+            assert!(left.iter().chain(&right).all(|s| s.is_finite() && s.abs() < 4.0));
+            assert_eq!(Plugin::from_ptr(plugin).instrument().engine.transport().tempo(), 14000);
+
+            // State round-trips through the host's streams
+            let state = &*p.get_extension.unwrap()(plugin, CLAP_EXT_STATE.as_ptr()).cast::<clap_plugin_state>();
+            let mut saved = Vec::<u8>::new();
+            let out = clap_ostream { ctx: (&mut saved as *mut Vec<u8>).cast(), write: Some(stream_write) };
+            assert!(state.save.unwrap()(plugin, &out));
+            let copy = factory.create_plugin.unwrap()(factory, &HOST, desc.id);
+            let mut rest = saved.as_slice();
+            let input = clap_istream { ctx: (&mut rest as *mut &[u8]).cast(), read: Some(stream_read) };
+            assert!(state.load.unwrap()(copy, &input));
+            assert!(Plugin::from_ptr(copy).instrument().song().channels[1].muted);
+
+            (*copy).destroy.unwrap()(copy);
+            p.deactivate.unwrap()(plugin);
+            p.destroy.unwrap()(plugin);
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! masterblaster as a CLAP instrument.
+//!
+//! Built as a cdylib, this crate is a `.clap` bundle exporting one
+//! instrument that plays a masterblaster song inside a DAW. The song
+//! follows the host transport: it plays while the host plays, starts from
+//! the host's beat position (one song beat per host quarter note), and
+//! runs at the host tempo rounded to a hundredth of a BPM. Song files load through
+//! the host's preset browser, and the song travels with the DAW project
+//! as plugin state. Master gain and per-channel mutes are automatable
+//! parameters.
+//!
+//! `Instrument` is the host-agnostic core; the `entry` module wraps it in
+//! the CLAP ABI.
+
+mod entry;
+
+use mb_engine::Engine;
+use mb_formats::FormatError;
+use mb_ir::{Edit, MusicalTime, Song, BLOCK_SIZE, SUB_BEAT_UNIT};
+
+/// Master gain parameter, in dB.
+pub const PARAM_MASTER_GAIN: u32 = 0;
+/// First channel mute parameter; song channel `n` is `PARAM_MUTE_BASE + n`.
+pub const PARAM_MUTE_BASE: u32 = 1;
+/// Song channels with a mute parameter.
+pub const MUTE_CHANNELS: u32 = 32;
+
+/// Master gain range in dB; the minimum is silence.
+pub const GAIN_MIN_DB: f64 = -60.0;
+pub const GAIN_MAX_DB: f64 = 12.0;
+
+/// How far (in beats) the song may drift from the host before it's relocated.
+const RESYNC_BEATS: f64 = 0.25;
+
+const STATE_MAGIC: &[u8; 4] = b"MBCP";
+
+/// The host's transport at the start of a block.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HostTransport {
+    pub playing: bool,
+    /// Tempo in BPM, if the host reports one
+    pub tempo: Option<f64>,
+    /// Song position in quarter notes, if the host reports one
+    pub beats: Option<f64>,
+}
+
+/// A song and the engine playing it in step with a host.
+pub struct Instrument {
+    engine: Engine,
+    sample_rate: u32,
+    gain_db: f64,
+    playing: bool,
+    /// Stereo frames for one engine block, reused by every render call
+    block: Vec<[f32; 2]>,
+}
+
+impl Instrument {
+    /// An instrument with an empty song, rendering at `sample_rate`.
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            engine: Engine::new(Song::with_channels("Untitled", 4), sample_rate),
+            sample_rate,
+            gain_db: 0.0,
+            playing: false,
+            block: vec![[0.0; 2]; BLOCK_SIZE],
+        }
+    }
+
+    /// Output sample rate.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Render at `sample_rate` from now on. Rebuilds the engine (allocates);
+    /// the next playing block relocates to the host position.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        if sample_rate != self.sample_rate {
+            self.sample_rate = sample_rate;
+            self.set_song(self.engine.song().clone());
+        }
+    }
+
+    /// Load a song file, picking the parser by `extension` (e.g. "it";
+    /// anything unrecognised is tried as a MOD).
+    pub fn load(&mut self, data: &[u8], extension: &str) -> Result<(), FormatError> {
+        let song = match extension.to_ascii_lowercase().as_str() {
+            "mbp" => mb_formats::load_song(data)?,
+            "bmx" => mb_formats::load_bmx(data)?,
+            "it" => mb_formats::load_it(data)?,
+            "s3m" => mb_formats::load_s3m(data)?,
+            "mid" | "midi" => mb_formats::load_midi(data, self.engine.song().rows_per_beat)?,
+            _ => mb_formats::load_mod(data)?,
+        };
+        self.set_song(song);
+        Ok(())
+    }
+
+    /// Replace the song. It picks up the host transport on the next block.
+    pub fn set_song(&mut self, song: Song) {
+        let interpolation = self.engine.interpolation();
+        self.engine = Engine::new(song, self.sample_rate);
+        self.engine.set_interpolation(interpolation);
+        self.engine.schedule_song();
+        self.playing = false;
+    }
+
+    /// The loaded song.
+    pub fn song(&self) -> &Song {
+        self.engine.song()
+    }
+
+    /// Current value of parameter `id`, if it exists.
+    pub fn param(&self, id: u32) -> Option<f64> {
+        match id {
+            PARAM_MASTER_GAIN => Some(self.gain_db),
+            _ => {
+                let channel = self.mute_channel(id)?;
+                Some(if self.engine.song().channels.get(channel).is_some_and(|c| c.muted) { 1.0 } else { 0.0 })
+            }
+        }
+    }
+
+    /// Set parameter `id`; unknown ids are ignored.
+    pub fn set_param(&mut self, id: u32, value: f64) {
+        match id {
+            PARAM_MASTER_GAIN => self.gain_db = value.clamp(GAIN_MIN_DB, GAIN_MAX_DB),
+            _ => {
+                if let Some(channel) = self.mute_channel(id) {
+                    self.engine.apply_edits(&[Edit::SetChannelMute { channel: channel as u8, muted: value >= 0.5 }]);
+                }
+            }
+        }
+    }
+
+    /// Song channel muted by parameter `id`, if it's a mute parameter.
+    fn mute_channel(&self, id: u32) -> Option<usize> {
+        let channel = id.checked_sub(PARAM_MUTE_BASE).filter(|&c| c < MUTE_CHANNELS)?;
+        Some(channel as usize)
+    }
+
+    /// Follow the host transport. Starting playback or a jump in the host
+    /// position relocates the song (allocates, like `Engine::seek`).
+    pub fn sync(&mut self, transport: &HostTransport) {
+        if let Some(tempo) = transport.tempo {
+            let tempo = (tempo * 100.0).round().max(100.0) as u32;
+            if tempo != self.engine.transport().tempo() {
+                self.engine.apply_edits(&[Edit::SetTempo { tempo }]);
+            }
+        }
+        if transport.playing != self.playing {
+            self.playing = transport.playing;
+            if !self.playing {
+                self.engine.stop();
+                return;
+            }
+            if let Some(beats) = transport.beats {
+                self.locate(beats);
+            }
+            self.engine.play();
+        } else if let Some(beats) = transport.beats.filter(|_| self.playing) {
+            if (beats - to_beats(self.engine.position())).abs() > RESYNC_BEATS {
+                self.locate(beats);
+                self.engine.play();
+            }
+        }
+    }
+
+    fn locate(&mut self, beats: f64) {
+        let sub_beats = (beats.max(0.0) * SUB_BEAT_UNIT as f64) as u64;
+        self.engine.seek(MusicalTime::from_sub_beats(sub_beats));
+    }
+
+    /// Fill separate left and right buffers, rendering the shorter of the
+    /// two lengths and zeroing the rest. Silent while the host is stopped
+    /// or the song has ended. Doesn't allocate.
+    pub fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let frames = left.len().min(right.len());
+        let gain = if self.gain_db <= GAIN_MIN_DB { 0.0 } else { 10f64.powf(self.gain_db / 20.0) as f32 };
+        let mut done = 0;
+        while done < frames {
+            let n = (frames - done).min(BLOCK_SIZE);
+            if !self.playing || self.engine.is_finished() {
+                self.block[..n].fill([0.0; 2]);
+            } else {
+                self.engine.render_block(&mut self.block[..n]);
+            }
+            for (i, frame) in self.block[..n].iter().enumerate() {
+                left[done + i] = frame[0] * gain;
+                right[done + i] = frame[1] * gain;
+            }
+            done += n;
+        }
+        left[frames..].fill(0.0);
+        right[frames..].fill(0.0);
+    }
+
+    /// The song and parameters, for the host to store with its project.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut data = STATE_MAGIC.to_vec();
+        data.extend_from_slice(&self.gain_db.to_le_bytes());
+        data.extend_from_slice(&mb_formats::save_song(self.engine.song()));
+        data
+    }
+
+    /// Restore what `save_state` stored.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), FormatError> {
+        let rest = data.strip_prefix(STATE_MAGIC).ok_or(FormatError::InvalidHeader)?;
+        let (gain, song) = rest.split_first_chunk::<8>().ok_or(FormatError::UnexpectedEof)?;
+        self.set_song(mb_formats::load_song(song)?);
+        self.set_param(PARAM_MASTER_GAIN, f64::from_le_bytes(*gain));
+        Ok(())
+    }
+}
+
+fn to_beats(time: MusicalTime) -> f64 {
+    time.as_sub_beats() as f64 / SUB_BEAT_UNIT as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mb_ir::SongTemplate;
+
+    fn instrument() -> Instrument {
+        let mut instrument = Instrument::new(8000);
+        instrument.set_song(SongTemplate { rows: 64, ..SongTemplate::default() }.build());
+        instrument
+    }
+
+    fn playing_at(beats: f64) -> HostTransport {
+        HostTransport { playing: true, tempo: Some(125.0), beats: Some(beats) }
+    }
+
+    /// Reference: the engine driven directly from `time`.
+    fn engine_frames(time: MusicalTime, frames: usize) -> Vec<[f32; 2]> {
+        let mut engine = Engine::new(SongTemplate { rows: 64, ..SongTemplate::default() }.build(), 8000);
+        engine.seek(time);
+        engine.play();
+        engine.render_frames(frames)
+    }
+
+    fn render(instrument: &mut Instrument, frames: usize) -> Vec<[f32; 2]> {
+        let (mut left, mut right) = (vec![1.0f32; frames], vec![1.0f32; frames]);
+        instrument.render(&mut left, &mut right);
+        left.into_iter().zip(right).map(|(l, r)| [l, r]).collect()
+    }
+
+    #[test]
+    fn plays_only_while_the_host_plays_from_its_position() {
+        let mut instrument = instrument();
+        instrument.sync(&HostTransport::default());
+        assert!(render(&mut instrument, 100).iter().all(|f| *f == [0.0; 2]));
+
+        instrument.sync(&playing_at(2.0));
+        assert_eq!(render(&mut instrument, 300), engine_frames(MusicalTime::from_beats(2), 300));
+
+        // Small drift is tolerated, a jump relocates
+        instrument.sync(&playing_at(2.1));
+        assert!(instrument.engine.position() < MusicalTime::from_beats(3));
+        instrument.sync(&playing_at(8.0));
+        assert_eq!(instrument.engine.position(), MusicalTime::from_beats(8));
+
+        instrument.sync(&HostTransport { playing: false, ..playing_at(8.0) });
+        assert!(render(&mut instrument, 100).iter().all(|f| *f == [0.0; 2]));
+    }
+
+    #[test]
+    fn host_tempo_is_followed_to_a_hundredth_of_a_bpm() {
+        let mut instrument = instrument();
+        instrument.sync(&HostTransport { tempo: Some(97.625), ..HostTransport::default() });
+        assert_eq!(instrument.engine.transport().tempo(), 9763);
+        instrument.sync(&HostTransport { tempo: Some(300.0), ..HostTransport::default() });
+        assert_eq!(instrument.engine.transport().tempo(), 30000);
+    }
+
+    #[test]
+    fn gain_and_mutes_are_parameters_kept_in_the_state() {
+        let mut instrument = instrument();
+        instrument.set_param(PARAM_MASTER_GAIN, -6.0);
+        instrument.set_param(PARAM_MUTE_BASE + 1, 1.0);
+        assert_eq!(instrument.param(PARAM_MUTE_BASE + 1), Some(1.0));
+        assert!(instrument.song().channels[1].muted);
+        assert_eq!(instrument.param(PARAM_MUTE_BASE + MUTE_CHANNELS), None);
+
+        let state = instrument.save_state();
+        let mut restored = Instrument::new(8000);
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.param(PARAM_MASTER_GAIN), Some(-6.0));
+        assert!(restored.song().channels[1].muted);
+        assert!(matches!(restored.load_state(b"nope"), Err(FormatError::InvalidHeader)));
+
+        instrument.set_param(PARAM_MASTER_GAIN, GAIN_MIN_DB);
+        instrument.sync(&playing_at(0.0));
+        assert!(render(&mut instrument, 100).iter().all(|f| *f == [0.0; 2]));
+    }
+}
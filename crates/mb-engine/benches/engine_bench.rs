@@ -4,6 +4,7 @@ use criterion::{criterion_group, criterion_main, Criterion};
 use mb_engine::Engine;
 use mb_ir::{
     build_tracks, Cell, Instrument, Note, OrderEntry, Pattern, Sample, SampleData, Song,
+    SongTemplate,
 };
 
 const SAMPLE_RATE: u32 = 44100;
@@ -25,7 +26,7 @@ fn sine_wave(len: usize) -> Vec<i8> {
 /// Deterministic pseudo-random note for a given row and channel.
 fn pseudo_note(row: u16, channel: u8) -> Option<u8> {
     let hash = (row as u32).wrapping_mul(31).wrapping_add(channel as u32).wrapping_mul(17);
-    if hash % 4 == 0 {
+    if hash.is_multiple_of(4) {
         Some(36 + (hash % 48) as u8) // C-2 to B-5
     } else {
         None
@@ -54,7 +55,7 @@ fn build_bench_song(num_channels: u8, rows: u16) -> Song {
     let mut song = Song::with_channels("bench", num_channels);
 
     let mut sample = Sample::new("sine");
-    sample.data = SampleData::Mono8(sine_wave(SINE_LEN));
+    sample.set_data(SampleData::Mono8(sine_wave(SINE_LEN)));
     sample.default_volume = 64;
     sample.c4_speed = 8363;
     song.samples.push(sample);
@@ -161,10 +162,58 @@ fn bench_render_10_channels_10_passthrough(c: &mut Criterion) {
     });
 }
 
+fn bench_schedule_worst_case(c: &mut Criterion) {
+    let song = SongTemplate::worst_case().build();
+    c.bench_function("schedule_worst_case_256tracks", |b| {
+        b.iter_batched(
+            || Engine::new(song.clone(), SAMPLE_RATE),
+            |mut engine| engine.schedule_song(),
+            criterion::BatchSize::LargeInput,
+        );
+    });
+}
+
+fn bench_render_worst_case(c: &mut Criterion) {
+    let song = SongTemplate::worst_case().build();
+    c.bench_function("render_worst_case_256tracks_10x100ms", |b| {
+        b.iter_batched(
+            || setup_engine(song.clone()),
+            |mut engine| {
+                let mut buf = [[0.0f32; 2]; FRAMES_PER_CHUNK];
+                for _ in 0..10 {
+                    engine.render_block(&mut buf);
+                }
+            },
+            criterion::BatchSize::LargeInput,
+        );
+    });
+}
+
+fn bench_render_track_scaling(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render_tracks_100ms");
+    for tracks in [16u16, 64, 256] {
+        let song = SongTemplate { tracks, effect_density: 50, ..SongTemplate::default() }.build();
+        group.bench_function(tracks.to_string(), |b| {
+            b.iter_batched(
+                || setup_engine(song.clone()),
+                |mut engine| {
+                    let mut buf = [[0.0f32; 2]; FRAMES_PER_CHUNK];
+                    engine.render_block(&mut buf);
+                },
+                criterion::BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
 criterion_group!(
     benches,
     bench_render_10_channels,
     bench_render_20_channels,
     bench_render_10_channels_10_passthrough,
+    bench_schedule_worst_case,
+    bench_render_worst_case,
+    bench_render_track_scaling,
 );
 criterion_main!(benches);
@@ -1,13 +1,67 @@
 //! Channel state for tracker playback.
 
 use mb_ir::{
-    Effect, ModEnvelope, ModMode, Sample,
+    AutoVibrato, Effect, Envelope, Instrument, Interpolation, LoopType, ModEnvelope, ModMode, Sample,
     add_mode_sine_envelope, arpeggio_envelope, note_cut_envelope, porta_envelope,
-    retrigger_envelope, tone_porta_envelope, volume_slide_envelope,
+    retrigger_envelope, tone_porta_envelope, volume_slide_envelope, mix, BLOCK_SIZE,
 };
 
+use crate::compat::{EffectMemory, InstrumentChange, OffsetOverflow, Quirks};
 use crate::envelope_state::EnvelopeState;
-use crate::frequency::{clamp_period, note_to_period, period_to_increment, PERIOD_MAX, PERIOD_MIN};
+use crate::frequency::{
+    linear_period_to_increment, note_to_linear_period, note_to_period, period_to_increment,
+    LINEAR_PERIOD_MAX, LINEAR_PERIOD_MIN, PERIOD_MAX, PERIOD_MIN,
+};
+
+/// Full-scale fade volume (IT fadeout units).
+pub const FADE_MAX: u32 = 65536;
+
+/// ProTracker funk repeat rates: per-tick steps toward flipping the next
+/// loop frame (one flip per 128).
+const FUNK_TABLE: [u8; 16] = [0, 5, 6, 7, 8, 10, 11, 13, 16, 19, 22, 26, 32, 43, 64, 128];
+
+/// Effect classes that remember their last non-zero parameter.
+///
+/// Which effects share a slot depends on `quirks.effect_memory`: FT2 gives
+/// each class its own, while IT keeps one for all portamentos and one for
+/// all volume slides.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemorySlot {
+    PortaUp,
+    PortaDown,
+    FinePortaUp,
+    FinePortaDown,
+    /// Volume slide, alone or with tone porta or vibrato
+    VolumeSlide,
+    FineVolumeSlideUp,
+    FineVolumeSlideDown,
+}
+
+impl MemorySlot {
+    pub const COUNT: usize = 7;
+
+    /// The slot `effect` recalls its parameter from under `mode`, if any.
+    pub fn of(effect: &Effect, mode: EffectMemory) -> Option<Self> {
+        let slot = match effect {
+            Effect::PortaUp(_) => Self::PortaUp,
+            Effect::PortaDown(_) => Self::PortaDown,
+            Effect::FinePortaUp(_) => Self::FinePortaUp,
+            Effect::FinePortaDown(_) => Self::FinePortaDown,
+            Effect::VolumeSlide(_) | Effect::TonePortaVolSlide(_) | Effect::VibratoVolSlide(_) => Self::VolumeSlide,
+            Effect::FineVolumeSlideUp(_) => Self::FineVolumeSlideUp,
+            Effect::FineVolumeSlideDown(_) => Self::FineVolumeSlideDown,
+            _ => return None,
+        };
+        match (mode, slot) {
+            (EffectMemory::None, _) => None,
+            (EffectMemory::PerEffect, slot) => Some(slot),
+            (EffectMemory::Shared, Self::PortaUp | Self::PortaDown | Self::FinePortaUp | Self::FinePortaDown) => {
+                Some(Self::PortaUp)
+            }
+            (EffectMemory::Shared, _) => Some(Self::VolumeSlide),
+        }
+    }
+}
 
 /// An active envelope-based modulator on a channel parameter.
 #[derive(Clone, Debug)]
@@ -39,8 +93,20 @@ pub struct ChannelState {
     // Base parameter values
     /// Current volume (0-64)
     pub volume: u8,
-    /// Envelope tick position
+    /// Volume envelope tick position
     pub envelope_tick: u16,
+    /// Panning envelope tick position
+    pub pan_envelope_tick: u16,
+    /// Volume envelope output (0-64, 64 without an envelope)
+    pub envelope_volume: u8,
+    /// Panning envelope output (-64 to +64, 0 without an envelope)
+    pub envelope_pan: i8,
+    /// Key released: envelopes leave their sustain loops
+    pub released: bool,
+    /// Instrument fadeout in progress
+    pub fading: bool,
+    /// Fade volume (0..=FADE_MAX)
+    pub fade: u32,
     /// Current panning (-64 to +64)
     pub panning: i8,
     /// Current instrument
@@ -51,7 +117,9 @@ pub struct ChannelState {
     pub loop_forward: bool,
 
     // Pitch state
-    /// Current Amiga period (higher = lower pitch)
+    /// Periods are linear (64 per semitone) rather than Amiga periods
+    pub linear: bool,
+    /// Current period (higher = lower pitch; Amiga or linear, see `linear`)
     pub period: u16,
     /// Sample's playback rate at C-4 (typically 8363 Hz)
     pub c4_speed: u32,
@@ -59,6 +127,8 @@ pub struct ChannelState {
     pub target_period: u16,
     /// Tone portamento speed (period units per tick)
     pub porta_speed: u8,
+    /// Glissando: tone portamento plays in semitone steps
+    pub glissando: bool,
 
     // Effect memory (for tracker effect parameter persistence)
     /// Last vibrato speed
@@ -73,6 +143,29 @@ pub struct ChannelState {
     pub tremolo_depth: u8,
     /// Tremolo waveform (0=sine, 1=ramp, 2=square; bit 2=no retrig)
     pub tremolo_waveform: u8,
+    /// Last sample offset parameter (256-frame units)
+    pub sample_offset: u8,
+    /// High sample offset (65536-frame units, IT SAx)
+    pub high_offset: u8,
+    /// Last non-zero slide parameters, indexed by `MemorySlot`
+    pub effect_memory: [i16; MemorySlot::COUNT],
+
+    /// Tracker quirks to reproduce
+    pub quirks: Quirks,
+    /// How sample values between frames are reconstructed
+    pub interpolation: Interpolation,
+    /// Instrument and sample queued by a ProTracker instrument change
+    pub pending_sample: Option<(u8, u8)>,
+    /// The sample has wrapped its loop or ended since the note started
+    pub looped: bool,
+
+    // Funk repeat (invert loop)
+    /// Funk speed (0=off, 1-15 index `FUNK_TABLE`)
+    pub funk_speed: u8,
+    /// Accumulates `FUNK_TABLE` steps; one loop frame flips per 128
+    funk_accumulator: u8,
+    /// Loop frames flipped so far, modulo twice the loop length
+    pub funk_count: u32,
 
     // Envelope-based modulators (Add/Trigger mode)
     /// Period modulator (vibrato, arpeggio)
@@ -87,6 +180,28 @@ pub struct ChannelState {
     pub period_offset: i16,
     /// Volume offset from tremolo
     pub volume_offset: i8,
+
+    // Sample auto-vibrato
+    /// Ticks since the note started (drives the sweep)
+    pub auto_vibrato_ticks: u32,
+    /// Auto-vibrato waveform phase (256 steps per cycle)
+    pub auto_vibrato_phase: u8,
+    /// Period offset from auto-vibrato, added on top of `period_offset`
+    pub auto_vibrato_offset: i16,
+    /// State of the random auto-vibrato waveform
+    random_seed: u32,
+
+    // Volume ramping (declick)
+    /// Left/right gains applied to the last rendered frame
+    gains: (f32, f32),
+    /// Gains the current ramp is heading for
+    ramp_target: (f32, f32),
+    /// Per-frame gain change while ramping
+    ramp_step: (f32, f32),
+    /// Frames left in the current ramp
+    ramp_remaining: u32,
+    /// Ramping down to silence, then stopping
+    stopping: bool,
 }
 
 impl ChannelState {
@@ -94,6 +209,8 @@ impl ChannelState {
     pub fn new() -> Self {
         Self {
             volume: 64,
+            envelope_volume: 64,
+            fade: FADE_MAX,
             loop_forward: true,
             ..Default::default()
         }
@@ -107,9 +224,27 @@ impl ChannelState {
         self.position = 0;
         self.playing = true;
         self.envelope_tick = 0;
+        self.pan_envelope_tick = 0;
+        self.envelope_volume = 64;
+        self.envelope_pan = 0;
+        self.released = false;
+        self.fading = false;
+        self.fade = FADE_MAX;
         self.loop_forward = true;
+        self.looped = false;
+        self.pending_sample = None;
+        self.stopping = false;
+        if self.quirks.volume_ramp > 0 {
+            // Fade in from silence
+            self.gains = (0.0, 0.0);
+            self.ramp_target = (0.0, 0.0);
+            self.ramp_remaining = 0;
+        }
         self.period_offset = 0;
         self.volume_offset = 0;
+        self.auto_vibrato_ticks = 0;
+        self.auto_vibrato_phase = 0;
+        self.auto_vibrato_offset = 0;
         // Clear modulators (respect no-retrig waveform flag)
         if self.vibrato_waveform & 4 == 0 {
             self.period_mod = None;
@@ -125,22 +260,188 @@ impl ChannelState {
         self.playing = false;
     }
 
+    /// Stop playback after ramping the output down to silence, when
+    /// volume ramping is on.
+    pub fn stop_ramped(&mut self) {
+        if self.quirks.volume_ramp == 0 {
+            self.stop();
+        } else {
+            self.stopping = true;
+        }
+    }
+
+    /// Release the key: envelopes leave their sustain loops (IT note-off).
+    ///
+    /// The fadeout starts right away only when nothing else would end the
+    /// note: without a volume envelope, or with one that loops forever.
+    /// Otherwise it starts once the envelope reaches its end (see
+    /// `advance_envelopes`). With neither a volume envelope nor a fadeout
+    /// there is nothing to release into, so the note stops (MOD/S3M
+    /// note-off behavior).
+    pub fn key_off(&mut self, instrument: Option<&Instrument>) {
+        let vol_env = instrument.and_then(|i| enabled(&i.volume_envelope));
+        if vol_env.is_none() && instrument.is_none_or(|i| i.fadeout == 0) {
+            self.stop_ramped();
+            return;
+        }
+        self.released = true;
+        if vol_env.is_none_or(|e| e.loop_start.is_some() && e.loop_end.is_some()) {
+            self.fading = true;
+        }
+    }
+
+    /// Start the fadeout without releasing envelopes (IT note fade).
+    ///
+    /// A zero fadeout would never finish, so it stops the note instead.
+    pub fn note_fade(&mut self, instrument: Option<&Instrument>) {
+        if instrument.is_none_or(|i| i.fadeout == 0) {
+            self.stop_ramped();
+            return;
+        }
+        self.fading = true;
+    }
+
+    /// Evaluate instrument envelopes at their current positions.
+    ///
+    /// Called on note trigger so the first tick already plays the envelope's
+    /// starting values.
+    pub fn update_envelopes(&mut self, instrument: Option<&Instrument>) {
+        let vol_env = instrument.and_then(|i| enabled(&i.volume_envelope));
+        let pan_env = instrument.and_then(|i| enabled(&i.panning_envelope));
+        self.envelope_volume = vol_env.map_or(64, |e| e.value_at(self.envelope_tick).clamp(0, 64) as u8);
+        self.envelope_pan = pan_env.map_or(0, |e| e.value_at(self.pan_envelope_tick).clamp(-64, 64));
+    }
+
+    /// Advance instrument envelopes and fadeout by one tick.
+    ///
+    /// A volume envelope that reaches its end starts the fadeout (as in IT).
+    /// An envelope that ends at zero, or a completed fadeout, stops the note.
+    pub fn advance_envelopes(&mut self, instrument: Option<&Instrument>) {
+        let Some(inst) = instrument else { return };
+        if let Some(env) = enabled(&inst.volume_envelope) {
+            self.envelope_tick = env.next_tick(self.envelope_tick, self.released);
+        }
+        if let Some(env) = enabled(&inst.panning_envelope) {
+            self.pan_envelope_tick = env.next_tick(self.pan_envelope_tick, self.released);
+        }
+        self.update_envelopes(instrument);
+        let env_end = enabled(&inst.volume_envelope).is_some_and(|e| e.at_end(self.envelope_tick));
+        if env_end {
+            self.fading = true;
+        }
+        if self.fading {
+            self.fade = self.fade.saturating_sub((inst.fadeout as u32) << 5);
+        }
+        let env_done = env_end && self.envelope_volume == 0;
+        if env_done || self.fade == 0 {
+            self.stop();
+        }
+    }
+
     /// Recompute the playback increment from the current period and c4_speed.
     /// Applies period_offset (from vibrato/arpeggio) without modifying the base period.
     pub fn update_increment(&mut self, sample_rate: u32) {
         if self.period > 0 {
-            let effective = (self.period as i32 + self.period_offset as i32)
-                .clamp(PERIOD_MIN as i32, PERIOD_MAX as i32) as u16;
-            self.increment = period_to_increment(effective, self.c4_speed, sample_rate);
+            let period = self.effective_period();
+            self.increment = if self.linear {
+                linear_period_to_increment(period, self.c4_speed, sample_rate)
+            } else {
+                period_to_increment(period, self.c4_speed, sample_rate)
+            };
         }
     }
 
+    /// Period of `note` on this channel's period scale.
+    pub fn note_period(&self, note: u8) -> u16 {
+        if self.linear { note_to_linear_period(note) } else { note_to_period(note) }
+    }
+
+    /// Valid period range on this channel's period scale.
+    fn period_range(&self) -> (u16, u16) {
+        if self.linear { (LINEAR_PERIOD_MIN, LINEAR_PERIOD_MAX) } else { (PERIOD_MIN, PERIOD_MAX) }
+    }
+
+    /// Clamp a period into the valid range.
+    fn clamp_period(&self, period: i32) -> u16 {
+        let (min, max) = self.period_range();
+        period.clamp(min as i32, max as i32) as u16
+    }
+
+    /// Period units per effect slide unit: effect parameters are in Amiga
+    /// terms, and linear slides move 4 units per step (as in XM).
+    fn slide_scale(&self) -> u16 {
+        if self.linear { 4 } else { 1 }
+    }
+
+    /// Period actually played this tick: base period plus vibrato/arpeggio
+    /// and auto-vibrato offsets. With glissando on, a tone portamento's
+    /// base period is rounded to the nearest semitone.
+    pub fn effective_period(&self) -> u16 {
+        let base = if self.glissando && self.target_period > 0 {
+            self.nearest_note_period(self.period)
+        } else {
+            self.period
+        };
+        self.clamp_period(base as i32 + self.period_offset as i32 + self.auto_vibrato_offset as i32)
+    }
+
+    /// The note period closest to `period` on this channel's period scale.
+    fn nearest_note_period(&self, period: u16) -> u16 {
+        if self.linear {
+            let semitone = note_to_linear_period(1) - note_to_linear_period(2);
+            return (period + semitone / 2) / semitone * semitone;
+        }
+        (1..=119)
+            .map(note_to_period)
+            .min_by_key(|p| p.abs_diff(period))
+            .unwrap_or(period)
+    }
+
+    /// Advance the sample's auto-vibrato by one tick.
+    ///
+    /// Depth is in quarter periods (so 64, the IT maximum, swings ±16
+    /// periods) and fades in over the sweep.
+    pub fn advance_auto_vibrato(&mut self, vibrato: Option<&AutoVibrato>) {
+        let Some(vib) = vibrato.filter(|v| v.depth > 0) else {
+            self.auto_vibrato_offset = 0;
+            return;
+        };
+        self.random_seed = self.random_seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let random = ((self.random_seed >> 16) % 129) as i8 - 64;
+        let value = vib.waveform_value(self.auto_vibrato_phase, random);
+        let depth = vib.depth as i32 * vib.sweep_scale(self.auto_vibrato_ticks) as i32;
+        self.auto_vibrato_offset = (value * depth * self.slide_scale() as i32 / (64 * 256 * 4)) as i16;
+        self.auto_vibrato_phase = self.auto_vibrato_phase.wrapping_add(vib.speed);
+        self.auto_vibrato_ticks = self.auto_vibrato_ticks.saturating_add(1);
+    }
+
+    /// Volume actually played this tick: base volume plus tremolo offset (0-64).
+    pub fn effective_volume(&self) -> u8 {
+        (self.volume as i32 + self.volume_offset as i32).clamp(0, 64) as u8
+    }
+
+    /// Final output level (0.0-1.0): effective volume scaled by the
+    /// instrument's volume envelope and fadeout.
+    pub fn output_level(&self) -> f32 {
+        self.effective_volume() as f32 / 64.0
+            * (self.envelope_volume as f32 / 64.0)
+            * (self.fade as f32 / FADE_MAX as f32)
+    }
+
+    /// Panning actually played (-64 to +64): the panning envelope swings
+    /// around the channel pan, scaled by the room left toward each side.
+    pub fn effective_panning(&self) -> i8 {
+        let pan = self.panning as i32;
+        let swing = self.envelope_pan as i32 * (64 - pan.abs()) / 64;
+        (pan + swing).clamp(-64, 64) as i8
+    }
+
     /// Apply a row effect (first-tick / immediate).
     pub fn apply_row_effect(&mut self, effect: &Effect) {
+        let effect = &self.recall_memory(*effect);
         match effect {
             Effect::SetVolume(v) => self.volume = (*v).min(64),
             Effect::SetPan(p) => self.panning = (*p as i16 - 128).clamp(-64, 64) as i8,
-            Effect::SampleOffset(o) => self.position = (*o as u64) << 24,
             Effect::FineVolumeSlideUp(v) => {
                 self.volume = (self.volume as i16 + *v as i16).clamp(0, 64) as u8;
             }
@@ -148,18 +449,87 @@ impl ChannelState {
                 self.volume = (self.volume as i16 - *v as i16).clamp(0, 64) as u8;
             }
             Effect::FinePortaUp(v) => {
-                self.period = clamp_period(self.period.saturating_sub(*v as u16));
+                self.period = self.clamp_period(self.period as i32 - (*v as u16 * self.slide_scale()) as i32);
             }
             Effect::FinePortaDown(v) => {
-                self.period = clamp_period(self.period.saturating_add(*v as u16));
+                self.period = self.clamp_period(self.period as i32 + (*v as u16 * self.slide_scale()) as i32);
             }
             Effect::NoteCut(0) => self.volume = 0,
             Effect::SetVibratoWaveform(w) => self.vibrato_waveform = *w,
             Effect::SetTremoloWaveform(w) => self.tremolo_waveform = *w,
+            Effect::SetGlissando(v) => self.glissando = *v != 0,
+            Effect::SetHighOffset(v) => self.high_offset = *v,
+            Effect::FunkRepeat(v) => {
+                self.funk_speed = (*v).min(15);
+                self.funk_accumulator = 0;
+            }
             _ => {}
         }
     }
 
+    /// Jump to a sample offset: `param` × 256 frames plus the high offset
+    /// × 65536. A zero parameter reuses the last one. Offsets past the end
+    /// of `sample` follow `quirks.offset_overflow`.
+    pub fn apply_sample_offset(&mut self, param: u8, sample: Option<&Sample>) {
+        if param > 0 {
+            self.sample_offset = param;
+        }
+        let frame = ((self.high_offset as u64) << 16) | ((self.sample_offset as u64) << 8);
+        let len = sample.map_or(0, |s| s.len() as u64);
+        if frame < len {
+            self.position = frame << 16;
+            return;
+        }
+        match (self.quirks.offset_overflow, sample.filter(|s| s.has_loop())) {
+            (OffsetOverflow::Cut, _) => self.stop(),
+            (OffsetOverflow::Clamp, Some(s)) => self.position = (s.loop_start as u64) << 16,
+            (OffsetOverflow::Clamp, None) => self.position = len.saturating_sub(1) << 16,
+        }
+    }
+
+    /// Advance funk repeat by one tick, flipping the next frame of the
+    /// sample's loop when the accumulator fills.
+    ///
+    /// ProTracker inverts the sample data itself; here the flips are kept
+    /// per channel as a count and applied during rendering, so the song's
+    /// samples are never modified. After one pass the whole loop is
+    /// inverted, and the next pass restores it.
+    pub fn advance_funk(&mut self, sample: Option<&Sample>) {
+        let Some(sample) = sample.filter(|s| s.has_loop() && self.funk_speed > 0) else {
+            return;
+        };
+        self.funk_accumulator = self.funk_accumulator.saturating_add(FUNK_TABLE[self.funk_speed as usize & 0x0F]);
+        if self.funk_accumulator >= 128 {
+            self.funk_accumulator = 0;
+            let loop_len = sample.loop_end - sample.loop_start;
+            self.funk_count = (self.funk_count + 1) % (2 * loop_len);
+        }
+    }
+
+    /// Whether funk repeat has inverted `frame` of `sample`.
+    fn funk_inverted(&self, sample: &Sample, frame: usize) -> bool {
+        let (start, end) = (sample.loop_start as usize, sample.loop_end as usize);
+        if self.funk_count == 0 || !sample.has_loop() || frame < start || frame >= end {
+            return false;
+        }
+        let (passes, flipped) = (self.funk_count as usize / (end - start), self.funk_count as usize % (end - start));
+        (passes + (frame - start < flipped) as usize) % 2 == 1
+    }
+
+    /// Interpolated stereo frame at the current position with funk repeat
+    /// inversions applied.
+    fn funk_interpolated(&self, sample: &Sample) -> (i16, i16) {
+        let idx = (self.position >> 16) as usize;
+        let frac = (self.position & 0xFFFF) as i64;
+        let frame = |i: usize| {
+            let (l, r) = (sample.data.get_mono(i), sample.data.get_right(i));
+            if self.funk_inverted(sample, i) { (!l, !r) } else { (l, r) }
+        };
+        let ((al, ar), (bl, br)) = (frame(idx), frame(idx + 1));
+        let lerp = |a: i16, b: i16| (a as i64 + (((b as i64 - a as i64) * frac) >> 16)) as i16;
+        (lerp(al, bl), lerp(ar, br))
+    }
+
     /// Clear temporary per-tick modulation before applying effects.
     pub fn clear_modulation(&mut self) {
         self.period_offset = 0;
@@ -168,12 +538,13 @@ impl ChannelState {
 
     /// Advance the period modulator and apply based on mode.
     fn advance_period_mod(&mut self, spt: u32) {
+        let (min, max) = self.period_range();
         if let Some(m) = &mut self.period_mod {
             let prev = self.period;
             m.state.advance(&m.envelope, spt);
             match m.mode {
                 ModMode::Set => {
-                    let mut p = clamp_period(m.state.value() as u16);
+                    let mut p = (m.state.value() as u16).clamp(min, max);
                     // Prevent overshoot for tone portamento
                     if self.target_period > 0 {
                         p = clamp_toward(p, prev, self.target_period);
@@ -218,6 +589,9 @@ impl ChannelState {
     /// Set up envelope-based modulators for the current effect.
     /// Called when a new per-tick effect is dispatched.
     pub fn setup_modulator(&mut self, effect: &Effect, spt: u32) {
+        let scale = self.slide_scale();
+        let (min, max) = self.period_range();
+        let effect = &self.recall_memory(*effect);
         match effect {
             Effect::VolumeSlide(delta) => {
                 let env = volume_slide_envelope(self.volume as f32, *delta as f32, spt);
@@ -228,8 +602,8 @@ impl ChannelState {
             Effect::PortaUp(v) => {
                 self.target_period = 0; // clear tone porta target
                 let env = porta_envelope(
-                    self.period as f32, -(*v as f32),
-                    PERIOD_MIN as f32, PERIOD_MAX as f32, spt,
+                    self.period as f32, -((*v as u16 * scale) as f32),
+                    min as f32, max as f32, spt,
                 );
                 self.period_mod = Some(ActiveMod::new(env, ModMode::Set));
                 self.volume_mod = None;
@@ -238,8 +612,8 @@ impl ChannelState {
             Effect::PortaDown(v) => {
                 self.target_period = 0; // clear tone porta target
                 let env = porta_envelope(
-                    self.period as f32, *v as f32,
-                    PERIOD_MIN as f32, PERIOD_MAX as f32, spt,
+                    self.period as f32, (*v as u16 * scale) as f32,
+                    min as f32, max as f32, spt,
                 );
                 self.period_mod = Some(ActiveMod::new(env, ModMode::Set));
                 self.volume_mod = None;
@@ -248,7 +622,7 @@ impl ChannelState {
             Effect::TonePorta(_speed) => {
                 let env = tone_porta_envelope(
                     self.period as f32, self.target_period as f32,
-                    self.porta_speed as f32, spt,
+                    (self.porta_speed as u16 * scale) as f32, spt,
                 );
                 self.period_mod = Some(ActiveMod::new(env, ModMode::Set));
                 self.volume_mod = None;
@@ -257,7 +631,7 @@ impl ChannelState {
             Effect::TonePortaVolSlide(delta) => {
                 let period_env = tone_porta_envelope(
                     self.period as f32, self.target_period as f32,
-                    self.porta_speed as f32, spt,
+                    (self.porta_speed as u16 * scale) as f32, spt,
                 );
                 self.period_mod = Some(ActiveMod::new(period_env, ModMode::Set));
                 let vol_env = volume_slide_envelope(self.volume as f32, *delta as f32, spt);
@@ -269,7 +643,8 @@ impl ChannelState {
                 let d = if *depth > 0 { *depth } else { self.vibrato_depth };
                 if *speed > 0 { self.vibrato_speed = s; }
                 if *depth > 0 { self.vibrato_depth = d; }
-                self.period_mod = build_add_mode_sine_mod(s, d, spt);
+                let depth = d.saturating_mul(scale as u8).saturating_mul(self.quirks.vibrato_depth_scale);
+                self.period_mod = build_add_mode_sine_mod(s, depth, spt);
                 self.volume_mod = None;
                 self.trigger_mod = None;
             }
@@ -277,8 +652,10 @@ impl ChannelState {
                 // Keep existing period_mod (vibrato continues from previous row)
                 // If no vibrato mod exists, create one from stored params
                 if self.period_mod.is_none() && self.vibrato_speed > 0 {
-                    self.period_mod =
-                        build_add_mode_sine_mod(self.vibrato_speed, self.vibrato_depth, spt);
+                    let depth = self.vibrato_depth
+                        .saturating_mul(scale as u8)
+                        .saturating_mul(self.quirks.vibrato_depth_scale);
+                    self.period_mod = build_add_mode_sine_mod(self.vibrato_speed, depth, spt);
                 }
                 let vol_env = volume_slide_envelope(self.volume as f32, *delta as f32, spt);
                 self.volume_mod = Some(ActiveMod::new(vol_env, ModMode::Set));
@@ -294,7 +671,7 @@ impl ChannelState {
                 self.trigger_mod = None;
             }
             Effect::Arpeggio { x, y } => {
-                self.period_mod = build_arpeggio_mod(self.note, self.period, *x, *y, spt);
+                self.period_mod = self.arpeggio_mod(*x, *y, spt);
                 self.volume_mod = None;
                 self.trigger_mod = None;
             }
@@ -319,8 +696,110 @@ impl ChannelState {
         }
     }
 
+    /// Fill a zero slide parameter from the effect's `MemorySlot`, per
+    /// `quirks.effect_memory`, and remember non-zero ones.
+    ///
+    /// Volume slides are remembered with their direction, so in IT a fine
+    /// slide down followed by `D00` keeps sliding down; fine slides always
+    /// keep their own direction.
+    fn recall_memory(&mut self, effect: Effect) -> Effect {
+        let Some(slot) = MemorySlot::of(&effect, self.quirks.effect_memory) else {
+            return effect;
+        };
+        let memory = &mut self.effect_memory[slot as usize];
+        match effect {
+            Effect::FineVolumeSlideDown(v) if v > 0 => *memory = -(v as i16),
+            _ => match effect.param() {
+                Some(v) if v != 0 => *memory = v,
+                _ => {}
+            },
+        }
+        match effect {
+            Effect::FineVolumeSlideUp(_) | Effect::FineVolumeSlideDown(_) => effect.with_param(memory.abs()),
+            _ => effect.with_param(*memory),
+        }
+    }
+
+    /// An instrument number without a note: per `quirks.instrument_change`
+    /// queue a sample swap or restart the envelopes, resetting the volume
+    /// to the sample's default.
+    pub fn change_instrument(&mut self, instrument: u8, sample_index: u8, sample: Option<&Sample>, inst: Option<&Instrument>) {
+        match self.quirks.instrument_change {
+            InstrumentChange::Ignore => {}
+            InstrumentChange::SwapSample => {
+                if sample_index == self.sample_index {
+                    return;
+                }
+                if let Some(s) = sample {
+                    self.volume = s.default_volume;
+                }
+                if self.period > 0 {
+                    self.pending_sample = Some((instrument, sample_index));
+                }
+            }
+            InstrumentChange::ResetEnvelopes => {
+                if let Some(s) = sample {
+                    self.volume = s.default_volume;
+                }
+                self.envelope_tick = 0;
+                self.pan_envelope_tick = 0;
+                self.released = false;
+                self.fading = false;
+                self.fade = FADE_MAX;
+                self.update_envelopes(inst);
+            }
+        }
+    }
+
+    /// Switch to the sample queued by `change_instrument` once the playing
+    /// one has looped or ended. As on the Amiga, the new sample plays its
+    /// loop, and one without a loop falls silent.
+    pub fn swap_pending_sample(&mut self, samples: &[Sample]) {
+        let Some((instrument, sample_index)) = self.pending_sample else { return };
+        if self.playing && !self.looped {
+            return;
+        }
+        self.pending_sample = None;
+        self.instrument = instrument;
+        self.sample_index = sample_index;
+        self.looped = false;
+        match samples.get(sample_index as usize).filter(|s| s.has_loop()) {
+            Some(s) => {
+                self.c4_speed = s.c4_speed;
+                self.position = (s.loop_start as u64) << 16;
+                self.loop_forward = true;
+                self.playing = true;
+            }
+            None => self.stop(),
+        }
+    }
+
+    /// Set up the modulators for a second-column effect, keeping the ones
+    /// it doesn't use (so e.g. a volume slide runs alongside a vibrato).
+    pub fn layer_modulator(&mut self, effect: &Effect, spt: u32) {
+        let (period, volume, trigger) = (self.period_mod.take(), self.volume_mod.take(), self.trigger_mod.take());
+        self.setup_modulator(effect, spt);
+        self.period_mod = self.period_mod.take().or(period);
+        self.volume_mod = self.volume_mod.take().or(volume);
+        self.trigger_mod = self.trigger_mod.take().or(trigger);
+    }
+
+    /// Arpeggio modulator: period offsets to the note `x` and `y` semitones up.
+    fn arpeggio_mod(&self, x: u8, y: u8, spt: u32) -> Option<ActiveMod> {
+        let offset = |semitones: u8| {
+            let target = self.note_period(self.note.saturating_add(semitones));
+            if semitones == 0 || target == 0 { 0.0 } else { target as f32 - self.period as f32 }
+        };
+        let env = arpeggio_envelope([0.0, offset(x), offset(y)], spt);
+        Some(ActiveMod::new(env, ModMode::Add))
+    }
+
     /// Render a block of frames, accumulating into left/right slices.
-    /// Volume and panning are hoisted outside the loop (constant within sub-block).
+    /// Volume and panning are hoisted outside the loop (constant within
+    /// sub-block); changes to them ramp in over `quirks.volume_ramp` frames.
+    ///
+    /// Sample frames are read a block at a time, then scaled and summed
+    /// into the output with `mb_ir::mix` (vectorized with the `simd` feature).
     pub(crate) fn render_block(
         &mut self,
         sample: &Sample,
@@ -328,38 +807,154 @@ impl ChannelState {
         right: &mut [f32],
         gain: f32,
     ) {
-        let vol = (self.volume as i32 + self.volume_offset as i32).clamp(0, 64);
-        let pan_right = self.panning as i32 + 64;
-        let left_gain = ((128 - pan_right) as f32 / 128.0) * (vol as f32 / 64.0) * gain / 32768.0;
-        let right_gain = (pan_right as f32 / 128.0) * (vol as f32 / 64.0) * gain / 32768.0;
+        let level = if self.stopping { 0.0 } else { self.output_level() };
+        let pan_right = self.effective_panning() as i32 + 64;
+        let left_gain = ((128 - pan_right) as f32 / 128.0) * level * gain / 32768.0;
+        let right_gain = (pan_right as f32 / 128.0) * level * gain / 32768.0;
+        self.ramp_to((left_gain, right_gain));
 
-        for i in 0..left.len() {
+        let mut src_l = [0.0f32; BLOCK_SIZE];
+        let mut src_r = [0.0f32; BLOCK_SIZE];
+        for (left, right) in left.chunks_mut(BLOCK_SIZE).zip(right.chunks_mut(BLOCK_SIZE)) {
             if !self.playing { break; }
+            let frames = self.fetch_frames(sample, &mut src_l[..left.len()], &mut src_r[..left.len()]);
+            self.apply_gains(&src_l[..frames], &src_r[..frames], left, right);
+            if self.stopping && self.ramp_remaining == 0 {
+                self.playing = false;
+            }
+        }
+    }
 
-            let (sample_l, sample_r) = sample.data.get_stereo_interpolated(self.position);
-            left[i] += sample_l as f32 * left_gain;
-            right[i] += sample_r as f32 * right_gain;
+    /// Read sample frames into `left`/`right` (unscaled), advancing the
+    /// play position. Returns the frames read: fewer than asked once the
+    /// note ends or a stopping ramp runs out.
+    fn fetch_frames(&mut self, sample: &Sample, left: &mut [f32], right: &mut [f32]) -> usize {
+        let limit = if self.stopping {
+            left.len().min(self.ramp_remaining.max(1) as usize)
+        } else {
+            left.len()
+        };
+        for i in 0..limit {
+            if !self.playing { return i; }
 
+            let (sample_l, sample_r) = if self.funk_count == 0 {
+                sample.data.get_stereo_with(self.position, self.interpolation, sample.loop_taps(self.looped))
+            } else {
+                self.funk_interpolated(sample)
+            };
+            left[i] = sample_l as f32;
+            right[i] = sample_r as f32;
+
+            if sample.has_loop() && sample.loop_type == LoopType::PingPong {
+                self.advance_ping_pong(sample);
+                continue;
+            }
             self.position += self.increment;
             let pos_samples = self.position >> 16;
             if sample.has_loop() && pos_samples >= sample.loop_end as u64 {
                 let loop_len = (sample.loop_end - sample.loop_start) as u64;
                 self.position -= loop_len << 16;
+                self.looped = true;
             } else if pos_samples >= sample.len() as u64 {
                 self.playing = false;
+                self.looped = true;
             }
         }
+        limit
+    }
+
+    /// Scale fetched frames by the channel gains and add them to the output:
+    /// frame by frame while ramping, then in one pass at the settled gains.
+    fn apply_gains(&mut self, src_l: &[f32], src_r: &[f32], left: &mut [f32], right: &mut [f32]) {
+        let ramped = (self.ramp_remaining as usize).min(src_l.len());
+        for i in 0..ramped {
+            let (left_gain, right_gain) = self.next_gains();
+            left[i] += src_l[i] * left_gain;
+            right[i] += src_r[i] * right_gain;
+        }
+        if ramped == src_l.len() {
+            return;
+        }
+        self.gains = self.ramp_target;
+        mix::add_scaled(&mut left[ramped..], &src_l[ramped..], self.gains.0);
+        mix::add_scaled(&mut right[ramped..], &src_r[ramped..], self.gains.1);
+    }
+
+    /// Head for new gains: at once without ramping, otherwise linearly over
+    /// `quirks.volume_ramp` frames from the gains currently applied.
+    fn ramp_to(&mut self, target: (f32, f32)) {
+        if target == self.ramp_target {
+            return;
+        }
+        self.ramp_target = target;
+        let frames = self.quirks.volume_ramp as u32;
+        if frames == 0 {
+            self.gains = target;
+            self.ramp_remaining = 0;
+            return;
+        }
+        self.ramp_step = ((target.0 - self.gains.0) / frames as f32, (target.1 - self.gains.1) / frames as f32);
+        self.ramp_remaining = frames;
+    }
+
+    /// Gains for the next frame, advancing any ramp in progress.
+    fn next_gains(&mut self) -> (f32, f32) {
+        match self.ramp_remaining {
+            0 => self.gains = self.ramp_target,
+            1 => {
+                self.gains = self.ramp_target;
+                self.ramp_remaining = 0;
+            }
+            _ => {
+                self.gains = (self.gains.0 + self.ramp_step.0, self.gains.1 + self.ramp_step.1);
+                self.ramp_remaining -= 1;
+            }
+        }
+        self.gains
+    }
+
+    /// Step through a ping-pong loop, reflecting off both ends.
+    ///
+    /// The loop runs from `loop_start` to the last loop frame (`loop_end - 1`)
+    /// and back, so the interpolation neighbour never leaves the loop: at the
+    /// turnaround the position lands exactly on the last frame, and on the
+    /// way back it blends between the same frames it passed going forward.
+    fn advance_ping_pong(&mut self, sample: &Sample) {
+        let start = (sample.loop_start as u64) << 16;
+        let last = ((sample.loop_end - 1) as u64) << 16;
+
+        // Before entering the loop (e.g. after a sample offset), play straight on
+        if self.loop_forward && self.position + self.increment <= last {
+            self.position += self.increment;
+            return;
+        }
+        let span = last - start;
+        self.looped = true;
+        if span == 0 {
+            self.position = start;
+            return;
+        }
+
+        // Unfold into one forward-then-back cycle of length 2 * span
+        let offset = self.position.saturating_sub(start);
+        let unfolded = if self.loop_forward { offset } else { 2 * span - offset };
+        let unfolded = (unfolded + self.increment) % (2 * span);
+        self.loop_forward = unfolded < span;
+        self.position = if self.loop_forward { start + unfolded } else { start + 2 * span - unfolded };
     }
 
 }
 
+/// An instrument envelope, if present and switched on.
+fn enabled(envelope: &Option<Envelope>) -> Option<&Envelope> {
+    envelope.as_ref().filter(|e| e.enabled && !e.points.is_empty())
+}
+
 /// Clamp `value` so it doesn't overshoot `target` relative to `prev`.
 /// If `prev` was above `target` and `value` went below it, return `target`.
 /// If `prev` was below `target` and `value` went above it, return `target`.
 fn clamp_toward(value: u16, prev: u16, target: u16) -> u16 {
-    if prev > target && value < target {
-        target
-    } else if prev < target && value > target {
+    if (prev > target && value < target) || (prev < target && value > target) {
         target
     } else {
         value
@@ -374,19 +969,79 @@ fn build_add_mode_sine_mod(speed: u8, depth: u8, spt: u32) -> Option<ActiveMod>
     Some(ActiveMod::new(env, ModMode::Add))
 }
 
-fn build_arpeggio_mod(note: u8, period: u16, x: u8, y: u8, spt: u32) -> Option<ActiveMod> {
-    let offset_x = if x == 0 {
-        0.0
-    } else {
-        let target = note_to_period(note.saturating_add(x));
-        if target > 0 { target as f32 - period as f32 } else { 0.0 }
-    };
-    let offset_y = if y == 0 {
-        0.0
-    } else {
-        let target = note_to_period(note.saturating_add(y));
-        if target > 0 { target as f32 - period as f32 } else { 0.0 }
-    };
-    let env = arpeggio_envelope([0.0, offset_x, offset_y], spt);
-    Some(ActiveMod::new(env, ModMode::Add))
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+    use mb_ir::SampleData;
+
+    /// Ramp sample 0, 1, ..., 7 (scaled) with a ping-pong loop over frames 2..6.
+    fn ping_pong_sample() -> Sample {
+        let mut sample = Sample::new("ramp");
+        sample.set_data(SampleData::Mono8((0..8).map(|i| i * 10).collect()));
+        sample.loop_start = 2;
+        sample.loop_end = 6;
+        sample.loop_type = LoopType::PingPong;
+        sample
+    }
+
+    /// Play `frames` frames one at a time, collecting the frame index read.
+    fn positions(ch: &mut ChannelState, sample: &Sample, frames: usize) -> Vec<u64> {
+        let (mut l, mut r) = ([0.0f32], [0.0f32]);
+        (0..frames)
+            .map(|_| {
+                let pos = ch.position;
+                ch.render_block(sample, &mut l, &mut r, 1.0);
+                pos >> 16
+            })
+            .collect()
+    }
+
+    fn playing_channel(increment: u64) -> ChannelState {
+        let mut ch = ChannelState::new();
+        ch.trigger(48, 1, 0);
+        ch.increment = increment;
+        ch
+    }
+
+    #[test]
+    fn ping_pong_reflects_at_both_ends() {
+        let sample = ping_pong_sample();
+        let mut ch = playing_channel(1 << 16);
+        assert_eq!(positions(&mut ch, &sample, 14), [0, 1, 2, 3, 4, 5, 4, 3, 2, 3, 4, 5, 4, 3]);
+        assert!(ch.playing);
+    }
+
+    #[test]
+    fn ping_pong_interpolates_symmetrically_across_turnaround() {
+        let sample = ping_pong_sample();
+        let mut ch = playing_channel(1 << 15);
+        ch.position = 4 << 16;
+        let mut out = Vec::new();
+        for _ in 0..5 {
+            let (mut l, mut r) = ([0.0f32], [0.0f32]);
+            ch.render_block(&sample, &mut l, &mut r, 1.0);
+            out.push(sample.data.get_mono_interpolated(ch.position));
+        }
+        // 4.5, 5, 4.5, 4, 3.5: never blends in frame 6 past the loop
+        let expected: Vec<i16> = [45, 50, 45, 40, 35].iter().map(|v| v * 256).collect();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn ping_pong_stays_in_loop_with_large_increment() {
+        let sample = ping_pong_sample();
+        let mut ch = playing_channel(11 << 16);
+        let pos = positions(&mut ch, &sample, 20);
+        assert!(pos[1..].iter().all(|&p| (2..6).contains(&p)), "{:?}", pos);
+    }
+
+    #[test]
+    fn forward_loop_still_wraps() {
+        let mut sample = ping_pong_sample();
+        sample.loop_type = LoopType::Forward;
+        let mut ch = playing_channel(1 << 16);
+        assert_eq!(positions(&mut ch, &sample, 10), [0, 1, 2, 3, 4, 5, 2, 3, 4, 5]);
+    }
 }
@@ -1,14 +1,24 @@
 //! ClipSourceState — lazy, incremental event source for a single track.
 //!
 //! Walks a track's sequence entries and clips row by row, generating events
-//! on demand as playback advances. Mirrors the logic of `schedule_track` but
-//! reads pattern data lazily so edits ahead of the cursor take effect.
+//! on demand as playback advances. Mirrors the logic of `schedule_track`,
+//! pattern loops included, but reads pattern data lazily so edits ahead of
+//! the cursor take effect.
+//!
+//! In session mode a source ignores the sequence and plays whichever clip
+//! was last launched on its track, looping it from the launch time until
+//! another clip is launched or the track is stopped.
 
+use alloc::sync::Arc;
 use alloc::vec::Vec;
-use mb_ir::{Effect, Event, MusicalTime, Song, Track};
+use mb_ir::{Effect, Event, EventPayload, MusicalTime, Song, Track};
 
 use crate::event_source::EventSource;
-use crate::scheduler::{schedule_cell, target_for_track_column};
+use crate::limits::{LimitHit, Limits};
+use crate::scheduler::{
+    check_limits, is_scheduled, schedule_boundary, schedule_row, target_for_track_column, PatternLoops,
+};
+use crate::tempo_map::TempoMap;
 
 /// Incremental event source for one track.
 #[derive(Clone, Debug)]
@@ -33,18 +43,55 @@ pub struct ClipSourceState {
     exhausted: bool,
     /// The time at which this source became exhausted (accounts for PatternBreak/PositionJump).
     end_time: Option<MusicalTime>,
+    /// Safety limits on length and event count
+    limits: Limits,
+    /// Events generated so far
+    events_emitted: usize,
+    /// Limit that exhausted this source early, if any
+    truncated: Option<LimitHit>,
+    /// Whether the track plays its sequence (false in session mode)
+    follows_sequence: bool,
+    /// Clip looping in place of the sequence, since it was launched
+    launched: Option<u16>,
+    /// Launch (`Some(clip)`) or stop (`None`) waiting for its time
+    queued: Option<(MusicalTime, Option<u16>)>,
+    /// Pattern loop (E6x) state of the current sequence entry
+    loops: PatternLoops,
+    /// Sequence entry `loops` belongs to
+    loop_entry: Option<usize>,
+    /// Time pattern loops have pushed later entries back by, in sub-beats
+    shift: u64,
+    /// Tempo over the song, for millisecond track offsets
+    tempo_map: Option<Arc<TempoMap>>,
 }
 
 impl ClipSourceState {
-    /// Create a new ClipSourceState for a given track.
+    /// Create a new ClipSourceState for a given track, with default limits.
     pub fn new(song: &Song, track_idx: usize) -> Self {
+        Self::with_limits(song, track_idx, Limits::default())
+    }
+
+    /// Point the source at the track's new index after the track list was
+    /// reordered.
+    pub fn set_track_idx(&mut self, track_idx: usize) {
+        self.track_idx = track_idx;
+    }
+
+    /// Create a source that stops early once `limits` are exceeded.
+    pub fn with_limits(song: &Song, track_idx: usize, limits: Limits) -> Self {
+        let loops = PatternLoops::with_capacity(max_clip_channels(&song.tracks[track_idx]));
+        Self::with_loops(song, track_idx, limits, loops)
+    }
+
+    /// Like `with_limits`, reusing `loops` for the pattern loop state.
+    fn with_loops(song: &Song, track_idx: usize, limits: Limits, loops: PatternLoops) -> Self {
         let track = &song.tracks[track_idx];
         let time = track.sequence.first()
             .map(|e| e.start)
             .unwrap_or(MusicalTime::zero());
         let exhausted = track.sequence.is_empty()
             || track.muted
-            || !song.is_tracker(track);
+            || !is_scheduled(song, track);
         Self {
             track_idx,
             seq_idx: 0,
@@ -56,9 +103,155 @@ impl ClipSourceState {
             rows_processed: 0,
             exhausted,
             end_time: if exhausted { Some(MusicalTime::zero()) } else { None },
+            limits,
+            events_emitted: 0,
+            truncated: None,
+            follows_sequence: true,
+            launched: None,
+            queued: None,
+            loops,
+            loop_entry: None,
+            shift: 0,
+            tempo_map: None,
+        }
+    }
+
+    /// Create a session-mode source: silent until a clip is launched.
+    pub fn idle(song: &Song, track_idx: usize, limits: Limits) -> Self {
+        Self::with_limits(song, track_idx, limits).into_idle()
+    }
+
+    fn into_idle(self) -> Self {
+        Self {
+            exhausted: true,
+            end_time: Some(MusicalTime::zero()),
+            follows_sequence: false,
+            ..self
         }
     }
 
+    /// Loop `clip` from `at` on instead of the sequence, or stop the track
+    /// at `at` with `None`; notes still sounding are released then. Replaces
+    /// any launch still waiting.
+    pub fn queue_launch(&mut self, at: MusicalTime, clip: Option<u16>) {
+        self.queued = Some((at, clip));
+    }
+
+    /// Convert millisecond track offsets at the tempo `map` gives where
+    /// each row plays, instead of at the song's initial tempo.
+    pub fn set_tempo_map(&mut self, map: Option<Arc<TempoMap>>) {
+        self.tempo_map = map;
+    }
+
+    /// The tempo at `time` for millisecond offsets.
+    fn offset_tempo(&self, song: &Song, time: MusicalTime) -> u32 {
+        self.tempo_map.as_ref().map_or(song.initial_tempo, |map| map.tempo_at(time))
+    }
+
+    /// The clip looping in place of the sequence, if any.
+    pub fn launched(&self) -> Option<u16> {
+        self.launched
+    }
+
+    /// Play the launched clip again from its first row at `at`, as when
+    /// playback jumps back to a loop start. Notes still sounding carry over.
+    pub fn restart_launched(&mut self, at: MusicalTime) {
+        if self.launched.is_some() {
+            self.row = 0;
+            self.time = at;
+        }
+    }
+
+    /// Switch to the queued launch at its time.
+    fn launch(&mut self, at: MusicalTime, clip: Option<u16>, song: &Song, out: &mut Vec<Event>) {
+        let track = &song.tracks[self.track_idx];
+        for col in 0..track.num_channels {
+            out.push(Event::new(at, target_for_track_column(track, col), EventPayload::NoteOff { note: 0 }));
+        }
+        self.launched = clip.filter(|&c| track.get_pattern_at(c as usize).is_some());
+        self.row = 0;
+        self.time = at;
+        self.exhausted = self.launched.is_none();
+        self.end_time = self.exhausted.then_some(at);
+    }
+
+    /// How far past the play head the row due on the grid at `at` is
+    /// drained, so rows a track offset moves earlier are still scheduled in
+    /// time: the offset there, plus a tick, as the play head is drained at
+    /// least once a tick.
+    fn lookahead(&self, song: &Song, at: MusicalTime) -> u64 {
+        match self.early(song, at) {
+            0 => 0,
+            early => early + MusicalTime::zero().add_ticks(1, self.speed * self.song_rpb).as_sub_beats(),
+        }
+    }
+
+    /// Sub-beats the track offset moves the row due on the grid at `at`
+    /// earlier (0 for later).
+    fn early(&self, song: &Song, at: MusicalTime) -> u64 {
+        let Some(track) = song.tracks.get(self.track_idx) else { return 0 };
+        let offset = track.offset.sub_beats(self.speed * self.song_rpb, self.offset_tempo(song, at));
+        offset.min(0).unsigned_abs()
+    }
+
+    /// Pick the track's edited sequence up at `now` without replaying it:
+    /// from the first row that plays at or after `now`, in the entry
+    /// playing then. Keeps the speed and the time pattern loops have added
+    /// so far.
+    pub fn resync(&mut self, now: MusicalTime, song: &Song) {
+        let track = &song.tracks[self.track_idx];
+        self.exhausted = track.sequence.is_empty() || track.muted || !is_scheduled(song, track);
+        self.end_time = self.exhausted.then_some(MusicalTime::zero());
+        self.loop_entry = None;
+        let from = MusicalTime::from_sub_beats(now.as_sub_beats() + self.early(song, now));
+
+        let next = track.sequence.partition_point(|e| shifted(e.start, self.shift) <= from);
+        self.seq_idx = next;
+        self.row = 0;
+        self.time = advance_to_seq_entry(track, next, self.shift, from);
+        let Some(entry) = next.checked_sub(1).map(|i| &track.sequence[i]) else { return };
+        let Some(clip) = track.get_pattern_at(entry.clip_idx as usize) else { return };
+        let start = shifted(entry.start, self.shift);
+        let rpb = clip.rows_per_beat.map_or(self.song_rpb, |r| r as u32);
+        let row_len = MusicalTime::zero().add_rows(1, rpb).as_sub_beats().max(1);
+        let row = (from.as_sub_beats() - start.as_sub_beats()).div_ceil(row_len);
+        if row < entry.length.min(clip.rows) as u64 {
+            self.seq_idx = next - 1;
+            self.row = row as u16;
+            self.time = start.add_rows(row as u32, rpb);
+        }
+    }
+
+    /// Schedule the launched clip's current row and move to the next,
+    /// wrapping at the end of the clip. Breaks and jumps are ignored.
+    fn play_launched_row(&mut self, clip_idx: u16, song: &Song, out: &mut Vec<Event>) {
+        let track = &song.tracks[self.track_idx];
+        let Some(clip) = track.get_pattern_at(clip_idx as usize) else {
+            self.launched = None;
+            self.exhausted = true;
+            self.end_time = Some(self.time);
+            return;
+        };
+        let rpb = clip.rows_per_beat.map_or(self.song_rpb, |r| r as u32);
+        if self.row >= clip.rows {
+            self.row = 0;
+        }
+        let tempo = self.offset_tempo(song, self.time);
+        schedule_row(song, track, clip, self.row, self.time, self.speed, rpb, tempo, out);
+
+        let fc = scan_row_flow_control(clip, self.row);
+        if let Some(s) = fc.new_speed {
+            self.speed = s;
+        }
+        self.time = self.time.add_rows(1 + fc.pattern_delay as u32, rpb);
+        self.row = (self.row + 1) % clip.rows.max(1);
+    }
+
+    /// The limit that cut this source short, if any.
+    pub fn truncated(&self) -> Option<LimitHit> {
+        self.truncated
+    }
+
     /// Update the internal speed (called when a SetSpeed event is observed).
     pub fn set_speed(&mut self, speed: u8) {
         self.speed = speed as u32;
@@ -82,13 +275,10 @@ fn compute_max_rows(track: &Track) -> u64 {
     from_clips.max(from_seq) * channels * 2 + 256
 }
 
-/// Resolve effective speed for a pattern.
-fn effective_speed(pattern: &mb_ir::Pattern, global_speed: u32) -> u32 {
-    if pattern.ticks_per_row > 0 {
-        pattern.ticks_per_row as u32
-    } else {
-        global_speed
-    }
+/// Most channels any of the track's clips has.
+fn max_clip_channels(track: &Track) -> usize {
+    let widest = track.clips.iter().filter_map(|c| c.pattern().map(|p| p.channels)).max();
+    widest.unwrap_or(0).max(track.num_channels) as usize
 }
 
 /// Flow control state from a pattern row.
@@ -108,8 +298,8 @@ fn scan_row_flow_control(pattern: &mb_ir::Pattern, row: u16) -> FlowControl {
         pattern_delay: 0,
     };
     if row >= pattern.rows { return fc; }
-    for col in 0..pattern.channels {
-        match pattern.cell(row, col).effect {
+    for effect in (0..pattern.channels).flat_map(|col| pattern.cell(row, col).effects()) {
+        match effect {
             Effect::PatternBreak(r) => fc.break_row = Some(r),
             Effect::PositionJump(p) => fc.jump_order = Some(p),
             Effect::SetSpeed(s) if s > 0 => fc.new_speed = Some(s as u32),
@@ -120,16 +310,33 @@ fn scan_row_flow_control(pattern: &mb_ir::Pattern, row: u16) -> FlowControl {
     fc
 }
 
-/// Get the start time for the next sequence entry.
-fn advance_to_seq_entry(track: &Track, seq_idx: usize, current: MusicalTime) -> MusicalTime {
-    track.sequence.get(seq_idx).map_or(current, |e| e.start)
+/// Get the start time for the next sequence entry, pushed back by the
+/// `shift` sub-beats pattern loops have added so far.
+fn advance_to_seq_entry(track: &Track, seq_idx: usize, shift: u64, current: MusicalTime) -> MusicalTime {
+    track.sequence.get(seq_idx).map_or(current, |e| shifted(e.start, shift))
+}
+
+/// `time` pushed back by `shift` sub-beats.
+fn shifted(time: MusicalTime, shift: u64) -> MusicalTime {
+    MusicalTime::from_sub_beats(time.as_sub_beats() + shift)
 }
 
 impl EventSource for ClipSourceState {
     fn drain_until(&mut self, time: MusicalTime, song: &Song, out: &mut Vec<Event>) -> usize {
         let start_len = out.len();
 
-        while !self.exhausted && self.time <= time {
+        loop {
+            if let Some((at, clip)) = self.queued.filter(|&(at, _)| at <= time && (at <= self.time || self.exhausted)) {
+                self.queued = None;
+                self.launch(at, clip, song, out);
+            }
+            if self.exhausted || self.time.as_sub_beats() > time.as_sub_beats() + self.lookahead(song, self.time) {
+                break;
+            }
+            if let Some(clip) = self.launched {
+                self.play_launched_row(clip, song, out);
+                continue;
+            }
             let track = &song.tracks[self.track_idx];
 
             if self.seq_idx >= track.sequence.len() {
@@ -147,19 +354,19 @@ impl EventSource for ClipSourceState {
                 None => {
                     self.seq_idx += 1;
                     self.row = 0;
-                    self.time = advance_to_seq_entry(track, self.seq_idx, self.time);
+                    self.time = advance_to_seq_entry(track, self.seq_idx, self.shift, self.time);
                     continue;
                 }
             };
 
             let num_rows = entry_length.min(clip.rows);
             let rpb = clip.rows_per_beat.map_or(self.song_rpb, |r| r as u32);
-            let eff_speed = effective_speed(clip, self.speed);
 
             // Check if we've passed the next entry's start
-            let next_start = track.sequence.get(self.seq_idx + 1).map(|e| e.start);
+            let next_start = track.sequence.get(self.seq_idx + 1).map(|e| shifted(e.start, self.shift));
             if let Some(ns) = next_start {
                 if self.time >= ns {
+                    schedule_boundary(track, entry, ns, out);
                     self.seq_idx += 1;
                     self.row = 0;
                     self.time = ns;
@@ -170,20 +377,30 @@ impl EventSource for ClipSourceState {
             if self.row >= num_rows {
                 self.seq_idx += 1;
                 self.row = 0;
-                self.time = advance_to_seq_entry(track, self.seq_idx, self.time);
+                self.time = advance_to_seq_entry(track, self.seq_idx, self.shift, self.time);
                 continue;
             }
+            if self.loop_entry != Some(self.seq_idx) {
+                self.loops.reset(clip.channels);
+                self.loop_entry = Some(self.seq_idx);
+            }
 
-            // Schedule all columns at this row
-            for col in 0..clip.channels {
-                let target = target_for_track_column(track, col);
-                schedule_cell(clip.cell(self.row, col), self.time, target, eff_speed, rpb, out);
+            let emitted = self.events_emitted + (out.len() - start_len);
+            if let Some(hit) = check_limits(&self.limits, self.time, emitted) {
+                self.exhausted = true;
+                self.truncated = Some(hit);
+                self.end_time = Some(self.time.min(self.limits.max_time()));
+                break;
             }
 
+            let tempo = self.offset_tempo(song, self.time);
+            schedule_row(song, track, clip, self.row, self.time, self.speed, rpb, tempo, out);
+
             let fc = scan_row_flow_control(clip, self.row);
             if let Some(s) = fc.new_speed {
                 self.speed = s;
             }
+            let loop_to = self.loops.row(clip, self.row, song.compatibility);
 
             self.time = self.time.add_rows(1 + fc.pattern_delay as u32, rpb);
             self.rows_processed += 1;
@@ -193,30 +410,56 @@ impl EventSource for ClipSourceState {
                 break;
             }
 
-            match (fc.jump_order, fc.break_row) {
-                (Some(pos), Some(r)) => { self.seq_idx = pos as usize; self.row = r as u16; }
-                (Some(pos), None) => { self.seq_idx = pos as usize; self.row = 0; }
-                (None, Some(r)) => { self.seq_idx += 1; self.row = r as u16; }
-                (None, None) => {
+            // A break or jump on the same row wins over a loop
+            let leaves_entry = fc.jump_order.is_some()
+                || fc.break_row.is_some()
+                || (loop_to.is_none() && self.row + 1 >= num_rows);
+            if leaves_entry {
+                schedule_boundary(track, entry, self.time, out);
+            }
+
+            match (fc.jump_order, fc.break_row, loop_to) {
+                (Some(pos), Some(r), _) => { self.seq_idx = track.jump_target(pos); self.row = r as u16; }
+                (Some(pos), None, _) => { self.seq_idx = track.jump_target(pos); self.row = 0; }
+                (None, Some(r), _) => { self.seq_idx += 1; self.row = r as u16; }
+                // Pattern loop: replayed rows don't count toward the loop detection
+                (None, None, Some(start)) => {
+                    let replayed = (self.row + 1).saturating_sub(start);
+                    self.shift += MusicalTime::zero().add_rows(replayed as u32, rpb).as_sub_beats();
+                    self.rows_processed = self.rows_processed.saturating_sub(replayed as u64);
+                    self.row = start;
+                }
+                (None, None, None) => {
                     self.row += 1;
                     if self.row >= num_rows {
                         self.seq_idx += 1;
                         self.row = 0;
-                        self.time = advance_to_seq_entry(track, self.seq_idx, self.time);
+                        self.time = advance_to_seq_entry(track, self.seq_idx, self.shift, self.time);
                     }
                 }
             }
         }
 
+        self.events_emitted += out.len() - start_len;
         out.len() - start_len
     }
 
+    /// Restart from the top of the sequence, or idle in session mode;
+    /// launched clips are dropped. Allocates only if the track gained
+    /// channels.
     fn seek(&mut self, _time: MusicalTime, song: &Song) {
-        *self = Self::new(song, self.track_idx);
+        let loops = core::mem::take(&mut self.loops);
+        let mut fresh = Self::with_loops(song, self.track_idx, self.limits, loops);
+        fresh.tempo_map = self.tempo_map.take();
+        *self = if self.follows_sequence { fresh } else { fresh.into_idle() };
     }
 
     fn peek_time(&self) -> Option<MusicalTime> {
-        if self.exhausted { None } else { Some(self.time) }
+        match self.queued {
+            Some((at, _)) if self.exhausted || at < self.time => Some(at),
+            _ if self.exhausted => None,
+            _ => Some(self.time),
+        }
     }
 }
 
@@ -259,9 +502,9 @@ mod tests {
 
     /// Drain all sources and compare with schedule_song output.
     fn assert_matches_schedule_song(song: &Song) {
-        let expected = scheduler::schedule_song(song);
+        let expected = scheduler::schedule_song(song, 44100);
         let mut expected_events = expected.events;
-        expected_events.sort_by(|a, b| a.time.cmp(&b.time));
+        expected_events.sort_by_key(|a| a.time);
 
         let mut actual_events = Vec::new();
         for track_idx in 0..song.tracks.len() {
@@ -269,7 +512,7 @@ mod tests {
             let far_future = MusicalTime::from_beats(10000);
             source.drain_until(far_future, song, &mut actual_events);
         }
-        actual_events.sort_by(|a, b| a.time.cmp(&b.time));
+        actual_events.sort_by_key(|a| a.time);
 
         assert_eq!(
             actual_events.len(), expected_events.len(),
@@ -379,6 +622,30 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn pattern_loop_matches_scheduler() {
+        let mut pat0 = Pattern::new(4, 2);
+        pat0.cell_mut(1, 0).note = Note::On(60);
+        pat0.cell_mut(1, 0).instrument = 1;
+        pat0.cell_mut(1, 0).effect = Effect::PatternLoop(0);
+        pat0.cell_mut(2, 0).effect = Effect::PatternLoop(2);
+        pat0.cell_mut(3, 1).note = Note::On(62);
+        pat0.cell_mut(3, 1).instrument = 1;
+        let mut pat1 = Pattern::new(4, 2);
+        pat1.cell_mut(0, 0).note = Note::On(64);
+        pat1.cell_mut(0, 0).instrument = 1;
+        let song = song_from(2, vec![pat0, pat1], vec![OrderEntry::Pattern(0), OrderEntry::Pattern(1)]);
+        assert_matches_schedule_song(&song);
+
+        // Rows 0 1 2 1 2 1 2 3 push the second pattern back 4 rows
+        let mut source = ClipSourceState::new(&song, 0);
+        let mut events = Vec::new();
+        source.drain_until(MusicalTime::from_beats(100), &song, &mut events);
+        let notes = events.iter().filter(|e| matches!(e.payload, EventPayload::NoteOn { .. })).count();
+        assert_eq!(notes, 5);
+        assert_eq!(source.end_time(), Some(MusicalTime::from_beats(3)));
+    }
+
     #[test]
     fn set_speed_matches_scheduler() {
         let mut pat = Pattern::new(4, 1);
@@ -461,4 +728,69 @@ mod tests {
         pat.cell_mut(0, 0).effect = Effect::TonePorta(8);
         assert_matches_schedule_song(&one_channel_song(pat));
     }
+
+    #[test]
+    fn clip_boundaries_match_scheduler() {
+        let mut pat = Pattern::new(4, 2);
+        pat.cell_mut(0, 0).note = Note::On(60);
+        pat.cell_mut(0, 0).instrument = 1;
+        let order = vec![OrderEntry::Pattern(0), OrderEntry::Pattern(0), OrderEntry::Pattern(0)];
+        let mut song = song_from(2, vec![pat], order);
+        song.tracks[0].sequence[0].boundary = mb_ir::ClipBoundary::Cut;
+        song.tracks[0].sequence[1].boundary = mb_ir::ClipBoundary::Release;
+        song.tracks[0].sequence[2].start = MusicalTime::from_beats(1).add_rows(2, 4);
+        assert_matches_schedule_song(&song);
+    }
+
+    #[test]
+    fn limits_truncate_like_scheduler() {
+        let mut pat = Pattern::new(64, 1);
+        for r in 0..64 {
+            pat.cell_mut(r, 0).note = Note::On(60);
+            pat.cell_mut(r, 0).instrument = 1;
+        }
+        let song = one_channel_song(pat);
+        for limits in [
+            Limits { max_events: 10, ..Limits::default() },
+            Limits { max_beats: 2, ..Limits::default() },
+        ] {
+            let expected = scheduler::schedule_song_with_limits(&song, &limits, 44100);
+            let mut source = ClipSourceState::with_limits(&song, 0, limits);
+            let mut events = Vec::new();
+            source.drain_until(MusicalTime::from_beats(10000), &song, &mut events);
+            assert_eq!(events.len(), expected.events.len());
+            assert_eq!(source.truncated(), expected.truncated);
+        }
+    }
+
+    #[test]
+    fn launched_clips_loop_from_their_launch_until_stopped() {
+        let mut pat = Pattern::new(4, 1);
+        pat.cell_mut(0, 0).note = Note::On(60);
+        pat.cell_mut(0, 0).instrument = 1;
+        let song = one_channel_song(pat);
+        let beat = MusicalTime::from_beats;
+        let mut source = ClipSourceState::idle(&song, 0, Limits::default());
+        let mut events = Vec::new();
+        let mut drain = |source: &mut ClipSourceState, until| {
+            events.clear();
+            source.drain_until(until, &song, &mut events);
+            let notes = events.iter().filter(|e| matches!(e.payload, EventPayload::NoteOn { .. }));
+            (notes.map(|e| e.time.beat).collect::<Vec<_>>(), events.len())
+        };
+        assert_eq!(drain(&mut source, beat(1)), (vec![], 0));
+
+        source.queue_launch(beat(2), Some(0));
+        assert_eq!(source.peek_time(), Some(beat(2)));
+        let (notes, _) = drain(&mut source, beat(4));
+        assert_eq!(notes, [2, 3, 4]);
+        assert_eq!(source.launched(), Some(0));
+        assert_eq!(source.end_time(), None);
+
+        source.queue_launch(beat(5), None);
+        assert_eq!(drain(&mut source, beat(8)).0, []);
+        assert_eq!(source.end_time(), Some(beat(5)));
+        source.seek(MusicalTime::zero(), &song);
+        assert_eq!(source.peek_time(), None);
+    }
 }
@@ -0,0 +1,106 @@
+//! Playback quirks of the trackers a song may come from.
+//!
+//! A song's `CompatibilityMode` expands into a set of `Quirks` that each
+//! tracker channel consults where the source trackers disagree.
+
+use mb_ir::CompatibilityMode;
+
+/// What a sample offset past the end of the sample does.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OffsetOverflow {
+    /// Stop the note (FT2)
+    #[default]
+    Cut,
+    /// Play from the loop start, or from the last frame of an unlooped
+    /// sample (ProTracker, IT old effects)
+    Clamp,
+}
+
+/// How a zero slide parameter recalls earlier ones (see `MemorySlot`). In
+/// both memory modes the volume slides (including the tone porta and
+/// vibrato combinations) share one parameter.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EffectMemory {
+    /// A zero parameter slides by zero (ProTracker)
+    #[default]
+    None,
+    /// Porta up, porta down, their fine versions and the fine volume
+    /// slides each remember their own parameter (FT2)
+    PerEffect,
+    /// All portamentos share one parameter (IT Exx/Fxx), and fine volume
+    /// slides share the volume slide's (IT Dxy)
+    Shared,
+}
+
+/// What an instrument number without a note does.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InstrumentChange {
+    /// Nothing
+    #[default]
+    Ignore,
+    /// A different sample sets its default volume at once and takes over
+    /// once the playing one loops or ends, without retriggering
+    /// (ProTracker)
+    SwapSample,
+    /// Reset the volume to the sample's default and restart the
+    /// instrument envelopes and fadeout (FT2)
+    ResetEnvelopes,
+}
+
+/// Behavior switches for one tracker channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// Sample offsets past the end of the sample
+    pub offset_overflow: OffsetOverflow,
+    /// Recall of zero slide parameters
+    pub effect_memory: EffectMemory,
+    /// Instrument numbers without a note
+    pub instrument_change: InstrumentChange,
+    /// Vibrato depth multiplier (FT2 doubles it)
+    pub vibrato_depth_scale: u8,
+    /// Length of the declick ramp on gain changes, note starts and cuts,
+    /// in output frames (0 = off)
+    pub volume_ramp: u16,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::for_mode(CompatibilityMode::Native)
+    }
+}
+
+impl Quirks {
+    /// The quirks `mode` reproduces.
+    pub fn for_mode(mode: CompatibilityMode) -> Self {
+        let native = Self {
+            offset_overflow: OffsetOverflow::Cut,
+            effect_memory: EffectMemory::None,
+            instrument_change: InstrumentChange::Ignore,
+            vibrato_depth_scale: 1,
+            volume_ramp: 64,
+        };
+        match mode {
+            CompatibilityMode::Native => native,
+            CompatibilityMode::ProTracker => Self {
+                offset_overflow: OffsetOverflow::Clamp,
+                instrument_change: InstrumentChange::SwapSample,
+                // Paula switches volume and samples without ramping
+                volume_ramp: 0,
+                ..native
+            },
+            CompatibilityMode::FastTracker2 => Self {
+                effect_memory: EffectMemory::PerEffect,
+                instrument_change: InstrumentChange::ResetEnvelopes,
+                vibrato_depth_scale: 2,
+                // FT2 ramps over 5 ms
+                volume_ramp: 220,
+                ..native
+            },
+            CompatibilityMode::ImpulseTracker => Self {
+                offset_overflow: OffsetOverflow::Clamp,
+                effect_memory: EffectMemory::Shared,
+                ..native
+            },
+        }
+    }
+}
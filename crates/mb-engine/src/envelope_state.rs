@@ -1,6 +1,36 @@
 //! Runtime evaluator for `ModEnvelope`.
 
-use mb_ir::{interpolate, ModEnvelope};
+use mb_ir::{interpolate, ModEnvelope, SUB_BEAT_UNIT};
+
+/// Control-rate evaluation: the envelope is advanced once every `interval`
+/// samples and linearly interpolated to audio rate in between.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ControlRate {
+    /// Samples between control updates (at least 1).
+    interval: u16,
+    /// Whole sub-beat units the envelope advances per control update.
+    sub_beats: u32,
+    /// The fraction of a sub-beat left over per update, over `divisor`;
+    /// carried from update to update so the envelope doesn't drift.
+    remainder: u64,
+    divisor: u64,
+}
+
+impl ControlRate {
+    /// Control rate updating every `interval` samples at `tempo`
+    /// (hundredths of a BPM) and `sample_rate`.
+    pub fn new(interval: u16, tempo: u32, sample_rate: u32) -> Self {
+        let interval = interval.max(1);
+        let num = SUB_BEAT_UNIT as u64 * tempo as u64 * interval as u64;
+        let divisor = 6000 * sample_rate.max(1) as u64;
+        Self { interval, sub_beats: (num / divisor) as u32, remainder: num % divisor, divisor }
+    }
+
+    /// Samples between control updates.
+    pub fn interval(&self) -> u16 {
+        self.interval
+    }
+}
 
 /// Runtime state for a playing envelope.
 #[derive(Clone, Debug)]
@@ -17,13 +47,22 @@ pub struct EnvelopeState {
     gate_held: bool,
     /// Whether a loop point was hit on the last advance (for Trigger mode).
     looped: bool,
+    /// Value at the start of the current control interval.
+    ramp_from: f32,
+    /// Sample offset within the current control interval.
+    ramp_phase: u16,
+    /// Sub-beat fraction carried between control updates (see `ControlRate`).
+    carry: u64,
 }
 
 impl EnvelopeState {
     /// Create a new state starting at the first breakpoint.
     pub fn new(envelope: &ModEnvelope) -> Self {
         let value = envelope.points.first().map_or(0.0, |p| p.value);
-        Self { segment: 0, time_in_segment: 0, value, finished: false, gate_held: false, looped: false }
+        Self {
+            segment: 0, time_in_segment: 0, value, finished: false, gate_held: false, looped: false,
+            ramp_from: value, ramp_phase: 0, carry: 0,
+        }
     }
 
     /// Current output value.
@@ -57,6 +96,31 @@ impl EnvelopeState {
         self.resolve(envelope);
     }
 
+    /// Fill `out` with audio-rate values, advancing the envelope at `rate`.
+    ///
+    /// Each control update computes the value at the end of the interval;
+    /// samples inside the interval ramp linearly from the previous value.
+    pub fn render(&mut self, envelope: &ModEnvelope, rate: ControlRate, out: &mut [f32]) {
+        for sample in out {
+            if self.ramp_phase == 0 {
+                self.ramp_from = self.value;
+                let mut delta = rate.sub_beats;
+                self.carry += rate.remainder;
+                if self.carry >= rate.divisor {
+                    self.carry -= rate.divisor;
+                    delta += 1;
+                }
+                self.advance(envelope, delta);
+            }
+            let t = self.ramp_phase as f32 / rate.interval as f32;
+            *sample = self.ramp_from + (self.value - self.ramp_from) * t;
+            self.ramp_phase += 1;
+            if self.ramp_phase >= rate.interval {
+                self.ramp_phase = 0;
+            }
+        }
+    }
+
     /// Walk forward through breakpoints until time_in_segment is within
     /// the current segment, handling loop and sustain.
     fn resolve(&mut self, envelope: &ModEnvelope) {
@@ -300,4 +364,74 @@ mod tests {
         assert!((state.value() - 20.0).abs() < 0.01);
         assert!(state.is_finished());
     }
+
+    // --- Control-rate rendering ---
+
+    #[test]
+    fn control_rate_from_tempo() {
+        // 120 BPM at 48 kHz: 2 beats/s → 16 samples = 1/1500 beat = 480.48 sub-beats
+        let rate = ControlRate::new(16, 12000, 48000);
+        assert_eq!(rate.sub_beats, 480);
+        assert_eq!(rate.remainder * 100 / rate.divisor, 48);
+        assert_eq!(ControlRate::new(0, 12000, 48000).interval(), 1);
+    }
+
+    #[test]
+    fn control_rate_carries_fractional_sub_beats() {
+        // A minute at 30.03 BPM is 30.03 beats, which whole sub-beats per
+        // update alone would fall short of
+        let env = ModEnvelope::one_shot(&[bp(0, 0.0, CurveKind::Linear), bp(40 * SUB_BEAT_UNIT, 40.0, CurveKind::Step)]);
+        let mut state = EnvelopeState::new(&env);
+        let mut out = [0.0f32; 4800];
+        for _ in 0..600 {
+            state.render(&env, ControlRate::new(16, 3003, 48000), &mut out);
+        }
+        assert_eq!(state.time_in_segment, 3003 * SUB_BEAT_UNIT / 100);
+    }
+
+    fn rate(interval: u16, sub_beats: u32) -> ControlRate {
+        ControlRate { interval, sub_beats, remainder: 0, divisor: 1 }
+    }
+
+    #[test]
+    fn render_ramps_linearly_between_updates() {
+        let env = ModEnvelope::one_shot(&[
+            bp(0, 0.0, CurveKind::Linear),
+            bp(400, 4.0, CurveKind::Step),
+        ]);
+        let mut state = EnvelopeState::new(&env);
+        let mut out = [0.0f32; 8];
+        state.render(&env, rate(4, 100), &mut out);
+        let expected = [0.0, 0.25, 0.5, 0.75, 1.0, 1.25, 1.5, 1.75];
+        for (got, want) in out.iter().zip(expected) {
+            assert!((got - want).abs() < 1e-5, "{:?}", out);
+        }
+    }
+
+    #[test]
+    fn render_is_continuous_across_calls() {
+        let env = ModEnvelope::one_shot(&[
+            bp(0, 0.0, CurveKind::Linear),
+            bp(400, 4.0, CurveKind::Step),
+        ]);
+        let rate = rate(4, 100);
+        let mut whole = EnvelopeState::new(&env);
+        let mut expected = [0.0f32; 12];
+        whole.render(&env, rate, &mut expected);
+
+        let mut split = EnvelopeState::new(&env);
+        let mut got = [0.0f32; 12];
+        split.render(&env, rate, &mut got[..5]);
+        split.render(&env, rate, &mut got[5..]);
+        assert_eq!(got, expected);
+    }
+
+    #[test]
+    fn render_step_curve_holds_within_interval() {
+        let env = ModEnvelope::one_shot(&[bp(0, 3.0, CurveKind::Step), bp(1000, 9.0, CurveKind::Step)]);
+        let mut state = EnvelopeState::new(&env);
+        let mut out = [0.0f32; 16];
+        state.render(&env, rate(16, 10), &mut out);
+        assert!(out.iter().all(|&v| v == 3.0));
+    }
 }
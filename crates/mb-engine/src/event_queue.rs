@@ -1,73 +1,179 @@
-//! Priority queue for scheduled events.
+//! Time-bucketed queue for events scheduled ahead of the play head.
+//!
+//! Events live in a slab of preallocated slots. Each slot belongs to the
+//! bucket of its event's beat (modulo `BUCKETS`), where slots are linked in
+//! time order, so inserting only walks the events of one beat and finding
+//! the next event only looks at the bucket heads. Inserting, popping and
+//! removing reuse free slots and never allocate; only `push_or_grow`
+//! makes room, for callers off the audio thread.
 
 use alloc::vec::Vec;
 use mb_ir::{Event, MusicalTime};
 
-/// A priority queue of events sorted by timestamp.
-///
-/// During playback, events are consumed via a cursor that advances forward
-/// without removing elements — making the realtime drain path allocation-free.
-#[allow(dead_code)]
-#[derive(Clone, Debug, Default)]
-pub struct EventQueue {
-    events: Vec<Event>,
-    /// Next event index to process (advances during playback).
-    cursor: usize,
+/// Events a queue holds without growing.
+pub(crate) const EVENT_QUEUE_CAPACITY: usize = 256;
+
+/// Beats covered by one turn of the bucket ring.
+const BUCKETS: usize = 64;
+
+/// End of a slot list.
+const NIL: u32 = u32::MAX;
+
+/// Events sorted by time, with a fixed capacity.
+#[derive(Clone, Debug)]
+pub(crate) struct EventQueue {
+    /// Event storage; `None` for free slots
+    slots: Vec<Option<Event>>,
+    /// Next slot in the same bucket (or in the free list)
+    next: Vec<u32>,
+    /// First slot of each bucket, earliest event first
+    buckets: [u32; BUCKETS],
+    /// First free slot
+    free: u32,
+    len: usize,
+}
+
+impl Default for EventQueue {
+    fn default() -> Self {
+        Self::with_capacity(EVENT_QUEUE_CAPACITY)
+    }
 }
 
-#[allow(dead_code)]
 impl EventQueue {
-    /// Create a new empty event queue.
-    pub fn new() -> Self {
-        Self { events: Vec::new(), cursor: 0 }
-    }
-
-    /// Push an event into the queue.
-    pub fn push(&mut self, event: Event) {
-        // Find insertion point to maintain sorted order
-        let pos = self
-            .events
-            .binary_search_by(|e| e.time.cmp(&event.time))
-            .unwrap_or_else(|pos| pos);
-        self.events.insert(pos, event);
-    }
-
-    /// Return the index range of events at or before `time` (cursor-based, zero allocation).
-    ///
-    /// Advances the internal cursor past all consumed events. The returned
-    /// range can be used to index `self.events` directly.
-    pub fn drain_until(&mut self, time: MusicalTime) -> core::ops::Range<usize> {
-        let start = self.cursor;
-        while self.cursor < self.events.len() {
-            if self.events[self.cursor].time <= time {
-                self.cursor += 1;
-            } else {
-                break;
+    /// An empty queue with room for `capacity` events.
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        let mut queue = Self { slots: Vec::new(), next: Vec::new(), buckets: [NIL; BUCKETS], free: NIL, len: 0 };
+        queue.add_slots(capacity);
+        queue
+    }
+
+    /// Whether another event fits without growing.
+    pub(crate) fn has_room(&self) -> bool {
+        self.free != NIL
+    }
+
+    /// Insert `event` after any others at the same time. Hands the event
+    /// back when the queue is full; never allocates.
+    pub(crate) fn push(&mut self, event: Event) -> Result<(), Event> {
+        if self.free == NIL {
+            return Err(event);
+        }
+        let slot = self.free;
+        self.free = self.next[slot as usize];
+
+        let bucket = bucket_of(event.time);
+        let mut prev = NIL;
+        let mut cur = self.buckets[bucket];
+        while cur != NIL && self.time_of(cur) <= event.time {
+            prev = cur;
+            cur = self.next[cur as usize];
+        }
+        self.next[slot as usize] = cur;
+        match prev {
+            NIL => self.buckets[bucket] = slot,
+            prev => self.next[prev as usize] = slot,
+        }
+        self.slots[slot as usize] = Some(event);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Insert `event`, doubling the capacity first if the queue is full.
+    /// Allocates; not for the audio thread.
+    pub(crate) fn push_or_grow(&mut self, event: Event) {
+        if !self.has_room() {
+            self.add_slots(self.slots.len().max(1));
+        }
+        let _ = self.push(event);
+    }
+
+    /// Time of the earliest event.
+    pub(crate) fn next_time(&self) -> Option<MusicalTime> {
+        self.earliest_bucket().map(|b| self.time_of(self.buckets[b]))
+    }
+
+    /// Remove and return the earliest event if it is due at `time`.
+    pub(crate) fn pop_due(&mut self, time: MusicalTime) -> Option<Event> {
+        let bucket = self.earliest_bucket()?;
+        let slot = self.buckets[bucket];
+        if self.time_of(slot) > time {
+            return None;
+        }
+        self.buckets[bucket] = self.next[slot as usize];
+        Some(self.release(slot))
+    }
+
+    /// Keep only the events `f` returns true for (`f` may modify them
+    /// without changing their time).
+    pub(crate) fn retain_mut(&mut self, mut f: impl FnMut(&mut Event) -> bool) {
+        for bucket in 0..BUCKETS {
+            let mut prev = NIL;
+            let mut cur = self.buckets[bucket];
+            while cur != NIL {
+                let next = self.next[cur as usize];
+                if self.slots[cur as usize].as_mut().is_some_and(&mut f) {
+                    prev = cur;
+                } else {
+                    match prev {
+                        NIL => self.buckets[bucket] = next,
+                        prev => self.next[prev as usize] = next,
+                    }
+                    self.release(cur);
+                }
+                cur = next;
             }
         }
-        start..self.cursor
     }
 
-    /// Get an event by index (for use with `drain_until` ranges).
-    pub fn get(&self, index: usize) -> Option<&Event> {
-        self.events.get(index)
+    /// Drop all events, keeping the capacity.
+    pub(crate) fn clear(&mut self) {
+        self.retain_mut(|_| false);
     }
 
-    /// Reset cursor to the beginning (called after scheduling).
-    pub fn reset_cursor(&mut self) {
-        self.cursor = 0;
+    /// The queued events, in no particular order.
+    #[cfg(test)]
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Event> {
+        self.slots.iter().flatten()
+    }
+
+    /// Bucket whose head is the earliest event.
+    fn earliest_bucket(&self) -> Option<usize> {
+        if self.len == 0 {
+            return None;
+        }
+        (0..BUCKETS)
+            .filter(|&b| self.buckets[b] != NIL)
+            .min_by_key(|&b| self.time_of(self.buckets[b]))
     }
 
-    /// Clear all events and reset cursor.
-    pub fn clear(&mut self) {
-        self.events.clear();
-        self.cursor = 0;
+    fn time_of(&self, slot: u32) -> MusicalTime {
+        self.slots[slot as usize].as_ref().map_or(MusicalTime::zero(), |e| e.time)
     }
 
-    /// Retain only events matching the predicate, removing the rest.
-    pub fn retain<F: FnMut(&Event) -> bool>(&mut self, f: F) {
-        self.events.retain(f);
+    /// Take the event out of an unlinked slot and free the slot.
+    fn release(&mut self, slot: u32) -> Event {
+        let event = self.slots[slot as usize].take();
+        self.next[slot as usize] = self.free;
+        self.free = slot;
+        self.len -= 1;
+        event.expect("queued slot holds an event")
     }
+
+    /// Append `count` free slots.
+    fn add_slots(&mut self, count: usize) {
+        let first = self.slots.len();
+        self.slots.resize(first + count, None);
+        self.next.resize(first + count, NIL);
+        for slot in (first..first + count).rev() {
+            self.next[slot] = self.free;
+            self.free = slot as u32;
+        }
+    }
+}
+
+/// Bucket holding events at `time`.
+fn bucket_of(time: MusicalTime) -> usize {
+    (time.beat % BUCKETS as u64) as usize
 }
 
 #[cfg(test)]
@@ -75,69 +181,50 @@ mod tests {
     use super::*;
     use mb_ir::{EventPayload, EventTarget};
 
-    #[test]
-    fn event_ordering() {
-        let mut queue = EventQueue::new();
-
-        queue.push(Event::new(
-            MusicalTime::from_beats(10),
-            EventTarget::Global,
-            EventPayload::SetTempo(12500),
-        ));
-        queue.push(Event::new(
-            MusicalTime::from_beats(5),
-            EventTarget::Global,
-            EventPayload::SetSpeed(6),
-        ));
-        queue.push(Event::new(
-            MusicalTime::from_beats(15),
-            EventTarget::Global,
-            EventPayload::SetTempo(14000),
-        ));
-
-        let range = queue.drain_until(MusicalTime::from_beats(20));
-        assert_eq!(range, 0..3);
-        assert_eq!(queue.get(0).unwrap().time.beat, 5);
-        assert_eq!(queue.get(1).unwrap().time.beat, 10);
-        assert_eq!(queue.get(2).unwrap().time.beat, 15);
+    fn speed_at(beat: u64, sub_beat: u32, speed: u8) -> Event {
+        Event::new(MusicalTime { beat, sub_beat }, EventTarget::Global, EventPayload::SetSpeed(speed))
     }
 
-    #[test]
-    fn drain_until_returns_range() {
-        let mut queue = EventQueue::new();
-        queue.push(Event::new(MusicalTime::from_beats(5), EventTarget::Global, EventPayload::SetSpeed(6)));
-        queue.push(Event::new(MusicalTime::from_beats(10), EventTarget::Global, EventPayload::SetTempo(12500)));
-        queue.push(Event::new(MusicalTime::from_beats(15), EventTarget::Global, EventPayload::SetTempo(14000)));
-
-        let range = queue.drain_until(MusicalTime::from_beats(12));
-        assert_eq!(range, 0..2);
-        assert_eq!(queue.get(0).unwrap().time.beat, 5);
-        assert_eq!(queue.get(1).unwrap().time.beat, 10);
+    fn speeds_due(queue: &mut EventQueue, time: MusicalTime) -> Vec<u8> {
+        core::iter::from_fn(|| queue.pop_due(time))
+            .map(|e| match e.payload {
+                EventPayload::SetSpeed(s) => s,
+                _ => unreachable!(),
+            })
+            .collect()
     }
 
     #[test]
-    fn drain_until_advances_cursor() {
-        let mut queue = EventQueue::new();
-        queue.push(Event::new(MusicalTime::from_beats(5), EventTarget::Global, EventPayload::SetSpeed(6)));
-        queue.push(Event::new(MusicalTime::from_beats(10), EventTarget::Global, EventPayload::SetTempo(12500)));
-
-        let r1 = queue.drain_until(MusicalTime::from_beats(7));
-        assert_eq!(r1, 0..1);
-
-        let r2 = queue.drain_until(MusicalTime::from_beats(15));
-        assert_eq!(r2, 1..2);
+    fn pops_due_events_in_time_then_insertion_order() {
+        let mut queue = EventQueue::default();
+        // Beats 1 and 65 share a bucket
+        for event in [speed_at(65, 0, 1), speed_at(10, 0, 2), speed_at(1, 0, 3), speed_at(10, 0, 4), speed_at(1, 5, 5)] {
+            queue.push(event).unwrap();
+        }
+        assert_eq!(queue.next_time(), Some(MusicalTime::from_beats(1)));
+        assert_eq!(speeds_due(&mut queue, MusicalTime::from_beats(12)), [3, 5, 2, 4]);
+        assert_eq!(queue.iter().count(), 1);
+        assert_eq!(speeds_due(&mut queue, MusicalTime::from_beats(100)), [1]);
+        assert_eq!(queue.next_time(), None);
     }
 
     #[test]
-    fn reset_cursor_allows_replay() {
-        let mut queue = EventQueue::new();
-        queue.push(Event::new(MusicalTime::from_beats(1), EventTarget::Global, EventPayload::SetSpeed(6)));
-
-        let r1 = queue.drain_until(MusicalTime::from_beats(5));
-        assert_eq!(r1.len(), 1);
-
-        queue.reset_cursor();
-        let r2 = queue.drain_until(MusicalTime::from_beats(5));
-        assert_eq!(r2.len(), 1);
+    fn full_queue_refuses_until_grown_or_drained() {
+        let mut queue = EventQueue::with_capacity(2);
+        queue.push(speed_at(3, 0, 1)).unwrap();
+        queue.push(speed_at(2, 0, 2)).unwrap();
+        assert!(!queue.has_room());
+        assert!(queue.push(speed_at(1, 0, 3)).is_err());
+
+        queue.retain_mut(|e| e.time.beat != 2);
+        assert!(queue.has_room());
+        queue.push(speed_at(1, 0, 3)).unwrap();
+        queue.push_or_grow(speed_at(0, 0, 4));
+        assert_eq!(queue.iter().count(), 3);
+        assert_eq!(speeds_due(&mut queue, MusicalTime::from_beats(5)), [4, 3, 1]);
+
+        queue.push(speed_at(0, 0, 5)).unwrap();
+        queue.clear();
+        assert_eq!((queue.iter().count(), queue.next_time()), (0, None));
     }
 }
@@ -2,6 +2,10 @@
 //!
 //! Converts a MIDI note number + sample c4_speed + output sample rate
 //! into a 16.16 fixed-point increment for stepping through sample data.
+//!
+//! Two period scales are supported: Amiga periods (MOD/S3M, slides are
+//! uneven across octaves) and XM-style linear periods (64 units per
+//! semitone, so slides sound the same in every octave).
 
 /// The MIDI note number that corresponds to a sample's c4_speed.
 /// In MOD files, period 428 (Amiga C-2) maps to note 48 via period_to_note.
@@ -16,6 +20,21 @@ pub const PERIOD_MIN: u16 = 113;
 /// Highest allowed period (lowest pitch, C-1 in Amiga notation).
 pub const PERIOD_MAX: u16 = 856;
 
+/// Linear period per semitone.
+const LINEAR_SEMITONE: i32 = 64;
+
+/// Linear period of note 0; each note up is `LINEAR_SEMITONE` lower.
+const LINEAR_NOTE_ZERO: i32 = 120 * LINEAR_SEMITONE;
+
+/// Linear period of the reference note (plays at c4_speed).
+const LINEAR_C4_PERIOD: i32 = LINEAR_NOTE_ZERO - REFERENCE_NOTE as i32 * LINEAR_SEMITONE;
+
+/// Lowest allowed linear period (note 119).
+pub const LINEAR_PERIOD_MIN: u16 = LINEAR_SEMITONE as u16;
+
+/// Highest allowed linear period (note 0).
+pub const LINEAR_PERIOD_MAX: u16 = LINEAR_NOTE_ZERO as u16;
+
 /// Base periods for the lowest MOD octave (notes 36-47, C-1 to B-1 in Amiga notation).
 const BASE_PERIODS: [u16; 12] = [
     856, 808, 762, 720, 678, 640, 604, 570, 538, 508, 480, 453,
@@ -40,6 +59,34 @@ pub fn note_to_period(note: u8) -> u16 {
     }
 }
 
+/// Convert a MIDI note to a linear period (64 units per semitone).
+///
+/// Note 48 = 4608, the XM linear period for C-4. Returns 0 for note 0 (no note).
+pub fn note_to_linear_period(note: u8) -> u16 {
+    if note == 0 {
+        return 0;
+    }
+    (LINEAR_NOTE_ZERO - note.min(119) as i32 * LINEAR_SEMITONE) as u16
+}
+
+/// Convert a linear period + c4_speed to a 16.16 fixed-point increment.
+///
+/// Formula: freq = c4_speed * 2^((4608 - period) / 768).
+pub fn linear_period_to_increment(period: u16, c4_speed: u32, sample_rate: u32) -> u64 {
+    if period == 0 || sample_rate == 0 {
+        return 0;
+    }
+    let shift = LINEAR_C4_PERIOD - period as i32;
+    let octaves = shift.div_euclid(12 * LINEAR_SEMITONE);
+    let within = shift.rem_euclid(12 * LINEAR_SEMITONE);
+    let semitone = SEMITONE_MUL[(within / LINEAR_SEMITONE) as usize] as u64;
+    let fine = FINE_MUL[(within % LINEAR_SEMITONE) as usize] as u64;
+    // freq * 65536
+    let freq = c4_speed as u64 * ((semitone * fine) >> 16);
+    let freq = if octaves >= 0 { freq << octaves } else { freq >> -octaves };
+    freq / sample_rate as u64
+}
+
 /// Convert an Amiga period + c4_speed to a 16.16 fixed-point increment.
 ///
 /// Formula: freq = c4_speed * 428 / period, then increment = freq * 65536 / sample_rate.
@@ -73,6 +120,35 @@ pub fn note_to_increment(note: u8, c4_speed: u32, sample_rate: u32) -> u64 {
     (freq as u64 * 65536) / sample_rate as u64
 }
 
+/// Multipliers for 0-11 semitones, scaled by 65536 (16.16 fixed-point)
+/// semitone_multiplier[n] = round(2^(n/12) * 65536)
+const SEMITONE_MUL: [u32; 12] = [
+    65536, // 0:  1.0
+    69433, // 1:  2^(1/12)
+    73562, // 2:  2^(2/12)
+    77936, // 3:  2^(3/12)
+    82570, // 4:  2^(4/12)
+    87480, // 5:  2^(5/12)
+    92682, // 6:  2^(6/12)
+    98193, // 7:  2^(7/12)
+    104032, // 8: 2^(8/12)
+    110218, // 9: 2^(9/12)
+    116772, // 10: 2^(10/12)
+    123715, // 11: 2^(11/12)
+];
+
+/// Multipliers for 0-63 64ths of a semitone: round(2^(n/768) * 65536).
+const FINE_MUL: [u32; 64] = [
+    65536, 65595, 65654, 65714, 65773, 65832, 65892, 65951,
+    66011, 66071, 66130, 66190, 66250, 66309, 66369, 66429,
+    66489, 66549, 66609, 66670, 66730, 66790, 66850, 66911,
+    66971, 67032, 67092, 67153, 67213, 67274, 67335, 67395,
+    67456, 67517, 67578, 67639, 67700, 67761, 67823, 67884,
+    67945, 68007, 68068, 68129, 68191, 68252, 68314, 68376,
+    68438, 68499, 68561, 68623, 68685, 68747, 68809, 68871,
+    68933, 68996, 69058, 69120, 69183, 69245, 69308, 69370,
+];
+
 /// Shift a frequency by a number of semitones using 12-TET.
 /// Positive = higher pitch, negative = lower pitch.
 fn shift_frequency(base_freq: u32, semitones: i16) -> u32 {
@@ -83,23 +159,6 @@ fn shift_frequency(base_freq: u32, semitones: i16) -> u32 {
     let octaves = semitones.div_euclid(12);
     let remainder = semitones.rem_euclid(12) as usize;
 
-    // Multipliers for 0-11 semitones, scaled by 65536 (16.16 fixed-point)
-    // semitone_multiplier[n] = round(2^(n/12) * 65536)
-    const SEMITONE_MUL: [u32; 12] = [
-        65536, // 0:  1.0
-        69433, // 1:  2^(1/12)
-        73562, // 2:  2^(2/12)
-        77936, // 3:  2^(3/12)
-        82570, // 4:  2^(4/12)
-        87480, // 5:  2^(5/12)
-        92682, // 6:  2^(6/12)
-        98193, // 7:  2^(7/12)
-        104032, // 8: 2^(8/12)
-        110218, // 9: 2^(9/12)
-        116772, // 10: 2^(10/12)
-        123715, // 11: 2^(11/12)
-    ];
-
     // freq = base_freq * 2^octaves * semitone_multiplier / 65536
     let scaled = base_freq as u64 * SEMITONE_MUL[remainder] as u64;
     let freq = scaled >> 16; // divide by 65536
@@ -274,4 +333,31 @@ mod tests {
     fn clamp_period_above_max() {
         assert_eq!(clamp_period(1000), PERIOD_MAX);
     }
+
+    #[test]
+    fn linear_period_of_reference_note() {
+        assert_eq!(note_to_linear_period(48), 4608);
+        assert_eq!(note_to_linear_period(49), 4608 - 64);
+        assert_eq!(note_to_linear_period(0), 0);
+    }
+
+    #[test]
+    fn linear_period_matches_note_increment() {
+        for note in [24, 36, 48, 55, 60, 71, 84] {
+            let linear = linear_period_to_increment(note_to_linear_period(note), C4_SPEED, SAMPLE_RATE);
+            let direct = note_to_increment(note, C4_SPEED, SAMPLE_RATE);
+            assert!(linear.abs_diff(direct) <= 2, "note {}: {} vs {}", note, linear, direct);
+        }
+    }
+
+    #[test]
+    fn linear_slide_is_even_across_octaves() {
+        // The same 32-unit slide is half a semitone in any octave
+        for note in [36, 60, 84] {
+            let period = note_to_linear_period(note);
+            let base = linear_period_to_increment(period, C4_SPEED, SAMPLE_RATE) as f64;
+            let slid = linear_period_to_increment(period - 32, C4_SPEED, SAMPLE_RATE) as f64;
+            assert!((slid / base - 2f64.powf(1.0 / 24.0)).abs() < 1e-3);
+        }
+    }
 }
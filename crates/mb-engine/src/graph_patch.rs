@@ -0,0 +1,93 @@
+//! Graph edits prepared off the audio thread.
+//!
+//! Adding, removing or rewiring a node rebuilds the graph's buffers and
+//! traversal order, and adding one starts its machine. `GraphPatch::new`
+//! does all of that on the calling thread; a playing engine then swaps the
+//! result in with `Engine::apply_graph_patch` without allocating, leaving
+//! what it replaced in the patch to be dropped back where it came from.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use mb_ir::{AudioGraph, Edit, Song, WireLane};
+
+use crate::graph_state::GraphState;
+use crate::machine::Machine;
+use crate::machines;
+use crate::mixer::init_machine;
+
+/// A graph edit ready to swap into a playing engine.
+pub struct GraphPatch {
+    /// The graph edit this patch applies
+    pub(crate) edit: Edit,
+    /// The graph after the edit; the engine's old graph once applied
+    pub(crate) graph: AudioGraph,
+    /// Buffers and traversal order for `graph`
+    pub(crate) graph_state: GraphState,
+    /// The added node's machine, or the removed node's once applied
+    pub(crate) machine: Option<Box<dyn Machine>>,
+    /// Room for the engine's machines and bypass flags with the added node
+    pub(crate) machines: Vec<Option<Box<dyn Machine>>>,
+    pub(crate) node_bypass: Vec<bool>,
+    /// Room for the wire lanes the removed node drove
+    pub(crate) wire_lanes: Vec<WireLane>,
+    /// Render pool job buffers widened for the added machine, if it needs
+    /// more channels than the graph's buses
+    #[cfg(feature = "std")]
+    pub(crate) pool_buffers: Vec<mb_ir::AudioBuffer>,
+}
+
+impl GraphPatch {
+    /// Prepare `edit` for an engine playing `song` (as it is before the
+    /// edit) at `sample_rate`.
+    ///
+    /// Returns `None` for edits that don't change the graph's shape, and
+    /// for graph edits that don't apply to `song`.
+    pub fn new(song: &Song, edit: &Edit, sample_rate: u32) -> Option<Box<Self>> {
+        let mut graph = song.graph.clone();
+        let mut machine = None;
+        let mut wire_lanes = Vec::new();
+        match edit {
+            Edit::AddNode { node_type } => {
+                let id = machines::add_node(&mut graph, node_type.clone());
+                machine = init_machine(song, &graph.nodes[id as usize], sample_rate);
+            }
+            Edit::RemoveNode { node } => {
+                if !graph.remove_node(*node) {
+                    return None;
+                }
+                wire_lanes.reserve_exact(song.wire_lanes_from(*node));
+            }
+            Edit::Connect { from, to, gain } if graph.try_connect(*from, *to, *gain) => {}
+            Edit::Disconnect { from, to } if graph.disconnect(*from, *to) => {}
+            _ => return None,
+        }
+
+        let nodes = graph.nodes.len();
+        let mut graph_state = GraphState::from_graph(&graph);
+        // The engine fills in the other machines' layouts as it swaps this in
+        let mut configs: Vec<_> = (0..nodes).map(|_| None).collect();
+        if let (Some(machine), Some(last)) = (&machine, configs.last_mut()) {
+            *last = Some(machine.channel_config());
+        }
+        graph_state.negotiate_channels(&configs);
+        #[cfg(feature = "std")]
+        let pool_buffers = crate::render_pool::job_buffers(graph_state.scratch.channels());
+
+        Some(Box::new(Self {
+            edit: edit.clone(),
+            graph,
+            graph_state,
+            machine,
+            machines: Vec::with_capacity(nodes),
+            node_bypass: Vec::with_capacity(nodes),
+            wire_lanes,
+            #[cfg(feature = "std")]
+            pool_buffers,
+        }))
+    }
+
+    /// The graph edit this patch applies.
+    pub fn edit(&self) -> &Edit {
+        &self.edit
+    }
+}
@@ -3,7 +3,12 @@
 use alloc::vec;
 use alloc::vec::Vec;
 
-use mb_ir::{AudioBuffer, AudioGraph, BLOCK_SIZE, NodeId};
+use mb_ir::{AudioBuffer, AudioGraph, ChannelConfig, Connection, BLOCK_SIZE, NodeId, NodeType};
+
+/// Channel count of the buses between graph nodes.
+pub const BUS_CHANNELS: u16 = 2;
+
+const STEREO: ChannelConfig = ChannelConfig { inputs: BUS_CHANNELS, outputs: BUS_CHANNELS };
 
 /// Runtime state for the audio graph during playback.
 pub struct GraphState {
@@ -11,11 +16,49 @@ pub struct GraphState {
     pub node_outputs: Vec<AudioBuffer>,
     /// Pre-computed topological traversal order (sources first, Master last).
     pub topo_order: Vec<NodeId>,
+    /// Nodes grouped by depth: every node's inputs lie in earlier groups,
+    /// so the nodes of one group can render in any order, or at once.
+    pub levels: Vec<Vec<NodeId>>,
     /// Scratch buffer for gather_inputs (avoids borrow conflicts).
     pub scratch: AudioBuffer,
-    /// Pre-indexed connections by destination node: `conn_by_dest[node_id] = [(from, gain)]`.
-    /// Gains are precomputed to linear scale at init time.
-    pub conn_by_dest: Vec<Vec<(NodeId, f32)>>,
+    /// Pre-indexed connections by destination node: `conn_by_dest[node_id] = [(from, [left, right])]`.
+    /// Wire gain and pan are precomputed to linear per-side gains at init time.
+    pub conn_by_dest: Vec<Vec<(NodeId, [f32; 2])>>,
+    /// Delay lines for feedback sends (indexed by NodeId; `Some` only for sends).
+    pub feedback: Vec<Option<FeedbackLine>>,
+    /// Negotiated machine channel layouts (indexed by NodeId; stereo by default).
+    pub node_channels: Vec<ChannelConfig>,
+}
+
+/// Fixed one-block delay between a feedback send and its return.
+pub struct FeedbackLine {
+    ring: Vec<[f32; 2]>,
+    pos: usize,
+}
+
+impl FeedbackLine {
+    fn new() -> Self {
+        Self { ring: vec![[0.0; 2]; BLOCK_SIZE], pos: 0 }
+    }
+
+    /// Write the delayed signal for the next `frames` frames into `out`.
+    pub fn read(&self, out: &mut AudioBuffer, frames: usize) {
+        let (left, right) = out.channels_mut_2(0, 1);
+        for i in 0..frames {
+            let [l, r] = self.ring[(self.pos + i) % BLOCK_SIZE];
+            left[i] = l;
+            right[i] = r;
+        }
+    }
+
+    /// Store `frames` frames of send input and advance past them.
+    pub fn write(&mut self, input: &AudioBuffer, frames: usize) {
+        let (left, right) = (input.channel(0), input.channel(1));
+        for i in 0..frames {
+            self.ring[(self.pos + i) % BLOCK_SIZE] = [left[i], right[i]];
+        }
+        self.pos = (self.pos + frames) % BLOCK_SIZE;
+    }
 }
 
 impl GraphState {
@@ -25,14 +68,62 @@ impl GraphState {
         let topo_order = topological_sort(graph);
         let n = graph.nodes.len();
         let conn_by_dest = index_connections_by_dest(graph, n);
+        let levels = group_levels(&topo_order, &conn_by_dest);
         let frames = BLOCK_SIZE as u16;
         Self {
             node_outputs: (0..n)
                 .map(|_| AudioBuffer::new(2, frames))
                 .collect(),
             topo_order,
+            levels,
             scratch: AudioBuffer::new(2, frames),
             conn_by_dest,
+            feedback: graph.nodes.iter()
+                .map(|n| matches!(n.node_type, NodeType::FeedbackSend).then(FeedbackLine::new))
+                .collect(),
+            node_channels: vec![STEREO; n],
+        }
+    }
+
+    /// Record each machine's channel layout and widen the scratch buffer to fit
+    /// the largest one. Call at init time, not from the audio thread.
+    pub fn negotiate_channels(&mut self, configs: &[Option<ChannelConfig>]) {
+        self.node_channels = (0..self.node_outputs.len())
+            .map(|i| configs.get(i).copied().flatten().unwrap_or(STEREO))
+            .collect();
+        let widest = self.node_channels.iter()
+            .map(|c| c.inputs.max(c.outputs))
+            .fold(BUS_CHANNELS, u16::max);
+        if widest > self.scratch.channels() {
+            self.scratch = AudioBuffer::new(widest, BLOCK_SIZE as u16);
+        }
+    }
+
+    /// Update the precomputed gains of wire `conn` in place after its gain
+    /// or pan changed, without allocating.
+    pub fn update_wire(&mut self, conn: &Connection) {
+        let Some(inputs) = self.conn_by_dest.get_mut(conn.to as usize) else { return };
+        for (_, gains) in inputs.iter_mut().filter(|(src, _)| *src == conn.from) {
+            *gains = wire_gains(conn);
+        }
+    }
+
+    /// Fill a feedback return's output with its send's delayed signal.
+    pub fn read_feedback(&mut self, send: NodeId, ret: NodeId, frames: usize) {
+        let Some(Some(line)) = self.feedback.get(send as usize) else { return };
+        if let Some(out) = self.node_outputs.get_mut(ret as usize) {
+            line.read(out, frames);
+        }
+    }
+
+    /// Push every send's gathered input into its delay line.
+    ///
+    /// Called once per rendered block, after all returns have been read.
+    pub fn commit_feedback(&mut self, frames: usize) {
+        for (id, slot) in self.feedback.iter_mut().enumerate() {
+            if let Some(line) = slot {
+                line.write(&self.node_outputs[id], frames);
+            }
         }
     }
 
@@ -44,10 +135,17 @@ impl GraphState {
     }
 }
 
+/// Whether `id` is a feedback send, whose outgoing wires are cut edges.
+fn is_feedback_send(graph: &AudioGraph, id: NodeId) -> bool {
+    graph.node(id).is_some_and(|n| matches!(n.node_type, NodeType::FeedbackSend))
+}
+
 /// Topological sort via Kahn's algorithm.
 ///
 /// Returns nodes ordered so that every source appears before its consumers.
 /// For a typical MOD file: [Chan0, Chan1, Chan2, Chan3, Master].
+/// Wires out of a feedback send are ignored, so loops closed through a
+/// send/return pair sort like a DAG.
 pub fn topological_sort(graph: &AudioGraph) -> Vec<NodeId> {
     let n = graph.nodes.len();
     if n == 0 {
@@ -57,7 +155,7 @@ pub fn topological_sort(graph: &AudioGraph) -> Vec<NodeId> {
     // Build in-degree map
     let mut in_degree = vec![0u32; n];
     for conn in &graph.connections {
-        if (conn.to as usize) < n {
+        if (conn.to as usize) < n && !is_feedback_send(graph, conn.from) {
             in_degree[conn.to as usize] += 1;
         }
     }
@@ -74,7 +172,7 @@ pub fn topological_sort(graph: &AudioGraph) -> Vec<NodeId> {
 
         // Decrement in-degree of successors
         for conn in &graph.connections {
-            if conn.from == node_id && (conn.to as usize) < n {
+            if conn.from == node_id && (conn.to as usize) < n && !is_feedback_send(graph, node_id) {
                 in_degree[conn.to as usize] -= 1;
                 if in_degree[conn.to as usize] == 0 {
                     queue.push(conn.to);
@@ -87,6 +185,26 @@ pub fn topological_sort(graph: &AudioGraph) -> Vec<NodeId> {
     result
 }
 
+/// Group `topo_order` by depth: sources at 0, every other node one past
+/// its deepest input.
+fn group_levels(topo_order: &[NodeId], conn_by_dest: &[Vec<(NodeId, [f32; 2])>]) -> Vec<Vec<NodeId>> {
+    let mut depth = vec![0usize; conn_by_dest.len()];
+    let mut levels: Vec<Vec<NodeId>> = Vec::new();
+    for &id in topo_order {
+        let d = conn_by_dest[id as usize].iter()
+            .filter_map(|&(from, _)| depth.get(from as usize))
+            .map(|&d| d + 1)
+            .max()
+            .unwrap_or(0);
+        depth[id as usize] = d;
+        if levels.len() <= d {
+            levels.resize_with(d + 1, Vec::new);
+        }
+        levels[d].push(id);
+    }
+    levels
+}
+
 /// Convert wire gain to linear scale.
 /// `gain` is stored as `(ratio * 100 - 100)` where 0 = unity.
 /// Clamps to minimum 0.0 to prevent negative gain from zero-amplitude wires.
@@ -94,21 +212,70 @@ fn gain_linear(gain: i16) -> f32 {
     ((gain as f32 + 100.0) / 100.0).max(0.0)
 }
 
+/// Linear `[left, right]` gains for a wire's gain and pan.
+///
+/// Pan is a balance control: the centre leaves both sides at the wire
+/// gain, and moving off centre fades the opposite side out.
+fn wire_gains(conn: &Connection) -> [f32; 2] {
+    let gain = gain_linear(conn.gain);
+    let pan = conn.pan.clamp(-64, 64) as f32 / 64.0;
+    [gain * (1.0 - pan).min(1.0), gain * (1.0 + pan).min(1.0)]
+}
+
 /// Pre-index connections by destination node with precomputed linear gains.
-fn index_connections_by_dest(graph: &AudioGraph, n: usize) -> Vec<Vec<(NodeId, f32)>> {
+fn index_connections_by_dest(graph: &AudioGraph, n: usize) -> Vec<Vec<(NodeId, [f32; 2])>> {
     let mut by_dest = vec![Vec::new(); n];
     for conn in &graph.connections {
-        if (conn.to as usize) < n {
-            by_dest[conn.to as usize].push((conn.from, gain_linear(conn.gain)));
+        if (conn.to as usize) < n && !is_feedback_send(graph, conn.from) {
+            by_dest[conn.to as usize].push((conn.from, wire_gains(conn)));
         }
     }
     by_dest
 }
 
+/// Convert `buf` in place from a `from`-channel to a `to`-channel layout.
+///
+/// Mono targets get the average of all channels (stereo→mono sum), mono
+/// sources are split to every channel, narrower targets fold extra channels
+/// onto `ch % to`, and wider targets get silent extra channels. A count of
+/// zero (generator input) means no conversion. `buf` must hold
+/// `max(from, to)` channels.
+pub fn adapt_channels(buf: &mut AudioBuffer, from: u16, to: u16) {
+    if from == 0 || to == 0 || from == to {
+        return;
+    }
+    if to == 1 {
+        let scale = 1.0 / from as f32;
+        for ch in 1..from {
+            let (mono, other) = buf.channels_mut_2(0, ch);
+            for (m, o) in mono.iter_mut().zip(other.iter()) {
+                *m += *o;
+            }
+        }
+        buf.channel_mut(0).iter_mut().for_each(|s| *s *= scale);
+    } else if from == 1 {
+        for ch in 1..to {
+            let (mono, dst) = buf.channels_mut_2(0, ch);
+            dst.copy_from_slice(mono);
+        }
+    } else if to < from {
+        for ch in to..from {
+            let (dst, src) = buf.channels_mut_2(ch % to, ch);
+            for (d, s) in dst.iter_mut().zip(src.iter()) {
+                *d += *s;
+            }
+        }
+    } else {
+        for ch in from..to {
+            buf.channel_mut(ch).fill(0.0);
+        }
+    }
+}
+
 /// Gather input buffers from all connections feeding into `node_id`.
 /// Uses pre-indexed connections for O(inputs) instead of O(all_connections).
 pub fn gather_inputs(
-    conn_by_dest: &[Vec<(NodeId, f32)>],
+    conn_by_dest: &[Vec<(NodeId, [f32; 2])>],
     node_outputs: &[AudioBuffer],
     node_id: NodeId,
     scratch: &mut AudioBuffer,
@@ -118,9 +285,9 @@ pub fn gather_inputs(
         Some(v) => v,
         None => return,
     };
-    for &(from, gain) in inputs {
+    for &(from, gains) in inputs {
         if let Some(src) = node_outputs.get(from as usize) {
-            scratch.mix_from_scaled(src, gain);
+            scratch.mix_from_panned(src, gains);
         }
     }
 }
@@ -130,7 +297,7 @@ mod tests {
     use super::*;
     use mb_ir::{AudioGraph, NodeType};
 
-    fn conn_index(graph: &AudioGraph) -> Vec<Vec<(NodeId, f32)>> {
+    fn conn_index(graph: &AudioGraph) -> Vec<Vec<(NodeId, [f32; 2])>> {
         index_connections_by_dest(graph, graph.nodes.len())
     }
 
@@ -182,6 +349,21 @@ mod tests {
         assert!(pos_b < pos_m);
     }
 
+    #[test]
+    fn levels_group_independent_branches() {
+        // A → B → Master, C → Master
+        let mut graph = AudioGraph::with_master();
+        let a = graph.add_node(effect_node("A"));
+        let b = graph.add_node(effect_node("B"));
+        let c = graph.add_node(effect_node("C"));
+        graph.connect(a, b);
+        graph.connect(b, 0);
+        graph.connect(c, 0);
+        let mut levels = GraphState::from_graph(&graph).levels;
+        levels[0].sort();
+        assert_eq!(levels, vec![vec![a, c], vec![b], vec![0]]);
+    }
+
     #[test]
     fn gather_inputs_sums_sources() {
         let mut graph = AudioGraph::with_master();
@@ -224,6 +406,7 @@ mod tests {
             from_channel: 0,
             to_channel: 0,
             gain: -50,
+            pan: 0,
         });
 
         let mut outputs: Vec<AudioBuffer> = (0..2).map(|_| AudioBuffer::new(2, 1)).collect();
@@ -234,6 +417,36 @@ mod tests {
         assert!((scratch.channel(0)[0] - 0.5).abs() < 1e-6);
     }
 
+    #[test]
+    fn gather_inputs_with_pan() {
+        let mut graph = AudioGraph::with_master();
+        let a = graph.add_node(effect_node("A"));
+        graph.connections.clear();
+        graph.connect(a, 0);
+        graph.set_connection_pan(a, 0, -32);
+
+        let mut outputs: Vec<AudioBuffer> = (0..2).map(|_| AudioBuffer::new(2, 1)).collect();
+        outputs[a as usize].channel_mut(0)[0] = 1.0;
+        outputs[a as usize].channel_mut(1)[0] = 1.0;
+
+        let mut scratch = AudioBuffer::new(2, 1);
+        gather_inputs(&conn_index(&graph), &outputs, 0, &mut scratch);
+        assert_eq!(scratch.channel(0)[0], 1.0);
+        assert!((scratch.channel(1)[0] - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn update_wire_changes_gains_in_place() {
+        let mut graph = AudioGraph::with_master();
+        let a = graph.add_node(effect_node("A"));
+        graph.connect(a, 0);
+        let mut state = GraphState::from_graph(&graph);
+        graph.set_connection_gain(a, 0, -50);
+        graph.set_connection_pan(a, 0, 64);
+        state.update_wire(graph.connection(a, 0).unwrap());
+        assert_eq!(state.conn_by_dest[0], [(a, [0.0, 0.5])]);
+    }
+
     #[test]
     fn gain_linear_clamps_negative_to_zero() {
         // gain = -100 maps to 0.0 linear (silence)
@@ -255,7 +468,7 @@ mod tests {
         let a = graph.add_node(effect_node("A"));
         graph.connections.clear();
         graph.connections.push(mb_ir::Connection {
-            from: a, to: 0, from_channel: 0, to_channel: 0, gain: -100,
+            from: a, to: 0, from_channel: 0, to_channel: 0, gain: -100, pan: 0,
         });
 
         let mut outputs: Vec<AudioBuffer> = (0..2).map(|_| AudioBuffer::new(2, 1)).collect();
@@ -277,4 +490,106 @@ mod tests {
         assert_eq!(state.node_outputs.len(), 5);
         assert_eq!(state.topo_order.len(), 5);
     }
+
+    // --- Feedback pairs ---
+
+    /// Master ← A ← Return, A → Send (loop closed via the pair).
+    fn feedback_graph() -> (AudioGraph, NodeId, NodeId, NodeId) {
+        let mut graph = AudioGraph::with_master();
+        let a = graph.add_node(effect_node("A"));
+        let (send, ret) = graph.add_feedback_pair();
+        graph.connect(ret, a);
+        graph.connect(a, send);
+        graph.connect(a, 0);
+        (graph, a, send, ret)
+    }
+
+    #[test]
+    fn feedback_loop_sorts_all_nodes() {
+        let (mut graph, a, send, ret) = feedback_graph();
+        // A stray wire out of the send must not create a cycle either
+        graph.connect(send, a);
+        let order = topological_sort(&graph);
+        assert_eq!(order.len(), 4);
+        let pos = |id| order.iter().position(|&x| x == id).unwrap();
+        assert!(pos(ret) < pos(a));
+        assert!(pos(a) < pos(send));
+    }
+
+    #[test]
+    fn feedback_return_is_one_block_late() {
+        let (graph, _, send, ret) = feedback_graph();
+        let mut state = GraphState::from_graph(&graph);
+        assert!(state.feedback[send as usize].is_some());
+
+        // Block 1: send receives a ramp; return must still be silent
+        for i in 0..BLOCK_SIZE {
+            state.node_outputs[send as usize].channel_mut(0)[i] = i as f32;
+        }
+        state.read_feedback(send, ret, BLOCK_SIZE);
+        assert!(state.node_outputs[ret as usize].channel(0).iter().all(|&v| v == 0.0));
+        state.commit_feedback(BLOCK_SIZE);
+
+        // Block 2 (split in two sub-blocks): return replays block 1
+        let half = BLOCK_SIZE / 2;
+        state.clear_outputs();
+        state.read_feedback(send, ret, half);
+        state.commit_feedback(half);
+        assert_eq!(state.node_outputs[ret as usize].channel(0)[half - 1], (half - 1) as f32);
+        state.read_feedback(send, ret, half);
+        assert_eq!(state.node_outputs[ret as usize].channel(0)[0], half as f32);
+    }
+
+    // --- Channel negotiation ---
+
+    fn stereo_buf(l: f32, r: f32, channels: u16) -> AudioBuffer {
+        let mut buf = AudioBuffer::new(channels, 2);
+        buf.channel_mut(0).fill(l);
+        buf.channel_mut(1).fill(r);
+        buf
+    }
+
+    #[test]
+    fn stereo_to_mono_averages() {
+        let mut buf = stereo_buf(1.0, 0.5, 2);
+        adapt_channels(&mut buf, 2, 1);
+        assert_eq!(buf.channel(0), &[0.75, 0.75]);
+    }
+
+    #[test]
+    fn mono_to_stereo_splits() {
+        let mut buf = stereo_buf(0.25, 9.0, 2);
+        adapt_channels(&mut buf, 1, 2);
+        assert_eq!(buf.channel(1), &[0.25, 0.25]);
+    }
+
+    #[test]
+    fn quad_folds_to_stereo_and_widens_silently() {
+        let mut buf = stereo_buf(1.0, 2.0, 4);
+        buf.channel_mut(2).fill(10.0);
+        buf.channel_mut(3).fill(20.0);
+        adapt_channels(&mut buf, 4, 2);
+        assert_eq!(buf.channel(0), &[11.0, 11.0]);
+        assert_eq!(buf.channel(1), &[22.0, 22.0]);
+
+        adapt_channels(&mut buf, 2, 4);
+        assert_eq!(buf.channel(3), &[0.0, 0.0]);
+    }
+
+    #[test]
+    fn generator_input_is_untouched() {
+        let mut buf = stereo_buf(1.0, 0.5, 2);
+        adapt_channels(&mut buf, 2, 0);
+        assert_eq!(buf.channel(1), &[0.5, 0.5]);
+    }
+
+    #[test]
+    fn negotiation_widens_scratch() {
+        let graph = AudioGraph::with_master();
+        let mut state = GraphState::from_graph(&graph);
+        assert_eq!(state.node_channels[0], STEREO);
+        state.negotiate_channels(&[Some(ChannelConfig { inputs: 6, outputs: 1 })]);
+        assert_eq!(state.scratch.channels(), 6);
+        assert_eq!(state.node_channels[0].outputs, 1);
+    }
 }
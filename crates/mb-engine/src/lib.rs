@@ -7,21 +7,46 @@
 extern crate alloc;
 
 mod channel;
+mod compat;
 pub mod clip_source;
 pub mod envelope_state;
 pub mod event_source;
 mod event_queue;
 mod frequency;
+mod graph_patch;
 mod graph_state;
+mod limits;
 pub mod machine;
 pub mod machines;
+mod meters;
+mod metronome;
+pub mod midi_sync;
 mod mixer;
+#[cfg(feature = "std")]
+mod render_pool;
+mod sample_preview;
 pub mod scheduler;
+mod tempo_map;
+mod transport;
+pub mod voice_pool;
 
-pub use channel::ChannelState;
+pub use channel::{ChannelState, MemorySlot};
 pub use clip_source::ClipSourceState;
-pub use envelope_state::EnvelopeState;
+pub use compat::{EffectMemory, InstrumentChange, OffsetOverflow, Quirks};
+pub use envelope_state::{ControlRate, EnvelopeState};
 pub use event_source::EventSource;
-pub use frequency::{note_to_increment, note_to_period, period_to_increment, clamp_period, PERIOD_MIN, PERIOD_MAX};
+pub use limits::{LimitHit, Limits};
+pub use frequency::{
+    note_to_increment, note_to_period, note_to_linear_period, period_to_increment, linear_period_to_increment,
+    clamp_period, PERIOD_MIN, PERIOD_MAX, LINEAR_PERIOD_MIN, LINEAR_PERIOD_MAX,
+};
+pub use graph_patch::GraphPatch;
+pub use meters::{MeterBank, MeterReading, MASTER_HISTORY_FRAMES, SCOPE_FRAMES};
+pub use metronome::Metronome;
+pub use machines::midi_out::MidiOutMessage;
+pub use midi_sync::{SyncFollower, SyncMessage, Timecode};
 pub use mixer::Engine;
-pub use scheduler::{schedule_cell, schedule_song, target_for_track_column, ScheduleResult};
+pub use scheduler::{preview_target, schedule_cell, schedule_song, schedule_song_with_limits, target_for_track_column, ScheduleResult};
+pub use tempo_map::{TempoChange, TempoMap};
+pub use transport::Transport;
+pub use voice_pool::{Voice, VoicePool};
@@ -0,0 +1,85 @@
+//! Safety limits against runaway schedules.
+//!
+//! Malformed songs (jump loops, huge pattern delays) can otherwise generate
+//! unbounded event streams. Scheduling stops gracefully at a limit and
+//! reports which one was hit.
+
+use mb_ir::MusicalTime;
+
+/// Bounds on how much a song may schedule.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum events generated per track
+    pub max_events: usize,
+    /// Maximum song length in beats
+    pub max_beats: u64,
+}
+
+impl Default for Limits {
+    /// One million events per track, 20000 beats (2h40m at 125 BPM).
+    fn default() -> Self {
+        Self { max_events: 1_000_000, max_beats: 20_000 }
+    }
+}
+
+impl Limits {
+    /// Song length cut-off as a time.
+    pub fn max_time(&self) -> MusicalTime {
+        MusicalTime::from_beats(self.max_beats)
+    }
+}
+
+/// Why scheduling or playback was cut short.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LimitHit {
+    /// `Limits::max_events` reached
+    Events,
+    /// `Limits::max_beats` reached
+    Length,
+    /// The render loop stopped making progress and playback was halted
+    Stalled,
+}
+
+impl LimitHit {
+    /// Compact code for sharing across threads (0 is reserved for "none").
+    pub fn code(self) -> u8 {
+        match self {
+            LimitHit::Events => 1,
+            LimitHit::Length => 2,
+            LimitHit::Stalled => 3,
+        }
+    }
+
+    /// Inverse of `code`.
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(LimitHit::Events),
+            2 => Some(LimitHit::Length),
+            3 => Some(LimitHit::Stalled),
+            _ => None,
+        }
+    }
+}
+
+impl core::fmt::Display for LimitHit {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            LimitHit::Events => "event limit reached, song truncated",
+            LimitHit::Length => "length limit reached, song truncated",
+            LimitHit::Stalled => "playback stalled and was stopped",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_round_trip() {
+        for hit in [LimitHit::Events, LimitHit::Length, LimitHit::Stalled] {
+            assert_eq!(LimitHit::from_code(hit.code()), Some(hit));
+        }
+        assert_eq!(LimitHit::from_code(0), None);
+    }
+}
@@ -1,6 +1,8 @@
 //! Machine trait for audio generators and effects.
 
-use mb_ir::{AudioStream, EventPayload};
+use mb_ir::{AudioStream, EventPayload, Instrument, Interpolation, ParamCurve, ParamUnit, Parameter, Sample, SampleFields};
+
+use crate::channel::ChannelState;
 
 /// Whether a machine generates or processes audio.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -17,6 +19,16 @@ pub struct ParamInfo {
     pub max: i32,
     pub default: i32,
     pub no_value: i32,
+    pub unit: ParamUnit,
+    pub curve: ParamCurve,
+}
+
+impl ParamInfo {
+    /// Build a graph parameter (at its default value) carrying this display metadata.
+    pub fn to_parameter(&self) -> Parameter {
+        Parameter::new(self.id, self.name, self.min, self.max, self.default)
+            .with_display(self.unit, self.curve)
+    }
 }
 
 /// Static metadata about a machine.
@@ -43,4 +55,28 @@ pub trait Machine: AudioStream + Send {
 
     /// Notify the machine of a speed change (ticks per row).
     fn set_speed(&mut self, _speed: u8) {}
+
+    /// Pass the song tempo (hundredths of a BPM) before each tick, for
+    /// machines that keep time in beats.
+    fn set_tempo(&mut self, _tempo: u32) {}
+
+    /// Update the playback settings of a sample the machine plays.
+    fn update_sample(&mut self, _index: u8, _fields: &SampleFields) {}
+
+    /// Take the song's samples and instruments after one was added,
+    /// removed or replaced (for machines that play samples). Allocates.
+    fn update_samples(&mut self, _samples: &[Sample], _instruments: &[Instrument]) {}
+
+    /// Silence a sub-channel while it keeps playing (mute/solo).
+    fn set_channel_muted(&mut self, _channel: u8, _muted: bool) {}
+
+    /// Choose how sampled audio is resampled (for machines that play samples).
+    fn set_interpolation(&mut self, _quality: Interpolation) {}
+
+    /// Playback state of a sub-channel, for machines that drive tracker channels.
+    fn channel_state(&self, _channel: u8) -> Option<&ChannelState> { None }
+
+    /// Hand the MIDI messages produced since the last call to `f`, for
+    /// machines that play external gear.
+    fn drain_midi(&mut self, _f: &mut dyn FnMut([u8; 3])) {}
 }
@@ -5,7 +5,7 @@
 
 use core::f32::consts::TAU;
 
-use mb_ir::{AudioBuffer, AudioStream, ChannelConfig};
+use mb_ir::{AudioBuffer, AudioStream, ChannelConfig, ParamCurve, ParamUnit};
 use crate::machine::{Machine, MachineInfo, MachineType, ParamInfo};
 
 const DEFAULT_CUTOFF: i32 = 4410;
@@ -17,6 +17,8 @@ static PARAMS: &[ParamInfo] = &[ParamInfo {
     max: 22050,
     default: DEFAULT_CUTOFF,
     no_value: 0,
+    unit: ParamUnit::Hz,
+    curve: ParamCurve::Raw,
 }];
 
 static INFO: MachineInfo = MachineInfo {
@@ -64,9 +66,9 @@ impl AudioStream for AmigaFilter {
         // Process left channel
         let left = output.channel_mut(0);
         let mut prev = self.prev_left;
-        for i in 0..frames {
-            prev += alpha * (left[i] - prev);
-            left[i] = prev;
+        for s in &mut left[..frames] {
+            prev += alpha * (*s - prev);
+            *s = prev;
         }
         self.prev_left = prev;
 
@@ -74,9 +76,9 @@ impl AudioStream for AmigaFilter {
         if output.channels() >= 2 {
             let right = output.channel_mut(1);
             let mut prev = self.prev_right;
-            for i in 0..frames {
-                prev += alpha * (right[i] - prev);
-                right[i] = prev;
+            for s in &mut right[..frames] {
+                prev += alpha * (*s - prev);
+                *s = prev;
             }
             self.prev_right = prev;
         }
@@ -177,4 +179,11 @@ mod tests {
         assert_eq!(f.prev_left, 0.0);
         assert_eq!(f.prev_right, 0.0);
     }
+
+    #[test]
+    fn cutoff_displays_in_khz() {
+        let params = super::super::default_parameters("Amiga Filter");
+        assert_eq!(params.len(), 1);
+        assert_eq!(params[0].display_value(), "4.4 kHz");
+    }
 }
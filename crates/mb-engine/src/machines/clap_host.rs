@@ -0,0 +1,947 @@
+//! CLAP plugins hosted as graph machines.
+//!
+//! A `NodeType::Plugin` node names a `.clap` bundle and a plugin id in it.
+//! Bundles are loaded on first use and stay loaded for the rest of the
+//! process. Each instance is activated for the engine's sample rate and
+//! blocks of up to `BLOCK_SIZE` frames, and receives pattern notes and
+//! parameter changes as CLAP events at the start of its next block.
+//!
+//! CLAP parameters become machine parameters, so graph `Parameter`s and
+//! pattern parameter columns drive them: stepped parameters keep their
+//! integer range, continuous ones map onto `0..=PARAM_STEPS` with a linear
+//! display curve over the plugin's range. The host offers no extensions
+//! of its own (GUI, state, thread pool).
+//!
+//! CLAP splits plugin calls between a main thread and an audio thread.
+//! Instances are created, activated, deactivated and destroyed where the
+//! engine is built and dropped, and process on the thread that renders it;
+//! a host playing live builds the engine before handing it to its audio
+//! thread and takes it back to drop it.
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ffi::{c_char, c_void, CStr};
+use core::ptr;
+use std::ffi::CString;
+use std::sync::Mutex;
+
+use clap_sys::audio_buffer::clap_audio_buffer;
+use clap_sys::entry::clap_plugin_entry;
+use clap_sys::events::{
+    clap_event_header, clap_event_note, clap_event_param_value, clap_input_events, clap_output_events,
+    CLAP_CORE_EVENT_SPACE_ID, CLAP_EVENT_NOTE_CHOKE, CLAP_EVENT_NOTE_OFF, CLAP_EVENT_NOTE_ON, CLAP_EVENT_PARAM_VALUE,
+};
+use clap_sys::ext::audio_ports::{clap_audio_port_info, clap_plugin_audio_ports, CLAP_EXT_AUDIO_PORTS};
+use clap_sys::ext::params::{clap_param_info, clap_plugin_params, CLAP_EXT_PARAMS, CLAP_PARAM_IS_STEPPED};
+use clap_sys::factory::plugin_factory::{clap_plugin_factory, CLAP_PLUGIN_FACTORY_ID};
+use clap_sys::host::clap_host;
+use clap_sys::id::clap_id;
+use clap_sys::plugin::clap_plugin;
+use clap_sys::process::{clap_process, CLAP_PROCESS_ERROR};
+use clap_sys::version::{clap_version_is_compatible, CLAP_VERSION};
+use libloading::Library;
+use mb_ir::{AudioBuffer, AudioStream, ChannelConfig, EventPayload, ParamCurve, ParamUnit, BLOCK_SIZE};
+
+use crate::machine::{Machine, MachineInfo, MachineType, ParamInfo};
+
+/// Raw range of a continuous CLAP parameter (Buzz word-parameter sized).
+pub const PARAM_STEPS: i32 = 0xFFFE;
+
+/// Most channels across all of a plugin's input or output ports.
+const MAX_PORT_CHANNELS: usize = 32;
+
+/// Widest main port the graph sees; further channels are left unused.
+const MAX_MAIN_CHANNELS: u32 = 8;
+
+/// Events queued for one block; later ones are dropped.
+const MAX_EVENTS: usize = 256;
+
+/// Tracker note 48 is C-4, MIDI key 60.
+const KEY_OFFSET: i16 = 12;
+
+/// Why a CLAP plugin couldn't be hosted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClapError {
+    /// The bundle couldn't be opened or isn't a CLAP bundle
+    Load(String),
+    /// The bundle has no plugin with the requested id
+    UnknownPlugin(String),
+    /// The plugin failed to create or initialise
+    Init(String),
+}
+
+impl core::fmt::Display for ClapError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ClapError::Load(msg) => write!(f, "can't load CLAP bundle: {}", msg),
+            ClapError::UnknownPlugin(id) => write!(f, "no CLAP plugin {}", id),
+            ClapError::Init(id) => write!(f, "CLAP plugin {} failed to initialise", id),
+        }
+    }
+}
+
+impl std::error::Error for ClapError {}
+
+/// A plugin a bundle provides, as listed by `plugin_descriptors`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClapDescriptor {
+    pub id: String,
+    pub name: String,
+    pub vendor: String,
+}
+
+/// An initialised bundle and its plugin factory.
+struct Bundle {
+    path: String,
+    factory: *const clap_plugin_factory,
+    _library: Option<Library>,
+    /// Plugin kinds seen so far, by id
+    kinds: Mutex<Vec<Arc<PluginKind>>>,
+}
+
+// SAFETY: `factory` points into the library, which `_library` keeps loaded
+// (bundles are never unloaded), and CLAP requires every factory method to
+// be thread-safe; the rest of the bundle is owned or behind a `Mutex`
+unsafe impl Send for Bundle {}
+unsafe impl Sync for Bundle {}
+
+static BUNDLES: Mutex<Vec<Arc<Bundle>>> = Mutex::new(Vec::new());
+
+static HOST: clap_host = clap_host {
+    clap_version: CLAP_VERSION,
+    host_data: ptr::null_mut(),
+    name: c"masterblaster".as_ptr(),
+    vendor: c"masterblaster".as_ptr(),
+    url: c"".as_ptr(),
+    version: c"0.1".as_ptr(),
+    get_extension: Some(host_get_extension),
+    request_restart: Some(host_request),
+    request_process: Some(host_request),
+    request_callback: Some(host_request),
+};
+
+unsafe extern "C" fn host_get_extension(_host: *const clap_host, _id: *const c_char) -> *const c_void {
+    ptr::null()
+}
+
+unsafe extern "C" fn host_request(_host: *const clap_host) {}
+
+/// The bundle at `path`, loading and initialising it on first use.
+fn bundle(path: &str) -> Result<Arc<Bundle>, ClapError> {
+    let mut bundles = BUNDLES.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(bundle) = bundles.iter().find(|b| b.path == path) {
+        return Ok(Arc::clone(bundle));
+    }
+    // SAFETY: loading runs the bundle's initialisers; the song chose it
+    let library = unsafe { Library::new(path) }.map_err(|e| ClapError::Load(e.to_string()))?;
+    // SAFETY: `clap_entry` is a `clap_plugin_entry` by the CLAP spec
+    let entry = unsafe { library.get::<*const clap_plugin_entry>(b"clap_entry\0") }
+        .map(|symbol| *symbol)
+        .map_err(|e| ClapError::Load(e.to_string()))?;
+    // SAFETY: the entry lives as long as the library, which the bundle keeps
+    let bundle = unsafe { init_bundle(path, &*entry, Some(library)) }?;
+    bundles.push(Arc::clone(&bundle));
+    Ok(bundle)
+}
+
+/// Initialise a bundle through its entry point.
+///
+/// # Safety
+///
+/// `entry` must be a valid CLAP entry that outlives the bundle.
+unsafe fn init_bundle(path: &str, entry: &clap_plugin_entry, library: Option<Library>) -> Result<Arc<Bundle>, ClapError> {
+    if !clap_version_is_compatible(entry.clap_version) {
+        return Err(ClapError::Load("incompatible CLAP version".into()));
+    }
+    let (Some(init), Some(get_factory)) = (entry.init, entry.get_factory) else {
+        return Err(ClapError::Load("incomplete entry point".into()));
+    };
+    let c_path = CString::new(path).map_err(|e| ClapError::Load(e.to_string()))?;
+    // SAFETY: called once per bundle, before anything else
+    if !unsafe { init(c_path.as_ptr()) } {
+        return Err(ClapError::Load("bundle failed to initialise".into()));
+    }
+    // SAFETY: initialised above
+    let factory = unsafe { get_factory(CLAP_PLUGIN_FACTORY_ID.as_ptr()) }.cast::<clap_plugin_factory>();
+    if factory.is_null() {
+        return Err(ClapError::Load("no plugin factory".into()));
+    }
+    Ok(Arc::new(Bundle { path: path.to_string(), factory, _library: library, kinds: Mutex::new(Vec::new()) }))
+}
+
+/// The plugins in the bundle at `path`.
+pub fn plugin_descriptors(path: &str) -> Result<Vec<ClapDescriptor>, ClapError> {
+    let bundle = bundle(path)?;
+    // SAFETY: a live factory from an initialised bundle
+    let factory = unsafe { &*bundle.factory };
+    let (Some(count), Some(get)) = (factory.get_plugin_count, factory.get_plugin_descriptor) else {
+        return Ok(Vec::new());
+    };
+    // SAFETY: factory calls with the factory itself
+    let n = unsafe { count(bundle.factory) };
+    Ok((0..n)
+        .filter_map(|i| {
+            // SAFETY: index in range; descriptors live as long as the bundle
+            let desc = unsafe { get(bundle.factory, i).as_ref() }?;
+            Some(ClapDescriptor {
+                id: c_string(desc.id)?,
+                name: c_string(desc.name).unwrap_or_default(),
+                vendor: c_string(desc.vendor).unwrap_or_default(),
+            })
+        })
+        .collect())
+}
+
+/// Copy a C string, if set and UTF-8.
+fn c_string(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    // SAFETY: CLAP strings are NUL-terminated
+    unsafe { CStr::from_ptr(ptr) }.to_str().ok().map(str::to_string)
+}
+
+/// How a machine parameter maps onto a CLAP parameter.
+#[derive(Clone, Copy, Debug)]
+struct ParamMap {
+    id: clap_id,
+    min: f64,
+    max: f64,
+    stepped: bool,
+}
+
+impl ParamMap {
+    fn to_clap(self, raw: i32) -> f64 {
+        if self.stepped {
+            (raw as f64).clamp(self.min, self.max)
+        } else {
+            self.min + (self.max - self.min) * raw.clamp(0, PARAM_STEPS) as f64 / PARAM_STEPS as f64
+        }
+    }
+
+    fn to_raw(self, value: f64) -> i32 {
+        if self.stepped {
+            value.round() as i32
+        } else if self.max > self.min {
+            ((value - self.min) / (self.max - self.min) * PARAM_STEPS as f64).round() as i32
+        } else {
+            0
+        }
+    }
+}
+
+/// What every instance of one plugin shares.
+struct PluginKind {
+    id: String,
+    /// Leaked once per plugin, as `MachineInfo` requires
+    info: &'static MachineInfo,
+    params: Vec<ParamMap>,
+    /// Channels per input and output port (the first of each is the main one)
+    inputs: Vec<u32>,
+    outputs: Vec<u32>,
+}
+
+impl PluginKind {
+    /// Describe a freshly initialised plugin.
+    ///
+    /// # Safety
+    ///
+    /// `plugin` must be initialised and not yet activated.
+    unsafe fn probe(id: &str, plugin: &clap_plugin) -> Result<Self, ClapError> {
+        // SAFETY: per the caller
+        let (inputs, outputs) = unsafe { audio_ports(plugin) };
+        if outputs.is_empty() || inputs.iter().sum::<u32>() as usize > MAX_PORT_CHANNELS
+            || outputs.iter().sum::<u32>() as usize > MAX_PORT_CHANNELS
+        {
+            return Err(ClapError::Init(id.to_string()));
+        }
+        let mut params = Vec::new();
+        let mut infos = Vec::new();
+        // SAFETY: per the caller
+        for info in unsafe { param_infos(plugin) } {
+            let map = ParamMap {
+                id: info.id,
+                min: info.min_value,
+                max: info.max_value.max(info.min_value),
+                stepped: info.flags & CLAP_PARAM_IS_STEPPED != 0,
+            };
+            // SAFETY: CLAP fills `name` with a NUL-terminated string
+            let name = unsafe { CStr::from_ptr(info.name.as_ptr()) }.to_string_lossy();
+            let (min, max, curve) = if map.stepped {
+                (map.min.round() as i32, map.max.round() as i32, ParamCurve::Raw)
+            } else {
+                (0, PARAM_STEPS, ParamCurve::Linear { lo: map.min as f32, hi: map.max as f32 })
+            };
+            infos.push(ParamInfo {
+                id: infos.len() as u16,
+                name: Box::leak(super::param_name(&name).into()),
+                min,
+                max,
+                default: map.to_raw(info.default_value).clamp(min, max),
+                no_value: max.saturating_add(1),
+                unit: ParamUnit::None,
+                curve,
+            });
+            params.push(map);
+            if infos.len() > u16::MAX as usize {
+                break;
+            }
+        }
+        // SAFETY: descriptors outlive their plugins
+        let desc = unsafe { plugin.desc.as_ref() };
+        let name = desc.and_then(|d| c_string(d.name)).unwrap_or_else(|| id.to_string());
+        let vendor = desc.and_then(|d| c_string(d.vendor)).unwrap_or_default();
+        let name: &'static str = Box::leak(name.into_boxed_str());
+        let info = Box::leak(Box::new(MachineInfo {
+            name,
+            short_name: name,
+            author: Box::leak(vendor.into_boxed_str()),
+            machine_type: if inputs.is_empty() { MachineType::Generator } else { MachineType::Effect },
+            params: Box::leak(infos.into_boxed_slice()),
+        }));
+        Ok(Self { id: id.to_string(), info, params, inputs, outputs })
+    }
+}
+
+/// A plugin extension, if it provides it.
+///
+/// # Safety
+///
+/// `plugin` must be initialised, and `T` the extension struct for `id`.
+unsafe fn extension<'a, T>(plugin: &'a clap_plugin, id: &CStr) -> Option<&'a T> {
+    let get_extension = plugin.get_extension?;
+    // SAFETY: per the caller; extensions live as long as the plugin
+    unsafe { get_extension(plugin, id.as_ptr()).cast::<T>().as_ref() }
+}
+
+/// Channel counts of the input and output ports (stereo out if undeclared).
+///
+/// # Safety
+///
+/// `plugin` must be initialised.
+unsafe fn audio_ports(plugin: &clap_plugin) -> (Vec<u32>, Vec<u32>) {
+    // SAFETY: per the caller
+    let Some(ports) = (unsafe { extension::<clap_plugin_audio_ports>(plugin, CLAP_EXT_AUDIO_PORTS) }) else {
+        return (Vec::new(), vec![2]);
+    };
+    let (Some(count), Some(get)) = (ports.count, ports.get) else { return (Vec::new(), vec![2]) };
+    let side = |is_input: bool| -> Vec<u32> {
+        // SAFETY: extension calls on an initialised plugin
+        let n = unsafe { count(plugin, is_input) };
+        (0..n)
+            .map(|i| {
+                // SAFETY: `info` is written by `get` before it's read
+                let mut info: clap_audio_port_info = unsafe { core::mem::zeroed() };
+                if unsafe { get(plugin, i, is_input, &mut info) } { info.channel_count } else { 0 }
+            })
+            .collect()
+    };
+    (side(true), side(false))
+}
+
+/// The plugin's parameter descriptions, in index order.
+///
+/// # Safety
+///
+/// `plugin` must be initialised.
+unsafe fn param_infos(plugin: &clap_plugin) -> Vec<clap_param_info> {
+    // SAFETY: per the caller
+    let Some(params) = (unsafe { extension::<clap_plugin_params>(plugin, CLAP_EXT_PARAMS) }) else {
+        return Vec::new();
+    };
+    let (Some(count), Some(get_info)) = (params.count, params.get_info) else { return Vec::new() };
+    // SAFETY: extension calls on an initialised plugin
+    let n = unsafe { count(plugin) };
+    (0..n)
+        .filter_map(|i| {
+            // SAFETY: `info` is written by `get_info` before it's read
+            let mut info: clap_param_info = unsafe { core::mem::zeroed() };
+            unsafe { get_info(plugin, i, &mut info) }.then_some(info)
+        })
+        .collect()
+}
+
+/// A queued input event, laid out so its header pointer is the event's.
+#[derive(Clone, Copy)]
+enum QueuedEvent {
+    Note(clap_event_note),
+    Param(clap_event_param_value),
+}
+
+impl QueuedEvent {
+    fn header(&self) -> *const clap_event_header {
+        match self {
+            QueuedEvent::Note(e) => &e.header,
+            QueuedEvent::Param(e) => &e.header,
+        }
+    }
+}
+
+fn header<T>(type_: u16) -> clap_event_header {
+    clap_event_header {
+        size: core::mem::size_of::<T>() as u32,
+        time: 0,
+        space_id: CLAP_CORE_EVENT_SPACE_ID,
+        type_,
+        flags: 0,
+    }
+}
+
+unsafe extern "C" fn events_size(list: *const clap_input_events) -> u32 {
+    // SAFETY: `ctx` is the machine's event queue for the process call
+    let events = unsafe { &*(*list).ctx.cast::<Vec<QueuedEvent>>() };
+    events.len() as u32
+}
+
+unsafe extern "C" fn events_get(list: *const clap_input_events, index: u32) -> *const clap_event_header {
+    // SAFETY: as in `events_size`
+    let events = unsafe { &*(*list).ctx.cast::<Vec<QueuedEvent>>() };
+    events.get(index as usize).map_or(ptr::null(), QueuedEvent::header)
+}
+
+unsafe extern "C" fn discard_output(_list: *const clap_output_events, _event: *const clap_event_header) -> bool {
+    true
+}
+
+/// One hosted CLAP plugin instance.
+pub struct ClapMachine {
+    kind: Arc<PluginKind>,
+    plugin: *const clap_plugin,
+    _bundle: Arc<Bundle>,
+    active: bool,
+    processing: bool,
+    steady_time: i64,
+    events: Vec<QueuedEvent>,
+    /// Input port audio, `BLOCK_SIZE` frames per channel
+    in_data: Vec<f32>,
+    /// Output audio of ports past the main one
+    out_data: Vec<f32>,
+    in_ptrs: Vec<*mut f32>,
+    out_ptrs: Vec<*mut f32>,
+    in_bufs: Vec<clap_audio_buffer>,
+    out_bufs: Vec<clap_audio_buffer>,
+}
+
+// SAFETY: the instance is only reached through `&mut self`, so calls never
+// overlap, and moving it keeps CLAP's thread rules: the main-thread calls
+// (create, activate, deactivate, destroy) happen where the engine is built
+// and dropped, the audio-thread ones where it renders (see the module doc).
+// The port pointers point into this machine's own buffers and the block
+// being rendered, and are rewired before every `process` call
+unsafe impl Send for ClapMachine {}
+
+impl ClapMachine {
+    /// Create plugin `plugin_id` from the bundle at `path`.
+    pub fn load(path: &str, plugin_id: &str) -> Result<Self, ClapError> {
+        Self::create(bundle(path)?, plugin_id)
+    }
+
+    fn create(bundle: Arc<Bundle>, plugin_id: &str) -> Result<Self, ClapError> {
+        let id = CString::new(plugin_id).map_err(|_| ClapError::UnknownPlugin(plugin_id.to_string()))?;
+        // SAFETY: a live factory from an initialised bundle
+        let create = unsafe { (*bundle.factory).create_plugin }.ok_or_else(|| ClapError::UnknownPlugin(plugin_id.to_string()))?;
+        // SAFETY: factory call with a host that outlives every plugin
+        let plugin = unsafe { create(bundle.factory, &HOST, id.as_ptr()) };
+        // SAFETY: null or a plugin the factory just created
+        let Some(plugin_ref) = (unsafe { plugin.as_ref() }) else {
+            return Err(ClapError::UnknownPlugin(plugin_id.to_string()));
+        };
+        // SAFETY: plugins are initialised once, right after creation
+        let initialised = plugin_ref.init.is_some_and(|init| unsafe { init(plugin) });
+        let kind = if initialised { Self::kind(&bundle, plugin_id, plugin_ref) } else { Err(ClapError::Init(plugin_id.to_string())) };
+        let kind = match kind {
+            Ok(kind) => kind,
+            Err(e) => {
+                if let Some(destroy) = plugin_ref.destroy {
+                    // SAFETY: the plugin is dropped here and never used again
+                    unsafe { destroy(plugin) };
+                }
+                return Err(e);
+            }
+        };
+        let in_channels: usize = kind.inputs.iter().sum::<u32>() as usize;
+        let out_channels: usize = kind.outputs.iter().skip(1).sum::<u32>() as usize;
+        Ok(Self {
+            in_ptrs: Vec::with_capacity(in_channels),
+            out_ptrs: Vec::with_capacity(out_channels + kind.outputs[0] as usize),
+            in_bufs: Vec::with_capacity(kind.inputs.len()),
+            out_bufs: Vec::with_capacity(kind.outputs.len()),
+            in_data: vec![0.0; in_channels * BLOCK_SIZE],
+            out_data: vec![0.0; out_channels * BLOCK_SIZE],
+            kind,
+            plugin,
+            _bundle: bundle,
+            active: false,
+            processing: false,
+            steady_time: 0,
+            events: Vec::with_capacity(MAX_EVENTS),
+        })
+    }
+
+    /// The bundle's shared description of `plugin_id`, probing `plugin` the first time.
+    fn kind(bundle: &Bundle, plugin_id: &str, plugin: &clap_plugin) -> Result<Arc<PluginKind>, ClapError> {
+        let mut kinds = bundle.kinds.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(kind) = kinds.iter().find(|k| k.id == plugin_id) {
+            return Ok(Arc::clone(kind));
+        }
+        // SAFETY: initialised and not yet activated
+        let kind = Arc::new(unsafe { PluginKind::probe(plugin_id, plugin) }?);
+        kinds.push(Arc::clone(&kind));
+        Ok(kind)
+    }
+
+    fn plugin(&self) -> &clap_plugin {
+        // SAFETY: created in `create` and destroyed only on drop
+        unsafe { &*self.plugin }
+    }
+
+    fn queue(&mut self, event: QueuedEvent) {
+        if self.events.len() < MAX_EVENTS {
+            self.events.push(event);
+        }
+    }
+
+    /// Queue a note event; -1 for `channel` or `key` matches them all.
+    fn queue_note(&mut self, type_: u16, channel: i16, key: i16, velocity: f64) {
+        self.queue(QueuedEvent::Note(clap_event_note {
+            header: header::<clap_event_note>(type_),
+            note_id: -1,
+            port_index: 0,
+            channel,
+            key,
+            velocity,
+        }));
+    }
+
+    fn deactivate(&mut self) {
+        let plugin = *self.plugin();
+        if self.processing {
+            if let Some(stop_processing) = plugin.stop_processing {
+                // SAFETY: processing was started on this instance
+                unsafe { stop_processing(self.plugin) };
+            }
+            self.processing = false;
+        }
+        if self.active {
+            if let Some(deactivate) = plugin.deactivate {
+                // SAFETY: the instance is active
+                unsafe { deactivate(self.plugin) };
+            }
+            self.active = false;
+        }
+    }
+
+    /// Point the port buffers at `output` (main ports) and the scratch
+    /// areas (other ports), copying the main input in.
+    fn wire_ports(&mut self, output: &mut AudioBuffer, frames: usize) {
+        let channels = output.channels() as usize;
+        self.in_ptrs.clear();
+        self.in_data.fill(0.0);
+        for (ch, chunk) in self.in_data.chunks_exact_mut(BLOCK_SIZE).enumerate() {
+            if ch < self.kind.inputs.first().copied().unwrap_or(0) as usize && ch < channels {
+                chunk[..frames].copy_from_slice(output.channel(ch as u16));
+            }
+            self.in_ptrs.push(chunk.as_mut_ptr());
+        }
+        self.out_ptrs.clear();
+        for ch in 0..self.kind.outputs[0] as usize {
+            // Main output channels past the buffer's width write to its last channel
+            let buf = output.channel_mut(ch.min(channels - 1) as u16);
+            buf.fill(0.0);
+            self.out_ptrs.push(buf.as_mut_ptr());
+        }
+        for chunk in self.out_data.chunks_exact_mut(BLOCK_SIZE) {
+            self.out_ptrs.push(chunk.as_mut_ptr());
+        }
+
+        let buffers = |counts: &[u32], ptrs: &mut Vec<*mut f32>, bufs: &mut Vec<clap_audio_buffer>| {
+            bufs.clear();
+            let mut start = 0;
+            for &count in counts {
+                bufs.push(clap_audio_buffer {
+                    data32: ptrs[start..].as_mut_ptr(),
+                    data64: ptr::null_mut(),
+                    channel_count: count,
+                    latency: 0,
+                    constant_mask: 0,
+                });
+                start += count as usize;
+            }
+        };
+        buffers(&self.kind.inputs, &mut self.in_ptrs, &mut self.in_bufs);
+        buffers(&self.kind.outputs, &mut self.out_ptrs, &mut self.out_bufs);
+    }
+}
+
+impl Drop for ClapMachine {
+    fn drop(&mut self) {
+        self.deactivate();
+        if let Some(destroy) = self.plugin().destroy {
+            // SAFETY: the instance is deactivated and never used again
+            unsafe { destroy(self.plugin) };
+        }
+    }
+}
+
+impl AudioStream for ClapMachine {
+    fn channel_config(&self) -> ChannelConfig {
+        let clamp = |n: u32| n.min(MAX_MAIN_CHANNELS) as u16;
+        ChannelConfig {
+            inputs: clamp(self.kind.inputs.first().copied().unwrap_or(0)),
+            outputs: clamp(self.kind.outputs[0]).max(1),
+        }
+    }
+
+    fn render(&mut self, output: &mut AudioBuffer) {
+        let frames = output.frames() as usize;
+        let plugin = *self.plugin();
+        if !self.processing && self.active {
+            // SAFETY: the instance is active
+            self.processing = plugin.start_processing.is_some_and(|start| unsafe { start(self.plugin) });
+        }
+        let Some(process) = plugin.process.filter(|_| self.processing && frames <= BLOCK_SIZE && output.channels() > 0) else {
+            if self.kind.info.machine_type == MachineType::Generator {
+                output.silence();
+            }
+            self.events.clear();
+            return;
+        };
+
+        self.wire_ports(output, frames);
+        let in_events = clap_input_events {
+            ctx: (&mut self.events as *mut Vec<QueuedEvent>).cast(),
+            size: Some(events_size),
+            get: Some(events_get),
+        };
+        let out_events = clap_output_events { ctx: ptr::null_mut(), try_push: Some(discard_output) };
+        let call = clap_process {
+            steady_time: self.steady_time,
+            frames_count: frames as u32,
+            transport: ptr::null(),
+            audio_inputs: self.in_bufs.as_ptr(),
+            audio_outputs: self.out_bufs.as_mut_ptr(),
+            audio_inputs_count: self.in_bufs.len() as u32,
+            audio_outputs_count: self.out_bufs.len() as u32,
+            in_events: &in_events,
+            out_events: &out_events,
+        };
+        // SAFETY: the instance is processing, and every buffer, event and
+        // list the call points to outlives it
+        let status = unsafe { process(self.plugin, &call) };
+        if status == CLAP_PROCESS_ERROR {
+            output.silence();
+        }
+        self.events.clear();
+        self.steady_time += frames as i64;
+    }
+}
+
+impl Machine for ClapMachine {
+    fn info(&self) -> &MachineInfo {
+        self.kind.info
+    }
+
+    fn init(&mut self, sample_rate: u32) {
+        self.deactivate();
+        let Some(activate) = self.plugin().activate else { return };
+        // SAFETY: the instance is initialised and inactive
+        self.active = unsafe { activate(self.plugin, sample_rate as f64, 1, BLOCK_SIZE as u32) };
+    }
+
+    fn tick(&mut self) {}
+
+    fn stop(&mut self) {
+        self.queue_note(CLAP_EVENT_NOTE_CHOKE, -1, -1, 0.0);
+    }
+
+    fn set_param(&mut self, param: u16, value: i32) {
+        let Some(&map) = self.kind.params.get(param as usize) else { return };
+        self.queue(QueuedEvent::Param(clap_event_param_value {
+            header: header::<clap_event_param_value>(CLAP_EVENT_PARAM_VALUE),
+            param_id: map.id,
+            cookie: ptr::null_mut(),
+            note_id: -1,
+            port_index: -1,
+            channel: -1,
+            key: -1,
+            value: map.to_clap(value),
+        }));
+    }
+
+    fn apply_event(&mut self, channel: u8, payload: &EventPayload) {
+        match *payload {
+            EventPayload::NoteOn { note, velocity, .. } => {
+                let key = (note as i16 + KEY_OFFSET).min(127);
+                self.queue_note(CLAP_EVENT_NOTE_ON, (channel % 16) as i16, key, velocity.min(64) as f64 / 64.0);
+            }
+            EventPayload::NoteOff { note } => {
+                let key = (note as i16 + KEY_OFFSET).min(127);
+                self.queue_note(CLAP_EVENT_NOTE_OFF, (channel % 16) as i16, key, 0.0);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    /// An in-process "bundle" with one plugin: a stereo gain effect whose
+    /// one parameter runs 0.0-2.0, counting the notes it receives.
+    struct Gain {
+        plugin: clap_plugin,
+        gain: f64,
+    }
+
+    static NOTES: AtomicU32 = AtomicU32::new(0);
+    static CHOKES: AtomicU32 = AtomicU32::new(0);
+
+    static DESCRIPTOR: clap_sys::plugin::clap_plugin_descriptor = clap_sys::plugin::clap_plugin_descriptor {
+        clap_version: CLAP_VERSION,
+        id: c"test.gain".as_ptr(),
+        name: c"Test Gain".as_ptr(),
+        vendor: c"tests".as_ptr(),
+        url: ptr::null(),
+        manual_url: ptr::null(),
+        support_url: ptr::null(),
+        version: ptr::null(),
+        description: ptr::null(),
+        features: ptr::null(),
+    };
+
+    static FACTORY: clap_plugin_factory = clap_plugin_factory {
+        get_plugin_count: Some(plugin_count),
+        get_plugin_descriptor: Some(plugin_descriptor),
+        create_plugin: Some(create_plugin),
+    };
+
+    static ENTRY: clap_plugin_entry = clap_plugin_entry {
+        clap_version: CLAP_VERSION,
+        init: Some(entry_init),
+        deinit: None,
+        get_factory: Some(get_factory),
+    };
+
+    static PARAMS: clap_plugin_params = clap_plugin_params {
+        count: Some(param_count),
+        get_info: Some(param_info),
+        get_value: None,
+        value_to_text: None,
+        text_to_value: None,
+        flush: None,
+    };
+
+    static PORTS: clap_plugin_audio_ports = clap_plugin_audio_ports { count: Some(port_count), get: Some(port_info) };
+
+    unsafe extern "C" fn entry_init(_path: *const c_char) -> bool {
+        true
+    }
+
+    unsafe extern "C" fn get_factory(id: *const c_char) -> *const c_void {
+        if unsafe { CStr::from_ptr(id) } == CLAP_PLUGIN_FACTORY_ID {
+            (&FACTORY as *const clap_plugin_factory).cast()
+        } else {
+            ptr::null()
+        }
+    }
+
+    unsafe extern "C" fn plugin_count(_f: *const clap_plugin_factory) -> u32 {
+        1
+    }
+
+    unsafe extern "C" fn plugin_descriptor(_f: *const clap_plugin_factory, _i: u32) -> *const clap_sys::plugin::clap_plugin_descriptor {
+        &DESCRIPTOR
+    }
+
+    unsafe extern "C" fn create_plugin(_f: *const clap_plugin_factory, _h: *const clap_host, id: *const c_char) -> *const clap_plugin {
+        if unsafe { CStr::from_ptr(id) } != c"test.gain" {
+            return ptr::null();
+        }
+        let gain = Box::into_raw(Box::new(Gain {
+            plugin: clap_plugin {
+                desc: &DESCRIPTOR,
+                plugin_data: ptr::null_mut(),
+                init: Some(plugin_init),
+                destroy: Some(plugin_destroy),
+                activate: Some(plugin_activate),
+                deactivate: None,
+                start_processing: Some(plugin_start),
+                stop_processing: None,
+                reset: None,
+                process: Some(plugin_process),
+                get_extension: Some(plugin_extension),
+                on_main_thread: None,
+            },
+            gain: 1.0,
+        }));
+        unsafe {
+            (*gain).plugin.plugin_data = gain.cast();
+            &(*gain).plugin
+        }
+    }
+
+    fn gain(plugin: *const clap_plugin) -> &'static mut Gain {
+        unsafe { &mut *(*plugin).plugin_data.cast::<Gain>() }
+    }
+
+    unsafe extern "C" fn plugin_init(_p: *const clap_plugin) -> bool {
+        true
+    }
+
+    unsafe extern "C" fn plugin_destroy(p: *const clap_plugin) {
+        drop(unsafe { Box::from_raw((*p).plugin_data.cast::<Gain>()) });
+    }
+
+    unsafe extern "C" fn plugin_activate(_p: *const clap_plugin, _sr: f64, _min: u32, _max: u32) -> bool {
+        true
+    }
+
+    unsafe extern "C" fn plugin_start(_p: *const clap_plugin) -> bool {
+        true
+    }
+
+    unsafe extern "C" fn plugin_extension(_p: *const clap_plugin, id: *const c_char) -> *const c_void {
+        let id = unsafe { CStr::from_ptr(id) };
+        if id == CLAP_EXT_PARAMS {
+            (&PARAMS as *const clap_plugin_params).cast()
+        } else if id == CLAP_EXT_AUDIO_PORTS {
+            (&PORTS as *const clap_plugin_audio_ports).cast()
+        } else {
+            ptr::null()
+        }
+    }
+
+    unsafe extern "C" fn param_count(_p: *const clap_plugin) -> u32 {
+        1
+    }
+
+    unsafe extern "C" fn param_info(_p: *const clap_plugin, _i: u32, info: *mut clap_param_info) -> bool {
+        let info = unsafe { &mut *info };
+        info.id = 7;
+        info.flags = 0;
+        for (dst, &src) in info.name.iter_mut().zip(b"Output Gain With A Long Name\0") {
+            *dst = src as c_char;
+        }
+        info.min_value = 0.0;
+        info.max_value = 2.0;
+        info.default_value = 1.0;
+        true
+    }
+
+    unsafe extern "C" fn port_count(_p: *const clap_plugin, _is_input: bool) -> u32 {
+        1
+    }
+
+    unsafe extern "C" fn port_info(_p: *const clap_plugin, _i: u32, _is_input: bool, info: *mut clap_audio_port_info) -> bool {
+        unsafe { (*info).channel_count = 2 };
+        true
+    }
+
+    unsafe extern "C" fn plugin_process(p: *const clap_plugin, process: *const clap_process) -> i32 {
+        let (gain, process) = (gain(p), unsafe { &*process });
+        let events = unsafe { &*process.in_events };
+        for i in 0..unsafe { events.size.unwrap()(events) } {
+            let header = unsafe { &*events.get.unwrap()(events, i) };
+            match header.type_ {
+                CLAP_EVENT_PARAM_VALUE => {
+                    let event = unsafe { &*(header as *const clap_event_header).cast::<clap_event_param_value>() };
+                    assert_eq!(event.param_id, 7);
+                    gain.gain = event.value;
+                }
+                CLAP_EVENT_NOTE_ON => {
+                    let event = unsafe { &*(header as *const clap_event_header).cast::<clap_event_note>() };
+                    assert_eq!(event.key, 60);
+                    NOTES.fetch_add(1, Ordering::Relaxed);
+                }
+                CLAP_EVENT_NOTE_CHOKE => {
+                    let event = unsafe { &*(header as *const clap_event_header).cast::<clap_event_note>() };
+                    assert_eq!((event.channel, event.key), (-1, -1));
+                    CHOKES.fetch_add(1, Ordering::Relaxed);
+                }
+                _ => {}
+            }
+        }
+        let (input, output) = unsafe { (&*process.audio_inputs, &*process.audio_outputs) };
+        for ch in 0..2 {
+            let frames = process.frames_count as usize;
+            let src = unsafe { core::slice::from_raw_parts(*input.data32.add(ch), frames) };
+            let dst = unsafe { core::slice::from_raw_parts_mut(*output.data32.add(ch), frames) };
+            for (d, s) in dst.iter_mut().zip(src) {
+                *d = s * gain.gain as f32;
+            }
+        }
+        clap_sys::process::CLAP_PROCESS_CONTINUE
+    }
+
+    fn gain_machine() -> ClapMachine {
+        let bundle = {
+            let mut bundles = BUNDLES.lock().unwrap();
+            match bundles.iter().find(|b| b.path == "test://gain") {
+                Some(bundle) => Arc::clone(bundle),
+                None => {
+                    // SAFETY: the entry is a static with valid calls
+                    let bundle = unsafe { init_bundle("test://gain", &ENTRY, None) }.unwrap();
+                    bundles.push(Arc::clone(&bundle));
+                    bundle
+                }
+            }
+        };
+        let mut machine = ClapMachine::create(bundle, "test.gain").unwrap();
+        machine.init(44100);
+        machine
+    }
+
+    fn render_half(machine: &mut ClapMachine) -> AudioBuffer {
+        let mut buf = AudioBuffer::new(2, 8);
+        buf.channel_mut(0).fill(0.5);
+        buf.channel_mut(1).fill(-0.5);
+        machine.render(&mut buf);
+        buf
+    }
+
+    #[test]
+    fn clap_parameters_become_machine_parameters() {
+        let mut machine = gain_machine();
+        let info = machine.info();
+        assert_eq!((info.name, info.author, info.machine_type), ("Test Gain", "tests", MachineType::Effect));
+        let param = &info.params[0];
+        assert_eq!((param.name, param.min, param.max, param.default), ("Output Gain With", 0, PARAM_STEPS, PARAM_STEPS / 2));
+        assert_eq!(machine.channel_config(), ChannelConfig { inputs: 2, outputs: 2 });
+
+        assert_eq!(render_half(&mut machine).channel(0), [0.5; 8]);
+        machine.set_param(0, PARAM_STEPS);
+        let buf = render_half(&mut machine);
+        assert_eq!((buf.channel(0), buf.channel(1)), ([1.0; 8].as_slice(), [-1.0; 8].as_slice()));
+    }
+
+    #[test]
+    fn pattern_notes_reach_the_plugin_as_midi_keys() {
+        let mut machine = gain_machine();
+        let before = NOTES.load(Ordering::Relaxed);
+        machine.apply_event(0, &EventPayload::NoteOn { note: 48, velocity: 64, instrument: 0 });
+        render_half(&mut machine);
+        assert_eq!(NOTES.load(Ordering::Relaxed), before + 1);
+        assert!(matches!(plugin_descriptors("test://gain").as_deref(), Ok([d]) if d.id == "test.gain"));
+    }
+
+    #[test]
+    fn stop_chokes_every_channel() {
+        let mut machine = gain_machine();
+        let before = CHOKES.load(Ordering::Relaxed);
+        machine.stop();
+        render_half(&mut machine);
+        assert_eq!(CHOKES.load(Ordering::Relaxed), before + 1);
+    }
+
+    #[test]
+    fn missing_bundles_fall_back_to_passthrough() {
+        assert!(matches!(ClapMachine::load("/nonexistent/Verb.clap", "x"), Err(ClapError::Load(_))));
+        let machine = crate::machines::create_plugin("/nonexistent/Verb.clap", "x");
+        assert_eq!(machine.info().name, "Passthrough");
+    }
+}
@@ -0,0 +1,307 @@
+//! Feed-forward compressor/limiter.
+//!
+//! A stereo-linked peak detector drives a gain computer with a hard knee.
+//! The same machine plays "Geonik's Compressor" nodes and, built with
+//! `Compressor::limiter`, the engine's optional master bus limiter: with
+//! an infinite ratio and zero attack the output never exceeds the
+//! threshold, so summing many channels can't clip.
+
+use mb_ir::{AudioBuffer, AudioStream, ChannelConfig, ParamCurve, ParamUnit};
+use crate::machine::{Machine, MachineInfo, MachineType, ParamInfo};
+
+const PARAM_THRESHOLD: u16 = 0;
+const PARAM_RATIO: u16 = 1;
+const PARAM_ATTACK: u16 = 2;
+const PARAM_RELEASE: u16 = 3;
+const PARAM_MAKEUP: u16 = 4;
+
+static PARAMS: &[ParamInfo] = &[
+    ParamInfo {
+        id: PARAM_THRESHOLD,
+        name: "Threshold",
+        min: -60,
+        max: 0,
+        default: -12,
+        no_value: 0xFF,
+        unit: ParamUnit::Decibels,
+        curve: ParamCurve::Raw,
+    },
+    ParamInfo {
+        id: PARAM_RATIO,
+        name: "Ratio",
+        min: 1,
+        max: 20,
+        default: 4,
+        no_value: 0xFF,
+        unit: ParamUnit::None,
+        curve: ParamCurve::Raw,
+    },
+    ParamInfo {
+        id: PARAM_ATTACK,
+        name: "Attack",
+        min: 0,
+        max: 250,
+        default: 10,
+        no_value: 0xFF,
+        unit: ParamUnit::Milliseconds,
+        curve: ParamCurve::Raw,
+    },
+    ParamInfo {
+        id: PARAM_RELEASE,
+        name: "Release",
+        min: 1,
+        max: 2000,
+        default: 200,
+        no_value: 0xFFFF,
+        unit: ParamUnit::Milliseconds,
+        curve: ParamCurve::Raw,
+    },
+    ParamInfo {
+        id: PARAM_MAKEUP,
+        name: "Makeup",
+        min: 0,
+        max: 24,
+        default: 0,
+        no_value: 0xFF,
+        unit: ParamUnit::Decibels,
+        curve: ParamCurve::Raw,
+    },
+];
+
+static INFO: MachineInfo = MachineInfo {
+    name: "Compressor",
+    short_name: "Comp",
+    author: "masterblaster",
+    machine_type: MachineType::Effect,
+    params: PARAMS,
+};
+
+/// Release time of the master limiter.
+const LIMITER_RELEASE_MS: f32 = 50.0;
+
+fn db_to_gain(db: f32) -> f32 {
+    libm::powf(10.0, db / 20.0)
+}
+
+/// One-pole smoothing coefficient for a time constant (0 = instant).
+fn time_coef(ms: f32, sample_rate: u32) -> f32 {
+    if ms <= 0.0 {
+        return 0.0;
+    }
+    libm::expf(-1000.0 / (ms * sample_rate as f32))
+}
+
+/// Stereo dynamics processor.
+pub struct Compressor {
+    sample_rate: u32,
+    /// Threshold as linear amplitude
+    threshold: f32,
+    /// Compression ratio (infinite for a limiter)
+    ratio: f32,
+    attack_ms: f32,
+    release_ms: f32,
+    makeup: f32,
+    attack_coef: f32,
+    release_coef: f32,
+    /// Detector envelope (linear peak level)
+    envelope: f32,
+}
+
+impl Compressor {
+    pub fn new() -> Self {
+        let default = |i: usize| PARAMS[i].default as f32;
+        let mut comp = Self {
+            sample_rate: 44100,
+            threshold: db_to_gain(default(0)),
+            ratio: default(1),
+            attack_ms: default(2),
+            release_ms: default(3),
+            makeup: db_to_gain(default(4)),
+            attack_coef: 0.0,
+            release_coef: 0.0,
+            envelope: 0.0,
+        };
+        comp.recompute();
+        comp
+    }
+
+    /// A brick-wall limiter with its ceiling at full scale.
+    pub fn limiter(sample_rate: u32) -> Self {
+        let mut comp = Self {
+            threshold: 1.0,
+            ratio: f32::INFINITY,
+            attack_ms: 0.0,
+            release_ms: LIMITER_RELEASE_MS,
+            makeup: 1.0,
+            ..Self::new()
+        };
+        comp.init(sample_rate);
+        comp
+    }
+
+    fn recompute(&mut self) {
+        self.attack_coef = time_coef(self.attack_ms, self.sample_rate);
+        self.release_coef = time_coef(self.release_ms, self.sample_rate);
+    }
+
+    /// Gain for the current detector level.
+    fn gain(&self) -> f32 {
+        if self.envelope <= self.threshold {
+            return self.makeup;
+        }
+        // Above threshold the output rises 1/ratio as fast as the input
+        let slope = 1.0 / self.ratio - 1.0;
+        self.makeup * libm::powf(self.envelope / self.threshold, slope)
+    }
+
+    fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let level = l.abs().max(r.abs());
+            let coef = if level > self.envelope { self.attack_coef } else { self.release_coef };
+            self.envelope = level + coef * (self.envelope - level);
+            let gain = self.gain();
+            *l *= gain;
+            *r *= gain;
+        }
+    }
+}
+
+impl AudioStream for Compressor {
+    fn channel_config(&self) -> ChannelConfig {
+        ChannelConfig { inputs: 2, outputs: 2 }
+    }
+
+    fn render(&mut self, output: &mut AudioBuffer) {
+        let frames = output.frames() as usize;
+        if output.channels() < 2 {
+            return;
+        }
+        let (left, right) = output.channels_mut_2(0, 1);
+        self.process(&mut left[..frames], &mut right[..frames]);
+    }
+}
+
+impl Machine for Compressor {
+    fn info(&self) -> &MachineInfo {
+        &INFO
+    }
+
+    fn init(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.recompute();
+    }
+
+    fn tick(&mut self) {}
+
+    fn stop(&mut self) {
+        self.envelope = 0.0;
+    }
+
+    fn set_param(&mut self, param: u16, value: i32) {
+        let clamped = |i: usize| value.clamp(PARAMS[i].min, PARAMS[i].max) as f32;
+        match param {
+            PARAM_THRESHOLD => self.threshold = db_to_gain(clamped(0)),
+            PARAM_RATIO => self.ratio = clamped(1),
+            PARAM_ATTACK => self.attack_ms = clamped(2),
+            PARAM_RELEASE => self.release_ms = clamped(3),
+            PARAM_MAKEUP => self.makeup = db_to_gain(clamped(4)),
+            _ => return,
+        }
+        self.recompute();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: u32 = 44100;
+
+    /// Peak output over the second half of a constant-level block.
+    fn settled_peak(comp: &mut Compressor, level: f32) -> f32 {
+        let n = 4096;
+        let mut buf = AudioBuffer::new(2, n as u16);
+        for i in 0..n {
+            let v = if i % 2 == 0 { level } else { -level };
+            buf.channel_mut(0)[i] = v;
+            buf.channel_mut(1)[i] = v;
+        }
+        comp.render(&mut buf);
+        buf.channel(0)[n / 2..].iter().fold(0.0f32, |m, s| m.max(s.abs()))
+    }
+
+    fn compressor(threshold: i32, ratio: i32) -> Compressor {
+        let mut c = Compressor::new();
+        c.init(SR);
+        c.set_param(PARAM_THRESHOLD, threshold);
+        c.set_param(PARAM_RATIO, ratio);
+        c.set_param(PARAM_ATTACK, 0);
+        c
+    }
+
+    #[test]
+    fn below_threshold_is_untouched() {
+        let mut c = compressor(-6, 4);
+        assert_eq!(settled_peak(&mut c, 0.25), 0.25);
+    }
+
+    #[test]
+    fn ratio_scales_overshoot() {
+        // 12 dB over a -12 dB threshold at 4:1 comes out 3 dB over
+        let mut c = compressor(-12, 4);
+        let out = settled_peak(&mut c, 1.0);
+        assert!((out - db_to_gain(-9.0)).abs() < 1e-3, "{}", out);
+    }
+
+    #[test]
+    fn makeup_adds_gain() {
+        let mut c = compressor(0, 1);
+        c.set_param(PARAM_MAKEUP, 6);
+        assert!((settled_peak(&mut c, 0.25) - 0.25 * db_to_gain(6.0)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn attack_lets_transient_through() {
+        let mut c = compressor(-12, 20);
+        c.set_param(PARAM_ATTACK, 50);
+        let mut buf = AudioBuffer::new(2, 16);
+        buf.channel_mut(0).fill(1.0);
+        c.render(&mut buf);
+        assert!(buf.channel(0)[0] > 0.9);
+    }
+
+    #[test]
+    fn release_recovers_gain_slowly() {
+        let mut c = compressor(-12, 20);
+        settled_peak(&mut c, 1.0);
+        let squashed = c.gain();
+        let mut buf = AudioBuffer::new(2, 64);
+        c.render(&mut buf);
+        assert!(c.gain() > squashed && c.gain() < 1.0);
+        c.stop();
+        assert_eq!(c.gain(), 1.0);
+    }
+
+    #[test]
+    fn limiter_never_exceeds_full_scale() {
+        let mut l = Compressor::limiter(SR);
+        let mut buf = AudioBuffer::new(2, 256);
+        for i in 0..256 {
+            buf.channel_mut(0)[i] = 4.0 * libm::sinf(i as f32 * 0.1);
+            buf.channel_mut(1)[i] = -3.0;
+        }
+        l.render(&mut buf);
+        for ch in 0..2 {
+            assert!(buf.channel(ch).iter().all(|s| s.abs() <= 1.0 + 1e-6));
+        }
+        assert_eq!(settled_peak(&mut Compressor::limiter(SR), 0.5), 0.5);
+    }
+
+    #[test]
+    fn geonik_compressor_gets_real_dynamics() {
+        let m = super::super::create_machine("Geonik's Compressor").unwrap();
+        assert_eq!(m.info().name, "Compressor");
+        let params = super::super::default_parameters("Compressor");
+        assert_eq!(params[PARAM_THRESHOLD as usize].display_value(), "-12.0 dB");
+    }
+}
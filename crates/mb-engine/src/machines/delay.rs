@@ -0,0 +1,353 @@
+//! Stereo feedback delay with tempo-synced lengths.
+//!
+//! Parameters follow Jeskola Delay's layout (dry thru, length, length
+//! unit, feedback, wet out) so BMX songs map straight on, plus a
+//! cross-feedback amount for ping-pong echoes. Tick-based lengths follow
+//! the tempo by measuring how many frames are rendered between `tick()`
+//! calls, so the machine needs no tempo plumbing.
+
+use alloc::vec::Vec;
+
+use mb_ir::{AudioBuffer, AudioStream, ChannelConfig, ParamCurve, ParamUnit};
+use crate::machine::{Machine, MachineInfo, MachineType, ParamInfo};
+
+const PARAM_DRY_THRU: u16 = 0;
+const PARAM_LENGTH: u16 = 1;
+const PARAM_UNIT: u16 = 2;
+const PARAM_FEEDBACK: u16 = 3;
+const PARAM_WET: u16 = 4;
+const PARAM_CROSS: u16 = 5;
+
+/// Full scale of the feedback, wet and cross-feedback amounts.
+const AMOUNT_MAX: i32 = 128;
+
+/// Longest echo the buffer holds.
+const MAX_DELAY_SECONDS: u32 = 4;
+
+const UNIT_LABELS: &[&str] = &["tick", "ms", "sample", "256th of tick"];
+const SWITCH_LABELS: &[&str] = &["off", "on"];
+const PERCENT: ParamCurve = ParamCurve::Linear { lo: 0.0, hi: 100.0 };
+
+static PARAMS: &[ParamInfo] = &[
+    ParamInfo {
+        id: PARAM_DRY_THRU,
+        name: "Dry thru",
+        min: 0,
+        max: 1,
+        default: 1,
+        no_value: 0xFF,
+        unit: ParamUnit::Enum(SWITCH_LABELS),
+        curve: ParamCurve::Raw,
+    },
+    ParamInfo {
+        id: PARAM_LENGTH,
+        name: "Length",
+        min: 1,
+        max: 0xFFFE,
+        default: 3,
+        no_value: 0,
+        unit: ParamUnit::None,
+        curve: ParamCurve::Raw,
+    },
+    ParamInfo {
+        id: PARAM_UNIT,
+        name: "Length unit",
+        min: 0,
+        max: 3,
+        default: 0,
+        no_value: 0xFF,
+        unit: ParamUnit::Enum(UNIT_LABELS),
+        curve: ParamCurve::Raw,
+    },
+    ParamInfo {
+        id: PARAM_FEEDBACK,
+        name: "Feedback",
+        min: 0,
+        max: AMOUNT_MAX,
+        default: 64,
+        no_value: 0xFF,
+        unit: ParamUnit::Percent,
+        curve: PERCENT,
+    },
+    ParamInfo {
+        id: PARAM_WET,
+        name: "Wet out",
+        min: 0,
+        max: AMOUNT_MAX,
+        default: 64,
+        no_value: 0xFF,
+        unit: ParamUnit::Percent,
+        curve: PERCENT,
+    },
+    ParamInfo {
+        id: PARAM_CROSS,
+        name: "Cross feedback",
+        min: 0,
+        max: AMOUNT_MAX,
+        default: 0,
+        no_value: 0xFF,
+        unit: ParamUnit::Percent,
+        curve: PERCENT,
+    },
+];
+
+static INFO: MachineInfo = MachineInfo {
+    name: "Delay",
+    short_name: "Delay",
+    author: "masterblaster",
+    machine_type: MachineType::Effect,
+    params: PARAMS,
+};
+
+/// How the length parameter is measured.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LengthUnit {
+    Ticks,
+    Milliseconds,
+    Samples,
+    TickFractions,
+}
+
+/// Stereo delay line with feedback and cross-feedback.
+pub struct Delay {
+    /// Circular buffers, one per side
+    buffers: [Vec<f32>; 2],
+    write_pos: usize,
+    /// Current echo length in frames
+    delay_frames: usize,
+    sample_rate: u32,
+    /// Frames per tick, as measured between `tick()` calls
+    samples_per_tick: u32,
+    /// Frames rendered since the last `tick()`
+    frames_since_tick: u32,
+    length: u32,
+    unit: LengthUnit,
+    dry_thru: bool,
+    feedback: f32,
+    wet: f32,
+    cross: f32,
+}
+
+impl Delay {
+    pub fn new() -> Self {
+        let amount = |i: usize| PARAMS[i].default as f32 / AMOUNT_MAX as f32;
+        let mut delay = Self {
+            buffers: [Vec::new(), Vec::new()],
+            write_pos: 0,
+            delay_frames: 1,
+            sample_rate: 44100,
+            samples_per_tick: 0,
+            frames_since_tick: 0,
+            length: PARAMS[1].default as u32,
+            unit: LengthUnit::Ticks,
+            dry_thru: PARAMS[0].default != 0,
+            feedback: amount(3),
+            wet: amount(4),
+            cross: amount(5),
+        };
+        delay.samples_per_tick = default_samples_per_tick(delay.sample_rate);
+        delay
+    }
+
+    /// Recompute the echo length in frames from length, unit and tempo.
+    fn update_delay_frames(&mut self) {
+        let length = self.length as u64;
+        let frames = match self.unit {
+            LengthUnit::Ticks => length * self.samples_per_tick as u64,
+            LengthUnit::Milliseconds => length * self.sample_rate as u64 / 1000,
+            LengthUnit::Samples => length,
+            LengthUnit::TickFractions => length * self.samples_per_tick as u64 / 256,
+        };
+        let max = self.buffers[0].len().saturating_sub(1).max(1);
+        self.delay_frames = (frames as usize).clamp(1, max);
+    }
+
+    fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let len = self.buffers[0].len();
+        if len == 0 {
+            return;
+        }
+        let dry = if self.dry_thru { 1.0 } else { 0.0 };
+        let (straight, crossed) = (self.feedback * (1.0 - self.cross), self.feedback * self.cross);
+        let [buf_l, buf_r] = &mut self.buffers;
+        let mut write = self.write_pos;
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let read = (write + len - self.delay_frames) % len;
+            let (echo_l, echo_r) = (buf_l[read], buf_r[read]);
+            buf_l[write] = *l + straight * echo_l + crossed * echo_r;
+            buf_r[write] = *r + straight * echo_r + crossed * echo_l;
+            *l = dry * *l + self.wet * echo_l;
+            *r = dry * *r + self.wet * echo_r;
+            write = (write + 1) % len;
+        }
+        self.write_pos = write;
+    }
+}
+
+/// Frames per tick at the default 125 BPM, until a real tick is measured.
+fn default_samples_per_tick(sample_rate: u32) -> u32 {
+    sample_rate * 5 / (125 * 2)
+}
+
+impl AudioStream for Delay {
+    fn channel_config(&self) -> ChannelConfig {
+        ChannelConfig { inputs: 2, outputs: 2 }
+    }
+
+    fn render(&mut self, output: &mut AudioBuffer) {
+        let frames = output.frames() as usize;
+        self.frames_since_tick = self.frames_since_tick.saturating_add(frames as u32);
+        if output.channels() < 2 {
+            return;
+        }
+        let (left, right) = output.channels_mut_2(0, 1);
+        self.process(&mut left[..frames], &mut right[..frames]);
+    }
+}
+
+impl Machine for Delay {
+    fn info(&self) -> &MachineInfo {
+        &INFO
+    }
+
+    fn init(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.samples_per_tick = default_samples_per_tick(sample_rate);
+        let len = (sample_rate * MAX_DELAY_SECONDS) as usize;
+        self.buffers = [alloc::vec![0.0; len], alloc::vec![0.0; len]];
+        self.write_pos = 0;
+        self.update_delay_frames();
+    }
+
+    fn tick(&mut self) {
+        if self.frames_since_tick > 0 && self.frames_since_tick != self.samples_per_tick {
+            self.samples_per_tick = self.frames_since_tick;
+            self.update_delay_frames();
+        }
+        self.frames_since_tick = 0;
+    }
+
+    fn stop(&mut self) {
+        for buffer in &mut self.buffers {
+            buffer.fill(0.0);
+        }
+        self.frames_since_tick = 0;
+    }
+
+    fn set_param(&mut self, param: u16, value: i32) {
+        let amount = value.clamp(0, AMOUNT_MAX) as f32 / AMOUNT_MAX as f32;
+        match param {
+            PARAM_DRY_THRU => self.dry_thru = value != 0,
+            PARAM_LENGTH => self.length = value.clamp(1, 0xFFFE) as u32,
+            PARAM_UNIT => {
+                self.unit = match value {
+                    1 => LengthUnit::Milliseconds,
+                    2 => LengthUnit::Samples,
+                    3 => LengthUnit::TickFractions,
+                    _ => LengthUnit::Ticks,
+                };
+            }
+            PARAM_FEEDBACK => self.feedback = amount,
+            PARAM_WET => self.wet = amount,
+            PARAM_CROSS => self.cross = amount,
+            _ => return,
+        }
+        self.update_delay_frames();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: u32 = 8000;
+
+    fn delay(length: i32, unit: i32) -> Delay {
+        let mut d = Delay::new();
+        d.init(SR);
+        d.set_param(PARAM_LENGTH, length);
+        d.set_param(PARAM_UNIT, unit);
+        d
+    }
+
+    /// Render `frames` frames one block at a time with an impulse on the left at frame 0.
+    fn impulse_response(d: &mut Delay, frames: usize) -> Vec<[f32; 2]> {
+        let mut out = Vec::with_capacity(frames);
+        let mut buf = AudioBuffer::new(2, 1);
+        for i in 0..frames {
+            buf.channel_mut(0)[0] = if i == 0 { 1.0 } else { 0.0 };
+            buf.channel_mut(1)[0] = 0.0;
+            d.render(&mut buf);
+            out.push([buf.channel(0)[0], buf.channel(1)[0]]);
+        }
+        out
+    }
+
+    #[test]
+    fn echo_arrives_after_length() {
+        let mut d = delay(100, 2);
+        let out = impulse_response(&mut d, 301);
+        assert_eq!(out[0][0], 1.0);
+        assert!(out[1..100].iter().all(|f| f[0] == 0.0));
+        assert_eq!(out[100][0], 0.5);
+        assert_eq!(out[200][0], 0.25);
+        assert_eq!(out[300][0], 0.125);
+    }
+
+    #[test]
+    fn dry_thru_off_leaves_only_echoes() {
+        let mut d = delay(10, 2);
+        d.set_param(PARAM_DRY_THRU, 0);
+        let out = impulse_response(&mut d, 11);
+        assert_eq!(out[0][0], 0.0);
+        assert_eq!(out[10][0], 0.5);
+    }
+
+    #[test]
+    fn cross_feedback_bounces_between_sides() {
+        let mut d = delay(10, 2);
+        d.set_param(PARAM_CROSS, AMOUNT_MAX);
+        let out = impulse_response(&mut d, 21);
+        assert_eq!(out[10], [0.5, 0.0]);
+        assert_eq!(out[20], [0.0, 0.25]);
+    }
+
+    #[test]
+    fn millisecond_length() {
+        let mut d = delay(5, 1);
+        d.update_delay_frames();
+        assert_eq!(d.delay_frames, 40);
+    }
+
+    #[test]
+    fn tick_length_follows_measured_tempo() {
+        let mut d = delay(2, 0);
+        assert_eq!(d.delay_frames, 2 * default_samples_per_tick(SR) as usize);
+        let mut buf = AudioBuffer::new(2, 50);
+        d.render(&mut buf);
+        d.render(&mut buf);
+        d.tick();
+        assert_eq!(d.delay_frames, 200);
+        d.set_param(PARAM_UNIT, 3);
+        d.set_param(PARAM_LENGTH, 128);
+        assert_eq!(d.delay_frames, 50);
+    }
+
+    #[test]
+    fn stop_clears_echoes() {
+        let mut d = delay(10, 2);
+        impulse_response(&mut d, 5);
+        d.stop();
+        let out = impulse_response(&mut d, 11);
+        assert_eq!(out[10][0], 0.5);
+        assert!(out[1..10].iter().all(|f| f[0] == 0.0));
+    }
+
+    #[test]
+    fn jeskola_delay_gets_real_echoes() {
+        let m = super::super::create_machine("Jeskola Delay").unwrap();
+        assert_eq!(m.info().name, "Delay");
+        let params = super::super::default_parameters("Delay");
+        assert_eq!(params[PARAM_UNIT as usize].display_value(), "tick");
+    }
+}
@@ -0,0 +1,366 @@
+//! Kick drum synthesizer.
+//!
+//! A sine oscillator swept from a start to an end pitch, under an
+//! exponential amplitude decay, with a short noise click on the attack.
+//! Pattern notes trigger it (any note number; the volume column sets the
+//! hit's level), which is how "Jeskola Kick XP" trigger columns in BMX
+//! songs are played.
+
+use core::f32::consts::TAU;
+
+use mb_ir::{AudioBuffer, AudioStream, ChannelConfig, Effect, EventPayload, ParamCurve, ParamUnit};
+use crate::machine::{Machine, MachineInfo, MachineType, ParamInfo};
+
+const PARAM_START: u16 = 0;
+const PARAM_END: u16 = 1;
+const PARAM_PITCH_DECAY: u16 = 2;
+const PARAM_DECAY: u16 = 3;
+const PARAM_CLICK: u16 = 4;
+const PARAM_VOLUME: u16 = 5;
+
+/// Independent kick voices, one per pattern column.
+pub const MAX_VOICES: usize = 8;
+
+/// Length of the attack click.
+const CLICK_MS: f32 = 2.0;
+
+/// Hits fade to nothing once the envelope falls below this.
+const SILENCE: f32 = 1.0 / 65536.0;
+
+const PERCENT: ParamCurve = ParamCurve::Linear { lo: 0.0, hi: 100.0 };
+
+static PARAMS: &[ParamInfo] = &[
+    ParamInfo {
+        id: PARAM_START,
+        name: "Start",
+        min: 20,
+        max: 2000,
+        default: 220,
+        no_value: 0xFFFF,
+        unit: ParamUnit::Hz,
+        curve: ParamCurve::Raw,
+    },
+    ParamInfo {
+        id: PARAM_END,
+        name: "End",
+        min: 20,
+        max: 500,
+        default: 50,
+        no_value: 0xFFFF,
+        unit: ParamUnit::Hz,
+        curve: ParamCurve::Raw,
+    },
+    ParamInfo {
+        id: PARAM_PITCH_DECAY,
+        name: "Pitch decay",
+        min: 1,
+        max: 1000,
+        default: 30,
+        no_value: 0xFFFF,
+        unit: ParamUnit::Milliseconds,
+        curve: ParamCurve::Raw,
+    },
+    ParamInfo {
+        id: PARAM_DECAY,
+        name: "Decay",
+        min: 10,
+        max: 5000,
+        default: 300,
+        no_value: 0xFFFF,
+        unit: ParamUnit::Milliseconds,
+        curve: ParamCurve::Raw,
+    },
+    ParamInfo {
+        id: PARAM_CLICK,
+        name: "Click",
+        min: 0,
+        max: 100,
+        default: 30,
+        no_value: 0xFF,
+        unit: ParamUnit::Percent,
+        curve: ParamCurve::Raw,
+    },
+    ParamInfo {
+        id: PARAM_VOLUME,
+        name: "Volume",
+        min: 0,
+        max: 128,
+        default: 128,
+        no_value: 0xFF,
+        unit: ParamUnit::Percent,
+        curve: PERCENT,
+    },
+];
+
+static INFO: MachineInfo = MachineInfo {
+    name: "Kick",
+    short_name: "Kick",
+    author: "masterblaster",
+    machine_type: MachineType::Generator,
+    params: PARAMS,
+};
+
+/// One sounding hit.
+#[derive(Clone, Copy, Debug)]
+struct Voice {
+    /// Amplitude envelope; 0 when idle
+    amp: f32,
+    /// Pitch sweep remaining, decaying from 1 to 0
+    sweep: f32,
+    /// Click envelope
+    click: f32,
+    /// Oscillator phase, 0.0–1.0
+    phase: f32,
+    /// Hit level, 0.0–1.0
+    volume: f32,
+}
+
+impl Voice {
+    const IDLE: Self = Self { amp: 0.0, sweep: 0.0, click: 0.0, phase: 0.0, volume: 0.0 };
+}
+
+/// Per-frame multiplier for an exponential decay with time constant `ms`.
+fn decay_coef(ms: i32, sample_rate: u32) -> f32 {
+    libm::expf(-1000.0 / (ms.max(1) as f32 * sample_rate as f32))
+}
+
+/// Pattern-triggered kick drum.
+pub struct Kick {
+    voices: [Voice; MAX_VOICES],
+    sample_rate: u32,
+    values: [i32; PARAMS.len()],
+    start_hz: f32,
+    end_hz: f32,
+    sweep_coef: f32,
+    amp_coef: f32,
+    click_coef: f32,
+    click: f32,
+    volume: f32,
+    /// Click noise state (LCG)
+    seed: u32,
+}
+
+impl Kick {
+    pub fn new() -> Self {
+        let mut values = [0; PARAMS.len()];
+        for (v, p) in values.iter_mut().zip(PARAMS) {
+            *v = p.default;
+        }
+        let mut kick = Self {
+            voices: [Voice::IDLE; MAX_VOICES],
+            sample_rate: 44100,
+            values,
+            start_hz: 0.0,
+            end_hz: 0.0,
+            sweep_coef: 0.0,
+            amp_coef: 0.0,
+            click_coef: 0.0,
+            click: 0.0,
+            volume: 0.0,
+            seed: 1,
+        };
+        kick.recompute();
+        kick
+    }
+
+    fn recompute(&mut self) {
+        let v = &self.values;
+        let sr = self.sample_rate;
+        self.start_hz = v[PARAM_START as usize] as f32;
+        self.end_hz = v[PARAM_END as usize] as f32;
+        self.sweep_coef = decay_coef(v[PARAM_PITCH_DECAY as usize], sr);
+        self.amp_coef = decay_coef(v[PARAM_DECAY as usize], sr);
+        self.click_coef = libm::expf(-1000.0 / (CLICK_MS * sr as f32));
+        self.click = v[PARAM_CLICK as usize] as f32 / 100.0;
+        self.volume = v[PARAM_VOLUME as usize] as f32 / 128.0;
+    }
+
+    fn trigger(&mut self, channel: usize, volume: f32) {
+        self.voices[channel] = Voice { amp: 1.0, sweep: 1.0, click: 1.0, phase: 0.0, volume };
+    }
+
+    fn next_noise(&mut self) -> f32 {
+        self.seed = self.seed.wrapping_mul(1664525).wrapping_add(1013904223);
+        (self.seed >> 8) as f32 / (1u32 << 23) as f32 - 1.0
+    }
+
+    fn render_voice(&mut self, index: usize, left: &mut [f32], right: &mut [f32]) {
+        let mut voice = self.voices[index];
+        let inv_sr = 1.0 / self.sample_rate as f32;
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            if voice.amp < SILENCE {
+                voice = Voice::IDLE;
+                break;
+            }
+            let hz = self.end_hz + (self.start_hz - self.end_hz) * voice.sweep;
+            let tone = libm::sinf(TAU * voice.phase);
+            let click = self.click * voice.click * self.next_noise();
+            let out = (tone * voice.amp + click) * voice.volume * self.volume;
+            *l += out;
+            *r += out;
+            voice.phase = (voice.phase + hz * inv_sr) % 1.0;
+            voice.sweep *= self.sweep_coef;
+            voice.amp *= self.amp_coef;
+            voice.click *= self.click_coef;
+        }
+        self.voices[index] = voice;
+    }
+}
+
+impl AudioStream for Kick {
+    fn channel_config(&self) -> ChannelConfig {
+        ChannelConfig { inputs: 0, outputs: 2 }
+    }
+
+    fn render(&mut self, output: &mut AudioBuffer) {
+        let frames = output.frames() as usize;
+        if output.channels() < 2 {
+            return;
+        }
+        let (left, right) = output.channels_mut_2(0, 1);
+        left[..frames].fill(0.0);
+        right[..frames].fill(0.0);
+        for i in 0..MAX_VOICES {
+            if self.voices[i].amp > 0.0 {
+                self.render_voice(i, &mut left[..frames], &mut right[..frames]);
+            }
+        }
+    }
+}
+
+impl Machine for Kick {
+    fn info(&self) -> &MachineInfo {
+        &INFO
+    }
+
+    fn init(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.recompute();
+    }
+
+    fn tick(&mut self) {}
+
+    fn stop(&mut self) {
+        self.voices = [Voice::IDLE; MAX_VOICES];
+    }
+
+    fn set_param(&mut self, param: u16, value: i32) {
+        let Some(info) = PARAMS.get(param as usize) else { return };
+        self.values[param as usize] = value.clamp(info.min, info.max);
+        self.recompute();
+    }
+
+    fn apply_event(&mut self, channel: u8, payload: &EventPayload) {
+        let ch = channel as usize;
+        if ch >= MAX_VOICES {
+            return;
+        }
+        match payload {
+            EventPayload::NoteOn { velocity, .. } => self.trigger(ch, (*velocity).min(64) as f32 / 64.0),
+            EventPayload::Effect(Effect::SetVolume(v)) => self.voices[ch].volume = (*v).min(64) as f32 / 64.0,
+            EventPayload::Effect(Effect::NoteCut(0)) => self.voices[ch] = Voice::IDLE,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: u32 = 44100;
+
+    fn kick() -> Kick {
+        let mut k = Kick::new();
+        k.init(SR);
+        k
+    }
+
+    fn hit(k: &mut Kick, ch: u8) {
+        k.apply_event(ch, &EventPayload::NoteOn { note: 48, velocity: 64, instrument: 0 });
+    }
+
+    fn render(k: &mut Kick, frames: u16) -> AudioBuffer {
+        let mut buf = AudioBuffer::new(2, frames);
+        k.render(&mut buf);
+        buf
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0f32, |m, s| m.max(s.abs()))
+    }
+
+    /// Rising zero crossings.
+    fn crossings(samples: &[f32]) -> usize {
+        samples.windows(2).filter(|w| w[0] < 0.0 && w[1] >= 0.0).count()
+    }
+
+    #[test]
+    fn silent_until_triggered() {
+        let mut k = kick();
+        assert_eq!(peak(render(&mut k, 256).channel(0)), 0.0);
+        hit(&mut k, 0);
+        assert!(peak(render(&mut k, 256).channel(0)) > 0.5);
+    }
+
+    #[test]
+    fn pitch_falls_from_start_to_end() {
+        let mut k = kick();
+        k.set_param(PARAM_CLICK, 0);
+        hit(&mut k, 0);
+        let buf = render(&mut k, 8820);
+        let head = crossings(&buf.channel(0)[..2205]);
+        let tail = crossings(&buf.channel(0)[6615..]);
+        assert!(head > tail, "{} vs {}", head, tail);
+        // Settled at the 50 Hz end pitch: ~2.5 cycles in 50 ms
+        assert!((2..=3).contains(&tail), "{}", tail);
+    }
+
+    #[test]
+    fn decay_fades_hit() {
+        let mut k = kick();
+        k.set_param(PARAM_DECAY, 20);
+        hit(&mut k, 0);
+        let early = peak(render(&mut k, 441).channel(0));
+        render(&mut k, 8000);
+        let late = peak(render(&mut k, 441).channel(0));
+        assert!(late < early * 0.01);
+        render(&mut k, 20000);
+        assert_eq!(k.voices[0].amp, 0.0);
+    }
+
+    #[test]
+    fn click_adds_attack_transient() {
+        let mut clean = kick();
+        clean.set_param(PARAM_CLICK, 0);
+        let mut clicky = kick();
+        clicky.set_param(PARAM_CLICK, 100);
+        hit(&mut clean, 0);
+        hit(&mut clicky, 0);
+        let (a, b) = (render(&mut clean, 32), render(&mut clicky, 32));
+        assert!(peak(&b.channel(0)[..8]) > peak(&a.channel(0)[..8]));
+    }
+
+    #[test]
+    fn volume_column_scales_hit() {
+        let mut k = kick();
+        hit(&mut k, 0);
+        k.apply_event(0, &EventPayload::Effect(Effect::SetVolume(16)));
+        let quiet = peak(render(&mut k, 441).channel(0));
+        hit(&mut k, 0);
+        let loud = peak(render(&mut k, 441).channel(0));
+        assert!((quiet / loud - 0.25).abs() < 0.05);
+    }
+
+    #[test]
+    fn columns_layer_and_stop_silences() {
+        let mut k = kick();
+        hit(&mut k, 0);
+        hit(&mut k, 1);
+        hit(&mut k, MAX_VOICES as u8);
+        render(&mut k, 64);
+        assert!(k.voices[0].amp > 0.0 && k.voices[1].amp > 0.0);
+        k.stop();
+        assert_eq!(peak(render(&mut k, 64).channel(0)), 0.0);
+    }
+}
@@ -0,0 +1,246 @@
+//! MIDI Out machine — plays a track on external gear.
+//!
+//! Produces no audio. Notes and a handful of channel effects become MIDI
+//! channel messages that the engine collects, stamped with their frame
+//! offset, for the host to send (see `Engine::drain_midi_output`). Each
+//! track column holds at most one note, as on a tracker channel: a new
+//! note releases the previous one.
+//!
+//! Note-ons wait until the engine collects the messages of that instant,
+//! so a volume column on the same row becomes the note's velocity. Any
+//! other volume is sent as channel volume (CC 7); panning, filter cutoff
+//! and resonance go out as CC 10, 74 and 71.
+
+use heapless::Vec;
+use mb_ir::{AudioBuffer, AudioStream, ChannelConfig, Effect, EventPayload, NodeId};
+
+use crate::machine::{Machine, MachineInfo, MachineType};
+
+static INFO: MachineInfo = MachineInfo {
+    name: "MIDI Out",
+    short_name: "MIDI",
+    author: "masterblaster",
+    machine_type: MachineType::Generator,
+    params: &[],
+};
+
+/// Track columns whose notes are followed; notes on later columns are dropped.
+pub const MIDI_COLUMNS: usize = 32;
+
+/// Messages held between collections; more are dropped.
+const QUEUE_CAPACITY: usize = 128;
+
+/// Tracker note 48 (C-4) is MIDI key 60.
+const KEY_OFFSET: u8 = 12;
+
+/// Velocity of notes without a volume column.
+const DEFAULT_VELOCITY: u8 = 100;
+
+const NOTE_OFF: u8 = 0x80;
+const NOTE_ON: u8 = 0x90;
+const CONTROL_CHANGE: u8 = 0xB0;
+const CC_VOLUME: u8 = 7;
+const CC_PAN: u8 = 10;
+const CC_RESONANCE: u8 = 71;
+const CC_CUTOFF: u8 = 74;
+
+/// A MIDI channel message from a MIDI Out node, `offset` frames into the
+/// block it was rendered in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MidiOutMessage {
+    pub node: NodeId,
+    pub offset: u32,
+    pub bytes: [u8; 3],
+}
+
+/// A note-on waiting for its velocity.
+#[derive(Clone, Copy)]
+struct PendingNote {
+    column: u8,
+    key: u8,
+    velocity: u8,
+}
+
+pub struct MidiOut {
+    /// MIDI channel (0-15)
+    channel: u8,
+    /// Key each column holds down
+    held: [Option<u8>; MIDI_COLUMNS],
+    /// Ticks until each column's note is cut (0 = no cut pending)
+    cut: [u8; MIDI_COLUMNS],
+    /// Bit per muted column
+    muted: u32,
+    pending: Vec<PendingNote, MIDI_COLUMNS>,
+    queue: Vec<[u8; 3], QUEUE_CAPACITY>,
+}
+
+impl MidiOut {
+    pub fn new(channel: u8) -> Self {
+        Self {
+            channel: channel & 0x0F,
+            held: [None; MIDI_COLUMNS],
+            cut: [0; MIDI_COLUMNS],
+            muted: 0,
+            pending: Vec::new(),
+            queue: Vec::new(),
+        }
+    }
+
+    fn send(&mut self, status: u8, data1: u8, data2: u8) {
+        let _ = self.queue.push([status | self.channel, data1 & 0x7F, data2 & 0x7F]);
+    }
+
+    /// Release whatever `column` holds, including a note not yet sent.
+    fn release(&mut self, column: usize) {
+        self.pending.retain(|p| p.column as usize != column);
+        self.cut[column] = 0;
+        if let Some(key) = self.held[column].take() {
+            self.send(NOTE_OFF, key, 0);
+        }
+    }
+
+    fn note_on(&mut self, column: usize, note: u8) {
+        self.release(column);
+        if self.muted & (1 << column) != 0 {
+            return;
+        }
+        let key = note.saturating_add(KEY_OFFSET).min(127);
+        let _ = self.pending.push(PendingNote { column: column as u8, key, velocity: DEFAULT_VELOCITY });
+    }
+
+    fn apply_effect(&mut self, column: usize, effect: &Effect) {
+        match *effect {
+            Effect::SetVolume(volume) => {
+                let value = (volume.min(64) as u16 * 127 / 64) as u8;
+                match self.pending.iter_mut().find(|p| p.column as usize == column) {
+                    Some(note) => note.velocity = value.max(1),
+                    None => self.send(CONTROL_CHANGE, CC_VOLUME, value),
+                }
+            }
+            Effect::SetPan(pan) => self.send(CONTROL_CHANGE, CC_PAN, pan / 2),
+            Effect::SetFilterCutoff(cutoff) => self.send(CONTROL_CHANGE, CC_CUTOFF, cutoff.min(127)),
+            Effect::SetFilterResonance(resonance) => self.send(CONTROL_CHANGE, CC_RESONANCE, resonance.min(127)),
+            Effect::NoteCut(0) => self.release(column),
+            Effect::NoteCut(ticks) => self.cut[column] = ticks,
+            _ => {}
+        }
+    }
+}
+
+impl AudioStream for MidiOut {
+    fn channel_config(&self) -> ChannelConfig {
+        ChannelConfig { inputs: 0, outputs: 2 }
+    }
+
+    fn render(&mut self, output: &mut AudioBuffer) {
+        output.silence();
+    }
+}
+
+impl Machine for MidiOut {
+    fn info(&self) -> &MachineInfo { &INFO }
+    fn init(&mut self, _sample_rate: u32) {}
+
+    fn tick(&mut self) {
+        for column in 0..MIDI_COLUMNS {
+            if self.cut[column] > 0 {
+                self.cut[column] -= 1;
+                if self.cut[column] == 0 {
+                    self.release(column);
+                }
+            }
+        }
+    }
+
+    /// Release every held note.
+    fn stop(&mut self) {
+        for column in 0..MIDI_COLUMNS {
+            self.release(column);
+        }
+    }
+
+    fn set_param(&mut self, _param: u16, _value: i32) {}
+
+    fn apply_event(&mut self, channel: u8, payload: &EventPayload) {
+        let column = channel as usize;
+        if column >= MIDI_COLUMNS {
+            return;
+        }
+        match payload {
+            EventPayload::NoteOn { note, .. } => self.note_on(column, *note),
+            EventPayload::NoteOff { .. } | EventPayload::NoteFade => self.release(column),
+            EventPayload::Effect(effect) | EventPayload::SecondEffect(effect) => self.apply_effect(column, effect),
+            _ => {}
+        }
+    }
+
+    fn set_channel_muted(&mut self, channel: u8, muted: bool) {
+        let column = channel as usize;
+        if column >= MIDI_COLUMNS {
+            return;
+        }
+        if muted {
+            self.muted |= 1 << column;
+            self.release(column);
+        } else {
+            self.muted &= !(1 << column);
+        }
+    }
+
+    fn drain_midi(&mut self, f: &mut dyn FnMut([u8; 3])) {
+        for i in 0..self.pending.len() {
+            let note = self.pending[i];
+            self.held[note.column as usize] = Some(note.key);
+            self.send(NOTE_ON, note.key, note.velocity);
+        }
+        self.pending.clear();
+        for &message in &self.queue {
+            f(message);
+        }
+        self.queue.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(machine: &mut MidiOut) -> alloc::vec::Vec<[u8; 3]> {
+        let mut out = alloc::vec::Vec::new();
+        machine.drain_midi(&mut |m| out.push(m));
+        out
+    }
+
+    #[test]
+    fn notes_take_their_velocity_from_the_volume_column() {
+        let mut m = MidiOut::new(2);
+        m.apply_event(0, &EventPayload::NoteOn { note: 48, velocity: 64, instrument: 1 });
+        m.apply_event(0, &EventPayload::Effect(Effect::SetVolume(32)));
+        m.apply_event(1, &EventPayload::NoteOn { note: 50, velocity: 64, instrument: 1 });
+        assert_eq!(drain(&mut m), [[0x92, 60, 63], [0x92, 62, DEFAULT_VELOCITY]]);
+
+        // Later volume is channel volume; a new note releases the old one
+        m.apply_event(0, &EventPayload::Effect(Effect::SetVolume(64)));
+        m.apply_event(0, &EventPayload::NoteOn { note: 36, velocity: 64, instrument: 1 });
+        assert_eq!(drain(&mut m), [[0xB2, CC_VOLUME, 127], [0x82, 60, 0], [0x92, 48, DEFAULT_VELOCITY]]);
+    }
+
+    #[test]
+    fn note_cut_and_stop_release_held_notes() {
+        let mut m = MidiOut::new(0);
+        m.apply_event(0, &EventPayload::NoteOn { note: 48, velocity: 64, instrument: 1 });
+        m.apply_event(0, &EventPayload::Effect(Effect::NoteCut(2)));
+        m.apply_event(3, &EventPayload::NoteOn { note: 55, velocity: 64, instrument: 1 });
+        drain(&mut m);
+        m.tick();
+        assert!(drain(&mut m).is_empty());
+        m.tick();
+        assert_eq!(drain(&mut m), [[0x80, 60, 0]]);
+
+        m.stop();
+        assert_eq!(drain(&mut m), [[0x80, 67, 0]]);
+        m.set_channel_muted(0, true);
+        m.apply_event(0, &EventPayload::NoteOn { note: 48, velocity: 64, instrument: 1 });
+        assert!(drain(&mut m).is_empty());
+    }
+}
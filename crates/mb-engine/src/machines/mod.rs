@@ -1,20 +1,124 @@
 //! Built-in machine implementations.
 
 mod amiga_filter;
+#[cfg(feature = "clap")]
+pub mod clap_host;
+mod compressor;
+mod delay;
+mod kick;
+pub mod midi_out;
+mod oversample;
 mod passthrough;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+#[cfg(feature = "std")]
+pub mod registry;
+mod svf;
+mod synth;
 pub mod tracker;
 
+pub(crate) use compressor::Compressor;
+
 use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use mb_ir::{AudioGraph, NodeId, NodeType, Parameter};
 
 use crate::machine::Machine;
 
 /// Create a machine by name.
 ///
-/// Returns the matching implementation if available, otherwise a
-/// `PassthroughMachine` so the graph shape is preserved.
+/// Returns the matching implementation if available (built-ins first,
+/// then machines registered at runtime), otherwise a `PassthroughMachine`
+/// so the graph shape is preserved.
 pub fn create_machine(name: &str) -> Option<Box<dyn Machine>> {
     Some(match name {
         "Amiga Filter" => Box::new(amiga_filter::AmigaFilter::new()),
-        _ => Box::new(passthrough::PassthroughMachine),
+        "Resonant Filter" | "Jeskola Filter 2" => Box::new(svf::ResonantFilter::new()),
+        "Delay" | "Jeskola Delay" => Box::new(delay::Delay::new()),
+        "Compressor" | "Geonik's Compressor" => Box::new(compressor::Compressor::new()),
+        "Synth" => Box::new(synth::Synth::new()),
+        "Kick" | "Jeskola Kick XP" => Box::new(kick::Kick::new()),
+        _ => {
+            #[cfg(feature = "std")]
+            if let Some(machine) = registry::create(name) {
+                return Some(machine);
+            }
+            Box::new(passthrough::PassthroughMachine)
+        }
     })
 }
+
+/// Create the machine hosting CLAP plugin `plugin_id` from the bundle at
+/// `path`, or a `PassthroughMachine` if it can't be loaded (or CLAP
+/// hosting isn't compiled in) so the graph shape is preserved.
+#[cfg_attr(not(feature = "clap"), allow(unused_variables))]
+pub fn create_plugin(path: &str, plugin_id: &str) -> Box<dyn Machine> {
+    #[cfg(feature = "clap")]
+    if let Ok(machine) = clap_host::ClapMachine::load(path, plugin_id) {
+        return Box::new(machine);
+    }
+    Box::new(passthrough::PassthroughMachine)
+}
+
+/// Longest parameter name a graph `Parameter` holds, in bytes.
+#[cfg(any(feature = "plugins", feature = "clap"))]
+const PARAM_NAME_LEN: usize = 16;
+
+/// `name` cut to fit a graph `Parameter`, so the node's parameters still
+/// match the machine's by name.
+#[cfg(any(feature = "plugins", feature = "clap"))]
+fn param_name(name: &str) -> &str {
+    let mut end = name.len().min(PARAM_NAME_LEN);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    &name[..end]
+}
+
+/// Whether a native machine is played by pattern notes, so a track can target it.
+pub fn plays_notes(name: &str) -> bool {
+    if matches!(name, "Synth" | "Kick" | "Jeskola Kick XP") {
+        return true;
+    }
+    #[cfg(feature = "std")]
+    if registry::plays_notes(name) {
+        return true;
+    }
+    false
+}
+
+/// Wrap a machine to run at `factor` × the engine sample rate (1 = unwrapped).
+pub fn oversampled(machine: Box<dyn Machine>, factor: u8) -> Box<dyn Machine> {
+    if factor <= 1 {
+        return machine;
+    }
+    Box::new(oversample::Oversampled::new(machine, factor))
+}
+
+/// Graph parameters (with display metadata) for a native machine by name.
+///
+/// Empty for names without a native implementation.
+pub fn default_parameters(name: &str) -> Vec<Parameter> {
+    create_machine(name)
+        .map(|m| m.info().params.iter().map(|p| p.to_parameter()).collect())
+        .unwrap_or_default()
+}
+
+/// Add a node to `graph`, giving a native machine or plugin its default parameters.
+///
+/// Returns the new node's ID.
+pub fn add_node(graph: &mut AudioGraph, node_type: NodeType) -> NodeId {
+    let parameters = match &node_type {
+        NodeType::Machine { machine_name, is_tracker: false } => default_parameters(machine_name),
+        NodeType::Plugin { path, plugin_id } => {
+            create_plugin(path, plugin_id).info().params.iter().map(|p| p.to_parameter()).collect()
+        }
+        _ => Vec::new(),
+    };
+    let id = graph.add_node(node_type);
+    if let Some(node) = graph.node_mut(id) {
+        node.parameters = parameters;
+    }
+    id
+}
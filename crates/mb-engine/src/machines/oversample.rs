@@ -0,0 +1,368 @@
+//! Oversampling wrapper — runs an inner machine at 2× or 4× the output rate.
+//!
+//! Each 2× step is a half-band FIR low-pass (Blackman-windowed sinc, `TAPS`
+//! long): upsampling zero-stuffs and filters, decimation filters and keeps
+//! every other sample. 4× cascades two steps. The stop band sits around
+//! -70 dB, so harmonics a nonlinear machine makes above the output Nyquist are
+//! removed before they fold back. The filters delay the signal by 31 frames
+//! at 2× and about 47 frames at 4×; the graph does not compensate for it.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::f32::consts::PI;
+
+use mb_ir::{AudioBuffer, AudioStream, ChannelConfig, EventPayload, Instrument, Interpolation, Sample, SampleFields, BLOCK_SIZE, MAX_CHANNELS};
+use crate::channel::ChannelState;
+use crate::machine::{Machine, MachineInfo};
+
+/// Supported oversampling factors.
+pub const MAX_FACTOR: usize = 4;
+
+/// Half-band filter length. Odd, with the centre tap at `MID`.
+const TAPS: usize = 63;
+const MID: usize = TAPS / 2;
+/// Non-zero taps on each side of the centre (the odd offsets 1, 3, .. MID).
+const SIDE: usize = MID.div_ceil(2);
+
+/// Symmetric half-band low-pass cutting at a quarter of the rate it runs at.
+/// Every even offset from the centre is zero, so only `side` is stored.
+struct HalfBand {
+    side: [f32; SIDE],
+}
+
+impl HalfBand {
+    fn new() -> Self {
+        let mut side = [0.0; SIDE];
+        for (j, tap) in side.iter_mut().enumerate() {
+            let offset = (2 * j + 1) as f32;
+            let x = PI * offset / 2.0;
+            let sinc = libm::sinf(x) / x;
+            // Blackman window spread over TAPS + 2 points so the ends aren't zero
+            let phase = 2.0 * PI * (MID as f32 + offset + 1.0) / (TAPS + 1) as f32;
+            let window = 0.42 - 0.5 * libm::cosf(phase) + 0.08 * libm::cosf(2.0 * phase);
+            *tap = 0.5 * sinc * window;
+        }
+        // Both polyphase halves sum to exactly 1/2, so DC passes untouched
+        let sum: f32 = side.iter().sum::<f32>() * 2.0;
+        for tap in &mut side {
+            *tap *= 0.5 / sum;
+        }
+        Self { side }
+    }
+
+    fn apply(&self, delay: &Delay) -> f32 {
+        let w = &delay.hist[delay.pos..delay.pos + TAPS];
+        let mut acc = 0.5 * w[MID];
+        for (j, &tap) in self.side.iter().enumerate() {
+            let offset = 2 * j + 1;
+            acc += tap * (w[MID - offset] + w[MID + offset]);
+        }
+        acc
+    }
+
+    /// Upsample `src` by 2 into `dst` (zero-stuff, then filter).
+    fn interpolate(&self, delay: &mut Delay, src: &[f32], dst: &mut [f32]) {
+        for (&x, pair) in src.iter().zip(dst.chunks_exact_mut(2)) {
+            delay.push(x);
+            pair[0] = 2.0 * self.apply(delay);
+            delay.push(0.0);
+            pair[1] = 2.0 * self.apply(delay);
+        }
+    }
+
+    /// Downsample `src` by 2 into `dst` (filter, then drop every other sample).
+    fn decimate(&self, delay: &mut Delay, src: &[f32], dst: &mut [f32]) {
+        for (pair, out) in src.chunks_exact(2).zip(dst.iter_mut()) {
+            delay.push(pair[0]);
+            delay.push(pair[1]);
+            *out = self.apply(delay);
+        }
+    }
+}
+
+/// Filter history, newest sample first. Stored twice over so the last
+/// `TAPS` samples are always one contiguous slice starting at `pos`.
+#[derive(Clone)]
+struct Delay {
+    hist: [f32; 2 * TAPS],
+    pos: usize,
+}
+
+impl Delay {
+    const EMPTY: Self = Self { hist: [0.0; 2 * TAPS], pos: 0 };
+
+    fn push(&mut self, x: f32) {
+        self.pos = if self.pos == 0 { TAPS - 1 } else { self.pos - 1 };
+        self.hist[self.pos] = x;
+        self.hist[self.pos + TAPS] = x;
+    }
+}
+
+/// Per-channel filter state for one 2× step.
+struct Stage {
+    up: [Delay; MAX_CHANNELS as usize],
+    down: [Delay; MAX_CHANNELS as usize],
+}
+
+impl Stage {
+    const EMPTY: Self = Self {
+        up: [Delay::EMPTY; MAX_CHANNELS as usize],
+        down: [Delay::EMPTY; MAX_CHANNELS as usize],
+    };
+}
+
+/// A machine rendered at `factor` × the engine sample rate.
+pub struct Oversampled {
+    inner: Box<dyn Machine>,
+    factor: usize,
+    filter: HalfBand,
+    /// One entry per 2× step: none at 1×, two at 4× (outer step first).
+    stages: Vec<Stage>,
+    /// Signal at the inner machine's rate.
+    buf: AudioBuffer,
+    /// Signal between the two steps at 4× (twice the output rate).
+    mid: AudioBuffer,
+    /// Channels processed (widest of the inner machine's inputs/outputs).
+    channels: u16,
+}
+
+impl Oversampled {
+    /// Wrap `inner`; `factor` is 1, 2 or 4, and anything else is rounded to
+    /// the nearest of those (3 and up run at 4×).
+    pub fn new(inner: Box<dyn Machine>, factor: u8) -> Self {
+        let factor = match factor {
+            0 | 1 => 1,
+            2 => 2,
+            _ => MAX_FACTOR,
+        };
+        let config = inner.channel_config();
+        let channels = config.inputs.max(config.outputs).clamp(1, MAX_CHANNELS);
+        let stages = (0..factor.trailing_zeros()).map(|_| Stage::EMPTY).collect();
+        Self {
+            inner,
+            factor,
+            filter: HalfBand::new(),
+            stages,
+            buf: AudioBuffer::new(channels, (BLOCK_SIZE * factor) as u16),
+            mid: AudioBuffer::new(channels, (BLOCK_SIZE * 2) as u16),
+            channels,
+        }
+    }
+
+    /// Fill the inner buffer with `frames` input frames upsampled by `factor`.
+    fn upsample(&mut self, input: &AudioBuffer, frames: usize) {
+        let Self { filter, stages, buf, mid, .. } = self;
+        buf.set_frames((frames * self.factor) as u16);
+        mid.set_frames((frames * 2) as u16);
+        for ch in 0..self.channels.min(input.channels()) {
+            let c = ch as usize;
+            let src = &input.channel(ch)[..frames];
+            match stages.as_mut_slice() {
+                [] => buf.channel_mut(ch)[..frames].copy_from_slice(src),
+                [outer] => filter.interpolate(&mut outer.up[c], src, buf.channel_mut(ch)),
+                [outer, inner] => {
+                    filter.interpolate(&mut outer.up[c], src, mid.channel_mut(ch));
+                    filter.interpolate(&mut inner.up[c], mid.channel(ch), buf.channel_mut(ch));
+                }
+                _ => unreachable!("at most two 2× steps"),
+            }
+        }
+    }
+
+    /// Decimate the inner buffer back down into `frames` output frames.
+    fn downsample(&mut self, output: &mut AudioBuffer, frames: usize) {
+        let Self { filter, stages, buf, mid, .. } = self;
+        for ch in 0..self.channels.min(output.channels()) {
+            let c = ch as usize;
+            let dst = &mut output.channel_mut(ch)[..frames];
+            match stages.as_mut_slice() {
+                [] => dst.copy_from_slice(&buf.channel(ch)[..frames]),
+                [outer] => filter.decimate(&mut outer.down[c], buf.channel(ch), dst),
+                [outer, inner] => {
+                    filter.decimate(&mut inner.down[c], buf.channel(ch), mid.channel_mut(ch));
+                    filter.decimate(&mut outer.down[c], mid.channel(ch), dst);
+                }
+                _ => unreachable!("at most two 2× steps"),
+            }
+        }
+    }
+}
+
+impl AudioStream for Oversampled {
+    fn channel_config(&self) -> ChannelConfig {
+        self.inner.channel_config()
+    }
+
+    fn render(&mut self, output: &mut AudioBuffer) {
+        let frames = output.frames() as usize;
+        self.upsample(output, frames);
+        self.inner.render(&mut self.buf);
+        self.downsample(output, frames);
+    }
+}
+
+impl Machine for Oversampled {
+    fn info(&self) -> &MachineInfo { self.inner.info() }
+    fn init(&mut self, sample_rate: u32) {
+        self.inner.init(sample_rate * self.factor as u32);
+    }
+    fn tick(&mut self) { self.inner.tick(); }
+    fn stop(&mut self) {
+        for stage in &mut self.stages {
+            *stage = Stage::EMPTY;
+        }
+        self.inner.stop();
+    }
+    fn set_param(&mut self, param: u16, value: i32) { self.inner.set_param(param, value); }
+    fn apply_event(&mut self, channel: u8, payload: &EventPayload) {
+        self.inner.apply_event(channel, payload);
+    }
+    fn set_speed(&mut self, speed: u8) { self.inner.set_speed(speed); }
+    fn set_tempo(&mut self, tempo: u32) { self.inner.set_tempo(tempo); }
+    fn update_sample(&mut self, index: u8, fields: &SampleFields) { self.inner.update_sample(index, fields); }
+    fn update_samples(&mut self, samples: &[Sample], instruments: &[Instrument]) {
+        self.inner.update_samples(samples, instruments);
+    }
+    fn set_channel_muted(&mut self, channel: u8, muted: bool) { self.inner.set_channel_muted(channel, muted); }
+    fn set_interpolation(&mut self, quality: Interpolation) { self.inner.set_interpolation(quality); }
+    fn channel_state(&self, channel: u8) -> Option<&ChannelState> { self.inner.channel_state(channel) }
+    fn drain_midi(&mut self, f: &mut dyn FnMut([u8; 3])) { self.inner.drain_midi(f); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machines::passthrough::PassthroughMachine;
+
+    fn buffer(values: &[f32]) -> AudioBuffer {
+        let mut buf = AudioBuffer::new(2, values.len() as u16);
+        buf.channel_mut(0).copy_from_slice(values);
+        buf.channel_mut(1).copy_from_slice(values);
+        buf
+    }
+
+    #[test]
+    fn passthrough_preserves_dc() {
+        let mut m = Oversampled::new(Box::new(PassthroughMachine), 4);
+        m.init(44100);
+        let mut buf = buffer(&[0.5; 128]);
+        m.render(&mut buf); // first block ramps in through the filters
+        let mut buf = buffer(&[0.5; 64]);
+        m.render(&mut buf);
+        assert!(buf.channel(0).iter().all(|&v| (v - 0.5).abs() < 1e-5));
+    }
+
+    #[test]
+    fn factor_is_clamped() {
+        let factor = |f| Oversampled::new(Box::new(PassthroughMachine), f).factor;
+        assert_eq!(factor(0), 1);
+        assert_eq!(factor(2), 2);
+        assert_eq!(factor(3), 4);
+        assert_eq!(factor(16), MAX_FACTOR);
+    }
+
+    #[test]
+    fn stop_clears_the_filters() {
+        let mut m = Oversampled::new(Box::new(PassthroughMachine), 2);
+        m.init(44100);
+        m.render(&mut buffer(&[1.0; 64]));
+        m.stop();
+        let mut buf = buffer(&[0.0; 64]);
+        m.render(&mut buf);
+        assert!(buf.channel(0).iter().all(|&v| v == 0.0));
+    }
+
+    /// Hard-clips at ±0.25, which makes plenty of harmonics above Nyquist.
+    struct Clipper;
+
+    impl AudioStream for Clipper {
+        fn channel_config(&self) -> ChannelConfig { ChannelConfig { inputs: 2, outputs: 2 } }
+        fn render(&mut self, output: &mut AudioBuffer) {
+            for ch in 0..output.channels() {
+                for v in output.channel_mut(ch) {
+                    *v = v.clamp(-0.25, 0.25);
+                }
+            }
+        }
+    }
+
+    impl Machine for Clipper {
+        fn info(&self) -> &MachineInfo { PassthroughMachine.info() }
+        fn init(&mut self, _sample_rate: u32) {}
+        fn tick(&mut self) {}
+        fn stop(&mut self) {}
+        fn set_param(&mut self, _param: u16, _value: i32) {}
+    }
+
+    /// Clip a ~15 kHz sine and return the energy left outside the
+    /// fundamental. Every harmonic of it lies above 22 kHz, so all of that
+    /// energy is aliasing.
+    fn aliased_energy(machine: &mut dyn Machine) -> f32 {
+        const N: usize = 2048;
+        const BIN: usize = 697; // 15009 Hz at 44.1 kHz, a whole number of cycles in N
+        machine.init(44100);
+        let w = 2.0 * PI * BIN as f32 / N as f32;
+        let mut signal = Vec::new();
+        // Two windows: the first flushes the filter delay
+        for block in 0..2 * N / BLOCK_SIZE {
+            let start = block * BLOCK_SIZE;
+            let values: Vec<f32> = (start..start + BLOCK_SIZE)
+                .map(|n| libm::sinf(w * (n % N) as f32))
+                .collect();
+            let mut buf = buffer(&values);
+            machine.render(&mut buf);
+            signal.extend_from_slice(buf.channel(0));
+        }
+        let window = &signal[N..];
+        let (mut re, mut im) = (0.0, 0.0);
+        for (n, &v) in window.iter().enumerate() {
+            re += v * libm::cosf(w * n as f32);
+            im += v * libm::sinf(w * n as f32);
+        }
+        let total: f32 = window.iter().map(|v| v * v).sum();
+        total - 2.0 * (re * re + im * im) / N as f32
+    }
+
+    #[test]
+    fn oversampling_removes_clipping_aliases() {
+        let plain = aliased_energy(&mut Clipper);
+        let twice = aliased_energy(&mut Oversampled::new(Box::new(Clipper), 2));
+        let four = aliased_energy(&mut Oversampled::new(Box::new(Clipper), 4));
+        // At 2× the clipper's own 5th harmonic (75 kHz) still folds into the
+        // audio band before the decimator sees it
+        assert!(twice < plain / 3.0, "2×: {twice} vs {plain}");
+        assert!(four < plain / 100.0, "4×: {four} vs {plain}");
+    }
+
+    #[test]
+    fn factor_one_is_transparent() {
+        let mut m = Oversampled::new(Box::new(PassthroughMachine), 1);
+        m.init(44100);
+        let mut buf = buffer(&[0.1, -0.3, 0.7, 0.2]);
+        m.render(&mut buf);
+        assert_eq!(buf.channel(0), &[0.1, -0.3, 0.7, 0.2]);
+    }
+
+    /// Asserts the rate and block size the wrapper hands it.
+    struct Probe { rate: u32, frames: u16 }
+
+    impl AudioStream for Probe {
+        fn channel_config(&self) -> ChannelConfig { ChannelConfig { inputs: 2, outputs: 2 } }
+        fn render(&mut self, output: &mut AudioBuffer) { assert_eq!(output.frames(), self.frames); }
+    }
+
+    impl Machine for Probe {
+        fn info(&self) -> &MachineInfo { PassthroughMachine.info() }
+        fn init(&mut self, sample_rate: u32) { assert_eq!(sample_rate, self.rate); }
+        fn tick(&mut self) {}
+        fn stop(&mut self) {}
+        fn set_param(&mut self, _param: u16, _value: i32) {}
+    }
+
+    #[test]
+    fn inner_runs_at_multiplied_rate() {
+        let mut m = Oversampled::new(Box::new(Probe { rate: 88200, frames: 128 }), 2);
+        m.init(44100);
+        m.render(&mut buffer(&[0.0; 64]));
+    }
+}
@@ -0,0 +1,651 @@
+//! Third-party machines loaded from shared libraries.
+//!
+//! A plugin is a cdylib exporting `mb_machine_entry`, which returns a
+//! `MachineDescriptor` for each machine index it provides and null past
+//! the last one. Descriptors are plain C (strings, parameter ranges,
+//! channel counts and lifecycle function pointers), so plugins can be
+//! written in any language with a C ABI. `load_plugin` registers every
+//! machine a library provides (see `registry`), after which songs naming
+//! them get the plugin instead of a passthrough.
+//!
+//! Plugins run in the host's process with no isolation: a plugin that
+//! crashes, unwinds, hangs or scribbles over memory takes the host down
+//! with it, so only load libraries you trust. What the host does check is
+//! the plugin's side of the ABI: parameter values are clamped to their
+//! declared ranges, and a non-zero status or non-finite or runaway output
+//! faults the instance. A faulted instance is never called again; it
+//! renders silence, or passes its input through if it is an effect.
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ffi::{c_char, c_void, CStr};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use libloading::Library;
+use mb_ir::{AudioBuffer, AudioStream, ChannelConfig, EventPayload, ParamCurve, ParamUnit};
+
+use crate::machine::{Machine, MachineInfo, MachineType, ParamInfo};
+use crate::machines::registry;
+
+/// Layout version of `MachineDescriptor`; bumped on incompatible changes.
+pub const MACHINE_ABI_VERSION: u32 = 1;
+
+/// Symbol every plugin exports, of type `EntryFn`.
+pub const ENTRY_SYMBOL: &str = "mb_machine_entry";
+
+/// Most channels a plugin machine may take or produce.
+pub const MAX_PLUGIN_CHANNELS: u16 = 8;
+
+/// Most machines one library may provide.
+const MAX_MACHINES_PER_LIBRARY: u32 = 256;
+
+/// Output beyond this (+36 dB) means the plugin has run away.
+const RUNAWAY_LEVEL: f32 = 64.0;
+
+/// `const MachineDescriptor *mb_machine_entry(uint32_t index)`
+pub type EntryFn = unsafe extern "C" fn(index: u32) -> *const MachineDescriptor;
+
+/// One parameter of a plugin machine.
+#[repr(C)]
+pub struct ParamDescriptor {
+    /// NUL-terminated UTF-8
+    pub name: *const c_char,
+    pub min: i32,
+    pub max: i32,
+    pub default: i32,
+    pub no_value: i32,
+}
+
+/// A plugin machine: metadata plus its lifecycle calls. Every call gets
+/// the instance pointer `create` returned; calls on one instance never
+/// overlap, though they may come from different threads.
+#[repr(C)]
+pub struct MachineDescriptor {
+    /// Must equal `MACHINE_ABI_VERSION`
+    pub abi_version: u32,
+    /// NUL-terminated UTF-8; the name songs refer to the machine by
+    pub name: *const c_char,
+    pub short_name: *const c_char,
+    pub author: *const c_char,
+    /// 0 = generator, 1 = effect
+    pub machine_type: u32,
+    pub params: *const ParamDescriptor,
+    pub param_count: u32,
+    /// Input and output channels (at most `MAX_PLUGIN_CHANNELS`)
+    pub inputs: u16,
+    pub outputs: u16,
+    /// A new instance, or null on failure
+    pub create: Option<unsafe extern "C" fn() -> *mut c_void>,
+    pub destroy: Option<unsafe extern "C" fn(instance: *mut c_void)>,
+    /// Returns 0 on success
+    pub init: Option<unsafe extern "C" fn(instance: *mut c_void, sample_rate: u32) -> i32>,
+    /// Once per tracker tick; returns 0 on success
+    pub tick: Option<unsafe extern "C" fn(instance: *mut c_void) -> i32>,
+    /// Silence all voices
+    pub stop: Option<unsafe extern "C" fn(instance: *mut c_void)>,
+    /// `value` is within the parameter's declared range
+    pub set_param: Option<unsafe extern "C" fn(instance: *mut c_void, param: u16, value: i32)>,
+    /// Pattern note on a sub-channel: velocity 1-64 starts `note`, 0
+    /// releases it. Optional; without it tracks can't play the machine.
+    pub note: Option<unsafe extern "C" fn(instance: *mut c_void, channel: u8, note: u8, velocity: u8)>,
+    /// Process `frames` frames in place over `channels` planar buffers,
+    /// the first `inputs` holding input and the first `outputs` receiving
+    /// output. Returns 0 on success.
+    pub render: Option<unsafe extern "C" fn(instance: *mut c_void, buffers: *const *mut f32, channels: u16, frames: u32) -> i32>,
+}
+
+/// Why a plugin couldn't be loaded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PluginError {
+    /// The library couldn't be opened or lacks `mb_machine_entry`
+    Load(String),
+    /// A descriptor was built for another ABI version
+    AbiVersion { machine: u32, version: u32 },
+    /// A descriptor is malformed (missing calls, bad strings or ranges)
+    Invalid { machine: u32, reason: String },
+}
+
+impl core::fmt::Display for PluginError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PluginError::Load(msg) => write!(f, "can't load plugin: {}", msg),
+            PluginError::AbiVersion { machine, version } => write!(
+                f, "machine {} uses ABI version {} (expected {})", machine, version, MACHINE_ABI_VERSION
+            ),
+            PluginError::Invalid { machine, reason } => write!(f, "machine {} is invalid: {}", machine, reason),
+        }
+    }
+}
+
+impl std::error::Error for PluginError {}
+
+/// The checked calls of one plugin machine.
+struct Calls {
+    create: unsafe extern "C" fn() -> *mut c_void,
+    destroy: unsafe extern "C" fn(*mut c_void),
+    init: unsafe extern "C" fn(*mut c_void, u32) -> i32,
+    tick: Option<unsafe extern "C" fn(*mut c_void) -> i32>,
+    stop: Option<unsafe extern "C" fn(*mut c_void)>,
+    set_param: Option<unsafe extern "C" fn(*mut c_void, u16, i32)>,
+    note: Option<unsafe extern "C" fn(*mut c_void, u8, u8, u8)>,
+    render: unsafe extern "C" fn(*mut c_void, *const *mut f32, u16, u32) -> i32,
+}
+
+/// A validated plugin machine, shared by its instances.
+pub struct PluginMachineType {
+    /// Lives for the rest of the process, as `MachineInfo` requires; see
+    /// `intern_info`
+    info: &'static MachineInfo,
+    config: ChannelConfig,
+    calls: Calls,
+    /// Keeps the code behind `calls` mapped; `None` for in-process plugins
+    _library: Option<Arc<Library>>,
+}
+
+impl PluginMachineType {
+    /// Validate a descriptor. `library` is the library it came from, kept
+    /// loaded for as long as the machine exists.
+    ///
+    /// # Safety
+    ///
+    /// `desc` must point to a `MachineDescriptor` whose pointers are valid
+    /// as documented, and whose calls behave as documented.
+    pub unsafe fn from_descriptor(
+        desc: &MachineDescriptor,
+        index: u32,
+        library: Option<Arc<Library>>,
+    ) -> Result<Self, PluginError> {
+        let invalid = |reason: &str| PluginError::Invalid { machine: index, reason: reason.to_string() };
+        if desc.abi_version != MACHINE_ABI_VERSION {
+            return Err(PluginError::AbiVersion { machine: index, version: desc.abi_version });
+        }
+        let (Some(create), Some(destroy), Some(init), Some(render)) = (desc.create, desc.destroy, desc.init, desc.render) else {
+            return Err(invalid("missing create, destroy, init or render"));
+        };
+        if desc.outputs == 0 || desc.inputs > MAX_PLUGIN_CHANNELS || desc.outputs > MAX_PLUGIN_CHANNELS {
+            return Err(invalid("unsupported channel counts"));
+        }
+        let machine_type = match desc.machine_type {
+            0 => MachineType::Generator,
+            1 => MachineType::Effect,
+            _ => return Err(invalid("unknown machine type")),
+        };
+        let name = c_string(desc.name).filter(|n| !n.is_empty()).ok_or_else(|| invalid("bad name"))?;
+        let short_name = c_string(desc.short_name).unwrap_or_else(|| name.clone());
+        let author = c_string(desc.author).unwrap_or_default();
+
+        let mut params: Vec<(String, &ParamDescriptor)> = Vec::with_capacity(desc.param_count as usize);
+        if desc.param_count > 0 {
+            if desc.params.is_null() {
+                return Err(invalid("missing parameters"));
+            }
+            // SAFETY: the caller guarantees `params` points to `param_count` descriptors
+            let raw = unsafe { core::slice::from_raw_parts(desc.params, desc.param_count as usize) };
+            for (id, p) in raw.iter().enumerate() {
+                let param_name = c_string(p.name).ok_or_else(|| invalid("bad parameter name"))?;
+                if p.min > p.max || id > u16::MAX as usize {
+                    return Err(invalid("bad parameter range"));
+                }
+                params.push((super::param_name(&param_name).to_string(), p));
+            }
+        }
+
+        let info = intern_info(&name, &short_name, &author, machine_type, &params);
+        Ok(Self {
+            info,
+            config: ChannelConfig { inputs: desc.inputs, outputs: desc.outputs },
+            calls: Calls {
+                create,
+                destroy,
+                init,
+                tick: desc.tick,
+                stop: desc.stop,
+                set_param: desc.set_param,
+                note: desc.note,
+                render,
+            },
+            _library: library,
+        })
+    }
+
+    /// The machine's name.
+    pub fn name(&self) -> &'static str {
+        self.info.name
+    }
+
+    /// Whether pattern notes can play the machine.
+    pub fn plays_notes(&self) -> bool {
+        self.calls.note.is_some()
+    }
+
+    /// A new instance, faulted from the start if the plugin can't create one.
+    pub fn instantiate(self: &Arc<Self>) -> PluginMachine {
+        // SAFETY: `create` is a validated call of a live descriptor
+        let instance = unsafe { (self.calls.create)() };
+        PluginMachine {
+            kind: Arc::clone(self),
+            instance,
+            fault: instance.is_null().then_some(Fault::Create),
+        }
+    }
+
+    /// Make the machine available to `create_machine` under its name.
+    pub fn register(self: &Arc<Self>) {
+        let kind = Arc::clone(self);
+        registry::register_machine(self.name(), self.plays_notes(), Arc::new(move || Box::new(kind.instantiate())));
+    }
+}
+
+/// Copy a NUL-terminated UTF-8 string, if the pointer is set and valid.
+fn c_string(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    // SAFETY: non-null descriptor strings are NUL-terminated per the ABI
+    unsafe { CStr::from_ptr(ptr) }.to_str().ok().map(str::to_string)
+}
+
+/// Every `MachineInfo` handed out so far. Loading the same machine again
+/// (rescanning the plugin directory, say) reuses its info instead of
+/// leaking another copy.
+static INFOS: Mutex<Vec<&'static MachineInfo>> = Mutex::new(Vec::new());
+
+/// The process-lifetime `MachineInfo` for a validated descriptor.
+fn intern_info(
+    name: &str,
+    short_name: &str,
+    author: &str,
+    machine_type: MachineType,
+    params: &[(String, &ParamDescriptor)],
+) -> &'static MachineInfo {
+    let matches = |info: &MachineInfo| {
+        info.name == name
+            && info.short_name == short_name
+            && info.author == author
+            && info.machine_type == machine_type
+            && info.params.len() == params.len()
+            && info.params.iter().zip(params).all(|(info, (param_name, p))| {
+                info.name == param_name
+                    && (info.min, info.max, info.no_value) == (p.min, p.max, p.no_value)
+                    && info.default == p.default.clamp(p.min, p.max)
+            })
+    };
+    let mut infos = INFOS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(info) = infos.iter().find(|info| matches(info)) {
+        return info;
+    }
+    let params: Vec<ParamInfo> = params.iter().enumerate().map(|(id, (param_name, p))| ParamInfo {
+        id: id as u16,
+        name: leak(param_name.clone()),
+        min: p.min,
+        max: p.max,
+        default: p.default.clamp(p.min, p.max),
+        no_value: p.no_value,
+        unit: ParamUnit::None,
+        curve: ParamCurve::Raw,
+    }).collect();
+    let info: &'static MachineInfo = Box::leak(Box::new(MachineInfo {
+        name: leak(name.to_string()),
+        short_name: leak(short_name.to_string()),
+        author: leak(author.to_string()),
+        machine_type,
+        params: Box::leak(params.into_boxed_slice()),
+    }));
+    infos.push(info);
+    info
+}
+
+fn leak(s: String) -> &'static str {
+    Box::leak(s.into_boxed_str())
+}
+
+/// What made a plugin instance stop being called.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// `create` returned null
+    Create,
+    /// `init` returned the status
+    Init(i32),
+    /// `tick` returned the status
+    Tick(i32),
+    /// `render` returned the status
+    Render(i32),
+    /// `render` produced NaN, infinity or runaway levels
+    BadOutput,
+}
+
+/// One instance of a plugin machine.
+pub struct PluginMachine {
+    kind: Arc<PluginMachineType>,
+    instance: *mut c_void,
+    fault: Option<Fault>,
+}
+
+// SAFETY: the ABI requires instances to accept calls from any thread as
+// long as they don't overlap, which `&mut self` guarantees
+unsafe impl Send for PluginMachine {}
+
+impl PluginMachine {
+    /// Why the instance was shut off, if it was.
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+
+    /// The live instance, unless it has faulted.
+    fn live(&self) -> Option<*mut c_void> {
+        self.fault.is_none().then_some(self.instance)
+    }
+
+    fn check(&mut self, status: i32, fault: fn(i32) -> Fault) {
+        if status != 0 {
+            self.fault = Some(fault(status));
+        }
+    }
+}
+
+impl Drop for PluginMachine {
+    fn drop(&mut self) {
+        if !self.instance.is_null() {
+            // SAFETY: the instance came from `create` and is destroyed once
+            unsafe { (self.kind.calls.destroy)(self.instance) };
+        }
+    }
+}
+
+impl AudioStream for PluginMachine {
+    fn channel_config(&self) -> ChannelConfig {
+        self.kind.config
+    }
+
+    fn render(&mut self, output: &mut AudioBuffer) {
+        let config = self.kind.config;
+        let channels = config.inputs.max(config.outputs).min(output.channels());
+        let frames = output.frames();
+        let Some(instance) = self.live() else {
+            if self.kind.info.machine_type == MachineType::Generator {
+                output.silence();
+            }
+            return;
+        };
+        let mut buffers = [core::ptr::null_mut::<f32>(); MAX_PLUGIN_CHANNELS as usize];
+        for (ch, ptr) in buffers.iter_mut().enumerate().take(channels as usize) {
+            *ptr = output.channel_mut(ch as u16).as_mut_ptr();
+        }
+        // SAFETY: each pointer addresses `frames` samples of a distinct
+        // channel, and `output` stays borrowed for the call
+        let status = unsafe { (self.kind.calls.render)(instance, buffers.as_ptr(), channels, frames as u32) };
+        self.check(status, Fault::Render);
+        let runaway = (0..config.outputs.min(channels))
+            .any(|ch| output.channel(ch).iter().any(|s| !s.is_finite() || s.abs() > RUNAWAY_LEVEL));
+        if runaway {
+            self.fault = Some(Fault::BadOutput);
+        }
+        if self.fault.is_some() {
+            output.silence();
+        }
+    }
+}
+
+impl Machine for PluginMachine {
+    fn info(&self) -> &MachineInfo {
+        self.kind.info
+    }
+
+    fn init(&mut self, sample_rate: u32) {
+        let Some(instance) = self.live() else { return };
+        // SAFETY: live instance from `create`
+        let status = unsafe { (self.kind.calls.init)(instance, sample_rate) };
+        self.check(status, Fault::Init);
+    }
+
+    fn tick(&mut self) {
+        let (Some(instance), Some(tick)) = (self.live(), self.kind.calls.tick) else { return };
+        // SAFETY: live instance from `create`
+        let status = unsafe { tick(instance) };
+        self.check(status, Fault::Tick);
+    }
+
+    fn stop(&mut self) {
+        let (Some(instance), Some(stop)) = (self.live(), self.kind.calls.stop) else { return };
+        // SAFETY: live instance from `create`
+        unsafe { stop(instance) };
+    }
+
+    fn set_param(&mut self, param: u16, value: i32) {
+        let Some(info) = self.kind.info.params.get(param as usize) else { return };
+        let (Some(instance), Some(set_param)) = (self.live(), self.kind.calls.set_param) else { return };
+        // SAFETY: live instance from `create`; the value is in range
+        unsafe { set_param(instance, param, value.clamp(info.min, info.max)) };
+    }
+
+    fn apply_event(&mut self, channel: u8, payload: &EventPayload) {
+        let (Some(instance), Some(note_call)) = (self.live(), self.kind.calls.note) else { return };
+        let (note, velocity) = match *payload {
+            EventPayload::NoteOn { note, velocity, .. } => (note, velocity.clamp(1, 64)),
+            EventPayload::NoteOff { note } => (note, 0),
+            _ => return,
+        };
+        // SAFETY: live instance from `create`
+        unsafe { note_call(instance, channel, note, velocity) };
+    }
+}
+
+/// A shared library's machines, validated and ready to register.
+pub struct PluginLibrary {
+    path: PathBuf,
+    machines: Vec<Arc<PluginMachineType>>,
+}
+
+impl PluginLibrary {
+    /// Open the library at `path` and validate every machine it provides.
+    ///
+    /// Opening a library runs its initialisers, so only load plugins you
+    /// trust to that degree.
+    pub fn open(path: &Path) -> Result<Self, PluginError> {
+        // SAFETY: loading runs foreign initialisers; the caller chose the library
+        let library = unsafe { Library::new(path) }.map_err(|e| PluginError::Load(e.to_string()))?;
+        let library = Arc::new(library);
+        // SAFETY: the ABI fixes the entry point's signature
+        let entry: EntryFn = *unsafe { library.get::<EntryFn>(ENTRY_SYMBOL.as_bytes()) }
+            .map_err(|e| PluginError::Load(e.to_string()))?;
+        let mut machines = Vec::new();
+        for index in 0..MAX_MACHINES_PER_LIBRARY {
+            // SAFETY: entry point per the ABI; null ends the list
+            let desc = unsafe { entry(index) };
+            if desc.is_null() {
+                break;
+            }
+            // SAFETY: non-null descriptors are valid for the library's lifetime,
+            // which the machine type extends by holding `library`
+            let kind = unsafe { PluginMachineType::from_descriptor(&*desc, index, Some(Arc::clone(&library))) }?;
+            machines.push(Arc::new(kind));
+        }
+        Ok(Self { path: path.to_path_buf(), machines })
+    }
+
+    /// Where the library was loaded from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The machines the library provides.
+    pub fn machines(&self) -> &[Arc<PluginMachineType>] {
+        &self.machines
+    }
+
+    /// Register every machine with `create_machine`.
+    pub fn register(&self) {
+        for kind in &self.machines {
+            kind.register();
+        }
+    }
+}
+
+/// Load the plugin at `path` and register its machines, returning their names.
+pub fn load_plugin(path: &Path) -> Result<Vec<String>, PluginError> {
+    let library = PluginLibrary::open(path)?;
+    library.register();
+    Ok(library.machines().iter().map(|m| m.name().to_string()).collect())
+}
+
+/// A library `load_plugin_dir` tried: its path, and its registered machine
+/// names or the reason it was skipped.
+pub type PluginLoad = (PathBuf, Result<Vec<String>, PluginError>);
+
+/// Load every shared library in `dir` (`.so`, `.dylib` or `.dll`).
+pub fn load_plugin_dir(dir: &Path) -> std::io::Result<Vec<PluginLoad>> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.extension().and_then(|e| e.to_str()).is_some_and(|e| matches!(e, "so" | "dylib" | "dll")))
+        .collect();
+    paths.sort();
+    Ok(paths.into_iter().map(|p| {
+        let result = load_plugin(&p);
+        (p, result)
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    /// A one-parameter gain effect: output = input * gain / 100.
+    struct Gain {
+        gain: i32,
+    }
+
+    unsafe extern "C" fn create() -> *mut c_void {
+        Box::into_raw(Box::new(Gain { gain: 100 })).cast()
+    }
+
+    unsafe extern "C" fn destroy(instance: *mut c_void) {
+        drop(unsafe { Box::from_raw(instance.cast::<Gain>()) });
+    }
+
+    unsafe extern "C" fn init(_instance: *mut c_void, sample_rate: u32) -> i32 {
+        if sample_rate == 0 { -1 } else { 0 }
+    }
+
+    unsafe extern "C" fn set_param(instance: *mut c_void, _param: u16, value: i32) {
+        unsafe { (*instance.cast::<Gain>()).gain = value };
+    }
+
+    unsafe extern "C" fn render(instance: *mut c_void, buffers: *const *mut f32, channels: u16, frames: u32) -> i32 {
+        let gain = unsafe { (*instance.cast::<Gain>()).gain } as f32 / 100.0;
+        for ch in 0..channels as usize {
+            let buf = unsafe { core::slice::from_raw_parts_mut(*buffers.add(ch), frames as usize) };
+            buf.iter_mut().for_each(|s| *s *= gain);
+        }
+        0
+    }
+
+    const GAIN: ParamDescriptor = ParamDescriptor {
+        name: c"Gain".as_ptr(),
+        min: 0,
+        max: 1000,
+        default: 100,
+        no_value: -1,
+    };
+
+    fn descriptor(params: &[ParamDescriptor]) -> MachineDescriptor {
+        MachineDescriptor {
+            abi_version: MACHINE_ABI_VERSION,
+            name: c"Plugin Test Gain".as_ptr(),
+            short_name: core::ptr::null(),
+            author: c"tests".as_ptr(),
+            machine_type: 1,
+            params: params.as_ptr(),
+            param_count: params.len() as u32,
+            inputs: 2,
+            outputs: 2,
+            create: Some(create),
+            destroy: Some(destroy),
+            init: Some(init),
+            tick: None,
+            stop: None,
+            set_param: Some(set_param),
+            note: None,
+            render: Some(render),
+        }
+    }
+
+    fn gain_type() -> Arc<PluginMachineType> {
+        let params = [GAIN];
+        // SAFETY: the descriptor's strings are literals and its calls are above
+        Arc::new(unsafe { PluginMachineType::from_descriptor(&descriptor(&params), 0, None) }.unwrap())
+    }
+
+    fn render_half(machine: &mut dyn Machine) -> AudioBuffer {
+        let mut buf = AudioBuffer::new(2, 4);
+        buf.channel_mut(0).fill(0.5);
+        buf.channel_mut(1).fill(-0.5);
+        machine.render(&mut buf);
+        buf
+    }
+
+    #[test]
+    fn descriptor_becomes_a_registered_machine() {
+        let kind = gain_type();
+        assert_eq!(kind.name(), "Plugin Test Gain");
+        assert_eq!(kind.info.short_name, "Plugin Test Gain");
+        assert!(!kind.plays_notes());
+        kind.register();
+
+        let mut machine = crate::machines::create_machine("Plugin Test Gain").unwrap();
+        machine.init(44100);
+        assert_eq!(machine.info().params[0].default, 100);
+        machine.set_param(0, 5000);
+        assert_eq!(render_half(machine.as_mut()).channel(0), [5.0; 4]);
+        assert!(registry::unregister_machine("Plugin Test Gain"));
+    }
+
+    #[test]
+    fn reloading_a_machine_reuses_its_info() {
+        assert!(core::ptr::eq(gain_type().info, gain_type().info));
+        let params = [ParamDescriptor { max: 200, ..GAIN }];
+        // SAFETY: as in `gain_type`
+        let wider = unsafe { PluginMachineType::from_descriptor(&descriptor(&params), 0, None) }.unwrap();
+        assert!(!core::ptr::eq(wider.info, gain_type().info));
+        assert_eq!(wider.info.params[0].max, 200);
+    }
+
+    #[test]
+    fn faulted_instances_stop_being_called() {
+        let kind = gain_type();
+        let mut failed_init = kind.instantiate();
+        failed_init.init(0);
+        assert_eq!(failed_init.fault(), Some(Fault::Init(-1)));
+        // A faulted effect passes its input through untouched
+        failed_init.set_param(0, 0);
+        assert_eq!(render_half(&mut failed_init).channel(0), [0.5; 4]);
+
+        let mut runaway = kind.instantiate();
+        runaway.init(44100);
+        runaway.set_param(0, 1000);
+        let mut buf = AudioBuffer::new(2, 4);
+        buf.channel_mut(0).fill(100.0);
+        runaway.render(&mut buf);
+        assert_eq!(runaway.fault(), Some(Fault::BadOutput));
+        assert_eq!(buf.channel(0), [0.0; 4]);
+    }
+
+    #[test]
+    fn malformed_descriptors_are_rejected() {
+        let mut old = descriptor(&[GAIN]);
+        old.abi_version = 0;
+        // SAFETY: as in `gain_type`
+        let err = unsafe { PluginMachineType::from_descriptor(&old, 3, None) }.err();
+        assert_eq!(err, Some(PluginError::AbiVersion { machine: 3, version: 0 }));
+
+        let mut no_render = descriptor(&[]);
+        no_render.render = None;
+        // SAFETY: as in `gain_type`
+        let err = unsafe { PluginMachineType::from_descriptor(&no_render, 0, None) }.err();
+        assert!(matches!(err, Some(PluginError::Invalid { .. })));
+
+        let missing = PluginLibrary::open(Path::new("/nonexistent/libmachine.so")).err();
+        assert!(matches!(missing, Some(PluginError::Load(_))));
+    }
+}
@@ -0,0 +1,88 @@
+//! Machines supplied at runtime, by name.
+//!
+//! `create_machine` looks here for names that aren't built in, so an
+//! unknown BMX machine can be provided by a plugin (see `plugin`) or by
+//! the host application instead of falling back to a passthrough.
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use std::sync::RwLock;
+
+use crate::machine::Machine;
+
+/// Builds a fresh instance of a registered machine.
+pub type MachineFactory = Arc<dyn Fn() -> Box<dyn Machine> + Send + Sync>;
+
+struct Entry {
+    name: String,
+    plays_notes: bool,
+    factory: MachineFactory,
+}
+
+static REGISTRY: RwLock<Vec<Entry>> = RwLock::new(Vec::new());
+
+/// Make `name` available to `create_machine`, replacing any earlier
+/// registration of it. `plays_notes` lets tracks target the machine.
+/// Built-in machines keep priority over registered ones of the same name.
+pub fn register_machine(name: &str, plays_notes: bool, factory: MachineFactory) {
+    let mut registry = REGISTRY.write().unwrap_or_else(|e| e.into_inner());
+    registry.retain(|e| e.name != name);
+    registry.push(Entry { name: name.to_string(), plays_notes, factory });
+}
+
+/// Forget a registered machine. Returns whether it was registered.
+/// Nodes already using it keep their instances.
+pub fn unregister_machine(name: &str) -> bool {
+    let mut registry = REGISTRY.write().unwrap_or_else(|e| e.into_inner());
+    let before = registry.len();
+    registry.retain(|e| e.name != name);
+    registry.len() != before
+}
+
+/// Names of the registered machines, in registration order.
+pub fn registered_machines() -> Vec<String> {
+    let registry = REGISTRY.read().unwrap_or_else(|e| e.into_inner());
+    registry.iter().map(|e| e.name.clone()).collect()
+}
+
+/// A new instance of the machine registered as `name`.
+pub(crate) fn create(name: &str) -> Option<Box<dyn Machine>> {
+    let factory = {
+        let registry = REGISTRY.read().unwrap_or_else(|e| e.into_inner());
+        registry.iter().find(|e| e.name == name)?.factory.clone()
+    };
+    Some(factory())
+}
+
+/// Whether the machine registered as `name` is played by pattern notes.
+pub(crate) fn plays_notes(name: &str) -> bool {
+    let registry = REGISTRY.read().unwrap_or_else(|e| e.into_inner());
+    registry.iter().any(|e| e.name == name && e.plays_notes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machines::{create_machine, plays_notes as machine_plays_notes};
+
+    #[test]
+    fn registered_machines_replace_the_passthrough_fallback() {
+        let name = "Registry Test Kick";
+        assert_eq!(create_machine(name).unwrap().info().name, "Passthrough");
+        register_machine(name, true, Arc::new(|| create_machine("Kick").unwrap()));
+        assert_eq!(create_machine(name).unwrap().info().name, "Kick");
+        assert!(machine_plays_notes(name));
+        assert!(registered_machines().iter().any(|n| n == name));
+
+        // Built-ins win over a registration of the same name
+        register_machine("Delay", false, Arc::new(|| create_machine("Kick").unwrap()));
+        assert_eq!(create_machine("Delay").unwrap().info().name, "Delay");
+        assert!(unregister_machine("Delay"));
+
+        assert!(unregister_machine(name));
+        assert!(!unregister_machine(name));
+        assert!(!machine_plays_notes(name));
+    }
+}
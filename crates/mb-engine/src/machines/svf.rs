@@ -0,0 +1,309 @@
+//! Resonant state-variable filter (low-, high- or band-pass).
+//!
+//! Trapezoidal (zero-delay feedback) SVF, which stays stable up to
+//! Nyquist and under fast cutoff changes. Parameters follow Jeskola
+//! Filter 2's byte layout, so BMX songs using it map straight on.
+
+use core::f32::consts::PI;
+
+use mb_ir::{AudioBuffer, AudioStream, ChannelConfig, ParamCurve, ParamUnit};
+use crate::machine::{Machine, MachineInfo, MachineType, ParamInfo};
+
+const PARAM_TYPE: u16 = 0;
+const PARAM_CUTOFF: u16 = 1;
+const PARAM_RESONANCE: u16 = 2;
+
+/// Top of the cutoff and resonance byte ranges.
+pub(super) const PARAM_MAX: i32 = 240;
+
+/// Cutoff range covered by `0..=PARAM_MAX`.
+pub(super) const CUTOFF_LO: f32 = 20.0;
+pub(super) const CUTOFF_HI: f32 = 20000.0;
+
+const TYPE_LABELS: &[&str] = &["low-pass", "high-pass", "band-pass"];
+
+static PARAMS: &[ParamInfo] = &[
+    ParamInfo {
+        id: PARAM_TYPE,
+        name: "Filter Type",
+        min: 0,
+        max: 2,
+        default: 0,
+        no_value: 0xFF,
+        unit: ParamUnit::Enum(TYPE_LABELS),
+        curve: ParamCurve::Raw,
+    },
+    ParamInfo {
+        id: PARAM_CUTOFF,
+        name: "Cutoff",
+        min: 0,
+        max: PARAM_MAX,
+        default: 160,
+        no_value: 0xFF,
+        unit: ParamUnit::Hz,
+        curve: ParamCurve::Exponential { lo: CUTOFF_LO, hi: CUTOFF_HI },
+    },
+    ParamInfo {
+        id: PARAM_RESONANCE,
+        name: "Resonance",
+        min: 0,
+        max: PARAM_MAX,
+        default: 0,
+        no_value: 0xFF,
+        unit: ParamUnit::Percent,
+        curve: ParamCurve::Linear { lo: 0.0, hi: 100.0 },
+    },
+];
+
+static INFO: MachineInfo = MachineInfo {
+    name: "Resonant Filter",
+    short_name: "Filter",
+    author: "masterblaster",
+    machine_type: MachineType::Effect,
+    params: PARAMS,
+};
+
+/// Which filter output to use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Low,
+    High,
+    Band,
+}
+
+/// Integrator state for one channel.
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct State {
+    ic1: f32,
+    ic2: f32,
+}
+
+impl State {
+    pub(super) const ZERO: Self = Self { ic1: 0.0, ic2: 0.0 };
+}
+
+/// Filter outputs for one sample.
+pub(super) struct Outputs {
+    pub low: f32,
+    pub band: f32,
+    pub high: f32,
+}
+
+/// Coefficients shared by every channel of one filter setting.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Svf {
+    /// Damping (2 = none, toward 0 = self-oscillation)
+    k: f32,
+    a1: f32,
+    a2: f32,
+    a3: f32,
+}
+
+impl Svf {
+    /// Coefficients for a cutoff in Hz and a resonance in `0.0..=1.0`.
+    ///
+    /// The cutoff is kept below Nyquist.
+    pub(super) fn new(cutoff_hz: f32, resonance: f32, sample_rate: u32) -> Self {
+        let hz = cutoff_hz.min(sample_rate as f32 * 0.49);
+        let g = libm::tanf(PI * hz / sample_rate as f32);
+        // Full resonance stops just short of self-oscillation
+        let k = 2.0 - 1.96 * resonance;
+        let a1 = 1.0 / (1.0 + g * (g + k));
+        let a2 = g * a1;
+        Self { k, a1, a2, a3: g * a2 }
+    }
+
+    /// Filter one sample.
+    pub(super) fn step(&self, state: &mut State, v0: f32) -> Outputs {
+        let v3 = v0 - state.ic2;
+        let v1 = self.a1 * state.ic1 + self.a2 * v3;
+        let v2 = state.ic2 + self.a2 * state.ic1 + self.a3 * v3;
+        state.ic1 = 2.0 * v1 - state.ic1;
+        state.ic2 = 2.0 * v2 - state.ic2;
+        Outputs { low: v2, band: v1, high: v0 - self.k * v1 - v2 }
+    }
+}
+
+/// Map a 0..=`PARAM_MAX` cutoff parameter to Hz.
+pub(super) fn cutoff_param_hz(value: i32) -> f32 {
+    let t = value as f32 / PARAM_MAX as f32;
+    CUTOFF_LO * libm::powf(CUTOFF_HI / CUTOFF_LO, t)
+}
+
+/// Stereo resonant state-variable filter.
+pub struct ResonantFilter {
+    mode: Mode,
+    cutoff: i32,
+    resonance: i32,
+    sample_rate: u32,
+    svf: Svf,
+    state: [State; 2],
+}
+
+impl ResonantFilter {
+    pub fn new() -> Self {
+        let mut filter = Self {
+            mode: Mode::Low,
+            cutoff: PARAMS[1].default,
+            resonance: PARAMS[2].default,
+            sample_rate: 44100,
+            svf: Svf::new(1000.0, 0.0, 44100),
+            state: [State::default(); 2],
+        };
+        filter.recompute();
+        filter
+    }
+
+    /// Cutoff in Hz for the current parameter value, kept below Nyquist.
+    fn cutoff_hz(&self) -> f32 {
+        cutoff_param_hz(self.cutoff).min(self.sample_rate as f32 * 0.49)
+    }
+
+    fn recompute(&mut self) {
+        let resonance = self.resonance as f32 / PARAM_MAX as f32;
+        self.svf = Svf::new(self.cutoff_hz(), resonance, self.sample_rate);
+    }
+
+    fn process(&self, state: &mut State, samples: &mut [f32]) {
+        for s in samples {
+            let out = self.svf.step(state, *s);
+            *s = match self.mode {
+                Mode::Low => out.low,
+                Mode::Band => out.band,
+                Mode::High => out.high,
+            };
+        }
+    }
+}
+
+impl AudioStream for ResonantFilter {
+    fn channel_config(&self) -> ChannelConfig {
+        ChannelConfig { inputs: 2, outputs: 2 }
+    }
+
+    fn render(&mut self, output: &mut AudioBuffer) {
+        let frames = output.frames() as usize;
+        let channels = (output.channels() as usize).min(2);
+        for ch in 0..channels {
+            let mut state = self.state[ch];
+            self.process(&mut state, &mut output.channel_mut(ch as u16)[..frames]);
+            self.state[ch] = state;
+        }
+    }
+}
+
+impl Machine for ResonantFilter {
+    fn info(&self) -> &MachineInfo {
+        &INFO
+    }
+
+    fn init(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.recompute();
+    }
+
+    fn tick(&mut self) {}
+
+    fn stop(&mut self) {
+        self.state = [State::default(); 2];
+    }
+
+    fn set_param(&mut self, param: u16, value: i32) {
+        match param {
+            PARAM_TYPE => {
+                self.mode = match value {
+                    1 => Mode::High,
+                    2 => Mode::Band,
+                    _ => Mode::Low,
+                };
+            }
+            PARAM_CUTOFF => self.cutoff = value.clamp(0, PARAM_MAX),
+            PARAM_RESONANCE => self.resonance = value.clamp(0, PARAM_MAX),
+            _ => return,
+        }
+        self.recompute();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: u32 = 44100;
+
+    fn filter(mode: i32, cutoff: i32, resonance: i32) -> ResonantFilter {
+        let mut f = ResonantFilter::new();
+        f.init(SR);
+        f.set_param(PARAM_TYPE, mode);
+        f.set_param(PARAM_CUTOFF, cutoff);
+        f.set_param(PARAM_RESONANCE, resonance);
+        f
+    }
+
+    /// Peak output level after settling, for a sine at `hz`.
+    fn response(f: &mut ResonantFilter, hz: f32) -> f32 {
+        let n = 4096;
+        let mut buf = AudioBuffer::new(2, n as u16);
+        for i in 0..n {
+            let v = libm::sinf(2.0 * PI * hz * i as f32 / SR as f32);
+            buf.channel_mut(0)[i] = v;
+            buf.channel_mut(1)[i] = v;
+        }
+        f.render(&mut buf);
+        buf.channel(0)[n / 2..].iter().fold(0.0f32, |m, s| m.max(s.abs()))
+    }
+
+    /// Cutoff parameter value for roughly 1 kHz.
+    const CUTOFF_1K: i32 = 136;
+
+    #[test]
+    fn cutoff_maps_exponentially() {
+        let f = filter(0, CUTOFF_1K, 0);
+        assert!((f.cutoff_hz() - 1000.0).abs() < 60.0, "{}", f.cutoff_hz());
+        assert_eq!(filter(0, 0, 0).cutoff_hz(), CUTOFF_LO);
+    }
+
+    #[test]
+    fn low_pass_passes_lows_and_cuts_highs() {
+        assert!(response(&mut filter(0, CUTOFF_1K, 0), 100.0) > 0.95);
+        assert!(response(&mut filter(0, CUTOFF_1K, 0), 10000.0) < 0.05);
+    }
+
+    #[test]
+    fn high_pass_passes_highs_and_cuts_lows() {
+        assert!(response(&mut filter(1, CUTOFF_1K, 0), 10000.0) > 0.95);
+        assert!(response(&mut filter(1, CUTOFF_1K, 0), 100.0) < 0.05);
+    }
+
+    #[test]
+    fn band_pass_peaks_at_cutoff() {
+        let at = response(&mut filter(2, CUTOFF_1K, 120), 1000.0);
+        assert!(at > response(&mut filter(2, CUTOFF_1K, 120), 100.0));
+        assert!(at > response(&mut filter(2, CUTOFF_1K, 120), 10000.0));
+    }
+
+    #[test]
+    fn resonance_boosts_cutoff() {
+        let flat = response(&mut filter(0, CUTOFF_1K, 0), 1000.0);
+        let resonant = response(&mut filter(0, CUTOFF_1K, PARAM_MAX), 1000.0);
+        assert!(resonant > 4.0 * flat, "{} vs {}", resonant, flat);
+    }
+
+    #[test]
+    fn stop_resets_state() {
+        let mut f = filter(0, CUTOFF_1K, 0);
+        response(&mut f, 100.0);
+        f.stop();
+        assert_eq!(f.state[0].ic1, 0.0);
+        assert_eq!(f.state[1].ic2, 0.0);
+    }
+
+    #[test]
+    fn jeskola_filter_2_gets_real_filtering() {
+        let m = super::super::create_machine("Jeskola Filter 2").unwrap();
+        assert_eq!(m.info().name, "Resonant Filter");
+        let params = super::super::default_parameters("Resonant Filter");
+        assert_eq!(params.len(), 3);
+        assert_eq!(params[0].display_value(), "low-pass");
+    }
+}
@@ -0,0 +1,587 @@
+//! Subtractive synth generator driven by pattern notes.
+//!
+//! Each pattern column of the synth's track plays one voice: a mix of
+//! band-limited saw and square plus white noise, shaped by an ADSR
+//! envelope and a resonant low-pass filter. Notes arrive through
+//! `apply_event` exactly like on tracker channels, so the synth can be any
+//! track's target node.
+//!
+//! An optional tremolo synced to the song tempo scales the mix. Its LFO is
+//! a `ModEnvelope` evaluated at a control rate and ramped to audio rate.
+
+use mb_ir::{
+    AudioBuffer, AudioStream, ChannelConfig, CurveKind, Effect, EventPayload, ModBreakPoint, ModEnvelope, ParamCurve,
+    ParamUnit, SUB_BEAT_UNIT,
+};
+use crate::envelope_state::{ControlRate, EnvelopeState};
+use crate::machine::{Machine, MachineInfo, MachineType, ParamInfo};
+
+use super::svf::{self, Svf};
+
+const PARAM_SAW: u16 = 0;
+const PARAM_SQUARE: u16 = 1;
+const PARAM_NOISE: u16 = 2;
+const PARAM_ATTACK: u16 = 3;
+const PARAM_DECAY: u16 = 4;
+const PARAM_SUSTAIN: u16 = 5;
+const PARAM_RELEASE: u16 = 6;
+const PARAM_CUTOFF: u16 = 7;
+const PARAM_RESONANCE: u16 = 8;
+const PARAM_VOLUME: u16 = 9;
+const PARAM_TREMOLO_LENGTH: u16 = 10;
+const PARAM_TREMOLO_DEPTH: u16 = 11;
+
+/// Samples between tremolo LFO updates; the gain ramps linearly in between.
+const CONTROL_INTERVAL: u16 = 16;
+
+/// Frames of tremolo gain computed at a time.
+const GAIN_CHUNK: usize = 64;
+
+/// Full scale of the level parameters.
+const LEVEL_MAX: i32 = 128;
+
+/// Voices (pattern columns) the synth plays.
+pub const MAX_VOICES: usize = 16;
+
+/// Note number of A-4 (440 Hz); note 48 is C-4 as on tracker channels.
+const A4_NOTE: f32 = 57.0;
+
+const PERCENT: ParamCurve = ParamCurve::Linear { lo: 0.0, hi: 100.0 };
+
+const fn level(id: u16, name: &'static str, default: i32) -> ParamInfo {
+    ParamInfo {
+        id,
+        name,
+        min: 0,
+        max: LEVEL_MAX,
+        default,
+        no_value: 0xFF,
+        unit: ParamUnit::Percent,
+        curve: PERCENT,
+    }
+}
+
+const fn time(id: u16, name: &'static str, max: i32, default: i32) -> ParamInfo {
+    ParamInfo {
+        id,
+        name,
+        min: 0,
+        max,
+        default,
+        no_value: 0xFFFF,
+        unit: ParamUnit::Milliseconds,
+        curve: ParamCurve::Raw,
+    }
+}
+
+static PARAMS: &[ParamInfo] = &[
+    level(PARAM_SAW, "Saw", LEVEL_MAX),
+    level(PARAM_SQUARE, "Square", 0),
+    level(PARAM_NOISE, "Noise", 0),
+    time(PARAM_ATTACK, "Attack", 2000, 5),
+    time(PARAM_DECAY, "Decay", 2000, 200),
+    level(PARAM_SUSTAIN, "Sustain", 96),
+    time(PARAM_RELEASE, "Release", 5000, 200),
+    ParamInfo {
+        id: PARAM_CUTOFF,
+        name: "Cutoff",
+        min: 0,
+        max: svf::PARAM_MAX,
+        default: 180,
+        no_value: 0xFF,
+        unit: ParamUnit::Hz,
+        curve: ParamCurve::Exponential { lo: svf::CUTOFF_LO, hi: svf::CUTOFF_HI },
+    },
+    ParamInfo {
+        id: PARAM_RESONANCE,
+        name: "Resonance",
+        min: 0,
+        max: svf::PARAM_MAX,
+        default: 0,
+        no_value: 0xFF,
+        unit: ParamUnit::Percent,
+        curve: PERCENT,
+    },
+    level(PARAM_VOLUME, "Volume", 96),
+    ParamInfo {
+        id: PARAM_TREMOLO_LENGTH,
+        name: "Tremolo Length",
+        min: 1,
+        max: 64,
+        default: 16,
+        no_value: 0xFF,
+        unit: ParamUnit::None,
+        curve: ParamCurve::Raw,
+    },
+    level(PARAM_TREMOLO_DEPTH, "Tremolo Depth", 0),
+];
+
+static INFO: MachineInfo = MachineInfo {
+    name: "Synth",
+    short_name: "Synth",
+    author: "masterblaster",
+    machine_type: MachineType::Generator,
+    params: PARAMS,
+};
+
+/// Envelope stage of a voice.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// One monophonic voice.
+#[derive(Clone, Copy, Debug)]
+struct Voice {
+    stage: Stage,
+    /// Envelope level, 0.0–1.0
+    env: f32,
+    /// Oscillator phase, 0.0–1.0
+    phase: f32,
+    /// Phase increment per frame
+    step: f32,
+    /// Note volume, 0.0–1.0 (velocity, then volume column)
+    volume: f32,
+    /// Ticks until a pending note cut
+    cut_in: Option<u8>,
+    filter: svf::State,
+}
+
+impl Voice {
+    const IDLE: Self = Self {
+        stage: Stage::Idle,
+        env: 0.0,
+        phase: 0.0,
+        step: 0.0,
+        volume: 0.0,
+        cut_in: None,
+        filter: svf::State::ZERO,
+    };
+}
+
+/// Correction for the discontinuity of a naive oscillator at phase `t`.
+fn poly_blep(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let t = t / dt;
+        t + t - t * t - 1.0
+    } else if t > 1.0 - dt {
+        let t = (t - 1.0) / dt;
+        t * t + t + t + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// Per-frame envelope step for a segment lasting `ms` (instant when 0).
+fn segment_step(ms: i32, sample_rate: u32) -> f32 {
+    let frames = ms as f32 * sample_rate as f32 / 1000.0;
+    if frames < 1.0 { 1.0 } else { 1.0 / frames }
+}
+
+/// Tremolo LFO cycling over `sixteenths` of a beat: a triangle from 0 up
+/// to 1 and back.
+fn tremolo_envelope(sixteenths: i32) -> ModEnvelope {
+    let half = sixteenths as u32 * SUB_BEAT_UNIT / 32;
+    ModEnvelope::looping(
+        &[
+            ModBreakPoint::new(0, 0.0, CurveKind::Linear),
+            ModBreakPoint::new(half, 1.0, CurveKind::Linear),
+            ModBreakPoint::new(half, 0.0, CurveKind::Step),
+        ],
+        0,
+        2,
+    )
+}
+
+/// Pattern-driven subtractive synthesizer.
+pub struct Synth {
+    voices: [Voice; MAX_VOICES],
+    sample_rate: u32,
+    values: [i32; PARAMS.len()],
+    saw: f32,
+    square: f32,
+    noise: f32,
+    sustain: f32,
+    volume: f32,
+    attack_step: f32,
+    decay_step: f32,
+    release_step: f32,
+    svf: Svf,
+    /// Noise generator state (LCG)
+    seed: u32,
+    /// Song tempo (hundredths of a BPM)
+    tempo: u32,
+    tremolo: ModEnvelope,
+    tremolo_state: EnvelopeState,
+    tremolo_depth: f32,
+    /// Tremolo LFO updates at the current tempo
+    control: ControlRate,
+}
+
+impl Synth {
+    pub fn new() -> Self {
+        let mut values = [0; PARAMS.len()];
+        for (v, p) in values.iter_mut().zip(PARAMS) {
+            *v = p.default;
+        }
+        let tremolo = tremolo_envelope(values[PARAM_TREMOLO_LENGTH as usize]);
+        let mut synth = Self {
+            voices: [Voice::IDLE; MAX_VOICES],
+            sample_rate: 44100,
+            values,
+            saw: 0.0,
+            square: 0.0,
+            noise: 0.0,
+            sustain: 0.0,
+            volume: 0.0,
+            attack_step: 0.0,
+            decay_step: 0.0,
+            release_step: 0.0,
+            svf: Svf::new(1000.0, 0.0, 44100),
+            seed: 1,
+            tempo: 12500,
+            tremolo_state: EnvelopeState::new(&tremolo),
+            tremolo,
+            tremolo_depth: 0.0,
+            control: ControlRate::new(CONTROL_INTERVAL, 12500, 44100),
+        };
+        synth.recompute();
+        synth
+    }
+
+    fn recompute(&mut self) {
+        let v = &self.values;
+        let level = |id: u16| v[id as usize] as f32 / LEVEL_MAX as f32;
+        self.saw = level(PARAM_SAW);
+        self.square = level(PARAM_SQUARE);
+        self.noise = level(PARAM_NOISE);
+        self.sustain = level(PARAM_SUSTAIN);
+        self.volume = level(PARAM_VOLUME);
+        self.attack_step = segment_step(v[PARAM_ATTACK as usize], self.sample_rate);
+        self.decay_step = segment_step(v[PARAM_DECAY as usize], self.sample_rate);
+        self.release_step = segment_step(v[PARAM_RELEASE as usize], self.sample_rate);
+        let resonance = v[PARAM_RESONANCE as usize] as f32 / svf::PARAM_MAX as f32;
+        let cutoff = svf::cutoff_param_hz(v[PARAM_CUTOFF as usize]);
+        self.svf = Svf::new(cutoff, resonance, self.sample_rate);
+        self.tremolo = tremolo_envelope(v[PARAM_TREMOLO_LENGTH as usize]);
+        self.tremolo_depth = level(PARAM_TREMOLO_DEPTH);
+        self.control = ControlRate::new(CONTROL_INTERVAL, self.tempo, self.sample_rate);
+    }
+
+    fn note_on(&mut self, channel: usize, note: u8, velocity: u8) {
+        let hz = 440.0 * libm::powf(2.0, (note as f32 - A4_NOTE) / 12.0);
+        let step = hz / self.sample_rate as f32;
+        let voice = &mut self.voices[channel];
+        // Retrigger from the current level so legato notes don't click
+        *voice = Voice {
+            stage: Stage::Attack,
+            step,
+            volume: velocity.min(64) as f32 / 64.0,
+            ..*voice
+        };
+        voice.cut_in = None;
+        if voice.env == 0.0 {
+            voice.phase = 0.0;
+            voice.filter = svf::State::ZERO;
+        }
+    }
+
+    fn next_noise(&mut self) -> f32 {
+        self.seed = self.seed.wrapping_mul(1664525).wrapping_add(1013904223);
+        (self.seed >> 8) as f32 / (1u32 << 23) as f32 - 1.0
+    }
+
+    /// Advance a voice's envelope by one frame.
+    fn advance_envelope(&self, voice: &mut Voice) {
+        match voice.stage {
+            Stage::Attack => {
+                voice.env += self.attack_step;
+                if voice.env >= 1.0 {
+                    voice.env = 1.0;
+                    voice.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                voice.env -= self.decay_step;
+                if voice.env <= self.sustain {
+                    voice.env = self.sustain;
+                    voice.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => voice.env = self.sustain,
+            Stage::Release => {
+                voice.env -= self.release_step;
+                if voice.env <= 0.0 {
+                    *voice = Voice::IDLE;
+                }
+            }
+            Stage::Idle => {}
+        }
+        if voice.stage == Stage::Sustain && voice.env == 0.0 {
+            *voice = Voice::IDLE;
+        }
+    }
+
+    fn render_voice(&mut self, index: usize, left: &mut [f32], right: &mut [f32]) {
+        let mut voice = self.voices[index];
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            if voice.stage == Stage::Idle {
+                break;
+            }
+            let (t, dt) = (voice.phase, voice.step);
+            let saw = 2.0 * t - 1.0 - poly_blep(t, dt);
+            let half = (t + 0.5) % 1.0;
+            let square = if t < 0.5 { 1.0 } else { -1.0 } + poly_blep(t, dt) - poly_blep(half, dt);
+            let noise = self.next_noise();
+            let osc = self.saw * saw + self.square * square + self.noise * noise;
+            let out = self.svf.step(&mut voice.filter, osc).low * voice.env * voice.volume * self.volume;
+            *l += out;
+            *r += out;
+            voice.phase = (t + dt) % 1.0;
+            self.advance_envelope(&mut voice);
+        }
+        self.voices[index] = voice;
+    }
+
+    /// Scale the mix by the tremolo gain, advancing its LFO.
+    fn apply_tremolo(&mut self, left: &mut [f32], right: &mut [f32]) {
+        if self.tremolo_depth == 0.0 {
+            return;
+        }
+        let mut lfo = [0.0; GAIN_CHUNK];
+        for (left, right) in left.chunks_mut(GAIN_CHUNK).zip(right.chunks_mut(GAIN_CHUNK)) {
+            let lfo = &mut lfo[..left.len()];
+            self.tremolo_state.render(&self.tremolo, self.control, lfo);
+            for ((l, r), x) in left.iter_mut().zip(right.iter_mut()).zip(lfo.iter()) {
+                let gain = 1.0 - self.tremolo_depth * x;
+                *l *= gain;
+                *r *= gain;
+            }
+        }
+    }
+}
+
+impl AudioStream for Synth {
+    fn channel_config(&self) -> ChannelConfig {
+        ChannelConfig { inputs: 0, outputs: 2 }
+    }
+
+    fn render(&mut self, output: &mut AudioBuffer) {
+        let frames = output.frames() as usize;
+        if output.channels() < 2 {
+            return;
+        }
+        let (left, right) = output.channels_mut_2(0, 1);
+        left[..frames].fill(0.0);
+        right[..frames].fill(0.0);
+        for i in 0..MAX_VOICES {
+            self.render_voice(i, &mut left[..frames], &mut right[..frames]);
+        }
+        self.apply_tremolo(&mut left[..frames], &mut right[..frames]);
+    }
+}
+
+impl Machine for Synth {
+    fn info(&self) -> &MachineInfo {
+        &INFO
+    }
+
+    fn init(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.recompute();
+    }
+
+    fn tick(&mut self) {
+        for voice in &mut self.voices {
+            match voice.cut_in {
+                Some(0) | None => {}
+                Some(1) => *voice = Voice::IDLE,
+                Some(n) => voice.cut_in = Some(n - 1),
+            }
+        }
+    }
+
+    fn stop(&mut self) {
+        self.voices = [Voice::IDLE; MAX_VOICES];
+    }
+
+    fn set_tempo(&mut self, tempo: u32) {
+        if tempo != self.tempo {
+            self.tempo = tempo;
+            self.control = ControlRate::new(CONTROL_INTERVAL, tempo, self.sample_rate);
+        }
+    }
+
+    fn set_param(&mut self, param: u16, value: i32) {
+        let Some(info) = PARAMS.get(param as usize) else { return };
+        self.values[param as usize] = value.clamp(info.min, info.max);
+        self.recompute();
+    }
+
+    fn apply_event(&mut self, channel: u8, payload: &EventPayload) {
+        let ch = channel as usize;
+        if ch >= MAX_VOICES {
+            return;
+        }
+        match payload {
+            EventPayload::NoteOn { note, velocity, .. } => self.note_on(ch, *note, *velocity),
+            EventPayload::NoteOff { .. } | EventPayload::NoteFade => {
+                let voice = &mut self.voices[ch];
+                if voice.stage != Stage::Idle {
+                    voice.stage = Stage::Release;
+                }
+            }
+            EventPayload::Effect(Effect::SetVolume(v)) => {
+                self.voices[ch].volume = (*v).min(64) as f32 / 64.0;
+            }
+            EventPayload::Effect(Effect::NoteCut(0)) => self.voices[ch] = Voice::IDLE,
+            EventPayload::Effect(Effect::NoteCut(ticks)) => self.voices[ch].cut_in = Some(*ticks),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: u32 = 44100;
+
+    fn synth() -> Synth {
+        let mut s = Synth::new();
+        s.init(SR);
+        s.set_param(PARAM_CUTOFF, svf::PARAM_MAX);
+        s
+    }
+
+    fn render(s: &mut Synth, frames: u16) -> AudioBuffer {
+        let mut buf = AudioBuffer::new(2, frames);
+        s.render(&mut buf);
+        buf
+    }
+
+    fn peak(buf: &AudioBuffer) -> f32 {
+        buf.channel(0).iter().fold(0.0f32, |m, s| m.max(s.abs()))
+    }
+
+    fn note(s: &mut Synth, ch: u8, note: u8) {
+        s.apply_event(ch, &EventPayload::NoteOn { note, velocity: 64, instrument: 1 });
+    }
+
+    /// Count rising zero crossings in a channel.
+    fn crossings(buf: &AudioBuffer) -> usize {
+        buf.channel(0).windows(2).filter(|w| w[0] < 0.0 && w[1] >= 0.0).count()
+    }
+
+    #[test]
+    fn silent_until_note() {
+        let mut s = synth();
+        assert_eq!(peak(&render(&mut s, 256)), 0.0);
+        note(&mut s, 0, 57);
+        assert!(peak(&render(&mut s, 1024)) > 0.1);
+    }
+
+    #[test]
+    fn a4_plays_440_hz() {
+        let mut s = synth();
+        note(&mut s, 0, 57);
+        let buf = render(&mut s, 4410);
+        // 0.1 s of 440 Hz
+        assert!((43..=45).contains(&crossings(&buf)), "{}", crossings(&buf));
+    }
+
+    #[test]
+    fn octave_doubles_pitch() {
+        let mut s = synth();
+        note(&mut s, 0, 69);
+        let buf = render(&mut s, 4410);
+        assert!((87..=89).contains(&crossings(&buf)), "{}", crossings(&buf));
+    }
+
+    #[test]
+    fn release_fades_to_silence() {
+        let mut s = synth();
+        s.set_param(PARAM_RELEASE, 10);
+        note(&mut s, 0, 57);
+        render(&mut s, 2048);
+        s.apply_event(0, &EventPayload::NoteOff { note: 57 });
+        render(&mut s, 441);
+        assert_eq!(s.voices[0].stage, Stage::Idle);
+        assert_eq!(peak(&render(&mut s, 256)), 0.0);
+    }
+
+    #[test]
+    fn envelope_settles_at_sustain() {
+        let mut s = synth();
+        s.set_param(PARAM_DECAY, 10);
+        note(&mut s, 0, 57);
+        render(&mut s, 4096);
+        assert_eq!(s.voices[0].stage, Stage::Sustain);
+        assert_eq!(s.voices[0].env, 96.0 / 128.0);
+    }
+
+    #[test]
+    fn columns_play_separate_voices() {
+        let mut s = synth();
+        note(&mut s, 0, 57);
+        note(&mut s, 1, 64);
+        render(&mut s, 64);
+        assert!(s.voices[..2].iter().all(|v| v.stage != Stage::Idle));
+        note(&mut s, MAX_VOICES as u8, 57);
+    }
+
+    #[test]
+    fn low_cutoff_darkens_sound() {
+        let mut bright = synth();
+        let mut dark = synth();
+        dark.set_param(PARAM_CUTOFF, 40);
+        note(&mut bright, 0, 81);
+        note(&mut dark, 0, 81);
+        assert!(peak(&render(&mut dark, 4096)) < 0.5 * peak(&render(&mut bright, 4096)));
+    }
+
+    #[test]
+    fn noise_only_is_not_periodic() {
+        let mut s = synth();
+        s.set_param(PARAM_SAW, 0);
+        s.set_param(PARAM_NOISE, LEVEL_MAX);
+        note(&mut s, 0, 0);
+        assert!(crossings(&render(&mut s, 4410)) > 500);
+    }
+
+    #[test]
+    fn note_cut_and_stop_silence_voices() {
+        let mut s = synth();
+        note(&mut s, 0, 57);
+        note(&mut s, 1, 57);
+        s.apply_event(0, &EventPayload::Effect(Effect::NoteCut(2)));
+        s.tick();
+        assert_ne!(s.voices[0].stage, Stage::Idle);
+        s.tick();
+        assert_eq!(s.voices[0].stage, Stage::Idle);
+        s.stop();
+        assert_eq!(peak(&render(&mut s, 256)), 0.0);
+    }
+
+    #[test]
+    fn tremolo_ramps_the_gain_at_the_tempo() {
+        let mut s = synth();
+        s.set_param(PARAM_TREMOLO_DEPTH, LEVEL_MAX);
+        s.set_tempo(12000);
+        // One beat at 120 BPM: down to silence over half of it and back
+        let mut left = vec![1.0; SR as usize / 2];
+        let mut right = left.clone();
+        s.apply_tremolo(&mut left, &mut right);
+        assert!(left[0] > 0.99);
+        assert!(left[SR as usize / 4] < 0.01);
+        assert!(left[SR as usize / 2 - 1] > 0.99);
+        // Interpolated between control updates, so no steps
+        let steepest = left.windows(2).map(|w| (w[1] - w[0]).abs()).fold(0.0, f32::max);
+        assert!(steepest < 1.5 * 4.0 / SR as f32, "{}", steepest);
+        assert_eq!(left, right);
+    }
+}
@@ -7,13 +7,14 @@
 use alloc::vec::Vec;
 
 use mb_ir::{
-    AudioBuffer, AudioStream, ChannelConfig, ChannelSettings, Effect,
-    EventPayload, Instrument, Sample, sub_beats_per_tick,
+    AudioBuffer, AudioStream, ChannelConfig, ChannelSettings, CompatibilityMode, Effect,
+    EventPayload, FrequencyMode, Instrument, Interpolation, Sample, SampleFields, sub_beats_per_tick,
 };
 
 use crate::channel::ChannelState;
-use crate::frequency::note_to_period;
+use crate::compat::Quirks;
 use crate::machine::{Machine, MachineInfo, MachineType};
+use crate::voice_pool::{VoicePool, DEFAULT_VOICES};
 
 static INFO: MachineInfo = MachineInfo {
     name: "Tracker",
@@ -24,8 +25,15 @@ static INFO: MachineInfo = MachineInfo {
 };
 
 /// A Machine that drives N tracker channels, rendering and mixing them.
+///
+/// One more channel follows the song's, played only by previews (see
+/// `scheduler::preview_target`).
 pub struct TrackerMachine {
     channels: Vec<ChannelState>,
+    /// Per-channel mute; muted channels advance but are mixed at zero gain
+    muted: Vec<bool>,
+    /// Notes released by New Note Actions, still sounding
+    voices: VoicePool,
     samples: Vec<Sample>,
     instruments: Vec<Instrument>,
     speed: u8,
@@ -45,17 +53,20 @@ impl TrackerMachine {
         sample_rate: u32,
         mix_gain: f32,
     ) -> Self {
-        let channels = channel_settings
+        let channels: Vec<_> = channel_settings
             .iter()
             .map(|s| {
                 let mut ch = ChannelState::new();
                 ch.panning = s.initial_pan;
                 ch
             })
+            .chain(core::iter::once(ChannelState::new()))
             .collect();
 
         Self {
+            muted: alloc::vec![false; channels.len()],
             channels,
+            voices: VoicePool::new(DEFAULT_VOICES),
             samples,
             instruments,
             speed,
@@ -65,12 +76,44 @@ impl TrackerMachine {
         }
     }
 
+    /// Use `mode`'s period scale for notes and pitch slides on every channel.
+    pub fn with_frequency_mode(mut self, mode: FrequencyMode) -> Self {
+        for channel in &mut self.channels {
+            channel.linear = mode == FrequencyMode::Linear;
+        }
+        self
+    }
+
+    /// Ramp volume changes, note starts and cuts over `frames` output
+    /// frames on every channel (0 = off), overriding the compatibility
+    /// mode's ramp.
+    pub fn with_volume_ramp(mut self, frames: u16) -> Self {
+        for channel in &mut self.channels {
+            channel.quirks.volume_ramp = frames;
+        }
+        self
+    }
+
+    /// Reproduce `mode`'s playback quirks on every channel.
+    pub fn with_compatibility(mut self, mode: CompatibilityMode) -> Self {
+        for channel in &mut self.channels {
+            channel.quirks = Quirks::for_mode(mode);
+        }
+        self
+    }
+
     /// Access a channel (for testing).
     #[cfg(test)]
     pub(crate) fn channel(&self, index: usize) -> Option<&ChannelState> {
         self.channels.get(index)
     }
 
+    /// Background voices (for testing).
+    #[cfg(test)]
+    pub(crate) fn voices(&self) -> &VoicePool {
+        &self.voices
+    }
+
     /// Sub-beat units per tick (for modulator timing).
     fn spt(&self) -> u32 {
         sub_beats_per_tick(self.speed, self.rows_per_beat)
@@ -107,6 +150,16 @@ impl TrackerMachine {
         }
     }
 
+    /// Hand a channel's sounding note to the voice pool per its instrument's
+    /// NNA, or let it ramp out there if the NNA cuts it.
+    fn release_to_background(&mut self, ch: u8) {
+        let Some(channel) = self.channels.get(ch as usize) else { return };
+        let instrument = self.instruments.get(channel.instrument as usize);
+        if !self.voices.release(ch, channel, instrument) {
+            self.voices.declick(ch, channel);
+        }
+    }
+
     /// Apply an event payload to a specific channel.
     fn apply_channel_event(&mut self, ch: u8, payload: &EventPayload) {
         match payload {
@@ -115,11 +168,13 @@ impl TrackerMachine {
                 let c4_speed = self.sample_c4_speed(sample_idx);
                 let default_vol = self.samples.get(sample_idx as usize).map(|s| s.default_volume);
                 let sample_rate = self.sample_rate;
+                self.release_to_background(ch);
 
                 if let Some(channel) = self.channels.get_mut(ch as usize) {
                     channel.trigger(*note, inst_idx, sample_idx);
+                    channel.update_envelopes(self.instruments.get(inst_idx as usize));
                     channel.c4_speed = c4_speed;
-                    channel.period = note_to_period(*note);
+                    channel.period = channel.note_period(*note);
                     channel.update_increment(sample_rate);
                     if let Some(vol) = default_vol {
                         channel.volume = vol;
@@ -130,10 +185,8 @@ impl TrackerMachine {
                 let (inst_idx, sample_idx) = self.resolve_sample(*instrument, *note);
                 let c4_speed = self.sample_c4_speed(sample_idx);
                 let default_vol = self.samples.get(sample_idx as usize).map(|s| s.default_volume);
-                let target_period = note_to_period(*note);
-
                 if let Some(channel) = self.channels.get_mut(ch as usize) {
-                    channel.target_period = target_period;
+                    channel.target_period = channel.note_period(*note);
                     if *instrument > 0 && inst_idx != channel.instrument {
                         channel.instrument = inst_idx;
                         channel.sample_index = sample_idx;
@@ -144,12 +197,27 @@ impl TrackerMachine {
                     }
                 }
             }
+            EventPayload::InstrumentChange { instrument } => {
+                let Some(channel) = self.channels.get(ch as usize) else { return };
+                let (inst_idx, sample_idx) = self.resolve_sample(*instrument, channel.note);
+                let sample = self.samples.get(sample_idx as usize);
+                let inst = self.instruments.get(inst_idx as usize);
+                if let Some(channel) = self.channels.get_mut(ch as usize) {
+                    channel.change_instrument(inst_idx, sample_idx, sample, inst);
+                }
+            }
             EventPayload::NoteOff { note: _ } => {
                 if let Some(channel) = self.channels.get_mut(ch as usize) {
-                    channel.stop();
+                    channel.key_off(self.instruments.get(channel.instrument as usize));
                 }
             }
-            EventPayload::Effect(effect) => {
+            EventPayload::NoteFade => {
+                if let Some(channel) = self.channels.get_mut(ch as usize) {
+                    channel.note_fade(self.instruments.get(channel.instrument as usize));
+                }
+            }
+            EventPayload::Effect(effect) | EventPayload::SecondEffect(effect) => {
+                let layered = matches!(payload, EventPayload::SecondEffect(_));
                 let spt = self.spt();
                 if let Some(channel) = self.channels.get_mut(ch as usize) {
                     if let Effect::TonePorta(speed) = effect {
@@ -165,9 +233,13 @@ impl TrackerMachine {
                             .map_or(0, |s| s.len());
                         let offset = (*param as u64 * sample_len as u64) / 256;
                         channel.position = offset << 16;
+                    } else if let Effect::SampleOffset(param) = effect {
+                        channel.apply_sample_offset(*param, self.samples.get(channel.sample_index as usize));
                     } else if effect.is_row_effect() {
                         channel.apply_row_effect(effect);
                         channel.update_increment(self.sample_rate);
+                    } else if layered {
+                        channel.layer_modulator(effect, spt);
                     } else {
                         channel.setup_modulator(effect, spt);
                     }
@@ -182,13 +254,19 @@ impl TrackerMachine {
         let sample_rate = self.sample_rate;
         let spt = self.spt();
         for channel in &mut self.channels {
+            channel.swap_pending_sample(&self.samples);
             if !channel.playing {
                 continue;
             }
             channel.clear_modulation();
             channel.advance_modulators(spt);
+            let sample = self.samples.get(channel.sample_index as usize);
+            channel.advance_auto_vibrato(sample.and_then(|s| s.vibrato.as_ref()));
+            channel.advance_funk(sample);
             channel.update_increment(sample_rate);
+            channel.advance_envelopes(self.instruments.get(channel.instrument as usize));
         }
+        self.voices.tick(&self.instruments, &self.samples, sample_rate);
     }
 }
 
@@ -199,15 +277,21 @@ impl AudioStream for TrackerMachine {
 
     fn render(&mut self, output: &mut AudioBuffer) {
         let frames = output.frames() as usize;
-        for channel in &mut self.channels {
+        for (channel, &muted) in self.channels.iter_mut().zip(&self.muted) {
             if !channel.playing { continue; }
             let sample = match self.samples.get(channel.sample_index as usize) {
                 Some(s) => s,
                 None => continue,
             };
+            let gain = if muted { 0.0 } else { self.mix_gain };
             let (left, right) = output.channels_mut_2(0, 1);
-            channel.render_block(sample, &mut left[..frames], &mut right[..frames], self.mix_gain);
+            channel.render_block(sample, &mut left[..frames], &mut right[..frames], gain);
         }
+        let (muted, mix_gain) = (&self.muted, self.mix_gain);
+        let (left, right) = output.channels_mut_2(0, 1);
+        self.voices.render(&self.samples, &mut left[..frames], &mut right[..frames], |ch| {
+            if muted.get(ch as usize).copied().unwrap_or(false) { 0.0 } else { mix_gain }
+        });
     }
 }
 
@@ -226,6 +310,7 @@ impl Machine for TrackerMachine {
         for channel in &mut self.channels {
             channel.stop();
         }
+        self.voices.clear();
     }
 
     fn set_param(&mut self, _param: u16, _value: i32) {}
@@ -237,27 +322,54 @@ impl Machine for TrackerMachine {
     fn set_speed(&mut self, speed: u8) {
         self.speed = speed;
     }
+
+    fn update_sample(&mut self, index: u8, fields: &SampleFields) {
+        if let Some(sample) = self.samples.get_mut(index as usize) {
+            fields.apply_to(sample);
+        }
+    }
+
+    fn update_samples(&mut self, samples: &[Sample], instruments: &[Instrument]) {
+        self.samples = samples.to_vec();
+        self.instruments = instruments.to_vec();
+    }
+
+    fn set_channel_muted(&mut self, channel: u8, muted: bool) {
+        if let Some(slot) = self.muted.get_mut(channel as usize) {
+            *slot = muted;
+        }
+    }
+
+    fn set_interpolation(&mut self, quality: Interpolation) {
+        for channel in &mut self.channels {
+            channel.interpolation = quality;
+        }
+    }
+
+    fn channel_state(&self, channel: u8) -> Option<&ChannelState> {
+        self.channels.get(channel as usize)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::frequency::period_to_increment;
+    use crate::frequency::{linear_period_to_increment, note_to_period, period_to_increment};
     use mb_ir::{ChannelSettings, Instrument, Sample, SampleData};
 
     const SR: u32 = 44100;
 
     fn make_machine(data: Vec<i8>, volume: u8) -> TrackerMachine {
-        let settings = [ChannelSettings { initial_pan: -64, initial_vol: 64, muted: false }];
+        let settings = [ChannelSettings { initial_pan: -64, initial_vol: 64, muted: false, solo: false }];
         let mut sample = Sample::new("test");
-        sample.data = SampleData::Mono8(data);
+        sample.set_data(SampleData::Mono8(data));
         sample.default_volume = volume;
         sample.c4_speed = 8363;
         let mut inst = Instrument::new("test");
         inst.set_single_sample(0);
 
-        // mix_gain = 1.0 (1 channel → shift=0)
-        TrackerMachine::new(&settings, vec![sample], vec![inst], 6, 4, SR, 1.0)
+        // mix_gain = 1.0 (1 channel → shift=0); no ramps so levels are exact
+        TrackerMachine::new(&settings, vec![sample], vec![inst], 6, 4, SR, 1.0).with_volume_ramp(0)
     }
 
     fn note_on(machine: &mut TrackerMachine, note: u8, instrument: u8) {
@@ -388,6 +500,19 @@ mod tests {
         assert!(m.channel(0).unwrap().period_mod.is_none());
     }
 
+    #[test]
+    fn second_effect_layers_on_first() {
+        let mut m = make_machine(vec![127; 100000], 32);
+        note_on(&mut m, 48, 1);
+        effect(&mut m, Effect::Vibrato { speed: 4, depth: 4 });
+        m.apply_event(0, &EventPayload::SecondEffect(Effect::VolumeSlide(4)));
+        let ch = m.channel(0).unwrap();
+        assert!(ch.period_mod.is_some() && ch.volume_mod.is_some());
+        // A first-column effect still replaces everything
+        effect(&mut m, Effect::PortaUp(2));
+        assert!(m.channel(0).unwrap().volume_mod.is_none());
+    }
+
     #[test]
     fn porta_up_decreases_period() {
         let mut m = make_machine(vec![127; 100000], 64);
@@ -465,6 +590,208 @@ mod tests {
         assert!(m.channel(0).unwrap().position >= pos_before);
     }
 
+    #[test]
+    fn sample_offset_remembers_param_and_adds_high_offset() {
+        let mut m = make_machine(vec![127; 100000], 64);
+        note_on(&mut m, 48, 1);
+        effect(&mut m, Effect::SampleOffset(0x10));
+        assert_eq!(m.channel(0).unwrap().position >> 16, 0x1000);
+        note_on(&mut m, 48, 1);
+        effect(&mut m, Effect::SetHighOffset(1));
+        effect(&mut m, Effect::SampleOffset(0));
+        assert_eq!(m.channel(0).unwrap().position >> 16, 0x11000);
+    }
+
+    #[test]
+    fn sample_offset_past_end_cuts_or_clamps() {
+        let mut m = make_machine(vec![127; 1000], 64);
+        note_on(&mut m, 48, 1);
+        effect(&mut m, Effect::SampleOffset(0x10));
+        assert!(!m.channel(0).unwrap().playing);
+
+        let mut m = make_machine(vec![127; 1000], 64).with_compatibility(CompatibilityMode::ProTracker);
+        note_on(&mut m, 48, 1);
+        effect(&mut m, Effect::SampleOffset(0x10));
+        assert_eq!(m.channel(0).unwrap().position >> 16, 999);
+        m.samples[0].loop_type = mb_ir::LoopType::Forward;
+        (m.samples[0].loop_start, m.samples[0].loop_end) = (200, 1000);
+        note_on(&mut m, 48, 1);
+        effect(&mut m, Effect::SampleOffset(0x10));
+        let ch = m.channel(0).unwrap();
+        assert!(ch.playing);
+        assert_eq!(ch.position >> 16, 200);
+    }
+
+    /// A machine with a second, looped sample as instrument 2.
+    fn two_sample_machine(mode: CompatibilityMode) -> TrackerMachine {
+        let mut m = make_machine(vec![127; 1000], 64).with_compatibility(mode);
+        let mut looped = Sample::new("looped");
+        looped.set_data(SampleData::Mono8(vec![-64; 1000]));
+        looped.default_volume = 40;
+        looped.loop_type = mb_ir::LoopType::Forward;
+        (looped.loop_start, looped.loop_end) = (100, 1000);
+        m.samples.push(looped);
+        let mut inst = Instrument::new("looped");
+        inst.set_single_sample(1);
+        m.instruments.push(inst);
+        m
+    }
+
+    fn render_frames(m: &mut TrackerMachine, frames: usize) {
+        let mut buf = AudioBuffer::new(2, 1);
+        for _ in 0..frames {
+            buf.silence();
+            m.render(&mut buf);
+        }
+    }
+
+    #[test]
+    fn protracker_instrument_change_swaps_sample_at_end() {
+        let mut m = two_sample_machine(CompatibilityMode::ProTracker);
+        note_on(&mut m, 48, 1);
+        m.channels[0].volume = 20;
+        // Repeating the playing instrument changes nothing
+        m.apply_event(0, &EventPayload::InstrumentChange { instrument: 1 });
+        assert_eq!(m.channel(0).unwrap().volume, 20);
+        m.apply_event(0, &EventPayload::InstrumentChange { instrument: 2 });
+        m.tick();
+        let ch = m.channel(0).unwrap();
+        assert_eq!((ch.sample_index, ch.volume), (0, 40));
+        render_frames(&mut m, 8000);
+        m.tick();
+        let ch = m.channel(0).unwrap();
+        assert!(ch.playing);
+        assert_eq!(ch.sample_index, 1);
+        assert_eq!(ch.position >> 16, 100);
+    }
+
+    #[test]
+    fn native_ignores_instrument_change() {
+        let mut m = two_sample_machine(CompatibilityMode::Native);
+        note_on(&mut m, 48, 1);
+        m.apply_event(0, &EventPayload::InstrumentChange { instrument: 2 });
+        render_frames(&mut m, 8000);
+        m.tick();
+        let ch = m.channel(0).unwrap();
+        assert_eq!((ch.sample_index, ch.volume, ch.playing), (0, 64, false));
+    }
+
+    #[test]
+    fn ft2_instrument_change_restarts_envelopes() {
+        let mut m = make_machine(vec![127; 100000], 64).with_compatibility(CompatibilityMode::FastTracker2);
+        m.instruments[0].volume_envelope = Some(volume_envelope(&[(0, 64), (8, 0)], None));
+        note_on(&mut m, 48, 1);
+        for _ in 0..4 { m.tick(); }
+        m.apply_event(0, &EventPayload::InstrumentChange { instrument: 1 });
+        let ch = m.channel(0).unwrap();
+        assert_eq!((ch.envelope_tick, ch.envelope_volume), (0, 64));
+    }
+
+    #[test]
+    fn effect_memory_follows_compatibility_mode() {
+        let porta_after = |mode, first: Effect, second: Effect| {
+            let mut m = make_machine(vec![127; 100000], 64).with_compatibility(mode);
+            note_on(&mut m, 48, 1);
+            effect(&mut m, first);
+            effect(&mut m, second);
+            m.tick();
+            m.channel(0).unwrap().period
+        };
+        // ProTracker: a zero parameter doesn't slide
+        assert_eq!(porta_after(CompatibilityMode::ProTracker, Effect::PortaUp(4), Effect::PortaUp(0)), 428);
+        // FT2: porta up and down remember separately
+        assert_eq!(porta_after(CompatibilityMode::FastTracker2, Effect::PortaUp(4), Effect::PortaUp(0)), 424);
+        assert_eq!(porta_after(CompatibilityMode::FastTracker2, Effect::PortaUp(4), Effect::PortaDown(0)), 428);
+        // IT: Exx and Fxx share
+        assert_eq!(porta_after(CompatibilityMode::ImpulseTracker, Effect::PortaUp(4), Effect::PortaDown(0)), 432);
+
+        let mut m = make_machine(vec![127; 100000], 32).with_compatibility(CompatibilityMode::ImpulseTracker);
+        note_on(&mut m, 48, 1);
+        effect(&mut m, Effect::VolumeSlide(2));
+        effect(&mut m, Effect::VibratoVolSlide(0));
+        m.tick();
+        assert_eq!(m.channel(0).unwrap().volume, 34);
+    }
+
+    #[test]
+    fn fine_slides_recall_their_memory() {
+        let volume_after = |mode, slides: &[Effect]| {
+            let mut m = make_machine(vec![127; 100000], 32).with_compatibility(mode);
+            note_on(&mut m, 48, 1);
+            for &slide in slides {
+                effect(&mut m, slide);
+            }
+            m.channel(0).unwrap().volume
+        };
+        let (up, down) = (Effect::FineVolumeSlideUp, Effect::FineVolumeSlideDown);
+        assert_eq!(volume_after(CompatibilityMode::ProTracker, &[up(4), up(0)]), 36);
+        // FT2: EAx and EBx remember separately
+        assert_eq!(volume_after(CompatibilityMode::FastTracker2, &[up(4), up(0)]), 40);
+        assert_eq!(volume_after(CompatibilityMode::FastTracker2, &[up(4), down(0)]), 36);
+        // IT: DxF/DFx share Dxy's memory, direction included
+        assert_eq!(volume_after(CompatibilityMode::ImpulseTracker, &[up(4), down(0)]), 32);
+        assert_eq!(volume_after(CompatibilityMode::ImpulseTracker, &[Effect::VolumeSlide(-2), up(0)]), 34);
+
+        let mut m = make_machine(vec![127; 100000], 64).with_compatibility(CompatibilityMode::ImpulseTracker);
+        note_on(&mut m, 48, 1);
+        effect(&mut m, Effect::PortaDown(2));
+        effect(&mut m, Effect::FinePortaUp(0));
+        assert_eq!(m.channel(0).unwrap().period, 426);
+    }
+
+    #[test]
+    fn ft2_doubles_vibrato_depth() {
+        let swing = |mode| {
+            let mut m = make_machine(vec![127; 100000], 64).with_compatibility(mode);
+            note_on(&mut m, 48, 1);
+            effect(&mut m, Effect::Vibrato { speed: 8, depth: 4 });
+            (0..8).map(|_| { m.tick(); m.channel(0).unwrap().period_offset.abs() }).max().unwrap()
+        };
+        let (pt, ft2) = (swing(CompatibilityMode::ProTracker), swing(CompatibilityMode::FastTracker2));
+        assert!(pt > 0);
+        assert!((ft2 - 2 * pt).abs() <= 1, "{} vs {}", ft2, pt);
+    }
+
+    #[test]
+    fn glissando_steps_tone_porta_by_semitones() {
+        let mut m = make_machine(vec![127; 100000], 64);
+        note_on(&mut m, 48, 1);
+        effect(&mut m, Effect::SetGlissando(1));
+        m.apply_event(0, &EventPayload::PortaTarget { note: 60, instrument: 1 });
+        effect(&mut m, Effect::TonePorta(8));
+        let notes: Vec<u16> = (1..=119).map(note_to_period).collect();
+        for _ in 0..5 {
+            m.tick();
+            let ch = m.channel(0).unwrap();
+            assert!(notes.contains(&ch.effective_period()), "{} is not a note", ch.effective_period());
+        }
+        let ch = m.channel(0).unwrap();
+        assert!(ch.period < 428 && ch.period > 214);
+        assert_eq!(ch.effective_period(), 381); // D-4, nearest to 428 - 5 * 8
+    }
+
+    #[test]
+    fn funk_repeat_inverts_loop_without_touching_sample() {
+        let mut m = make_machine(vec![100; 8], 64);
+        m.samples[0].loop_type = mb_ir::LoopType::Forward;
+        m.samples[0].loop_end = 8;
+        note_on(&mut m, 48, 1);
+        effect(&mut m, Effect::FunkRepeat(15));
+        m.tick();
+        m.tick();
+        assert_eq!(m.channel(0).unwrap().funk_count, 2);
+
+        let mut buf = AudioBuffer::new(2, 1);
+        m.render(&mut buf);
+        assert!(buf.channel(0)[0] < 0.0);
+        assert_eq!(m.samples[0].data.get_mono(0), 100 * 256);
+
+        // EF0 stops further flips
+        effect(&mut m, Effect::FunkRepeat(0));
+        m.tick();
+        assert_eq!(m.channel(0).unwrap().funk_count, 2);
+    }
+
     #[test]
     fn tone_porta_vol_slide_does_both() {
         let mut m = make_machine(vec![127; 100000], 32);
@@ -712,4 +1039,321 @@ mod tests {
         m.set_speed(3);
         assert_eq!(m.speed, 3);
     }
+
+    #[test]
+    fn muted_channel_is_silent_but_keeps_playing() {
+        let mut m = make_machine(vec![127; 100000], 64);
+        m.set_channel_muted(0, true);
+        note_on(&mut m, 48, 1);
+        let mut buf = AudioBuffer::new(2, 1);
+        m.render(&mut buf);
+        assert_eq!(buf.channel(0)[0], 0.0);
+        assert!(m.channel(0).unwrap().position > 0);
+
+        m.set_channel_muted(0, false);
+        buf.silence();
+        m.render(&mut buf);
+        assert!(buf.channel(0)[0] != 0.0);
+    }
+
+    fn machine_with_nna(action: mb_ir::NewNoteAction, fadeout: u16) -> TrackerMachine {
+        let mut m = make_machine(vec![127; 100000], 64);
+        m.instruments[0].new_note_action = action;
+        m.instruments[0].fadeout = fadeout;
+        m
+    }
+
+    fn render_one(m: &mut TrackerMachine) -> f32 {
+        let mut buf = AudioBuffer::new(2, 1);
+        m.render(&mut buf);
+        buf.channel(0)[0]
+    }
+
+    #[test]
+    fn nna_cut_replaces_note() {
+        let mut m = machine_with_nna(mb_ir::NewNoteAction::Cut, 0);
+        note_on(&mut m, 48, 1);
+        let single = render_one(&mut m);
+        note_on(&mut m, 60, 1);
+        assert!(m.voices().is_empty());
+        assert_eq!(render_one(&mut m), single);
+    }
+
+    #[test]
+    fn nna_continue_layers_notes() {
+        let mut m = machine_with_nna(mb_ir::NewNoteAction::Continue, 0);
+        note_on(&mut m, 48, 1);
+        let single = render_one(&mut m);
+        note_on(&mut m, 60, 1);
+        assert_eq!(m.voices().len(), 1);
+        assert!((render_one(&mut m) - 2.0 * single).abs() < 1e-6);
+    }
+
+    #[test]
+    fn nna_fade_voice_dies_after_fadeout() {
+        let mut m = machine_with_nna(mb_ir::NewNoteAction::Fade, 1024);
+        note_on(&mut m, 48, 1);
+        note_on(&mut m, 60, 1);
+        m.tick();
+        assert_eq!(m.voices().len(), 1);
+        m.tick();
+        assert!(m.voices().is_empty());
+    }
+
+    #[test]
+    fn background_voices_follow_mute_and_stop() {
+        let mut m = machine_with_nna(mb_ir::NewNoteAction::Continue, 0);
+        note_on(&mut m, 48, 1);
+        note_on(&mut m, 60, 1);
+        m.set_channel_muted(0, true);
+        assert_eq!(render_one(&mut m), 0.0);
+        m.stop();
+        assert!(m.voices().is_empty());
+    }
+
+    fn volume_envelope(points: &[(u16, i8)], sustain: Option<u8>) -> mb_ir::Envelope {
+        let mut env = mb_ir::Envelope::new();
+        for &(tick, value) in points {
+            env.add_point(tick, value);
+        }
+        env.sustain_start = sustain;
+        env.sustain_end = sustain;
+        env.enabled = true;
+        env
+    }
+
+    #[test]
+    fn volume_envelope_shapes_output() {
+        let mut m = make_machine(vec![127; 100000], 64);
+        m.instruments[0].volume_envelope = Some(volume_envelope(&[(0, 64), (4, 0)], None));
+        note_on(&mut m, 48, 1);
+        let full = render_one(&mut m);
+        m.tick();
+        m.tick();
+        assert_eq!(m.channel(0).unwrap().envelope_volume, 32);
+        assert!((render_one(&mut m) - full / 2.0).abs() < 1e-3);
+        m.tick();
+        m.tick();
+        assert!(!m.channel(0).unwrap().playing);
+    }
+
+    #[test]
+    fn note_off_leaves_sustain_and_fades_at_envelope_end() {
+        let mut m = make_machine(vec![127; 100000], 64);
+        m.instruments[0].volume_envelope = Some(volume_envelope(&[(0, 64), (2, 48), (6, 32)], Some(1)));
+        m.instruments[0].fadeout = 256;
+        note_on(&mut m, 48, 1);
+        for _ in 0..20 { m.tick(); }
+        assert_eq!(m.channel(0).unwrap().envelope_volume, 48);
+        m.apply_event(0, &EventPayload::NoteOff { note: 0 });
+        m.tick();
+        let ch = m.channel(0).unwrap();
+        assert!(ch.playing && ch.released && !ch.fading);
+        assert_eq!(ch.fade, crate::channel::FADE_MAX);
+        assert!(ch.envelope_volume < 48);
+        for _ in 0..3 { m.tick(); }
+        let ch = m.channel(0).unwrap();
+        assert!(ch.fading && ch.playing);
+        assert_eq!(ch.fade, crate::channel::FADE_MAX - 256 * 32);
+    }
+
+    #[test]
+    fn note_off_fades_at_once_without_envelope_or_with_looping_one() {
+        let mut m = make_machine(vec![127; 100000], 64);
+        m.instruments[0].fadeout = 256;
+        note_on(&mut m, 48, 1);
+        m.apply_event(0, &EventPayload::NoteOff { note: 0 });
+        assert!(m.channel(0).unwrap().fading);
+
+        let mut env = volume_envelope(&[(0, 64), (4, 32)], None);
+        (env.loop_start, env.loop_end) = (Some(0), Some(1));
+        m.instruments[0].volume_envelope = Some(env);
+        note_on(&mut m, 48, 1);
+        m.apply_event(0, &EventPayload::NoteOff { note: 0 });
+        assert!(m.channel(0).unwrap().fading);
+    }
+
+    #[test]
+    fn note_fade_keeps_sustain() {
+        let mut m = make_machine(vec![127; 100000], 64);
+        m.instruments[0].volume_envelope = Some(volume_envelope(&[(0, 64), (2, 48), (10, 0)], Some(1)));
+        m.instruments[0].fadeout = 1024;
+        note_on(&mut m, 48, 1);
+        m.apply_event(0, &EventPayload::NoteFade);
+        for _ in 0..5 { m.tick(); }
+        assert_eq!(m.channel(0).unwrap().envelope_tick, 2);
+        assert!(!m.channel(0).unwrap().playing);
+    }
+
+    #[test]
+    fn panning_envelope_swings_around_channel_pan() {
+        let mut m = make_machine(vec![127; 100000], 64);
+        let mut env = volume_envelope(&[(0, 64)], None);
+        env.points[0].value = 64;
+        m.instruments[0].panning_envelope = Some(env);
+        m.channels[0].panning = -32;
+        note_on(&mut m, 48, 1);
+        // Half left, swung fully right by the room left on that side: center
+        assert_eq!(m.channel(0).unwrap().effective_panning(), 0);
+    }
+
+    #[test]
+    fn new_note_restarts_envelopes() {
+        let mut m = make_machine(vec![127; 100000], 64);
+        m.instruments[0].volume_envelope = Some(volume_envelope(&[(0, 64), (8, 0)], None));
+        note_on(&mut m, 48, 1);
+        for _ in 0..4 { m.tick(); }
+        note_on(&mut m, 50, 1);
+        let ch = m.channel(0).unwrap();
+        assert_eq!((ch.envelope_tick, ch.envelope_volume), (0, 64));
+    }
+
+    #[test]
+    fn auto_vibrato_offsets_period() {
+        let mut m = make_machine(vec![127; 100000], 64);
+        m.samples[0].vibrato = Some(mb_ir::AutoVibrato { speed: 64, depth: 64, sweep: 0, waveform: 0 });
+        note_on(&mut m, 48, 1);
+        m.tick();
+        assert_eq!(m.channel(0).unwrap().auto_vibrato_offset, 0);
+        m.tick();
+        let ch = m.channel(0).unwrap();
+        assert_eq!(ch.auto_vibrato_offset, 16);
+        assert_eq!(ch.period, 428);
+        assert_eq!(ch.increment, period_to_increment(444, 8363, SR));
+    }
+
+    #[test]
+    fn auto_vibrato_layers_with_vibrato_effect() {
+        let mut m = make_machine(vec![127; 100000], 64);
+        m.samples[0].vibrato = Some(mb_ir::AutoVibrato { speed: 64, depth: 64, sweep: 0, waveform: 2 });
+        note_on(&mut m, 48, 1);
+        effect(&mut m, Effect::Vibrato { speed: 8, depth: 8 });
+        m.tick();
+        m.tick();
+        let ch = m.channel(0).unwrap();
+        assert_ne!(ch.period_offset, 0);
+        assert_eq!(ch.auto_vibrato_offset, 16);
+        assert_eq!(ch.effective_period() as i32, 428 + ch.period_offset as i32 + 16);
+    }
+
+    #[test]
+    fn auto_vibrato_sweeps_in() {
+        let mut m = make_machine(vec![127; 100000], 64);
+        m.samples[0].vibrato = Some(mb_ir::AutoVibrato { speed: 0, depth: 64, sweep: 4, waveform: 2 });
+        note_on(&mut m, 48, 1);
+        let mut offsets = Vec::new();
+        for _ in 0..6 {
+            m.tick();
+            offsets.push(m.channel(0).unwrap().auto_vibrato_offset);
+        }
+        assert_eq!(offsets, vec![0, 4, 8, 12, 16, 16]);
+    }
+
+    fn linear_machine() -> TrackerMachine {
+        make_machine(vec![127; 100000], 64).with_frequency_mode(FrequencyMode::Linear)
+    }
+
+    #[test]
+    fn linear_mode_uses_linear_periods() {
+        let mut m = linear_machine();
+        note_on(&mut m, 48, 1);
+        let ch = m.channel(0).unwrap();
+        assert_eq!(ch.period, 4608);
+        assert_eq!(ch.increment, linear_period_to_increment(4608, 8363, SR));
+    }
+
+    #[test]
+    fn linear_porta_moves_four_units_per_step() {
+        let mut m = linear_machine();
+        note_on(&mut m, 48, 1);
+        effect(&mut m, Effect::PortaUp(4));
+        m.tick();
+        assert_eq!(m.channel(0).unwrap().period, 4608 - 16);
+        effect(&mut m, Effect::FinePortaDown(2));
+        assert_eq!(m.channel(0).unwrap().period, 4608 - 8);
+    }
+
+    #[test]
+    fn linear_tone_porta_reaches_target_note() {
+        let mut m = linear_machine();
+        note_on(&mut m, 48, 1);
+        m.apply_event(0, &EventPayload::PortaTarget { note: 60, instrument: 1 });
+        assert_eq!(m.channel(0).unwrap().target_period, 4608 - 12 * 64);
+        effect(&mut m, Effect::TonePorta(255));
+        m.tick();
+        assert_eq!(m.channel(0).unwrap().period, 4608 - 12 * 64);
+    }
+
+    #[test]
+    fn linear_arpeggio_offsets_are_semitones() {
+        let mut m = linear_machine();
+        note_on(&mut m, 48, 1);
+        effect(&mut m, Effect::Arpeggio { x: 12, y: 7 });
+        m.tick();
+        assert_eq!(m.channel(0).unwrap().period_offset, -12 * 64);
+        m.tick();
+        assert_eq!(m.channel(0).unwrap().period_offset, -7 * 64);
+    }
+
+    fn render_block(m: &mut TrackerMachine, frames: u16) -> Vec<f32> {
+        let mut buf = AudioBuffer::new(2, frames);
+        m.render(&mut buf);
+        buf.channel(0).to_vec()
+    }
+
+    #[test]
+    fn note_start_and_volume_jump_ramp_linearly() {
+        let mut m = make_machine(vec![127; 100000], 64).with_volume_ramp(4);
+        note_on(&mut m, 48, 1);
+        let out = render_block(&mut m, 6);
+        let full = out[5];
+        assert!(full > 0.0);
+        for (i, &v) in out[..4].iter().enumerate() {
+            assert!((v - full * (i + 1) as f32 / 4.0).abs() < 1e-6);
+        }
+
+        effect(&mut m, Effect::SetVolume(32));
+        let out = render_block(&mut m, 6);
+        assert!((out[0] - full * 7.0 / 8.0).abs() < 1e-6);
+        assert!((out[4] - full / 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn note_off_ramps_out_before_stopping() {
+        let mut m = make_machine(vec![127; 100000], 64).with_volume_ramp(4);
+        note_on(&mut m, 48, 1);
+        let full = render_block(&mut m, 8)[7];
+        m.apply_event(0, &EventPayload::NoteOff { note: 0 });
+        assert!(m.channel(0).unwrap().playing);
+        let out = render_block(&mut m, 8);
+        assert!((out[0] - full * 0.75).abs() < 1e-6);
+        assert!(out[3..].iter().all(|&v| v == 0.0));
+        assert!(!m.channel(0).unwrap().playing);
+    }
+
+    #[test]
+    fn retrigger_ramps_old_note_out_in_background() {
+        let mut m = make_machine(vec![127; 100000], 64).with_volume_ramp(4);
+        note_on(&mut m, 48, 1);
+        render_block(&mut m, 8);
+        note_on(&mut m, 60, 1);
+        assert_eq!(m.voices().len(), 1);
+        render_block(&mut m, 8);
+        assert!(m.voices().is_empty());
+    }
+
+    #[test]
+    fn long_render_matches_block_sized_renders() {
+        let data: Vec<i8> = (0..5000).map(|i| (i % 200) as i8).collect();
+        let render = |sizes: &[u16]| {
+            let mut m = make_machine(data.clone(), 64).with_volume_ramp(64);
+            note_on(&mut m, 48, 1);
+            let mut out = Vec::new();
+            for &frames in sizes {
+                out.extend(render_block(&mut m, frames));
+            }
+            out
+        };
+        assert_eq!(render(&[700]), render(&[256, 256, 188]));
+    }
 }
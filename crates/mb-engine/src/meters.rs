@@ -0,0 +1,256 @@
+//! Level meters and waveform taps on graph nodes.
+//!
+//! The engine measures every node's output after each rendered block and
+//! publishes the results through relaxed atomics in a shared `MeterBank`,
+//! so a UI thread can poll peaks, RMS levels and a short scope trace
+//! without locking or touching the audio thread.
+
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use mb_ir::AudioBuffer;
+
+/// Frames of waveform each node's scope keeps.
+pub const SCOPE_FRAMES: usize = 256;
+
+/// Frames of master output kept for analysis (e.g. a spectrum).
+pub const MASTER_HISTORY_FRAMES: usize = 1024;
+
+/// Frames averaged into each published RMS level.
+const RMS_WINDOW: u32 = 1024;
+
+/// Latest levels of one node's output, per side.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MeterReading {
+    /// Largest absolute sample since the previous reading
+    pub peak: [f32; 2],
+    /// RMS over the most recent `RMS_WINDOW` frames
+    pub rms: [f32; 2],
+}
+
+/// One node's published levels and scope ring.
+struct NodeMeter {
+    /// `f32` bits of the absolute peak; non-negative floats order like
+    /// their bits, so `fetch_max` keeps the largest
+    peak: [AtomicU32; 2],
+    /// `f32` bits of the last RMS window
+    rms: [AtomicU32; 2],
+    /// `f32` bits of the mono (L+R)/2 signal
+    scope: [AtomicU32; SCOPE_FRAMES],
+    /// Next scope slot to write
+    scope_pos: AtomicUsize,
+}
+
+impl NodeMeter {
+    fn new() -> Self {
+        Self {
+            peak: [AtomicU32::new(0), AtomicU32::new(0)],
+            rms: [AtomicU32::new(0), AtomicU32::new(0)],
+            scope: core::array::from_fn(|_| AtomicU32::new(0)),
+            scope_pos: AtomicUsize::new(0),
+        }
+    }
+
+    fn clear(&self) {
+        for side in 0..2 {
+            self.peak[side].store(0, Ordering::Relaxed);
+            self.rms[side].store(0, Ordering::Relaxed);
+        }
+        for slot in &self.scope {
+            slot.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Meters shared between the engine (writer) and any number of readers.
+///
+/// Holds a fixed number of node slots so it never reallocates while a
+/// reader holds it; nodes past the end go unmetered.
+pub struct MeterBank {
+    nodes: Vec<NodeMeter>,
+    /// `f32` bits of the mono master output, longer than a scope
+    master: Vec<AtomicU32>,
+    /// Next `master` slot to write
+    master_pos: AtomicUsize,
+}
+
+impl MeterBank {
+    /// A bank metering nodes `0..nodes`.
+    pub fn new(nodes: usize) -> Self {
+        Self {
+            nodes: (0..nodes).map(|_| NodeMeter::new()).collect(),
+            master: (0..MASTER_HISTORY_FRAMES).map(|_| AtomicU32::new(0)).collect(),
+            master_pos: AtomicUsize::new(0),
+        }
+    }
+
+    /// Number of node slots.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether the bank has no node slots.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The node's peak since the previous reading (which this resets) and
+    /// its latest RMS level.
+    pub fn take_reading(&self, node: u16) -> Option<MeterReading> {
+        let meter = self.nodes.get(node as usize)?;
+        let load = |a: &AtomicU32| f32::from_bits(a.load(Ordering::Relaxed));
+        Some(MeterReading {
+            peak: [0, 1].map(|side| f32::from_bits(meter.peak[side].swap(0, Ordering::Relaxed))),
+            rms: [load(&meter.rms[0]), load(&meter.rms[1])],
+        })
+    }
+
+    /// Copy the node's most recent scope frames into `out`, oldest first.
+    /// Returns the frames written (at most `SCOPE_FRAMES`).
+    pub fn scope(&self, node: u16, out: &mut [f32]) -> usize {
+        let Some(meter) = self.nodes.get(node as usize) else { return 0 };
+        let frames = out.len().min(SCOPE_FRAMES);
+        let end = meter.scope_pos.load(Ordering::Relaxed);
+        let start = end + SCOPE_FRAMES - frames;
+        for (i, sample) in out[..frames].iter_mut().enumerate() {
+            *sample = f32::from_bits(meter.scope[(start + i) % SCOPE_FRAMES].load(Ordering::Relaxed));
+        }
+        frames
+    }
+
+    /// Copy the most recent mono master output into `out`, oldest first.
+    /// Returns the frames written (at most `MASTER_HISTORY_FRAMES`).
+    pub fn master_history(&self, out: &mut [f32]) -> usize {
+        let frames = out.len().min(MASTER_HISTORY_FRAMES);
+        let start = self.master_pos.load(Ordering::Relaxed) + MASTER_HISTORY_FRAMES - frames;
+        for (i, sample) in out[..frames].iter_mut().enumerate() {
+            *sample = f32::from_bits(self.master[(start + i) % MASTER_HISTORY_FRAMES].load(Ordering::Relaxed));
+        }
+        frames
+    }
+
+    /// Zero every level and scope (e.g. when playback stops).
+    pub fn clear(&self) {
+        for meter in &self.nodes {
+            meter.clear();
+        }
+        for slot in &self.master {
+            slot.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The engine's writing end of a `MeterBank`, with the per-node RMS sums
+/// it accumulates between publications.
+pub(crate) struct MeterTap {
+    bank: Arc<MeterBank>,
+    /// Sums of squares per node and side since the last RMS publication
+    sums: Vec<[f32; 2]>,
+    /// Frames in `sums` per node
+    counts: Vec<u32>,
+}
+
+impl MeterTap {
+    pub fn new(bank: Arc<MeterBank>) -> Self {
+        let n = bank.len();
+        Self { bank, sums: vec![[0.0; 2]; n], counts: vec![0; n] }
+    }
+
+    /// Measure the first `frames` frames of every node's output.
+    pub fn measure(&mut self, outputs: &[AudioBuffer], frames: usize) {
+        if let Some(master) = outputs.first() {
+            self.record_master(master, frames);
+        }
+        for (id, buf) in outputs.iter().enumerate().take(self.bank.len()) {
+            let meter = &self.bank.nodes[id];
+            let (left, right) = (&buf.channel(0)[..frames], &buf.channel(1)[..frames]);
+            for (side, samples) in [left, right].into_iter().enumerate() {
+                let (peak, sum) = samples.iter().fold((0.0f32, 0.0f32), |(p, s), &x| (p.max(x.abs()), s + x * x));
+                meter.peak[side].fetch_max(peak.to_bits(), Ordering::Relaxed);
+                self.sums[id][side] += sum;
+            }
+
+            let mut pos = meter.scope_pos.load(Ordering::Relaxed);
+            for (&l, &r) in left.iter().zip(right) {
+                meter.scope[pos].store(((l + r) * 0.5).to_bits(), Ordering::Relaxed);
+                pos = (pos + 1) % SCOPE_FRAMES;
+            }
+            meter.scope_pos.store(pos, Ordering::Relaxed);
+
+            self.counts[id] += frames as u32;
+            if self.counts[id] >= RMS_WINDOW {
+                let n = self.counts[id] as f32;
+                for side in 0..2 {
+                    let rms = libm::sqrtf(self.sums[id][side] / n);
+                    meter.rms[side].store(rms.to_bits(), Ordering::Relaxed);
+                }
+                self.sums[id] = [0.0; 2];
+                self.counts[id] = 0;
+            }
+        }
+    }
+
+    /// Append the master output to the bank's master history.
+    fn record_master(&self, master: &AudioBuffer, frames: usize) {
+        let bank = &self.bank;
+        let mut pos = bank.master_pos.load(Ordering::Relaxed);
+        for (&l, &r) in master.channel(0)[..frames].iter().zip(&master.channel(1)[..frames]) {
+            bank.master[pos].store(((l + r) * 0.5).to_bits(), Ordering::Relaxed);
+            pos = (pos + 1) % MASTER_HISTORY_FRAMES;
+        }
+        bank.master_pos.store(pos, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(frames: u16, left: f32, right: f32) -> AudioBuffer {
+        let mut buf = AudioBuffer::new(2, frames);
+        buf.channel_mut(0).fill(left);
+        buf.channel_mut(1).fill(right);
+        buf
+    }
+
+    #[test]
+    fn peak_is_taken_and_rms_published_per_window() {
+        let bank = Arc::new(MeterBank::new(1));
+        let mut tap = MeterTap::new(bank.clone());
+        tap.measure(&[block(256, -0.5, 0.25)], 256);
+        let reading = bank.take_reading(0).unwrap();
+        assert_eq!(reading.peak, [0.5, 0.25]);
+        assert_eq!(reading.rms, [0.0, 0.0]);
+        assert_eq!(bank.take_reading(0).unwrap().peak, [0.0, 0.0]);
+
+        for _ in 0..3 {
+            tap.measure(&[block(256, -0.5, 0.25)], 256);
+        }
+        assert_eq!(bank.take_reading(0).unwrap().rms, [0.5, 0.25]);
+        assert_eq!(bank.take_reading(1), None);
+    }
+
+    #[test]
+    fn scope_returns_latest_frames_oldest_first() {
+        let bank = Arc::new(MeterBank::new(1));
+        let mut tap = MeterTap::new(bank.clone());
+        let mut buf = AudioBuffer::new(2, 300);
+        for (i, s) in buf.channel_mut(0).iter_mut().enumerate() {
+            *s = i as f32;
+        }
+        tap.measure(core::slice::from_ref(&buf), 300);
+        let mut out = [0.0f32; 4];
+        assert_eq!(bank.scope(0, &mut out), 4);
+        assert_eq!(out, [148.0, 148.5, 149.0, 149.5]);
+
+        let mut history = [0.0f32; 2];
+        assert_eq!(bank.master_history(&mut history), 2);
+        assert_eq!(history, [149.0, 149.5]);
+
+        bank.clear();
+        bank.scope(0, &mut out);
+        assert_eq!(out, [0.0; 4]);
+    }
+}
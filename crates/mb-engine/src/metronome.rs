@@ -0,0 +1,226 @@
+//! Metronome: a click on every beat, mixed after the master bus.
+//!
+//! The engine calls `Metronome::trigger` when the transport lands on a
+//! beat boundary and `Metronome::render` on each sub-block. The click is a
+//! short decaying sine burst, pitched higher on the first beat of the bar.
+//! A `CountIn` plays clicks on its own clock before the song starts.
+
+use core::f32::consts::TAU;
+
+/// Click length in milliseconds.
+const CLICK_MS: f32 = 30.0;
+
+/// Click pitch on the first beat of the bar.
+const ACCENT_HZ: f32 = 1760.0;
+
+/// Click pitch on the other beats.
+const BEAT_HZ: f32 = 880.0;
+
+/// Click amplitude below which the click is over.
+const SILENCE: f32 = 1e-4;
+
+/// Beats per bar until told otherwise.
+pub const DEFAULT_BEATS_PER_BAR: u32 = 4;
+
+/// Beat-synchronized click generator.
+#[derive(Clone, Debug)]
+pub struct Metronome {
+    /// Whether clicks are triggered
+    enabled: bool,
+    /// Linear output gain
+    gain: f32,
+    /// Beats per bar; beat 0 of each bar is accented
+    beats_per_bar: u32,
+    sample_rate: u32,
+    /// Oscillator phase (0..1)
+    phase: f32,
+    /// Phase increment per frame for the current click
+    step: f32,
+    /// Current click amplitude (0 when silent)
+    level: f32,
+    /// Per-frame amplitude decay
+    decay: f32,
+}
+
+impl Metronome {
+    /// A disabled metronome at full gain.
+    pub fn new(sample_rate: u32) -> Self {
+        let frames = CLICK_MS * sample_rate as f32 / 1000.0;
+        Self {
+            enabled: false,
+            gain: 1.0,
+            beats_per_bar: DEFAULT_BEATS_PER_BAR,
+            sample_rate,
+            phase: 0.0,
+            step: 0.0,
+            level: 0.0,
+            // Decays to about -60 dB over the click
+            decay: libm::expf(-6.9 / frames.max(1.0)),
+        }
+    }
+
+    /// Turn clicking on or off; a click already sounding finishes.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Whether clicks are triggered.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Set the output gain (linear, clamped to 0..=1).
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = gain.clamp(0.0, 1.0);
+    }
+
+    /// Output gain (linear).
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// Set the bar length used for accents (0 is treated as 1).
+    pub fn set_beats_per_bar(&mut self, beats: u32) {
+        self.beats_per_bar = beats.max(1);
+    }
+
+    /// Beats per bar used for accents.
+    pub fn beats_per_bar(&self) -> u32 {
+        self.beats_per_bar
+    }
+
+    /// Start a click for `beat` (counted from a bar start), if enabled.
+    pub fn trigger(&mut self, beat: u64) {
+        if self.enabled {
+            self.click(beat);
+        }
+    }
+
+    /// Start a click for `beat` even when disabled (for count-ins).
+    pub fn click(&mut self, beat: u64) {
+        let hz = if beat.is_multiple_of(self.beats_per_bar as u64) { ACCENT_HZ } else { BEAT_HZ };
+        self.step = hz / self.sample_rate as f32;
+        self.phase = 0.0;
+        self.level = 1.0;
+    }
+
+    /// Mix the sounding click into both sides of `out`.
+    pub fn render(&mut self, out: &mut [[f32; 2]]) {
+        for frame in out {
+            if self.level < SILENCE {
+                self.level = 0.0;
+                return;
+            }
+            let s = libm::sinf(TAU * self.phase) * self.level * self.gain;
+            frame[0] += s;
+            frame[1] += s;
+            self.phase = (self.phase + self.step).fract();
+            self.level *= self.decay;
+        }
+    }
+}
+
+/// Clicks before the song starts, with the song clock held at zero.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct CountIn {
+    /// Beat being played (0-based)
+    beat: u32,
+    /// Total beats to play
+    beats: u32,
+    /// Frames into the current beat
+    frame: u32,
+    /// Beat length in frames
+    beat_frames: u32,
+}
+
+impl CountIn {
+    /// Count in `beats` beats of `beat_frames` frames each.
+    pub(crate) fn new(beats: u32, beat_frames: u32) -> Self {
+        Self { beat: 0, beats, frame: 0, beat_frames: beat_frames.max(1) }
+    }
+
+    /// Whether every beat has been played.
+    pub(crate) fn finished(&self) -> bool {
+        self.beat >= self.beats
+    }
+
+    /// Fill the front of `out` with clicks over silence until the count-in
+    /// ends or `out` is full; returns the frames written.
+    pub(crate) fn render(&mut self, metronome: &mut Metronome, out: &mut [[f32; 2]]) -> usize {
+        let mut offset = 0;
+        while !self.finished() && offset < out.len() {
+            if self.frame == 0 {
+                metronome.click(self.beat as u64);
+            }
+            let n = ((self.beat_frames - self.frame) as usize).min(out.len() - offset);
+            let span = &mut out[offset..offset + n];
+            span.fill([0.0, 0.0]);
+            metronome.render(span);
+            offset += n;
+            self.frame += n as u32;
+            if self.frame == self.beat_frames {
+                self.frame = 0;
+                self.beat += 1;
+            }
+        }
+        offset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn click(metronome: &mut Metronome, beat: u64) -> Vec<f32> {
+        let mut out = [[0.0; 2]; 64];
+        metronome.trigger(beat);
+        metronome.render(&mut out);
+        assert!(out.iter().all(|f| f[0] == f[1]));
+        out.iter().map(|f| f[0]).collect()
+    }
+
+    fn zero_crossings(buf: &[f32]) -> usize {
+        buf.windows(2).filter(|w| (w[0] < 0.0) != (w[1] < 0.0)).count()
+    }
+
+    #[test]
+    fn disabled_metronome_is_silent() {
+        let mut metronome = Metronome::new(44100);
+        assert!(click(&mut metronome, 0).iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn first_beat_of_bar_is_accented() {
+        let mut metronome = Metronome::new(44100);
+        metronome.set_enabled(true);
+        let accent = click(&mut metronome, 4);
+        let beat = click(&mut metronome, 5);
+        assert!(zero_crossings(&accent) > zero_crossings(&beat));
+    }
+
+    #[test]
+    fn gain_scales_click_and_click_dies_away() {
+        let mut metronome = Metronome::new(44100);
+        metronome.set_enabled(true);
+        let full = click(&mut metronome, 1);
+        metronome.set_gain(0.5);
+        let half = click(&mut metronome, 1);
+        assert!((half[10] - full[10] * 0.5).abs() < 1e-6);
+
+        metronome.render(&mut [[0.0; 2]; 4096]);
+        assert_eq!(metronome.level, 0.0);
+    }
+
+    #[test]
+    fn count_in_clicks_each_beat_then_stops() {
+        let mut metronome = Metronome::new(44100);
+        let mut count_in = CountIn::new(2, 1000);
+        let mut out = [[0.0; 2]; 1500];
+        assert_eq!(count_in.render(&mut metronome, &mut out), 1500);
+        assert!(out[1000][0] == 0.0 && out[1001][0] != 0.0);
+        assert!(!count_in.finished());
+        assert_eq!(count_in.render(&mut metronome, &mut out), 500);
+        assert!(count_in.finished());
+        assert_eq!(count_in.render(&mut metronome, &mut out), 0);
+    }
+}
@@ -0,0 +1,519 @@
+//! MIDI clock and MIDI time code (MTC), sent and followed.
+//!
+//! `SyncSender` turns the playback clock into 24 PPQN clock pulses (one
+//! song beat per quarter note), Start/Continue/Stop and Song Position
+//! Pointer, and 25 fps MTC quarter frames. The engine runs it on every
+//! sub-block; the messages wait, stamped with their frame offset in the
+//! block being rendered, until the host drains them.
+//!
+//! `SyncFollower` reads incoming clock or MTC. Clock pulses give a tempo
+//! and a beat position the engine locks to by nudging its tempo (in
+//! hundredths of a BPM); transport messages start, stop and relocate playback. MTC full
+//! frames and complete quarter-frame sequences relocate playback when it
+//! is more than `MTC_TOLERANCE` off. Timestamps are microseconds on any
+//! clock the host keeps monotonic.
+
+use alloc::vec::Vec;
+
+use mb_ir::{MusicalTime, SUB_BEAT_UNIT};
+
+use crate::transport::Transport;
+
+/// MIDI clock pulses per beat (quarter note).
+pub const CLOCKS_PER_BEAT: u64 = 24;
+
+/// MTC frame rate sent and assumed when following.
+pub const MTC_FPS: u32 = 25;
+
+/// Sync messages buffered between drains; later ones are dropped.
+pub const SYNC_OUTPUT_CAPACITY: usize = 256;
+
+/// How far (seconds) playback may drift from incoming MTC before it's relocated.
+pub const MTC_TOLERANCE: f64 = 0.1;
+
+/// Microseconds without clock or MTC after which a follower stops playback.
+pub const SYNC_TIMEOUT_MICROS: u64 = 500_000;
+
+const SUB_BEATS_PER_CLOCK: u64 = SUB_BEAT_UNIT as u64 / CLOCKS_PER_BEAT;
+
+/// Clocks per Song Position Pointer step (a sixteenth note).
+const CLOCKS_PER_SPP: u64 = 6;
+
+/// MTC quarter frames per second.
+const QUARTER_FRAMES_PER_SECOND: u64 = MTC_FPS as u64 * 4;
+
+/// MTC rate code for 25 fps (bits 5-6 of the hours byte).
+const MTC_RATE_25: u8 = 1;
+
+/// A MIDI clock or time code message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncMessage {
+    /// One of 24 clock pulses per beat
+    Clock,
+    /// Play from the song start
+    Start,
+    /// Play from the current position
+    Continue,
+    Stop,
+    /// Song Position Pointer, in sixteenth notes
+    SongPosition(u16),
+    /// MTC quarter frame: piece (0-7) in the high nibble, value in the low
+    QuarterFrame(u8),
+    /// MTC full frame (locate)
+    FullFrame(Timecode),
+}
+
+impl SyncMessage {
+    /// Encode into `buf`, returning the byte count.
+    pub fn encode(&self, buf: &mut [u8; 10]) -> usize {
+        let bytes: &[u8] = match *self {
+            SyncMessage::Clock => &[0xF8],
+            SyncMessage::Start => &[0xFA],
+            SyncMessage::Continue => &[0xFB],
+            SyncMessage::Stop => &[0xFC],
+            SyncMessage::SongPosition(pos) => &[0xF2, (pos & 0x7F) as u8, ((pos >> 7) & 0x7F) as u8],
+            SyncMessage::QuarterFrame(data) => &[0xF1, data & 0x7F],
+            SyncMessage::FullFrame(tc) => &[
+                0xF0, 0x7F, 0x7F, 0x01, 0x01,
+                (tc.hours & 0x1F) | (MTC_RATE_25 << 5), tc.minutes, tc.seconds, tc.frames, 0xF7,
+            ],
+        };
+        buf[..bytes.len()].copy_from_slice(bytes);
+        bytes.len()
+    }
+
+    /// Decode one complete MIDI message, if it is a sync message.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        Some(match *bytes {
+            [0xF8, ..] => SyncMessage::Clock,
+            [0xFA, ..] => SyncMessage::Start,
+            [0xFB, ..] => SyncMessage::Continue,
+            [0xFC, ..] => SyncMessage::Stop,
+            [0xF2, lsb, msb, ..] => SyncMessage::SongPosition((lsb & 0x7F) as u16 | ((msb & 0x7F) as u16) << 7),
+            [0xF1, data, ..] => SyncMessage::QuarterFrame(data & 0x7F),
+            [0xF0, 0x7F, _, 0x01, 0x01, hours, minutes, seconds, frames, ..] => SyncMessage::FullFrame(Timecode {
+                hours: hours & 0x1F,
+                minutes,
+                seconds,
+                frames,
+            }),
+            _ => return None,
+        })
+    }
+
+    /// Whether following this message may relocate playback (which allocates).
+    pub fn may_relocate(&self) -> bool {
+        !matches!(self, SyncMessage::Clock | SyncMessage::Continue | SyncMessage::Stop)
+    }
+}
+
+/// An MTC time at `MTC_FPS`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timecode {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+}
+
+impl Timecode {
+    /// The frame containing `seconds` (wrapping at 24 hours).
+    pub fn from_seconds(seconds: f64) -> Self {
+        let frames = (seconds.max(0.0) * MTC_FPS as f64) as u64;
+        let secs = frames / MTC_FPS as u64;
+        Self {
+            hours: (secs / 3600 % 24) as u8,
+            minutes: (secs / 60 % 60) as u8,
+            seconds: (secs % 60) as u8,
+            frames: (frames % MTC_FPS as u64) as u8,
+        }
+    }
+
+    /// Seconds since midnight.
+    pub fn as_seconds(&self) -> f64 {
+        let secs = self.hours as u32 * 3600 + self.minutes as u32 * 60 + self.seconds as u32;
+        secs as f64 + self.frames as f64 / MTC_FPS as f64
+    }
+
+    /// The value of quarter-frame piece `piece` (0-7).
+    fn piece(&self, piece: u8) -> u8 {
+        match piece {
+            0 => self.frames & 0x0F,
+            1 => self.frames >> 4,
+            2 => self.seconds & 0x0F,
+            3 => self.seconds >> 4,
+            4 => self.minutes & 0x0F,
+            5 => self.minutes >> 4,
+            6 => self.hours & 0x0F,
+            _ => (self.hours >> 4) & 0x01 | MTC_RATE_25 << 1,
+        }
+    }
+}
+
+/// Generates outgoing clock and MTC from the playback clock.
+#[derive(Clone, Debug)]
+pub(crate) struct SyncSender {
+    clock: bool,
+    mtc: bool,
+    sample_rate: u32,
+    /// Whether Start or Continue went out without a Stop since
+    running: bool,
+    /// Index of the next clock pulse from the song start
+    next_clock: u64,
+    /// Song seconds at the last locate
+    mtc_base: f64,
+    /// Frames played since the last locate
+    mtc_frames: u64,
+    /// Index of the next quarter frame since the last locate
+    next_quarter: u64,
+    output: Vec<(u32, SyncMessage)>,
+}
+
+impl SyncSender {
+    pub(crate) fn new(sample_rate: u32) -> Self {
+        Self {
+            clock: false,
+            mtc: false,
+            sample_rate,
+            running: false,
+            next_clock: 0,
+            mtc_base: 0.0,
+            mtc_frames: 0,
+            next_quarter: 0,
+            output: Vec::with_capacity(SYNC_OUTPUT_CAPACITY),
+        }
+    }
+
+    pub(crate) fn set_clock(&mut self, enabled: bool) {
+        self.clock = enabled;
+    }
+
+    pub(crate) fn clock(&self) -> bool {
+        self.clock
+    }
+
+    pub(crate) fn set_mtc(&mut self, enabled: bool) {
+        self.mtc = enabled;
+    }
+
+    pub(crate) fn mtc(&self) -> bool {
+        self.mtc
+    }
+
+    fn push(&mut self, offset: u32, message: SyncMessage) {
+        if self.output.len() < SYNC_OUTPUT_CAPACITY {
+            self.output.push((offset, message));
+        }
+    }
+
+    /// Playback started at `transport`'s position.
+    pub(crate) fn start(&mut self, transport: &Transport) {
+        self.running = true;
+        if !self.clock {
+            return;
+        }
+        let now = transport.now();
+        if now == MusicalTime::zero() {
+            self.push(0, SyncMessage::Start);
+        } else {
+            self.push(0, SyncMessage::SongPosition(song_position(now)));
+            self.push(0, SyncMessage::Continue);
+        }
+    }
+
+    /// Playback stopped.
+    pub(crate) fn stop(&mut self) {
+        if self.running && self.clock {
+            self.push(0, SyncMessage::Stop);
+        }
+        self.running = false;
+    }
+
+    /// Playback jumped to `transport`'s position, `seconds` into the song.
+    pub(crate) fn locate(&mut self, transport: &Transport, seconds: f64) {
+        let now = transport.now();
+        self.next_clock = now.as_sub_beats().div_ceil(SUB_BEATS_PER_CLOCK);
+        self.mtc_base = seconds;
+        self.mtc_frames = 0;
+        self.next_quarter = 0;
+        if self.clock {
+            // Song Position Pointer is only honoured while stopped
+            if self.running {
+                self.push(0, SyncMessage::Stop);
+            }
+            self.push(0, SyncMessage::SongPosition(song_position(now)));
+            if self.running {
+                self.push(0, SyncMessage::Continue);
+            }
+        }
+        if self.mtc {
+            self.push(0, SyncMessage::FullFrame(Timecode::from_seconds(seconds)));
+        }
+    }
+
+    /// Emit the pulses and quarter frames falling in the `frames` frames
+    /// from `transport`'s position, which start `offset` frames into the block.
+    pub(crate) fn render(&mut self, transport: &Transport, offset: usize, frames: usize) {
+        if self.clock {
+            loop {
+                let at = MusicalTime::from_sub_beats(self.next_clock * SUB_BEATS_PER_CLOCK);
+                let due = transport.frames_until(at);
+                if due >= frames as u64 {
+                    break;
+                }
+                self.push((offset as u64 + due) as u32, SyncMessage::Clock);
+                self.next_clock += 1;
+            }
+        }
+        if self.mtc {
+            loop {
+                let due = (self.next_quarter * self.sample_rate as u64).div_ceil(QUARTER_FRAMES_PER_SECOND);
+                let Some(due) = due.checked_sub(self.mtc_frames).filter(|&d| d < frames as u64) else { break };
+                // Each run of eight pieces carries the time of its first one
+                let piece = (self.next_quarter % 8) as u8;
+                let group = (self.next_quarter - piece as u64) as f64 / QUARTER_FRAMES_PER_SECOND as f64;
+                let value = Timecode::from_seconds(self.mtc_base + group).piece(piece);
+                self.push((offset as u64 + due) as u32, SyncMessage::QuarterFrame(piece << 4 | value));
+                self.next_quarter += 1;
+            }
+            self.mtc_frames += frames as u64;
+        }
+    }
+
+    /// Hand the buffered messages to `f` with their frame offsets and forget them.
+    pub(crate) fn drain(&mut self, mut f: impl FnMut(u32, SyncMessage)) {
+        for &(offset, message) in &self.output {
+            f(offset, message);
+        }
+        self.output.clear();
+    }
+}
+
+/// Song Position Pointer for `time` (sixteenths, saturating).
+fn song_position(time: MusicalTime) -> u16 {
+    let clocks = time.as_sub_beats() / SUB_BEATS_PER_CLOCK;
+    (clocks / CLOCKS_PER_SPP).min(0x3FFF) as u16
+}
+
+/// What a `SyncFollower` asks of playback.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SyncAction {
+    /// Play from the song start
+    Start,
+    /// Play from the current position
+    Continue,
+    Stop,
+    /// Move to a song position
+    Locate(MusicalTime),
+    /// Be `seconds` into the song and playing
+    Chase { seconds: f64 },
+    /// A clock pulse arrived
+    Pulse,
+}
+
+/// Tracks incoming clock and MTC.
+#[derive(Clone, Debug, Default)]
+pub struct SyncFollower {
+    /// Smoothed microseconds between clock pulses
+    pulse_micros: Option<f64>,
+    last_pulse: Option<u64>,
+    /// Pulses since the song start, as the source counts them
+    pulses: u64,
+    /// Whether the clock source is playing
+    running: bool,
+    /// Quarter-frame pieces received so far (bit per piece)
+    pieces_seen: u8,
+    pieces: [u8; 8],
+    /// When the last clock or time code arrived
+    last_heard: Option<u64>,
+}
+
+impl SyncFollower {
+    /// Take in `message`, received at `micros`.
+    pub fn receive(&mut self, message: SyncMessage, micros: u64) -> Option<SyncAction> {
+        self.last_heard = Some(micros);
+        match message {
+            SyncMessage::Clock => {
+                if let Some(last) = self.last_pulse {
+                    let interval = micros.saturating_sub(last) as f64;
+                    // Ignore gaps (source paused between Stop and Continue)
+                    if self.pulse_micros.is_none_or(|p| interval < p * 4.0) {
+                        self.pulse_micros = Some(match self.pulse_micros {
+                            Some(p) => p + (interval - p) / 8.0,
+                            None => interval,
+                        });
+                    }
+                }
+                self.last_pulse = Some(micros);
+                if !self.running {
+                    return None;
+                }
+                self.pulses += 1;
+                Some(SyncAction::Pulse)
+            }
+            SyncMessage::Start => {
+                self.pulses = 0;
+                self.running = true;
+                Some(SyncAction::Start)
+            }
+            SyncMessage::Continue => {
+                self.running = true;
+                Some(SyncAction::Continue)
+            }
+            SyncMessage::Stop => {
+                self.running = false;
+                Some(SyncAction::Stop)
+            }
+            SyncMessage::SongPosition(pos) => {
+                self.pulses = pos as u64 * CLOCKS_PER_SPP;
+                Some(SyncAction::Locate(self.clock_position()))
+            }
+            SyncMessage::QuarterFrame(data) => {
+                let piece = (data >> 4) & 0x07;
+                self.pieces[piece as usize] = data & 0x0F;
+                self.pieces_seen |= 1 << piece;
+                if piece != 7 || self.pieces_seen != 0xFF {
+                    return None;
+                }
+                self.pieces_seen = 0;
+                let p = &self.pieces;
+                let tc = Timecode {
+                    frames: p[0] | p[1] << 4,
+                    seconds: p[2] | p[3] << 4,
+                    minutes: p[4] | p[5] << 4,
+                    hours: p[6] | (p[7] & 0x01) << 4,
+                };
+                // The last piece arrives two frames after the time it carries
+                Some(SyncAction::Chase { seconds: tc.as_seconds() + 2.0 / MTC_FPS as f64 })
+            }
+            SyncMessage::FullFrame(tc) => {
+                self.pieces_seen = 0;
+                Some(SyncAction::Chase { seconds: tc.as_seconds() })
+            }
+        }
+    }
+
+    /// Whether the source has gone quiet for `SYNC_TIMEOUT_MICROS` by `micros`.
+    pub fn timed_out(&self, micros: u64) -> bool {
+        self.last_heard.is_some_and(|t| micros.saturating_sub(t) > SYNC_TIMEOUT_MICROS)
+    }
+
+    /// Forget the source after a timeout, so it must speak again to count.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// The source's tempo, once two pulses have arrived.
+    pub fn tempo(&self) -> Option<f64> {
+        self.pulse_micros.filter(|&p| p > 0.0).map(|p| 60_000_000.0 / (p * CLOCKS_PER_BEAT as f64))
+    }
+
+    /// The source's song position.
+    pub fn clock_position(&self) -> MusicalTime {
+        MusicalTime::from_sub_beats(self.pulses * SUB_BEATS_PER_CLOCK)
+    }
+
+    /// Whether the clock source is playing.
+    pub fn running(&self) -> bool {
+        self.running
+    }
+}
+
+/// Tempo (hundredths of a BPM) that keeps playback at `position` locked to
+/// a source at `tempo` BPM and `target`: up to 5% faster when behind,
+/// slower when ahead.
+pub(crate) fn locked_tempo(tempo: f64, target: MusicalTime, position: MusicalTime) -> u32 {
+    let error = (target.as_sub_beats() as f64 - position.as_sub_beats() as f64) / SUB_BEAT_UNIT as f64;
+    let correction = (error * 0.5).clamp(-0.05, 0.05);
+    libm::round(tempo * (1.0 + correction) * 100.0).max(100.0) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drained(sender: &mut SyncSender) -> Vec<(u32, SyncMessage)> {
+        let mut out = Vec::new();
+        sender.drain(|offset, message| out.push((offset, message)));
+        out
+    }
+
+    #[test]
+    fn messages_round_trip_through_bytes() {
+        let messages = [
+            SyncMessage::Clock,
+            SyncMessage::Start,
+            SyncMessage::Continue,
+            SyncMessage::Stop,
+            SyncMessage::SongPosition(1234),
+            SyncMessage::QuarterFrame(0x75),
+            SyncMessage::FullFrame(Timecode { hours: 1, minutes: 2, seconds: 3, frames: 24 }),
+        ];
+        for message in messages {
+            let mut buf = [0; 10];
+            let n = message.encode(&mut buf);
+            assert_eq!(SyncMessage::parse(&buf[..n]), Some(message));
+        }
+        assert_eq!(SyncMessage::parse(&[0x90, 60, 100]), None);
+    }
+
+    #[test]
+    fn sender_emits_24_pulses_per_beat_at_frame_offsets() {
+        // 125 BPM, speed 6, 4 rows per beat: 24 ticks of 882 frames per beat
+        let transport = Transport::new(12500, 6, 4, 44100);
+        let mut sender = SyncSender::new(44100);
+        sender.set_clock(true);
+        sender.start(&transport);
+        sender.render(&transport, 100, 882);
+        assert_eq!(drained(&mut sender), [(0, SyncMessage::Start), (100, SyncMessage::Clock)]);
+
+        let mut transport = transport;
+        transport.advance(882);
+        sender.render(&transport, 0, 882);
+        assert_eq!(drained(&mut sender), [(0, SyncMessage::Clock)]);
+
+        sender.stop();
+        assert_eq!(drained(&mut sender), [(0, SyncMessage::Stop)]);
+    }
+
+    #[test]
+    fn sender_emits_100_quarter_frames_per_second_with_the_time() {
+        let transport = Transport::new(12500, 6, 4, 1000);
+        let mut sender = SyncSender::new(1000);
+        sender.set_mtc(true);
+        sender.locate(&transport, 3661.0);
+        let out = drained(&mut sender);
+        assert_eq!(out, [(0, SyncMessage::FullFrame(Timecode { hours: 1, minutes: 1, seconds: 1, frames: 0 }))]);
+
+        sender.render(&transport, 0, 80);
+        let out = drained(&mut sender);
+        assert_eq!(out.len(), 8);
+        assert_eq!(out[1].0, 10);
+        let mut follower = SyncFollower::default();
+        let actions: Vec<_> = out.iter().filter_map(|&(_, m)| follower.receive(m, 0)).collect();
+        assert_eq!(actions, [SyncAction::Chase { seconds: 3661.0 + 2.0 / 25.0 }]);
+    }
+
+    #[test]
+    fn follower_measures_tempo_and_counts_position() {
+        let mut follower = SyncFollower::default();
+        assert_eq!(follower.receive(SyncMessage::SongPosition(8), 0), Some(SyncAction::Locate(MusicalTime::from_beats(2))));
+        assert_eq!(follower.receive(SyncMessage::Continue, 0), Some(SyncAction::Continue));
+        // 120 BPM: 20833 µs per pulse
+        for i in 0..48 {
+            follower.receive(SyncMessage::Clock, i * 20_833);
+        }
+        assert_eq!(follower.tempo().map(libm::round), Some(120.0));
+        assert_eq!(follower.clock_position(), MusicalTime::from_beats(4));
+        assert!(!follower.timed_out(48 * 20_833));
+        assert!(follower.timed_out(48 * 20_833 + SYNC_TIMEOUT_MICROS * 2));
+    }
+
+    #[test]
+    fn locked_tempo_catches_up_and_holds_back() {
+        let beat = MusicalTime::from_beats;
+        assert_eq!(locked_tempo(120.0, beat(4), beat(4)), 12000);
+        assert_eq!(locked_tempo(120.0, beat(5), beat(4)), 12600);
+        assert_eq!(locked_tempo(120.0, beat(4), beat(5)), 11400);
+    }
+}
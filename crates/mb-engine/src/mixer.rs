@@ -1,14 +1,56 @@
 //! Main playback engine.
 
 use alloc::boxed::Box;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
-use mb_ir::{Edit, Event, EventPayload, EventTarget, MusicalTime, NodeType, Song, SUB_BEAT_UNIT};
+use mb_ir::{AudioStream, Edit, Effect, Event, EventPayload, EventTarget, Interpolation, MusicalTime, NodeType, SampleFields, Song};
 
 use crate::clip_source::ClipSourceState;
 use crate::event_source::EventSource;
+use crate::event_queue::EventQueue;
+use crate::graph_patch::GraphPatch;
 use crate::graph_state::{self, GraphState};
+use crate::limits::{LimitHit, Limits};
 use crate::machine::Machine;
-use crate::machines;
+use crate::machines::{self, Compressor};
+use crate::machines::midi_out::MidiOutMessage;
+use crate::meters::{MeterBank, MeterTap};
+use crate::metronome::{CountIn, Metronome};
+use crate::midi_sync::{self, SyncAction, SyncFollower, SyncMessage, SyncSender, MTC_TOLERANCE};
+#[cfg(feature = "std")]
+use crate::render_pool::{Job, RenderPool};
+use crate::sample_preview::SamplePreview;
+use crate::scheduler::target_for_track_column;
+use crate::tempo_map::TempoMap;
+use crate::transport::Transport;
+
+/// Parameter glides `ParamRamp` events can run at once without allocating.
+const PARAM_RAMP_CAPACITY: usize = 64;
+
+/// MIDI Out messages held between drains; more are dropped.
+const MIDI_OUTPUT_CAPACITY: usize = 1024;
+
+/// A node parameter gliding to a target, one step per tick.
+#[derive(Clone, Copy, Debug)]
+struct ParamRamp {
+    node: u16,
+    param: u16,
+    from: i32,
+    target: i32,
+    /// Length in ticks
+    duration: u32,
+    /// Ticks done so far
+    elapsed: u32,
+}
+
+/// A fade of the output to silence, ending in a stop.
+#[derive(Clone, Copy, Debug)]
+struct FadeOut {
+    /// Frames still to fade
+    left: u32,
+    /// Length in frames
+    frames: u32,
+}
 
 /// The main playback engine.
 pub struct Engine {
@@ -20,32 +62,68 @@ pub struct Engine {
     sources: Vec<ClipSourceState>,
     /// Scratch buffer for drained events (reused each frame)
     event_buf: Vec<Event>,
+    /// Sort keys for `event_buf`, reserved alongside it
+    event_order: Vec<(MusicalTime, u32)>,
+    /// Leading events of `event_buf` due now; the rest are future events
+    /// held back because `pending_events` was full
+    event_due: usize,
     /// Manually scheduled events (via `schedule()`)
-    pending_events: Vec<Event>,
-    /// Current playback position in musical time
-    current_time: MusicalTime,
-    /// Audio sample rate (e.g., 44100)
-    sample_rate: u32,
-    /// Samples per tick at current tempo
-    samples_per_tick: u32,
-    /// Sample counter within current tick
-    sample_counter: u32,
-    /// Current tempo (BPM)
-    tempo: u8,
-    /// Current speed (ticks per row)
-    speed: u8,
-    /// Rows per beat (from song)
-    rows_per_beat: u32,
-    /// Tick counter within current beat (0..ticks_per_beat)
-    tick_in_beat: u32,
+    pending_events: EventQueue,
+    /// Playback clock (position, tempo, tick timing)
+    transport: Transport,
     /// Is playback active?
     playing: bool,
+    /// Fade to silence before stopping, if one was asked for
+    fade_out: Option<FadeOut>,
     /// Time at which the song ends (set by schedule_song)
     song_end_time: Option<MusicalTime>,
     /// Machine instances (indexed by NodeId; `Some` only for BuzzMachine nodes).
     machines: Vec<Option<Box<dyn Machine>>>,
     /// Per-node bypass flags (indexed by NodeId).
     node_bypass: Vec<bool>,
+    /// Safety limits applied to event sources by `schedule_song`
+    limits: Limits,
+    /// Set by the render watchdog when the clock stops advancing
+    stalled: bool,
+    /// Loop region `[start, end)`; playback wraps back to `start` at `end`
+    loop_range: Option<(MusicalTime, MusicalTime)>,
+    /// Limiter applied to the Master node's output
+    master_limiter: Compressor,
+    /// Whether `master_limiter` is in the signal path
+    limit_master: bool,
+    /// Parameter glides in progress (from `ParamRamp` node events)
+    param_ramps: Vec<ParamRamp>,
+    /// Live input for the current `render_block`, played by AudioInput nodes
+    input: Vec<[f32; 2]>,
+    /// Frames of `input` already rendered
+    input_pos: usize,
+    /// Beat click mixed after the master bus
+    metronome: Metronome,
+    /// Clicks still to play before the song starts
+    count_in: Option<CountIn>,
+    /// Sample auditioned after the master bus, playing or not
+    sample_preview: SamplePreview,
+    /// Resampling quality of sample-playing machines
+    interpolation: Interpolation,
+    /// Level and scope taps on node outputs, if anyone is watching
+    meters: Option<MeterTap>,
+    /// Outgoing MIDI clock and MTC
+    sync_out: SyncSender,
+    /// Incoming clock and MTC tracker, while following one
+    sync_in: Option<SyncFollower>,
+    /// Song timeline for MTC, built while MTC is sent or followed
+    sync_map: Option<TempoMap>,
+    /// Song timeline the sources convert millisecond track offsets with,
+    /// built by `schedule_song`
+    offset_tempo: Option<Arc<TempoMap>>,
+    /// Messages collected from MIDI Out nodes since the last drain
+    midi_out: Vec<MidiOutMessage>,
+    /// Tracks play launched clips instead of their sequences
+    session: bool,
+    /// Worker threads rendering independent graph branches (`None` renders
+    /// everything on the calling thread)
+    #[cfg(feature = "std")]
+    pool: Option<RenderPool>,
 }
 
 /// Find the channel settings slice for a tracker node from the song's tracks.
@@ -60,36 +138,150 @@ fn channels_for_node(song: &Song, node_id: u16) -> &[mb_ir::ChannelSettings] {
         .unwrap_or(&[])
 }
 
+/// The tracker node and sub-channel that play song channel `channel`.
+fn channel_owner(song: &Song, channel: u8) -> Option<(u16, u8)> {
+    song.tracks.iter().find_map(|t| {
+        let local = channel.checked_sub(t.base_channel)?;
+        (local < t.num_channels).then_some((t.machine_node?, local))
+    })
+}
+
 /// Instantiate machines for all BuzzMachine nodes in the graph.
-fn init_machines(song: &Song, sample_rate: u32) -> Vec<Option<Box<dyn Machine>>> {
-    song.graph.nodes.iter().map(|node| {
-        if let NodeType::Machine { is_tracker, machine_name } = &node.node_type {
-            if *is_tracker {
-                let ch_settings = channels_for_node(song, node.id);
-                let mix_gain = tracker_mix_gain(ch_settings.len() as u32);
-                let mut machine = machines::tracker::TrackerMachine::new(
-                    ch_settings,
-                    song.samples.clone(),
-                    song.instruments.clone(),
-                    song.initial_speed,
-                    song.rows_per_beat,
-                    sample_rate,
-                    mix_gain,
-                );
-                machine.init(sample_rate);
-                return Some(Box::new(machine) as Box<dyn Machine>);
-            }
-            let mut machine = machines::create_machine(machine_name)?;
-            machine.init(sample_rate);
-            // Apply initial parameter values from graph node
-            for param in &node.parameters {
-                machine.set_param(param.id, param.value);
+///
+/// Plugin nodes keep their instance from `running` (silenced), so only
+/// building an engine creates and activates plugins, never a seek.
+fn init_machines(song: &Song, sample_rate: u32, running: &mut [Option<Box<dyn Machine>>]) -> Vec<Option<Box<dyn Machine>>> {
+    song.graph.nodes.iter().enumerate()
+        .map(|(i, node)| {
+            let kept = matches!(node.node_type, NodeType::Plugin { .. })
+                .then(|| running.get_mut(i).and_then(Option::take))
+                .flatten();
+            match kept {
+                Some(mut machine) => {
+                    machine.stop();
+                    Some(machine)
+                }
+                None => init_machine(song, node, sample_rate),
             }
-            Some(machine)
-        } else {
-            None
+        })
+        .collect()
+}
+
+/// Instantiate the machine for one graph node (`None` for non-machine nodes).
+pub(crate) fn init_machine(song: &Song, node: &mb_ir::Node, sample_rate: u32) -> Option<Box<dyn Machine>> {
+    let machine = match &node.node_type {
+        NodeType::Machine { is_tracker: true, .. } => return Some(init_tracker(song, node, sample_rate)),
+        NodeType::Machine { machine_name, .. } => machines::create_machine(machine_name)?,
+        NodeType::Plugin { path, plugin_id } => machines::create_plugin(path, plugin_id),
+        NodeType::MidiOut { channel, .. } => Box::new(machines::midi_out::MidiOut::new(*channel)),
+        _ => return None,
+    };
+    let mut machine = machines::oversampled(machine, node.oversample);
+    machine.init(sample_rate);
+    // Apply initial parameter values from graph node
+    for param in &node.parameters {
+        if let Some(id) = machine_param_id(machine.as_ref(), &param.name) {
+            machine.set_param(id, param.value);
+        }
+    }
+    Some(machine)
+}
+
+/// The tracker machine playing the channels of `node`'s tracks.
+fn init_tracker(song: &Song, node: &mb_ir::Node, sample_rate: u32) -> Box<dyn Machine> {
+    let ch_settings = channels_for_node(song, node.id);
+    let mix_gain = tracker_mix_gain(ch_settings.len() as u32);
+    let mut machine = machines::tracker::TrackerMachine::new(
+        ch_settings,
+        song.samples.clone(),
+        song.instruments.clone(),
+        song.initial_speed,
+        song.rows_per_beat,
+        sample_rate,
+        mix_gain,
+    )
+    .with_frequency_mode(song.frequency_mode)
+    .with_compatibility(song.compatibility);
+    machine.init(sample_rate);
+    Box::new(machine)
+}
+
+/// The machine's parameter with `name`, matched case-insensitively.
+///
+/// Graph parameters are matched to machine parameters by name, so nodes
+/// imported with a foreign layout (e.g. a Buzz machine's PARA list) set
+/// only the parameters the native machine shares with them.
+fn machine_param_id(machine: &dyn Machine, name: &str) -> Option<u16> {
+    machine.info().params.iter()
+        .find(|p| p.name.eq_ignore_ascii_case(name))
+        .map(|p| p.id)
+}
+
+/// Tell tracker machines which channels are silenced by mute or solo.
+///
+/// A channel is silent when muted, or when any channel in the song is soloed
+/// and it is not.
+fn sync_channel_mutes(song: &Song, machines: &mut [Option<Box<dyn Machine>>]) {
+    let any_solo = song.channels.iter().any(|c| c.solo);
+    for track in &song.tracks {
+        let Some(Some(machine)) = track.machine_node.and_then(|n| machines.get_mut(n as usize)) else {
+            continue;
+        };
+        for ch in 0..track.num_channels {
+            let Some(settings) = song.channels.get(track.base_channel as usize + ch as usize) else { break };
+            machine.set_channel_muted(ch, settings.muted || (any_solo && !settings.solo));
+        }
+    }
+}
+
+/// Fresh graph buffers and machine instances for a song, keeping the
+/// plugin instances in `running`.
+fn init_runtime(
+    song: &Song,
+    sample_rate: u32,
+    running: &mut [Option<Box<dyn Machine>>],
+) -> (GraphState, Vec<Option<Box<dyn Machine>>>) {
+    let mut machines = init_machines(song, sample_rate, running);
+    sync_channel_mutes(song, &mut machines);
+    (build_graph_state(song, &machines), machines)
+}
+
+/// Graph buffers and traversal order for `song`'s graph, with channel
+/// layouts negotiated against `machines`.
+fn build_graph_state(song: &Song, machines: &[Option<Box<dyn Machine>>]) -> GraphState {
+    let mut graph_state = GraphState::from_graph(&song.graph);
+    let configs: Vec<_> = machines.iter()
+        .map(|m| m.as_ref().map(|m| m.channel_config()))
+        .collect();
+    graph_state.negotiate_channels(&configs);
+    graph_state
+}
+
+/// Shift a node-targeted event (and the wire source of a wire change) past
+/// the removal of node `removed`.
+///
+/// Returns false if the event targeted the removed node or its wires.
+fn renumber_event(event: &mut Event, removed: u16) -> bool {
+    if let EventPayload::WireChange { from, .. } = &mut event.payload {
+        if !renumber_node(from, removed) {
+            return false;
         }
-    }).collect()
+    }
+    match &mut event.target {
+        EventTarget::Node(node) | EventTarget::NodeChannel(node, _) => renumber_node(node, removed),
+        EventTarget::Channel(_) | EventTarget::Global => true,
+    }
+}
+
+/// Shift `node` past the removal of node `removed`; false if it was removed.
+fn renumber_node(node: &mut u16, removed: u16) -> bool {
+    if *node == removed {
+        return false;
+    }
+    if *node > removed {
+        *node -= 1;
+    }
+    true
 }
 
 /// Compute the right-shift needed to attenuate N inputs to prevent clipping.
@@ -120,56 +312,114 @@ fn copy_scratch_to_output(scratch: &mb_ir::AudioBuffer, output: &mut mb_ir::Audi
     dst_r[..frames].copy_from_slice(&src_r[..frames]);
 }
 
+/// Stable sort by time in O(n log n).
+///
+/// Keeps same-time events in source order (e.g. a clip-boundary cut before
+/// the next clip's first note). Sorts `(time, index)` keys in `order`, then
+/// moves each event into place by following the permutation's cycles, so
+/// nothing allocates while `order` has the capacity.
+fn sort_events_stable(events: &mut [Event], order: &mut Vec<(MusicalTime, u32)>) {
+    order.clear();
+    order.extend(events.iter().enumerate().map(|(i, e)| (e.time, i as u32)));
+    order.sort_unstable();
+    for start in 0..order.len() {
+        let mut cur = start;
+        while order[cur].1 != u32::MAX {
+            let from = order[cur].1 as usize;
+            order[cur].1 = u32::MAX;
+            if from == start {
+                break;
+            }
+            events.swap(cur, from);
+            cur = from;
+        }
+    }
+}
+
 impl Engine {
     /// Create a new engine for the given song.
     pub fn new(song: Song, sample_rate: u32) -> Self {
-        let tempo = song.initial_tempo;
-        let speed = song.initial_speed;
-        let rows_per_beat = song.rows_per_beat as u32;
+        let transport = Transport::new(song.initial_tempo, song.initial_speed, song.rows_per_beat as u32, sample_rate);
 
-        let graph_state = GraphState::from_graph(&song.graph);
-
-        // Instantiate machines for BuzzMachine nodes
-        let machines_vec = init_machines(&song, sample_rate);
+        let (graph_state, machines_vec) = init_runtime(&song, sample_rate, &mut []);
         let node_bypass = vec![false; song.graph.nodes.len()];
 
-        let mut engine = Self {
+        Self {
             song,
             graph_state,
             sources: Vec::new(),
             event_buf: Vec::new(),
-            pending_events: Vec::new(),
-            current_time: MusicalTime::zero(),
-            sample_rate,
-            samples_per_tick: 0,
-            sample_counter: 0,
-            tempo,
-            speed,
-            rows_per_beat,
-            tick_in_beat: 0,
+            event_order: Vec::new(),
+            event_due: 0,
+            pending_events: EventQueue::default(),
+            transport,
             playing: false,
+            fade_out: None,
             song_end_time: None,
             machines: machines_vec,
             node_bypass,
-        };
-
-        engine.update_samples_per_tick();
-        engine
-    }
-
-    /// Update samples_per_tick based on current tempo.
-    fn update_samples_per_tick(&mut self) {
-        self.samples_per_tick = (self.sample_rate * 5) / (self.tempo as u32 * 2);
+            limits: Limits::default(),
+            stalled: false,
+            loop_range: None,
+            master_limiter: Compressor::limiter(sample_rate),
+            limit_master: false,
+            param_ramps: Vec::with_capacity(PARAM_RAMP_CAPACITY),
+            input: Vec::with_capacity(mb_ir::BLOCK_SIZE),
+            input_pos: 0,
+            metronome: Metronome::new(sample_rate),
+            count_in: None,
+            sample_preview: SamplePreview::new(sample_rate),
+            interpolation: Interpolation::default(),
+            meters: None,
+            sync_out: SyncSender::new(sample_rate),
+            sync_in: None,
+            sync_map: None,
+            offset_tempo: None,
+            midi_out: Vec::with_capacity(MIDI_OUTPUT_CAPACITY),
+            session: false,
+            #[cfg(feature = "std")]
+            pool: None,
+        }
     }
 
     /// Start playback.
     pub fn play(&mut self) {
+        if !self.playing {
+            self.sync_out.start(&self.transport);
+        }
         self.playing = true;
+        self.fade_out = None;
     }
 
-    /// Stop playback.
+    /// Stop playback, releasing the notes MIDI Out nodes hold.
     pub fn stop(&mut self) {
+        if self.playing {
+            self.sync_out.stop();
+            self.release_midi();
+        }
         self.playing = false;
+        self.fade_out = None;
+    }
+
+    /// Fade the output to silence over `frames` frames, then stop. Stops
+    /// right away when nothing is playing; a fade in progress keeps going.
+    pub fn fade_out(&mut self, frames: u32) {
+        if !self.playing || frames == 0 {
+            self.stop();
+        } else if self.fade_out.is_none() {
+            self.fade_out = Some(FadeOut { left: frames, frames });
+        }
+    }
+
+    /// Whether a `fade_out` is still under way.
+    pub fn fading_out(&self) -> bool {
+        self.fade_out.is_some()
+    }
+
+    /// Whether playback is running (false until `play`, and after a stop,
+    /// a finished fade-out or a watchdog halt).
+    pub fn is_playing(&self) -> bool {
+        self.playing
     }
 
     /// Generate one frame of audio as [f32; 2].
@@ -186,22 +436,33 @@ impl Engine {
         buf
     }
 
-    /// Drain events from all sources + pending into `event_buf`.
+    /// Drain events from all sources + pending into `event_buf`, sorted,
+    /// with the `event_due` events due at `time` first.
     fn drain_all_sources(&mut self, time: MusicalTime) {
-        self.event_buf.clear();
-        // Include manually scheduled events at or before current time
-        let mut i = 0;
-        while i < self.pending_events.len() {
-            if self.pending_events[i].time <= time {
-                self.event_buf.push(self.pending_events.swap_remove(i));
-            } else {
-                i += 1;
-            }
+        // Drop the events dispatched last time, keeping any held back
+        self.event_buf.drain(..self.event_due);
+        let held = self.event_buf.len();
+        // Include scheduled events at or before current time (order-preserving,
+        // so same-time events keep their row order), ahead of the held ones
+        while let Some(event) = self.pending_events.pop_due(time) {
+            self.event_buf.push(event);
         }
+        self.event_buf.rotate_left(held);
         for source in &mut self.sources {
             source.drain_until(time, &self.song, &mut self.event_buf);
         }
-        self.event_buf.sort_unstable_by(|a, b| a.time.cmp(&b.time));
+        sort_events_stable(&mut self.event_buf, &mut self.event_order);
+        self.event_due = self.event_buf.partition_point(|e| e.time <= time);
+
+        // Sources emit whole rows, so delayed notes can lie in the future:
+        // schedule them so they fire on their exact frame. Whatever doesn't
+        // fit stays held here until it is due.
+        let mut moved = self.event_due;
+        while moved < self.event_buf.len() && self.has_schedule_room() {
+            let _ = self.pending_events.push(self.event_buf[moved].clone());
+            moved += 1;
+        }
+        self.event_buf.drain(self.event_due..moved);
 
         // Once all sources are exhausted, lock in the end time so is_finished()
         // triggers on the same frame (no 1-frame lag).
@@ -215,32 +476,40 @@ impl Engine {
         }
     }
 
-    /// Advance by one tick in beat-space.
-    fn advance_tick(&mut self) {
-        self.tick_in_beat += 1;
-        let tpb = self.ticks_per_beat();
-        if self.tick_in_beat >= tpb {
-            self.tick_in_beat = 0;
-            self.current_time.beat += 1;
-            self.current_time.sub_beat = 0;
-        } else {
-            self.current_time.sub_beat =
-                self.tick_in_beat * SUB_BEAT_UNIT / tpb;
-        }
-    }
-
-    /// Ticks per beat = speed * rows_per_beat.
-    fn ticks_per_beat(&self) -> u32 {
-        self.speed as u32 * self.rows_per_beat
+    /// Frames until the earliest pending or held event is due (`usize::MAX`
+    /// if none).
+    fn frames_to_next_event(&self) -> usize {
+        let held = self.event_buf.get(self.event_due).map(|e| e.time);
+        let next = match (self.pending_events.next_time(), held) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        next.map_or(usize::MAX, |time| self.transport.frames_until(time).min(usize::MAX as u64) as usize)
     }
 
     /// Process a tick (called once per tick).
     fn process_tick(&mut self) {
+        self.advance_param_ramps();
+        let tempo = self.transport.tempo();
         for machine in self.machines.iter_mut().flatten() {
+            machine.set_tempo(tempo);
             machine.tick();
         }
     }
 
+    /// Step every parameter glide by one tick, dropping finished ones.
+    fn advance_param_ramps(&mut self) {
+        for i in 0..self.param_ramps.len() {
+            let ramp = &mut self.param_ramps[i];
+            ramp.elapsed += 1;
+            let span = (ramp.target - ramp.from) as i64;
+            let value = ramp.from + (span * ramp.elapsed as i64 / ramp.duration as i64) as i32;
+            let (node, param) = (ramp.node, ramp.param);
+            self.set_node_param(node, param, value);
+        }
+        self.param_ramps.retain(|r| r.elapsed < r.duration);
+    }
+
     /// Dispatch an event to its target.
     fn dispatch_event(&mut self, event: &Event) {
         match event.target {
@@ -253,8 +522,77 @@ impl Engine {
             EventTarget::Global => {
                 self.apply_global_event(&event.payload);
             }
-            EventTarget::Node(_id) => {
-                // TODO: Route to graph node
+            EventTarget::Node(node) => self.apply_node_event(node, &event.payload),
+        }
+    }
+
+    /// Apply an event addressed to a whole graph node.
+    ///
+    /// Parameter changes and glides update both the graph and the machine;
+    /// wire changes retune the wire into the node; `Stop` silences the
+    /// machine; anything else (notes, effects) goes to the machine's first
+    /// sub-channel.
+    fn apply_node_event(&mut self, node: u16, payload: &EventPayload) {
+        match payload {
+            EventPayload::ParamChange { param, value } => {
+                self.param_ramps.retain(|r| (r.node, r.param) != (node, *param));
+                self.set_node_param(node, *param, *value);
+            }
+            EventPayload::ParamRamp { param, target, duration } => {
+                self.start_param_ramp(node, *param, *target, *duration);
+            }
+            EventPayload::WireChange { from, gain, pan } => {
+                let gain_set = gain.is_some_and(|g| self.song.graph.set_connection_gain(*from, node, g));
+                let pan_set = pan.is_some_and(|p| self.song.graph.set_connection_pan(*from, node, p));
+                if gain_set || pan_set {
+                    self.update_wire(*from, node);
+                }
+            }
+            EventPayload::Stop => {
+                self.param_ramps.retain(|r| r.node != node);
+                if let Some(Some(machine)) = self.machines.get_mut(node as usize) {
+                    machine.stop();
+                }
+            }
+            _ => {
+                if let Some(Some(machine)) = self.machines.get_mut(node as usize) {
+                    machine.apply_event(0, payload);
+                }
+            }
+        }
+    }
+
+    /// Glide a node parameter from its current value to `target` over `duration` ticks.
+    ///
+    /// Jumps straight to `target` for zero-length glides, or when all glide
+    /// slots are busy.
+    fn start_param_ramp(&mut self, node: u16, param: u16, target: i32, duration: u32) {
+        self.param_ramps.retain(|r| (r.node, r.param) != (node, param));
+        let Some(from) = self.song.graph.node(node)
+            .and_then(|n| n.parameters.iter().find(|p| p.id == param))
+            .map(|p| p.value)
+        else {
+            return;
+        };
+        if duration == 0 || self.param_ramps.len() == self.param_ramps.capacity() {
+            self.set_node_param(node, param, target);
+            return;
+        }
+        self.param_ramps.push(ParamRamp { node, param, from, target, duration, elapsed: 0 });
+    }
+
+    /// Set a graph node parameter (clamped) and pass it on to the node's machine.
+    fn set_node_param(&mut self, node: u16, param: u16, value: i32) {
+        let Some(value) = self.song.graph.set_param(node, param, value) else { return };
+        let Some(name) = self.song.graph.node(node)
+            .and_then(|n| n.parameters.iter().find(|p| p.id == param))
+            .map(|p| p.name)
+        else {
+            return;
+        };
+        if let Some(Some(machine)) = self.machines.get_mut(node as usize) {
+            if let Some(id) = machine_param_id(machine.as_ref(), &name) {
+                machine.set_param(id, value);
             }
         }
     }
@@ -263,11 +601,10 @@ impl Engine {
     fn apply_global_event(&mut self, payload: &EventPayload) {
         match payload {
             EventPayload::SetTempo(tempo) => {
-                self.tempo = (*tempo / 100) as u8;
-                self.update_samples_per_tick();
+                self.transport.set_tempo(*tempo);
             }
             EventPayload::SetSpeed(speed) => {
-                self.speed = *speed;
+                self.transport.set_speed(*speed);
                 for machine in self.machines.iter_mut().flatten() {
                     machine.set_speed(*speed);
                 }
@@ -290,27 +627,120 @@ impl Engine {
 
         self.graph_state.clear_outputs();
 
-        for i in 0..self.graph_state.topo_order.len() {
-            let node_id = self.graph_state.topo_order[i];
-            let node = match self.song.graph.node(node_id) {
-                Some(n) => n,
-                None => continue,
-            };
+        #[cfg(feature = "std")]
+        let parallel = self.pool.is_some();
+        #[cfg(not(feature = "std"))]
+        let parallel = false;
+        if parallel {
+            #[cfg(feature = "std")]
+            self.render_levels(frames);
+        } else {
+            for i in 0..self.graph_state.topo_order.len() {
+                self.render_node_block(self.graph_state.topo_order[i], frames);
+            }
+        }
+        self.graph_state.commit_feedback(frames);
+        self.input_pos += frames;
+    }
 
-            match &node.node_type {
-                NodeType::Machine { .. } => {
-                    self.render_machine_block(node_id, frames);
-                }
-                NodeType::Master => {
-                    graph_state::gather_inputs(
-                        &self.graph_state.conn_by_dest,
-                        &self.graph_state.node_outputs,
-                        node_id,
-                        &mut self.graph_state.scratch,
-                    );
-                    copy_scratch_to_output(&self.graph_state.scratch, &mut self.graph_state.node_outputs[node_id as usize], frames);
+    /// Render one graph node for N frames.
+    fn render_node_block(&mut self, node_id: u16, frames: usize) {
+        let node = match self.song.graph.node(node_id) {
+            Some(n) => n,
+            None => return,
+        };
+
+        match &node.node_type {
+            NodeType::Machine { .. } | NodeType::Plugin { .. } => {
+                self.render_machine_block(node_id, frames);
+            }
+            NodeType::Master | NodeType::FeedbackSend => {
+                graph_state::gather_inputs(
+                    &self.graph_state.conn_by_dest,
+                    &self.graph_state.node_outputs,
+                    node_id,
+                    &mut self.graph_state.scratch,
+                );
+                copy_scratch_to_output(&self.graph_state.scratch, &mut self.graph_state.node_outputs[node_id as usize], frames);
+            }
+            NodeType::FeedbackReturn { send } => {
+                let send = *send;
+                self.graph_state.read_feedback(send, node_id, frames);
+            }
+            NodeType::AudioInput => self.render_input_block(node_id, frames),
+            // Plays external gear; its output stays silent
+            NodeType::MidiOut { .. } => {}
+        }
+    }
+
+    /// Render the graph level by level, spreading the machines of each
+    /// level over the render pool and joining before the next level. The
+    /// join renders any job no worker has picked up yet, so a level never
+    /// waits on a sleeping worker.
+    #[cfg(feature = "std")]
+    fn render_levels(&mut self, frames: usize) {
+        for level in 0..self.graph_state.levels.len() {
+            let len = self.graph_state.levels[level].len();
+            for i in 0..len {
+                let node_id = self.graph_state.levels[level][i];
+                if len == 1 || !self.dispatch_machine_block(node_id, frames) {
+                    self.render_node_block(node_id, frames);
                 }
             }
+            while self.pool.as_ref().is_some_and(|p| p.in_flight() > 0) {
+                self.collect_machine_block(frames);
+            }
+        }
+    }
+
+    /// Gather a machine node's input and hand it to the render pool.
+    /// Returns false for nodes that must render on this thread.
+    #[cfg(feature = "std")]
+    fn dispatch_machine_block(&mut self, node_id: u16, frames: usize) -> bool {
+        let idx = node_id as usize;
+        let is_machine = self.song.graph.node(node_id)
+            .is_some_and(|n| matches!(n.node_type, NodeType::Machine { .. } | NodeType::Plugin { .. }));
+        let bypassed = self.node_bypass.get(idx).copied().unwrap_or(false);
+        let has_machine = self.machines.get(idx).is_some_and(Option::is_some);
+        if !is_machine || bypassed || !has_machine || self.pool.is_none() {
+            return false;
+        }
+
+        let mut buf = loop {
+            if let Some(buf) = self.pool.as_mut().and_then(RenderPool::free_buffer) {
+                break buf;
+            }
+            self.collect_machine_block(frames);
+        };
+        buf.set_frames(frames as u16);
+        graph_state::gather_inputs(&self.graph_state.conn_by_dest, &self.graph_state.node_outputs, node_id, &mut buf);
+        let config = self.graph_state.node_channels[idx];
+        if let (Some(machine), Some(pool)) = (self.machines[idx].take(), self.pool.as_mut()) {
+            pool.submit(Job { node: node_id, machine, buf, config });
+        }
+        true
+    }
+
+    /// Wait for a pooled machine to finish, then store its output and take
+    /// the machine back.
+    #[cfg(feature = "std")]
+    fn collect_machine_block(&mut self, frames: usize) {
+        let Some(pool) = self.pool.as_mut() else { return };
+        let job = pool.wait();
+        let idx = job.node as usize;
+        copy_scratch_to_output(&job.buf, &mut self.graph_state.node_outputs[idx], frames);
+        self.machines[idx] = Some(job.machine);
+        pool.recycle(job.buf);
+    }
+
+    /// Copy the next `frames` frames of live input into an AudioInput node.
+    fn render_input_block(&mut self, node_id: u16, frames: usize) {
+        let start = self.input_pos.min(self.input.len());
+        let input = &self.input[start..(start + frames).min(self.input.len())];
+        let (left, right) = self.graph_state.node_outputs[node_id as usize].channels_mut_2(0, 1);
+        for (i, &[l, r]) in input.iter().enumerate() {
+            left[i] = l;
+            right[i] = r;
         }
     }
 
@@ -328,12 +758,26 @@ impl Engine {
         );
 
         if let Some(Some(machine)) = self.machines.get_mut(node_id as usize) {
-            machine.render(&mut self.graph_state.scratch);
+            let config = self.graph_state.node_channels[node_id as usize];
+            let scratch = &mut self.graph_state.scratch;
+            graph_state::adapt_channels(scratch, graph_state::BUS_CHANNELS, config.inputs);
+            machine.render(scratch);
+            graph_state::adapt_channels(scratch, config.outputs, graph_state::BUS_CHANNELS);
         }
 
         copy_scratch_to_output(&self.graph_state.scratch, &mut self.graph_state.node_outputs[node_id as usize], frames);
     }
 
+    /// Supply the live input for the next `render_block` call.
+    ///
+    /// AudioInput nodes play these frames; anything past the end (or
+    /// beyond one block) renders as silence. Doesn't allocate.
+    pub fn set_input(&mut self, frames: &[[f32; 2]]) {
+        self.input.clear();
+        self.input.extend_from_slice(&frames[..frames.len().min(mb_ir::BLOCK_SIZE)]);
+        self.input_pos = 0;
+    }
+
     /// Render a block of audio into the output buffer.
     ///
     /// Sub-block splitting: drains events, finds tick boundaries, renders
@@ -341,11 +785,31 @@ impl Engine {
     pub fn render_block(&mut self, output: &mut [[f32; 2]]) {
         #[cfg(feature = "alloc_check")]
         {
-            assert_no_alloc::assert_no_alloc(|| self.render_block_inner(output));
+            assert_no_alloc::assert_no_alloc(|| {
+                self.render_block_inner(output);
+                self.sample_preview.render(output);
+                self.apply_fade_out(output);
+            });
         }
         #[cfg(not(feature = "alloc_check"))]
         {
             self.render_block_inner(output);
+            self.sample_preview.render(output);
+            self.apply_fade_out(output);
+        }
+    }
+
+    /// Ramp `output` down along the fade-out, stopping once it ends.
+    fn apply_fade_out(&mut self, output: &mut [[f32; 2]]) {
+        let Some(fade) = &mut self.fade_out else { return };
+        for frame in output.iter_mut() {
+            let gain = fade.left as f32 / fade.frames as f32;
+            frame[0] *= gain;
+            frame[1] *= gain;
+            fade.left = fade.left.saturating_sub(1);
+        }
+        if fade.left == 0 {
+            self.stop();
         }
     }
 
@@ -356,24 +820,56 @@ impl Engine {
         }
 
         let total_frames = output.len();
-        let mut offset = 0;
+        let mut offset = self.render_count_in(output);
 
         while offset < total_frames {
-            // Drain events at current time
-            self.drain_all_sources(self.current_time);
-            for i in 0..self.event_buf.len() {
+            // Drain events at current time, wrapping around the loop region
+            self.drain_all_sources(self.transport.now());
+            if let Some(start) = self.loop_restart() {
+                self.rewind_loop(start);
+                self.drain_all_sources(start);
+            }
+            for i in 0..self.event_due {
                 let event = self.event_buf[i].clone();
                 self.dispatch_event(&event);
             }
+            self.follow_tempo_envelope();
+            self.collect_midi(offset as u32);
 
-            // Find sub-block size: frames until next tick boundary, capped by buffer capacity
+            // Find sub-block size: frames until the next tick boundary or
+            // pending event, capped by buffer capacity
             let remaining = total_frames - offset;
-            let frames_to_tick = (self.samples_per_tick - self.sample_counter) as usize;
-            let sub_block = remaining.min(frames_to_tick).min(mb_ir::BLOCK_SIZE);
+            let frames_to_tick = self.transport.frames_to_tick() as usize;
+            let sub_block = remaining
+                .min(frames_to_tick)
+                .min(self.frames_to_next_event())
+                .min(mb_ir::BLOCK_SIZE);
+
+            // Watchdog: a clock that can't advance would spin forever; halt instead
+            if sub_block == 0 {
+                self.stalled = true;
+                self.playing = false;
+                for frame in &mut output[offset..] { *frame = [0.0, 0.0]; }
+                return;
+            }
+
+            if self.transport.on_beat() {
+                let beat = self.transport.position().beat;
+                let (_, beat_in_bar) = self.song.meter.bar_beat(beat);
+                self.metronome.set_beats_per_bar(self.song.meter.signature_at(beat).beats() as u32);
+                self.metronome.trigger(beat_in_bar as u64);
+            }
 
             // Render graph for sub-block
             self.render_graph_block(sub_block);
 
+            if self.limit_master {
+                self.master_limiter.render(&mut self.graph_state.node_outputs[0]);
+            }
+            if let Some(meters) = &mut self.meters {
+                meters.measure(&self.graph_state.node_outputs, sub_block);
+            }
+
             // Copy master output to caller's buffer
             let master = &self.graph_state.node_outputs[0];
             let left = master.channel(0);
@@ -381,31 +877,71 @@ impl Engine {
             for i in 0..sub_block {
                 output[offset + i] = [left[i], right[i]];
             }
+            self.metronome.render(&mut output[offset..offset + sub_block]);
+            self.sync_out.render(&self.transport, offset, sub_block);
 
             // Advance time by sub_block samples
-            self.sample_counter += sub_block as u32;
             offset += sub_block;
-
-            if self.sample_counter >= self.samples_per_tick {
-                self.sample_counter = 0;
-                self.advance_tick();
+            if self.transport.advance(sub_block as u32) {
                 self.process_tick();
             }
         }
     }
 
+    /// At each tick, take the tempo from the song's tempo envelope, if it
+    /// has reached the play head. Following external sync wins.
+    fn follow_tempo_envelope(&mut self) {
+        if !self.transport.on_tick() || self.sync_in.is_some() {
+            return;
+        }
+        if let Some(tempo) = self.song.tempo_envelope.tempo_at(self.transport.position()) {
+            if tempo != self.transport.tempo() {
+                self.transport.set_tempo(tempo);
+            }
+        }
+    }
+
+    /// Play the count-in's clicks into the front of `output`; returns the
+    /// frames written.
+    fn render_count_in(&mut self, output: &mut [[f32; 2]]) -> usize {
+        let Some(count_in) = &mut self.count_in else { return 0 };
+        let frames = count_in.render(&mut self.metronome, output);
+        if count_in.finished() {
+            self.count_in = None;
+        }
+        frames
+    }
+
     /// Get the current playback position.
     pub fn position(&self) -> MusicalTime {
-        self.current_time
+        self.transport.position()
+    }
+
+    /// The playback clock.
+    pub fn transport(&self) -> &Transport {
+        &self.transport
+    }
+
+    /// Playback position `frames` output frames ago (see `Transport::time_before`).
+    pub fn time_before(&self, frames: u32) -> MusicalTime {
+        self.transport.time_before(frames)
     }
 
     /// Returns true when playback has reached the song's end time.
     ///
     /// End time is determined from source exhaustion (accounts for PatternBreak/
     /// PositionJump shortening a pattern) or from an explicit `song_end_time`.
+    /// Never true while a loop region is set.
     pub fn is_finished(&self) -> bool {
+        self.count_in.is_none() && self.loop_range.is_none() && self.song_ended()
+    }
+
+    fn song_ended(&self) -> bool {
+        if self.session {
+            return false;
+        }
         if let Some(end) = self.song_end_time {
-            return self.current_time >= end;
+            return self.transport.position() >= end;
         }
         // All sources must be exhausted and we must be past all their end times
         if self.sources.is_empty() {
@@ -413,116 +949,857 @@ impl Engine {
         }
         self.sources.iter().all(|s| {
             s.end_time()
-                .is_some_and(|end| self.current_time >= end)
+                .is_some_and(|end| self.transport.position() >= end)
         })
     }
 
-    /// Schedule an event for dispatch on the next render call.
+    /// Schedule an event for dispatch once playback reaches `event.time`.
+    ///
+    /// Events at or before the current position fire on the next render
+    /// call. Never allocates: when the queue is full the event is dropped
+    /// (see `has_schedule_room`).
     pub fn schedule(&mut self, event: Event) {
-        self.pending_events.push(event);
+        let _ = self.pending_events.push(event);
+    }
+
+    /// Whether `schedule` can accept another event.
+    pub fn has_schedule_room(&self) -> bool {
+        self.pending_events.has_room()
+    }
+
+    /// Set the safety limits used by the next `schedule_song`.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
     }
 
-    /// Build lazy event sources from the song's tracks.
+    /// The safety limit that cut playback short, if any.
+    pub fn limit_hit(&self) -> Option<LimitHit> {
+        if self.stalled {
+            return Some(LimitHit::Stalled);
+        }
+        self.sources.iter().find_map(|s| s.truncated())
+    }
+
+    /// Build lazy event sources from the song's tracks, with the tempo map
+    /// they convert millisecond track offsets with. Allocates.
     pub fn schedule_song(&mut self) {
         self.song_end_time = None; // Determined lazily from source exhaustion
-        self.sources = (0..self.song.tracks.len())
-            .map(|i| ClipSourceState::new(&self.song, i))
-            .collect();
+        self.offset_tempo = Some(Arc::new(self.tempo_map().0));
+        self.sources = (0..self.song.tracks.len()).map(|i| self.new_source(i)).collect();
         // Pre-allocate event buffer to avoid allocations in the hot path.
         // Worst case: every column on every track produces ~3 events per row.
         let total_columns: usize = self.song.tracks.iter()
             .map(|t| t.num_channels as usize)
             .sum();
         self.event_buf.reserve(total_columns * 3 + 16);
+        self.event_order.reserve(self.event_buf.capacity());
     }
 
-    /// Get a reference to a machine by node ID (for testing).
-    pub fn machine(&self, node_id: u16) -> Option<&dyn Machine> {
-        self.machines.get(node_id as usize)?.as_deref()
+    /// An event source for a track: its sequence, or idle in session mode.
+    fn new_source(&self, track_idx: usize) -> ClipSourceState {
+        let mut source = if self.session {
+            ClipSourceState::idle(&self.song, track_idx, self.limits)
+        } else {
+            ClipSourceState::with_limits(&self.song, track_idx, self.limits)
+        };
+        source.set_tempo_map(self.offset_tempo.clone());
+        source
     }
 
-    /// Get a reference to the song.
-    pub fn song(&self) -> &Song {
-        &self.song
+    /// Play clips launched per track (see `launch_clip`) instead of the
+    /// sequences, from the next `schedule_song` or `seek`. Tracks start
+    /// silent, and the song never ends by itself.
+    pub fn set_session(&mut self, enabled: bool) {
+        self.session = enabled;
     }
 
-    /// Apply a batch of edits to the song data and update the event queue.
-    pub fn apply_edits(&mut self, edits: &[Edit]) {
-        for edit in edits {
-            self.apply_edit(edit);
-        }
+    /// Whether tracks play launched clips instead of their sequences.
+    pub fn session(&self) -> bool {
+        self.session
     }
 
-    fn apply_edit(&mut self, edit: &Edit) {
-        match edit {
-            Edit::SetCell { track, clip, row, column, cell } => {
-                self.apply_set_cell(*track, *clip, *row, *column, *cell);
-            }
-            Edit::SetNodeBypass { node, bypassed } => {
-                if let Some(slot) = self.node_bypass.get_mut(*node as usize) {
-                    *slot = *bypassed;
-                }
-            }
-            Edit::SetSeqEntry { .. } => {} // Sequence edits handled by Controller only
+    /// Loop `clip` on a track from `at` (quantize it to a beat or bar) in
+    /// place of its sequence, or stop the track at `at` with `None`. Works
+    /// in and out of session mode; notes still sounding are released at
+    /// `at`. Seeking drops launched clips.
+    pub fn launch_clip(&mut self, track: usize, clip: Option<u16>, at: MusicalTime) {
+        if let Some(source) = self.sources.get_mut(track) {
+            source.queue_launch(at, clip);
+            self.song_end_time = None;
         }
     }
 
-    fn apply_set_cell(
-        &mut self,
-        track_idx: u16,
-        clip_idx: u16,
-        row: u16,
-        column: u8,
-        cell: mb_ir::Cell,
-    ) {
-        // Mutate track clip data. ClipSources read lazily, so edits
-        // ahead of the cursor are picked up automatically.
-        let Some(track) = self.song.tracks.get_mut(track_idx as usize) else { return };
-        let Some(c) = track.clips.get_mut(clip_idx as usize) else { return };
-        let Some(pat) = c.pattern_mut() else { return };
-        if row >= pat.rows || column >= pat.channels { return; }
-        *pat.cell_mut(row, column) = cell;
+    /// The clip a track is looping since it was launched, if any.
+    pub fn launched_clip(&self, track: usize) -> Option<u16> {
+        self.sources.get(track).and_then(|s| s.launched())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use mb_ir::{find_tracker_node, Instrument, Sample, SampleData};
+    /// The song's tempo map and length, from a full schedule of the song.
+    ///
+    /// Allocates; not for the audio thread.
+    pub fn tempo_map(&self) -> (TempoMap, MusicalTime) {
+        let result = crate::scheduler::schedule_song_with_limits(&self.song, &self.limits, self.transport.sample_rate());
+        (result.tempo_map, result.total_time)
+    }
 
-    const SAMPLE_RATE: u32 = 44100;
+    /// Length of the song in output frames, following its tempo and speed
+    /// changes (see `tempo_map`).
+    pub fn duration_frames(&self) -> u64 {
+        let (map, end) = self.tempo_map();
+        map.frames_at(end, self.transport.sample_rate())
+    }
 
-    /// Build a 1-channel song with one sample containing `data`.
-    fn song_with_sample(data: Vec<i8>, volume: u8) -> Song {
-        let mut song = Song::with_channels("test", 1);
+    /// Seconds from the start of the song to `time` (see `tempo_map`).
+    pub fn time_at(&self, time: MusicalTime) -> f64 {
+        self.tempo_map().0.seconds_at(time)
+    }
 
-        let mut sample = Sample::new("test sample");
-        sample.data = SampleData::Mono8(data);
-        sample.default_volume = volume;
-        sample.c4_speed = 8363;
-        song.samples.push(sample);
+    /// Move playback to `time` as if the song had played up to there.
+    ///
+    /// Rebuilds the event sources and machines, replays the global events
+    /// (tempo, speed) before `time`, and parks the transport on the last
+    /// tick at or before it. Channels start silent: notes triggered before
+    /// `time` are not resumed. Manually scheduled events are dropped.
+    /// Plugin nodes keep their instances, silenced, so seeking never
+    /// creates or activates plugins. Allocates; call it before handing the
+    /// engine to the audio thread.
+    pub fn seek(&mut self, time: MusicalTime) {
+        self.release_midi();
+        let sample_rate = self.transport.sample_rate();
+        (self.graph_state, self.machines) = init_runtime(&self.song, sample_rate, &mut self.machines);
+        self.set_interpolation(self.interpolation);
+        self.fit_render_pool();
+        self.transport = Transport::new(
+            self.song.initial_tempo,
+            self.song.initial_speed,
+            self.song.rows_per_beat as u32,
+            sample_rate,
+        );
+        self.stalled = false;
+        self.pending_events.clear();
+        self.event_buf.clear();
+        self.event_due = 0;
+        self.param_ramps.clear();
+        self.schedule_song();
 
-        let mut inst = Instrument::new("test inst");
-        inst.set_single_sample(0);
-        song.instruments.push(inst);
+        let mut events = Vec::new();
+        for source in &mut self.sources {
+            source.drain_until(time, &self.song, &mut events);
+        }
+        sort_events_stable(&mut events, &mut Vec::new());
+        for event in events {
+            if event.time >= time {
+                self.pending_events.push_or_grow(event);
+            } else if event.target == EventTarget::Global {
+                self.apply_global_event(&event.payload);
+            } else if let EventTarget::Node(node) = event.target {
+                // Automation before `time` lands on its final value
+                match event.payload {
+                    EventPayload::ParamChange { param, value } => self.set_node_param(node, param, value),
+                    EventPayload::ParamRamp { param, target, .. } => self.set_node_param(node, param, target),
+                    EventPayload::WireChange { .. } => self.apply_node_event(node, &event.payload),
+                    _ => {}
+                }
+            }
+        }
+        self.transport.locate(time);
+        if self.sync_map.is_some() {
+            self.sync_map = Some(self.tempo_map().0);
+        }
+        self.sync_out.locate(&self.transport, self.song_seconds(time));
+    }
 
-        // Add a Track linking the Tracker node to channels (required by init_machines)
-        let tracker_id = find_tracker_node(&song.graph);
-        song.tracks.push(mb_ir::Track::new(tracker_id, 0, 1));
+    /// Loop playback over `[start, end)`, or play straight through with `None`.
+    ///
+    /// When the play head reaches `end` (or the song ends inside the region)
+    /// it jumps back to `start` and replays the events from there. Notes
+    /// still sounding carry over the jump. Empty ranges clear the loop.
+    pub fn set_loop(&mut self, range: Option<(MusicalTime, MusicalTime)>) {
+        self.loop_range = range.filter(|(start, end)| start < end);
+    }
 
-        song
+    /// The current loop region, if any.
+    pub fn loop_range(&self) -> Option<(MusicalTime, MusicalTime)> {
+        self.loop_range
     }
 
-    /// Find the Tracker node ID in a song's graph.
-    fn tracker_node(song: &Song) -> u16 {
-        find_tracker_node(&song.graph).expect("no Tracker node")
+    /// Loop start to jump to, if the play head has run off the loop region.
+    fn loop_restart(&self) -> Option<MusicalTime> {
+        let (start, end) = self.loop_range?;
+        (self.transport.position() >= end || self.song_ended()).then_some(start)
     }
 
-    /// Schedule a NoteOn at the engine's current time via NodeChannel to Tracker.
-    fn schedule_note(engine: &mut Engine, song: &Song, note: u8, instrument: u8) {
-        let node_id = tracker_node(song);
-        engine.schedule(Event::new(
-            engine.position(),
+    /// Jump back to `start` without allocating (safe on the audio thread).
+    ///
+    /// Like `seek`, but keeps the machines running and fast-forwards the
+    /// sources one row at a time through the reserved `event_buf`.
+    /// Launched clips restart from `start`, and tracks idle in session mode
+    /// stay idle with any launch still waiting.
+    fn rewind_loop(&mut self, start: MusicalTime) {
+        self.song_end_time = None;
+        self.pending_events.clear();
+        self.apply_global_event(&EventPayload::SetTempo(self.song.initial_tempo));
+        self.apply_global_event(&EventPayload::SetSpeed(self.song.initial_speed));
+        for i in 0..self.sources.len() {
+            if self.sources[i].launched().is_some() {
+                self.sources[i].restart_launched(start);
+                continue;
+            }
+            if self.session {
+                continue;
+            }
+            self.sources[i].seek(MusicalTime::zero(), &self.song);
+            while let Some(row_time) = self.sources[i].peek_time().filter(|&t| t < start) {
+                self.event_buf.clear();
+                self.sources[i].drain_until(row_time, &self.song, &mut self.event_buf);
+                for j in 0..self.event_buf.len() {
+                    let event = self.event_buf[j].clone();
+                    if event.time >= start {
+                        let _ = self.pending_events.push(event);
+                    } else if event.target == EventTarget::Global {
+                        self.apply_global_event(&event.payload);
+                    }
+                }
+            }
+        }
+        self.event_buf.clear();
+        self.event_due = 0;
+        self.transport.locate(start);
+        self.sync_out.locate(&self.transport, self.song_seconds(start));
+    }
+
+    /// Pick a track's event source up at the play head after a sequence or
+    /// groove edit (see `ClipSourceState::resync`), dropping the events it
+    /// scheduled from before the edit. Rows before the play head are not
+    /// replayed. Note-offs still fire, so notes already started don't hang.
+    fn resync_track(&mut self, track_idx: usize) {
+        if track_idx >= self.sources.len() {
+            return; // Not scheduled yet
+        }
+        if self.session || self.sources[track_idx].launched().is_some() {
+            return; // Not following the sequence
+        }
+        self.purge_track_events(track_idx);
+        self.sources[track_idx].resync(self.transport.now(), &self.song);
+        self.sources[track_idx].set_speed(self.transport.speed());
+        self.song_end_time = None;
+    }
+
+    /// Drop the events waiting for a track's columns and wires, except
+    /// note-offs.
+    fn purge_track_events(&mut self, track_idx: usize) {
+        let track = &self.song.tracks[track_idx];
+        let keep = |event: &Event| {
+            let from_track = match event.target {
+                EventTarget::Node(node) => {
+                    track.machine_node == Some(node) && matches!(event.payload, EventPayload::WireChange { .. })
+                }
+                target => (0..track.num_channels).any(|col| target_for_track_column(track, col) == target),
+            };
+            !from_track || matches!(event.payload, EventPayload::NoteOff { .. })
+        };
+        self.pending_events.retain_mut(|event| keep(event));
+        self.event_buf.drain(..self.event_due);
+        self.event_due = 0;
+        self.event_buf.retain(keep);
+    }
+
+    /// Send MIDI clock (24 PPQN), Start/Continue/Stop and Song Position
+    /// Pointer as playback runs (see `drain_sync_output`).
+    pub fn set_midi_clock_out(&mut self, enabled: bool) {
+        self.sync_out.set_clock(enabled);
+    }
+
+    /// Whether MIDI clock is sent.
+    pub fn midi_clock_out(&self) -> bool {
+        self.sync_out.clock()
+    }
+
+    /// Send 25 fps MIDI time code as playback runs, with a full frame on
+    /// every jump (see `drain_sync_output`). Enabling allocates.
+    pub fn set_mtc_out(&mut self, enabled: bool) {
+        self.sync_out.set_mtc(enabled);
+        self.update_sync_map();
+    }
+
+    /// Whether MIDI time code is sent.
+    pub fn mtc_out(&self) -> bool {
+        self.sync_out.mtc()
+    }
+
+    /// Hand the messages MIDI Out nodes produced since the last drain to
+    /// `f`, with their frame offsets into the block rendered last (0 for
+    /// those from `stop`, `seek` and edits). Bypassed nodes send nothing.
+    /// Call after each `render_block`.
+    pub fn drain_midi_output(&mut self, mut f: impl FnMut(MidiOutMessage)) {
+        self.collect_midi(0);
+        for &message in &self.midi_out {
+            f(message);
+        }
+        self.midi_out.clear();
+    }
+
+    /// Queue the messages machines produced, `offset` frames into the block.
+    fn collect_midi(&mut self, offset: u32) {
+        let queue = &mut self.midi_out;
+        for (node, machine) in self.machines.iter_mut().enumerate() {
+            let Some(machine) = machine else { continue };
+            let bypassed = self.node_bypass.get(node).copied().unwrap_or(false);
+            machine.drain_midi(&mut |bytes| {
+                if !bypassed && queue.len() < queue.capacity() {
+                    queue.push(MidiOutMessage { node: node as u16, offset, bytes });
+                }
+            });
+        }
+    }
+
+    /// Release the notes every MIDI Out node holds.
+    fn release_midi(&mut self) {
+        for node in 0..self.song.graph.nodes.len() {
+            self.release_midi_node(node as u16);
+        }
+    }
+
+    /// Release the notes a MIDI Out node holds, queueing the note-offs.
+    fn release_midi_node(&mut self, node: u16) {
+        let is_midi = self.song.graph.node(node).is_some_and(|n| matches!(n.node_type, NodeType::MidiOut { .. }));
+        if let (true, Some(Some(machine))) = (is_midi, self.machines.get_mut(node as usize)) {
+            machine.stop();
+            self.collect_midi(0);
+        }
+    }
+
+    /// Hand the clock and MTC messages produced since the last drain to
+    /// `f`, with their frame offsets into the block rendered last (0 for
+    /// those from `play`, `stop` and `seek`). Call after each `render_block`.
+    pub fn drain_sync_output(&mut self, f: impl FnMut(u32, SyncMessage)) {
+        self.sync_out.drain(f);
+    }
+
+    /// Follow incoming MIDI clock and MTC fed to `receive_sync`: playback
+    /// pauses until the source starts, then tracks its position and tempo.
+    /// Enabling allocates.
+    pub fn set_sync_follow(&mut self, enabled: bool) {
+        self.sync_in = enabled.then(SyncFollower::default);
+        self.update_sync_map();
+        if enabled {
+            self.stop();
+        }
+    }
+
+    /// Whether playback follows incoming clock and MTC.
+    pub fn sync_follow(&self) -> bool {
+        self.sync_in.is_some()
+    }
+
+    /// Act on a clock or MTC message received at `micros` (a monotonic
+    /// microsecond clock). Ignored unless following. Messages that may
+    /// relocate (see `SyncMessage::may_relocate`) can allocate.
+    ///
+    /// Clock pulses set the tempo to the source's, nudged by up to 5% (in
+    /// hundredths of a BPM) to close any gap to the source's beat position.
+    pub fn receive_sync(&mut self, message: SyncMessage, micros: u64) {
+        let Some(follower) = &mut self.sync_in else { return };
+        let Some(action) = follower.receive(message, micros) else { return };
+        match action {
+            SyncAction::Start => {
+                self.seek(MusicalTime::zero());
+                self.play();
+            }
+            SyncAction::Continue => self.play(),
+            SyncAction::Stop => self.stop(),
+            SyncAction::Locate(time) => self.seek(time),
+            SyncAction::Chase { seconds } => {
+                let drift = libm::fabs(self.song_seconds(self.transport.now()) - seconds);
+                if !self.playing || drift > MTC_TOLERANCE {
+                    let time = self.sync_map.as_ref().map_or(MusicalTime::zero(), |m| m.time_at_seconds(seconds));
+                    self.seek(time);
+                    self.play();
+                }
+            }
+            SyncAction::Pulse => {
+                let Some(tempo) = follower.tempo() else { return };
+                let tempo = midi_sync::locked_tempo(tempo, follower.clock_position(), self.transport.now());
+                if tempo != self.transport.tempo() {
+                    self.transport.set_tempo(tempo);
+                }
+            }
+        }
+    }
+
+    /// Pause playback if the followed source has gone quiet by `micros`.
+    pub fn poll_sync(&mut self, micros: u64) {
+        if let Some(follower) = self.sync_in.as_mut().filter(|f| f.timed_out(micros)) {
+            follower.reset();
+            self.stop();
+        }
+    }
+
+    /// Build or drop the MTC timeline as sending and following need it.
+    fn update_sync_map(&mut self) {
+        let needed = self.sync_out.mtc() || self.sync_in.is_some();
+        self.sync_map = match self.sync_map.take() {
+            Some(map) if needed => Some(map),
+            _ if needed => Some(self.tempo_map().0),
+            _ => None,
+        };
+    }
+
+    /// Seconds from the song start to `time`, if MTC is in use.
+    fn song_seconds(&self, time: MusicalTime) -> f64 {
+        self.sync_map.as_ref().map_or(0.0, |m| m.seconds_at(time))
+    }
+
+    /// Put a brick-wall limiter on the master bus, or take it off.
+    ///
+    /// Keeps many summed channels from clipping the output.
+    pub fn set_master_limiter(&mut self, enabled: bool) {
+        if enabled && !self.limit_master {
+            self.master_limiter.stop();
+        }
+        self.limit_master = enabled;
+    }
+
+    /// Whether the master bus limiter is on.
+    pub fn master_limiter(&self) -> bool {
+        self.limit_master
+    }
+
+    /// Turn the beat click on or off.
+    pub fn set_metronome(&mut self, enabled: bool) {
+        self.metronome.set_enabled(enabled);
+    }
+
+    /// Set the beat click's gain in percent (0..=100).
+    pub fn set_metronome_gain(&mut self, percent: u8) {
+        self.metronome.set_gain(percent as f32 / 100.0);
+    }
+
+    /// Click `bars` bars at the current tempo and time signature before
+    /// the song moves on, whether or not the metronome is on (0 cancels a
+    /// count-in).
+    pub fn count_in(&mut self, bars: u8) {
+        let signature = self.song.meter.signature_at(self.transport.position().beat);
+        self.metronome.set_beats_per_bar(signature.beats() as u32);
+        let beats = bars as u32 * self.metronome.beats_per_bar();
+        let beat_frames = self.transport.samples_per_tick() * self.transport.ticks_per_beat();
+        self.count_in = (beats > 0).then(|| CountIn::new(beats, beat_frames));
+    }
+
+    /// Whether a count-in is playing.
+    pub fn counting_in(&self) -> bool {
+        self.count_in.is_some()
+    }
+
+    /// The beat click generator.
+    pub fn metronome(&self) -> &Metronome {
+        &self.metronome
+    }
+
+    /// Set how sample-playing machines resample (linear by default).
+    ///
+    /// Cubic and sinc cost more per voice; offline renders can afford
+    /// them where the realtime path may not.
+    pub fn set_interpolation(&mut self, quality: Interpolation) {
+        self.interpolation = quality;
+        for machine in self.machines.iter_mut().flatten() {
+            machine.set_interpolation(quality);
+        }
+    }
+
+    /// Resampling quality of sample-playing machines.
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    /// Publish every node's levels and scope trace to `bank` after each
+    /// rendered block (`None` stops metering). Allocates; call it before
+    /// handing the engine to the audio thread.
+    pub fn set_meters(&mut self, bank: Option<Arc<MeterBank>>) {
+        self.meters = bank.map(MeterTap::new);
+    }
+
+    /// Render independent graph branches on `threads` worker threads
+    /// (0 renders everything on the calling thread).
+    ///
+    /// Machines at the same depth of the graph render at once and join
+    /// before the nodes they feed; the output is the same as rendering on
+    /// one thread. Spawns threads and allocates; call it before handing
+    /// the engine to the audio thread.
+    #[cfg(feature = "std")]
+    pub fn set_render_threads(&mut self, threads: usize) {
+        if threads == self.render_threads() {
+            return;
+        }
+        self.pool = (threads > 0).then(|| RenderPool::new(threads, self.graph_state.scratch.channels()));
+    }
+
+    /// Worker threads rendering graph branches (0 = none).
+    #[cfg(feature = "std")]
+    pub fn render_threads(&self) -> usize {
+        self.pool.as_ref().map_or(0, RenderPool::threads)
+    }
+
+    /// Get a reference to a machine by node ID (for testing).
+    pub fn machine(&self, node_id: u16) -> Option<&dyn Machine> {
+        self.machines.get(node_id as usize)?.as_deref()
+    }
+
+    /// Get a reference to the song.
+    pub fn song(&self) -> &Song {
+        &self.song
+    }
+
+    /// Apply a batch of edits to the song data and update the event queue.
+    pub fn apply_edits(&mut self, edits: &[Edit]) {
+        for edit in edits {
+            self.apply_edit(edit);
+        }
+    }
+
+    fn apply_edit(&mut self, edit: &Edit) {
+        match edit {
+            Edit::SetCell { track, clip, row, column, cell } => {
+                self.apply_set_cell(*track, *clip, *row, *column, *cell);
+            }
+            Edit::SetNodeBypass { node, bypassed } => {
+                if *bypassed {
+                    self.release_midi_node(*node);
+                }
+                if let Some(slot) = self.node_bypass.get_mut(*node as usize) {
+                    *slot = *bypassed;
+                }
+            }
+            Edit::SetSongGroove { .. } | Edit::SetTrackGroove { .. } | Edit::SetTrackOffset { .. } => {
+                if self.song.apply_groove_edit(edit) {
+                    // Only tracks without their own groove follow the song's
+                    for track in 0..self.song.tracks.len() {
+                        let affected = match edit {
+                            Edit::SetTrackGroove { track: t, .. } | Edit::SetTrackOffset { track: t, .. } => {
+                                *t as usize == track
+                            }
+                            _ => self.song.tracks[track].groove.is_none(),
+                        };
+                        if affected {
+                            self.resync_track(track);
+                        }
+                    }
+                }
+            }
+            Edit::SetSeqEntry { .. }
+            | Edit::InsertSeqEntry { .. }
+            | Edit::RemoveSeqEntry { .. }
+            | Edit::MoveSeqEntry { .. }
+            | Edit::DuplicateSeqEntry { .. }
+            | Edit::SetSeqClip { .. }
+            | Edit::SetSequence { .. } => {
+                if let Some(track) = self.song.apply_sequence_edit(edit) {
+                    self.resync_track(track);
+                }
+            }
+            Edit::ResizeClip { .. }
+            | Edit::InsertRows { .. }
+            | Edit::DeleteRows { .. }
+            | Edit::CloneClip { .. }
+            | Edit::AddClip { .. }
+            | Edit::RemoveLastClip { .. }
+            | Edit::SetClip { .. } => {
+                let applied = self.song.apply_clip_edit(edit);
+                // Resizing moves the track's sequence entries
+                if let (Some(track), Edit::ResizeClip { .. }) = (applied, edit) {
+                    self.resync_track(track);
+                }
+            }
+            Edit::AddTrack { .. } | Edit::AddMidiTrack { .. } => self.add_track(edit),
+            Edit::RemoveTrack { track } => self.remove_track(*track as usize),
+            Edit::MoveTrack { from, to } => {
+                let (from, to) = (*from as usize, *to as usize);
+                if self.song.move_track(from, to) && from.max(to) < self.sources.len() {
+                    let source = self.sources.remove(from);
+                    self.sources.insert(to, source);
+                    self.renumber_sources();
+                }
+            }
+            Edit::RenameTrack { .. } => {
+                self.song.apply_track_edit(edit);
+            }
+            Edit::SetLoop { range } => self.set_loop(*range),
+            Edit::SetMasterLimiter { enabled } => self.set_master_limiter(*enabled),
+            Edit::SetMetronome { enabled } => self.set_metronome(*enabled),
+            Edit::SetMetronomeGain { percent } => self.set_metronome_gain(*percent),
+            Edit::CountIn { bars } => self.count_in(*bars),
+            Edit::PreviewSample { sample, looped } => match sample {
+                Some((data, fields)) => self.sample_preview.start(data.clone(), *fields, *looped),
+                None => self.sample_preview.stop(),
+            },
+            Edit::LaunchClip { track, clip, at } => self.launch_clip(*track as usize, *clip, *at),
+            Edit::SetTempo { tempo } if *tempo > 0 => {
+                self.song.initial_tempo = *tempo;
+                self.apply_global_event(&EventPayload::SetTempo(*tempo));
+            }
+            Edit::SetSpeed { speed } if *speed > 0 => {
+                self.song.initial_speed = *speed;
+                self.apply_global_event(&EventPayload::SetSpeed(*speed));
+            }
+            Edit::SetTempo { .. } | Edit::SetSpeed { .. } => {}
+            Edit::SetTimeSignature { beat, signature } => self.song.meter.set(*beat, *signature),
+            Edit::SetTempoEnvelope { envelope } => {
+                self.song.tempo_envelope = envelope.clone();
+                if self.sync_map.is_some() {
+                    self.sync_map = Some(self.tempo_map().0);
+                }
+            }
+            Edit::SetChannelPan { channel, pan } => self.apply_set_channel_pan(*channel, *pan),
+            Edit::SetNodeParam { node, param, value } => self.set_node_param(*node, *param, *value),
+            Edit::SetSampleFields { sample, fields } => self.apply_set_sample_fields(*sample, fields),
+            Edit::SetSample { .. } | Edit::AddSample { .. } | Edit::RemoveLastSample => {
+                if self.song.apply_sample_edit(edit) {
+                    for machine in self.machines.iter_mut().flatten() {
+                        machine.update_samples(&self.song.samples, &self.song.instruments);
+                    }
+                }
+            }
+            // Markers only help find your way around; playback ignores them
+            Edit::AddMarker { .. } | Edit::RemoveMarker { .. } | Edit::RenameMarker { .. } => {}
+            Edit::AddNode { .. } | Edit::RemoveNode { .. } | Edit::Connect { .. } | Edit::Disconnect { .. } => {
+                if let Some(mut patch) = GraphPatch::new(&self.song, edit, self.transport.sample_rate()) {
+                    self.apply_graph_patch(&mut patch);
+                }
+            }
+            Edit::SetConnectionGain { from, to, gain } => {
+                if self.song.graph.set_connection_gain(*from, *to, *gain) {
+                    self.update_wire(*from, *to);
+                }
+            }
+            Edit::SetConnectionPan { from, to, pan } => {
+                if self.song.graph.set_connection_pan(*from, *to, *pan) {
+                    self.update_wire(*from, *to);
+                }
+            }
+            Edit::SetChannelMute { channel, muted } => {
+                if let Some(settings) = self.song.channels.get_mut(*channel as usize) {
+                    settings.muted = *muted;
+                    sync_channel_mutes(&self.song, &mut self.machines);
+                }
+            }
+            Edit::SetChannelSolo { channel, solo } => {
+                if let Some(settings) = self.song.channels.get_mut(*channel as usize) {
+                    settings.solo = *solo;
+                    sync_channel_mutes(&self.song, &mut self.machines);
+                }
+            }
+        }
+    }
+
+    /// Swap in a graph edit prepared with `GraphPatch::new`: an added node
+    /// starts silent until wired in, a removed one takes its machine and
+    /// wire lanes with it and the nodes after it are renumbered.
+    ///
+    /// Doesn't allocate or free: what the patch replaces is left in it, to
+    /// be dropped by whoever made it. Restarts feedback delay lines empty.
+    pub fn apply_graph_patch(&mut self, patch: &mut GraphPatch) {
+        match patch.edit {
+            Edit::AddNode { .. } => {
+                let mut machine = patch.machine.take();
+                if let Some(m) = &mut machine {
+                    m.set_interpolation(self.interpolation);
+                }
+                patch.machines.append(&mut self.machines);
+                patch.machines.push(machine);
+                core::mem::swap(&mut self.machines, &mut patch.machines);
+                patch.node_bypass.append(&mut self.node_bypass);
+                patch.node_bypass.push(false);
+                core::mem::swap(&mut self.node_bypass, &mut patch.node_bypass);
+            }
+            Edit::RemoveNode { node } => {
+                patch.machine = self.release_node(node);
+                self.song.detach_node(node, &mut patch.wire_lanes);
+            }
+            _ => {}
+        }
+        core::mem::swap(&mut self.song.graph, &mut patch.graph);
+
+        // The running scratch buffer already fits every machine but the new one
+        let state = &mut patch.graph_state;
+        for (config, machine) in state.node_channels.iter_mut().zip(&self.machines) {
+            if let Some(machine) = machine {
+                *config = machine.channel_config();
+            }
+        }
+        if self.graph_state.scratch.channels() > state.scratch.channels() {
+            core::mem::swap(&mut self.graph_state.scratch, &mut state.scratch);
+        }
+        core::mem::swap(&mut self.graph_state, state);
+        #[cfg(feature = "std")]
+        if let Some(pool) = &mut self.pool {
+            pool.widen(&mut patch.pool_buffers);
+        }
+    }
+
+    /// Drop the machine and state of a node the song no longer has.
+    fn forget_node(&mut self, node: u16) {
+        self.release_node(node);
+        self.rebuild_graph();
+    }
+
+    /// Take out the machine of a node on its way out of the graph and
+    /// renumber the engine state that refers to the nodes after it.
+    fn release_node(&mut self, node: u16) -> Option<Box<dyn Machine>> {
+        self.release_midi_node(node);
+        let machine = self.machines.remove(node as usize);
+        self.node_bypass.remove(node as usize);
+        self.param_ramps.retain(|r| r.node != node);
+        for ramp in &mut self.param_ramps {
+            if ramp.node > node {
+                ramp.node -= 1;
+            }
+        }
+        self.pending_events.retain_mut(|e| renumber_event(e, node));
+        machine
+    }
+
+    /// Add a track on a new Tracker or MIDI Out node and start its
+    /// machine; while playing, its sequence joins in at the play head.
+    fn add_track(&mut self, edit: &Edit) {
+        if !self.song.apply_track_edit(edit) {
+            return;
+        }
+        let track = self.song.tracks.len() - 1;
+        let node = &self.song.graph.nodes[self.song.graph.nodes.len() - 1];
+        let mut machine = init_machine(&self.song, node, self.transport.sample_rate());
+        if let Some(m) = &mut machine {
+            m.set_interpolation(self.interpolation);
+        }
+        self.machines.push(machine);
+        self.node_bypass.push(false);
+        self.rebuild_graph();
+        if self.sources.len() == track {
+            self.sources.push(self.new_source(track));
+            self.resync_track(track);
+        }
+    }
+
+    /// Remove a track with its exclusive node (see `Song::remove_track`).
+    fn remove_track(&mut self, track: usize) {
+        let node = self.song.exclusive_node(track);
+        if !self.song.remove_track(track) {
+            return;
+        }
+        if track < self.sources.len() {
+            self.sources.remove(track);
+            self.renumber_sources();
+        }
+        match node {
+            Some(node) => self.forget_node(node),
+            None => self.rebuild_graph(),
+        }
+    }
+
+    /// Match each source's track index to its place after tracks moved.
+    fn renumber_sources(&mut self) {
+        for (i, source) in self.sources.iter_mut().enumerate() {
+            source.set_track_idx(i);
+        }
+    }
+
+    /// Refresh a wire's mix gains after its gain or pan changed.
+    fn update_wire(&mut self, from: u16, to: u16) {
+        if let Some(conn) = self.song.graph.connection(from, to) {
+            self.graph_state.update_wire(conn);
+        }
+    }
+
+    /// Recompute the graph buffers and traversal order after the graph's
+    /// shape changed, keeping the running machines.
+    ///
+    /// Allocates, and restarts feedback delay lines empty.
+    fn rebuild_graph(&mut self) {
+        self.graph_state = build_graph_state(&self.song, &self.machines);
+        self.fit_render_pool();
+    }
+
+    /// Size the render pool's buffers for the current graph.
+    fn fit_render_pool(&mut self) {
+        #[cfg(feature = "std")]
+        if let Some(pool) = &mut self.pool {
+            pool.fit_channels(self.graph_state.scratch.channels());
+        }
+    }
+
+    fn apply_set_channel_pan(&mut self, channel: u8, pan: i8) {
+        let Some(settings) = self.song.channels.get_mut(channel as usize) else { return };
+        settings.initial_pan = pan.clamp(-64, 64);
+        let effect = Effect::SetPan((settings.initial_pan as i16 + 128) as u8);
+        let Some((node, ch)) = channel_owner(&self.song, channel) else { return };
+        if let Some(Some(machine)) = self.machines.get_mut(node as usize) {
+            machine.apply_event(ch, &EventPayload::Effect(effect));
+        }
+    }
+
+    fn apply_set_sample_fields(&mut self, index: u8, fields: &SampleFields) {
+        let Some(sample) = self.song.samples.get_mut(index as usize) else { return };
+        fields.apply_to(sample);
+        for machine in self.machines.iter_mut().flatten() {
+            machine.update_sample(index, fields);
+        }
+    }
+
+    fn apply_set_cell(
+        &mut self,
+        track_idx: u16,
+        clip_idx: u16,
+        row: u16,
+        column: u8,
+        cell: mb_ir::Cell,
+    ) {
+        // Mutate track clip data. ClipSources read lazily, so edits
+        // ahead of the cursor are picked up automatically.
+        let Some(track) = self.song.tracks.get_mut(track_idx as usize) else { return };
+        let Some(c) = track.clips.get_mut(clip_idx as usize) else { return };
+        let Some(pat) = c.pattern_mut() else { return };
+        if row >= pat.rows || column >= pat.channels { return; }
+        *pat.cell_mut(row, column) = cell;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mb_ir::{find_tracker_node, Instrument, Sample, SampleData};
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    /// Build a 1-channel song with one sample containing `data`.
+    fn song_with_sample(data: Vec<i8>, volume: u8) -> Song {
+        let mut song = Song::with_channels("test", 1);
+
+        let mut sample = Sample::new("test sample");
+        sample.set_data(SampleData::Mono8(data));
+        sample.default_volume = volume;
+        sample.c4_speed = 8363;
+        song.samples.push(sample);
+
+        let mut inst = Instrument::new("test inst");
+        inst.set_single_sample(0);
+        song.instruments.push(inst);
+
+        // Add a Track linking the Tracker node to channels (required by init_machines)
+        let tracker_id = find_tracker_node(&song.graph);
+        song.tracks.push(mb_ir::Track::new(tracker_id, 0, 1));
+
+        song
+    }
+
+    /// Find the Tracker node ID in a song's graph.
+    fn tracker_node(song: &Song) -> u16 {
+        find_tracker_node(&song.graph).expect("no Tracker node")
+    }
+
+    /// Schedule a NoteOn at the engine's current time via NodeChannel to Tracker.
+    fn schedule_note(engine: &mut Engine, song: &Song, note: u8, instrument: u8) {
+        let node_id = tracker_node(song);
+        engine.schedule(Event::new(
+            engine.position(),
             EventTarget::NodeChannel(node_id, 0),
             EventPayload::NoteOn { note, velocity: 64, instrument },
         ));
@@ -533,6 +1810,28 @@ mod tests {
         frame[0] != 0.0 || frame[1] != 0.0
     }
 
+    #[test]
+    fn sort_keeps_same_time_events_in_order() {
+        let mut events: Vec<Event> = (0..40u8)
+            .map(|i| {
+                let time = MusicalTime::from_beats((i as u64 * 7) % 5);
+                Event::new(time, EventTarget::Global, EventPayload::SetSpeed(i))
+            })
+            .collect();
+        let mut order = Vec::with_capacity(events.len());
+        let capacity = order.capacity();
+        sort_events_stable(&mut events, &mut order);
+
+        let keys: Vec<_> = events.iter().map(|e| match e.payload {
+            EventPayload::SetSpeed(i) => (e.time, i),
+            _ => unreachable!(),
+        }).collect();
+        let mut expected = keys.clone();
+        expected.sort();
+        assert_eq!(keys, expected);
+        assert_eq!(order.capacity(), capacity);
+    }
+
     #[test]
     fn silent_when_not_playing() {
         let song = song_with_sample(vec![127; 100], 64);
@@ -560,13 +1859,84 @@ mod tests {
         assert!(is_nonsilent(&frame), "Expected non-silent output");
     }
 
+    #[test]
+    fn interpolation_reaches_channels_and_survives_seek() {
+        let song = song_with_sample(vec![127; 1000], 64);
+        let node = tracker_node(&song);
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.set_interpolation(Interpolation::Sinc);
+        engine.seek(MusicalTime::zero());
+        let state = engine.machine(node).unwrap().channel_state(0).unwrap();
+        assert_eq!(state.interpolation, Interpolation::Sinc);
+    }
+
+    #[test]
+    fn interpolation_quality_changes_output() {
+        let data: Vec<i8> = (0..1000).map(|i| if i % 2 == 0 { 100 } else { -100 }).collect();
+        let song = song_with_sample(data, 64);
+        let render = |quality| {
+            let mut engine = Engine::new(song.clone(), SAMPLE_RATE);
+            engine.set_interpolation(quality);
+            engine.play();
+            schedule_note(&mut engine, &song, 48, 1);
+            engine.render_frames(256)
+        };
+        assert_ne!(render(Interpolation::Nearest), render(Interpolation::Linear));
+        assert_ne!(render(Interpolation::Cubic), render(Interpolation::Linear));
+    }
+
+    #[test]
+    fn stalled_clock_halts_playback() {
+        // A zero sample rate leaves no frames per tick, so time can't advance
+        let mut engine = Engine::new(song_with_sample(vec![127; 100], 64), 0);
+        engine.play();
+        let frames = engine.render_frames(64);
+        assert!(frames.iter().all(|f| *f == [0.0, 0.0]));
+        assert_eq!(engine.limit_hit(), Some(LimitHit::Stalled));
+    }
+
+    #[test]
+    fn future_event_fires_when_playhead_reaches_it() {
+        let song = song_with_sample(vec![127; 100_000], 64);
+        let node_id = tracker_node(&song);
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.play();
+        assert!(engine.has_schedule_room());
+        engine.schedule(Event::new(
+            MusicalTime::from_beats(1),
+            EventTarget::NodeChannel(node_id, 0),
+            EventPayload::NoteOn { note: 48, velocity: 64, instrument: 1 },
+        ));
+        // 882 frames/tick, 24 ticks/beat
+        let before = engine.render_frames(882 * 24);
+        assert!(before.iter().all(|f| !is_nonsilent(f)));
+        assert!(is_nonsilent(&engine.render_frame()));
+    }
+
+    #[test]
+    fn mid_tick_event_fires_on_its_frame() {
+        let song = song_with_sample(vec![127; 100_000], 64);
+        let node_id = tracker_node(&song);
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.play();
+        let time = MusicalTime::from_sub_beats(engine.transport().frames_to_sub_beats(100) + 1);
+        engine.schedule(Event::new(
+            time,
+            EventTarget::NodeChannel(node_id, 0),
+            EventPayload::NoteOn { note: 48, velocity: 64, instrument: 1 },
+        ));
+        let frames = engine.render_frames(882);
+        assert!(frames[..101].iter().all(|f| !is_nonsilent(f)));
+        assert!(is_nonsilent(&frames[101]));
+    }
+
     #[test]
     fn set_tempo_changes_samples_per_tick() {
         let song = song_with_sample(vec![127; 100], 64);
         let mut engine = Engine::new(song, SAMPLE_RATE);
         engine.play();
 
-        let spt_before = engine.samples_per_tick;
+        let spt_before = engine.transport().samples_per_tick();
         assert_eq!(spt_before, 882);
 
         engine.schedule(Event::new(
@@ -576,30 +1946,409 @@ mod tests {
         ));
         engine.render_frame();
 
-        assert_eq!(engine.samples_per_tick, 735);
+        assert_eq!(engine.transport().samples_per_tick(), 735);
+    }
+
+    #[test]
+    fn time_before_rewinds_by_frames() {
+        let song = song_with_sample(vec![127; 100], 64);
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.play();
+        // 125 BPM, speed 6, rpb 4: 882 frames/tick, 24 ticks/beat
+        let frames_per_beat = 882 * 24;
+        engine.render_frames(frames_per_beat * 2);
+        assert_eq!(engine.position(), MusicalTime::from_beats(2));
+        assert_eq!(engine.time_before(frames_per_beat as u32), MusicalTime::from_beats(1));
+        assert_eq!(engine.time_before(0), MusicalTime::from_beats(2));
+    }
+
+    #[test]
+    fn time_before_saturates_at_song_start() {
+        let song = song_with_sample(vec![127; 100], 64);
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.play();
+        engine.render_frames(100);
+        assert_eq!(engine.time_before(10_000), MusicalTime::zero());
+    }
+
+    #[test]
+    fn time_before_includes_progress_within_tick() {
+        let song = song_with_sample(vec![127; 100], 64);
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.play();
+        engine.render_frames(441);
+        assert_eq!(engine.position(), MusicalTime::zero());
+        assert!(engine.time_before(0) > MusicalTime::zero());
+    }
+
+    #[test]
+    fn zero_volume_sample_produces_silence() {
+        let song = song_with_sample(vec![127; 1000], 0);
+        let mut engine = Engine::new(song.clone(), SAMPLE_RATE);
+        engine.play();
+        schedule_note(&mut engine, &song, 48, 1);
+        let frame = engine.render_frame();
+        assert_eq!(frame, [0.0, 0.0]);
+    }
+
+    // === Edit dispatch tests ===
+
+    use mb_ir::{build_tracks, Cell, Edit, Note, OrderEntry, Pattern};
+
+    /// Build a 1-channel song with one sample and a scheduled pattern.
+    fn song_with_pattern(data: Vec<i8>) -> Song {
+        let mut song = song_with_sample(data, 64);
+        let patterns = vec![Pattern::new(4, 1)];
+        let order = vec![OrderEntry::Pattern(0)];
+        build_tracks(&mut song, &patterns, &order);
+        song
+    }
+
+    #[test]
+    fn note_delay_starts_note_on_its_tick() {
+        let mut song = song_with_sample(vec![127; 100_000], 64);
+        let mut pat = Pattern::new(4, 1);
+        *pat.cell_mut(0, 0) = Cell {
+            note: Note::On(48),
+            instrument: 1,
+            effect: mb_ir::Effect::NoteDelay(3),
+            ..Cell::empty()
+        };
+        build_tracks(&mut song, &[pat], &[OrderEntry::Pattern(0)]);
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.schedule_song();
+        engine.play();
+        let frames = engine.render_frames(882 * 4);
+        assert!(frames[..882 * 3].iter().all(|f| !is_nonsilent(f)));
+        assert!(is_nonsilent(&frames[882 * 3]));
+    }
+
+    #[test]
+    fn note_delay_waits_for_its_tick_when_the_queue_is_full() {
+        let mut song = song_with_sample(vec![127; 100_000], 64);
+        let mut pat = Pattern::new(4, 1);
+        *pat.cell_mut(0, 0) = Cell {
+            note: Note::On(48),
+            instrument: 1,
+            effect: mb_ir::Effect::NoteDelay(3),
+            ..Cell::empty()
+        };
+        build_tracks(&mut song, &[pat], &[OrderEntry::Pattern(0)]);
+        let node_id = tracker_node(&song);
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.schedule_song();
+        while engine.has_schedule_room() {
+            engine.schedule(Event::new(
+                MusicalTime::from_beats(50),
+                EventTarget::NodeChannel(node_id, 0),
+                EventPayload::NoteOff { note: 48 },
+            ));
+        }
+        engine.play();
+        let frames = engine.render_frames(882 * 4);
+        assert!(frames[..882 * 3].iter().all(|f| !is_nonsilent(f)));
+        assert!(is_nonsilent(&frames[882 * 3]));
+    }
+
+    #[test]
+    fn inserted_seq_entry_plays_live() {
+        let mut song = song_with_sample(vec![127; 100_000], 64);
+        let mut note = Pattern::new(4, 1);
+        *note.cell_mut(0, 0) = Cell { note: Note::On(48), instrument: 1, ..Cell::empty() };
+        build_tracks(&mut song, &[Pattern::new(4, 1), note], &[OrderEntry::Pattern(0)]);
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.schedule_song();
+        engine.play();
+        let row = 6 * 882;
+        assert!(engine.render_frames(row * 2).iter().all(|f| !is_nonsilent(f)));
+        engine.apply_edits(&[Edit::InsertSeqEntry { track: 0, index: 1, clip: 1 }]);
+        let frames = engine.render_frames(row * 3);
+        assert!(frames[..row * 2].iter().all(|f| !is_nonsilent(f)));
+        assert!(frames[row * 2..].iter().any(is_nonsilent), "new entry plays after the first");
+        assert!(!engine.is_finished());
+    }
+
+    #[test]
+    fn sequence_edit_drops_events_waiting_from_the_old_sequence() {
+        let mut song = song_with_sample(vec![127; 100_000], 64);
+        let mut pat = Pattern::new(4, 1);
+        *pat.cell_mut(0, 0) = Cell {
+            note: Note::On(48),
+            instrument: 1,
+            effect: mb_ir::Effect::NoteDelay(3),
+            ..Cell::empty()
+        };
+        build_tracks(&mut song, &[pat, Pattern::new(4, 1)], &[OrderEntry::Pattern(0)]);
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.schedule_song();
+        engine.play();
+        engine.render_frames(882);
+        engine.apply_edits(&[Edit::SetSeqClip { track: 0, index: 0, clip: 1 }]);
+        assert!(engine.render_frames(882 * 24).iter().all(|f| !is_nonsilent(f)));
+    }
+
+    #[test]
+    fn resized_clip_plays_to_its_new_length() {
+        let mut song = song_with_sample(vec![127; 100_000], 64);
+        let mut note = Pattern::new(4, 1);
+        *note.cell_mut(0, 0) = Cell { note: Note::On(48), instrument: 1, ..Cell::empty() };
+        build_tracks(&mut song, &[Pattern::new(4, 1), note], &[OrderEntry::Pattern(0), OrderEntry::Pattern(1)]);
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.schedule_song();
+        engine.play();
+        let row = 6 * 882;
+        engine.render_frames(row);
+        engine.apply_edits(&[Edit::ResizeClip { track: 0, clip: 0, rows: 2 }]);
+        let frames = engine.render_frames(row * 2);
+        assert!(frames[..row].iter().all(|f| !is_nonsilent(f)));
+        assert!(frames[row..].iter().any(is_nonsilent), "next entry pulled in");
+    }
+
+    #[test]
+    fn track_edits_keep_machines_and_sources_in_step() {
+        let mut song = song_with_sample(vec![127; 100_000], 64);
+        let mut note = Pattern::new(4, 1);
+        *note.cell_mut(0, 0) = Cell { note: Note::On(48), instrument: 1, ..Cell::empty() };
+        build_tracks(&mut song, &[note], &[OrderEntry::Pattern(0)]);
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.schedule_song();
+        engine.play();
+        let nodes = engine.song().graph.nodes.len();
+        let name = mb_ir::track_name("Pad");
+        engine.apply_edits(&[Edit::AddTrack { channels: 2, name }, Edit::MoveTrack { from: 1, to: 0 }]);
+        assert_eq!((engine.machines.len(), engine.sources.len()), (nodes + 1, 2));
+        assert_eq!(engine.song().tracks[0].name.as_str(), "Pad");
+        assert!(engine.render_frames(1024).iter().any(is_nonsilent), "moved track still plays");
+
+        engine.apply_edits(&[Edit::RemoveTrack { track: 1 }]);
+        assert_eq!((engine.machines.len(), engine.node_bypass.len()), (nodes, nodes));
+        assert_eq!(engine.sources.len(), 1);
+        assert_eq!(engine.song().channels.len(), 2);
+        let frames = engine.render_frames(2048);
+        assert!(!loud(&frames[1024..]), "note went with its track");
+    }
+
+    #[test]
+    fn duration_follows_speed_changes() {
+        let mut song = song_with_sample(vec![64; 100], 64);
+        let mut pat = Pattern::new(8, 1);
+        pat.cell_mut(4, 0).effect = mb_ir::Effect::SetSpeed(12);
+        build_tracks(&mut song, &[pat], &[OrderEntry::Pattern(0)]);
+        let engine = Engine::new(song, SAMPLE_RATE);
+        // Rows 0-3 at speed 6, rows 4-7 at speed 12
+        assert_eq!(engine.duration_frames(), (4 * 6 + 4 * 12) * 882);
+        assert!((engine.time_at(MusicalTime::from_beats(1)) - 0.48).abs() < 1e-9);
+        assert!((engine.time_at(MusicalTime::from_beats(2)) - 0.48 * 3.0).abs() < 1e-9);
+    }
+
+    /// A song with one `machine` node driven by a 4-row, `columns`-wide pattern.
+    fn note_machine_song(machine: &str, columns: u8, notes: &[(u16, u8, u8)]) -> Song {
+        let mut song = Song::new("notes");
+        let node = song.graph.add_node(NodeType::Machine {
+            machine_name: alloc::string::String::from(machine),
+            is_tracker: false,
+        });
+        song.graph.connect(node, 0);
+        let mut pat = Pattern::new(4, columns);
+        for &(row, col, note) in notes {
+            pat.cell_mut(row, col).note = Note::On(note);
+        }
+        let mut track = mb_ir::Track::new(Some(node), 0, columns);
+        track.clips.push(mb_ir::Clip::Pattern(pat));
+        track.sequence.push(mb_ir::SeqEntry {
+            start: MusicalTime::zero(), clip_idx: 0, length: 4,
+            termination: mb_ir::SeqTermination::Natural,
+            boundary: mb_ir::ClipBoundary::Ring,
+        });
+        song.tracks.push(track);
+        song
+    }
+
+    #[test]
+    fn meters_follow_node_outputs() {
+        let song = song_with_sample(vec![64; 10000], 64);
+        let node = tracker_node(&song);
+        let bank = Arc::new(MeterBank::new(4));
+        let mut engine = Engine::new(song.clone(), SAMPLE_RATE);
+        engine.set_meters(Some(bank.clone()));
+        engine.play();
+        engine.render_frames(64);
+        assert_eq!(bank.take_reading(0).unwrap().peak, [0.0, 0.0]);
+
+        schedule_note(&mut engine, &song, 48, 1);
+        let frames = engine.render_frames(2048);
+        let master = bank.take_reading(0).unwrap();
+        assert_eq!(master.peak[0], frames.iter().fold(0.0f32, |m, f| m.max(f[0].abs())));
+        assert!(master.rms[0] > 0.0);
+        assert_eq!(bank.take_reading(node).unwrap().peak, master.peak);
+        let mut scope = [0.0f32; 4];
+        bank.scope(0, &mut scope);
+        let last = frames[frames.len() - 1];
+        assert_eq!(scope[3], (last[0] + last[1]) * 0.5);
+    }
+
+    #[test]
+    fn render_threads_match_single_threaded_output() {
+        let mut song = note_machine_song("Synth", 1, &[(0, 0, 57), (2, 0, 64)]);
+        let synth = song.graph.nodes.len() as u16 - 1;
+        for name in ["Delay", "Resonant Filter", "Compressor", "Delay"] {
+            let node = song.graph.add_node(NodeType::Machine {
+                machine_name: alloc::string::String::from(name),
+                is_tracker: false,
+            });
+            song.graph.connect(synth, node);
+            song.graph.connect(node, 0);
+        }
+        let render = |threads| {
+            let mut engine = Engine::new(song.clone(), SAMPLE_RATE);
+            engine.set_render_threads(threads);
+            assert_eq!(engine.render_threads(), threads);
+            engine.schedule_song();
+            engine.play();
+            engine.render_frames(882 * 16)
+        };
+        let single = render(0);
+        assert!(single.iter().any(is_nonsilent));
+        assert_eq!(render(3), single);
+    }
+
+    #[test]
+    fn synth_track_plays_pattern_notes() {
+        let song = note_machine_song("Synth", 2, &[(2, 0, 57), (2, 1, 64)]);
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.schedule_song();
+        engine.play();
+        // Row 2 starts 12 ticks of 882 frames in
+        let frames = engine.render_frames(882 * 14);
+        assert!(frames[..882 * 12].iter().all(|f| !is_nonsilent(f)));
+        assert!(frames[882 * 12..].iter().any(|f| f[0].abs() > 0.1));
+    }
+
+    #[test]
+    fn kick_xp_node_plays_triggers_and_ignores_foreign_params() {
+        let mut song = note_machine_song("Jeskola Kick XP", 1, &[(1, 0, 48)]);
+        // A Buzz-layout parameter the native kick doesn't share
+        song.graph.node_mut(1).unwrap().parameters.push(mb_ir::Parameter::new(0, "G0", 0, 255, 0));
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.schedule_song();
+        engine.play();
+        let frames = engine.render_frames(882 * 8);
+        assert!(frames[..882 * 6].iter().all(|f| !is_nonsilent(f)));
+        assert!(frames[882 * 6..].iter().any(|f| f[0].abs() > 0.5));
+    }
+
+    #[test]
+    fn midi_track_sends_notes_at_their_frame_and_releases_on_stop() {
+        let mut song = Song::new("midi");
+        let track = song.add_midi_track(1, "Juno", "USB MIDI", 3).unwrap();
+        let mut pat = Pattern::new(4, 1);
+        pat.cell_mut(2, 0).note = Note::On(48);
+        song.tracks[track].clips.push(mb_ir::Clip::Pattern(pat));
+        song.tracks[track].sequence.push(mb_ir::SeqEntry {
+            start: MusicalTime::zero(), clip_idx: 0, length: 4,
+            termination: mb_ir::SeqTermination::Natural,
+            boundary: mb_ir::ClipBoundary::Ring,
+        });
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.schedule_song();
+        engine.play();
+
+        // Row 2 starts 12 ticks of 882 frames in
+        let mut sent = Vec::new();
+        assert!(engine.render_frames(882 * 12 - 100).iter().all(|f| !is_nonsilent(f)));
+        engine.drain_midi_output(|m| sent.push(m));
+        assert!(sent.is_empty());
+        engine.render_frames(200);
+        engine.drain_midi_output(|m| sent.push(m));
+        assert_eq!(sent, [MidiOutMessage { node: 1, offset: 100, bytes: [0x93, 60, 100] }]);
+
+        sent.clear();
+        engine.stop();
+        engine.drain_midi_output(|m| sent.push(m));
+        assert_eq!(sent, [MidiOutMessage { node: 1, offset: 0, bytes: [0x83, 60, 0] }]);
     }
 
     #[test]
-    fn zero_volume_sample_produces_silence() {
-        let song = song_with_sample(vec![127; 1000], 0);
-        let mut engine = Engine::new(song.clone(), SAMPLE_RATE);
+    fn track_groove_edit_delays_its_rows() {
+        let mut song = Song::new("groove");
+        let track = song.add_midi_track(1, "Juno", "USB MIDI", 0).unwrap();
+        let mut pat = Pattern::new(4, 1);
+        pat.cell_mut(1, 0).note = Note::On(48);
+        song.tracks[track].clips.push(mb_ir::Clip::Pattern(pat));
+        song.tracks[track].sequence.push(mb_ir::SeqEntry {
+            start: MusicalTime::zero(), clip_idx: 0, length: 4,
+            termination: mb_ir::SeqTermination::Natural,
+            boundary: mb_ir::ClipBoundary::Ring,
+        });
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.schedule_song();
+        engine.apply_edits(&[Edit::SetTrackGroove { track: 0, groove: Some(mb_ir::Groove::Ticks(vec![0, 3])) }]);
         engine.play();
-        schedule_note(&mut engine, &song, 48, 1);
-        let frame = engine.render_frame();
-        assert_eq!(frame, [0.0, 0.0]);
+
+        // Row 1 is due 6 ticks of 882 frames in; the groove holds it 3 more
+        let mut sent = Vec::new();
+        engine.render_frames(882 * 9 - 100);
+        engine.drain_midi_output(|m| sent.push(m));
+        assert!(sent.is_empty());
+        engine.render_frames(200);
+        engine.drain_midi_output(|m| sent.push(m));
+        assert_eq!(sent, [MidiOutMessage { node: 1, offset: 100, bytes: [0x90, 60, 100] }]);
     }
 
-    // === Edit dispatch tests ===
+    #[test]
+    fn negative_track_offset_plays_rows_early() {
+        let mut song = Song::new("offset");
+        let track = song.add_midi_track(1, "Juno", "USB MIDI", 0).unwrap();
+        let mut pat = Pattern::new(4, 1);
+        pat.cell_mut(1, 0).note = Note::On(48);
+        song.tracks[track].clips.push(mb_ir::Clip::Pattern(pat));
+        song.tracks[track].sequence.push(mb_ir::SeqEntry {
+            start: MusicalTime::zero(), clip_idx: 0, length: 4,
+            termination: mb_ir::SeqTermination::Natural,
+            boundary: mb_ir::ClipBoundary::Ring,
+        });
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.schedule_song();
+        engine.apply_edits(&[Edit::SetTrackOffset { track: 0, offset: mb_ir::TrackOffset::Ticks(-2) }]);
+        engine.play();
 
-    use mb_ir::{build_tracks, Cell, Edit, Note, OrderEntry, Pattern};
+        // Row 1 is due 6 ticks of 882 frames in; the offset pulls it 2 earlier
+        let mut sent = Vec::new();
+        engine.render_frames(882 * 4 - 100);
+        engine.drain_midi_output(|m| sent.push(m));
+        assert!(sent.is_empty());
+        engine.render_frames(200);
+        engine.drain_midi_output(|m| sent.push(m));
+        assert_eq!(sent, [MidiOutMessage { node: 1, offset: 100, bytes: [0x90, 60, 100] }]);
+    }
 
-    /// Build a 1-channel song with one sample and a scheduled pattern.
-    fn song_with_pattern(data: Vec<i8>) -> Song {
-        let mut song = song_with_sample(data, 64);
-        let patterns = vec![Pattern::new(4, 1)];
-        let order = vec![OrderEntry::Pattern(0)];
-        build_tracks(&mut song, &patterns, &order);
-        song
+    #[test]
+    fn millisecond_offsets_follow_tempo_changes() {
+        let mut song = Song::new("offset");
+        let track = song.add_midi_track(1, "Juno", "USB MIDI", 0).unwrap();
+        let mut pat = Pattern::new(8, 1);
+        pat.cell_mut(0, 0).effect = Effect::SetTempo(250);
+        pat.cell_mut(4, 0).note = Note::On(48);
+        song.tracks[track].clips.push(mb_ir::Clip::Pattern(pat));
+        song.tracks[track].sequence.push(mb_ir::SeqEntry {
+            start: MusicalTime::zero(), clip_idx: 0, length: 8,
+            termination: mb_ir::SeqTermination::Natural,
+            boundary: mb_ir::ClipBoundary::Ring,
+        });
+        song.tracks[track].offset = mb_ir::TrackOffset::Millis(-60);
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.schedule_song();
+        engine.play();
+
+        // At 250 BPM a tick lasts 10 ms (441 frames): row 4 is due 24 ticks
+        // in, and 60 ms pulls it 6 ticks earlier
+        let mut sent = Vec::new();
+        engine.render_frames(441 * 18 - 100);
+        engine.drain_midi_output(|m| sent.push(m));
+        assert!(sent.is_empty());
+        engine.render_frames(200);
+        engine.drain_midi_output(|m| sent.push(m));
+        assert_eq!(sent, [MidiOutMessage { node: 1, offset: 100, bytes: [0x90, 60, 100] }]);
     }
 
     #[test]
@@ -663,6 +2412,364 @@ mod tests {
         engine.apply_edits(&[Edit::SetCell { track: 0, clip: 0, row: 999, column: 0, cell }]);
     }
 
+    // === Node event tests ===
+
+    /// Amiga filter node and its cutoff parameter in `Song::with_channels` songs.
+    const FILTER_NODE: u16 = 1;
+
+    fn filter_cutoff(engine: &Engine) -> i32 {
+        engine.song().graph.node(FILTER_NODE).unwrap().parameters[0].value
+    }
+
+    fn node_event(engine: &mut Engine, node: u16, payload: EventPayload) {
+        engine.schedule(Event::new(engine.position(), EventTarget::Node(node), payload));
+    }
+
+    #[test]
+    fn node_param_change_event_sets_parameter() {
+        let mut engine = Engine::new(song_with_sample(vec![0; 100], 64), SAMPLE_RATE);
+        engine.play();
+        node_event(&mut engine, FILTER_NODE, EventPayload::ParamChange { param: 0, value: 2000 });
+        engine.render_frame();
+        assert_eq!(filter_cutoff(&engine), 2000);
+        node_event(&mut engine, FILTER_NODE, EventPayload::ParamChange { param: 0, value: 99_999 });
+        engine.render_frame();
+        assert_eq!(filter_cutoff(&engine), 22050, "clamped to range");
+    }
+
+    #[test]
+    fn node_param_ramp_glides_per_tick() {
+        let mut engine = Engine::new(song_with_sample(vec![0; 100], 64), SAMPLE_RATE);
+        engine.play();
+        let start = filter_cutoff(&engine);
+        node_event(&mut engine, FILTER_NODE, EventPayload::ParamRamp { param: 0, target: start + 4000, duration: 4 });
+        engine.render_frames(882 * 2);
+        assert_eq!(filter_cutoff(&engine), start + 2000);
+        engine.render_frames(882 * 3);
+        assert_eq!(filter_cutoff(&engine), start + 4000);
+    }
+
+    #[test]
+    fn node_param_change_cancels_ramp() {
+        let mut engine = Engine::new(song_with_sample(vec![0; 100], 64), SAMPLE_RATE);
+        engine.play();
+        node_event(&mut engine, FILTER_NODE, EventPayload::ParamRamp { param: 0, target: 20000, duration: 8 });
+        engine.render_frames(882);
+        node_event(&mut engine, FILTER_NODE, EventPayload::ParamChange { param: 0, value: 3000 });
+        engine.render_frames(882 * 4);
+        assert_eq!(filter_cutoff(&engine), 3000);
+    }
+
+    #[test]
+    fn node_note_events_play_and_stop_generator() {
+        let song = note_machine_song("Synth", 1, &[]);
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.play();
+        node_event(&mut engine, 1, EventPayload::NoteOn { note: 57, velocity: 64, instrument: 0 });
+        assert!(engine.render_frames(1024).iter().any(|f| f[0].abs() > 0.1));
+        node_event(&mut engine, 1, EventPayload::Stop);
+        assert!(engine.render_frames(64).iter().all(|f| !is_nonsilent(f)));
+    }
+
+    // === Graph edit tests ===
+
+    fn synth_node() -> NodeType {
+        NodeType::Machine { machine_name: alloc::string::String::from("Synth"), is_tracker: false }
+    }
+
+    fn note_on() -> EventPayload {
+        EventPayload::NoteOn { note: 57, velocity: 64, instrument: 0 }
+    }
+
+    fn loud(frames: &[[f32; 2]]) -> bool {
+        frames.iter().any(|f| f[0].abs() > 0.1)
+    }
+
+    #[test]
+    fn added_node_plays_once_wired_in() {
+        let mut engine = Engine::new(Song::new("graph"), SAMPLE_RATE);
+        engine.play();
+        engine.apply_edits(&[Edit::AddNode { node_type: synth_node() }]);
+        assert_eq!(engine.machine(1).map(|m| m.info().name), Some("Synth"));
+        assert_eq!(engine.song().graph.nodes[1].parameters.len(), 12, "default parameters");
+        node_event(&mut engine, 1, note_on());
+        assert!(!loud(&engine.render_frames(1024)), "unwired node is silent");
+        engine.apply_edits(&[Edit::Connect { from: 1, to: 0, gain: 0 }]);
+        assert!(loud(&engine.render_frames(1024)));
+        engine.apply_edits(&[Edit::SetConnectionGain { from: 1, to: 0, gain: -100 }]);
+        assert!(!loud(&engine.render_frames(1024)), "zero gain");
+        engine.apply_edits(&[
+            Edit::SetConnectionGain { from: 1, to: 0, gain: 0 },
+            Edit::Disconnect { from: 1, to: 0 },
+        ]);
+        assert!(!loud(&engine.render_frames(1024)));
+    }
+
+    #[test]
+    fn wire_pan_edit_moves_node_live() {
+        let mut engine = Engine::new(Song::new("graph"), SAMPLE_RATE);
+        engine.play();
+        engine.apply_edits(&[
+            Edit::AddNode { node_type: synth_node() },
+            Edit::Connect { from: 1, to: 0, gain: 0 },
+            Edit::SetConnectionPan { from: 1, to: 0, pan: -64 },
+        ]);
+        node_event(&mut engine, 1, note_on());
+        let frames = engine.render_frames(1024);
+        assert!(loud(&frames));
+        assert!(frames.iter().all(|f| f[1] == 0.0), "right side silent");
+    }
+
+    #[test]
+    fn audio_input_node_plays_live_input_once() {
+        let mut engine = Engine::new(Song::new("input"), SAMPLE_RATE);
+        engine.play();
+        engine.apply_edits(&[
+            Edit::AddNode { node_type: NodeType::AudioInput },
+            Edit::Connect { from: 1, to: 0, gain: 0 },
+        ]);
+        let mut out = [[0.0; 2]; 8];
+        engine.set_input(&[[0.5, -0.25]; 6]);
+        engine.render_block(&mut out);
+        assert_eq!(out[..6], [[0.5, -0.25]; 6]);
+        assert_eq!(out[6..], [[0.0; 2]; 2], "short input pads with silence");
+        engine.render_block(&mut out);
+        assert_eq!(out, [[0.0; 2]; 8], "input is consumed");
+    }
+
+    #[test]
+    fn cyclic_connect_is_ignored() {
+        let mut engine = Engine::new(Song::new("graph"), SAMPLE_RATE);
+        engine.apply_edits(&[
+            Edit::AddNode { node_type: synth_node() },
+            Edit::AddNode { node_type: synth_node() },
+            Edit::Connect { from: 1, to: 2, gain: 0 },
+            Edit::Connect { from: 2, to: 1, gain: 0 },
+        ]);
+        assert_eq!(engine.song().graph.connections.len(), 1);
+        assert_eq!(engine.graph_state.topo_order.len(), 3);
+    }
+
+    #[test]
+    fn wire_lanes_automate_connections() {
+        let mut song = song_with_pattern(vec![0; 100]);
+        let tracker = tracker_node(&song);
+        let mut pat = Pattern::new(4, 0);
+        pat.wire_lane_mut(tracker).values[0].pan = Some(32);
+        pat.wire_lane_mut(tracker).values[2].gain = Some(-100);
+        let mut track = mb_ir::Track::new(Some(FILTER_NODE), 0, 0);
+        track.clips.push(mb_ir::Clip::Pattern(pat));
+        track.sequence.push(mb_ir::SeqEntry {
+            start: MusicalTime::zero(),
+            clip_idx: 0,
+            length: 4,
+            termination: mb_ir::SeqTermination::Natural,
+            boundary: mb_ir::ClipBoundary::Ring,
+        });
+        song.tracks.push(track);
+
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.schedule_song();
+        engine.play();
+        let wire = |e: &Engine| {
+            let conn = e.song().graph.connection(tracker, FILTER_NODE).unwrap();
+            (conn.gain, conn.pan)
+        };
+        engine.render_frames(882);
+        assert_eq!(wire(&engine), (0, 32));
+        engine.render_frames(882 * 12);
+        assert_eq!(wire(&engine), (-100, 32));
+        engine.seek(MusicalTime::zero().add_rows(3, 4));
+        assert_eq!(wire(&engine), (-100, 32), "seek replays wire changes");
+    }
+
+    #[test]
+    fn remove_node_renumbers_machines_and_events() {
+        let mut engine = Engine::new(Song::new("graph"), SAMPLE_RATE);
+        engine.play();
+        engine.apply_edits(&[
+            Edit::AddNode { node_type: synth_node() },
+            Edit::AddNode { node_type: synth_node() },
+            Edit::Connect { from: 1, to: 0, gain: 0 },
+            Edit::Connect { from: 2, to: 0, gain: 0 },
+        ]);
+        node_event(&mut engine, 1, note_on());
+        node_event(&mut engine, 2, note_on());
+        engine.apply_edits(&[Edit::RemoveNode { node: 1 }]);
+        assert_eq!(engine.machines.len(), 2);
+        assert_eq!(engine.node_bypass.len(), 2);
+        let targets: Vec<_> = engine.pending_events.iter().map(|e| e.target).collect();
+        assert_eq!(targets, [EventTarget::Node(1)]);
+        assert!(loud(&engine.render_frames(1024)), "old node 2 plays as node 1");
+        engine.apply_edits(&[Edit::RemoveNode { node: 0 }]);
+        assert_eq!(engine.song().graph.nodes.len(), 2, "Master stays");
+    }
+
+    // === Master limiter tests ===
+
+    /// A loud note boosted 4x on its wire into Master.
+    fn clipping_engine() -> Engine {
+        let mut song = song_with_sample(vec![127; 10000], 64);
+        for conn in &mut song.graph.connections {
+            conn.gain = 300;
+        }
+        engine_with_note(&song)
+    }
+
+    fn peak(frames: &[[f32; 2]]) -> f32 {
+        frames.iter().flatten().fold(0.0f32, |m, s| m.max(s.abs()))
+    }
+
+    #[test]
+    fn master_limiter_is_off_by_default() {
+        let mut engine = clipping_engine();
+        assert!(!engine.master_limiter());
+        assert!(peak(&engine.render_frames(512)) > 3.0);
+    }
+
+    #[test]
+    fn master_limiter_keeps_output_in_range() {
+        let mut engine = clipping_engine();
+        engine.apply_edits(&[Edit::SetMasterLimiter { enabled: true }]);
+        assert!(engine.master_limiter());
+        let frames = engine.render_frames(512);
+        assert!(peak(&frames) <= 1.0);
+        assert!(peak(&frames) > 0.9);
+    }
+
+    #[test]
+    fn fade_out_ramps_to_silence_then_stops() {
+        let mut engine = clipping_engine();
+        engine.render_frames(64);
+        engine.fade_out(400);
+        assert!(engine.fading_out());
+        let frames = engine.render_frames(512);
+        assert!(peak(&frames[..100]) > peak(&frames[300..400]));
+        assert_eq!(peak(&frames[400..]), 0.0);
+        assert!(!engine.fading_out());
+        assert!(!engine.is_playing());
+
+        // Nothing playing: stop at once
+        engine.fade_out(400);
+        assert!(!engine.fading_out());
+    }
+
+    // === MIDI sync tests ===
+
+    #[test]
+    fn midi_clock_out_follows_playback() {
+        let mut engine = Engine::new(Song::with_channels("clock", 4), SAMPLE_RATE);
+        engine.set_midi_clock_out(true);
+        engine.play();
+        let t = engine.transport();
+        let beat = (t.samples_per_tick() * t.ticks_per_beat()) as usize;
+        engine.render_frames(beat);
+        let mut sent = Vec::new();
+        engine.drain_sync_output(|offset, message| sent.push((offset, message)));
+        assert_eq!(sent[0], (0, SyncMessage::Start));
+        assert_eq!(sent.iter().filter(|(_, m)| *m == SyncMessage::Clock).count(), 24);
+        assert_eq!(sent[2].0 as usize, beat / 24);
+
+        engine.seek(MusicalTime::from_beats(2));
+        engine.stop();
+        let mut sent = Vec::new();
+        engine.drain_sync_output(|_, message| sent.push(message));
+        assert_eq!(sent, [
+            SyncMessage::Stop,
+            SyncMessage::SongPosition(8),
+            SyncMessage::Continue,
+            SyncMessage::Stop,
+        ]);
+    }
+
+    #[test]
+    fn following_midi_clock_waits_for_start_and_takes_the_tempo() {
+        let mut engine = Engine::new(Song::with_channels("follow", 4), SAMPLE_RATE);
+        engine.play();
+        engine.set_sync_follow(true);
+        engine.receive_sync(SyncMessage::Clock, 0);
+        assert_eq!(engine.render_frames(256), vec![[0.0; 2]; 256]);
+        assert_eq!(engine.position(), MusicalTime::zero());
+
+        // 150 BPM: 16667 µs per pulse
+        engine.receive_sync(SyncMessage::Start, 0);
+        for i in 1..=24 {
+            engine.receive_sync(SyncMessage::Clock, i * 16_667);
+        }
+        assert_eq!(engine.transport().tempo(), 15750, "catches up with a source a beat ahead");
+        engine.render_frames(SAMPLE_RATE as usize * 2 / 5);
+        engine.receive_sync(SyncMessage::Clock, 25 * 16_667);
+        // Within a BPM of the source once caught up
+        assert!(engine.transport().tempo().abs_diff(15000) < 100, "{}", engine.transport().tempo());
+
+        engine.poll_sync(25 * 16_667 + 1_000_000);
+        assert_eq!(engine.render_frames(16), vec![[0.0; 2]; 16]);
+    }
+
+    // === Metronome tests ===
+
+    #[test]
+    fn metronome_clicks_on_each_beat() {
+        let mut engine = Engine::new(Song::with_channels("click", 4), SAMPLE_RATE);
+        engine.play();
+        engine.apply_edits(&[Edit::SetMetronome { enabled: true }]);
+        let t = engine.transport();
+        let beat = (t.samples_per_tick() * t.ticks_per_beat()) as usize;
+        let frames = engine.render_frames(beat + 512);
+        assert!(peak(&frames[..512]) > 0.5);
+        assert_eq!(peak(&frames[beat / 2..beat]), 0.0);
+        assert!(peak(&frames[beat..]) > 0.5);
+    }
+
+    #[test]
+    fn metronome_accents_follow_the_time_signature() {
+        let mut engine = Engine::new(Song::with_channels("click", 4), SAMPLE_RATE);
+        engine.play();
+        engine.apply_edits(&[
+            Edit::SetMetronome { enabled: true },
+            Edit::SetTimeSignature { beat: 0, signature: Some(mb_ir::TimeSignature::new(3, 4)) },
+        ]);
+        let t = engine.transport();
+        let beat = (t.samples_per_tick() * t.ticks_per_beat()) as usize;
+        let frames = engine.render_frames(beat * 4);
+        let crossings = |at: usize| {
+            let click: Vec<f32> = frames[at..at + 64].iter().map(|f| f[0]).collect();
+            click.windows(2).filter(|w| (w[0] < 0.0) != (w[1] < 0.0)).count()
+        };
+        // Beats 0 and 3 start bars
+        assert!(crossings(0) > crossings(beat));
+        assert!(crossings(beat * 3) > crossings(beat * 2));
+    }
+
+    #[test]
+    fn metronome_gain_scales_click() {
+        let mut engine = Engine::new(Song::with_channels("click", 4), SAMPLE_RATE);
+        engine.play();
+        engine.apply_edits(&[
+            Edit::SetMetronome { enabled: true },
+            Edit::SetMetronomeGain { percent: 0 },
+        ]);
+        assert_eq!(peak(&engine.render_frames(512)), 0.0);
+    }
+
+    #[test]
+    fn count_in_holds_song_at_zero() {
+        let mut engine = engine_with_note(&song_with_sample(vec![64; 100_000], 64));
+        engine.apply_edits(&[Edit::CountIn { bars: 1 }]);
+        assert!(engine.counting_in());
+        let t = engine.transport();
+        let beat = (t.samples_per_tick() * t.ticks_per_beat()) as usize;
+        let frames = engine.render_frames(beat * 4 - 100);
+        assert!(peak(&frames[..512]) > 0.5, "count-in clicks without the metronome");
+        assert_eq!(peak(&frames[beat / 2..beat]), 0.0, "song holds during count-in");
+        assert_eq!(engine.position(), MusicalTime::zero());
+        assert!(engine.counting_in() && !engine.is_finished());
+        let song = engine.render_frames(356);
+        assert!(!engine.counting_in());
+        assert_eq!(peak(&song[..100]), 0.0);
+        assert!(peak(&song[100..]) > 0.0, "song starts on the downbeat");
+    }
+
     // === Node bypass tests ===
 
     fn engine_with_note(song: &Song) -> Engine {
@@ -697,10 +2804,351 @@ mod tests {
         assert!(is_nonsilent(&frame), "unbypassed node should produce audio");
     }
 
+    /// `song_with_sample` with a second (idle) tracker channel.
+    fn two_channel_song() -> Song {
+        let mut song = song_with_sample(vec![127; 1000], 64);
+        song.channels.push(mb_ir::ChannelSettings::default());
+        song.tracks[0].num_channels = 2;
+        song
+    }
+
+    #[test]
+    fn channel_mute_silences_only_that_channel() {
+        let song = two_channel_song();
+        let mut engine = engine_with_note(&song);
+        engine.apply_edits(&[Edit::SetChannelMute { channel: 0, muted: true }]);
+        assert_eq!(engine.render_frame(), [0.0, 0.0]);
+        engine.apply_edits(&[
+            Edit::SetChannelMute { channel: 0, muted: false },
+            Edit::SetChannelMute { channel: 1, muted: true },
+        ]);
+        assert!(is_nonsilent(&engine.render_frame()));
+    }
+
+    #[test]
+    fn previews_play_on_their_own_channel() {
+        let song = song_with_sample(vec![127; 1000], 64);
+        let target = crate::preview_target(&song).unwrap();
+        assert_eq!(target, EventTarget::NodeChannel(tracker_node(&song), 1));
+
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.play();
+        engine.apply_edits(&[Edit::SetChannelMute { channel: 0, muted: true }]);
+        engine.schedule(Event::new(engine.position(), target, EventPayload::NoteOn { note: 48, velocity: 64, instrument: 1 }));
+        assert!(is_nonsilent(&engine.render_frame()));
+    }
+
+    #[test]
+    fn solo_silences_other_channels() {
+        let song = two_channel_song();
+        let mut engine = engine_with_note(&song);
+        engine.apply_edits(&[Edit::SetChannelSolo { channel: 1, solo: true }]);
+        assert_eq!(engine.render_frame(), [0.0, 0.0]);
+        engine.apply_edits(&[Edit::SetChannelSolo { channel: 0, solo: true }]);
+        assert!(is_nonsilent(&engine.render_frame()));
+    }
+
+    #[test]
+    fn song_channel_mute_applies_from_start() {
+        let mut song = song_with_sample(vec![127; 1000], 64);
+        song.channels[0].muted = true;
+        let mut engine = engine_with_note(&song);
+        assert_eq!(engine.render_frame(), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn tempo_and_speed_edits_apply_live() {
+        let song = song_with_sample(vec![127; 1000], 64);
+        let mut engine = engine_with_note(&song);
+        engine.apply_edits(&[Edit::SetTempo { tempo: 15000 }, Edit::SetSpeed { speed: 3 }]);
+        assert_eq!(engine.transport().tempo(), 15000);
+        assert_eq!(engine.transport().speed(), 3);
+        assert_eq!(engine.song().initial_tempo, 15000);
+
+        engine.apply_edits(&[Edit::SetTempo { tempo: 0 }, Edit::SetSpeed { speed: 0 }]);
+        assert_eq!(engine.transport().tempo(), 15000);
+        assert_eq!(engine.transport().speed(), 3);
+    }
+
+    #[test]
+    fn tempo_envelope_ramps_the_tempo_each_tick() {
+        let song = song_with_sample(vec![127; 1000], 64);
+        let mut engine = engine_with_note(&song);
+        let envelope = mb_ir::TempoEnvelope::new([
+            mb_ir::TempoPoint { time: MusicalTime::zero(), tempo: 12500, curve: mb_ir::TempoCurve::Linear },
+            mb_ir::TempoPoint { time: MusicalTime::from_beats(1), tempo: 14900, curve: mb_ir::TempoCurve::Step },
+        ]);
+        engine.apply_edits(&[Edit::SetTempoEnvelope { envelope }]);
+
+        // 24 ticks per beat, one BPM faster each
+        for tick in 0..24 {
+            engine.render_frames(1);
+            assert_eq!(engine.transport().tempo(), (125 + tick) * 100);
+            engine.render_frames(engine.transport().frames_to_tick() as usize);
+        }
+        engine.render_frames(1);
+        assert_eq!(engine.transport().tempo(), 14900);
+        assert_eq!(engine.position(), MusicalTime::from_beats(1));
+    }
+
+    #[test]
+    fn channel_pan_edit_moves_playing_channel() {
+        let song = song_with_sample(vec![127; 1000], 64);
+        let node_id = tracker_node(&song);
+        let mut engine = engine_with_note(&song);
+        engine.render_frame();
+        engine.apply_edits(&[Edit::SetChannelPan { channel: 0, pan: 32 }]);
+        assert_eq!(engine.song().channels[0].initial_pan, 32);
+        let machine = engine.machine(node_id).unwrap();
+        assert_eq!(machine.channel_state(0).unwrap().panning, 32);
+    }
+
+    #[test]
+    fn node_param_edit_is_clamped_into_song() {
+        let song = song_with_sample(vec![127; 1000], 64);
+        let filter = mb_ir::find_machine_node(&song.graph).unwrap(); // Amiga Filter
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.apply_edits(&[Edit::SetNodeParam { node: filter, param: 0, value: 5 }]);
+        assert_eq!(engine.song().graph.nodes[filter as usize].parameters[0].value, 1000);
+    }
+
+    #[test]
+    fn sample_fields_edit_reaches_tracker() {
+        let song = song_with_sample(vec![127; 1000], 64);
+        let node_id = tracker_node(&song);
+        let mut engine = Engine::new(song.clone(), SAMPLE_RATE);
+        engine.play();
+        let mut fields = mb_ir::SampleFields::of(&song.samples[0]);
+        fields.default_volume = 20;
+        engine.apply_edits(&[Edit::SetSampleFields { sample: 0, fields }]);
+        schedule_note(&mut engine, &song, 48, 1);
+        engine.render_frame();
+        let machine = engine.machine(node_id).unwrap();
+        assert_eq!(machine.channel_state(0).unwrap().volume, 20);
+    }
+
+    #[test]
+    fn added_sample_reaches_tracker() {
+        let song = song_with_sample(vec![127; 1000], 64);
+        let node_id = tracker_node(&song);
+        let mut engine = Engine::new(song.clone(), SAMPLE_RATE);
+        engine.play();
+        let mut sample = Sample::new("quiet");
+        sample.set_data(SampleData::Mono8(vec![127; 1000]));
+        sample.default_volume = 12;
+        engine.apply_edits(&[Edit::AddSample { sample: Box::new(sample) }]);
+        assert_eq!(engine.song().instruments.len(), 2);
+        schedule_note(&mut engine, &song, 48, 2);
+        engine.render_frame();
+        let machine = engine.machine(node_id).unwrap();
+        assert_eq!(machine.channel_state(0).unwrap().volume, 12);
+    }
+
     #[test]
     fn bypass_invalid_node_is_noop() {
         let song = song_with_sample(vec![127; 1000], 64);
         let mut engine = Engine::new(song, SAMPLE_RATE);
         engine.apply_edits(&[Edit::SetNodeBypass { node: 999, bypassed: true }]);
     }
+
+    // === Seek tests ===
+
+    /// 16 rows: tempo/speed change on row 0, a note on row 8 (beat 2 at speed 3).
+    fn song_for_seek() -> Song {
+        let mut song = song_with_sample((0..2000).map(|i| (i % 64) as i8).collect(), 64);
+        let mut pat = Pattern::new(16, 1);
+        pat.cell_mut(0, 0).effect = mb_ir::Effect::SetTempo(150);
+        pat.cell_mut(1, 0).effect = mb_ir::Effect::SetSpeed(3);
+        *pat.cell_mut(8, 0) = Cell { note: Note::On(48), instrument: 1, ..Cell::empty() };
+        build_tracks(&mut song, &[pat], &[OrderEntry::Pattern(0)]);
+        song
+    }
+
+    #[test]
+    fn seek_matches_straight_playback() {
+        let target = MusicalTime::from_beats(2);
+        let mut straight = Engine::new(song_for_seek(), SAMPLE_RATE);
+        straight.schedule_song();
+        straight.play();
+        while straight.position() < target {
+            straight.render_frame();
+        }
+
+        let mut seeked = Engine::new(song_for_seek(), SAMPLE_RATE);
+        seeked.seek(target);
+        seeked.play();
+        assert_eq!(seeked.position(), target);
+        assert_eq!(seeked.transport().tempo(), 15000);
+        assert_eq!(seeked.transport().speed(), 3);
+
+        let a = straight.render_frames(3000);
+        let b = seeked.render_frames(3000);
+        assert!(a.iter().any(is_nonsilent));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn seek_past_end_finishes() {
+        let mut engine = Engine::new(song_for_seek(), SAMPLE_RATE);
+        engine.seek(MusicalTime::from_beats(100));
+        engine.play();
+        engine.render_frame();
+        assert!(engine.is_finished());
+    }
+
+    #[test]
+    fn seek_back_restarts_cleanly() {
+        let mut engine = Engine::new(song_for_seek(), SAMPLE_RATE);
+        engine.schedule_song();
+        engine.play();
+        engine.render_frames(SAMPLE_RATE as usize);
+        engine.seek(MusicalTime::zero());
+        assert_eq!(engine.position(), MusicalTime::zero());
+        assert_eq!(engine.transport().tempo(), 12500);
+        assert!(!engine.is_finished());
+    }
+
+    // === Loop tests ===
+
+    /// Render in small blocks, returning the positions seen after each block.
+    fn loop_positions(engine: &mut Engine, blocks: usize) -> Vec<MusicalTime> {
+        let mut buf = [[0.0f32; 2]; 64];
+        (0..blocks)
+            .map(|_| {
+                engine.render_block(&mut buf);
+                engine.position()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn loop_wraps_at_end() {
+        let (start, end) = (MusicalTime::from_beats(1), MusicalTime::from_beats(2));
+        let mut engine = Engine::new(song_for_seek(), SAMPLE_RATE);
+        engine.set_loop(Some((start, end)));
+        engine.schedule_song();
+        engine.play();
+        let positions = loop_positions(&mut engine, 2000);
+        assert!(positions.iter().all(|&t| t < end));
+        assert!(positions.windows(2).any(|w| w[1] < w[0]));
+        assert!(!engine.is_finished());
+    }
+
+    #[test]
+    fn loop_past_song_end_wraps_at_song_end() {
+        let start = MusicalTime::from_beats(3);
+        let mut engine = Engine::new(song_for_seek(), SAMPLE_RATE);
+        engine.set_loop(Some((start, MusicalTime::from_beats(100))));
+        engine.schedule_song();
+        engine.play();
+        let positions = loop_positions(&mut engine, 2000);
+        assert!(positions.iter().all(|&t| t <= MusicalTime::from_beats(4)));
+        assert!(positions.windows(2).any(|w| w[1] < w[0] && w[1] >= start));
+        assert!(!engine.is_finished());
+    }
+
+    #[test]
+    fn loop_replays_events() {
+        let (start, end) = (MusicalTime::from_beats(2), MusicalTime::from_beats(3));
+        let mut engine = Engine::new(song_for_seek(), SAMPLE_RATE);
+        engine.seek(start);
+        engine.set_loop(Some((start, end)));
+        engine.play();
+        let mut first = Vec::new();
+        let mut last = engine.position();
+        loop {
+            first.push(engine.render_frame());
+            if engine.position() < last {
+                break;
+            }
+            last = engine.position();
+            assert!(first.len() < SAMPLE_RATE as usize * 10, "loop never wrapped");
+        }
+        assert_eq!(engine.position(), start);
+        assert_eq!(engine.transport().speed(), 3);
+        // The retrigger declicks against the ringing note, then settles
+        // into the same audio as the first pass.
+        let second = engine.render_frames(200);
+        assert!(second.iter().any(is_nonsilent));
+        assert_eq!(&first[101..201], &second[100..]);
+    }
+
+    #[test]
+    fn loop_undoes_speed_changes_inside_the_range() {
+        let (start, end) = (MusicalTime::from_beats(2), MusicalTime::from_beats(3));
+        let mut song = song_with_sample(vec![127; 1000], 64);
+        let mut pat = Pattern::new(16, 1);
+        pat.cell_mut(9, 0).effect = mb_ir::Effect::SetSpeed(3);
+        build_tracks(&mut song, &[pat], &[OrderEntry::Pattern(0)]);
+        let initial_speed = song.initial_speed;
+        let mut engine = Engine::new(song, SAMPLE_RATE);
+        engine.set_loop(Some((start, end)));
+        engine.schedule_song();
+        engine.play();
+        let mut last = engine.position();
+        let mut sped_up = false;
+        loop {
+            engine.render_frame();
+            if engine.position() < last && last >= start {
+                break;
+            }
+            sped_up |= engine.transport().speed() == 3;
+            last = engine.position();
+        }
+        assert!(sped_up);
+        assert_eq!(engine.transport().speed(), initial_speed);
+    }
+
+    #[test]
+    fn loop_restarts_launched_clips_in_session_mode() {
+        let (start, end) = (MusicalTime::zero(), MusicalTime::from_beats(3));
+        let mut engine = Engine::new(song_for_seek(), SAMPLE_RATE);
+        engine.set_session(true);
+        engine.set_loop(Some((start, end)));
+        engine.schedule_song();
+        engine.launch_clip(0, Some(0), start);
+        engine.play();
+        let mut last = engine.position();
+        loop {
+            engine.render_frame();
+            if engine.position() < last {
+                break;
+            }
+            last = engine.position();
+            assert!(last < MusicalTime::from_beats(4), "loop never wrapped");
+        }
+        assert_eq!(engine.launched_clip(0), Some(0));
+        // The note on row 8 plays again on the second pass
+        let mut second = Vec::new();
+        while engine.position() < MusicalTime::from_beats(2).add_rows(1, 4) {
+            second.push(engine.render_frame());
+        }
+        assert!(second.iter().any(is_nonsilent));
+    }
+
+    #[test]
+    fn empty_loop_range_is_ignored() {
+        let mut engine = Engine::new(song_for_seek(), SAMPLE_RATE);
+        engine.set_loop(Some((MusicalTime::from_beats(2), MusicalTime::from_beats(2))));
+        assert_eq!(engine.loop_range(), None);
+    }
+
+    #[test]
+    fn clearing_loop_lets_song_finish() {
+        let mut engine = Engine::new(song_for_seek(), SAMPLE_RATE);
+        engine.apply_edits(&[Edit::SetLoop {
+            range: Some((MusicalTime::zero(), MusicalTime::from_beats(1))),
+        }]);
+        engine.schedule_song();
+        engine.play();
+        loop_positions(&mut engine, 200);
+        engine.apply_edits(&[Edit::SetLoop { range: None }]);
+        for _ in 0..100_000 {
+            if engine.is_finished() {
+                return;
+            }
+            engine.render_frames(64);
+        }
+        panic!("song never finished");
+    }
 }
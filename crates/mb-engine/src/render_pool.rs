@@ -0,0 +1,298 @@
+//! Worker threads that render independent graph nodes in parallel.
+//!
+//! The engine hands each job's machine and gathered input over by value,
+//! so whoever renders a job owns everything it touches. Jobs sit in a
+//! fixed set of slots and change hands through each slot's atomic state:
+//! the engine fills an empty slot and marks it ready, and a worker or the
+//! engine itself claims it, renders it and marks it done. Waiting for a
+//! job never takes a lock: the engine renders jobs no worker has claimed
+//! yet, and only spins on ones a worker is already rendering. Job buffers
+//! are allocated up front, so dispatching doesn't allocate either.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use mb_ir::{AudioBuffer, ChannelConfig, NodeId, BLOCK_SIZE};
+
+use crate::graph_state::{adapt_channels, BUS_CHANNELS};
+use crate::machine::Machine;
+
+/// Jobs that can be in flight at once (one buffer each).
+const JOB_SLOTS: usize = 32;
+
+/// A machine node rendering on a worker.
+pub(crate) struct Job {
+    /// Node the machine belongs to
+    pub node: NodeId,
+    /// The node's machine, lent to the worker
+    pub machine: Box<dyn Machine>,
+    /// Gathered input on the way out, rendered output on the way back
+    pub buf: AudioBuffer,
+    /// The machine's negotiated channel layout
+    pub config: ChannelConfig,
+}
+
+impl Job {
+    fn render(&mut self) {
+        adapt_channels(&mut self.buf, BUS_CHANNELS, self.config.inputs);
+        self.machine.render(&mut self.buf);
+        adapt_channels(&mut self.buf, self.config.outputs, BUS_CHANNELS);
+    }
+}
+
+/// Slot states. Only the engine moves a slot from `EMPTY` to `READY` and
+/// from `DONE` back to `EMPTY`; whoever wins the `READY` to `CLAIMED`
+/// exchange renders the job and marks it `DONE`.
+const EMPTY: u8 = 0;
+const READY: u8 = 1;
+const CLAIMED: u8 = 2;
+const DONE: u8 = 3;
+
+/// One job's place in the pool.
+struct Slot {
+    state: AtomicU8,
+    job: UnsafeCell<Option<Job>>,
+}
+
+// SAFETY: `job` is only touched by the one thread the slot's state hands
+// it to: the engine while `EMPTY` or `DONE`, the claimant while `CLAIMED`.
+// Jobs themselves are `Send`.
+unsafe impl Sync for Slot {}
+
+impl Slot {
+    /// Claim a ready job and render it. Returns whether there was one.
+    fn try_render(&self) -> bool {
+        if self.state.compare_exchange(READY, CLAIMED, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            return false;
+        }
+        // SAFETY: winning the exchange hands the job to this thread
+        if let Some(job) = unsafe { &mut *self.job.get() } {
+            job.render();
+        }
+        self.state.store(DONE, Ordering::Release);
+        true
+    }
+}
+
+/// State shared between the engine and the workers.
+struct Shared {
+    slots: [Slot; JOB_SLOTS],
+    shutdown: AtomicBool,
+}
+
+/// A fixed set of render threads and the buffers their jobs run in.
+pub(crate) struct RenderPool {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+    free: Vec<AudioBuffer>,
+    in_flight: usize,
+}
+
+impl RenderPool {
+    /// Spawn `threads` workers with job buffers of `channels` channels.
+    pub fn new(threads: usize, channels: u16) -> Self {
+        let shared = Arc::new(Shared {
+            slots: core::array::from_fn(|_| Slot { state: AtomicU8::new(EMPTY), job: UnsafeCell::new(None) }),
+            shutdown: AtomicBool::new(false),
+        });
+        let workers = (0..threads)
+            .map(|i| {
+                let shared = Arc::clone(&shared);
+                std::thread::Builder::new()
+                    .name(alloc::format!("mb-render-{i}"))
+                    .spawn(move || work(&shared))
+                    .expect("failed to spawn render thread")
+            })
+            .collect();
+        Self {
+            shared,
+            workers,
+            free: (0..JOB_SLOTS).map(|_| AudioBuffer::new(channels, BLOCK_SIZE as u16)).collect(),
+            in_flight: 0,
+        }
+    }
+
+    /// Number of worker threads.
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Widen the job buffers to hold `channels` channels. Allocates; call
+    /// between blocks, with no jobs in flight.
+    pub fn fit_channels(&mut self, channels: u16) {
+        for buf in &mut self.free {
+            if buf.channels() < channels {
+                *buf = AudioBuffer::new(channels, BLOCK_SIZE as u16);
+            }
+        }
+    }
+
+    /// Take `buffers` as the job buffers if they're wider than the current
+    /// ones, leaving the current ones in `buffers`. Doesn't allocate; call
+    /// between blocks, with no jobs in flight.
+    pub fn widen(&mut self, buffers: &mut Vec<AudioBuffer>) {
+        let wider = |b: &[AudioBuffer]| b.first().map_or(0, AudioBuffer::channels);
+        if wider(buffers) > wider(&self.free) {
+            core::mem::swap(&mut self.free, buffers);
+        }
+    }
+
+    /// Jobs submitted but not yet collected with `wait`.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// A job buffer, unless all of them are in flight.
+    pub fn free_buffer(&mut self) -> Option<AudioBuffer> {
+        self.free.pop()
+    }
+
+    /// Queue a job for the next idle worker. There is a slot for every
+    /// job buffer, so one is always empty while `free_buffer` succeeds.
+    ///
+    /// Panics if every slot is taken: quietly dropping the job would free
+    /// its machine on the audio thread and leave the node silent.
+    pub fn submit(&mut self, job: Job) {
+        let slot = self
+            .shared
+            .slots
+            .iter()
+            .find(|s| s.state.load(Ordering::Relaxed) == EMPTY)
+            .expect("a job was submitted without a free job buffer");
+        // SAFETY: only this thread touches an empty slot's job
+        unsafe { *slot.job.get() = Some(job) };
+        slot.state.store(READY, Ordering::Release);
+        self.in_flight += 1;
+        for worker in &self.workers {
+            worker.thread().unpark();
+        }
+    }
+
+    /// Take back a finished job, rendering one on this thread if no
+    /// worker has claimed it; spins only while workers finish the jobs
+    /// they hold. Only call with jobs in flight; hand its buffer back with
+    /// `recycle` once read.
+    pub fn wait(&mut self) -> Job {
+        loop {
+            for slot in &self.shared.slots {
+                slot.try_render();
+                if slot.state.load(Ordering::Acquire) == DONE {
+                    // SAFETY: a done slot's job is back with this thread
+                    let job = unsafe { (*slot.job.get()).take() };
+                    slot.state.store(EMPTY, Ordering::Relaxed);
+                    if let Some(job) = job {
+                        self.in_flight -= 1;
+                        return job;
+                    }
+                }
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Return a finished job's buffer for reuse.
+    pub fn recycle(&mut self, buf: AudioBuffer) {
+        self.free.push(buf);
+    }
+}
+
+impl Drop for RenderPool {
+    fn drop(&mut self) {
+        while self.in_flight > 0 {
+            self.wait();
+        }
+        self.shared.shutdown.store(true, Ordering::Release);
+        for worker in self.workers.drain(..) {
+            worker.thread().unpark();
+            let _ = worker.join();
+        }
+    }
+}
+
+/// A full set of job buffers for machines of up to `channels` channels,
+/// for `RenderPool::widen`; none when the buses' width will do.
+pub(crate) fn job_buffers(channels: u16) -> Vec<AudioBuffer> {
+    if channels <= BUS_CHANNELS {
+        return Vec::new();
+    }
+    (0..JOB_SLOTS).map(|_| AudioBuffer::new(channels, BLOCK_SIZE as u16)).collect()
+}
+
+/// Worker loop: render ready jobs, parking when there are none, until
+/// the pool shuts down.
+fn work(shared: &Shared) {
+    while !shared.shutdown.load(Ordering::Acquire) {
+        let mut rendered = false;
+        for slot in &shared.slots {
+            rendered |= slot.try_render();
+        }
+        if !rendered {
+            // `submit` unparks after marking a job ready, so a job queued
+            // since the scan above wakes this straight back up
+            std::thread::park();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machines::create_machine;
+
+    #[test]
+    fn jobs_come_back_with_their_machines() {
+        let mut pool = RenderPool::new(2, 2);
+        assert_eq!(pool.threads(), 2);
+        for node in 0..3 {
+            let mut machine = create_machine("Passthrough").unwrap();
+            machine.init(44100);
+            let mut buf = pool.free_buffer().unwrap();
+            buf.set_frames(4);
+            buf.channel_mut(0).fill(0.5);
+            let config = machine.channel_config();
+            pool.submit(Job { node, machine, buf, config });
+        }
+        assert_eq!(pool.in_flight(), 3);
+        let mut nodes: Vec<NodeId> = (0..3).map(|_| {
+            let job = pool.wait();
+            assert!(job.buf.channel(0).iter().all(|&s| s != 0.0));
+            pool.recycle(job.buf);
+            job.node
+        }).collect();
+        nodes.sort();
+        assert_eq!(nodes, [0, 1, 2]);
+        assert_eq!(pool.in_flight(), 0);
+    }
+
+    #[test]
+    fn waiting_renders_unclaimed_jobs() {
+        // With no workers every job renders inside `wait`
+        let mut pool = RenderPool::new(0, 2);
+        let machine = create_machine("Synth").unwrap();
+        let mut buf = pool.free_buffer().unwrap();
+        buf.set_frames(4);
+        buf.channel_mut(0).fill(1.0);
+        let config = ChannelConfig { inputs: 2, outputs: 2 };
+        pool.submit(Job { node: 7, machine, buf, config });
+        let job = pool.wait();
+        assert_eq!(job.node, 7);
+        // An idle synth renders over its input with silence
+        assert_eq!(job.buf.channel(0), [0.0; 4]);
+        assert_eq!(pool.in_flight(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "without a free job buffer")]
+    fn submitting_past_the_slots_panics() {
+        let mut pool = RenderPool::new(0, 2);
+        for node in 0..=JOB_SLOTS as NodeId {
+            let machine = create_machine("Passthrough").unwrap();
+            let buf = pool.free_buffer().unwrap_or_else(|| AudioBuffer::new(2, 4));
+            pool.submit(Job { node, machine, buf, config: ChannelConfig { inputs: 2, outputs: 2 } });
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Sample preview: a song sample played as it is, mixed after the master bus.
+//!
+//! Auditions a sample without an instrument or a channel: at its C-4
+//! speed and default volume, once through or round its loop (ping-pong
+//! loops play forward). It plays whether or not the song does. The
+//! controller holds on to every sample it sends here until the preview
+//! lets go of it, so stopping or replacing one never frees audio on the
+//! audio thread.
+
+use alloc::sync::Arc;
+use mb_ir::{SampleData, SampleFields};
+
+/// A sample playing for audition.
+#[derive(Clone, Debug)]
+pub(crate) struct SamplePreview {
+    /// Audio playing, if any
+    data: Option<Arc<SampleData>>,
+    /// Loop, speed and volume of the playing sample
+    fields: SampleFields,
+    /// Whether to go round the loop instead of stopping at the end
+    looped: bool,
+    /// Read position, 16.16 fixed point
+    position: u64,
+    /// Position step per output frame, 16.16 fixed point
+    step: u64,
+    sample_rate: u32,
+}
+
+impl SamplePreview {
+    pub(crate) fn new(sample_rate: u32) -> Self {
+        let fields = SampleFields::of(&mb_ir::Sample::default());
+        Self { data: None, fields, looped: false, position: 0, step: 0, sample_rate }
+    }
+
+    /// Play `data` from the start with `fields`' speed, volume and loop.
+    pub(crate) fn start(&mut self, data: Arc<SampleData>, fields: SampleFields, looped: bool) {
+        self.step = ((fields.c4_speed as u64) << 16) / self.sample_rate.max(1) as u64;
+        (self.data, self.fields, self.looped, self.position) = (Some(data), fields, looped, 0);
+    }
+
+    pub(crate) fn stop(&mut self) {
+        self.data = None;
+    }
+
+    /// Mix the playing sample into `out`.
+    pub(crate) fn render(&mut self, out: &mut [[f32; 2]]) {
+        let Some(data) = &self.data else { return };
+        let fields = &self.fields;
+        let repeat = (self.looped && fields.loop_type != mb_ir::LoopType::None && fields.loop_end > fields.loop_start)
+            .then(|| ((fields.loop_start as u64) << 16, (fields.loop_end.min(data.len() as u32) as u64) << 16))
+            .filter(|(start, end)| end > start);
+        let end = (data.len() as u64) << 16;
+        let gain = fields.default_volume.min(64) as f32 / (64.0 * 32768.0);
+        let mut ended = false;
+        for frame in out {
+            match repeat {
+                Some((start, loop_end)) if self.position >= loop_end => {
+                    self.position = start + (self.position - loop_end) % (loop_end - start);
+                }
+                None if self.position >= end => {
+                    ended = true;
+                    break;
+                }
+                _ => {}
+            }
+            let (left, right) = data.get_stereo_interpolated(self.position);
+            frame[0] += left as f32 * gain;
+            frame[1] += right as f32 * gain;
+            self.position += self.step;
+        }
+        if ended {
+            self.data = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use mb_ir::LoopType;
+
+    fn fields(loop_type: LoopType) -> SampleFields {
+        SampleFields { loop_start: 2, loop_end: 4, loop_type, default_volume: 64, default_pan: 0, c4_speed: 8000 }
+    }
+
+    #[test]
+    fn plays_once_at_c4_speed_or_round_the_loop() {
+        let data = Arc::new(SampleData::Mono16(vec![1000; 4]));
+        let mut preview = SamplePreview::new(16000);
+        preview.start(data.clone(), fields(LoopType::Forward), false);
+        let mut out = [[0.0f32; 2]; 10];
+        preview.render(&mut out);
+        // Half speed: 4 frames last 8 output frames
+        assert!(out[7][0] > 0.0);
+        assert_eq!(out[8], [0.0, 0.0]);
+        assert!(preview.data.is_none());
+
+        preview.start(data, fields(LoopType::Forward), true);
+        let mut out = [[0.0f32; 2]; 100];
+        preview.render(&mut out);
+        assert!(out[99][0] > 0.0);
+        preview.stop();
+        let mut out = [[0.0f32; 2]; 4];
+        preview.render(&mut out);
+        assert_eq!(out, [[0.0; 2]; 4]);
+    }
+}
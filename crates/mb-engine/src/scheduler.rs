@@ -4,15 +4,22 @@
 //! that the engine can consume for playback.
 
 use alloc::vec::Vec;
+use crate::limits::{LimitHit, Limits};
+use crate::machines;
+use crate::tempo_map::TempoMap;
 use mb_ir::{
-    Cell, Effect, Event, EventPayload, EventTarget, MusicalTime, Note, Song,
-    Track, VolumeCommand
+    Cell, ClipBoundary, CompatibilityMode, Effect, Event, EventPayload, EventTarget, MusicalTime, NodeType, Note,
+    Pattern, SeqEntry, Song, Track, TrackOffset, VolumeCommand, WireValue
 };
 
-/// Result of scheduling a song: events and total length.
+/// Result of scheduling a song: events, total length and tempo map.
 pub struct ScheduleResult {
     pub events: Vec<Event>,
     pub total_time: MusicalTime,
+    /// Set when a track was cut short by `Limits`.
+    pub truncated: Option<LimitHit>,
+    /// Tempo and speed changes found in `events`.
+    pub tempo_map: TempoMap,
 }
 
 /// Flow control state extracted from a pattern row.
@@ -23,20 +30,141 @@ struct FlowControl {
     pattern_delay: u8,
 }
 
-/// Schedule all events from per-track clips + sequences.
-pub fn schedule_song(song: &Song) -> ScheduleResult {
+/// Pattern loop (E6x) state of one channel.
+#[derive(Clone, Copy, Debug, Default)]
+struct LoopChannel {
+    /// Row the loop jumps back to (set by E60)
+    start: u16,
+    /// Repeats left, 0 when the channel isn't looping
+    remaining: u8,
+}
+
+/// Per-channel pattern loops within one sequence entry.
+///
+/// Each channel keeps its own loop start and counter, as in the trackers.
+/// When several channels loop on the same row the rightmost one picks the
+/// row to jump to, while every channel still counts its repeat, so loops
+/// on different channels interleave the way ProTracker plays them.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PatternLoops {
+    channels: Vec<LoopChannel>,
+}
+
+impl PatternLoops {
+    /// Loop state with room for `channels` channels, so resetting to at
+    /// most that many never allocates.
+    pub(crate) fn with_capacity(channels: usize) -> Self {
+        Self { channels: Vec::with_capacity(channels) }
+    }
+
+    /// Forget all loops (on entering a sequence entry).
+    pub(crate) fn reset(&mut self, channels: u8) {
+        self.channels.clear();
+        self.channels.resize(channels as usize, LoopChannel::default());
+    }
+
+    /// Apply the pattern loop commands on `row`; returns the row to jump
+    /// back to, if any channel loops.
+    ///
+    /// In IT a finished loop starts again after its E6x row, so a second
+    /// E6x further down doesn't replay the first loop's rows.
+    pub(crate) fn row(&mut self, pattern: &Pattern, row: u16, mode: CompatibilityMode) -> Option<u16> {
+        let mut target = None;
+        for (col, channel) in self.channels.iter_mut().enumerate().take(pattern.channels as usize) {
+            for effect in pattern.cell(row, col as u8).effects() {
+                match effect {
+                    Effect::PatternLoop(0) => channel.start = row,
+                    Effect::PatternLoop(count) => {
+                        if channel.remaining == 0 {
+                            channel.remaining = count;
+                        } else {
+                            channel.remaining -= 1;
+                        }
+                        if channel.remaining > 0 {
+                            target = Some(channel.start);
+                        } else if mode == CompatibilityMode::ImpulseTracker {
+                            channel.start = row + 1;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        target
+    }
+}
+
+/// Schedule all events from per-track clips + sequences, with default
+/// limits. `sample_rate` is the output rate the tempo map's ticks are
+/// timed at.
+pub fn schedule_song(song: &Song, sample_rate: u32) -> ScheduleResult {
+    schedule_song_with_limits(song, &Limits::default(), sample_rate)
+}
+
+/// Schedule all events, truncating any track that exceeds `limits`.
+///
+/// Millisecond track offsets depend on the tempo where each row plays, so
+/// songs using them are scheduled twice: the second time with the first
+/// pass's tempo map.
+pub fn schedule_song_with_limits(song: &Song, limits: &Limits, sample_rate: u32) -> ScheduleResult {
+    let result = schedule_tracks(song, limits, sample_rate, None);
+    if song.tracks.iter().any(|t| matches!(t.offset, TrackOffset::Millis(ms) if ms != 0)) {
+        return schedule_tracks(song, limits, sample_rate, Some(&result.tempo_map));
+    }
+    result
+}
+
+/// Schedule every track, converting millisecond offsets with `tempo_map`
+/// (or at the initial tempo without one).
+fn schedule_tracks(song: &Song, limits: &Limits, sample_rate: u32, tempo_map: Option<&TempoMap>) -> ScheduleResult {
     let mut events = Vec::new();
     let mut max_time = MusicalTime::zero();
+    let mut truncated = None;
 
     for track in &song.tracks {
-        if track.muted || !song.is_tracker(track) {
+        if track.muted || !is_scheduled(song, track) {
             continue;
         }
-        let t = schedule_track(track, song, &mut events);
+        let (t, hit) = schedule_track(track, song, limits, tempo_map, &mut events);
         if t > max_time { max_time = t; }
+        truncated = truncated.or(hit);
     }
 
-    ScheduleResult { events, total_time: max_time }
+    let tempo_map = TempoMap::from_events(song, &events, sample_rate);
+    ScheduleResult { events, total_time: max_time, truncated, tempo_map }
+}
+
+/// Whether a track's patterns drive its machine: a tracker, a native
+/// note-playing generator such as the synth, or a hosted plugin.
+pub(crate) fn plays_notes(song: &Song, track: &Track) -> bool {
+    let node = track.machine_node.and_then(|id| song.graph.node(id));
+    match node.map(|n| &n.node_type) {
+        Some(NodeType::Machine { is_tracker: true, .. }) => true,
+        Some(NodeType::Machine { machine_name, .. }) => machines::plays_notes(machine_name),
+        Some(NodeType::Plugin { .. } | NodeType::MidiOut { .. }) => true,
+        _ => false,
+    }
+}
+
+/// Whether a track produces events: it plays notes, or its patterns
+/// automate the wires into its machine.
+pub(crate) fn is_scheduled(song: &Song, track: &Track) -> bool {
+    plays_notes(song, track)
+        || track.clips.iter().filter_map(|c| c.pattern()).any(|p| !p.wires.is_empty())
+}
+
+/// Check a track's progress against `limits` before scheduling the next row.
+///
+/// Event counts are checked per row, so a track may overshoot
+/// `max_events` by one row's worth.
+pub(crate) fn check_limits(limits: &Limits, time: MusicalTime, events_emitted: usize) -> Option<LimitHit> {
+    if time >= limits.max_time() {
+        Some(LimitHit::Length)
+    } else if events_emitted >= limits.max_events {
+        Some(LimitHit::Events)
+    } else {
+        None
+    }
 }
 
 /// Resolve effective speed for a pattern row.
@@ -69,6 +197,15 @@ pub fn target_for_track_column(track: &Track, column: u8) -> EventTarget {
     }
 }
 
+/// Where previews play: the spare channel after the first tracker track's
+/// columns, which no pattern reaches.
+pub fn preview_target(song: &Song) -> Option<EventTarget> {
+    let track = song.tracks.iter().find(|t| t.machine_node.is_some() && t.num_channels > 0)?;
+    // The tracker only gets the columns the song has channels for
+    let end = (track.base_channel as usize + track.num_channels as usize).min(song.channels.len());
+    Some(target_for_track_column(track, end.saturating_sub(track.base_channel as usize) as u8))
+}
+
 /// Convert a single cell into events and append them to the output.
 ///
 /// `speed` and `rpb` are needed for NoteDelay sub-beat computation:
@@ -81,13 +218,14 @@ pub fn schedule_cell(
     rpb: u32,
     events: &mut Vec<Event>,
 ) {
-    let delay = note_delay_amount(&cell.effect);
+    let effects = cell.effects();
+    let delay = effects.iter().map(note_delay_amount).max().unwrap_or(0);
     let tpb = speed * rpb;
     let note_time = time.add_ticks(delay, tpb);
 
     match cell.note {
         Note::On(note) => {
-            if is_tone_porta(&cell.effect) {
+            if effects.iter().any(is_tone_porta) {
                 events.push(Event::new(
                     note_time,
                     target,
@@ -108,20 +246,106 @@ pub fn schedule_cell(
                 ));
             }
         }
-        Note::Off | Note::Fade => {
+        Note::Off => {
             events.push(Event::new(
                 note_time,
                 target,
                 EventPayload::NoteOff { note: 0 },
             ));
         }
+        Note::Fade => {
+            events.push(Event::new(note_time, target, EventPayload::NoteFade));
+        }
+        Note::None if cell.instrument > 0 => {
+            events.push(Event::new(note_time, target, EventPayload::InstrumentChange { instrument: cell.instrument }));
+        }
         Note::None => {}
     }
 
     // Volume command is delayed with the note
     schedule_volume_command(&cell.volume, note_time, target, events);
-    // Effect fires at row time (except NoteDelay/PatternDelay are consumed)
-    schedule_effect(&cell.effect, time, target, events);
+    // Effects fire at row time (except NoteDelay/PatternDelay are consumed)
+    schedule_effect(&cell.effect, false, time, target, events);
+    schedule_effect(&cell.effect2, true, time, target, events);
+}
+
+/// Schedule all columns of `row` of `pattern`, due at `time` and shifted by
+/// the track's groove and offset at `tempo`: the cells, wire changes and
+/// the ends of the row's per-tick effects. `speed` is the speed before the
+/// row.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn schedule_row(
+    song: &Song,
+    track: &Track,
+    pattern: &Pattern,
+    row: u16,
+    time: MusicalTime,
+    speed: u32,
+    rpb: u32,
+    tempo: u32,
+    events: &mut Vec<Event>,
+) {
+    let eff_speed = effective_speed(pattern, speed);
+    let row_time = song.track_row_time(track, time, row, eff_speed, rpb, tempo);
+    for col in 0..pattern.channels {
+        let target = target_for_track_column(track, col);
+        schedule_cell(pattern.cell(row, col), row_time, target, eff_speed, rpb, events);
+    }
+    schedule_wires(track, pattern, row, row_time, events);
+    schedule_effect_ends(track, pattern, row, row_time, speed, rpb, events);
+}
+
+/// Emit wire changes on `row` of `pattern`, addressed to the track's machine.
+pub fn schedule_wires(track: &Track, pattern: &Pattern, row: u16, time: MusicalTime, events: &mut Vec<Event>) {
+    let Some(node) = track.machine_node else { return };
+    for lane in &pattern.wires {
+        let Some(&WireValue { gain, pan }) = lane.values.get(row as usize) else { continue };
+        if gain.is_some() || pan.is_some() {
+            events.push(Event::new(time, EventTarget::Node(node), EventPayload::WireChange { from: lane.from, gain, pan }));
+        }
+    }
+}
+
+/// End the per-tick effects (slides, vibrato, arpeggio and the like) that
+/// `row` of `pattern` starts on its last tick, pattern delay included, as
+/// the trackers only run them for their own row. `speed` is the speed
+/// before the row.
+///
+/// The end is an `Effect::None` sent after the last tick's update, so
+/// nothing carries over into the next row's first tick.
+pub(crate) fn schedule_effect_ends(
+    track: &Track,
+    pattern: &Pattern,
+    row: u16,
+    time: MusicalTime,
+    speed: u32,
+    rpb: u32,
+    events: &mut Vec<Event>,
+) {
+    let fc = scan_row_flow_control(pattern, row);
+    let speed = effective_speed(pattern, fc.new_speed.unwrap_or(speed)).max(1);
+    let end = time.add_ticks(speed * (1 + fc.pattern_delay as u32) - 1, speed * rpb);
+    let ticking = |e: &Effect| *e != Effect::None && !e.is_row_effect() && !is_scheduler_directive(e);
+    for col in 0..pattern.channels {
+        let cell = pattern.cell(row, col);
+        if cell.effects().iter().any(ticking) || cell.volume.as_effect().is_some_and(|e| ticking(&e)) {
+            events.push(Event::new(end, target_for_track_column(track, col), EventPayload::Effect(Effect::None)));
+        }
+    }
+}
+
+/// Emit the cut/release events for a sequence entry that ends at `time`.
+///
+/// One event per track column; `ClipBoundary::Ring` emits nothing.
+pub fn schedule_boundary(track: &Track, entry: &SeqEntry, time: MusicalTime, events: &mut Vec<Event>) {
+    let payload = match entry.boundary {
+        ClipBoundary::Ring => return,
+        ClipBoundary::Cut => EventPayload::Effect(Effect::NoteCut(0)),
+        ClipBoundary::Release => EventPayload::NoteOff { note: 0 },
+    };
+    for col in 0..track.num_channels {
+        events.push(Event::new(time, target_for_track_column(track, col), payload.clone()));
+    }
 }
 
 /// Convert a volume column command into an event.
@@ -131,19 +355,7 @@ fn schedule_volume_command(
     target: EventTarget,
     events: &mut Vec<Event>,
 ) {
-    let effect = match vol {
-        VolumeCommand::None => return,
-        VolumeCommand::Volume(v) => Effect::SetVolume(*v),
-        VolumeCommand::Panning(p) => Effect::SetPan(*p),
-        VolumeCommand::TonePorta(v) => Effect::TonePorta(*v),
-        VolumeCommand::Vibrato(v) => Effect::Vibrato { speed: 0, depth: *v },
-        VolumeCommand::VolumeSlideDown(v) => Effect::VolumeSlide(-(*v as i8)),
-        VolumeCommand::VolumeSlideUp(v) => Effect::VolumeSlide(*v as i8),
-        VolumeCommand::FineVolSlideDown(v) => Effect::FineVolumeSlideDown(*v),
-        VolumeCommand::FineVolSlideUp(v) => Effect::FineVolumeSlideUp(*v),
-        VolumeCommand::PortaDown(v) => Effect::PortaDown(*v),
-        VolumeCommand::PortaUp(v) => Effect::PortaUp(*v),
-    };
+    let Some(effect) = vol.as_effect() else { return };
     events.push(Event::new(time, target, EventPayload::Effect(effect)));
 }
 
@@ -154,12 +366,16 @@ fn is_scheduler_directive(effect: &Effect) -> bool {
         Effect::PatternBreak(_)
             | Effect::PositionJump(_)
             | Effect::PatternDelay(_)
+            | Effect::PatternLoop(_)
             | Effect::NoteDelay(_)
     )
 }
 
 /// Convert an effect command into an event, routing tempo/speed to Global.
-fn schedule_effect(effect: &Effect, time: MusicalTime, target: EventTarget, events: &mut Vec<Event>) {
+///
+/// `second` marks the second effect column, whose per-tick effects are sent
+/// as `SecondEffect` so they layer on the first column's.
+fn schedule_effect(effect: &Effect, second: bool, time: MusicalTime, target: EventTarget, events: &mut Vec<Event>) {
     match effect {
         Effect::None => {}
         e if is_scheduler_directive(e) => {} // consumed by scheduler
@@ -167,7 +383,7 @@ fn schedule_effect(effect: &Effect, time: MusicalTime, target: EventTarget, even
             events.push(Event::new(
                 time,
                 EventTarget::Global,
-                EventPayload::SetTempo(*t as u16 * 100),
+                EventPayload::SetTempo(*t as u32 * 100),
             ));
         }
         Effect::SetSpeed(s) => {
@@ -177,6 +393,9 @@ fn schedule_effect(effect: &Effect, time: MusicalTime, target: EventTarget, even
                 EventPayload::SetSpeed(*s),
             ));
         }
+        other if second && !other.is_row_effect() => {
+            events.push(Event::new(time, target, EventPayload::SecondEffect(*other)));
+        }
         other => {
             events.push(Event::new(
                 time,
@@ -193,14 +412,19 @@ pub fn track_column_to_channel(track: &Track, column: u8) -> u8 {
 }
 
 /// Schedule events for a single track (walks sequence, iterates multi-channel patterns).
+///
+/// Returns the end time and the limit that cut the track short, if any.
 fn schedule_track(
     track: &Track,
     song: &Song,
+    limits: &Limits,
+    tempo_map: Option<&TempoMap>,
     events: &mut Vec<Event>,
-) -> MusicalTime {
+) -> (MusicalTime, Option<LimitHit>) {
     if track.sequence.is_empty() {
-        return MusicalTime::zero();
+        return (MusicalTime::zero(), None);
     }
+    let first_event = events.len();
 
     let song_rpb = song.rows_per_beat as u32;
     let mut speed: u32 = song.initial_speed as u32;
@@ -210,6 +434,10 @@ fn schedule_track(
 
     let max_rows = compute_max_rows(track);
     let mut rows_processed: u64 = 0;
+    // Pattern loops replay rows, pushing later entries back by `shift` sub-beats
+    let mut loops = PatternLoops::default();
+    let mut loop_entry = None;
+    let mut shift: u64 = 0;
 
     loop {
         if seq_idx >= track.sequence.len() { break; }
@@ -218,16 +446,16 @@ fn schedule_track(
         let clip_idx = track.sequence[seq_idx].clip_idx as usize;
         let clip = match track.get_pattern_at(clip_idx) {
             Some(p) => p,
-            None => { seq_idx += 1; row = 0; time = advance_to_seq_entry(track, seq_idx, time); continue; }
+            None => { seq_idx += 1; row = 0; time = advance_to_seq_entry(track, seq_idx, shift, time); continue; }
         };
         let num_rows = entry_length.min(clip.rows);
         let rpb = clip.rows_per_beat.map_or(song_rpb, |r| r as u32);
-        let eff_speed = effective_speed(clip, speed);
 
         // Truncate: if current time has reached the next entry's start, advance
-        let next_start = track.sequence.get(seq_idx + 1).map(|e| e.start);
+        let next_start = track.sequence.get(seq_idx + 1).map(|e| shifted(e.start, shift));
         if let Some(ns) = next_start {
             if time >= ns {
+                schedule_boundary(track, &track.sequence[seq_idx], ns, events);
                 seq_idx += 1;
                 row = 0;
                 time = ns;
@@ -238,41 +466,60 @@ fn schedule_track(
         if row >= num_rows {
             seq_idx += 1;
             row = 0;
-            time = advance_to_seq_entry(track, seq_idx, time);
+            time = advance_to_seq_entry(track, seq_idx, shift, time);
             continue;
         }
+        if loop_entry != Some(seq_idx) {
+            loops.reset(clip.channels);
+            loop_entry = Some(seq_idx);
+        }
 
-        // Schedule all columns at this row
-        for col in 0..clip.channels {
-            let target = target_for_track_column(track, col);
-            schedule_cell(clip.cell(row, col), time, target, eff_speed, rpb, events);
+        if let Some(hit) = check_limits(limits, time, events.len() - first_event) {
+            return (time.min(limits.max_time()), Some(hit));
         }
 
+        let tempo = tempo_map.map_or(song.initial_tempo, |map| map.tempo_at(time));
+        schedule_row(song, track, clip, row, time, speed, rpb, tempo, events);
+
         let fc = scan_row_flow_control(clip, row);
         if let Some(s) = fc.new_speed { speed = s; }
+        let loop_to = loops.row(clip, row, song.compatibility);
 
         time = time.add_rows(1 + fc.pattern_delay as u32, rpb);
         rows_processed += 1;
         if rows_processed >= max_rows { break; }
 
-        match (fc.jump_order, fc.break_row) {
+        // A break or jump on the same row wins over a loop
+        let leaves_entry = fc.jump_order.is_some() || fc.break_row.is_some() || (loop_to.is_none() && row + 1 >= num_rows);
+        if leaves_entry {
+            schedule_boundary(track, &track.sequence[seq_idx], time, events);
+        }
+
+        match (fc.jump_order, fc.break_row, loop_to) {
             // Flow control: keep linear time (SeqEntry.start assumes no breaks)
-            (Some(pos), Some(r)) => { seq_idx = pos as usize; row = r as u16; }
-            (Some(pos), None) => { seq_idx = pos as usize; row = 0; }
-            (None, Some(r)) => { seq_idx += 1; row = r as u16; }
+            (Some(pos), Some(r), _) => { seq_idx = track.jump_target(pos); row = r as u16; }
+            (Some(pos), None, _) => { seq_idx = track.jump_target(pos); row = 0; }
+            (None, Some(r), _) => { seq_idx += 1; row = r as u16; }
+            // Pattern loop: replayed rows don't count toward the loop detection
+            (None, None, Some(start)) => {
+                let replayed = (row + 1).saturating_sub(start);
+                shift += MusicalTime::zero().add_rows(replayed as u32, rpb).as_sub_beats();
+                rows_processed = rows_processed.saturating_sub(replayed as u64);
+                row = start;
+            }
             // Normal advancement: use absolute SeqEntry.start
-            (None, None) => {
+            (None, None, None) => {
                 row += 1;
                 if row >= num_rows {
                     seq_idx += 1;
                     row = 0;
-                    time = advance_to_seq_entry(track, seq_idx, time);
+                    time = advance_to_seq_entry(track, seq_idx, shift, time);
                 }
             }
         }
     }
 
-    time
+    (time, None)
 }
 
 
@@ -281,8 +528,14 @@ fn schedule_track(
 /// Used when seq_idx advances: sets time to the entry's absolute start position.
 /// For MOD files (contiguous entries), this equals where the linear time would be.
 /// For BMX files (absolute positions), this jumps to the correct timeline position.
-fn advance_to_seq_entry(track: &Track, seq_idx: usize, current: MusicalTime) -> MusicalTime {
-    track.sequence.get(seq_idx).map_or(current, |e| e.start)
+/// `shift` is the time added by pattern loops so far, in sub-beats.
+fn advance_to_seq_entry(track: &Track, seq_idx: usize, shift: u64, current: MusicalTime) -> MusicalTime {
+    track.sequence.get(seq_idx).map_or(current, |e| shifted(e.start, shift))
+}
+
+/// `time` pushed back by `shift` sub-beats.
+fn shifted(time: MusicalTime, shift: u64) -> MusicalTime {
+    MusicalTime::from_sub_beats(time.as_sub_beats() + shift)
 }
 
 /// Compute max rows for loop detection across all clips in a track.
@@ -309,8 +562,8 @@ fn scan_row_flow_control(pattern: &mb_ir::Pattern, row: u16) -> FlowControl {
         pattern_delay: 0,
     };
     if row >= pattern.rows { return fc; }
-    for col in 0..pattern.channels {
-        match pattern.cell(row, col).effect {
+    for effect in (0..pattern.channels).flat_map(|col| pattern.cell(row, col).effects()) {
+        match effect {
             Effect::PatternBreak(r) => fc.break_row = Some(r),
             Effect::PositionJump(p) => fc.jump_order = Some(p),
             Effect::SetSpeed(s) if s > 0 => fc.new_speed = Some(s as u32),
@@ -328,7 +581,7 @@ mod tests {
 
     /// Schedule and return just the events (convenience for tests).
     fn schedule_events(song: &Song) -> Vec<Event> {
-        schedule_song(song).events
+        schedule_song(song, 44100).events
     }
 
     /// Build a minimal 1-channel song with a single pattern via build_tracks.
@@ -483,9 +736,13 @@ mod tests {
 
         let events = schedule_events(&one_channel_song(pat));
 
-        assert_eq!(events.len(), 1);
+        assert_eq!(events.len(), 2);
         assert_eq!(events[0].target, EventTarget::NodeChannel(2, 0));
         assert_eq!(events[0].payload, EventPayload::Effect(Effect::VolumeSlide(4)));
+        // Ended on the row's last tick
+        assert_eq!(events[1].target, EventTarget::NodeChannel(2, 0));
+        assert_eq!(events[1].payload, EventPayload::Effect(Effect::None));
+        assert_eq!(events[1].time, MusicalTime::zero().add_ticks(5, 6 * 4));
     }
 
     #[test]
@@ -497,12 +754,47 @@ mod tests {
 
         let events = schedule_events(&one_channel_song(pat));
 
-        assert_eq!(events.len(), 2);
+        assert_eq!(events.len(), 3);
         assert!(matches!(events[0].payload, EventPayload::NoteOn { .. }));
         assert!(matches!(events[1].payload, EventPayload::Effect(_)));
         assert_eq!(events[0].time, events[1].time);
     }
 
+    #[test]
+    fn second_effect_column() {
+        let mut pat = Pattern::new(4, 1);
+        pat.cell_mut(0, 0).effect = Effect::Vibrato { speed: 4, depth: 2 };
+        pat.cell_mut(0, 0).effect2 = Effect::VolumeSlide(2);
+        pat.cell_mut(1, 0).note = Note::On(60);
+        pat.cell_mut(1, 0).effect2 = Effect::NoteDelay(3);
+        pat.cell_mut(2, 0).effect2 = Effect::SetVolume(10);
+
+        let events = schedule_events(&one_channel_song(pat));
+
+        let payloads: Vec<_> = events.iter().map(|e| e.payload.clone()).collect();
+        assert_eq!(payloads[..3], [
+            EventPayload::Effect(Effect::Vibrato { speed: 4, depth: 2 }),
+            EventPayload::SecondEffect(Effect::VolumeSlide(2)),
+            EventPayload::Effect(Effect::None),
+        ]);
+        // One end covers both columns' per-tick effects
+        assert_eq!(events[2].time, MusicalTime::zero().add_ticks(5, 24));
+        // Row effects go out as plain effects; the delay moves the note
+        assert_eq!(events[3].time, MusicalTime::zero().add_ticks(6 + 3, 24));
+        assert_eq!(payloads[4], EventPayload::Effect(Effect::SetVolume(10)));
+    }
+
+    #[test]
+    fn instrument_without_note_produces_event() {
+        let mut pat = Pattern::new(4, 1);
+        pat.cell_mut(0, 0).instrument = 2;
+
+        let events = schedule_events(&one_channel_song(pat));
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].payload, EventPayload::InstrumentChange { instrument: 2 });
+    }
+
     #[test]
     fn volume_column_produces_event() {
         let mut pat = Pattern::new(4, 1);
@@ -544,7 +836,7 @@ mod tests {
     #[test]
     fn total_time_matches_pattern_rows() {
         let pat = Pattern::new(4, 1);
-        let result = schedule_song(&one_channel_song(pat));
+        let result = schedule_song(&one_channel_song(pat), 44100);
         assert_eq!(result.total_time, MusicalTime::from_beats(1));
     }
 
@@ -552,7 +844,7 @@ mod tests {
     fn total_time_sums_across_order() {
         let song = song_from(1, vec![Pattern::new(8, 1)],
             vec![OrderEntry::Pattern(0), OrderEntry::Pattern(0)]);
-        let result = schedule_song(&song);
+        let result = schedule_song(&song, 44100);
         assert_eq!(result.total_time, MusicalTime::from_beats(4));
     }
 
@@ -565,7 +857,7 @@ mod tests {
 
         let events = schedule_events(&one_channel_song(pat));
 
-        assert_eq!(events.len(), 2);
+        assert_eq!(events.len(), 3);
         assert_eq!(
             events[0].payload,
             EventPayload::PortaTarget { note: 60, instrument: 1 }
@@ -582,7 +874,7 @@ mod tests {
 
         let events = schedule_events(&one_channel_song(pat));
 
-        assert_eq!(events.len(), 2);
+        assert_eq!(events.len(), 3);
         assert_eq!(
             events[0].payload,
             EventPayload::PortaTarget { note: 64, instrument: 2 }
@@ -598,7 +890,7 @@ mod tests {
 
         let events = schedule_events(&one_channel_song(pat));
 
-        assert_eq!(events.len(), 2);
+        assert_eq!(events.len(), 3);
         assert!(matches!(events[0].payload, EventPayload::NoteOn { .. }));
     }
 
@@ -616,7 +908,7 @@ mod tests {
         pat1.cell_mut(0, 0).instrument = 1;
 
         let result = schedule_song(&song_from(1, vec![pat0, pat1],
-            vec![OrderEntry::Pattern(0), OrderEntry::Pattern(1)]));
+            vec![OrderEntry::Pattern(0), OrderEntry::Pattern(1)]), 44100);
 
         let notes: Vec<_> = result.events.iter().filter_map(|e| match e.payload {
             EventPayload::NoteOn { note, .. } => Some((note, e.time)),
@@ -653,7 +945,7 @@ mod tests {
         pat0.cell_mut(1, 0).effect = Effect::PatternBreak(0);
 
         let result = schedule_song(&song_from(1, vec![pat0, Pattern::new(4, 1)],
-            vec![OrderEntry::Pattern(0), OrderEntry::Pattern(1)]));
+            vec![OrderEntry::Pattern(0), OrderEntry::Pattern(1)]), 44100);
         assert_eq!(result.total_time, time_at_row(6));
     }
 
@@ -690,7 +982,7 @@ mod tests {
         let mut pat = Pattern::new(2, 1);
         pat.cell_mut(1, 0).effect = Effect::PositionJump(0);
 
-        let result = schedule_song(&one_channel_song(pat));
+        let result = schedule_song(&one_channel_song(pat), 44100);
         assert!(result.total_time > MusicalTime::zero());
     }
 
@@ -732,7 +1024,7 @@ mod tests {
         pat.cell_mut(2, 0).note = Note::On(64);
         pat.cell_mut(2, 0).instrument = 1;
 
-        let result = schedule_song(&one_channel_song(pat));
+        let result = schedule_song(&one_channel_song(pat), 44100);
 
         let note_events: Vec<_> = result.events.iter().filter_map(|e| match e.payload {
             EventPayload::NoteOn { note, .. } => Some((note, e.time)),
@@ -751,7 +1043,7 @@ mod tests {
         pat.ticks_per_row = 0;
         pat.cell_mut(2, 0).effect = Effect::SetSpeed(3);
 
-        let result = schedule_song(&one_channel_song(pat));
+        let result = schedule_song(&one_channel_song(pat), 44100);
         let speed_events: Vec<_> = result.events.iter().filter(|e|
             matches!(e.payload, EventPayload::SetSpeed(_))
         ).collect();
@@ -824,7 +1116,7 @@ mod tests {
         let mut pat = Pattern::new(2, 1);
         pat.cell_mut(0, 0).effect = Effect::PatternDelay(2);
 
-        let result = schedule_song(&one_channel_song(pat));
+        let result = schedule_song(&one_channel_song(pat), 44100);
         assert_eq!(result.total_time, MusicalTime::from_beats(1));
     }
 
@@ -860,6 +1152,59 @@ mod tests {
         assert!(events.is_empty());
     }
 
+    // --- PatternLoop tests ---
+
+    #[test]
+    fn pattern_loop_replays_rows_and_pushes_later_patterns_back() {
+        let mut pat0 = Pattern::new(4, 1);
+        pat0.cell_mut(1, 0).note = Note::On(60);
+        pat0.cell_mut(1, 0).effect = Effect::PatternLoop(0);
+        pat0.cell_mut(2, 0).effect = Effect::PatternLoop(2);
+        let mut pat1 = Pattern::new(4, 1);
+        pat1.cell_mut(0, 0).note = Note::On(64);
+
+        let song = song_from(1, vec![pat0, pat1], vec![OrderEntry::Pattern(0), OrderEntry::Pattern(1)]);
+        let result = schedule_song(&song, 44100);
+        let notes: Vec<_> = result.events.iter()
+            .filter(|e| matches!(e.payload, EventPayload::NoteOn { .. }))
+            .map(|e| e.time)
+            .collect();
+        // Rows 0 1 2 1 2 1 2 3, then the second pattern
+        assert_eq!(notes, [time_at_row(1), time_at_row(3), time_at_row(5), time_at_row(8)]);
+        assert_eq!(result.total_time, time_at_row(12));
+        assert!(result.events.iter().all(|e| !matches!(e.payload, EventPayload::Effect(Effect::PatternLoop(_)))));
+    }
+
+    #[test]
+    fn rightmost_channel_picks_the_loop_row() {
+        let mut pat = Pattern::new(4, 2);
+        pat.cell_mut(0, 0).effect = Effect::PatternLoop(0);
+        pat.cell_mut(2, 1).effect = Effect::PatternLoop(0);
+        pat.cell_mut(3, 0).effect = Effect::PatternLoop(1);
+        pat.cell_mut(3, 1).effect = Effect::PatternLoop(1);
+
+        // Rows 0 1 2 3 2 3: both channels count their repeat on the jump
+        let song = song_from(2, vec![pat], vec![OrderEntry::Pattern(0)]);
+        assert_eq!(schedule_song(&song, 44100).total_time, time_at_row(6));
+    }
+
+    #[test]
+    fn it_loops_restart_after_a_finished_loop() {
+        let mut pat = Pattern::new(4, 1);
+        pat.cell_mut(0, 0).effect = Effect::PatternLoop(0);
+        pat.cell_mut(1, 0).effect = Effect::PatternLoop(1);
+        pat.cell_mut(3, 0).effect = Effect::PatternLoop(1);
+        let mut song = one_channel_song(pat);
+
+        // ProTracker replays rows 0-3 forever
+        let limits = Limits { max_beats: 16, ..Limits::default() };
+        assert_eq!(schedule_song_with_limits(&song, &limits, 44100).truncated, Some(LimitHit::Length));
+
+        // IT: rows 0 1 0 1 2 3 2 3
+        song.compatibility = CompatibilityMode::ImpulseTracker;
+        assert_eq!(schedule_song(&song, 44100).total_time, time_at_row(8));
+    }
+
     // --- SeqEntry.length tests ---
 
     #[test]
@@ -878,6 +1223,7 @@ mod tests {
         track.sequence.push(mb_ir::SeqEntry {
             start: MusicalTime::zero(), clip_idx: 0, length: 4,
             termination: mb_ir::SeqTermination::Mute,
+            boundary: mb_ir::ClipBoundary::Ring,
         });
         song.tracks = alloc::vec![track];
 
@@ -906,6 +1252,7 @@ mod tests {
         track.sequence.push(mb_ir::SeqEntry {
             start: MusicalTime::zero(), clip_idx: 0, length: 4,
             termination: mb_ir::SeqTermination::Natural,
+            boundary: mb_ir::ClipBoundary::Ring,
         });
         song.tracks = alloc::vec![track];
 
@@ -931,6 +1278,7 @@ mod tests {
         track.sequence.push(mb_ir::SeqEntry {
             start: MusicalTime::zero(), clip_idx: 0, length: 3,
             termination: mb_ir::SeqTermination::Break,
+            boundary: mb_ir::ClipBoundary::Ring,
         });
         song.tracks = alloc::vec![track];
 
@@ -941,4 +1289,91 @@ mod tests {
         assert_eq!(notes.len(), 2, "break-truncated entry should only play rows 0-2");
     }
 
+    // --- Clip boundary tests ---
+
+    fn boundary_events(song: &Song) -> Vec<Event> {
+        schedule_events(song).into_iter()
+            .filter(|e| matches!(e.payload, EventPayload::NoteOff { .. } | EventPayload::Effect(Effect::NoteCut(0))))
+            .collect()
+    }
+
+    fn two_entry_song(boundary: ClipBoundary) -> Song {
+        let mut song = song_from(2, vec![Pattern::new(4, 2)], vec![OrderEntry::Pattern(0), OrderEntry::Pattern(0)]);
+        song.tracks[0].sequence[0].boundary = boundary;
+        song
+    }
+
+    #[test]
+    fn ring_boundary_emits_nothing() {
+        assert!(boundary_events(&two_entry_song(ClipBoundary::Ring)).is_empty());
+    }
+
+    #[test]
+    fn cut_boundary_emits_note_cut_per_column() {
+        let events = boundary_events(&two_entry_song(ClipBoundary::Cut));
+        assert_eq!(events.len(), 2);
+        for e in &events {
+            assert_eq!(e.time, time_at_row(4));
+            assert_eq!(e.payload, EventPayload::Effect(Effect::NoteCut(0)));
+        }
+    }
+
+    #[test]
+    fn release_boundary_emits_note_off() {
+        let events = boundary_events(&two_entry_song(ClipBoundary::Release));
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|e| e.payload == EventPayload::NoteOff { note: 0 }));
+    }
+
+    #[test]
+    fn truncated_entry_cuts_at_next_start() {
+        let mut song = two_entry_song(ClipBoundary::Cut);
+        song.tracks[0].sequence[1].start = time_at_row(2);
+        let events = boundary_events(&song);
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|e| e.time == time_at_row(2)));
+    }
+
+    #[test]
+    fn pattern_break_cuts_at_break_row() {
+        let mut pat = Pattern::new(8, 1);
+        pat.cell_mut(1, 0).effect = Effect::PatternBreak(0);
+        let mut song = song_from(1, vec![pat], vec![OrderEntry::Pattern(0), OrderEntry::Pattern(0)]);
+        song.tracks[0].sequence[0].boundary = ClipBoundary::Cut;
+        let events = boundary_events(&song);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].time, time_at_row(2));
+    }
+
+    // --- Safety limits ---
+
+    fn dense_song(rows: u16) -> Song {
+        let mut pat = Pattern::new(rows, 1);
+        for r in 0..rows {
+            pat.cell_mut(r, 0).note = Note::On(48);
+            pat.cell_mut(r, 0).instrument = 1;
+        }
+        one_channel_song(pat)
+    }
+
+    #[test]
+    fn event_limit_truncates_schedule() {
+        let limits = Limits { max_events: 10, ..Limits::default() };
+        let result = schedule_song_with_limits(&dense_song(64), &limits, 44100);
+        assert_eq!(result.truncated, Some(LimitHit::Events));
+        assert!(result.events.len() < 64);
+    }
+
+    #[test]
+    fn length_limit_truncates_schedule() {
+        let limits = Limits { max_beats: 2, ..Limits::default() };
+        let result = schedule_song_with_limits(&dense_song(64), &limits, 44100);
+        assert_eq!(result.truncated, Some(LimitHit::Length));
+        assert!(result.events.iter().all(|e| e.time < MusicalTime::from_beats(2)));
+    }
+
+    #[test]
+    fn default_limits_leave_normal_songs_alone() {
+        assert_eq!(schedule_song(&dense_song(64), 44100).truncated, None);
+    }
 }
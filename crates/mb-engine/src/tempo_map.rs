@@ -0,0 +1,260 @@
+//! Tempo map: converts song positions to wall-clock time.
+//!
+//! `MusicalTime` counts beats, but how long a beat lasts depends on the
+//! tempo (samples per tick) and speed (ticks per row) in force, both of
+//! which patterns can change mid-song. The map records every change found
+//! while scheduling so positions can be turned into seconds or frames
+//! without rendering. A tempo envelope becomes one change per tick that
+//! moves the tempo, the way the engine plays it.
+
+use alloc::vec::Vec;
+use mb_ir::{Event, EventPayload, EventTarget, MusicalTime, Song, TempoEnvelope, SUB_BEAT_UNIT};
+
+use crate::transport::{tick_frames, Transport};
+
+/// A tempo or speed change at a song position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TempoChange {
+    /// New tempo in hundredths of a BPM
+    Tempo(u32),
+    /// New speed in ticks per row
+    Speed(u8),
+}
+
+/// The song's tempo and speed over time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TempoMap {
+    /// Tempo at time zero (hundredths of a BPM)
+    tempo: u32,
+    /// Speed at time zero (ticks per row)
+    speed: u8,
+    rows_per_beat: u32,
+    /// Changes sorted by time
+    changes: Vec<(MusicalTime, TempoChange)>,
+}
+
+impl TempoMap {
+    /// A map with the song's initial tempo and speed and no changes.
+    pub fn new(song: &Song) -> Self {
+        Self {
+            tempo: song.initial_tempo.max(100),
+            speed: song.initial_speed,
+            rows_per_beat: song.rows_per_beat as u32,
+            changes: Vec::new(),
+        }
+    }
+
+    /// A map with the global tempo and speed events among `events`.
+    ///
+    /// Zero values are skipped, as the engine ignores them. A tempo
+    /// envelope is read at each tick of a transport running at
+    /// `sample_rate`.
+    pub fn from_events(song: &Song, events: &[Event], sample_rate: u32) -> Self {
+        let mut map = Self::new(song);
+        map.changes = events.iter()
+            .filter(|e| e.target == EventTarget::Global)
+            .filter_map(|e| match e.payload {
+                EventPayload::SetTempo(t) if t >= 100 => Some((e.time, TempoChange::Tempo(t))),
+                EventPayload::SetSpeed(s) if s > 0 => Some((e.time, TempoChange::Speed(s))),
+                _ => None,
+            })
+            .collect();
+        // Stable, so same-time changes keep their scheduling order
+        map.changes.sort_by_key(|(time, _)| *time);
+        map.add_envelope(&song.tempo_envelope, sample_rate);
+        map
+    }
+
+    /// Replace the tempo changes from the envelope's start on with the
+    /// envelope's, read at each tick as the engine does.
+    fn add_envelope(&mut self, envelope: &TempoEnvelope, sample_rate: u32) {
+        let (Some(start), Some(end)) = (envelope.start(), envelope.end()) else { return };
+        self.changes.retain(|&(time, change)| time < start || matches!(change, TempoChange::Speed(_)));
+
+        // Walk the ticks from the first one at or after `start`, at the speed in force
+        let mut clock = Transport::new(self.tempo, self.speed, self.rows_per_beat, sample_rate);
+        let mut speeds = self.changes.iter().filter_map(|&(time, change)| match change {
+            TempoChange::Speed(speed) => Some((time, speed)),
+            TempoChange::Tempo(_) => None,
+        }).peekable();
+        let mut set_speed = |clock: &mut Transport| {
+            while let Some((_, speed)) = speeds.next_if(|&(time, _)| time <= clock.position()) {
+                clock.set_speed(speed);
+            }
+        };
+        set_speed(&mut clock);
+        clock.locate(start);
+        if clock.position() < start {
+            clock.advance(clock.frames_to_tick());
+        }
+        let mut ramp = Vec::new();
+        let mut tempo = None;
+        loop {
+            set_speed(&mut clock);
+            let at = clock.position();
+            let bpm = envelope.tempo_at(at).unwrap_or(self.tempo);
+            if tempo != Some(bpm) {
+                ramp.push((at, TempoChange::Tempo(bpm)));
+                tempo = Some(bpm);
+            }
+            if at >= end {
+                break;
+            }
+            clock.advance(clock.frames_to_tick());
+        }
+        self.changes.extend(ramp);
+        self.changes.sort_by_key(|(time, _)| *time);
+    }
+
+    /// The tempo in force at `time`, in hundredths of a BPM.
+    pub fn tempo_at(&self, time: MusicalTime) -> u32 {
+        let upto = self.changes.partition_point(|&(at, _)| at <= time);
+        self.changes[..upto].iter().rev()
+            .find_map(|&(_, change)| match change {
+                TempoChange::Tempo(tempo) => Some(tempo),
+                TempoChange::Speed(_) => None,
+            })
+            .unwrap_or(self.tempo)
+    }
+
+    /// The recorded changes, in time order.
+    pub fn changes(&self) -> &[(MusicalTime, TempoChange)] {
+        &self.changes
+    }
+
+    /// Seconds from the start of the song to `time`.
+    pub fn seconds_at(&self, time: MusicalTime) -> f64 {
+        // A tick lasts 2.5 / BPM seconds
+        self.fold_until(time, 0.0, |acc, ticks, tempo| acc + ticks as f64 * 250.0 / tempo as f64)
+    }
+
+    /// Output frames from the start of the song to `time` at `sample_rate`,
+    /// counted the way the engine's transport does.
+    pub fn frames_at(&self, time: MusicalTime, sample_rate: u32) -> u64 {
+        self.fold_until(time, 0, |acc, ticks, tempo| acc + ticks * tick_frames(sample_rate, tempo) as u64)
+    }
+
+    /// Song position `seconds` after the start (tick resolution).
+    ///
+    /// Positions past the last change extrapolate at the final tempo.
+    pub fn time_at_seconds(&self, seconds: f64) -> MusicalTime {
+        let (mut tempo, mut speed) = (self.tempo, self.speed);
+        let mut from = MusicalTime::zero();
+        let mut left = seconds.max(0.0);
+        for &(at, change) in &self.changes {
+            let span = self.ticks_between(from, at, speed) as f64 * 250.0 / tempo as f64;
+            if span > left {
+                break;
+            }
+            left -= span;
+            from = at;
+            (tempo, speed) = apply(change, tempo, speed);
+        }
+        let tpb = speed as u64 * self.rows_per_beat as u64;
+        if tpb == 0 {
+            return from;
+        }
+        let ticks = (left * tempo as f64 / 250.0) as u64;
+        MusicalTime::from_sub_beats(from.as_sub_beats() + ticks * SUB_BEAT_UNIT as u64 / tpb)
+    }
+
+    /// Fold `f(acc, ticks, tempo)` over the constant-tempo spans up to `time`.
+    fn fold_until<T>(&self, time: MusicalTime, init: T, f: impl Fn(T, u64, u32) -> T) -> T {
+        let (mut tempo, mut speed) = (self.tempo, self.speed);
+        let mut from = MusicalTime::zero();
+        let mut acc = init;
+        for &(at, change) in &self.changes {
+            if at >= time {
+                break;
+            }
+            acc = f(acc, self.ticks_between(from, at, speed), tempo);
+            from = at;
+            (tempo, speed) = apply(change, tempo, speed);
+        }
+        f(acc, self.ticks_between(from, time, speed), tempo)
+    }
+
+    /// Whole ticks from `from` to `to` at `speed`.
+    fn ticks_between(&self, from: MusicalTime, to: MusicalTime, speed: u8) -> u64 {
+        let sub_beats = to.as_sub_beats().saturating_sub(from.as_sub_beats());
+        sub_beats * (speed as u64 * self.rows_per_beat as u64) / SUB_BEAT_UNIT as u64
+    }
+}
+
+/// Tempo and speed after `change`.
+fn apply(change: TempoChange, tempo: u32, speed: u8) -> (u32, u8) {
+    match change {
+        TempoChange::Tempo(t) => (t, speed),
+        TempoChange::Speed(s) => (tempo, s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mb_ir::{TempoCurve, TempoPoint};
+
+    fn song() -> Song {
+        let mut song = Song::with_channels("tempo", 1);
+        song.initial_tempo = 12500;
+        song.initial_speed = 6;
+        song.rows_per_beat = 4;
+        song
+    }
+
+    fn global(beat: u64, payload: EventPayload) -> Event {
+        Event::new(MusicalTime::from_beats(beat), EventTarget::Global, payload)
+    }
+
+    #[test]
+    fn constant_tempo_beat_lengths() {
+        let map = TempoMap::new(&song());
+        // 24 ticks of 2.5 / 125 s each
+        assert!((map.seconds_at(MusicalTime::from_beats(1)) - 0.48).abs() < 1e-9);
+        assert_eq!(map.frames_at(MusicalTime::from_beats(2), 44100), 2 * 24 * 882);
+    }
+
+    #[test]
+    fn changes_apply_from_their_position() {
+        let song = song();
+        let map = TempoMap::from_events(&song, &[
+            global(2, EventPayload::SetSpeed(3)),
+            global(1, EventPayload::SetTempo(250 * 100)),
+            global(1, EventPayload::SetSpeed(0)),
+        ], 44100);
+        assert_eq!(map.changes().len(), 2);
+        assert_eq!(map.changes()[0].0, MusicalTime::from_beats(1));
+        // 0.48 s, then a beat at double tempo, then half the ticks per beat
+        let secs = map.seconds_at(MusicalTime::from_beats(3));
+        assert!((secs - (0.48 + 0.24 + 0.12)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn envelope_ramps_tick_by_tick_and_overrides_tempo_commands() {
+        let mut song = song();
+        song.tempo_envelope = TempoEnvelope::new([
+            TempoPoint { time: MusicalTime::from_beats(1), tempo: 10000, curve: TempoCurve::Linear },
+            TempoPoint { time: MusicalTime::from_beats(2), tempo: 12400, curve: TempoCurve::Step },
+        ]);
+        let map = TempoMap::from_events(&song, &[
+            global(0, EventPayload::SetTempo(150 * 100)),
+            global(3, EventPayload::SetTempo(250 * 100)),
+        ], 44100);
+        // 150, then one step per tick from 100 to 124 over the 24 ticks of beat 1
+        let tempos: Vec<_> = map.changes().iter().map(|(_, c)| *c).collect();
+        assert_eq!(tempos.len(), 26);
+        assert_eq!(tempos[1], TempoChange::Tempo(10000));
+        assert_eq!(map.changes()[2].0, MusicalTime::zero().add_ticks(25, 24));
+        assert_eq!(tempos[25], TempoChange::Tempo(12400));
+    }
+
+    #[test]
+    fn time_at_seconds_inverts_seconds_at() {
+        let song = song();
+        let map = TempoMap::from_events(&song, &[global(1, EventPayload::SetTempo(250 * 100))], 44100);
+        for beat in [0, 1, 2, 5] {
+            let time = MusicalTime::from_beats(beat);
+            assert_eq!(map.time_at_seconds(map.seconds_at(time) + 1e-9), time);
+        }
+    }
+}
@@ -0,0 +1,288 @@
+//! Playback clock: musical time advancement and tick timing.
+//!
+//! `Transport` owns the tempo/speed state and converts between output
+//! frames, ticks and `MusicalTime`. The engine drives it one sub-block at a
+//! time; sync subsystems (MIDI clock, external sync) read it to follow the
+//! same timeline.
+
+use mb_ir::{MusicalTime, SUB_BEAT_UNIT};
+
+/// Slowest tempo (hundredths of a BPM); slower ones are raised to it.
+const MIN_TEMPO: u32 = 100;
+
+/// Output frames per tick at `tempo` (hundredths of a BPM): a tick lasts
+/// 2.5 / BPM seconds.
+pub(crate) fn tick_frames(sample_rate: u32, tempo: u32) -> u32 {
+    (sample_rate as u64 * 250 / tempo.max(MIN_TEMPO) as u64) as u32
+}
+
+/// Musical clock advanced by output frames.
+#[derive(Clone, Debug)]
+pub struct Transport {
+    /// Current playback position in musical time
+    position: MusicalTime,
+    /// Audio sample rate (e.g., 44100)
+    sample_rate: u32,
+    /// Samples per tick at current tempo
+    samples_per_tick: u32,
+    /// Sample counter within current tick
+    sample_counter: u32,
+    /// Current tempo (hundredths of a BPM)
+    tempo: u32,
+    /// Current speed (ticks per row)
+    speed: u8,
+    /// Rows per beat (from song)
+    rows_per_beat: u32,
+    /// Tick counter within current beat (0..ticks_per_beat)
+    tick_in_beat: u32,
+}
+
+impl Transport {
+    /// Create a transport at time zero.
+    pub fn new(tempo: u32, speed: u8, rows_per_beat: u32, sample_rate: u32) -> Self {
+        let mut transport = Self {
+            position: MusicalTime::zero(),
+            sample_rate,
+            samples_per_tick: 0,
+            sample_counter: 0,
+            tempo: tempo.max(MIN_TEMPO),
+            speed,
+            rows_per_beat,
+            tick_in_beat: 0,
+        };
+        transport.update_samples_per_tick();
+        transport
+    }
+
+    /// Current playback position (tick resolution).
+    pub fn position(&self) -> MusicalTime {
+        self.position
+    }
+
+    /// Current playback position including progress within the tick (frame resolution).
+    pub fn now(&self) -> MusicalTime {
+        let elapsed = self.frames_to_sub_beats(self.sample_counter);
+        MusicalTime::from_sub_beats(self.position.as_sub_beats() + elapsed)
+    }
+
+    /// Current tempo in hundredths of a BPM.
+    pub fn tempo(&self) -> u32 {
+        self.tempo
+    }
+
+    /// Current speed (ticks per row).
+    pub fn speed(&self) -> u8 {
+        self.speed
+    }
+
+    /// Rows per beat.
+    pub fn rows_per_beat(&self) -> u32 {
+        self.rows_per_beat
+    }
+
+    /// Output sample rate.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Samples per tick at the current tempo.
+    pub fn samples_per_tick(&self) -> u32 {
+        self.samples_per_tick
+    }
+
+    /// Ticks per beat = speed * rows_per_beat.
+    pub fn ticks_per_beat(&self) -> u32 {
+        self.speed as u32 * self.rows_per_beat
+    }
+
+    /// Set the tempo in hundredths of a BPM (at least 1 BPM).
+    pub fn set_tempo(&mut self, tempo: u32) {
+        self.tempo = tempo.max(MIN_TEMPO);
+        self.update_samples_per_tick();
+    }
+
+    /// Set the speed (ticks per row).
+    pub fn set_speed(&mut self, speed: u8) {
+        self.speed = speed;
+    }
+
+    /// Jump to the last tick at or before `time`, keeping tempo and speed.
+    pub fn locate(&mut self, time: MusicalTime) {
+        let tpb = self.ticks_per_beat().max(1);
+        self.tick_in_beat = (time.sub_beat as u64 * tpb as u64 / SUB_BEAT_UNIT as u64) as u32;
+        self.position = MusicalTime { beat: time.beat, sub_beat: self.tick_in_beat * SUB_BEAT_UNIT / tpb };
+        self.sample_counter = 0;
+    }
+
+    /// Whether the clock sits exactly on a beat boundary.
+    pub fn on_beat(&self) -> bool {
+        self.sample_counter == 0 && self.position.sub_beat == 0
+    }
+
+    /// Whether the clock sits exactly on a tick boundary.
+    pub fn on_tick(&self) -> bool {
+        self.sample_counter == 0
+    }
+
+    /// Frames remaining until the next tick boundary.
+    pub fn frames_to_tick(&self) -> u32 {
+        self.samples_per_tick.saturating_sub(self.sample_counter)
+    }
+
+    /// Advance by `frames` output frames (at most `frames_to_tick()`).
+    ///
+    /// Returns true when a tick boundary was reached.
+    pub fn advance(&mut self, frames: u32) -> bool {
+        self.sample_counter += frames;
+        if self.sample_counter < self.samples_per_tick {
+            return false;
+        }
+        self.sample_counter = 0;
+        self.advance_tick();
+        true
+    }
+
+    /// Playback position `frames` output frames ago, at the current tempo and speed.
+    ///
+    /// Includes progress within the current tick, so the result has frame
+    /// (not tick) resolution. Used to compensate recorded input for latency.
+    pub fn time_before(&self, frames: u32) -> MusicalTime {
+        let now = self.now().as_sub_beats();
+        MusicalTime::from_sub_beats(now.saturating_sub(self.frames_to_sub_beats(frames)))
+    }
+
+    /// Output frames from now until `now()` reaches `time` (0 if already there).
+    ///
+    /// Assumes the current tempo and speed hold until then.
+    pub fn frames_until(&self, time: MusicalTime) -> u64 {
+        let frames_per_beat = self.ticks_per_beat() as u64 * self.samples_per_tick as u64;
+        let delta = time.as_sub_beats().saturating_sub(self.position.as_sub_beats());
+        let from_tick = delta.saturating_mul(frames_per_beat).div_ceil(SUB_BEAT_UNIT as u64);
+        from_tick.saturating_sub(self.sample_counter as u64)
+    }
+
+    /// Sub-beat units covered by `frames` output frames.
+    pub fn frames_to_sub_beats(&self, frames: u32) -> u64 {
+        let frames_per_beat = self.ticks_per_beat() as u64 * self.samples_per_tick as u64;
+        if frames_per_beat == 0 {
+            return 0;
+        }
+        frames as u64 * SUB_BEAT_UNIT as u64 / frames_per_beat
+    }
+
+    /// Update samples_per_tick based on current tempo.
+    fn update_samples_per_tick(&mut self) {
+        self.samples_per_tick = tick_frames(self.sample_rate, self.tempo);
+    }
+
+    /// Advance by one tick in beat-space.
+    fn advance_tick(&mut self) {
+        self.tick_in_beat += 1;
+        let tpb = self.ticks_per_beat();
+        if self.tick_in_beat >= tpb {
+            self.tick_in_beat = 0;
+            self.position.beat += 1;
+            self.position.sub_beat = 0;
+        } else {
+            self.position.sub_beat = self.tick_in_beat * SUB_BEAT_UNIT / tpb;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transport() -> Transport {
+        Transport::new(12500, 6, 4, 44100)
+    }
+
+    #[test]
+    fn samples_per_tick_from_tempo() {
+        let mut t = transport();
+        assert_eq!(t.samples_per_tick(), 882);
+        t.set_tempo(15000);
+        assert_eq!(t.samples_per_tick(), 735);
+        // Fractional and above 255 BPM
+        t.set_tempo(12550);
+        assert_eq!(t.samples_per_tick(), 878);
+        t.set_tempo(40000);
+        assert_eq!(t.samples_per_tick(), 275);
+    }
+
+    #[test]
+    fn advance_reports_tick_boundary() {
+        let mut t = transport();
+        assert!(!t.advance(800));
+        assert_eq!(t.frames_to_tick(), 82);
+        assert!(t.advance(82));
+        assert_eq!(t.frames_to_tick(), 882);
+        assert_eq!(t.position().sub_beat, SUB_BEAT_UNIT / 24);
+    }
+
+    #[test]
+    fn beat_rolls_over_after_ticks_per_beat() {
+        let mut t = transport();
+        for _ in 0..t.ticks_per_beat() {
+            t.advance(t.frames_to_tick());
+        }
+        assert_eq!(t.position(), MusicalTime::from_beats(1));
+    }
+
+    #[test]
+    fn on_beat_only_at_start_of_beat() {
+        let mut t = transport();
+        assert!(t.on_beat());
+        t.advance(1);
+        assert!(!t.on_beat());
+        t.advance(t.frames_to_tick());
+        assert!(!t.on_beat());
+        t.locate(MusicalTime::from_beats(2));
+        assert!(t.on_beat());
+    }
+
+    #[test]
+    fn locate_snaps_to_tick_and_continues_from_there() {
+        let mut t = transport();
+        t.advance(100);
+        t.locate(MusicalTime { beat: 3, sub_beat: SUB_BEAT_UNIT / 2 + 1 });
+        assert_eq!(t.position(), MusicalTime { beat: 3, sub_beat: SUB_BEAT_UNIT / 2 });
+        assert_eq!(t.frames_to_tick(), 882);
+        for _ in 0..12 {
+            t.advance(t.frames_to_tick());
+        }
+        assert_eq!(t.position(), MusicalTime::from_beats(4));
+    }
+
+    #[test]
+    fn now_includes_progress_within_tick() {
+        let mut t = transport();
+        t.advance(441);
+        assert_eq!(t.position(), MusicalTime::zero());
+        assert_eq!(t.now().sub_beat, SUB_BEAT_UNIT / 48);
+    }
+
+    #[test]
+    fn frames_until_reaches_exact_frame() {
+        let mut t = transport();
+        let target = MusicalTime::zero().add_ticks(2, 24);
+        assert_eq!(t.frames_until(target), 882 * 2);
+        t.advance(500);
+        assert_eq!(t.frames_until(target), 882 * 2 - 500);
+        assert_eq!(t.frames_until(MusicalTime::zero()), 0);
+        let odd = MusicalTime::from_sub_beats(t.frames_to_sub_beats(700) + 1);
+        let n = t.frames_until(odd);
+        t.advance(n as u32);
+        assert!(t.now() >= odd);
+        assert_eq!(n, 201);
+    }
+
+    #[test]
+    fn speed_change_alters_ticks_per_beat() {
+        let mut t = transport();
+        t.set_speed(3);
+        assert_eq!(t.ticks_per_beat(), 12);
+        t.advance(t.frames_to_tick());
+        assert_eq!(t.position().sub_beat, SUB_BEAT_UNIT / 12);
+    }
+}
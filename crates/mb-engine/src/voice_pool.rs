@@ -0,0 +1,254 @@
+//! Background voices for New Note Actions.
+//!
+//! When a new note arrives on a tracker channel whose instrument has a
+//! `NewNoteAction` other than `Cut`, the old note moves into the voice pool
+//! and keeps sounding (released or fading out, with its envelopes still
+//! running) while the channel plays the new one. The pool is preallocated
+//! so spawning never allocates on the audio thread; when it is full the
+//! quietest (then oldest) voice is stolen.
+
+use alloc::vec::Vec;
+
+use mb_ir::{Instrument, NewNoteAction, Sample};
+
+use crate::channel::ChannelState;
+
+/// Default number of background voices per tracker machine.
+pub const DEFAULT_VOICES: usize = 64;
+
+/// A note released from its channel, still sounding in the background.
+#[derive(Clone, Debug)]
+pub struct Voice {
+    /// Playback state taken over from the channel
+    pub state: ChannelState,
+    /// Channel the voice came from (follows that channel's mute)
+    pub channel: u8,
+    /// Spawn order, for stealing the oldest voice
+    serial: u32,
+}
+
+impl Voice {
+    /// Loudness used to pick a voice to steal.
+    fn loudness(&self) -> u32 {
+        (self.state.output_level() * 65536.0) as u32
+    }
+}
+
+/// Fixed-capacity set of background voices.
+#[derive(Clone, Debug)]
+pub struct VoicePool {
+    voices: Vec<Voice>,
+    next_serial: u32,
+}
+
+impl VoicePool {
+    /// Create a pool holding at most `capacity` voices.
+    pub fn new(capacity: usize) -> Self {
+        Self { voices: Vec::with_capacity(capacity), next_serial: 0 }
+    }
+
+    /// Maximum number of voices.
+    pub fn capacity(&self) -> usize {
+        self.voices.capacity()
+    }
+
+    /// Number of sounding voices.
+    pub fn len(&self) -> usize {
+        self.voices.len()
+    }
+
+    /// True when no voices are sounding.
+    pub fn is_empty(&self) -> bool {
+        self.voices.is_empty()
+    }
+
+    /// Sounding voices.
+    pub fn voices(&self) -> &[Voice] {
+        &self.voices
+    }
+
+    /// Move a channel's current note into the background per its instrument's NNA.
+    ///
+    /// `Cut` (or no instrument, or a silent channel) spawns nothing; `Off`
+    /// releases the key and `Fade` starts the fadeout on the moved note.
+    /// Returns whether a voice was spawned.
+    pub fn release(&mut self, channel: u8, state: &ChannelState, instrument: Option<&Instrument>) -> bool {
+        let action = instrument.map_or(NewNoteAction::Cut, |i| i.new_note_action);
+        if !state.playing || action == NewNoteAction::Cut || self.capacity() == 0 {
+            return false;
+        }
+        let mut state = state.clone();
+        match action {
+            NewNoteAction::Off => state.key_off(instrument),
+            NewNoteAction::Fade => state.note_fade(instrument),
+            NewNoteAction::Continue | NewNoteAction::Cut => {}
+        }
+        if !state.playing {
+            return false;
+        }
+        self.spawn(Voice { state, channel, serial: 0 });
+        true
+    }
+
+    /// Let a note that is being cut ramp down in the background instead of
+    /// stopping dead, when its channel ramps volume.
+    pub fn declick(&mut self, channel: u8, state: &ChannelState) {
+        if !state.playing || state.quirks.volume_ramp == 0 || self.capacity() == 0 {
+            return;
+        }
+        let mut state = state.clone();
+        state.stop_ramped();
+        self.spawn(Voice { state, channel, serial: 0 });
+    }
+
+    /// Add a voice, stealing the quietest (then oldest) one if the pool is full.
+    fn spawn(&mut self, mut voice: Voice) {
+        voice.serial = self.next_serial;
+        self.next_serial = self.next_serial.wrapping_add(1);
+        if self.voices.len() < self.voices.capacity() {
+            self.voices.push(voice);
+            return;
+        }
+        let victim = self
+            .voices
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, v)| (v.loudness(), v.serial))
+            .map(|(i, _)| i);
+        if let Some(i) = victim {
+            self.voices[i] = voice;
+        }
+    }
+
+    /// Advance auto-vibrato, envelopes and fades by one tick and drop finished voices.
+    pub fn tick(&mut self, instruments: &[Instrument], samples: &[Sample], sample_rate: u32) {
+        for voice in &mut self.voices {
+            let state = &mut voice.state;
+            let sample = samples.get(state.sample_index as usize);
+            state.advance_auto_vibrato(sample.and_then(|s| s.vibrato.as_ref()));
+            state.update_increment(sample_rate);
+            state.advance_envelopes(instruments.get(state.instrument as usize));
+        }
+        self.voices.retain(|v| v.state.playing);
+    }
+
+    /// Render all voices, accumulating into `left`/`right`.
+    ///
+    /// `gain(channel)` gives the mix gain for a voice's source channel.
+    pub(crate) fn render(
+        &mut self,
+        samples: &[Sample],
+        left: &mut [f32],
+        right: &mut [f32],
+        gain: impl Fn(u8) -> f32,
+    ) {
+        for voice in &mut self.voices {
+            let Some(sample) = samples.get(voice.state.sample_index as usize) else { continue };
+            voice.state.render_block(sample, left, right, gain(voice.channel));
+        }
+        self.voices.retain(|v| v.state.playing);
+    }
+
+    /// Silence every voice.
+    pub fn clear(&mut self) {
+        self.voices.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::FADE_MAX;
+
+    fn playing(volume: u8) -> ChannelState {
+        let mut state = ChannelState::new();
+        state.trigger(48, 0, 0);
+        state.volume = volume;
+        state
+    }
+
+    fn instrument(action: NewNoteAction, fadeout: u16) -> Instrument {
+        Instrument { new_note_action: action, fadeout, ..Instrument::default() }
+    }
+
+    #[test]
+    fn cut_spawns_nothing() {
+        let mut pool = VoicePool::new(4);
+        pool.release(0, &playing(64), Some(&instrument(NewNoteAction::Cut, 0)));
+        pool.release(0, &playing(64), None);
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn stopped_channel_spawns_nothing() {
+        let mut pool = VoicePool::new(4);
+        let inst = instrument(NewNoteAction::Continue, 0);
+        pool.release(0, &ChannelState::new(), Some(&inst));
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn continue_holds_full_volume() {
+        let mut pool = VoicePool::new(4);
+        let inst = instrument(NewNoteAction::Continue, 100);
+        pool.release(0, &playing(64), Some(&inst));
+        for _ in 0..100 {
+            pool.tick(core::slice::from_ref(&inst), &[], 44100);
+        }
+        assert_eq!(pool.voices()[0].state.fade, FADE_MAX);
+    }
+
+    #[test]
+    fn fade_drops_voice_when_silent() {
+        let mut pool = VoicePool::new(4);
+        let inst = instrument(NewNoteAction::Fade, 1024);
+        pool.release(0, &playing(64), Some(&inst));
+        pool.tick(core::slice::from_ref(&inst), &[], 44100);
+        assert_eq!(pool.voices()[0].state.fade, FADE_MAX - 1024 * 32);
+        pool.tick(core::slice::from_ref(&inst), &[], 44100);
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn off_without_envelope_or_fadeout_spawns_nothing() {
+        let mut pool = VoicePool::new(4);
+        let mut state = playing(64);
+        state.quirks.volume_ramp = 0;
+        assert!(!pool.release(0, &state, Some(&instrument(NewNoteAction::Off, 0))));
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn declick_ramps_cut_note_out_then_drops_it() {
+        let mut sample = Sample::new("s");
+        sample.set_data(mb_ir::SampleData::Mono8(alloc::vec![64; 100]));
+        let mut state = playing(64);
+        state.quirks.volume_ramp = 4;
+        let (mut left, mut right) = ([0.0f32; 8], [0.0f32; 8]);
+        state.render_block(&sample, &mut left, &mut right, 1.0);
+        let full = left[7];
+
+        let mut pool = VoicePool::new(4);
+        assert!(!pool.release(0, &state, Some(&instrument(NewNoteAction::Cut, 0))));
+        pool.declick(0, &state);
+        assert_eq!(pool.len(), 1);
+        let (mut left, mut right) = ([0.0f32; 8], [0.0f32; 8]);
+        pool.render(core::slice::from_ref(&sample), &mut left, &mut right, |_| 1.0);
+        assert!(pool.is_empty());
+        assert!((left[0] - full * 0.75).abs() < 1e-6);
+        assert!(left[3..].iter().all(|&v| v == 0.0));
+    }
+
+    #[test]
+    fn full_pool_steals_quietest_then_oldest() {
+        let mut pool = VoicePool::new(3);
+        let inst = instrument(NewNoteAction::Continue, 0);
+        pool.release(0, &playing(64), Some(&inst));
+        pool.release(1, &playing(10), Some(&inst));
+        pool.release(2, &playing(10), Some(&inst));
+        pool.release(3, &playing(64), Some(&inst));
+        let channels: Vec<u8> = pool.voices().iter().map(|v| v.channel).collect();
+        assert_eq!(channels, vec![0, 3, 2]);
+        assert_eq!(pool.len(), pool.capacity());
+    }
+}
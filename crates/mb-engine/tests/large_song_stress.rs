@@ -0,0 +1,40 @@
+//! Stress tests on synthetic worst-case songs.
+//!
+//! These catch scaling regressions (panics, event loss, NaNs) that only show
+//! up with many tracks and dense clips; timing is measured by the benches.
+
+use mb_engine::Engine;
+use mb_ir::SongTemplate;
+
+const SAMPLE_RATE: u32 = 44100;
+
+fn render(template: SongTemplate, frames: usize) -> Vec<[f32; 2]> {
+    let mut engine = Engine::new(template.build(), SAMPLE_RATE);
+    engine.schedule_song();
+    engine.play();
+    let mut buf = vec![[0.0f32; 2]; frames];
+    engine.render_block(&mut buf);
+    buf
+}
+
+#[test]
+fn worst_case_renders_finite_audio() {
+    let out = render(SongTemplate::worst_case(), SAMPLE_RATE as usize / 10);
+    assert!(out.iter().flatten().all(|s| s.is_finite()));
+    assert!(out.iter().flatten().any(|&s| s != 0.0));
+}
+
+#[test]
+fn dense_clips_render_past_clip_boundaries() {
+    let template = SongTemplate {
+        tracks: 32,
+        rows: 4,
+        clips_per_track: 8,
+        note_density: 100,
+        effect_density: 100,
+        ..SongTemplate::default()
+    };
+    // Eight one-beat clips at 125 BPM: render ~4s to cross every boundary
+    let out = render(template, SAMPLE_RATE as usize * 4);
+    assert!(out.iter().flatten().all(|s| s.is_finite()));
+}
@@ -0,0 +1,212 @@
+//! MOD effect conformance suite.
+//!
+//! Each case builds a tiny single-channel M.K. module exercising one effect,
+//! plays it through the engine tick by tick, and compares the channel's
+//! played period/volume on every tick against a reference trace. The
+//! references follow the ProTracker 2.3 replay routine (speed 6, effects on
+//! ticks 1..speed-1 unless noted), so a failing case names the quirk that
+//! diverges.
+
+use mb_engine::Engine;
+use mb_formats::load_mod;
+
+const SAMPLE_RATE: u32 = 44100;
+const ROWS: usize = 64;
+
+/// One pattern cell: Amiga period, sample number, effect nibble, parameter.
+#[derive(Clone, Copy, Default)]
+struct Cell {
+    period: u16,
+    sample: u8,
+    effect: u8,
+    param: u8,
+}
+
+/// Note with sample 1 and an effect.
+fn note(period: u16, effect: u8, param: u8) -> Cell {
+    Cell { period, sample: 1, effect, param }
+}
+
+/// Effect-only cell.
+fn fx(effect: u8, param: u8) -> Cell {
+    Cell { effect, param, ..Cell::default() }
+}
+
+/// Build a 4-channel M.K. module: one pattern, rows on channel 0, one looped
+/// square-wave sample at `volume`.
+fn build_mod(rows: &[Cell], volume: u8) -> Vec<u8> {
+    const SAMPLE_WORDS: u16 = 32;
+    let mut data = vec![0u8; 1084];
+    data[..4].copy_from_slice(b"conf");
+
+    let hdr = 20;
+    data[hdr..hdr + 6].copy_from_slice(b"square");
+    data[hdr + 22..hdr + 24].copy_from_slice(&SAMPLE_WORDS.to_be_bytes());
+    data[hdr + 25] = volume;
+    data[hdr + 28..hdr + 30].copy_from_slice(&SAMPLE_WORDS.to_be_bytes()); // loop whole sample
+
+    data[950] = 1; // song length
+    data[951] = 127;
+    data[1080..1084].copy_from_slice(b"M.K.");
+
+    let mut pattern = vec![0u8; ROWS * 4 * 4];
+    for (row, cell) in rows.iter().enumerate() {
+        let o = row * 16;
+        pattern[o] = (cell.sample & 0xF0) | (cell.period >> 8) as u8;
+        pattern[o + 1] = cell.period as u8;
+        pattern[o + 2] = (cell.sample << 4) | (cell.effect & 0x0F);
+        pattern[o + 3] = cell.param;
+    }
+    data.extend_from_slice(&pattern);
+    data.extend((0..SAMPLE_WORDS * 2).map(|i| if i < SAMPLE_WORDS { 0x40u8 } else { 0xC0 }));
+    data
+}
+
+/// Play `rows` and record channel 0's (period, volume) on each of `ticks` ticks.
+fn trace(rows: &[Cell], volume: u8, ticks: usize) -> Vec<(u16, u8)> {
+    let song = load_mod(&build_mod(rows, volume)).expect("conformance module should load");
+    let tracker = mb_ir::find_tracker_node(&song.graph).expect("tracker node");
+    let mut engine = Engine::new(song, SAMPLE_RATE);
+    engine.schedule_song();
+    engine.play();
+
+    let mut out = Vec::with_capacity(ticks);
+    for _ in 0..ticks {
+        // First frame of the tick dispatches its events; read state, then finish the tick.
+        engine.render_frame();
+        let ch = engine.machine(tracker).and_then(|m| m.channel_state(0)).expect("channel 0");
+        out.push((ch.effective_period(), ch.effective_volume()));
+        let rest = engine.transport().frames_to_tick() as usize;
+        engine.render_frames(rest);
+    }
+    out
+}
+
+fn assert_trace(name: &str, rows: &[Cell], volume: u8, expected: &[(u16, u8)]) {
+    let actual = trace(rows, volume, expected.len());
+    for (tick, (got, want)) in actual.iter().zip(expected).enumerate() {
+        assert_eq!(
+            got, want,
+            "{}: tick {} (row {}, tick {}) (period, volume) mismatch\n  got:      {:?}\n  expected: {:?}",
+            name, tick, tick / 6, tick % 6, actual, expected
+        );
+    }
+}
+
+/// Repeat a (period, volume) pair `n` times.
+fn hold(period: u16, volume: u8, n: usize) -> Vec<(u16, u8)> {
+    vec![(period, volume); n]
+}
+
+fn periods(ps: &[u16], volume: u8) -> Vec<(u16, u8)> {
+    ps.iter().map(|&p| (p, volume)).collect()
+}
+
+fn volumes(period: u16, vs: &[u8]) -> Vec<(u16, u8)> {
+    vs.iter().map(|&v| (period, v)).collect()
+}
+
+// --- Pitch ---
+
+#[test]
+fn porta_up_slides_on_non_zero_ticks() {
+    let expected = [periods(&[428, 426, 424, 422, 420, 418], 64), hold(418, 64, 6)].concat();
+    assert_trace("1xx porta up", &[note(428, 0x1, 2)], 64, &expected);
+}
+
+#[test]
+fn porta_down_slides_on_non_zero_ticks() {
+    let expected = [periods(&[428, 432, 436, 440, 444, 448], 64), hold(448, 64, 6)].concat();
+    assert_trace("2xx porta down", &[note(428, 0x2, 4)], 64, &expected);
+}
+
+#[test]
+fn fine_porta_up_applies_once_on_tick_zero() {
+    let expected = hold(424, 64, 12);
+    assert_trace("E1x fine porta up", &[note(428, 0xE, 0x14)], 64, &expected);
+}
+
+#[test]
+fn fine_porta_down_applies_once_on_tick_zero() {
+    let expected = hold(431, 64, 12);
+    assert_trace("E2x fine porta down", &[note(428, 0xE, 0x23)], 64, &expected);
+}
+
+#[test]
+fn tone_porta_slides_toward_target_and_stops() {
+    let rows = [note(428, 0, 0), note(404, 0x3, 8), fx(0x3, 0)];
+    let expected = [
+        hold(428, 64, 6),
+        periods(&[428, 420, 412, 404, 404, 404], 64),
+        hold(404, 64, 6),
+    ].concat();
+    assert_trace("3xx tone porta", &rows, 64, &expected);
+}
+
+#[test]
+fn arpeggio_cycles_base_plus_x_plus_y() {
+    // C-2, D#2, G-2
+    let expected = periods(&[428, 360, 285, 428, 360, 285], 64);
+    assert_trace("0xy arpeggio", &[note(428, 0x0, 0x37)], 64, &expected);
+}
+
+// --- Volume ---
+
+#[test]
+fn set_volume_applies_on_tick_zero() {
+    let expected = hold(428, 16, 12);
+    assert_trace("Cxx set volume", &[note(428, 0xC, 0x10)], 64, &expected);
+}
+
+#[test]
+fn set_volume_clamps_to_64() {
+    let expected = hold(428, 64, 6);
+    assert_trace("Cxx clamp", &[note(428, 0xC, 0x50)], 32, &expected);
+}
+
+#[test]
+fn volume_slide_down_on_non_zero_ticks() {
+    let expected = [volumes(428, &[64, 60, 56, 52, 48, 44]), hold(428, 44, 6)].concat();
+    assert_trace("A0x volume slide down", &[note(428, 0xA, 0x04)], 64, &expected);
+}
+
+#[test]
+fn volume_slide_up_clamps_at_64() {
+    let expected = volumes(428, &[50, 58, 64, 64, 64, 64]);
+    assert_trace("Ax0 volume slide up", &[note(428, 0xA, 0x80)], 50, &expected);
+}
+
+#[test]
+fn volume_slide_continues_from_previous_row() {
+    let rows = [note(428, 0xC, 0x20), fx(0xA, 0x20)];
+    let expected = [hold(428, 32, 6), volumes(428, &[32, 34, 36, 38, 40, 42])].concat();
+    assert_trace("Ax0 after Cxx", &rows, 64, &expected);
+}
+
+#[test]
+fn fine_volume_slide_down_applies_once() {
+    let expected = hold(428, 60, 12);
+    assert_trace("EBx fine volume slide down", &[note(428, 0xE, 0xB4)], 64, &expected);
+}
+
+#[test]
+fn fine_volume_slide_up_applies_once() {
+    let expected = hold(428, 36, 12);
+    assert_trace("EAx fine volume slide up", &[note(428, 0xE, 0xA4)], 32, &expected);
+}
+
+#[test]
+fn note_cut_silences_on_given_tick() {
+    let expected = [volumes(428, &[64, 64, 64, 0, 0, 0]), hold(428, 0, 6)].concat();
+    assert_trace("ECx note cut", &[note(428, 0xE, 0xC3)], 64, &expected);
+}
+
+// --- Timing ---
+
+#[test]
+fn set_speed_changes_ticks_per_row() {
+    // F03: three ticks per row, so the slide restarts on row 1 after 3 ticks
+    let rows = [note(428, 0xF, 0x03), fx(0x1, 2)];
+    let expected = [hold(428, 64, 3), periods(&[428, 426, 424], 64)].concat();
+    assert_trace("F0x set speed", &rows, 64, &expected);
+}
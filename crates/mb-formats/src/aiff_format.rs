@@ -0,0 +1,222 @@
+//! AIFF and AIFF-C sample import.
+
+use crate::raw_format::decode_pcm;
+use crate::FormatError;
+use mb_ir::{LoopType, Sample};
+
+/// MIDI note that plays as C-4.
+const MIDI_C4: f64 = 60.0;
+
+/// Load an AIFF or uncompressed AIFF-C file into a Sample.
+///
+/// 8- and 16-bit audio is kept as is; deeper audio is reduced to 16 bits.
+/// The instrument chunk's sustain loop and base note (with detune) set the
+/// loop and `c4_speed`.
+pub fn load_aiff(data: &[u8], name: &str) -> Result<Sample, FormatError> {
+    if data.len() < 12 {
+        return Err(FormatError::UnexpectedEof);
+    }
+    let aifc = match &data[8..12] {
+        b"AIFF" => false,
+        b"AIFC" => true,
+        _ => return Err(FormatError::InvalidHeader),
+    };
+    if &data[0..4] != b"FORM" {
+        return Err(FormatError::InvalidHeader);
+    }
+    let chunk = |wanted: &[u8; 4]| chunks(data).find(|(id, _)| id == wanted).map(|(_, body)| body);
+    let comm = chunk(b"COMM").ok_or(FormatError::InvalidHeader)?;
+    let ssnd = chunk(b"SSND").ok_or(FormatError::InvalidHeader)?;
+    if comm.len() < 18 || ssnd.len() < 8 {
+        return Err(FormatError::UnexpectedEof);
+    }
+
+    let channels = read_u16_be(comm, 0);
+    let frames = read_u32_be(comm, 2) as usize;
+    let bits = read_u16_be(comm, 6);
+    let sample_rate = read_extended(&comm[8..18]);
+    let little_endian = match comm.get(18..22).filter(|_| aifc) {
+        None | Some(b"NONE") | Some(b"twos") => false,
+        Some(b"sowt") => true,
+        Some(other) => {
+            let kind = String::from_utf8_lossy(other);
+            return Err(FormatError::Unsupported(format!("AIFF-C compression '{}'", kind)));
+        }
+    };
+    if !(1..=2).contains(&channels) || !(1..=32).contains(&bits) {
+        return Err(FormatError::UnsupportedVersion);
+    }
+
+    let bytes = bits.div_ceil(8) as usize;
+    let offset = read_u32_be(ssnd, 0) as usize;
+    let audio = ssnd.get(8 + offset..).unwrap_or(&[]);
+    let frames = frames.min(audio.len() / (bytes * channels as usize));
+    let audio = &audio[..frames * bytes * channels as usize];
+
+    let mut sample = Sample::new(name);
+    sample.set_data(decode_pcm(audio, channels == 2, bytes, false, !little_endian));
+    sample.c4_speed = sample_rate.round() as u32;
+    if let Some(inst) = chunk(b"INST").filter(|inst| inst.len() >= 14) {
+        apply_instrument(&mut sample, sample_rate, inst, chunk(b"MARK").unwrap_or(&[]));
+    }
+    Ok(sample)
+}
+
+/// Root key and sustain loop from an `INST` chunk (loop points are
+/// marker ids looked up in the `MARK` chunk).
+fn apply_instrument(sample: &mut Sample, sample_rate: f64, inst: &[u8], mark: &[u8]) {
+    let (base_note, detune) = (inst[0], inst[1] as i8);
+    if base_note < 128 {
+        let semitones = MIDI_C4 - base_note as f64 - detune as f64 / 100.0;
+        sample.c4_speed = (sample_rate * 2f64.powf(semitones / 12.0)).round() as u32;
+    }
+    let kind = match read_u16_be(inst, 8) {
+        1 => LoopType::Forward,
+        2 => LoopType::PingPong,
+        _ => return,
+    };
+    let markers = markers(mark);
+    let position = |id: u16| markers.iter().find(|&&(m, _)| m == id).map(|&(_, pos)| pos);
+    let (Some(start), Some(end)) = (position(read_u16_be(inst, 10)), position(read_u16_be(inst, 12))) else { return };
+    if start < end && end <= sample.len() as u32 {
+        sample.loop_start = start;
+        sample.loop_end = end;
+        sample.loop_type = kind;
+    }
+}
+
+/// `(id, position)` of each marker in a `MARK` chunk body.
+fn markers(mark: &[u8]) -> Vec<(u16, u32)> {
+    let count = if mark.len() >= 2 { read_u16_be(mark, 0) } else { 0 };
+    let mut pos = 2;
+    let mut out = Vec::new();
+    for _ in 0..count {
+        let Some(&name_len) = mark.get(pos + 6) else { break };
+        out.push((read_u16_be(mark, pos), read_u32_be(mark, pos + 2)));
+        // Count byte and name are padded to an even length
+        pos += 6 + (1 + name_len as usize).next_multiple_of(2);
+    }
+    out
+}
+
+/// `(id, body)` of each chunk in the FORM.
+fn chunks(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut pos = 12;
+    core::iter::from_fn(move || {
+        if pos + 8 > data.len() {
+            return None;
+        }
+        let size = read_u32_be(data, pos + 4) as usize;
+        let id = &data[pos..pos + 4];
+        let body = &data[pos + 8..(pos + 8 + size).min(data.len())];
+        pos += 8 + size.next_multiple_of(2);
+        Some((id, body))
+    })
+}
+
+/// Decode an 80-bit IEEE extended float (the COMM sample rate).
+fn read_extended(b: &[u8]) -> f64 {
+    let exponent = (read_u16_be(b, 0) & 0x7FFF) as i32;
+    let mantissa = u64::from_be_bytes([b[2], b[3], b[4], b[5], b[6], b[7], b[8], b[9]]);
+    let value = mantissa as f64 * 2f64.powi(exponent - 16383 - 63);
+    if b[0] & 0x80 != 0 { -value } else { value }
+}
+
+fn read_u16_be(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32_be(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mb_ir::SampleData;
+
+    fn extended(rate: u32) -> [u8; 10] {
+        let shift = rate.leading_zeros() + 32;
+        let exponent = (16383 + 63 - shift) as u16;
+        let mut out = [0; 10];
+        out[..2].copy_from_slice(&exponent.to_be_bytes());
+        out[2..].copy_from_slice(&((rate as u64) << shift).to_be_bytes());
+        out
+    }
+
+    fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = id.to_vec();
+        out.extend((body.len() as u32).to_be_bytes());
+        out.extend(body);
+        if body.len() % 2 == 1 {
+            out.push(0);
+        }
+        out
+    }
+
+    /// An AIFF (or AIFF-C with `compression`) file around `audio`.
+    fn aiff(channels: u16, bits: u16, compression: Option<&[u8; 4]>, audio: &[u8], extra: &[Vec<u8>]) -> Vec<u8> {
+        let frames = audio.len() / (channels as usize * bits.div_ceil(8) as usize);
+        let mut comm = channels.to_be_bytes().to_vec();
+        comm.extend((frames as u32).to_be_bytes());
+        comm.extend(bits.to_be_bytes());
+        comm.extend(extended(44100));
+        if let Some(kind) = compression {
+            comm.extend(kind);
+            comm.extend([0, 0]); // Empty compression name
+        }
+        let mut ssnd = vec![0; 8];
+        ssnd.extend(audio);
+        let mut body = if compression.is_some() { b"AIFC".to_vec() } else { b"AIFF".to_vec() };
+        body.extend(chunk(b"COMM", &comm));
+        for c in extra {
+            body.extend(c);
+        }
+        body.extend(chunk(b"SSND", &ssnd));
+        chunk(b"FORM", &body)
+    }
+
+    #[test]
+    fn reads_16bit_stereo() {
+        let sample = load_aiff(&aiff(2, 16, None, &[0x01, 0x02, 0xFF, 0xFE, 0x7F, 0xFF, 0x80, 0x00], &[]), "s").unwrap();
+        assert_eq!(*sample.data, SampleData::Stereo16(vec![0x0102, 0x7FFF], vec![-2, -0x8000]));
+        assert_eq!(sample.c4_speed, 44100);
+    }
+
+    #[test]
+    fn reads_8bit_and_24bit() {
+        let sample = load_aiff(&aiff(1, 8, None, &[0x00, 0x7F, 0x80], &[]), "8").unwrap();
+        assert_eq!(*sample.data, SampleData::Mono8(vec![0, 127, -128]));
+        let sample = load_aiff(&aiff(1, 24, None, &[0x12, 0x34, 0x56, 0xFF, 0xFF, 0xFF], &[]), "24").unwrap();
+        assert_eq!(*sample.data, SampleData::Mono16(vec![0x1234, -1]));
+    }
+
+    #[test]
+    fn aifc_byte_order_and_compression() {
+        let sample = load_aiff(&aiff(1, 16, Some(b"sowt"), &[0x01, 0x02], &[]), "sowt").unwrap();
+        assert_eq!(*sample.data, SampleData::Mono16(vec![0x0201]));
+        let sample = load_aiff(&aiff(1, 16, Some(b"NONE"), &[0x01, 0x02], &[]), "none").unwrap();
+        assert_eq!(*sample.data, SampleData::Mono16(vec![0x0102]));
+        assert!(matches!(load_aiff(&aiff(1, 16, Some(b"ima4"), &[0; 34], &[]), "adpcm"), Err(FormatError::Unsupported(_))));
+    }
+
+    #[test]
+    fn instrument_sets_loop_and_root_key() {
+        let mut mark = 2u16.to_be_bytes().to_vec();
+        mark.extend([0, 1, 0, 0, 0, 10, 3, b'b', b'e', b'g']);
+        mark.extend([0, 2, 0, 0, 0, 90, 0, 0]);
+        // Base note C-5, sustain loop forward/backward from marker 1 to 2
+        let inst = [72, 0, 0, 127, 1, 127, 0, 0, 0, 2, 0, 1, 0, 2, 0, 0, 0, 0, 0, 0];
+        let extra = [chunk(b"MARK", &mark), chunk(b"INST", &inst)];
+        let sample = load_aiff(&aiff(1, 8, None, &[0; 100], &extra), "inst").unwrap();
+        assert_eq!((sample.loop_start, sample.loop_end, sample.loop_type), (10, 90, LoopType::PingPong));
+        assert_eq!(sample.c4_speed, 22050);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(matches!(load_aiff(b"RIFF\0\0\0\0WAVE", "wav"), Err(FormatError::InvalidHeader)));
+        assert!(load_aiff(b"FORM", "short").is_err());
+        assert!(load_aiff(&aiff(3, 16, None, &[0; 6], &[]), "surround").is_err());
+    }
+}
@@ -0,0 +1,863 @@
+//! Buzz BMX writer.
+//!
+//! Writes the song's graph, tracks and instruments back out as a BMX file:
+//! machines (with a PARA section describing their parameters), wires,
+//! tracker and wire-automation patterns, sequences, and uncompressed waves.
+//! Anything BMX can't express is dropped, and each kind of loss is reported.
+
+use alloc::vec::Vec;
+use mb_ir::{
+    Cell, Connection, Effect, Envelope, Instrument, LoopType, Node, NodeType, Note, Pattern, Sample,
+    SampleData, SeqTermination, Song, Track, VolumeCommand, WireValue, SUB_BEAT_UNIT,
+};
+
+use crate::bmx_format::{
+    is_trigger_dll, master_para_def, trigger_param, BmxParaDef, BmxParam, ENVELOPE_X_PER_TICK, PT_BYTE,
+    PT_WORD, WIRE_NO_VALUE,
+};
+use crate::mod_export::encode_effect;
+use crate::FormatError;
+
+/// Written for tracker nodes with one effect column.
+const TRACKER_DLL: &str = "Jeskola Tracker";
+/// Written for tracker nodes whose cells use the second effect column.
+const TRACKER2_DLL: &str = "Matilde Tracker 2";
+/// Most bytes per tracker row and track: note, wave, volume, then two
+/// effects with arguments.
+const TRACKER_TRACK_BYTES: usize = 7;
+/// Most waves a Buzz wave table holds.
+const MAX_WAVES: usize = 200;
+/// Buzz parameter type of a note column.
+const PT_NOTE: u8 = 0;
+/// Parameter flag: the value is machine state, saved with the song.
+const MPF_STATE: i32 = 2;
+
+/// A written BMX file and the lossy conversions made to produce it.
+#[derive(Debug, Default)]
+pub struct BmxExport {
+    /// The BMX file bytes
+    pub data: Vec<u8>,
+    /// One line per kind of loss (empty if the export is exact)
+    pub warnings: Vec<String>,
+}
+
+impl BmxExport {
+    fn warn(&mut self, msg: String) {
+        if !self.warnings.contains(&msg) {
+            self.warnings.push(msg);
+        }
+    }
+}
+
+/// A graph node written as a Buzz machine.
+struct Machine<'a> {
+    node: &'a Node,
+    name: String,
+    /// Machine DLL; `None` for Master
+    dll: Option<String>,
+    para: BmxParaDef,
+    /// The track playing this machine's patterns
+    track: Option<&'a Track>,
+    /// Wires into this machine, in CONN order
+    inputs: Vec<(u16, &'a Connection)>,
+}
+
+impl Machine<'_> {
+    fn is_tracker(&self) -> bool {
+        matches!(self.node.node_type, NodeType::Machine { is_tracker: true, .. })
+    }
+
+    /// Tracks (tracker columns) the machine has.
+    fn num_tracks(&self) -> u16 {
+        if self.is_tracker() { self.track.map_or(0, |t| t.num_channels as u16) } else { 0 }
+    }
+}
+
+/// One Buzz wave: an instrument's samples as levels keyed by root note.
+struct Wave<'a> {
+    name: &'a str,
+    envelopes: [Option<&'a Envelope>; 3],
+    /// Samples with their root notes (engine note numbers), in level order
+    levels: Vec<(&'a Sample, u8)>,
+}
+
+/// Write a song as a Buzz BMX file.
+///
+/// Fails with `FormatError::Unsupported` when the graph has no Master node.
+pub fn write_bmx(song: &Song) -> Result<BmxExport, FormatError> {
+    let mut out = BmxExport::default();
+    if !matches!(song.graph.node(0).map(|n| &n.node_type), Some(NodeType::Master)) {
+        return Err(FormatError::Unsupported("graph has no Master node".into()));
+    }
+    if song.subsongs.len() > 1 {
+        out.warn("only the selected subsong is written".into());
+    }
+    let machines = collect_machines(song, &mut out);
+    let waves = collect_waves(song, &mut out);
+
+    let mut sections: Vec<(&[u8; 4], Vec<u8>)> = alloc::vec![
+        (b"PARA", write_para(&machines)),
+        (b"MACH", write_mach(song, &machines)),
+        (b"CONN", write_conn(&machines)),
+        (b"PATT", write_patt(&machines, waves.len(), &mut out)),
+        (b"SEQU", write_sequ(song, &machines, &mut out)),
+    ];
+    if !waves.is_empty() {
+        sections.push((b"WAVT", write_wavt(&waves, &mut out)));
+        sections.push((b"CWAV", write_cwav(&waves)));
+    }
+
+    let mut data = b"Buzz".to_vec();
+    put_u32(&mut data, sections.len() as u32);
+    let mut offset = 8 + sections.len() * 12;
+    for (name, body) in &sections {
+        data.extend_from_slice(*name);
+        put_u32(&mut data, offset as u32);
+        put_u32(&mut data, body.len() as u32);
+        offset += body.len();
+    }
+    for (_, body) in &sections {
+        data.extend_from_slice(body);
+    }
+    out.data = data;
+    Ok(out)
+}
+
+// ---------------------------------------------------------------------------
+// Machines and wires
+// ---------------------------------------------------------------------------
+
+/// Master first, then every machine node in graph order, each with the
+/// track that plays it and the wires into it.
+fn collect_machines<'a>(song: &'a Song, out: &mut BmxExport) -> Vec<Machine<'a>> {
+    let mut machines: Vec<Machine<'a>> = Vec::new();
+    let mut index = alloc::vec![None; song.graph.nodes.len()];
+    for node in &song.graph.nodes {
+        let (name, dll, para) = match &node.node_type {
+            NodeType::Master => (String::from("Master"), None, master_para_def()),
+            NodeType::Machine { machine_name, is_tracker: true } => {
+                (machine_name.clone(), Some(String::from(TRACKER_DLL)), tracker_para_def(false))
+            }
+            NodeType::Machine { machine_name, is_tracker: false } => {
+                (machine_name.clone(), Some(machine_name.clone()), node_para_def(node))
+            }
+            other => {
+                out.warn(format!("{} nodes have no BMX equivalent and were dropped", other.label()));
+                continue;
+            }
+        };
+        index[node.id as usize] = Some(machines.len() as u16);
+        let name = unique_name(&machines, name);
+        machines.push(Machine { node, name, dll, para, track: None, inputs: Vec::new() });
+    }
+
+    for conn in &song.graph.connections {
+        let ends = (index.get(conn.from as usize).copied().flatten(), index.get(conn.to as usize).copied().flatten());
+        match ends {
+            (Some(from), Some(to)) => machines[to as usize].inputs.push((from, conn)),
+            _ => out.warn("wires to or from dropped nodes were dropped".into()),
+        }
+    }
+
+    for track in &song.tracks {
+        let machine = track.machine_node.and_then(|n| index.get(n as usize).copied().flatten());
+        match machine.map(|m| &mut machines[m as usize]) {
+            Some(m) if m.track.is_none() => m.track = Some(track),
+            Some(_) => out.warn("tracks sharing a machine dropped after the first".into()),
+            None => out.warn("tracks without a machine dropped".into()),
+        }
+    }
+
+    for m in machines.iter_mut().filter(|m| m.is_tracker()) {
+        let second_effects = m.track.is_some_and(|t| {
+            t.clips.iter().filter_map(|c| c.pattern()).any(|p| p.data.iter().any(|c| c.effect2 != Effect::None))
+        });
+        if second_effects {
+            m.dll = Some(String::from(TRACKER2_DLL));
+            m.para = tracker_para_def(true);
+        }
+    }
+    machines
+}
+
+/// `name`, numbered if an earlier machine already uses it (Buzz looks
+/// machines up by name).
+fn unique_name(machines: &[Machine], name: String) -> String {
+    let taken = |n: &str| machines.iter().any(|m| m.name == n);
+    if !taken(&name) {
+        return name;
+    }
+    (2..).map(|i| format!("{} {}", name, i)).find(|n| !taken(n)).unwrap_or(name)
+}
+
+/// Jeskola Tracker: a subdivide byte, then note/wave/volume/effect/argument
+/// tracks. `second_effect` adds Matilde Tracker 2's second effect and argument.
+fn tracker_para_def(second_effect: bool) -> BmxParaDef {
+    let param = |param_type, name: &str, min, max, no_value| BmxParam {
+        param_type, name: String::from(name), min, max, no_value, flags: 0, default: no_value,
+    };
+    let mut track_params = alloc::vec![
+        param(PT_NOTE, "Note", 1, 0x9C, 0),
+        param(PT_BYTE, "Wave", 1, MAX_WAVES as i32, 0),
+        param(PT_BYTE, "Volume", 0, 0xFE, 0xFF),
+        param(PT_BYTE, "Effect", 0, 0xFF, 0),
+        param(PT_BYTE, "Argument", 0, 0xFF, 0),
+    ];
+    if second_effect {
+        track_params.push(param(PT_BYTE, "Effect 2", 0, 0xFF, 0));
+        track_params.push(param(PT_BYTE, "Argument 2", 0, 0xFF, 0));
+    }
+    BmxParaDef { global_params: alloc::vec![param(PT_BYTE, "Subdivide", 1, 32, 0xFF)], track_params }
+}
+
+/// Global parameters of a machine node: a byte where the range fits,
+/// otherwise a word.
+fn node_para_def(node: &Node) -> BmxParaDef {
+    let global_params = node.parameters.iter()
+        .map(|p| {
+            let byte = p.min >= 0 && p.max < 0xFF;
+            BmxParam {
+                param_type: if byte { PT_BYTE } else { PT_WORD },
+                name: String::from(p.name.as_str()),
+                min: p.min,
+                max: p.max,
+                no_value: if byte { 0xFF } else { WIRE_NO_VALUE as i32 },
+                flags: MPF_STATE,
+                default: p.default,
+            }
+        })
+        .collect();
+    BmxParaDef { global_params, track_params: Vec::new() }
+}
+
+fn write_para(machines: &[Machine]) -> Vec<u8> {
+    let mut data = Vec::new();
+    put_u32(&mut data, machines.len() as u32);
+    for m in machines {
+        put_str(&mut data, &m.name);
+        put_str(&mut data, m.dll.as_deref().unwrap_or("Master"));
+        put_u32(&mut data, m.para.global_params.len() as u32);
+        put_u32(&mut data, m.para.track_params.len() as u32);
+        for p in m.para.global_params.iter().chain(&m.para.track_params) {
+            data.push(p.param_type);
+            put_str(&mut data, &p.name);
+            for v in [p.min, p.max, p.no_value, p.flags, p.default] {
+                put_u32(&mut data, v as u32);
+            }
+        }
+    }
+    data
+}
+
+fn write_mach(song: &Song, machines: &[Machine]) -> Vec<u8> {
+    let mut data = Vec::new();
+    put_u16(&mut data, machines.len() as u16);
+    for (i, m) in machines.iter().enumerate() {
+        put_str(&mut data, &m.name);
+        // Master, generator (nothing wired in, or a tracker) or effect
+        let machine_type = match &m.dll {
+            None => 0,
+            Some(_) if m.is_tracker() || m.inputs.is_empty() => 1,
+            Some(_) => 2,
+        };
+        data.push(machine_type);
+        if let Some(dll) = &m.dll {
+            put_str(&mut data, dll);
+        }
+        // Spread the machines across the view, Master on the right
+        let x = if machine_type == 0 { 0.8 } else { -0.8 + 1.4 * i as f32 / machines.len() as f32 };
+        put_f32(&mut data, x);
+        put_f32(&mut data, if machine_type == 0 { 0.0 } else { -0.5 + (i % 3) as f32 * 0.5 });
+        put_u32(&mut data, 0); // init data size
+        put_u16(&mut data, 0); // attributes
+
+        // Global parameter state
+        if machine_type == 0 {
+            put_u16(&mut data, 0); // full volume
+            put_u16(&mut data, master_bpm(song));
+            data.push(song.rows_per_beat);
+        } else if m.is_tracker() {
+            data.push(4);
+        } else {
+            for (p, def) in m.node.parameters.iter().zip(&m.para.global_params) {
+                put_param(&mut data, def.param_type, p.value);
+            }
+        }
+
+        put_u16(&mut data, m.num_tracks());
+        for _ in 0..m.num_tracks() {
+            data.extend(m.para.track_params.iter().map(|p| p.no_value as u8));
+        }
+    }
+    data
+}
+
+/// Buzz BPM playing the song's rows at the same rate with one tick per row.
+fn master_bpm(song: &Song) -> u16 {
+    let per_beat = song.initial_speed.max(1) as u32 * song.rows_per_beat.max(1) as u32;
+    let hundredths = per_beat * 100;
+    ((song.initial_tempo * 24 + hundredths / 2) / hundredths).clamp(16, 512) as u16
+}
+
+fn write_conn(machines: &[Machine]) -> Vec<u8> {
+    let wires: Vec<(u16, u16, &Connection)> = machines.iter().enumerate()
+        .flat_map(|(to, m)| m.inputs.iter().map(move |&(from, conn)| (from, to as u16, conn)))
+        .collect();
+    let mut data = Vec::new();
+    put_u16(&mut data, wires.len() as u16);
+    for (from, to, conn) in wires {
+        put_u16(&mut data, from);
+        put_u16(&mut data, to);
+        put_u16(&mut data, gain_to_amplitude(conn.gain));
+        put_u16(&mut data, pan_to_buzz_pan(conn.pan));
+    }
+    data
+}
+
+/// Inverse of `bmx_format::amplitude_to_gain`: 0x4000 is unity.
+fn gain_to_amplitude(gain: i16) -> u16 {
+    ((gain as i32 + 100).max(0) * 0x4000 / 100).min(0xFFFE) as u16
+}
+
+/// Inverse of `bmx_format::buzz_pan_to_pan`: 0 left, 0x4000 centre, 0x8000 right.
+fn pan_to_buzz_pan(pan: i8) -> u16 {
+    (pan.clamp(-64, 64) as i32 * 0x4000 / 64 + 0x4000) as u16
+}
+
+// ---------------------------------------------------------------------------
+// Patterns and sequences
+// ---------------------------------------------------------------------------
+
+fn write_patt(machines: &[Machine], num_waves: usize, out: &mut BmxExport) -> Vec<u8> {
+    let mut data = Vec::new();
+    for m in machines {
+        let patterns: Vec<&Pattern> = m.track.map_or_else(Vec::new, |t| t.clips.iter().filter_map(|c| c.pattern()).collect());
+        put_u16(&mut data, patterns.len() as u16);
+        put_u16(&mut data, m.num_tracks());
+        for (i, pattern) in patterns.iter().enumerate() {
+            if pattern.ticks_per_row != 0 || pattern.rows_per_beat.is_some() {
+                out.warn("per-pattern speed and rows-per-beat overrides dropped".into());
+            }
+            put_str(&mut data, &format!("{:02}", i));
+            put_u16(&mut data, pattern.rows);
+            for &(from, conn) in &m.inputs {
+                put_u16(&mut data, from);
+                let lane = pattern.wires.iter().find(|l| l.from == conn.from);
+                for row in 0..pattern.rows as usize {
+                    let value = lane.and_then(|l| l.values.get(row)).copied().unwrap_or_default();
+                    put_u16(&mut data, value.gain.map_or(WIRE_NO_VALUE, gain_to_amplitude));
+                    put_u16(&mut data, value.pan.map_or(WIRE_NO_VALUE, pan_to_buzz_pan));
+                }
+            }
+            let wired = |lane: &&mb_ir::WireLane| m.inputs.iter().any(|(_, c)| c.from == lane.from);
+            if pattern.wires.iter().any(|lane| !wired(&lane) && lane.values.iter().any(|v| *v != WireValue::default())) {
+                out.warn("automation of missing wires dropped".into());
+            }
+            write_pattern_rows(&mut data, m, pattern, num_waves, out);
+        }
+    }
+    data
+}
+
+/// Global rows then track rows: tracker cells, drum triggers, or no values.
+fn write_pattern_rows(data: &mut Vec<u8>, m: &Machine, pattern: &Pattern, num_waves: usize, out: &mut BmxExport) {
+    let no_values: Vec<u8> = m.para.global_params.iter()
+        .flat_map(|p| {
+            let bytes = (p.no_value as u16).to_le_bytes();
+            bytes.into_iter().take(if p.param_type == PT_WORD { 2 } else { 1 })
+        })
+        .collect();
+    let trigger = m.dll.as_deref().filter(|dll| is_trigger_dll(dll)).and_then(|_| trigger_param(&m.para));
+    let notes_dropped = !m.is_tracker() && trigger.is_none()
+        && pattern.data.iter().any(|c| c.note != Note::None || c.effect != Effect::None);
+    if notes_dropped {
+        out.warn("notes on machines without a tracker dropped".into());
+    }
+
+    for row in 0..pattern.rows {
+        let start = data.len();
+        data.extend_from_slice(&no_values);
+        let Some(trigger) = trigger.as_ref().filter(|t| t.global) else { continue };
+        let cell = (pattern.channels > 0).then(|| pattern.cell(row, 0));
+        if let Some(Cell { note: Note::On(_), volume, .. }) = cell {
+            let value = match volume {
+                VolumeCommand::Volume(v) => volume_byte(*v).max(1),
+                _ => 0x80,
+            };
+            data[start + trigger.offset] = value;
+        }
+    }
+    if trigger.is_some() && pattern.channels > 1 {
+        out.warn("drum machine columns after the first dropped".into());
+    }
+
+    if m.is_tracker() {
+        for track in 0..m.num_tracks() as u8 {
+            for row in 0..pattern.rows {
+                let cell = if track < pattern.channels { *pattern.cell(row, track) } else { Cell::empty() };
+                let bytes = encode_cell(&cell, num_waves, out);
+                data.extend_from_slice(&bytes[..m.para.track_params.len()]);
+            }
+        }
+    }
+}
+
+/// Pack a cell into a tracker's note, wave, volume, and both effects with
+/// their arguments.
+fn encode_cell(cell: &Cell, num_waves: usize, out: &mut BmxExport) -> [u8; TRACKER_TRACK_BYTES] {
+    let note = match cell.note {
+        Note::None => 0,
+        Note::On(n) => ((n / 12).min(9) << 4) | (n % 12 + 1),
+        Note::Off => 255,
+        Note::Fade => {
+            out.warn("note fades written as note-offs".into());
+            255
+        }
+    };
+    let mut wave = cell.instrument;
+    if wave as usize > num_waves {
+        out.warn("cells referencing missing instruments cleared".into());
+        wave = 0;
+    }
+    let volume = match cell.volume {
+        VolumeCommand::None => 0xFF,
+        VolumeCommand::Volume(v) => volume_byte(v),
+        _ => {
+            out.warn("volume column commands other than volume dropped".into());
+            0xFF
+        }
+    };
+    let (cmd, arg) = encode_buzz_effect(cell.effect, out);
+    let (cmd2, arg2) = encode_buzz_effect(cell.effect2, out);
+    [note, wave, volume, cmd, arg, cmd2, arg2]
+}
+
+/// Tracker effect command and argument.
+fn encode_buzz_effect(effect: Effect, out: &mut BmxExport) -> (u8, u8) {
+    match effect {
+        Effect::FractionalSampleOffset(v) => (0x9, v),
+        Effect::SampleOffset(v) => {
+            out.warn("sample offsets written as Buzz fractional offsets".into());
+            (0x9, v)
+        }
+        effect => encode_effect(effect).unwrap_or_else(|| {
+            out.warn(format!("{} effects have no Buzz equivalent and were dropped", effect.name()));
+            (0, 0)
+        }),
+    }
+}
+
+/// Buzz volume byte for a 0-64 volume: 0x80 is full.
+fn volume_byte(v: u8) -> u8 {
+    (v.min(64) as u32 * 0x80 / 64) as u8
+}
+
+fn write_sequ(song: &Song, machines: &[Machine], out: &mut BmxExport) -> Vec<u8> {
+    let rpb = song.rows_per_beat.max(1) as u64;
+    let mut off_row = false;
+    let mut rows = |t: mb_ir::MusicalTime| {
+        let sub = t.as_sub_beats() * rpb;
+        off_row |= !sub.is_multiple_of(SUB_BEAT_UNIT as u64);
+        (sub / SUB_BEAT_UNIT as u64) as u32
+    };
+
+    let mut sequences = Vec::new();
+    for (i, m) in machines.iter().enumerate() {
+        // Every track gets a sequence, even an empty one: the loader only
+        // builds tracks for sequenced machines
+        let Some(track) = m.track else { continue };
+        let mut events: Vec<(u32, u16)> = Vec::new();
+        for (j, entry) in track.sequence.iter().enumerate() {
+            let start = rows(entry.start);
+            let end = start + entry.length as u32;
+            events.push((start, entry.clip_idx.min(0x7FFF - 16) + 16));
+            let clip_rows = track.get_pattern_at(entry.clip_idx as usize).map_or(0, |p| p.rows);
+            let next_start = track.sequence.get(j + 1).map(|e| rows(e.start));
+            let cut_short = entry.length < clip_rows && next_start.is_none_or(|n| n > end);
+            match entry.termination {
+                SeqTermination::Mute => events.push((end, 0)),
+                SeqTermination::Break => events.push((end, 1)),
+                SeqTermination::Natural if cut_short => events.push((end, 1)),
+                SeqTermination::Natural => {}
+            }
+        }
+        sequences.push((i as u16, events));
+    }
+    let end = rows(song.total_time());
+    if off_row {
+        out.warn("sequence entries starting between rows moved to the row before".into());
+    }
+
+    let mut data = Vec::new();
+    put_u32(&mut data, end);
+    put_u32(&mut data, 0); // loop start
+    put_u32(&mut data, end); // loop end
+    put_u16(&mut data, sequences.len() as u16);
+    for (machine, events) in sequences {
+        put_u16(&mut data, machine);
+        put_u32(&mut data, events.len() as u32);
+        if !events.is_empty() {
+            data.push(4); // bytes per event position
+            data.push(2); // bytes per event
+        }
+        for (position, event) in events {
+            put_u32(&mut data, position);
+            put_u16(&mut data, event);
+        }
+    }
+    data
+}
+
+// ---------------------------------------------------------------------------
+// Waves
+// ---------------------------------------------------------------------------
+
+/// One wave per instrument, or per sample for songs without instruments
+/// (whose cells address samples directly).
+fn collect_waves<'a>(song: &'a Song, out: &mut BmxExport) -> Vec<Wave<'a>> {
+    let mut waves: Vec<Wave<'a>> = if song.instruments.is_empty() {
+        song.samples.iter()
+            .map(|s| Wave { name: s.name.as_str(), envelopes: [None; 3], levels: alloc::vec![(s, 48)] })
+            .collect()
+    } else {
+        song.instruments.iter().map(|inst| instrument_wave(song, inst, out)).collect()
+    };
+    if waves.len() > MAX_WAVES {
+        out.warn(format!("wave table truncated from {} to {} waves", waves.len(), MAX_WAVES));
+        waves.truncate(MAX_WAVES);
+    }
+    waves
+}
+
+/// An instrument's distinct samples as levels. The loader gives each note
+/// the level with the nearest root, so each root is placed to put the split
+/// between neighbouring levels where the instrument's sample map has it.
+fn instrument_wave<'a>(song: &'a Song, inst: &'a Instrument, out: &mut BmxExport) -> Wave<'a> {
+    let mut ranges: Vec<(u8, u8, u8)> = Vec::new(); // (sample, first note, last note)
+    for (note, &sample) in inst.sample_map.iter().enumerate() {
+        if sample as usize >= song.samples.len() {
+            continue;
+        }
+        match ranges.iter_mut().find(|r| r.0 == sample) {
+            Some(r) => r.2 = note as u8,
+            None => ranges.push((sample, note as u8, note as u8)),
+        }
+    }
+
+    let mut roots: Vec<u8> = Vec::with_capacity(ranges.len());
+    for (i, &(_, first, last)) in ranges.iter().enumerate() {
+        let root = match i {
+            0 if ranges.len() == 1 => 48,
+            0 => last,
+            // Halfway between this root and the previous one is the previous range's last note
+            _ => (2 * ranges[i - 1].2 as i32 + 1 - roots[i - 1] as i32).clamp(first as i32, last as i32) as u8,
+        };
+        roots.push(root);
+    }
+    let split_kept = inst.sample_map.iter().enumerate()
+        .filter(|&(_, &sample)| (sample as usize) < song.samples.len())
+        .all(|(note, &sample)| {
+            let nearest = (0..roots.len()).min_by_key(|&i| (note as i32 - roots[i] as i32).abs());
+            nearest.is_some_and(|i| ranges[i].0 == sample)
+        });
+    if !split_kept {
+        out.warn("instrument key splits approximated".into());
+    }
+    if inst.fadeout != 0 {
+        out.warn("instrument fadeout dropped".into());
+    }
+
+    Wave {
+        name: inst.name.as_str(),
+        envelopes: [inst.volume_envelope.as_ref(), inst.panning_envelope.as_ref(), inst.pitch_envelope.as_ref()],
+        levels: ranges.iter().zip(roots).map(|(&(sample, ..), root)| (&song.samples[sample as usize], root)).collect(),
+    }
+}
+
+fn write_wavt(waves: &[Wave], out: &mut BmxExport) -> Vec<u8> {
+    let mut data = Vec::new();
+    put_u16(&mut data, waves.len() as u16);
+    for (i, wave) in waves.iter().enumerate() {
+        let first = wave.levels.first().map(|&(s, _)| s);
+        let stereo = first.is_some_and(|s| s.data.num_channels() == 2);
+        let num_envelopes = wave.envelopes.iter().rposition(Option::is_some).map_or(0, |i| i + 1);
+        // Buzz loops (or doesn't) a whole wave, each level between its own points
+        let loop_type = wave.levels.iter().map(|&(s, _)| s.loop_type).max_by_key(|&t| t != LoopType::None).unwrap_or(LoopType::None);
+        if wave.levels.iter().any(|&(s, _)| s.loop_type != loop_type) {
+            out.warn("loop types differing within an instrument unified".into());
+        }
+        let flags = (loop_type != LoopType::None) as u8
+            | (stereo as u8) << 3
+            | ((loop_type == LoopType::PingPong) as u8) << 4
+            | ((num_envelopes > 0) as u8) << 7;
+        if wave.levels.iter().any(|&(s, _)| s.default_volume != first.map_or(64, |f| f.default_volume)) {
+            out.warn("per-sample volumes within an instrument dropped".into());
+        }
+
+        put_u16(&mut data, i as u16);
+        put_str(&mut data, ""); // file name
+        put_str(&mut data, wave.name);
+        put_f32(&mut data, first.map_or(64, |s| s.default_volume.min(64)) as f32 / 64.0);
+        data.push(flags);
+        if num_envelopes > 0 {
+            put_u16(&mut data, num_envelopes as u16);
+            for (j, env) in wave.envelopes[..num_envelopes].iter().enumerate() {
+                write_envelope(&mut data, *env, j == 0, out);
+            }
+        }
+
+        data.push(wave.levels.len() as u8);
+        for &(sample, root) in &wave.levels {
+            put_u32(&mut data, sample.len() as u32);
+            put_u32(&mut data, sample.loop_start);
+            put_u32(&mut data, sample.loop_end);
+            // The loader pitches `sample_rate` up from the root note to C-4
+            let rate = sample.c4_speed as f64 * 2f64.powf((48.0 - root as f64) / 12.0);
+            put_u32(&mut data, rate.round() as u32);
+            data.push(((root / 12) << 4) | (root % 12 + 1));
+        }
+    }
+    data
+}
+
+/// Write one envelope (an empty, disabled one for `None`). Points past
+/// 65535 / `ENVELOPE_X_PER_TICK` ticks are clamped.
+fn write_envelope(data: &mut Vec<u8>, env: Option<&Envelope>, volume: bool, out: &mut BmxExport) {
+    data.extend_from_slice(&[0; 10]); // ADSR attack/decay/sustain/release, subdivide, flags
+    let Some(env) = env else {
+        put_u16(data, 0x8000);
+        return;
+    };
+    if env.loop_start.is_some() || env.sustain_start != env.sustain_end {
+        out.warn("envelope loops dropped".into());
+    }
+    let count = env.points.len().min(0x7FFF) as u16;
+    put_u16(data, count | if env.enabled { 0 } else { 0x8000 });
+    for (i, p) in env.points.iter().take(count as usize).enumerate() {
+        let x = (p.tick as u32 * ENVELOPE_X_PER_TICK as u32).min(0xFFFF) as u16;
+        let y = if volume {
+            p.value.clamp(0, 64) as u32 * 0xFFFF / 64
+        } else {
+            (p.value.clamp(-64, 64) as i32 * 0x8000 / 64 + 0x8000).min(0xFFFF) as u32
+        };
+        put_u16(data, x);
+        put_u16(data, y as u16);
+        data.push((env.sustain_start == Some(i as u8)) as u8);
+    }
+}
+
+fn write_cwav(waves: &[Wave]) -> Vec<u8> {
+    let mut data = Vec::new();
+    put_u16(&mut data, waves.len() as u16);
+    for (i, wave) in waves.iter().enumerate() {
+        let stereo = wave.levels.first().is_some_and(|&(s, _)| s.data.num_channels() == 2);
+        let pcm: Vec<i16> = wave.levels.iter().flat_map(|&(s, _)| interleaved_i16(&s.data, stereo)).collect();
+        put_u16(&mut data, i as u16);
+        data.push(0); // uncompressed
+        put_u32(&mut data, (pcm.len() * 2) as u32);
+        for v in pcm {
+            put_u16(&mut data, v as u16);
+        }
+    }
+    data
+}
+
+/// 16-bit frames, interleaved when `stereo` (mono data is doubled up,
+/// stereo data mixed down otherwise).
+fn interleaved_i16(data: &SampleData, stereo: bool) -> Vec<i16> {
+    let frames = data.len();
+    let mut pcm = Vec::with_capacity(frames * (stereo as usize + 1));
+    for i in 0..frames {
+        let (l, r) = (data.get_mono(i), data.get_right(i));
+        if stereo {
+            pcm.extend_from_slice(&[l, r]);
+        } else {
+            pcm.push(((l as i32 + r as i32) / 2) as i16);
+        }
+    }
+    pcm
+}
+
+// ---------------------------------------------------------------------------
+// Byte helpers
+// ---------------------------------------------------------------------------
+
+fn put_u16(data: &mut Vec<u8>, v: u16) {
+    data.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_u32(data: &mut Vec<u8>, v: u32) {
+    data.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_f32(data: &mut Vec<u8>, v: f32) {
+    data.extend_from_slice(&v.to_le_bytes());
+}
+
+/// Null-terminated string.
+fn put_str(data: &mut Vec<u8>, s: &str) {
+    data.extend_from_slice(s.as_bytes());
+    data.push(0);
+}
+
+/// A parameter value as a byte or word.
+fn put_param(data: &mut Vec<u8>, param_type: u8, value: i32) {
+    if param_type == PT_WORD {
+        put_u16(data, value.clamp(0, 0xFFFE) as u16);
+    } else {
+        data.push(value.clamp(0, 0xFE) as u8);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load_bmx;
+    use mb_ir::{Clip, Parameter};
+
+    /// Tracker → Jeskola Delay → Master, with a two-level instrument and
+    /// wire automation on the delay's pattern.
+    fn buzz_song() -> Song {
+        let mut song = Song::new("bmx");
+        song.rows_per_beat = 4;
+        song.initial_speed = 1;
+        song.initial_tempo = 2100;
+        let t = song.add_track(2, "lead").unwrap();
+        let tracker = song.tracks[t].machine_node.unwrap();
+        let delay = song.graph.add_node(NodeType::Machine { machine_name: "Jeskola Delay".into(), is_tracker: false });
+        song.graph.node_mut(delay).unwrap().parameters.push(Parameter::new(0, "Length", 1, 0xFFFE, 0x300));
+        song.graph.disconnect(tracker, 0);
+        song.graph.try_connect(tracker, delay, -50);
+        song.graph.set_connection_pan(tracker, delay, 32);
+        song.graph.connect(delay, 0);
+
+        let mut low = Sample::new("low");
+        low.set_data(SampleData::Mono8((0..32).map(|i| i * 4 - 64).collect()));
+        (low.loop_start, low.loop_end, low.loop_type) = (8, 32, LoopType::Forward);
+        let mut high = Sample::new("high");
+        high.set_data(SampleData::Mono16((0..16).map(|i| i * 1000).collect()));
+        (high.loop_start, high.loop_end, high.loop_type) = (4, 12, LoopType::Forward);
+        song.samples.extend([low, high]);
+        let mut inst = Instrument::new("keys");
+        inst.sample_map[48..].fill(1);
+        let mut volume = Envelope::new();
+        volume.add_point(0, 64);
+        volume.add_point(4, 32);
+        volume.add_point(10, 0);
+        (volume.sustain_start, volume.sustain_end) = (Some(1), Some(1));
+        let mut panning = Envelope::new();
+        panning.add_point(0, -32);
+        panning.add_point(8, 32);
+        (inst.volume_envelope, inst.panning_envelope) = (Some(volume), Some(panning));
+        song.instruments.push(inst);
+
+        let mut a = Pattern::new(16, 2);
+        *a.cell_mut(0, 0) = Cell { note: Note::On(48), instrument: 1, volume: VolumeCommand::Volume(32), effect: Effect::VolumeSlide(-4), ..Cell::empty() };
+        a.cell_mut(4, 1).note = Note::Off;
+        *a.cell_mut(8, 0) = Cell { note: Note::On(60), instrument: 1, effect: Effect::FractionalSampleOffset(0x40), ..Cell::empty() };
+        let track = &mut song.tracks[t];
+        track.clips.extend([Clip::Pattern(a), Clip::Pattern(Pattern::new(8, 2))]);
+        for (i, clip) in [0, 1, 0].into_iter().enumerate() {
+            track.insert_seq_entry(i, clip, 4);
+        }
+
+        let mut wires = Pattern::new(16, 1);
+        wires.wire_lane_mut(tracker).values[0].gain = Some(-25);
+        wires.wire_lane_mut(tracker).values[8].pan = Some(-64);
+        let mut track = Track::new(Some(delay), 0, 1);
+        track.clips.push(Clip::Pattern(wires));
+        track.insert_seq_entry(0, 0, 4);
+        song.tracks.push(track);
+        song
+    }
+
+    #[test]
+    fn buzz_tempos_keep_fractions_and_go_past_255() {
+        // 125 Buzz BPM at 4 ticks per beat, and 200 at 32
+        for (rows_per_beat, tempo) in [(4, 2083), (32, 26666)] {
+            let mut song = buzz_song();
+            song.rows_per_beat = rows_per_beat;
+            song.initial_tempo = tempo;
+            let loaded = load_bmx(&write_bmx(&song).unwrap().data).unwrap();
+            assert_eq!(loaded.initial_tempo, tempo);
+        }
+    }
+
+    #[test]
+    fn round_trips_through_load_bmx() {
+        let song = buzz_song();
+        let export = write_bmx(&song).unwrap();
+        assert!(export.warnings.is_empty(), "{:?}", export.warnings);
+
+        let loaded = load_bmx(&export.data).unwrap();
+        assert_eq!((loaded.initial_tempo, loaded.rows_per_beat), (2100, 4));
+        let names: Vec<String> = loaded.graph.nodes.iter().map(|n| n.node_type.label()).collect();
+        assert_eq!(names, ["Master", "Tracker", "Jeskola Delay"]);
+        assert_eq!(loaded.graph.nodes[2].parameters[0].value, 0x300);
+        let wire = loaded.graph.connection(1, 2).unwrap();
+        assert_eq!((wire.gain, wire.pan), (-50, 32));
+
+        let track = &loaded.tracks[0];
+        assert_eq!(track.sequence.iter().map(|e| e.clip_idx).collect::<Vec<_>>(), [0, 1, 0]);
+        assert_eq!(track.sequence[2].start, song.tracks[0].sequence[2].start);
+        assert_eq!(track.clips[0].pattern().unwrap().data, song.tracks[0].clips[0].pattern().unwrap().data);
+        let lane = &loaded.tracks[1].clips[0].pattern().unwrap().wires[0];
+        assert_eq!((lane.from, lane.values[0].gain, lane.values[8].pan), (1, Some(-25), Some(-64)));
+
+        assert_eq!(loaded.samples.len(), 2);
+        for (orig, back) in song.samples.iter().zip(&loaded.samples) {
+            assert_eq!(back.len(), orig.len());
+            assert!((0..orig.len()).all(|i| back.data.get_mono(i) == orig.data.get_mono(i)));
+            assert_eq!((back.loop_start, back.loop_end, back.loop_type), (orig.loop_start, orig.loop_end, orig.loop_type));
+            assert!(back.c4_speed.abs_diff(orig.c4_speed) <= 1);
+        }
+        let inst = &loaded.instruments[0];
+        assert_eq!(inst.sample_map, song.instruments[0].sample_map);
+        let volume = inst.volume_envelope.as_ref().unwrap();
+        assert_eq!(volume.points, song.instruments[0].volume_envelope.as_ref().unwrap().points);
+        assert_eq!(volume.sustain_start, Some(1));
+        assert_eq!(inst.panning_envelope.as_ref().unwrap().points[0].value, -32);
+    }
+
+    #[test]
+    fn second_effect_column_round_trips() {
+        let mut song = buzz_song();
+        song.tracks[0].clips[0].pattern_mut().unwrap().cell_mut(2, 1).effect2 = Effect::Vibrato { speed: 3, depth: 5 };
+        let export = write_bmx(&song).unwrap();
+        assert!(export.warnings.is_empty(), "{:?}", export.warnings);
+
+        let loaded = load_bmx(&export.data).unwrap();
+        assert_eq!(loaded.tracks[0].clips[0].pattern().unwrap().data, song.tracks[0].clips[0].pattern().unwrap().data);
+    }
+
+    #[test]
+    fn reports_lossy_conversions() {
+        let mut song = buzz_song();
+        song.graph.add_feedback_pair();
+        let pat = song.tracks[0].clips[0].pattern_mut().unwrap();
+        pat.cell_mut(1, 0).note = Note::Fade;
+        pat.cell_mut(2, 0).volume = VolumeCommand::VolumeSlideUp(2);
+        pat.cell_mut(3, 0).effect = Effect::SetGlobalVolume(32);
+        song.instruments[0].sample_map[100] = 0;
+
+        let export = write_bmx(&song).unwrap();
+        let has = |s: &str| export.warnings.iter().any(|w| w.contains(s));
+        assert!(has("Feedback Send"));
+        assert!(has("note-offs"));
+        assert!(has("volume column"));
+        assert!(has("SetGlobalVolume"));
+        assert!(has("key splits"));
+
+        let loaded = load_bmx(&export.data).unwrap();
+        let pat = loaded.tracks[0].clips[0].pattern().unwrap();
+        assert_eq!(pat.cell(1, 0).note, Note::Off);
+        assert_eq!(pat.cell(3, 0).effect, Effect::None);
+    }
+
+    #[test]
+    fn requires_master() {
+        let mut song = buzz_song();
+        song.graph.nodes[0].node_type = NodeType::AudioInput;
+        assert!(matches!(write_bmx(&song), Err(FormatError::Unsupported(_))));
+    }
+}
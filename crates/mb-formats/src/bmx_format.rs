@@ -6,9 +6,9 @@
 use alloc::string::String;
 use alloc::vec::Vec;
 use mb_ir::{
-    AudioGraph, Cell, ChannelSettings, Clip, Connection, Instrument, LoopType,
-    MusicalTime, NodeId, NodeType, Note, Parameter, Pattern, Sample, SampleData, SeqEntry, Song,
-    Track, VolumeCommand,
+    AudioGraph, Cell, ChannelSettings, Clip, Connection, Envelope, Instrument, LoopType,
+    MusicalTime, NodeId, NodeType, Note, ParamCurve, ParamUnit, Parameter, Pattern, Sample,
+    SampleData, SeqEntry, Song, Track, VolumeCommand, WireLane, WireValue,
 };
 
 use crate::FormatError;
@@ -134,8 +134,8 @@ impl<'a> BmxReader<'a> {
 // Parameter type constants
 // ---------------------------------------------------------------------------
 
-const PT_BYTE: u8 = 2;
-const PT_WORD: u8 = 3;
+pub(crate) const PT_BYTE: u8 = 2;
+pub(crate) const PT_WORD: u8 = 3;
 
 // ---------------------------------------------------------------------------
 // Intermediate types
@@ -148,26 +148,24 @@ struct SectionEntry {
     size: u32,
 }
 
-struct BmxParam {
-    param_type: u8,
-    name: String,
-    min: i32,
-    max: i32,
-    #[allow(dead_code)]
-    no_value: i32,
-    #[allow(dead_code)]
-    flags: i32,
-    default: i32,
+pub(crate) struct BmxParam {
+    pub(crate) param_type: u8,
+    pub(crate) name: String,
+    pub(crate) min: i32,
+    pub(crate) max: i32,
+    pub(crate) no_value: i32,
+    pub(crate) flags: i32,
+    pub(crate) default: i32,
 }
 
-struct BmxParaDef {
-    global_params: Vec<BmxParam>,
-    track_params: Vec<BmxParam>,
+pub(crate) struct BmxParaDef {
+    pub(crate) global_params: Vec<BmxParam>,
+    pub(crate) track_params: Vec<BmxParam>,
 }
 
 impl BmxParaDef {
     /// Total bytes per row of global parameter state.
-    fn global_byte_size(&self) -> usize {
+    pub(crate) fn global_byte_size(&self) -> usize {
         self.global_params.iter().map(|p| param_byte_size(p.param_type)).sum()
     }
 
@@ -197,13 +195,49 @@ struct BmxPattern {
     name: String,
     ticks: u16,
     pattern: Option<Pattern>,
+    /// Wire parameter rows for the machine's inputs
+    wires: Vec<WireLane>,
 }
 
 /// Returns true for DLL names that are tracker machines (cell-based).
-fn is_tracker_dll(dll: &str) -> bool {
+pub(crate) fn is_tracker_dll(dll: &str) -> bool {
     matches!(dll, "Jeskola Tracker" | "Matilde Tracker" | "Matilde Tracker 2" | "Matilde Tracker (Mono)")
 }
 
+/// Returns true for DLL names of drum machines whose trigger column becomes notes.
+pub(crate) fn is_trigger_dll(dll: &str) -> bool {
+    dll == "Jeskola Kick XP"
+}
+
+/// Note written for each hit of a trigger column.
+const TRIGGER_NOTE: u8 = 48;
+
+/// Where a drum machine's trigger byte sits in each PATT row.
+pub(crate) struct TriggerParam {
+    /// In the global row (one column) rather than each track row
+    pub(crate) global: bool,
+    /// Byte offset within the row
+    pub(crate) offset: usize,
+    pub(crate) no_value: u8,
+}
+
+/// Find the "Trigger" parameter, falling back to the first byte of the
+/// global row (or the track row, with no globals) for synthetic PARA defs.
+pub(crate) fn trigger_param(para: &BmxParaDef) -> Option<TriggerParam> {
+    let find = |params: &[BmxParam], global: bool| {
+        let i = params.iter().position(|p| p.name.eq_ignore_ascii_case("Trigger"))?;
+        let offset = params[..i].iter().map(|p| param_byte_size(p.param_type)).sum();
+        Some(TriggerParam { global, offset, no_value: params[i].no_value as u8 })
+    };
+    find(&para.track_params, false)
+        .or_else(|| find(&para.global_params, true))
+        .or_else(|| {
+            let global = !para.global_params.is_empty();
+            let first = if global { para.global_params.first() } else { para.track_params.first() }?;
+            Some(TriggerParam { global, offset: 0, no_value: first.no_value as u8 })
+        })
+}
+
 /// Convert a Buzz note byte to our Note type.
 /// Buzz encoding: high nibble = octave, low nibble = note (1=C..12=B).
 /// 0 = no note, 255 = note off.
@@ -256,7 +290,6 @@ struct BmxWaveLevel {
     loop_start: u32,
     loop_end: u32,
     sample_rate: u32,
-    #[allow(dead_code)]
     root_note: u8,
 }
 
@@ -266,6 +299,8 @@ struct BmxWave {
     volume: f32,
     flags: u8,
     levels: Vec<BmxWaveLevel>,
+    /// Volume, panning and pitch envelopes, in that order
+    envelopes: Vec<Envelope>,
 }
 
 // ---------------------------------------------------------------------------
@@ -292,6 +327,38 @@ fn known_machine_byte_sizes(dll_name: &str) -> Option<(usize, usize)> {
     }
 }
 
+const DELAY_LENGTH_UNITS: &[&str] = &["tick", "ms", "sample", "256th of tick"];
+pub(crate) const SWITCH_LABELS: &[&str] = &["off", "on"];
+
+/// Every enum label table used above, so saved projects can restore them.
+pub(crate) const KNOWN_ENUM_LABELS: &[&[&str]] = &[DELAY_LENGTH_UNITS, SWITCH_LABELS];
+const PERCENT: ParamCurve = ParamCurve::Linear { lo: 0.0, hi: 100.0 };
+
+/// Display metadata for known Buzz machine parameters, keyed by PARA name.
+fn known_param_display(dll_name: &str, param_name: &str) -> Option<(ParamUnit, ParamCurve)> {
+    match (dll_name, param_name) {
+        ("Jeskola Delay", "Length unit") => Some((ParamUnit::Enum(DELAY_LENGTH_UNITS), ParamCurve::Raw)),
+        ("Jeskola Delay", "Dry thru") => Some((ParamUnit::Enum(SWITCH_LABELS), ParamCurve::Raw)),
+        ("Jeskola Delay", "Feedback" | "Wet out") => Some((ParamUnit::Percent, PERCENT)),
+        ("Jeskola Reverb 2", "Dry Out" | "Rev Out" | "ER Out") => Some((ParamUnit::Percent, PERCENT)),
+        ("Jeskola Filter 2", "Resonance") => Some((ParamUnit::Percent, PERCENT)),
+        // Matches the engine's Resonant Filter, which plays this machine
+        ("Jeskola Filter 2", "Cutoff") => {
+            Some((ParamUnit::Hz, ParamCurve::Exponential { lo: 20.0, hi: 20000.0 }))
+        }
+        _ => None,
+    }
+}
+
+/// Build an IR parameter from a PARA definition, attaching known display metadata.
+fn ir_parameter(id: u16, p: &BmxParam, dll_name: Option<&str>) -> Parameter {
+    let param = Parameter::new(id, &p.name, p.min, p.max, p.default);
+    match dll_name.and_then(|dll| known_param_display(dll, &p.name)) {
+        Some((unit, curve)) => param.with_display(unit, curve),
+        None => param,
+    }
+}
+
 /// Build a synthetic BmxParaDef from known byte sizes (all BYTE params).
 fn synthetic_para_def(global_bytes: usize, track_bytes: usize) -> BmxParaDef {
     let global_params = (0..global_bytes)
@@ -388,7 +455,7 @@ fn read_param_defs(r: &mut BmxReader, count: usize) -> Result<Vec<BmxParam>, For
 // Master fallback PARA (hardcoded when no PARA section)
 // ---------------------------------------------------------------------------
 
-fn master_para_def() -> BmxParaDef {
+pub(crate) fn master_para_def() -> BmxParaDef {
     BmxParaDef {
         global_params: alloc::vec![
             BmxParam { param_type: PT_WORD, name: String::from("Volume"), min: 0, max: 0x4000, no_value: 0xFFFF, flags: 2, default: 0 },
@@ -517,13 +584,13 @@ fn parse_mach(
             let ids: Vec<NodeId> = (0..num_tracks).map(|_| id).collect();
             (id, ids)
         } else {
-            let id = graph.add_node(NodeType::Machine { machine_name: name.clone(), is_tracker: false });
+            // Named by machine type, which is what picks a native implementation
+            let machine_name = dll_name.clone().unwrap_or_else(|| name.clone());
+            let id = graph.add_node(NodeType::Machine { machine_name, is_tracker: false });
             // Add IR parameters to non-tracker graph nodes
             if let Some(node) = graph.node_mut(id) {
                 for (j, p) in para.global_params.iter().enumerate() {
-                    node.parameters.push(Parameter::new(
-                        j as u16, &p.name, p.min, p.max, p.default,
-                    ));
+                    node.parameters.push(ir_parameter(j as u16, p, dll_name.as_deref()));
                 }
             }
             (id, Vec::new())
@@ -566,21 +633,14 @@ fn parse_conn(
         let pan = r.read_u16_le()?;
 
         if src_idx < machines.len() && dst_idx < machines.len() {
-            let gain = amplitude_to_gain(amp);
-            let to_id = machines[dst_idx].node_id;
-
-            if machines[src_idx].is_tracker {
-                // Connect single Tracker node to destination
-                graph.connections.push(Connection {
-                    from: machines[src_idx].node_id, to: to_id,
-                    from_channel: 0, to_channel: 0, gain,
-                });
-            } else {
-                graph.connections.push(Connection {
-                    from: machines[src_idx].node_id, to: to_id,
-                    from_channel: 0, to_channel: 0, gain,
-                });
-            }
+            graph.connections.push(Connection {
+                from: machines[src_idx].node_id,
+                to: machines[dst_idx].node_id,
+                from_channel: 0,
+                to_channel: 0,
+                gain: amplitude_to_gain(amp),
+                pan: buzz_pan_to_pan(pan),
+            });
 
             machines[dst_idx].num_inputs += 1;
 
@@ -603,10 +663,18 @@ fn amplitude_to_gain(amp: u16) -> i16 {
     (ratio * 100.0 - 100.0) as i16
 }
 
+/// Convert Buzz wire pan (0 = left, 0x4000 = centre, 0x8000 = right) to -64..=64.
+fn buzz_pan_to_pan(pan: u16) -> i8 {
+    ((pan.min(0x8000) as i32 - 0x4000) * 64 / 0x4000) as i8
+}
+
 // ---------------------------------------------------------------------------
 // PATT
 // ---------------------------------------------------------------------------
 
+/// Wire parameter value for "no change" (amp and pan alike).
+pub(crate) const WIRE_NO_VALUE: u16 = 0xFFFF;
+
 fn parse_patt(
     r: &mut BmxReader,
     entry: &SectionEntry,
@@ -619,6 +687,7 @@ fn parse_patt(
     let mut all_patterns: Vec<Vec<BmxPattern>> = Vec::with_capacity(machines.len());
 
     for (mi, mach) in machines.iter().enumerate() {
+        let trigger_dll = mach.dll_name.as_deref().is_some_and(is_trigger_dll);
         let num_patterns = r.read_u16_le()? as usize;
         let num_tracks = r.read_u16_le()? as usize;
         let para = para_defs.get(mi).unwrap_or(&empty);
@@ -629,31 +698,49 @@ fn parse_patt(
             let name = r.read_null_string()?;
             let num_ticks = r.read_u16_le()?;
 
-            // Skip wire parameters: per input × (u16 src + num_ticks × (u16 amp + u16 pan))
+            // Wire parameters: per input × (u16 src + num_ticks × (u16 amp + u16 pan))
+            let mut wires = Vec::new();
             for _ in 0..mach.num_inputs {
-                let _src_idx = r.read_u16_le()?;
-                r.skip(num_ticks as usize * 4)?;
+                let src_idx = r.read_u16_le()? as usize;
+                let mut values = Vec::with_capacity(num_ticks as usize);
+                for _ in 0..num_ticks {
+                    let (amp, pan) = (r.read_u16_le()?, r.read_u16_le()?);
+                    values.push(WireValue {
+                        gain: (amp != WIRE_NO_VALUE).then(|| amplitude_to_gain(amp)),
+                        pan: (pan != WIRE_NO_VALUE).then(|| buzz_pan_to_pan(pan)),
+                    });
+                }
+                let src = machines.get(src_idx).filter(|_| values.iter().any(|v| !v.is_empty()));
+                if let Some(src) = src {
+                    wires.push(WireLane { from: src.node_id, values });
+                }
             }
 
-            // Skip global parameters: num_ticks × global_byte_size
-            r.skip(num_ticks as usize * para.global_byte_size())?;
+            let trigger = mach.dll_name.as_deref()
+                .filter(|dll| is_trigger_dll(dll))
+                .and_then(|_| trigger_param(para));
 
-            let pattern = if mach.is_tracker && track_bytes >= 5 {
+            let pattern = if let Some(trigger) = trigger {
+                Some(read_trigger_pattern(r, num_ticks, num_tracks, para, &trigger)?)
+            } else if mach.is_tracker && track_bytes >= 5 {
+                // Skip global parameters: num_ticks × global_byte_size
+                r.skip(num_ticks as usize * para.global_byte_size())?;
                 Some(read_tracker_pattern(r, num_ticks, num_tracks, track_bytes, wave_lookup)?)
             } else {
+                r.skip(num_ticks as usize * para.global_byte_size())?;
                 // Skip track parameters for non-tracker machines
                 r.skip(num_tracks * num_ticks as usize * track_bytes)?;
                 None
             };
 
-            patterns.push(BmxPattern { name, ticks: num_ticks, pattern });
+            patterns.push(BmxPattern { name, ticks: num_ticks, pattern, wires });
         }
 
         if !patterns.is_empty() {
             eprintln!(
                 "[BMX] PATT: machine \"{}\" has {} patterns{}",
                 mach.name, patterns.len(),
-                if mach.is_tracker { " [tracker cells]" } else { "" }
+                if mach.is_tracker { " [tracker cells]" } else if trigger_dll { " [triggers]" } else { "" }
             );
         }
 
@@ -665,7 +752,7 @@ fn parse_patt(
 
 /// Read tracker pattern cell data from track parameters.
 /// Layout per tick per track: Note(u8), Wave(u8), Vol(u8), Effect(u8), EffectArg(u8)
-/// Matilde Tracker 2 adds: Effect2(u8), EffectArg2(u8) (7 bytes total, second effect ignored).
+/// Matilde Tracker 2 adds: Effect2(u8), EffectArg2(u8) (7 bytes total), read into `effect2`.
 fn read_tracker_pattern(
     r: &mut BmxReader,
     num_ticks: u16,
@@ -683,14 +770,22 @@ fn read_tracker_pattern(
             let vol_byte = r.read_u8()?;
             let effect_cmd = r.read_u8()?;
             let effect_arg = r.read_u8()?;
-            // Skip extra bytes (e.g. Matilde Tracker 2's second effect column)
-            r.skip(extra_bytes)?;
+            // Matilde Tracker 2's second effect column, then anything else
+            let effect2 = if extra_bytes >= 2 {
+                let (cmd, arg) = (r.read_u8()?, r.read_u8()?);
+                r.skip(extra_bytes - 2)?;
+                parse_buzz_effect(cmd, arg)
+            } else {
+                r.skip(extra_bytes)?;
+                mb_ir::Effect::None
+            };
 
             let cell = Cell {
                 note: buzz_note_to_note(note_byte),
                 instrument: wave_to_instrument(wave_byte, wave_lookup),
                 volume: buzz_volume_to_cmd(vol_byte),
                 effect: parse_buzz_effect(effect_cmd, effect_arg),
+                effect2,
             };
 
             if !cell.is_empty() {
@@ -702,6 +797,46 @@ fn read_tracker_pattern(
     Ok(pattern)
 }
 
+/// Read a drum machine's PATT rows, turning each trigger byte into a note.
+///
+/// Global triggers fill one column; track triggers fill one column per track.
+/// The trigger value sets the hit's volume like a tracker volume byte.
+fn read_trigger_pattern(
+    r: &mut BmxReader,
+    num_ticks: u16,
+    num_tracks: usize,
+    para: &BmxParaDef,
+    trigger: &TriggerParam,
+) -> Result<Pattern, FormatError> {
+    let (row_bytes, columns) = if trigger.global {
+        (para.global_byte_size(), 1)
+    } else {
+        r.skip(num_ticks as usize * para.global_byte_size())?;
+        (para.track_byte_size(), num_tracks)
+    };
+    let mut pattern = Pattern::new(num_ticks, columns.max(1) as u8);
+
+    for col in 0..columns {
+        for tick in 0..num_ticks {
+            r.skip(trigger.offset)?;
+            let value = r.read_u8()?;
+            r.skip(row_bytes.saturating_sub(trigger.offset + 1))?;
+            if value != trigger.no_value && value != 0 {
+                *pattern.cell_mut(tick, col as u8) = Cell {
+                    note: Note::On(TRIGGER_NOTE),
+                    volume: buzz_volume_to_cmd(value),
+                    ..Cell::empty()
+                };
+            }
+        }
+    }
+
+    if trigger.global {
+        r.skip(num_tracks * num_ticks as usize * para.track_byte_size())?;
+    }
+    Ok(pattern)
+}
+
 // ---------------------------------------------------------------------------
 // SEQU
 // ---------------------------------------------------------------------------
@@ -771,7 +906,7 @@ fn parse_sequ(
                 let pat_length = all_patterns.get(machine_idx)
                     .and_then(|pats| pats.get(pat_idx as usize))
                     .map_or(0, |bp| bp.ticks);
-                seq_entries.push(SeqEntry { start, clip_idx: pat_idx, length: pat_length, termination: mb_ir::SeqTermination::Natural });
+                seq_entries.push(SeqEntry { start, clip_idx: pat_idx, length: pat_length, termination: mb_ir::SeqTermination::Natural, boundary: mb_ir::ClipBoundary::Ring });
                 prev_position = position;
             }
             // event_id 2 (Thru) and 3-15: ignored
@@ -787,10 +922,11 @@ fn parse_sequ(
             // Clone multi-channel patterns directly (no column extraction)
             if let Some(pats) = pats {
                 for bp in pats {
-                    let clip = match &bp.pattern {
+                    let mut clip = match &bp.pattern {
                         Some(pat) => pat.clone(),
                         None => Pattern::new(bp.ticks, num_channels),
                     };
+                    clip.wires = bp.wires.clone();
                     track.clips.push(Clip::Pattern(clip));
                 }
             }
@@ -799,12 +935,21 @@ fn parse_sequ(
             tracks.push(track);
         } else {
             let node_id = mach.map(|m| m.node_id);
-            let mut track = Track::new(node_id, 0, 1);
+            let pats = all_patterns.get(machine_idx);
+            // Trigger patterns carry their own column count; others are empty
+            let num_channels = pats
+                .and_then(|p| p.iter().find_map(|bp| bp.pattern.as_ref()))
+                .map_or(1, |p| p.channels);
+            let mut track = Track::new(node_id, 0, num_channels);
 
-            // Add empty clips from this machine's pattern pool
-            if let Some(pats) = all_patterns.get(machine_idx) {
+            if let Some(pats) = pats {
                 for bp in pats {
-                    track.clips.push(Clip::Pattern(Pattern::new(bp.ticks, 1)));
+                    let mut clip = match &bp.pattern {
+                        Some(pat) => pat.clone(),
+                        None => Pattern::new(bp.ticks, num_channels),
+                    };
+                    clip.wires = bp.wires.clone();
+                    track.clips.push(Clip::Pattern(clip));
                 }
             }
 
@@ -863,9 +1008,7 @@ fn parse_wavt(
             index, name, file_name, volume, loop_enabled, is_stereo, bidi_loop
         );
 
-        if has_envelopes {
-            skip_envelopes(r)?;
-        }
+        let envelopes = if has_envelopes { parse_envelopes(r)? } else { Vec::new() };
 
         let num_levels = r.read_u8()? as usize;
         let mut levels = Vec::with_capacity(num_levels);
@@ -886,22 +1029,46 @@ fn parse_wavt(
             });
         }
 
-        waves.push(BmxWave { index, name, volume, flags, levels });
+        waves.push(BmxWave { index, name, volume, flags, levels, envelopes });
     }
 
     eprintln!("[BMX] WAVT: {} waves", waves.len());
     Ok(waves)
 }
 
-fn skip_envelopes(r: &mut BmxReader) -> Result<(), FormatError> {
+/// Envelope x positions (0-65535) per instrument envelope tick.
+pub(crate) const ENVELOPE_X_PER_TICK: u16 = 256;
+
+/// Parse a wave's envelopes. Buzz stores the volume envelope first; any
+/// second and third envelopes are taken as panning and pitch.
+fn parse_envelopes(r: &mut BmxReader) -> Result<Vec<Envelope>, FormatError> {
     let num_envelopes = r.read_u16_le()? as usize;
-    for _ in 0..num_envelopes {
-        r.skip(10)?; // envelope header
+    let mut envelopes = Vec::with_capacity(num_envelopes);
+    for i in 0..num_envelopes {
+        r.skip(10)?; // ADSR attack/decay/sustain/release, subdivide, flags
         let raw_num_points = r.read_u16_le()?;
         let num_points = (raw_num_points & 0x7FFF) as usize;
-        r.skip(num_points * 5)?; // each point: u16 x + u16 y + u8 flags
+        let mut envelope = Envelope::new();
+        envelope.enabled = raw_num_points & 0x8000 == 0;
+        for p in 0..num_points {
+            let x = r.read_u16_le()?;
+            let y = r.read_u16_le()?;
+            let point_flags = r.read_u8()?;
+            let value = if i == 0 {
+                ((y as u32 * 64 + 0x7FFF) / 0xFFFF) as i8
+            } else {
+                // Centred at 0x8000, spanning -64..=64
+                ((y as i32 - 0x8000) * 64 / 0x8000).clamp(-64, 64) as i8
+            };
+            envelope.add_point(x / ENVELOPE_X_PER_TICK, value);
+            if point_flags & 0x01 != 0 && envelope.sustain_start.is_none() && p <= u8::MAX as usize {
+                envelope.sustain_start = Some(p as u8);
+                envelope.sustain_end = Some(p as u8);
+            }
+        }
+        envelopes.push(envelope);
     }
-    Ok(())
+    Ok(envelopes)
 }
 
 // ---------------------------------------------------------------------------
@@ -1240,35 +1407,73 @@ fn scale_i16(s: i16, volume: f32) -> i16 {
     (s as f32 * volume).round().clamp(-32768.0, 32767.0) as i16
 }
 
-fn build_samples(bmx_waves: &[BmxWave], wave_data: &[(u16, SampleData)]) -> Vec<Sample> {
-    bmx_waves.iter().map(|bw| {
+/// Build one Sample per wave level plus one Instrument per wave whose
+/// sample map sends each note to the level with the nearest root note.
+///
+/// Sample maps hold `u8` sample numbers, so songs with more than 256 wave
+/// levels in all are unsupported.
+fn build_instruments(
+    bmx_waves: &[BmxWave],
+    wave_data: &[(u16, SampleData)],
+) -> Result<(Vec<Instrument>, Vec<Sample>), FormatError> {
+    let next_sample = |samples: &[Sample]| {
+        u8::try_from(samples.len())
+            .map_err(|_| FormatError::Unsupported("more than 256 wave levels".into()))
+    };
+    let mut instruments = Vec::with_capacity(bmx_waves.len());
+    let mut samples = Vec::new();
+    for bw in bmx_waves {
         let loop_type = match (bw.flags & 0x01 != 0, bw.flags & 0x10 != 0) {
             (false, _) => LoopType::None,
             (true, true) => LoopType::PingPong,
             (true, false) => LoopType::Forward,
         };
 
-        let level = bw.levels.first();
-        let raw_data = wave_data
-            .iter()
-            .find(|(idx, _)| *idx == bw.index)
-            .map(|(_, d)| d.clone())
-            .unwrap_or_else(|| SampleData::Mono16(Vec::new()));
-
-        // Pre-scale sample data by wave volume (preserves >1.0 amplification)
-        let data = scale_sample_data(raw_data, bw.volume);
-
-        let mut sample = Sample::new(&bw.name);
-        sample.data = data;
-        sample.loop_type = loop_type;
-        sample.loop_start = level.map_or(0, |l| l.loop_start);
-        sample.loop_end = level.map_or(0, |l| l.loop_end);
-        sample.c4_speed = level.map_or(44100, |l| {
-            root_note_adjusted_c4_speed(l.sample_rate, l.root_note)
-        });
-        sample.default_volume = 64; // Full volume; wave volume baked into sample data
-        sample
-    }).collect()
+        // CWAV stores the levels' data in level order
+        let mut level_data = wave_data.iter().filter(|(idx, _)| *idx == bw.index).map(|(_, d)| d.clone());
+        let mut inst = Instrument::new(&bw.name);
+        let envelope = |i: usize| bw.envelopes.get(i).filter(|e| !e.points.is_empty()).cloned();
+        inst.volume_envelope = envelope(0);
+        inst.panning_envelope = envelope(1);
+        inst.pitch_envelope = envelope(2);
+        let mut roots: Vec<(u8, u8)> = Vec::new();
+        for level in &bw.levels {
+            let raw_data = level_data.next().unwrap_or_else(|| SampleData::Mono16(Vec::new()));
+            roots.push((buzz_root_to_midi(level.root_note), next_sample(&samples)?));
+            samples.push(level_sample(bw, level, loop_type, raw_data));
+        }
+        if roots.is_empty() {
+            // A wave without levels still gets a (silent) sample so numbering holds
+            let mut sample = Sample::new(&bw.name);
+            sample.c4_speed = 44100;
+            sample.default_volume = 64;
+            roots.push((48, next_sample(&samples)?));
+            samples.push(sample);
+        }
+        roots.sort_by_key(|&(root, _)| root);
+        for (note, slot) in inst.sample_map.iter_mut().enumerate() {
+            // Ties go to the lower level
+            let nearest = roots.iter().min_by_key(|&&(root, _)| (note as i32 - root as i32).abs());
+            *slot = nearest.map_or(0, |&(_, sample)| sample);
+        }
+        instruments.push(inst);
+    }
+    Ok((instruments, samples))
+}
+
+/// Sample for one wave level.
+fn level_sample(bw: &BmxWave, level: &BmxWaveLevel, loop_type: LoopType, raw_data: SampleData) -> Sample {
+    // Pre-scale sample data by wave volume (preserves >1.0 amplification)
+    let data = scale_sample_data(raw_data, bw.volume);
+
+    let mut sample = Sample::new(&bw.name);
+    sample.set_data(data);
+    sample.loop_type = loop_type;
+    sample.loop_start = level.loop_start;
+    sample.loop_end = level.loop_end;
+    sample.c4_speed = root_note_adjusted_c4_speed(level.sample_rate, level.root_note);
+    sample.default_volume = 64; // Full volume; wave volume baked into sample data
+    sample
 }
 
 // ---------------------------------------------------------------------------
@@ -1345,29 +1550,25 @@ pub fn load_bmx(data: &[u8]) -> Result<Song, FormatError> {
             initial_pan: 0, // Buzz trackers default to center panning
             initial_vol: 64,
             muted: false,
+            solo: false,
         })
         .collect();
 
-    // Build instruments (one per wave, single-sample mapping)
-    let instruments: Vec<Instrument> = bmx_waves.iter().enumerate()
-        .map(|(i, w)| {
-            let mut inst = Instrument::new(&w.name);
-            inst.set_single_sample(i as u8);
-            inst
-        })
-        .collect();
+    // Build instruments (one per wave, key-split across its levels)
+    let (instruments, samples) = build_instruments(&bmx_waves, &wave_data)?;
 
     // Assemble Song
     let mut song = Song::new("BMX Song");
     song.initial_speed = 1;
-    let pt_tempo = (master.bpm as u32 * song.initial_speed as u32 * rows_per_beat as u32) / 24;
-    song.initial_tempo = pt_tempo.clamp(1, 255) as u8;
+    // Tracker tempo (hundredths of a BPM) ticking once per Buzz tick
+    let pt_tempo = master.bpm as u32 * song.initial_speed as u32 * rows_per_beat as u32 * 100 / 24;
+    song.initial_tempo = pt_tempo.max(100);
     song.rows_per_beat = rows_per_beat;
     song.graph = graph;
     song.tracks = tracks;
     song.channels = channels;
     song.instruments = instruments;
-    song.samples = build_samples(&bmx_waves, &wave_data);
+    song.samples = samples;
 
     let total = song.total_time();
     eprintln!(
@@ -1386,16 +1587,8 @@ pub fn load_bmx(data: &[u8]) -> Result<Song, FormatError> {
 mod tests {
     use super::*;
 
-    fn make_minimal_bmx() -> Vec<u8> {
-        let mut buf = Vec::new();
-        buf.extend_from_slice(b"Buzz");
-        buf.extend_from_slice(&4u32.to_le_bytes());
-
-        let dir_end = 8 + 4 * 12;
-
-        // MACH: 1 machine (Master only)
-        let mut mach_data = Vec::new();
-        mach_data.extend_from_slice(&1u16.to_le_bytes());
+    /// The Master machine's MACH entry.
+    fn master_mach_entry(mach_data: &mut Vec<u8>) {
         mach_data.extend_from_slice(b"Master\0");
         mach_data.push(0); // type=0
         mach_data.extend_from_slice(&0f32.to_le_bytes());
@@ -1407,6 +1600,44 @@ mod tests {
         mach_data.extend_from_slice(&126u16.to_le_bytes());
         mach_data.push(4);
         mach_data.extend_from_slice(&0u16.to_le_bytes()); // num_tracks
+    }
+
+    /// Assemble a BMX file from its MACH, CONN, PATT and SEQU sections.
+    fn make_bmx(mach_data: &[u8], conn_data: &[u8], patt_data: &[u8], sequ_data: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(b"Buzz");
+        buf.extend_from_slice(&4u32.to_le_bytes());
+
+        let dir_end = 8 + 4 * 12;
+        let mach_off = dir_end;
+        let conn_off = mach_off + mach_data.len();
+        let patt_off = conn_off + conn_data.len();
+        let sequ_off = patt_off + patt_data.len();
+
+        // Section directory
+        for (name, off, data) in [
+            (b"MACH", mach_off, mach_data),
+            (b"CONN", conn_off, conn_data),
+            (b"PATT", patt_off, patt_data),
+            (b"SEQU", sequ_off, sequ_data),
+        ] {
+            buf.extend_from_slice(name);
+            buf.extend_from_slice(&(off as u32).to_le_bytes());
+            buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        }
+
+        buf.extend_from_slice(mach_data);
+        buf.extend_from_slice(conn_data);
+        buf.extend_from_slice(patt_data);
+        buf.extend_from_slice(sequ_data);
+        buf
+    }
+
+    fn make_minimal_bmx() -> Vec<u8> {
+        // MACH: 1 machine (Master only)
+        let mut mach_data = Vec::new();
+        mach_data.extend_from_slice(&1u16.to_le_bytes());
+        master_mach_entry(&mut mach_data);
 
         let mut conn_data = Vec::new();
         conn_data.extend_from_slice(&0u16.to_le_bytes());
@@ -1421,28 +1652,123 @@ mod tests {
         sequ_data.extend_from_slice(&0u32.to_le_bytes());
         sequ_data.extend_from_slice(&0u16.to_le_bytes());
 
-        let mach_off = dir_end;
-        let conn_off = mach_off + mach_data.len();
-        let patt_off = conn_off + conn_data.len();
-        let sequ_off = patt_off + patt_data.len();
+        make_bmx(&mach_data, &conn_data, &patt_data, &sequ_data)
+    }
 
-        // Section directory
-        for (name, off, data) in [
-            (b"MACH", mach_off, &mach_data),
-            (b"CONN", conn_off, &conn_data),
-            (b"PATT", patt_off, &patt_data),
-            (b"SEQU", sequ_off, &sequ_data),
-        ] {
-            buf.extend_from_slice(name);
-            buf.extend_from_slice(&(off as u32).to_le_bytes());
-            buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    /// Master plus a "Jeskola Kick XP" (no PARA: 9 global bytes) playing one
+    /// 4-row pattern whose trigger byte hits on rows 0 and 2.
+    fn make_kick_bmx() -> Vec<u8> {
+        make_kick_bmx_with_wire(None)
+    }
+
+    /// `make_kick_bmx`, plus a 4-row Master pattern carrying `(amp, pan)`
+    /// rows for the Kick -> Master wire when `wire` is given.
+    fn make_kick_bmx_with_wire(wire: Option<[(u16, u16); 4]>) -> Vec<u8> {
+        let mut mach_data = Vec::new();
+        mach_data.extend_from_slice(&2u16.to_le_bytes());
+        master_mach_entry(&mut mach_data);
+        mach_data.extend_from_slice(b"Kick\0");
+        mach_data.push(1); // generator
+        mach_data.extend_from_slice(b"Jeskola Kick XP\0");
+        mach_data.extend_from_slice(&0f32.to_le_bytes());
+        mach_data.extend_from_slice(&0f32.to_le_bytes());
+        mach_data.extend_from_slice(&0u32.to_le_bytes()); // data_size
+        mach_data.extend_from_slice(&0u16.to_le_bytes()); // num_attrs
+        mach_data.extend_from_slice(&[0xFF; 9]); // global state
+        mach_data.extend_from_slice(&0u16.to_le_bytes()); // num_tracks
+
+        // Wire: Kick -> Master at unity
+        let mut conn_data = Vec::new();
+        conn_data.extend_from_slice(&1u16.to_le_bytes());
+        for v in [1u16, 0, 0x4000, 0x4000] {
+            conn_data.extend_from_slice(&v.to_le_bytes());
         }
 
-        buf.extend_from_slice(&mach_data);
-        buf.extend_from_slice(&conn_data);
-        buf.extend_from_slice(&patt_data);
-        buf.extend_from_slice(&sequ_data);
-        buf
+        let mut patt_data = Vec::new();
+        if let Some(rows) = wire {
+            patt_data.extend_from_slice(&1u16.to_le_bytes()); // Master: 1 pattern, 0 tracks
+            patt_data.extend_from_slice(&0u16.to_le_bytes());
+            patt_data.extend_from_slice(b"00\0");
+            patt_data.extend_from_slice(&4u16.to_le_bytes());
+            patt_data.extend_from_slice(&1u16.to_le_bytes()); // input from Kick
+            for (amp, pan) in rows {
+                patt_data.extend_from_slice(&amp.to_le_bytes());
+                patt_data.extend_from_slice(&pan.to_le_bytes());
+            }
+            patt_data.extend_from_slice(&[0xFF; 4 * 5]); // global params
+        } else {
+            patt_data.extend_from_slice(&0u16.to_le_bytes()); // Master: no patterns
+            patt_data.extend_from_slice(&0u16.to_le_bytes());
+        }
+        patt_data.extend_from_slice(&1u16.to_le_bytes()); // Kick: 1 pattern, 0 tracks
+        patt_data.extend_from_slice(&0u16.to_le_bytes());
+        patt_data.extend_from_slice(b"00\0");
+        patt_data.extend_from_slice(&4u16.to_le_bytes());
+        for trigger in [0x80u8, 0xFF, 0x40, 0xFF] {
+            patt_data.push(trigger);
+            patt_data.extend_from_slice(&[0xFF; 8]);
+        }
+
+        let mut sequ_data = Vec::new();
+        sequ_data.extend_from_slice(&4u32.to_le_bytes()); // end of song
+        sequ_data.extend_from_slice(&0u32.to_le_bytes());
+        sequ_data.extend_from_slice(&4u32.to_le_bytes());
+        let sequenced: &[u16] = if wire.is_some() { &[1, 0] } else { &[1] }; // Kick, then Master
+        sequ_data.extend_from_slice(&(sequenced.len() as u16).to_le_bytes());
+        for &machine in sequenced {
+            sequ_data.extend_from_slice(&machine.to_le_bytes());
+            sequ_data.extend_from_slice(&1u32.to_le_bytes()); // 1 event
+            sequ_data.extend_from_slice(&[1, 1, 0, 0x10]); // at row 0: pattern 0
+        }
+
+        make_bmx(&mach_data, &conn_data, &patt_data, &sequ_data)
+    }
+
+    #[test]
+    fn kick_xp_triggers_become_notes() {
+        let song = load_bmx(&make_kick_bmx()).unwrap();
+        let node = song.graph.node(1).unwrap();
+        assert_eq!(node.node_type.label(), "Jeskola Kick XP");
+        let track = song.tracks.iter().find(|t| t.machine_node == Some(1)).unwrap();
+        assert_eq!(track.num_channels, 1);
+        let pat = track.get_pattern_at(0).unwrap();
+        assert_eq!(pat.cell(0, 0).note, Note::On(TRIGGER_NOTE));
+        assert_eq!(pat.cell(0, 0).volume, VolumeCommand::Volume(64));
+        assert!(pat.cell(1, 0).is_empty());
+        assert_eq!(pat.cell(2, 0).volume, VolumeCommand::Volume(32));
+    }
+
+    #[test]
+    fn wire_parameters_become_wire_lanes() {
+        let rows = [(0x2000, 0xFFFF), (0xFFFF, 0xFFFF), (0, 0), (0xFFFF, 0x8000)];
+        let song = load_bmx(&make_kick_bmx_with_wire(Some(rows))).unwrap();
+        let track = song.tracks.iter().find(|t| t.machine_node == Some(0)).unwrap();
+        let lanes = &track.get_pattern_at(0).unwrap().wires;
+        assert_eq!(lanes.len(), 1);
+        assert_eq!(lanes[0].from, 1);
+        let values: Vec<_> = lanes[0].values.iter().map(|v| (v.gain, v.pan)).collect();
+        assert_eq!(values, [(Some(-50), None), (None, None), (Some(-100), Some(-64)), (None, Some(64))]);
+
+        let song = load_bmx(&make_kick_bmx()).unwrap();
+        assert!(song.tracks.iter().all(|t| t.get_pattern_at(0).unwrap().wires.is_empty()));
+    }
+
+    #[test]
+    fn trigger_param_found_by_name() {
+        let byte = |name: &str| BmxParam {
+            param_type: PT_BYTE, name: String::from(name),
+            min: 0, max: 0xF0, no_value: 0, flags: 0, default: 0,
+        };
+        let para = BmxParaDef {
+            global_params: vec![byte("Volume")],
+            track_params: vec![byte("Start"), byte("Trigger")],
+        };
+        let t = trigger_param(&para).unwrap();
+        assert!(!t.global);
+        assert_eq!((t.offset, t.no_value), (1, 0));
+        let synthetic = trigger_param(&synthetic_para_def(9, 0)).unwrap();
+        assert!(synthetic.global);
+        assert_eq!((synthetic.offset, synthetic.no_value), (0, 0xFF));
     }
 
     #[test]
@@ -1487,6 +1813,24 @@ mod tests {
         assert!(amplitude_to_gain(0x2000) < 0);
     }
 
+    #[test]
+    fn buzz_pan_maps_to_balance() {
+        assert_eq!(buzz_pan_to_pan(0), -64);
+        assert_eq!(buzz_pan_to_pan(0x4000), 0);
+        assert_eq!(buzz_pan_to_pan(0x6000), 32);
+        assert_eq!(buzz_pan_to_pan(0xFFFF), 64);
+    }
+
+    #[test]
+    fn known_param_display_attaches_units() {
+        let p = BmxParam {
+            param_type: PT_BYTE, name: String::from("Length unit"),
+            min: 0, max: 3, no_value: 0xFF, flags: 0, default: 1,
+        };
+        assert_eq!(ir_parameter(2, &p, Some("Jeskola Delay")).display_value(), "ms");
+        assert_eq!(ir_parameter(2, &p, None).display_value(), "1");
+    }
+
     #[test]
     fn known_machine_lookup() {
         assert_eq!(known_machine_byte_sizes("Jeskola Tracker"), Some((1, 5)));
@@ -1612,4 +1956,75 @@ mod tests {
         let speed = root_note_adjusted_c4_speed(44100, 0x51);
         assert!((speed as f32 - 88200.0).abs() < 10.0);
     }
+
+    fn level(root_note: u8) -> BmxWaveLevel {
+        BmxWaveLevel { num_samples: 2, loop_start: 0, loop_end: 0, sample_rate: 44100, root_note }
+    }
+
+    #[test]
+    fn wave_levels_split_by_root_note() {
+        let waves = [
+            BmxWave { index: 0, name: "one".into(), volume: 1.0, flags: 0, levels: vec![level(0x41)], envelopes: Vec::new() },
+            // Levels rooted at C-5 and C-3, stored high level first
+            BmxWave { index: 3, name: "multi".into(), volume: 1.0, flags: 0, levels: vec![level(0x51), level(0x31)], envelopes: Vec::new() },
+        ];
+        let data = [
+            (0, SampleData::Mono16(vec![1, 1])),
+            (3, SampleData::Mono16(vec![5, 5])),
+            (3, SampleData::Mono16(vec![3, 3])),
+        ];
+        let (instruments, samples) = build_instruments(&waves, &data).unwrap();
+        assert_eq!(instruments.len(), 2);
+        assert_eq!(samples.len(), 3);
+        assert!(instruments[0].sample_map.iter().all(|&s| s == 0));
+        assert_eq!(*samples[1].data, SampleData::Mono16(vec![5, 5]));
+        assert_eq!(*samples[2].data, SampleData::Mono16(vec![3, 3]));
+        let map = &instruments[1].sample_map;
+        // C-3 level up to and including the midpoint C-4, C-5 level above
+        assert_eq!((map[0], map[36], map[48]), (2, 2, 2));
+        assert_eq!((map[49], map[60], map[119]), (1, 1, 1));
+        assert!(samples[1].c4_speed > samples[2].c4_speed);
+    }
+
+    #[test]
+    fn too_many_wave_levels_rejected() {
+        let wave = |index| BmxWave { index, name: "w".into(), volume: 1.0, flags: 0, levels: (0..16).map(|_| level(0x41)).collect(), envelopes: Vec::new() };
+        let fits: Vec<BmxWave> = (0..16).map(wave).collect();
+        assert_eq!(build_instruments(&fits, &[]).unwrap().1.len(), 256);
+        let over: Vec<BmxWave> = (0..17).map(wave).collect();
+        assert!(matches!(build_instruments(&over, &[]), Err(FormatError::Unsupported(_))));
+    }
+
+    #[test]
+    fn wave_envelopes_parsed() {
+        let mut data = 2u16.to_le_bytes().to_vec();
+        // Volume: attack to full, sustain at half, release to silence
+        data.extend([0; 10]);
+        data.extend(3u16.to_le_bytes());
+        for (x, y, flags) in [(0u16, 0u16, 0u8), (0x1000, 0xFFFF, 0), (0x2000, 0x8000, 1)] {
+            data.extend(x.to_le_bytes());
+            data.extend(y.to_le_bytes());
+            data.push(flags);
+        }
+        // Panning, disabled, hard left
+        data.extend([0; 10]);
+        data.extend(0x8001u16.to_le_bytes());
+        data.extend([0, 0, 0, 0, 0]);
+        let mut r = BmxReader::new(&data);
+        let envelopes = parse_envelopes(&mut r).unwrap();
+
+        let volume = &envelopes[0];
+        let points: Vec<_> = volume.points.iter().map(|p| (p.tick, p.value)).collect();
+        assert_eq!(points, vec![(0, 0), (16, 64), (32, 32)]);
+        assert_eq!((volume.sustain_start, volume.sustain_end), (Some(2), Some(2)));
+        assert!(volume.enabled);
+        assert!(!envelopes[1].enabled);
+        assert_eq!(envelopes[1].points[0].value, -64);
+
+        let wave = BmxWave { index: 0, name: "env".into(), volume: 1.0, flags: 0x80, levels: vec![level(0x41)], envelopes };
+        let (instruments, _) = build_instruments(&[wave], &[]).unwrap();
+        assert_eq!(instruments[0].volume_envelope.as_ref().unwrap().points.len(), 3);
+        assert!(instruments[0].panning_envelope.is_some());
+        assert!(instruments[0].pitch_envelope.is_none());
+    }
 }
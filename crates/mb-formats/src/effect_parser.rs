@@ -51,6 +51,7 @@ pub fn parse_extended_effect(param: u8) -> Effect {
     match cmd {
         0x1 => Effect::FinePortaUp(val),
         0x2 => Effect::FinePortaDown(val),
+        0x3 => Effect::SetGlissando(val),
         0x4 => Effect::SetVibratoWaveform(val),
         0x5 => Effect::SetFinetune(if val > 7 { val as i8 - 16 } else { val as i8 }),
         0x6 => Effect::PatternLoop(val),
@@ -62,6 +63,7 @@ pub fn parse_extended_effect(param: u8) -> Effect {
         0xC => Effect::NoteCut(val),
         0xD => Effect::NoteDelay(val),
         0xE => Effect::PatternDelay(val),
+        0xF => Effect::FunkRepeat(val),
         _ => Effect::None,
     }
 }
@@ -76,3 +78,89 @@ pub fn param_to_slide(param: u8) -> i8 {
         -(down as i8)
     }
 }
+
+/// Parse an S3M/IT letter command (`cmd` 1 = A ... 26 = Z).
+///
+/// Effects without an IR equivalent (channel volume, panbrello,
+/// MIDI macros beyond the default cutoff, ...) map to `Effect::None`. A zero
+/// parameter is passed through as-is; effect memory is left to the engine.
+pub fn parse_it_effect(cmd: u8, param: u8) -> Effect {
+    let x = param >> 4;
+    let y = param & 0x0F;
+    match cmd {
+        1 if param > 0 => Effect::SetSpeed(param),
+        2 => Effect::PositionJump(param),
+        3 => Effect::PatternBreak(param),
+        4 => parse_it_volume_slide(param),
+        5 => parse_it_porta(param, Effect::PortaDown, Effect::FinePortaDown, Effect::ExtraFinePortaDown),
+        6 => parse_it_porta(param, Effect::PortaUp, Effect::FinePortaUp, Effect::ExtraFinePortaUp),
+        7 => Effect::TonePorta(param),
+        8 => Effect::Vibrato { speed: x, depth: y },
+        9 => Effect::Tremor { on: x, off: y },
+        10 => Effect::Arpeggio { x, y },
+        11 => Effect::VibratoVolSlide(param_to_slide(param)),
+        12 => Effect::TonePortaVolSlide(param_to_slide(param)),
+        15 => Effect::SampleOffset(param),
+        16 => Effect::PanningSlide(if x > 0 { -(x as i8) } else { y as i8 }),
+        17 if x == 0 => Effect::RetriggerNote(y),
+        17 => Effect::Retrigger { interval: y, volume_change: retrigger_volume_change(x) },
+        18 => Effect::Tremolo { speed: x, depth: y },
+        19 => parse_it_extended(x, y),
+        20 if param >= 0x20 => Effect::SetTempo(param),
+        // Fine vibrato: a quarter of the regular depth
+        21 => Effect::Vibrato { speed: x, depth: y.div_ceil(4) },
+        22 => Effect::SetGlobalVolume(param.min(128)),
+        23 => Effect::GlobalVolumeSlide(param_to_slide(param)),
+        24 => Effect::SetPan(param),
+        // Default macro SF0: Z00-Z7F set the filter cutoff
+        26 if param < 0x80 => Effect::SetFilterCutoff(param),
+        _ => Effect::None,
+    }
+}
+
+/// Dxy: D0y/Dx0 slide every tick, DxF/DFy fine slide once per row.
+fn parse_it_volume_slide(param: u8) -> Effect {
+    match (param >> 4, param & 0x0F) {
+        (x, 0xF) if x > 0 => Effect::FineVolumeSlideUp(x),
+        (0xF, y) if y > 0 => Effect::FineVolumeSlideDown(y),
+        _ => Effect::VolumeSlide(param_to_slide(param)),
+    }
+}
+
+/// Exx/Fxx: regular slide, or EFx/FFx fine and EEx/FEx extra-fine.
+fn parse_it_porta(
+    param: u8,
+    regular: fn(u8) -> Effect,
+    fine: fn(u8) -> Effect,
+    extra_fine: fn(u8) -> Effect,
+) -> Effect {
+    match param >> 4 {
+        0xF => fine(param & 0x0F),
+        0xE => extra_fine(param & 0x0F),
+        _ => regular(param),
+    }
+}
+
+/// Sxy extended commands.
+fn parse_it_extended(x: u8, y: u8) -> Effect {
+    match x {
+        0x1 => Effect::SetGlissando(y),
+        0x2 => Effect::SetFinetune(if y > 7 { y as i8 - 16 } else { y as i8 }),
+        0x3 => Effect::SetVibratoWaveform(y),
+        0x4 => Effect::SetTremoloWaveform(y),
+        0x8 => Effect::SetPanPosition(y),
+        0xA => Effect::SetHighOffset(y),
+        0xB => Effect::PatternLoop(y),
+        0xC => Effect::NoteCut(y),
+        0xD => Effect::NoteDelay(y),
+        0xE => Effect::PatternDelay(y),
+        _ => Effect::None,
+    }
+}
+
+/// Qxy volume change per retrigger. Multiplicative steps are approximated
+/// as the change they make at full volume.
+fn retrigger_volume_change(x: u8) -> i8 {
+    const CHANGE: [i8; 16] = [0, -1, -2, -4, -8, -16, -21, -32, 0, 1, 2, 4, 8, 16, 32, 64];
+    CHANGE[x as usize & 0x0F]
+}
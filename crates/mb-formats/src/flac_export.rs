@@ -0,0 +1,452 @@
+//! FLAC encoding of rendered audio.
+//!
+//! Encodes stereo f32 frames as 16-bit FLAC using the fixed polynomial
+//! predictors (orders 0-4) with partitioned Rice residuals, choosing the
+//! cheapest stereo decorrelation per block. That gets most of the way to
+//! reference encoder sizes without LPC analysis.
+
+use std::io::{Seek, SeekFrom, Write};
+
+/// Frames per FLAC block.
+const BLOCK_SIZE: usize = 4096;
+
+/// Highest residual partition order tried.
+const MAX_PARTITION_ORDER: u32 = 8;
+
+/// Rice parameter escape code (4-bit parameters).
+const RICE_ESCAPE: u32 = 15;
+
+/// Encode stereo f32 frames to a FLAC byte buffer.
+pub fn frames_to_flac(frames: &[[f32; 2]], sample_rate: u32) -> Vec<u8> {
+    let mut stream = FlacStreamWriter::new(std::io::Cursor::new(Vec::new()), sample_rate)
+        .expect("Vec<u8> write cannot fail");
+    stream.write_frames(frames).expect("Vec<u8> write cannot fail");
+    stream.finish_seekable().expect("Vec<u8> write cannot fail").into_inner()
+}
+
+/// Stereo 16-bit FLAC writer that encodes frames as they arrive.
+///
+/// STREAMINFO goes out first with the length and frame sizes marked
+/// unknown, which FLAC allows; `finish_seekable` fills them in.
+pub struct FlacStreamWriter<W: Write> {
+    inner: W,
+    sample_rate: u32,
+    /// Frames waiting for a full block
+    pending: Vec<[i32; 2]>,
+    /// Blocks written so far
+    blocks: u64,
+    frames: u64,
+    /// Smallest and largest encoded frame, in bytes
+    frame_bytes: (u32, u32),
+}
+
+impl<W: Write> FlacStreamWriter<W> {
+    /// Write the stream header and start a stream at `sample_rate`.
+    pub fn new(mut inner: W, sample_rate: u32) -> std::io::Result<Self> {
+        inner.write_all(b"fLaC")?;
+        // Last metadata block, STREAMINFO, 34 bytes
+        inner.write_all(&[0x80, 0, 0, 34])?;
+        inner.write_all(&stream_info(sample_rate, 0, (0, 0)))?;
+        Ok(Self {
+            inner,
+            sample_rate,
+            pending: Vec::with_capacity(BLOCK_SIZE),
+            blocks: 0,
+            frames: 0,
+            frame_bytes: (u32::MAX, 0),
+        })
+    }
+
+    /// Append frames, encoding each full block.
+    pub fn write_frames(&mut self, frames: &[[f32; 2]]) -> std::io::Result<()> {
+        for frame in frames {
+            self.pending.push([to_i16(frame[0]), to_i16(frame[1])]);
+            if self.pending.len() == BLOCK_SIZE {
+                self.flush_block()?;
+            }
+        }
+        self.frames += frames.len() as u64;
+        Ok(())
+    }
+
+    /// Frames written so far.
+    pub fn frames_written(&self) -> u64 {
+        self.frames
+    }
+
+    /// Encode the last partial block, flush and return the destination.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.flush_block()?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn flush_block(&mut self) -> std::io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let frame = encode_frame(&self.pending, self.blocks);
+        self.inner.write_all(&frame)?;
+        let len = frame.len() as u32;
+        self.frame_bytes = (self.frame_bytes.0.min(len), self.frame_bytes.1.max(len));
+        self.blocks += 1;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> FlacStreamWriter<W> {
+    /// Encode the last block and patch the real length and frame sizes
+    /// into STREAMINFO, then flush.
+    pub fn finish_seekable(mut self) -> std::io::Result<W> {
+        self.flush_block()?;
+        let frame_bytes = if self.blocks == 0 { (0, 0) } else { self.frame_bytes };
+        let info = stream_info(self.sample_rate, self.frames, frame_bytes);
+        let end = self.inner.stream_position()?;
+        self.inner.seek(SeekFrom::Start(8))?;
+        self.inner.write_all(&info)?;
+        self.inner.seek(SeekFrom::Start(end))?;
+        self.finish()
+    }
+}
+
+/// Convert a single f32 sample to 16-bit (clamped).
+fn to_i16(val: f32) -> i32 {
+    (val * 32768.0).clamp(-32768.0, 32767.0) as i32
+}
+
+/// STREAMINFO for 16-bit stereo (`total_frames` 0 = unknown).
+fn stream_info(sample_rate: u32, total_frames: u64, (min_frame, max_frame): (u32, u32)) -> [u8; 34] {
+    let mut w = BitWriter::default();
+    w.put(BLOCK_SIZE as u32, 16); // Only the last block may be shorter
+    w.put(BLOCK_SIZE as u32, 16);
+    w.put(min_frame, 24);
+    w.put(max_frame, 24);
+    w.put(sample_rate, 20);
+    w.put(1, 3); // Channels - 1
+    w.put(15, 5); // Bits per sample - 1
+    w.put((total_frames >> 32) as u32 & 0xF, 4);
+    w.put(total_frames as u32, 32);
+    // MD5 of the audio left unset (all zero), which FLAC allows
+    w.bytes.resize(34, 0);
+    w.bytes.try_into().expect("STREAMINFO is 34 bytes")
+}
+
+/// Stereo decorrelation modes (FLAC channel assignment codes).
+const INDEPENDENT: u32 = 0b0001;
+const LEFT_SIDE: u32 = 0b1000;
+const RIGHT_SIDE: u32 = 0b1001;
+const MID_SIDE: u32 = 0b1010;
+
+/// Encode one block as a FLAC frame.
+fn encode_frame(block: &[[i32; 2]], number: u64) -> Vec<u8> {
+    let left: Vec<i32> = block.iter().map(|f| f[0]).collect();
+    let right: Vec<i32> = block.iter().map(|f| f[1]).collect();
+    let side: Vec<i32> = block.iter().map(|f| f[0] - f[1]).collect();
+    let mid: Vec<i32> = block.iter().map(|f| (f[0] + f[1]) >> 1).collect();
+
+    let [l, r, s, m] = [(&left, 16), (&right, 16), (&side, 17), (&mid, 16)].map(|(x, bps)| Subframe::best(x, bps));
+    let (assignment, first, second) = [
+        (INDEPENDENT, &l, &r),
+        (LEFT_SIDE, &l, &s),
+        (RIGHT_SIDE, &s, &r),
+        (MID_SIDE, &m, &s),
+    ]
+    .into_iter()
+    .min_by_key(|(_, a, b)| a.bits + b.bits)
+    .expect("four candidates");
+
+    let mut w = BitWriter::default();
+    w.put(0b11111111111110, 14); // Sync
+    w.put(0, 1);
+    w.put(0, 1); // Fixed block size
+    let odd_size = block.len() != BLOCK_SIZE;
+    w.put(if odd_size { 0b0111 } else { 0b1100 }, 4); // 16-bit size at end of header, or 4096
+    w.put(0, 4); // Sample rate from STREAMINFO
+    w.put(assignment, 4);
+    w.put(0b100, 3); // 16 bits per sample
+    w.put(0, 1);
+    write_utf8_number(&mut w, number);
+    if odd_size {
+        w.put(block.len() as u32 - 1, 16);
+    }
+    let crc = crc8(&w.bytes);
+    w.put(crc as u32, 8);
+
+    first.write(&mut w);
+    second.write(&mut w);
+    w.align();
+    let crc = crc16(&w.bytes);
+    w.put(crc as u32, 16);
+    w.bytes
+}
+
+/// FLAC's UTF-8-style variable length frame number.
+fn write_utf8_number(w: &mut BitWriter, n: u64) {
+    if n < 0x80 {
+        w.put(n as u32, 8);
+        return;
+    }
+    let extra = (1..=5u32).find(|&k| n < 1u64 << (6 + 5 * k)).unwrap_or(6);
+    let lead = (0xFF00u32 >> (extra + 1)) & 0xFF;
+    w.put(lead | (n >> (6 * extra)) as u32, 8);
+    for i in (0..extra).rev() {
+        w.put(0x80 | ((n >> (6 * i)) as u32 & 0x3F), 8);
+    }
+}
+
+/// A channel's encoding, ready to write.
+struct Subframe<'a> {
+    samples: &'a [i32],
+    bps: u32,
+    kind: SubframeKind,
+    /// Size in bits
+    bits: u64,
+}
+
+enum SubframeKind {
+    Constant,
+    Verbatim,
+    Fixed { order: usize, residual: Vec<i32>, partition_order: u32, params: Vec<u32> },
+}
+
+impl<'a> Subframe<'a> {
+    /// The smallest of the constant, verbatim and fixed encodings.
+    fn best(samples: &'a [i32], bps: u32) -> Self {
+        let header = 8;
+        if samples.iter().all(|&s| s == samples[0]) {
+            return Self { samples, bps, kind: SubframeKind::Constant, bits: header + bps as u64 };
+        }
+        let mut best = Self { samples, bps, kind: SubframeKind::Verbatim, bits: header + bps as u64 * samples.len() as u64 };
+        for order in 0..=4.min(samples.len() - 1) {
+            let residual = fixed_residual(samples, order);
+            let (partition_order, params, bits) = rice_partitions(&residual, samples.len(), order);
+            let bits = header + (order as u64 * bps as u64) + 6 + bits;
+            if bits < best.bits {
+                best = Self { samples, bps, kind: SubframeKind::Fixed { order, residual, partition_order, params }, bits };
+            }
+        }
+        best
+    }
+
+    fn write(&self, w: &mut BitWriter) {
+        match &self.kind {
+            SubframeKind::Constant => {
+                w.put(0, 8);
+                w.put_signed(self.samples[0], self.bps);
+            }
+            SubframeKind::Verbatim => {
+                w.put(0b0000_0010, 8);
+                for &s in self.samples {
+                    w.put_signed(s, self.bps);
+                }
+            }
+            SubframeKind::Fixed { order, residual, partition_order, params } => {
+                w.put((0b00_1000 | *order as u32) << 1, 8);
+                for &s in &self.samples[..*order] {
+                    w.put_signed(s, self.bps);
+                }
+                w.put(0, 2); // Rice coding with 4-bit parameters
+                w.put(*partition_order, 4);
+                let part_len = self.samples.len() >> partition_order;
+                let mut start = 0;
+                for (p, &k) in params.iter().enumerate() {
+                    let end = (p + 1) * part_len - order;
+                    w.put(k, 4);
+                    for &r in &residual[start..end] {
+                        w.put_rice(r, k);
+                    }
+                    start = end;
+                }
+            }
+        }
+    }
+}
+
+/// Residual of the fixed polynomial predictor of `order` (for samples
+/// `order..`).
+fn fixed_residual(x: &[i32], order: usize) -> Vec<i32> {
+    (order..x.len())
+        .map(|i| match order {
+            0 => x[i],
+            1 => x[i] - x[i - 1],
+            2 => x[i] - 2 * x[i - 1] + x[i - 2],
+            3 => x[i] - 3 * x[i - 1] + 3 * x[i - 2] - x[i - 3],
+            _ => x[i] - 4 * x[i - 1] + 6 * x[i - 2] - 4 * x[i - 3] + x[i - 4],
+        })
+        .collect()
+}
+
+/// Best partition order, per-partition Rice parameters and their total
+/// size in bits (excluding the 6-bit method and order fields).
+fn rice_partitions(residual: &[i32], block_len: usize, order: usize) -> (u32, Vec<u32>, u64) {
+    let zigzag: Vec<u32> = residual.iter().map(|&r| ((r << 1) ^ (r >> 31)) as u32).collect();
+    let mut best: Option<(u32, Vec<u32>, u64)> = None;
+    for partition_order in 0..=MAX_PARTITION_ORDER {
+        let part_len = block_len >> partition_order;
+        if !block_len.is_multiple_of(1 << partition_order) || part_len <= order {
+            break;
+        }
+        let mut params = Vec::with_capacity(1 << partition_order);
+        let mut bits = 0u64;
+        let mut start = 0;
+        for p in 0..1usize << partition_order {
+            let end = (p + 1) * part_len - order;
+            let (k, size) = best_rice_param(&zigzag[start..end]);
+            params.push(k);
+            bits += 4 + size;
+            start = end;
+        }
+        if best.as_ref().is_none_or(|b| bits < b.2) {
+            best = Some((partition_order, params, bits));
+        }
+    }
+    best.expect("partition order 0 always fits")
+}
+
+/// Cheapest Rice parameter for zigzagged values and the bits it takes.
+fn best_rice_param(values: &[u32]) -> (u32, u64) {
+    (0..RICE_ESCAPE)
+        .map(|k| (k, values.iter().map(|&u| (u >> k) as u64 + 1 + k as u64).sum()))
+        .min_by_key(|&(_, bits)| bits)
+        .expect("at least one parameter")
+}
+
+/// MSB-first bit packer.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// Bits used in the last byte (0 = byte aligned)
+    used: u32,
+}
+
+impl BitWriter {
+    /// Write the low `n` bits of `v` (n <= 32).
+    fn put(&mut self, v: u32, n: u32) {
+        for i in (0..n).rev() {
+            self.bit((v >> i) & 1 != 0);
+        }
+    }
+
+    fn put_signed(&mut self, v: i32, n: u32) {
+        self.put(v as u32 & ((1u64 << n) - 1) as u32, n);
+    }
+
+    /// Rice-code a residual with parameter `k`.
+    fn put_rice(&mut self, r: i32, k: u32) {
+        let u = ((r << 1) ^ (r >> 31)) as u32;
+        for _ in 0..u >> k {
+            self.bit(false);
+        }
+        self.bit(true);
+        self.put(u & ((1u64 << k) - 1) as u32, k);
+    }
+
+    fn bit(&mut self, set: bool) {
+        if self.used == 0 {
+            self.bytes.push(0);
+        }
+        if set {
+            *self.bytes.last_mut().expect("byte pushed above") |= 0x80 >> self.used;
+        }
+        self.used = (self.used + 1) % 8;
+    }
+
+    /// Pad with zero bits to a byte boundary.
+    fn align(&mut self) {
+        self.used = 0;
+    }
+}
+
+/// CRC-8, polynomial 0x07, over a frame header.
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |mut crc, &b| {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+/// CRC-16, polynomial 0x8005, over a whole frame.
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |mut crc, &b| {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(flac: &[u8]) -> (claxon::metadata::StreamInfo, Vec<i32>) {
+        let mut reader = claxon::FlacReader::new(flac).expect("valid FLAC header");
+        let info = reader.streaminfo();
+        let samples = reader.samples().map(|s| s.expect("valid FLAC frame")).collect();
+        (info, samples)
+    }
+
+    fn interleaved(frames: &[[f32; 2]]) -> Vec<i32> {
+        frames.iter().flat_map(|f| [to_i16(f[0]), to_i16(f[1])]).collect()
+    }
+
+    fn music(len: usize) -> Vec<[f32; 2]> {
+        (0..len)
+            .map(|i| {
+                let t = i as f32 / 44100.0;
+                let l = (t * 440.0 * core::f32::consts::TAU).sin() * 0.5;
+                let r = l * 0.8 + (t * 660.0 * core::f32::consts::TAU).sin() * 0.2;
+                [l, r]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn decodes_losslessly() {
+        let mut frames = music(10_000);
+        frames[5000] = [1.0, -1.0]; // Full-scale spike
+        frames[6000] = [0.3, 0.3];
+        let flac = frames_to_flac(&frames, 44100);
+        let (info, samples) = decode(&flac);
+        assert_eq!((info.sample_rate, info.channels, info.bits_per_sample), (44100, 2, 16));
+        assert_eq!(info.samples, Some(10_000));
+        assert_eq!(samples, interleaved(&frames));
+        assert!(flac.len() < frames.len() * 4 / 2, "{} bytes is barely compressed", flac.len());
+    }
+
+    #[test]
+    fn silence_and_empty_streams() {
+        let (_, samples) = decode(&frames_to_flac(&[[0.0; 2]; 5000], 8000));
+        assert_eq!(samples, vec![0; 10_000]);
+        let (info, samples) = decode(&frames_to_flac(&[], 8000));
+        assert!(samples.is_empty());
+        assert_eq!(info.samples, None, "a zero length reads as unknown");
+    }
+
+    #[test]
+    fn streamed_chunks_match_buffered_encoding() {
+        let frames = music(9000);
+        let mut stream = FlacStreamWriter::new(std::io::Cursor::new(Vec::new()), 44100).unwrap();
+        for chunk in frames.chunks(777) {
+            stream.write_frames(chunk).unwrap();
+        }
+        assert_eq!(stream.frames_written(), 9000);
+        let streamed = stream.finish_seekable().unwrap().into_inner();
+        assert_eq!(streamed, frames_to_flac(&frames, 44100));
+    }
+
+    #[test]
+    fn long_streams_use_multi_byte_frame_numbers() {
+        let mut w = BitWriter::default();
+        write_utf8_number(&mut w, 0x80);
+        assert_eq!(w.bytes, [0xC2, 0x80]);
+        let mut w = BitWriter::default();
+        write_utf8_number(&mut w, 0x800);
+        assert_eq!(w.bytes, [0xE0, 0xA0, 0x80]);
+    }
+}
@@ -0,0 +1,881 @@
+//! Impulse Tracker IT format parser.
+//!
+//! Loads patterns, samples (including IT214/IT215 compressed data) and
+//! instruments with their envelopes and New Note Action settings. IT notes
+//! are shifted down an octave so IT's C-5 lands on the engine's reference
+//! note, where a sample plays at its C5 speed.
+
+use alloc::vec::Vec;
+use mb_ir::{
+    build_tracks, AutoVibrato, Cell, ChannelSettings, CompatibilityMode, DuplicateCheck, Effect, Envelope, FrequencyMode,
+    Instrument, LoopType, NewNoteAction, Note, OrderEntry, Pattern, Sample, SampleData, Song, VolumeCommand,
+};
+
+use crate::effect_parser::parse_it_effect;
+use crate::FormatError;
+
+/// Maximum pattern channels in an IT file.
+const MAX_CHANNELS: usize = 64;
+
+/// Rows in the implicit pattern used for a zero pattern offset.
+const EMPTY_PATTERN_ROWS: u16 = 64;
+
+/// Semitones between IT note numbers and engine note numbers.
+const NOTE_OFFSET: u8 = 12;
+
+/// Sample-map entry for keyboard slots without a sample.
+const NO_SAMPLE: u8 = u8::MAX;
+
+/// Header flag: patterns reference instruments rather than samples.
+const FLAG_INSTRUMENTS: u16 = 0x04;
+
+/// Header flag: pitch slides use linear frequencies rather than Amiga periods.
+const FLAG_LINEAR_SLIDES: u16 = 0x08;
+
+/// Sample header flags.
+const SMP_PRESENT: u8 = 0x01;
+const SMP_16BIT: u8 = 0x02;
+const SMP_STEREO: u8 = 0x04;
+const SMP_COMPRESSED: u8 = 0x08;
+const SMP_LOOP: u8 = 0x10;
+const SMP_SUSTAIN: u8 = 0x20;
+const SMP_PING_PONG: u8 = 0x40;
+
+/// Sample conversion flags.
+const CVT_SIGNED: u8 = 0x01;
+const CVT_DELTA: u8 = 0x04;
+
+// Little-endian field readers, shared with the S3M loader. Reads past the
+// end of the file fail with `UnexpectedEof`.
+
+pub(crate) fn u8_at(data: &[u8], offset: usize) -> Result<u8, FormatError> {
+    data.get(offset).copied().ok_or(FormatError::UnexpectedEof)
+}
+
+pub(crate) fn u16_at(data: &[u8], offset: usize) -> Result<u16, FormatError> {
+    let b = bytes_at(data, offset, 2)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+pub(crate) fn u32_at(data: &[u8], offset: usize) -> Result<u32, FormatError> {
+    let b = bytes_at(data, offset, 4)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+pub(crate) fn bytes_at(data: &[u8], offset: usize, len: usize) -> Result<&[u8], FormatError> {
+    data.get(offset..offset + len).ok_or(FormatError::UnexpectedEof)
+}
+
+/// Read a table of `count` little-endian u32 offsets.
+fn offsets_at(data: &[u8], offset: usize, count: usize) -> Result<Vec<usize>, FormatError> {
+    (0..count).map(|i| u32_at(data, offset + i * 4).map(|o| o as usize)).collect()
+}
+
+/// Load an IT file from bytes.
+pub fn load_it(data: &[u8]) -> Result<Song, FormatError> {
+    if data.len() < 0xC0 {
+        return Err(FormatError::UnexpectedEof);
+    }
+    if &data[0..4] != b"IMPM" {
+        return Err(FormatError::InvalidHeader);
+    }
+
+    let title = parse_string(&data[4..30]);
+    let ord_num = u16_at(data, 0x20)? as usize;
+    let ins_num = u16_at(data, 0x22)? as usize;
+    let smp_num = u16_at(data, 0x24)? as usize;
+    let pat_num = u16_at(data, 0x26)? as usize;
+    let compat_version = u16_at(data, 0x2A)?;
+    let flags = u16_at(data, 0x2C)?;
+
+    let orders = bytes_at(data, 0xC0, ord_num)?;
+    let ins_table = 0xC0 + ord_num;
+    let smp_table = ins_table + ins_num * 4;
+    let pat_table = smp_table + smp_num * 4;
+    let ins_offsets = offsets_at(data, ins_table, ins_num)?;
+    let smp_offsets = offsets_at(data, smp_table, smp_num)?;
+    let pat_offsets = offsets_at(data, pat_table, pat_num)?;
+
+    // Patterns first: the channel count is the highest channel they use
+    let mut parsed = Vec::with_capacity(pat_num);
+    for (i, &offset) in pat_offsets.iter().enumerate() {
+        parsed.push(parse_pattern(data, offset).unwrap_or_else(|e| {
+            eprintln!("[IT] WARNING: pattern {} unreadable ({:?}), using an empty pattern", i, e);
+            ItPattern::empty()
+        }));
+    }
+    let num_channels = parsed
+        .iter()
+        .flat_map(|p| p.cells.iter().map(|&(_, ch, _)| ch + 1))
+        .max()
+        .unwrap_or(1);
+
+    let mut song = Song::with_channels(&title, num_channels);
+    song.rows_per_beat = 4;
+    song.compatibility = CompatibilityMode::ImpulseTracker;
+    song.initial_speed = data[0x32].max(1);
+    song.initial_tempo = data[0x33].max(32) as u32 * 100;
+    song.global_volume = data[0x30].min(128) / 2;
+    if flags & FLAG_LINEAR_SLIDES != 0 {
+        song.frequency_mode = FrequencyMode::Linear;
+    }
+    for (ch, settings) in song.channels.iter_mut().enumerate() {
+        *settings = parse_channel_settings(data[0x40 + ch], data[0x80 + ch]);
+    }
+
+    for (i, &offset) in smp_offsets.iter().enumerate() {
+        song.samples.push(parse_sample(data, offset).unwrap_or_else(|e| {
+            eprintln!("[IT] WARNING: sample {} unreadable ({:?}), leaving it empty", i + 1, e);
+            Sample::new("")
+        }));
+    }
+
+    if flags & FLAG_INSTRUMENTS != 0 {
+        for &offset in &ins_offsets {
+            let inst = if compat_version < 0x200 {
+                parse_old_instrument(data, offset)?
+            } else {
+                parse_instrument(data, offset)?
+            };
+            song.instruments.push(inst);
+        }
+    } else {
+        // Sample mode: each sample is its own instrument
+        for (i, sample) in song.samples.iter().enumerate() {
+            let mut inst = Instrument::new(&sample.name);
+            inst.set_single_sample(i as u8);
+            song.instruments.push(inst);
+        }
+    }
+
+    let patterns: Vec<Pattern> = parsed.iter().map(|p| p.to_pattern(num_channels)).collect();
+    let order: Vec<OrderEntry> = orders
+        .iter()
+        .map(|&o| match o {
+            255 => OrderEntry::End,
+            254 => OrderEntry::Skip,
+            p if (p as usize) < patterns.len() => OrderEntry::Pattern(p),
+            p => {
+                eprintln!("[IT] WARNING: order references missing pattern {}, skipping", p);
+                OrderEntry::Skip
+            }
+        })
+        .collect();
+    build_tracks(&mut song, &patterns, &order);
+
+    Ok(song)
+}
+
+/// Parse a fixed-size, null-padded string.
+fn parse_string(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).trim().to_string()
+}
+
+/// Channel pan (0-64, 100 = surround, +128 = disabled) and volume (0-64).
+fn parse_channel_settings(pan: u8, vol: u8) -> ChannelSettings {
+    let initial_pan = match pan & 0x7F {
+        p @ 0..=64 => ((p as i16 - 32) * 2).clamp(-64, 64) as i8,
+        _ => 0,
+    };
+    ChannelSettings { initial_pan, initial_vol: vol.min(64), muted: pan & 0x80 != 0, solo: false }
+}
+
+// ---------------------------------------------------------------------------
+// Patterns
+// ---------------------------------------------------------------------------
+
+/// A decoded pattern before the song's channel count is known.
+struct ItPattern {
+    rows: u16,
+    cells: Vec<(u16, u8, Cell)>,
+}
+
+impl ItPattern {
+    fn empty() -> Self {
+        Self { rows: EMPTY_PATTERN_ROWS, cells: Vec::new() }
+    }
+
+    fn to_pattern(&self, num_channels: u8) -> Pattern {
+        let mut pattern = Pattern::new(self.rows, num_channels);
+        for &(row, ch, cell) in &self.cells {
+            *pattern.cell_mut(row, ch) = cell;
+        }
+        pattern
+    }
+}
+
+/// Last values per channel, reused by the packed format's "same as last" bits.
+#[derive(Clone, Copy, Default)]
+struct ChannelMemory {
+    mask: u8,
+    note: u8,
+    instrument: u8,
+    volpan: u8,
+    command: u8,
+    param: u8,
+}
+
+/// Decode a packed pattern. Offset 0 means an empty 64-row pattern.
+fn parse_pattern(data: &[u8], offset: usize) -> Result<ItPattern, FormatError> {
+    if offset == 0 {
+        return Ok(ItPattern::empty());
+    }
+    let len = u16_at(data, offset)? as usize;
+    let rows = u16_at(data, offset + 2)?.clamp(1, 200);
+    let packed = bytes_at(data, offset + 8, len)?;
+
+    let mut memory = [ChannelMemory::default(); MAX_CHANNELS];
+    let mut cells = Vec::new();
+    let mut pos = 0;
+    let mut next = || -> Result<u8, FormatError> {
+        let b = u8_at(packed, pos)?;
+        pos += 1;
+        Ok(b)
+    };
+
+    let mut row = 0;
+    while row < rows {
+        let chan_var = next()?;
+        if chan_var == 0 {
+            row += 1;
+            continue;
+        }
+        let ch = ((chan_var - 1) & 63) as usize;
+        let mem = &mut memory[ch];
+        if chan_var & 0x80 != 0 {
+            mem.mask = next()?;
+        }
+        let mask = mem.mask;
+        if mask & 0x01 != 0 {
+            mem.note = next()?;
+        }
+        if mask & 0x02 != 0 {
+            mem.instrument = next()?;
+        }
+        if mask & 0x04 != 0 {
+            mem.volpan = next()?;
+        }
+        if mask & 0x08 != 0 {
+            mem.command = next()?;
+            mem.param = next()?;
+        }
+
+        let mut cell = Cell::empty();
+        if mask & 0x11 != 0 {
+            cell.note = parse_note(mem.note);
+        }
+        if mask & 0x22 != 0 {
+            cell.instrument = mem.instrument;
+        }
+        if mask & 0x44 != 0 {
+            cell.volume = parse_volume(mem.volpan);
+        }
+        if mask & 0x88 != 0 {
+            cell.effect = parse_it_effect(mem.command, mem.param);
+        }
+        // Note cut has no note value of its own; express it as an immediate cut
+        if mask & 0x11 != 0 && mem.note == 254 && cell.effect == Effect::None {
+            cell.effect = Effect::NoteCut(0);
+        }
+        cells.push((row, ch as u8, cell));
+    }
+
+    Ok(ItPattern { rows, cells })
+}
+
+/// IT note byte: 0-119 notes, 255 note off, 254 note cut, anything else fade.
+fn parse_note(note: u8) -> Note {
+    match note {
+        0..=119 => note.checked_sub(NOTE_OFFSET).map_or(Note::None, Note::On),
+        255 => Note::Off,
+        254 => Note::None,
+        _ => Note::Fade,
+    }
+}
+
+/// Decode the combined volume/panning column.
+fn parse_volume(v: u8) -> VolumeCommand {
+    /// Tone portamento speeds for volume-column Gx.
+    const PORTA: [u8; 10] = [0, 1, 4, 8, 16, 32, 64, 96, 128, 255];
+    match v {
+        0..=64 => VolumeCommand::Volume(v),
+        65..=74 => VolumeCommand::FineVolSlideUp(v - 65),
+        75..=84 => VolumeCommand::FineVolSlideDown(v - 75),
+        85..=94 => VolumeCommand::VolumeSlideUp(v - 85),
+        95..=104 => VolumeCommand::VolumeSlideDown(v - 95),
+        105..=114 => VolumeCommand::PortaDown((v - 105) * 4),
+        115..=124 => VolumeCommand::PortaUp((v - 115) * 4),
+        128..=192 => VolumeCommand::Panning(v - 128),
+        193..=202 => VolumeCommand::TonePorta(PORTA[(v - 193) as usize]),
+        203..=212 => VolumeCommand::Vibrato(v - 203),
+        _ => VolumeCommand::None,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Instruments
+// ---------------------------------------------------------------------------
+
+fn new_note_action(nna: u8) -> NewNoteAction {
+    match nna {
+        1 => NewNoteAction::Continue,
+        2 => NewNoteAction::Off,
+        3 => NewNoteAction::Fade,
+        _ => NewNoteAction::Cut,
+    }
+}
+
+fn duplicate_check(dct: u8) -> DuplicateCheck {
+    match dct {
+        1 => DuplicateCheck::Note,
+        2 => DuplicateCheck::Sample,
+        3 => DuplicateCheck::Instrument,
+        _ => DuplicateCheck::Off,
+    }
+}
+
+/// Convert the 120-entry (note, sample) keyboard table to a sample map.
+///
+/// Per-key note transposition is not represented in the IR and is dropped.
+fn parse_keyboard(table: &[u8]) -> [u8; 120] {
+    let mut map = [NO_SAMPLE; 120];
+    for (it_note, pair) in table.chunks_exact(2).enumerate().skip(NOTE_OFFSET as usize) {
+        if pair[1] > 0 {
+            map[it_note - NOTE_OFFSET as usize] = pair[1] - 1;
+        }
+    }
+    map
+}
+
+/// Instrument header for files saved by IT 2.00 and later.
+fn parse_instrument(data: &[u8], offset: usize) -> Result<Instrument, FormatError> {
+    let hdr = bytes_at(data, offset, 0x22A)?;
+    if &hdr[0..4] != b"IMPI" {
+        return Err(FormatError::InvalidHeader);
+    }
+    let mut inst = Instrument::new(&parse_string(&hdr[0x20..0x3A]));
+    inst.new_note_action = new_note_action(hdr[0x11]);
+    inst.duplicate_check = duplicate_check(hdr[0x12]);
+    inst.fadeout = u16::from_le_bytes([hdr[0x14], hdr[0x15]]);
+    inst.sample_map = parse_keyboard(&hdr[0x40..0x130]);
+    inst.volume_envelope = parse_envelope(&hdr[0x130..0x182], 1);
+    inst.panning_envelope = parse_envelope(&hdr[0x182..0x1D4], 2);
+    // Pitch values stay in IT's half-semitone units; the filter flag (bit 7) is not kept
+    inst.pitch_envelope = parse_envelope(&hdr[0x1D4..0x226], 1);
+    Ok(inst)
+}
+
+/// 82-byte envelope: flags, node count, loop/sustain points, then 25
+/// (value, tick) nodes. `scale` maps IT values onto the IR range.
+fn parse_envelope(env: &[u8], scale: i8) -> Option<Envelope> {
+    let flags = env[0];
+    let count = (env[1] as usize).min(25);
+    if count == 0 {
+        return None;
+    }
+    let mut envelope = Envelope::new();
+    for node in env[6..6 + count * 3].chunks_exact(3) {
+        let value = (node[0] as i8).saturating_mul(scale);
+        envelope.add_point(u16::from_le_bytes([node[1], node[2]]), value);
+    }
+    envelope.enabled = flags & 0x01 != 0;
+    if flags & 0x02 != 0 {
+        envelope.loop_start = Some(env[2]);
+        envelope.loop_end = Some(env[3]);
+    }
+    if flags & 0x04 != 0 {
+        envelope.sustain_start = Some(env[4]);
+        envelope.sustain_end = Some(env[5]);
+    }
+    Some(envelope)
+}
+
+/// Instrument header for files saved by IT 1.xx: volume envelope only,
+/// duplicate check on/off, and half-resolution fadeout.
+fn parse_old_instrument(data: &[u8], offset: usize) -> Result<Instrument, FormatError> {
+    let hdr = bytes_at(data, offset, 0x22A)?;
+    if &hdr[0..4] != b"IMPI" {
+        return Err(FormatError::InvalidHeader);
+    }
+    let mut inst = Instrument::new(&parse_string(&hdr[0x20..0x3A]));
+    let flags = hdr[0x11];
+    inst.fadeout = u16::from_le_bytes([hdr[0x18], hdr[0x19]]).saturating_mul(2);
+    inst.new_note_action = new_note_action(hdr[0x1A]);
+    inst.duplicate_check = if hdr[0x1B] != 0 { DuplicateCheck::Note } else { DuplicateCheck::Off };
+    inst.sample_map = parse_keyboard(&hdr[0x40..0x130]);
+
+    let mut envelope = Envelope::new();
+    for node in hdr[0x1F8..0x22A].chunks_exact(2).take_while(|n| n[0] != 0xFF) {
+        envelope.add_point(node[0] as u16, node[1].min(64) as i8);
+    }
+    if !envelope.points.is_empty() {
+        envelope.enabled = flags & 0x01 != 0;
+        if flags & 0x02 != 0 {
+            envelope.loop_start = Some(hdr[0x12]);
+            envelope.loop_end = Some(hdr[0x13]);
+        }
+        if flags & 0x04 != 0 {
+            envelope.sustain_start = Some(hdr[0x14]);
+            envelope.sustain_end = Some(hdr[0x15]);
+        }
+        inst.volume_envelope = Some(envelope);
+    }
+    Ok(inst)
+}
+
+// ---------------------------------------------------------------------------
+// Samples
+// ---------------------------------------------------------------------------
+
+/// IT auto-vibrato rate (depth gained per tick, in 1/256ths) as the IR's
+/// sweep time in ticks (0 = full depth at once).
+fn sweep_ticks(depth: u8, rate: u8) -> u8 {
+    if rate == 0 {
+        return 0;
+    }
+    (depth as u32 * 256 / rate as u32).min(255) as u8
+}
+
+/// Parse an IMPS sample header and its data.
+fn parse_sample(data: &[u8], offset: usize) -> Result<Sample, FormatError> {
+    let hdr = bytes_at(data, offset, 0x50)?;
+    if &hdr[0..4] != b"IMPS" {
+        return Err(FormatError::InvalidHeader);
+    }
+    let flags = hdr[0x12];
+    let cvt = hdr[0x2E];
+    let field = |o: usize| u32::from_le_bytes([hdr[o], hdr[o + 1], hdr[o + 2], hdr[o + 3]]);
+    let length = field(0x30);
+
+    let mut sample = Sample::new(&parse_string(&hdr[0x14..0x2E]));
+    sample.default_volume = hdr[0x13].min(64);
+    sample.c4_speed = field(0x3C);
+    let pan = hdr[0x2F];
+    if pan & 0x80 != 0 {
+        sample.default_pan = (((pan & 0x7F).min(64) as i16 - 32) * 2).clamp(-64, 64) as i8;
+    }
+    if hdr[0x4D] > 0 {
+        sample.vibrato = Some(AutoVibrato {
+            speed: hdr[0x4C],
+            depth: hdr[0x4D],
+            sweep: sweep_ticks(hdr[0x4D], hdr[0x4E]),
+            waveform: hdr[0x4F],
+        });
+    }
+
+    // One loop in the IR: the regular loop wins, else the sustain loop
+    let (loop_start, loop_end, loop_type) = if flags & SMP_LOOP != 0 {
+        let kind = if flags & SMP_PING_PONG != 0 { LoopType::PingPong } else { LoopType::Forward };
+        (field(0x34), field(0x38), kind)
+    } else if flags & SMP_SUSTAIN != 0 {
+        (field(0x40), field(0x44), LoopType::Sustain)
+    } else {
+        (0, 0, LoopType::None)
+    };
+    if loop_type != LoopType::None && loop_start < loop_end && loop_end <= length {
+        sample.loop_start = loop_start;
+        sample.loop_end = loop_end;
+        sample.loop_type = loop_type;
+    }
+
+    if flags & SMP_PRESENT != 0 && length > 0 {
+        let pointer = field(0x48) as usize;
+        sample.set_data(read_sample_data(data, pointer, length as usize, flags, cvt));
+    }
+    Ok(sample)
+}
+
+/// Read raw or compressed sample data. Truncated data is zero-padded, up
+/// to as many frames as the remaining bytes could hold.
+fn read_sample_data(data: &[u8], pointer: usize, frames: usize, flags: u8, cvt: u8) -> SampleData {
+    let src = data.get(pointer..).unwrap_or(&[]);
+    let is_16bit = flags & SMP_16BIT != 0;
+    let stereo = flags & SMP_STEREO != 0;
+    let channels = if stereo { 2 } else { 1 };
+    let frames = frames.min(max_frames(src.len(), is_16bit, flags & SMP_COMPRESSED != 0));
+
+    let mut decoded: Vec<Vec<i16>> = Vec::with_capacity(channels);
+    if flags & SMP_COMPRESSED != 0 {
+        let mut pos = 0;
+        let delta = cvt & CVT_DELTA != 0;
+        for _ in 0..channels {
+            let (chan, used) = decompress(&src[pos..], frames, is_16bit, delta);
+            decoded.push(chan);
+            pos += used;
+        }
+    } else {
+        let width = if is_16bit { 2 } else { 1 };
+        for c in 0..channels {
+            let start = (c * frames * width).min(src.len());
+            let raw = &src[start..(start + frames * width).min(src.len())];
+            let chan: Vec<i16> = if is_16bit {
+                raw.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect()
+            } else {
+                raw.iter().map(|&b| b as i8 as i16).collect()
+            };
+            decoded.push(chan);
+        }
+        if cvt & CVT_SIGNED == 0 {
+            let flip = if is_16bit { i16::MIN } else { -128 };
+            decoded.iter_mut().flatten().for_each(|s| *s ^= flip);
+        }
+    }
+
+    if decoded.iter().any(|c| c.len() < frames) {
+        eprintln!("[IT] WARNING: sample data truncated, padding with silence");
+        decoded.iter_mut().for_each(|c| c.resize(frames, 0));
+    }
+    let mut chans = decoded.into_iter();
+    let left = chans.next().unwrap_or_default();
+    let narrow = |v: Vec<i16>| v.into_iter().map(|s| s as i8).collect();
+    match (chans.next(), is_16bit) {
+        (None, false) => SampleData::Mono8(narrow(left)),
+        (None, true) => SampleData::Mono16(left),
+        (Some(right), false) => SampleData::Stereo8(narrow(left), narrow(right)),
+        (Some(right), true) => SampleData::Stereo16(left, right),
+    }
+}
+
+/// Most frames `bytes` of sample data can hold: compressed samples take at
+/// least a bit each.
+fn max_frames(bytes: usize, is_16bit: bool, compressed: bool) -> usize {
+    match (compressed, is_16bit) {
+        (true, _) => bytes.saturating_mul(8),
+        (false, true) => bytes.div_ceil(2),
+        (false, false) => bytes,
+    }
+}
+
+/// LSB-first bit reader over one compressed block.
+struct BitReader<'a> {
+    data: &'a [u8],
+    bit_pos: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, bits: u8) -> Option<u32> {
+        let mut value = 0u32;
+        for i in 0..bits as u32 {
+            let byte = *self.data.get(self.bit_pos / 8)?;
+            value |= (((byte >> (self.bit_pos % 8)) & 1) as u32) << i;
+            self.bit_pos += 1;
+        }
+        Some(value)
+    }
+}
+
+/// Decompress IT214 (or IT215 with `delta`) sample data.
+///
+/// Data comes in blocks of 0x8000 8-bit or 0x4000 16-bit samples, each
+/// prefixed with its compressed size. Samples are delta-coded with an
+/// adaptive bit width; IT215 integrates twice. Returns the decoded frames
+/// and the number of input bytes consumed.
+fn decompress(src: &[u8], frames: usize, is_16bit: bool, delta: bool) -> (Vec<i16>, usize) {
+    // (block samples, full width, width-change bits, method-2 half range, sample bits)
+    let (block_len, top, change_bits, half, sample_bits) =
+        if is_16bit { (0x4000, 17u8, 4, 8, 16u8) } else { (0x8000, 9u8, 3, 4, 8u8) };
+
+    let frames = frames.min(max_frames(src.len(), is_16bit, true));
+    let mut out = Vec::with_capacity(frames);
+    let mut pos = 0;
+    while out.len() < frames {
+        let Some(size) = src.get(pos..pos + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize) else {
+            break;
+        };
+        let block = &src[(pos + 2).min(src.len())..(pos + 2 + size).min(src.len())];
+        pos += 2 + size;
+
+        let mut reader = BitReader { data: block, bit_pos: 0 };
+        let mut width = top;
+        let (mut d1, mut d2) = (0i32, 0i32);
+        let count = block_len.min(frames - out.len());
+        let mut decoded = 0;
+        while decoded < count {
+            let Some(value) = reader.read(width) else { break };
+            let adjust = |w: u8| if w < width { w } else { w + 1 };
+            let new_width = if width < 7 {
+                // Method 1: a lone top bit announces a new width
+                if value != 1 << (width - 1) {
+                    None
+                } else if let Some(w) = reader.read(change_bits) {
+                    Some(adjust(w as u8 + 1))
+                } else {
+                    break;
+                }
+            } else if width < top {
+                // Method 2: values just above the border announce a new width
+                let border = ((1u32 << (width - 1)) - 1) - half;
+                (value > border && value <= border + 2 * half).then(|| adjust((value - border) as u8))
+            } else {
+                // Method 3: the top bit flags a width change
+                (value & (1 << (top - 1)) != 0).then_some((value + 1) as u8)
+            };
+            if let Some(w) = new_width {
+                if w == 0 || w > top {
+                    break;
+                }
+                width = w;
+                continue;
+            }
+
+            // Sign-extend from the current width
+            let shift = 32 - width.min(sample_bits) as u32;
+            let v = ((value << shift) as i32) >> shift;
+            d1 = wrap(d1 + v, sample_bits);
+            d2 = wrap(d2 + d1, sample_bits);
+            out.push((if delta { d2 } else { d1 }) as i16);
+            decoded += 1;
+        }
+        // Short or corrupt block: pad it so the next block stays aligned
+        out.resize(out.len() + (count - decoded), 0);
+    }
+    (out, pos.min(src.len()))
+}
+
+/// Wrap to a signed `bits`-bit integer.
+fn wrap(v: i32, bits: u8) -> i32 {
+    let shift = 32 - bits as u32;
+    (v << shift) >> shift
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// IMPM header with one sample-mode sample, one order, and one pattern.
+    fn header(pattern: &[u8], sample_data: &[u8], sample_flags: u8) -> Vec<u8> {
+        let mut data = alloc::vec![0u8; 0xC0];
+        data[0..4].copy_from_slice(b"IMPM");
+        data[4..8].copy_from_slice(b"Test");
+        data[0x20..0x22].copy_from_slice(&1u16.to_le_bytes()); // orders
+        data[0x24..0x26].copy_from_slice(&1u16.to_le_bytes()); // samples
+        data[0x26..0x28].copy_from_slice(&1u16.to_le_bytes()); // patterns
+        data[0x2A..0x2C].copy_from_slice(&0x214u16.to_le_bytes());
+        data[0x30] = 128;
+        data[0x32] = 3;
+        data[0x33] = 140;
+        data[0x40..0x80].fill(32);
+        data[0x40 + 1] = 0; // channel 2 hard left
+        data[0x80..0xC0].fill(64);
+        data.push(0); // order list: pattern 0
+        let tables = data.len();
+        data.resize(tables + 8, 0);
+
+        let smp_offset = data.len();
+        let mut smp = alloc::vec![0u8; 0x50];
+        smp[0..4].copy_from_slice(b"IMPS");
+        smp[0x12] = sample_flags;
+        smp[0x13] = 48;
+        smp[0x2E] = CVT_SIGNED;
+        smp[0x30..0x34].copy_from_slice(&(sample_data.len() as u32).to_le_bytes());
+        smp[0x34..0x38].copy_from_slice(&2u32.to_le_bytes());
+        smp[0x38..0x3C].copy_from_slice(&6u32.to_le_bytes());
+        smp[0x3C..0x40].copy_from_slice(&22050u32.to_le_bytes());
+        let ptr = smp_offset + 0x50;
+        smp[0x48..0x4C].copy_from_slice(&(ptr as u32).to_le_bytes());
+        data.extend_from_slice(&smp);
+        data.extend_from_slice(sample_data);
+
+        let pat_offset = data.len();
+        data.extend_from_slice(&(pattern.len() as u16).to_le_bytes());
+        data.extend_from_slice(&4u16.to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(pattern);
+
+        data[tables..tables + 4].copy_from_slice(&(smp_offset as u32).to_le_bytes());
+        data[tables + 4..tables + 8].copy_from_slice(&(pat_offset as u32).to_le_bytes());
+        data
+    }
+
+    /// Row 0: channel 2 plays C-5 with instrument 1, volume 32, effect A04.
+    /// Row 1: channel 2 repeats everything from memory. Rows 2-3 empty.
+    const PATTERN: [u8; 13] = [
+        0x82, 0x0F, 60, 1, 32, 1, 4, 0, // row 0
+        0x82, 0xF0, 0, // row 1: mask 0xF0 = all "last value"
+        0, 0,
+    ];
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut data = header(&PATTERN, &[], 0);
+        data[0] = b'X';
+        assert!(matches!(load_it(&data), Err(FormatError::InvalidHeader)));
+    }
+
+    #[test]
+    fn loads_header_and_packed_pattern() {
+        let song = load_it(&header(&PATTERN, &[0, 10, 20, 30, 40, 50, 60, 70], SMP_PRESENT | SMP_LOOP))
+            .unwrap();
+        assert_eq!(song.title.as_str(), "Test");
+        assert_eq!((song.initial_speed, song.initial_tempo, song.global_volume), (3, 14000, 64));
+        assert_eq!(song.channels.len(), 2);
+        assert_eq!(song.channels[1].initial_pan, -64);
+        assert_eq!(song.frequency_mode, FrequencyMode::Amiga);
+        assert_eq!(song.compatibility, CompatibilityMode::ImpulseTracker);
+
+        let pat = song.tracks[0].clips[0].pattern().unwrap();
+        assert_eq!(pat.rows, 4);
+        let cell = pat.cell(0, 1);
+        assert_eq!(cell.note, Note::On(48));
+        assert_eq!(cell.instrument, 1);
+        assert_eq!(cell.volume, VolumeCommand::Volume(32));
+        assert_eq!(cell.effect, Effect::SetSpeed(4));
+        assert_eq!(pat.cell(1, 1), cell);
+        assert_eq!(pat.cell(1, 0), &Cell::empty());
+    }
+
+    #[test]
+    fn linear_slides_flag_sets_frequency_mode() {
+        let mut data = header(&PATTERN, &[], 0);
+        data[0x2C] |= FLAG_LINEAR_SLIDES as u8;
+        assert_eq!(load_it(&data).unwrap().frequency_mode, FrequencyMode::Linear);
+    }
+
+    #[test]
+    fn auto_vibrato_rate_becomes_sweep_ticks() {
+        assert_eq!(sweep_ticks(8, 0), 0);
+        assert_eq!(sweep_ticks(8, 32), 64);
+        assert_eq!(sweep_ticks(64, 1), 255);
+    }
+
+    #[test]
+    fn loads_sample_header_and_data() {
+        let song = load_it(&header(&PATTERN, &[0, 10, 20, 30, 40, 50, 60, 70], SMP_PRESENT | SMP_LOOP | SMP_PING_PONG))
+            .unwrap();
+        let sample = &song.samples[0];
+        assert_eq!(sample.c4_speed, 22050);
+        assert_eq!(sample.default_volume, 48);
+        assert_eq!((sample.loop_start, sample.loop_end, sample.loop_type), (2, 6, LoopType::PingPong));
+        assert_eq!(sample.data.get_mono(3), 30 * 256);
+        // Sample mode: one instrument per sample
+        assert_eq!(song.instruments.len(), 1);
+    }
+
+    #[test]
+    fn note_bytes_map_to_ir_notes() {
+        assert_eq!(parse_note(60), Note::On(48));
+        assert_eq!(parse_note(5), Note::None);
+        assert_eq!(parse_note(255), Note::Off);
+        assert_eq!(parse_note(200), Note::Fade);
+    }
+
+    #[test]
+    fn volume_column_ranges() {
+        assert_eq!(parse_volume(64), VolumeCommand::Volume(64));
+        assert_eq!(parse_volume(70), VolumeCommand::FineVolSlideUp(5));
+        assert_eq!(parse_volume(100), VolumeCommand::VolumeSlideDown(5));
+        assert_eq!(parse_volume(160), VolumeCommand::Panning(32));
+        assert_eq!(parse_volume(195), VolumeCommand::TonePorta(4));
+    }
+
+    #[test]
+    fn instrument_envelopes_and_nna() {
+        let mut hdr = alloc::vec![0u8; 0x22A];
+        hdr[0..4].copy_from_slice(b"IMPI");
+        hdr[0x11] = 3; // note fade
+        hdr[0x12] = 2; // duplicate check: sample
+        hdr[0x14..0x16].copy_from_slice(&256u16.to_le_bytes());
+        // C-5 (IT note 60) -> sample 2
+        hdr[0x40 + 60 * 2 + 1] = 2;
+        // Volume envelope: on + sustain, 2 nodes
+        hdr[0x130] = 0x05;
+        hdr[0x131] = 2;
+        hdr[0x134] = 1;
+        hdr[0x135] = 1;
+        hdr[0x136..0x139].copy_from_slice(&[64, 0, 0]);
+        hdr[0x139..0x13C].copy_from_slice(&[0, 10, 0]);
+        // Panning envelope: on, one node at -16
+        hdr[0x182] = 0x01;
+        hdr[0x183] = 1;
+        hdr[0x188] = (-16i8) as u8;
+
+        let inst = parse_instrument(&hdr, 0).unwrap();
+        assert_eq!(inst.new_note_action, NewNoteAction::Fade);
+        assert_eq!(inst.duplicate_check, DuplicateCheck::Sample);
+        assert_eq!(inst.fadeout, 256);
+        assert_eq!(inst.sample_map[48], 1);
+        assert_eq!(inst.sample_map[47], NO_SAMPLE);
+        let vol = inst.volume_envelope.unwrap();
+        assert!(vol.enabled);
+        assert_eq!(vol.points.len(), 2);
+        assert_eq!(vol.value_at(5), 32);
+        assert_eq!((vol.sustain_start, vol.sustain_end, vol.loop_start), (Some(1), Some(1), None));
+        assert_eq!(inst.panning_envelope.unwrap().points[0].value, -32);
+        assert!(inst.pitch_envelope.is_none());
+    }
+
+    /// LSB-first bit writer for building compressed test blocks.
+    struct BitWriter {
+        bytes: Vec<u8>,
+        bit: usize,
+    }
+
+    impl BitWriter {
+        fn write(&mut self, value: u32, bits: u8) {
+            for i in 0..bits {
+                if self.bit.is_multiple_of(8) {
+                    self.bytes.push(0);
+                }
+                let last = self.bytes.last_mut().unwrap();
+                *last |= (((value >> i) & 1) as u8) << (self.bit % 8);
+                self.bit += 1;
+            }
+        }
+    }
+
+    fn block(bits: BitWriter) -> Vec<u8> {
+        let mut out = (bits.bytes.len() as u16).to_le_bytes().to_vec();
+        out.extend(bits.bytes);
+        out
+    }
+
+    #[test]
+    fn decompresses_it214_8bit_with_width_changes() {
+        let mut w = BitWriter { bytes: Vec::new(), bit: 0 };
+        // 9-bit deltas: +10, -3 (bit 8 is the width-change flag, so only 8 data bits)
+        w.write(10, 9);
+        w.write((-3i32 as u32) & 0xFF, 9);
+        // Method 3: 0x100 | 3 switches to width 4
+        w.write(0x100 | 3, 9);
+        // 4-bit deltas: +5, -8 + 1 = -7
+        w.write(5, 4);
+        w.write((-7i32 as u32) & 0xF, 4);
+        // Method 1: 1000 announces a change; 7 + 1 = 8 is not below 4, so width 9
+        w.write(0x8, 4);
+        w.write(7, 3);
+        w.write(1, 9);
+        let (out, used) = decompress(&block(w), 5, false, false);
+        assert_eq!(out, [10, 7, 12, 5, 6]);
+        assert!(used > 2);
+    }
+
+    #[test]
+    fn decompresses_it215_double_delta() {
+        let mut w = BitWriter { bytes: Vec::new(), bit: 0 };
+        for d in [1u32, 1, 0] {
+            w.write(d, 9);
+        }
+        // d1: 1, 2, 2; d2: 1, 3, 5
+        let (out, _) = decompress(&block(w), 3, false, true);
+        assert_eq!(out, [1, 3, 5]);
+    }
+
+    #[test]
+    fn decompresses_it214_16bit_and_pads_short_input() {
+        let mut w = BitWriter { bytes: Vec::new(), bit: 0 };
+        w.write(1000, 17);
+        w.write((-2000i32 as u32) & 0xFFFF, 17);
+        let (out, _) = decompress(&block(w), 4, true, false);
+        assert_eq!(out, [1000, -1000, 0, 0]);
+    }
+
+    #[test]
+    fn header_lengths_are_bounded_by_the_data_left() {
+        let data = [0u8; 64];
+        let raw = read_sample_data(&data, 32, u32::MAX as usize, SMP_16BIT | SMP_STEREO, CVT_SIGNED);
+        assert_eq!(raw.len(), 16);
+        let (out, _) = decompress(&[0, 0], usize::MAX, false, false);
+        assert_eq!(out.len(), 16);
+    }
+}
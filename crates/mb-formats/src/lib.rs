@@ -1,16 +1,39 @@
 //! Format parsers for masterblaster tracker.
 //!
-//! Parses MOD, XM, IT, S3M, and BMX files into the IR.
+//! Parses MOD, XM, IT, S3M, BMX and MIDI files into the IR, imports WAV,
+//! AIFF and raw PCM samples, and saves/loads songs in the native project
+//! format. Songs can be written back out as MOD or BMX, and rendered audio
+//! encoded as WAV, FLAC or Ogg Vorbis.
 
+mod aiff_format;
+mod bmx_export;
 #[allow(dead_code)]
 mod bmx_format;
 mod effect_parser;
+mod flac_export;
+mod vorbis_export;
+mod it_format;
+mod midi_format;
+mod mod_export;
 mod mod_format;
+mod project_format;
+mod raw_format;
+mod s3m_format;
 mod wav_format;
 
+pub use aiff_format::load_aiff;
+pub use bmx_export::{write_bmx, BmxExport};
 pub use bmx_format::load_bmx;
+pub use flac_export::{frames_to_flac, FlacStreamWriter};
+pub use it_format::load_it;
+pub use midi_format::load_midi;
+pub use mod_export::{write_mod, ModExport};
 pub use mod_format::load_mod;
-pub use wav_format::{frames_to_wav, load_wav, parse_wav_i16_samples, write_wav};
+pub use project_format::{load_song, save_song};
+pub use raw_format::{load_raw, RawFormat};
+pub use s3m_format::load_s3m;
+pub use vorbis_export::{frames_to_ogg, VorbisSpec, VorbisStreamWriter, MAX_VORBIS_QUALITY};
+pub use wav_format::{frames_to_wav, load_wav, parse_wav_i16_samples, write_wav, WavDepth, WavSpec, WavStreamWriter};
 
 /// Error type for format parsing.
 #[derive(Debug)]
@@ -21,6 +44,10 @@ pub enum FormatError {
     UnexpectedEof,
     /// Unsupported format version
     UnsupportedVersion,
+    /// Graph node kind this build doesn't know, by its project file tag
+    UnknownNodeType(u8),
+    /// Song can't be represented in the target format
+    Unsupported(alloc::string::String),
     /// I/O error
     Io(alloc::string::String),
 }
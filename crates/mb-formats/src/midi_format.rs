@@ -0,0 +1,600 @@
+//! Standard MIDI File (SMF) import.
+//!
+//! Every MIDI channel that plays notes becomes a track driving its own
+//! tracker node. Notes are quantized to the nearest row at the requested
+//! rows per beat and spread over as many columns as the channel's polyphony
+//! needs; tempo meta events become SetTempo effects. MIDI carries no audio,
+//! so every instrument plays the same sine wave.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use mb_ir::{
+    Cell, ChannelSettings, Clip, ClipBoundary, Effect, Instrument, LoopType, MusicalTime, Note,
+    NodeType, Pattern, Sample, SampleData, SeqEntry, SeqTermination, Song, Track, VolumeCommand,
+};
+
+use crate::FormatError;
+
+/// Beats per clip; each track's notes are cut into clips of this length.
+const CLIP_BEATS: u32 = 16;
+
+/// Most columns one MIDI channel may use (16 channels x 15 stay within u8 channel indices).
+const MAX_COLUMNS: usize = 15;
+
+/// Most rows an imported song may span; longer files are refused rather
+/// than laid out on a grid that size.
+const MAX_ROWS: u64 = 1 << 17;
+
+/// Tracker ticks per beat at which the tempo value equals BPM.
+const TICKS_PER_BEAT: u32 = 24;
+
+/// MIDI note 60 (middle C) plays as C-4.
+const NOTE_OFFSET: u8 = 12;
+
+/// Tempo until the first Set Tempo meta event: 120 BPM.
+const DEFAULT_USPQ: u32 = 500_000;
+
+/// MIDI channel 10, reserved for drums in General MIDI.
+const DRUM_CHANNEL: u8 = 9;
+
+/// A note with start and end in MIDI ticks.
+#[derive(Clone, Copy, Debug)]
+struct NoteSpan {
+    start: u64,
+    end: u64,
+    note: u8,
+    velocity: u8,
+}
+
+/// Everything the importer uses from the file, merged across tracks.
+#[derive(Default)]
+struct MidiData {
+    /// Ticks per quarter note
+    division: u16,
+    title: Option<String>,
+    notes: [Vec<NoteSpan>; 16],
+    programs: [Option<u8>; 16],
+    pans: [Option<u8>; 16],
+    /// (tick, microseconds per quarter note)
+    tempos: Vec<(u64, u32)>,
+}
+
+/// Big-endian cursor over chunk data.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn done(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], FormatError> {
+        let end = self.pos.checked_add(n).filter(|&e| e <= self.data.len());
+        let end = end.ok_or(FormatError::UnexpectedEof)?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, FormatError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, FormatError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, FormatError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// Variable-length quantity (at most 4 bytes).
+    fn vlq(&mut self) -> Result<u32, FormatError> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let b = self.u8()?;
+            value = (value << 7) | (b & 0x7F) as u32;
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(FormatError::InvalidHeader)
+    }
+}
+
+/// Load a Standard MIDI File, quantizing notes to `rows_per_beat`.
+pub fn load_midi(data: &[u8], rows_per_beat: u8) -> Result<Song, FormatError> {
+    let midi = parse_smf(data)?;
+    let rpb = rows_per_beat.max(1);
+    let division = midi.division as u64;
+    let to_row = |tick: u64| (tick * rpb as u64 + division / 2) / division;
+
+    let mut song = Song::new("");
+    if let Some(title) = &midi.title {
+        let _ = song.title.try_push_str(truncate(title, song.title.capacity()));
+    }
+    let speed = (TICKS_PER_BEAT / rpb as u32).max(1) as u8;
+    song.rows_per_beat = rpb;
+    song.initial_speed = speed;
+    song.samples.push(sine_sample());
+
+    let mut tempo_clamped = false;
+    let mut tempo_at = |uspq: u32| {
+        let (tempo, clamped) = tracker_tempo(uspq, speed, rpb);
+        tempo_clamped |= clamped;
+        tempo
+    };
+    let initial_uspq = midi.tempos.iter().find(|&&(tick, _)| tick == 0).map_or(DEFAULT_USPQ, |&(_, t)| t);
+    song.initial_tempo = tempo_at(initial_uspq) as u32 * 100;
+    let tempo_changes: Vec<(u64, u8)> = midi.tempos.iter()
+        .filter(|&&(tick, _)| tick > 0)
+        .map(|&(tick, uspq)| (to_row(tick), tempo_at(uspq)))
+        .collect();
+    let last_row = midi.notes.iter().flatten().map(|n| to_row(n.end.max(n.start)))
+        .chain(tempo_changes.iter().map(|&(row, _)| row))
+        .max()
+        .unwrap_or(0);
+    if last_row >= MAX_ROWS {
+        return Err(FormatError::Unsupported(format!("MIDI file longer than {} rows", MAX_ROWS)));
+    }
+
+    let mut grids = Vec::new();
+    let mut dropped_low = 0usize;
+    let mut capped = false;
+    for (ch, notes) in midi.notes.iter().enumerate() {
+        if notes.is_empty() {
+            continue;
+        }
+        let mut spans = notes.clone();
+        spans.sort_by_key(|n| (n.start, n.note));
+        let grid = NoteGrid::build(&spans, &to_row, (grids.len() + 1) as u8, &mut dropped_low, &mut capped);
+        grids.push((ch as u8, grid));
+    }
+    if grids.is_empty() {
+        return Err(FormatError::Unsupported(String::from("MIDI file has no notes")));
+    }
+
+    let total_rows = grids.iter().map(|(_, g)| g.rows)
+        .chain(tempo_changes.iter().map(|&(row, _)| row + 1))
+        .max()
+        .unwrap_or(1);
+    for &(row, tempo) in &tempo_changes {
+        grids[0].1.cell_mut(row, 0).effect = Effect::SetTempo(tempo);
+    }
+
+    for (ch, grid) in &grids {
+        let base_channel = song.channels.len() as u8;
+        let pan = midi.pans[*ch as usize].map_or(0, |p| (p as i16 - 64).clamp(-64, 64) as i8);
+        for _ in 0..grid.columns {
+            song.channels.push(ChannelSettings { initial_pan: pan, ..ChannelSettings::default() });
+        }
+        song.instruments.push(channel_instrument(*ch, midi.programs[*ch as usize]));
+
+        let node = song.graph.add_node(NodeType::Machine {
+            machine_name: format!("Tracker {}", ch + 1),
+            is_tracker: true,
+        });
+        song.graph.connect(node, 0);
+        let mut track = Track::new(Some(node), base_channel, grid.columns);
+        grid.fill_clips(&mut track, total_rows, rpb);
+        song.tracks.push(track);
+    }
+
+    if dropped_low > 0 {
+        eprintln!("[MIDI] WARNING: {} notes below C-0 dropped", dropped_low);
+    }
+    if capped {
+        eprintln!("[MIDI] WARNING: polyphony above {} voices per channel cut short", MAX_COLUMNS);
+    }
+    if tempo_clamped {
+        eprintln!("[MIDI] WARNING: tempo out of the tracker's range, clamped");
+    }
+    Ok(song)
+}
+
+/// Parse the header and all track chunks.
+fn parse_smf(data: &[u8]) -> Result<MidiData, FormatError> {
+    let mut r = Reader::new(data);
+    if r.bytes(4).map_err(|_| FormatError::InvalidHeader)? != b"MThd" {
+        return Err(FormatError::InvalidHeader);
+    }
+    let header_len = r.u32()? as usize;
+    let mut header = Reader::new(r.bytes(header_len)?);
+    let format = header.u16()?;
+    let num_tracks = header.u16()?;
+    let division = header.u16()?;
+    if format > 2 {
+        return Err(FormatError::UnsupportedVersion);
+    }
+    if division & 0x8000 != 0 {
+        return Err(FormatError::Unsupported(String::from("SMPTE time division")));
+    }
+    if division == 0 {
+        return Err(FormatError::InvalidHeader);
+    }
+    if format == 2 {
+        eprintln!("[MIDI] WARNING: format 2 sequences are merged and played together");
+    }
+
+    let mut midi = MidiData { division, ..MidiData::default() };
+    let mut track = 0;
+    while track < num_tracks && !r.done() {
+        let id = r.bytes(4)?;
+        let len = r.u32()? as usize;
+        let body = r.bytes(len)?;
+        if id != b"MTrk" {
+            continue; // Unknown chunks are skipped, per the spec
+        }
+        parse_track(body, track == 0, &mut midi)?;
+        track += 1;
+    }
+    Ok(midi)
+}
+
+/// Parse one track chunk into `midi`.
+fn parse_track(data: &[u8], first: bool, midi: &mut MidiData) -> Result<(), FormatError> {
+    let mut r = Reader::new(data);
+    let mut tick = 0u64;
+    let mut running = 0u8;
+    // (channel, note, start tick, velocity) of notes waiting for their note-off
+    let mut held: Vec<(u8, u8, u64, u8)> = Vec::new();
+
+    while !r.done() {
+        tick += r.vlq()? as u64;
+        let first_byte = r.u8()?;
+        let (status, data1) = if first_byte & 0x80 != 0 {
+            (first_byte, None)
+        } else {
+            (running, Some(first_byte))
+        };
+        match status {
+            0xFF => {
+                let kind = r.u8()?;
+                let len = r.vlq()? as usize;
+                let body = r.bytes(len)?;
+                match kind {
+                    0x03 if first && midi.title.is_none() => {
+                        midi.title = Some(body.iter().map(|&b| b as char).collect());
+                    }
+                    0x51 if len == 3 => {
+                        let uspq = u32::from_be_bytes([0, body[0], body[1], body[2]]);
+                        midi.tempos.push((tick, uspq.max(1)));
+                    }
+                    0x2F => break,
+                    _ => {}
+                }
+            }
+            0xF0 | 0xF7 => {
+                let len = r.vlq()? as usize;
+                r.bytes(len)?;
+            }
+            0x80..=0xEF => {
+                running = status;
+                let d1 = match data1 {
+                    Some(d) => d,
+                    None => r.u8()?,
+                };
+                let ch = status & 0x0F;
+                match status & 0xF0 {
+                    0xC0 => {
+                        midi.programs[ch as usize].get_or_insert(d1);
+                    }
+                    0xD0 => {}
+                    kind => {
+                        let d2 = r.u8()?;
+                        match kind {
+                            0x90 if d2 > 0 => {
+                                release(&mut held, ch, d1, tick, &mut midi.notes);
+                                held.push((ch, d1, tick, d2));
+                            }
+                            0x80 | 0x90 => release(&mut held, ch, d1, tick, &mut midi.notes),
+                            0xB0 if d1 == 10 => {
+                                midi.pans[ch as usize].get_or_insert(d2);
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            _ => return Err(FormatError::InvalidHeader), // data byte with no running status
+        }
+    }
+
+    // Notes still held at the end of the track stop there
+    for (ch, note, start, velocity) in held {
+        midi.notes[ch as usize].push(NoteSpan { start, end: tick, note, velocity });
+    }
+    Ok(())
+}
+
+/// End the oldest held `note` on `ch` at `tick`, if any.
+fn release(held: &mut Vec<(u8, u8, u64, u8)>, ch: u8, note: u8, tick: u64, notes: &mut [Vec<NoteSpan>; 16]) {
+    if let Some(i) = held.iter().position(|&(c, n, _, _)| c == ch && n == note) {
+        let (_, _, start, velocity) = held.remove(i);
+        notes[ch as usize].push(NoteSpan { start, end: tick, note, velocity });
+    }
+}
+
+/// One channel's notes laid out on rows and columns.
+struct NoteGrid {
+    cells: Vec<Cell>,
+    columns: u8,
+    rows: u64,
+}
+
+impl NoteGrid {
+    /// Place `spans` (sorted by start) on the grid, giving each note the
+    /// first column that is free by its start row.
+    fn build(
+        spans: &[NoteSpan],
+        to_row: &impl Fn(u64) -> u64,
+        instrument: u8,
+        dropped_low: &mut usize,
+        capped: &mut bool,
+    ) -> Self {
+        // (start row, end row, column) per placed note; column free-from rows
+        let mut placed = Vec::with_capacity(spans.len());
+        let mut free_from: Vec<u64> = Vec::new();
+        for span in spans {
+            let start = to_row(span.start);
+            let end = to_row(span.end).max(start + 1);
+            let column = match free_from.iter().position(|&f| f <= start) {
+                Some(c) => c,
+                None if free_from.len() < MAX_COLUMNS => {
+                    free_from.push(0);
+                    free_from.len() - 1
+                }
+                None => {
+                    *capped = true;
+                    (0..free_from.len()).min_by_key(|&c| free_from[c]).unwrap_or(0)
+                }
+            };
+            free_from[column] = end;
+            placed.push((start, end, column, span));
+        }
+
+        let columns = free_from.len().max(1);
+        let rows = placed.iter().map(|&(_, end, _, _)| end + 1).max().unwrap_or(1);
+        let mut grid = Self { cells: alloc::vec![Cell::empty(); rows as usize * columns], columns: columns as u8, rows };
+        let mut pending_off = alloc::vec![None; columns];
+        for (start, end, column, span) in placed {
+            let Some(note) = span.note.checked_sub(NOTE_OFFSET).filter(|&n| n > 0) else {
+                *dropped_low += 1;
+                continue;
+            };
+            // A stolen column cancels the cut-short note's note-off
+            if let Some(off_row) = pending_off[column].take().filter(|&r| r > start) {
+                grid.cell_mut(off_row, column as u8).note = Note::None;
+            }
+            *grid.cell_mut(start, column as u8) = Cell {
+                note: Note::On(note),
+                instrument,
+                volume: VolumeCommand::Volume(((span.velocity as u16 * 64 + 63) / 127) as u8),
+                ..Cell::empty()
+            };
+            let off = grid.cell_mut(end, column as u8);
+            if off.note == Note::None {
+                off.note = Note::Off;
+                pending_off[column] = Some(end);
+            }
+        }
+        grid
+    }
+
+    fn cell_mut(&mut self, row: u64, column: u8) -> &mut Cell {
+        if row >= self.rows {
+            self.rows = row + 1;
+            self.cells.resize(self.rows as usize * self.columns as usize, Cell::empty());
+        }
+        &mut self.cells[row as usize * self.columns as usize + column as usize]
+    }
+
+    /// Cut the first `total_rows` rows into clips of `CLIP_BEATS` beats.
+    fn fill_clips(&self, track: &mut Track, total_rows: u64, rpb: u8) {
+        let clip_rows = (CLIP_BEATS * rpb as u32).min(u16::MAX as u32) as u64;
+        let clip_count = total_rows.div_ceil(clip_rows);
+        for i in 0..clip_count {
+            let first = i * clip_rows;
+            let rows = clip_rows.min(total_rows - first) as u16;
+            let mut pattern = Pattern::new(rows, self.columns);
+            for row in 0..rows {
+                for col in 0..self.columns {
+                    let idx = (first + row as u64) as usize * self.columns as usize + col as usize;
+                    if let Some(cell) = self.cells.get(idx) {
+                        *pattern.cell_mut(row, col) = *cell;
+                    }
+                }
+            }
+            track.clips.push(Clip::Pattern(pattern));
+            track.sequence.push(SeqEntry {
+                start: MusicalTime::from_beats(i * CLIP_BEATS as u64),
+                clip_idx: i as u16,
+                length: rows,
+                termination: SeqTermination::Natural,
+                boundary: ClipBoundary::Ring,
+            });
+        }
+    }
+}
+
+/// Tracker tempo giving `uspq` microseconds per beat at `speed` x `rpb`
+/// ticks per beat, and whether it had to be clamped into range.
+fn tracker_tempo(uspq: u32, speed: u8, rpb: u8) -> (u8, bool) {
+    // BPM = 60e6 / uspq; one tracker tick lasts 2.5 / tempo seconds
+    let exact = (2_500_000 * speed as u64 * rpb as u64 + uspq as u64 / 2) / uspq as u64;
+    let tempo = exact.clamp(32, 255);
+    (tempo as u8, tempo != exact)
+}
+
+/// Instrument for MIDI channel `ch`, named after its program if set.
+fn channel_instrument(ch: u8, program: Option<u8>) -> Instrument {
+    let name = match program {
+        _ if ch == DRUM_CHANNEL => format!("Drums (ch {})", ch + 1),
+        Some(p) => format!("Program {} (ch {})", p, ch + 1),
+        None => format!("MIDI ch {}", ch + 1),
+    };
+    let mut inst = Instrument::new(&name);
+    inst.set_single_sample(0);
+    inst
+}
+
+/// Longest prefix of `s` that fits in `max` bytes.
+fn truncate(s: &str, max: usize) -> &str {
+    let mut end = s.len().min(max);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// Looped single-cycle sine; 32 samples per cycle puts C-4 near middle C.
+fn sine_sample() -> Sample {
+    const LEN: usize = 256;
+    let data: Vec<i8> = (0..LEN)
+        .map(|i| ((i as f32 * core::f32::consts::TAU / 32.0).sin() * 127.0) as i8)
+        .collect();
+    let mut sample = Sample::new("sine");
+    sample.set_data(SampleData::Mono8(data));
+    sample.loop_end = LEN as u32;
+    sample.loop_type = LoopType::Forward;
+    sample
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// SMF with the given format, division and track bodies (end-of-track appended).
+    fn smf(division: u16, tracks: &[&[u8]]) -> Vec<u8> {
+        let mut data = b"MThd".to_vec();
+        data.extend_from_slice(&6u32.to_be_bytes());
+        data.extend_from_slice(&1u16.to_be_bytes());
+        data.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+        data.extend_from_slice(&division.to_be_bytes());
+        for body in tracks {
+            data.extend_from_slice(b"MTrk");
+            data.extend_from_slice(&(body.len() as u32 + 4).to_be_bytes());
+            data.extend_from_slice(body);
+            data.extend_from_slice(&[0, 0xFF, 0x2F, 0]);
+        }
+        data
+    }
+
+    fn note_at(song: &Song, track: usize, row: u16, col: u8) -> Note {
+        let track = &song.tracks[track];
+        let rows = track.clips[0].pattern().unwrap().rows;
+        let clip = track.clips[(row / rows) as usize].pattern().unwrap();
+        clip.cell(row % rows, col).note
+    }
+
+    #[test]
+    fn notes_quantize_to_nearest_row() {
+        // division 96, rpb 4: 24 ticks per row
+        let body = [
+            0, 0x90, 60, 100, // row 0
+            50, 0x80, 60, 0, // tick 50 -> row 2
+            0, 0x90, 62, 127, // row 2
+            46, 0x80, 62, 0, // tick 96 -> row 4
+        ];
+        let song = load_midi(&smf(96, &[&body]), 4).unwrap();
+        assert_eq!(song.tracks.len(), 1);
+        assert_eq!(note_at(&song, 0, 0, 0), Note::On(48));
+        assert_eq!(note_at(&song, 0, 2, 0), Note::On(50));
+        assert_eq!(note_at(&song, 0, 4, 0), Note::Off);
+        let cell = song.tracks[0].clips[0].pattern().unwrap().cell(2, 0);
+        assert_eq!(cell.volume, VolumeCommand::Volume(64));
+        assert_eq!(cell.instrument, 1);
+    }
+
+    #[test]
+    fn channels_become_tracks_with_own_nodes() {
+        let body = [0, 0x90, 60, 100, 0, 0x99, 36, 100, 96, 0x80, 60, 0, 0, 0x89, 36, 0];
+        let song = load_midi(&smf(96, &[&body]), 4).unwrap();
+        assert_eq!(song.tracks.len(), 2);
+        assert_ne!(song.tracks[0].machine_node, song.tracks[1].machine_node);
+        assert_eq!(song.tracks[1].base_channel, 1);
+        assert_eq!(song.channels.len(), 2);
+        assert_eq!(song.instruments[1].name.as_str(), "Drums (ch 10)");
+        assert_eq!(song.tracks[1].clips[0].pattern().unwrap().cell(0, 0).instrument, 2);
+    }
+
+    #[test]
+    fn chords_spread_over_columns() {
+        // Running status for the chord and for the note-offs (velocity 0)
+        let body = [0, 0x90, 60, 100, 0, 64, 100, 0, 67, 100, 96, 60, 0, 0, 64, 0, 0, 67, 0];
+        let song = load_midi(&smf(96, &[&body]), 4).unwrap();
+        assert_eq!(song.tracks[0].num_channels, 3);
+        for (col, note) in [48, 52, 55].into_iter().enumerate() {
+            assert_eq!(note_at(&song, 0, 0, col as u8), Note::On(note));
+            assert_eq!(note_at(&song, 0, 4, col as u8), Note::Off);
+        }
+    }
+
+    #[test]
+    fn reused_column_keeps_following_note() {
+        // Second note starts where the first ends: no note-off in between
+        let body = [0, 0x90, 60, 100, 96, 0x80, 60, 0, 0, 0x90, 64, 100, 96, 0x80, 64, 0];
+        let song = load_midi(&smf(96, &[&body]), 4).unwrap();
+        assert_eq!(song.tracks[0].num_channels, 1);
+        assert_eq!(note_at(&song, 0, 4, 0), Note::On(52));
+        assert_eq!(note_at(&song, 0, 8, 0), Note::Off);
+    }
+
+    #[test]
+    fn tempo_events_set_initial_tempo_and_effects() {
+        let conductor = [
+            0, 0xFF, 0x03, 4, b'S', b'o', b'n', b'g',
+            0, 0xFF, 0x51, 3, 0x09, 0x27, 0xC0, // 600000 us = 100 BPM
+            0x81, 0x40, 0xFF, 0x51, 3, 0x06, 0x1A, 0x80, // tick 192: 400000 us = 150 BPM
+        ];
+        let notes = [0, 0x90, 60, 100, 0x83, 0x00, 0x80, 60, 0];
+        let song = load_midi(&smf(96, &[&conductor, &notes]), 4).unwrap();
+        assert_eq!(song.title.as_str(), "Song");
+        assert_eq!(song.initial_speed, 6);
+        assert_eq!(song.initial_tempo, 10000);
+        let pattern = song.tracks[0].clips[0].pattern().unwrap();
+        assert_eq!(pattern.cell(8, 0).effect, Effect::SetTempo(150));
+    }
+
+    #[test]
+    fn tempo_compensates_for_uneven_speed() {
+        // rpb 16: speed 1, 16 ticks per beat -> tempo 2/3 of the BPM
+        assert_eq!(tracker_tempo(500_000, 1, 16), (80, false));
+        assert_eq!(tracker_tempo(500_000, 6, 4), (120, false));
+        assert_eq!(tracker_tempo(100_000, 6, 4), (255, true));
+    }
+
+    #[test]
+    fn long_songs_split_into_clips() {
+        // Note-off at tick 96 * 40 = beat 40
+        let body = [0, 0x90, 60, 100, 0x9E, 0x00, 0x80, 60, 0];
+        let song = load_midi(&smf(96, &[&body]), 4).unwrap();
+        let track = &song.tracks[0];
+        assert_eq!(track.clips.len(), 3);
+        assert_eq!(track.sequence[2].start, MusicalTime::from_beats(32));
+        assert_eq!(note_at(&song, 0, 160, 0), Note::Off);
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        assert!(matches!(load_midi(b"RIFF\0\0\0\0", 4), Err(FormatError::InvalidHeader)));
+        let mut smpte = smf(96, &[&[0, 0x90, 60, 100]]);
+        smpte[12] = 0xE7;
+        assert!(matches!(load_midi(&smpte, 4), Err(FormatError::Unsupported(_))));
+        assert!(matches!(load_midi(&smf(96, &[&[]]), 4), Err(FormatError::Unsupported(_))));
+        let truncated = smf(96, &[&[0, 0x90, 60, 100]]);
+        assert!(matches!(load_midi(&truncated[..truncated.len() - 6], 4), Err(FormatError::UnexpectedEof)));
+        // A note held for 2^28 quarter notes would need billions of rows
+        let endless = [0, 0x90, 60, 100, 0xFF, 0xFF, 0xFF, 0x7F, 0x80, 60, 0];
+        assert!(matches!(load_midi(&smf(1, &[&endless]), 4), Err(FormatError::Unsupported(_))));
+    }
+}
@@ -0,0 +1,524 @@
+//! ProTracker MOD writer.
+//!
+//! Converts a song that fits the MOD model (one 4-channel pattern track,
+//! up to 31 instruments) into an M.K. module. Anything MOD can't express
+//! is approximated or dropped, and each kind of loss is reported.
+
+use alloc::vec::Vec;
+use mb_ir::{Bpm, Cell, Effect, LoopType, Note, Pattern, Sample, SampleData, Song, Track};
+
+use crate::mod_format::{FIRST_NOTE, PERIODS};
+use crate::FormatError;
+
+const CHANNELS: u8 = 4;
+const ROWS: u16 = 64;
+const MAX_SLOTS: usize = 31;
+/// Longest sample a MOD header can describe (65535 words).
+const MAX_SAMPLE_BYTES: usize = 0xFFFF * 2;
+
+/// A written MOD file and the lossy conversions made to produce it.
+#[derive(Debug, Default)]
+pub struct ModExport {
+    /// The module bytes
+    pub data: Vec<u8>,
+    /// One line per kind of loss (empty if the export is exact)
+    pub warnings: Vec<String>,
+}
+
+impl ModExport {
+    fn warn(&mut self, msg: String) {
+        if !self.warnings.contains(&msg) {
+            self.warnings.push(msg);
+        }
+    }
+}
+
+/// Write a song as a ProTracker MOD.
+///
+/// Fails with `FormatError::Unsupported` when the song has no single
+/// pattern track of at most 4 channels or needs more than 31 instruments.
+pub fn write_mod(song: &Song) -> Result<ModExport, FormatError> {
+    let mut out = ModExport::default();
+    let track = pattern_track(song)?;
+    let slots = instrument_slots(song, &mut out)?;
+
+    if song.rows_per_beat != 4 {
+        out.warn(format!("rows per beat {} not supported, MOD always uses 4", song.rows_per_beat));
+    }
+    if track.sequence.len() > 128 {
+        out.warn(format!("order list truncated from {} to 128 entries", track.sequence.len()));
+    }
+    let order: Vec<u8> = track.sequence.iter().take(128).map(|e| e.clip_idx as u8).collect();
+    if order.is_empty() {
+        return Err(FormatError::Unsupported("song has no sequence entries".into()));
+    }
+
+    let mut patterns: Vec<Vec<u8>> = track.clips.iter()
+        .map(|clip| clip.pattern().map_or_else(|| alloc::vec![0; 1024], |p| encode_pattern(p, slots.len(), &mut out)))
+        .collect();
+    set_initial_timing(song, &mut patterns[order[0] as usize], &mut out);
+
+    // Header
+    let mut data = Vec::with_capacity(1084);
+    put_string(&mut data, &song.title, 20);
+    let sample_data: Vec<Vec<i8>> = (0..MAX_SLOTS)
+        .map(|slot| {
+            let sample = slots.get(slot).copied().flatten().and_then(|i| song.samples.get(i));
+            let pcm = sample.map_or_else(Vec::new, |s| sample_pcm(s, slot, &mut out));
+            write_sample_header(&mut data, sample, &pcm, slot, &mut out);
+            pcm
+        })
+        .collect();
+
+    data.push(order.len() as u8);
+    data.push(127); // restart byte, ignored by ProTracker
+    let mut order_table = [0u8; 128];
+    order_table[..order.len()].copy_from_slice(&order);
+    data.extend_from_slice(&order_table);
+    let max_pattern = order.iter().copied().max().unwrap_or(0) as usize;
+    data.extend_from_slice(if max_pattern >= 64 { b"M!K!" } else { b"M.K." });
+
+    // Patterns up to the highest one referenced, then sample data
+    patterns.resize(max_pattern + 1, alloc::vec![0; 1024]);
+    for pattern in &patterns[..=max_pattern] {
+        data.extend_from_slice(pattern);
+    }
+    for pcm in &sample_data {
+        data.extend(pcm.iter().map(|&v| v as u8));
+    }
+
+    out.data = data;
+    Ok(out)
+}
+
+/// The single track holding pattern data.
+fn pattern_track(song: &Song) -> Result<&Track, FormatError> {
+    let mut tracks = song.tracks.iter().filter(|t| !t.clips.is_empty());
+    let track = tracks.next()
+        .ok_or_else(|| FormatError::Unsupported("song has no pattern data".into()))?;
+    if tracks.next().is_some() {
+        return Err(FormatError::Unsupported("MOD supports a single pattern track".into()));
+    }
+    if track.num_channels > CHANNELS {
+        return Err(FormatError::Unsupported(format!(
+            "track has {} channels, MOD supports {}", track.num_channels, CHANNELS
+        )));
+    }
+    if track.clips.len() > 128 {
+        return Err(FormatError::Unsupported(format!("{} patterns, MOD supports 128", track.clips.len())));
+    }
+    Ok(track)
+}
+
+/// Sample index for each MOD sample slot, one slot per cell instrument number.
+///
+/// Songs without instruments address samples directly, like the tracker does.
+fn instrument_slots(song: &Song, out: &mut ModExport) -> Result<Vec<Option<usize>>, FormatError> {
+    if song.instruments.is_empty() {
+        if song.samples.len() > MAX_SLOTS {
+            return Err(FormatError::Unsupported(format!("{} samples, MOD supports 31", song.samples.len())));
+        }
+        return Ok((0..song.samples.len()).map(Some).collect());
+    }
+    if song.instruments.len() > MAX_SLOTS {
+        return Err(FormatError::Unsupported(format!("{} instruments, MOD supports 31", song.instruments.len())));
+    }
+
+    let slots = song.instruments.iter().enumerate().map(|(i, inst)| {
+        let valid = |&s: &u8| (s as usize) < song.samples.len();
+        let primary = Some(inst.sample_map[48]).filter(valid)
+            .or_else(|| inst.sample_map.iter().copied().find(valid));
+        if inst.sample_map.iter().filter(|s| valid(s)).any(|&s| Some(s) != primary) {
+            out.warn(format!("instrument {} maps several samples, exported with its C-4 sample only", i + 1));
+        }
+        let has_envelope = [&inst.volume_envelope, &inst.panning_envelope, &inst.pitch_envelope]
+            .iter()
+            .any(|env| env.as_ref().is_some_and(|e| e.enabled));
+        if has_envelope {
+            out.warn(format!("instrument {} envelopes dropped", i + 1));
+        }
+        primary.map(|s| s as usize)
+    });
+    Ok(slots.collect())
+}
+
+/// Convert a sample to 8-bit mono of even length, as MOD requires.
+fn sample_pcm(sample: &Sample, slot: usize, out: &mut ModExport) -> Vec<i8> {
+    let n = slot + 1;
+    let mut pcm: Vec<i8> = match &*sample.data {
+        SampleData::Mono8(d) => d.clone(),
+        SampleData::Mono16(d) => {
+            out.warn(format!("sample {} reduced to 8-bit", n));
+            d.iter().map(|&v| (v >> 8) as i8).collect()
+        }
+        SampleData::Stereo8(l, r) => {
+            out.warn(format!("sample {} mixed down to mono", n));
+            l.iter().zip(r).map(|(&a, &b)| ((a as i16 + b as i16) / 2) as i8).collect()
+        }
+        SampleData::Stereo16(l, r) => {
+            out.warn(format!("sample {} mixed down to mono and reduced to 8-bit", n));
+            l.iter().zip(r).map(|(&a, &b)| ((a as i32 + b as i32) >> 9) as i8).collect()
+        }
+    };
+    if pcm.len() > MAX_SAMPLE_BYTES {
+        out.warn(format!("sample {} truncated to {} frames", n, MAX_SAMPLE_BYTES));
+        pcm.truncate(MAX_SAMPLE_BYTES);
+    }
+    if pcm.len() % 2 == 1 {
+        pcm.push(0);
+    }
+    pcm
+}
+
+/// Append a 30-byte sample header. Empty slots get a zeroed header.
+fn write_sample_header(data: &mut Vec<u8>, sample: Option<&Sample>, pcm: &[i8], slot: usize, out: &mut ModExport) {
+    let Some(sample) = sample else {
+        // Loop length of one word means "no loop"
+        data.extend_from_slice(&[0; 29]);
+        data.push(1);
+        return;
+    };
+    let n = slot + 1;
+    put_string(data, &sample.name, 22);
+    data.extend_from_slice(&((pcm.len() / 2) as u16).to_be_bytes());
+    data.push(finetune(sample, n, out) as u8 & 0x0F);
+    data.push(sample.default_volume.min(64));
+
+    if sample.vibrato.is_some() {
+        out.warn(format!("sample {} auto-vibrato dropped", n));
+    }
+    let looped = sample.has_loop() && match sample.loop_type {
+        LoopType::Forward => true,
+        LoopType::PingPong => {
+            out.warn(format!("sample {} ping-pong loop exported as forward loop", n));
+            true
+        }
+        LoopType::Sustain => {
+            out.warn(format!("sample {} sustain loop dropped", n));
+            false
+        }
+        LoopType::None => false,
+    };
+    let start = (sample.loop_start as usize).min(pcm.len()) / 2;
+    let end = (sample.loop_end as usize).min(pcm.len()) / 2;
+    let (start, len) = if looped && end > start { (start, end - start) } else { (0, 1) };
+    data.extend_from_slice(&(start as u16).to_be_bytes());
+    data.extend_from_slice(&(len as u16).to_be_bytes());
+}
+
+/// MOD finetune (-8..=7, eighth semitones) closest to the sample's C-4 speed.
+fn finetune(sample: &Sample, n: usize, out: &mut ModExport) -> i8 {
+    let steps = (96.0 * (sample.c4_speed.max(1) as f32 / 8363.0).log2()).round();
+    if !(-8.0..=7.0).contains(&steps) {
+        out.warn(format!("sample {} C-4 speed {} Hz out of finetune range, pitch will differ", n, sample.c4_speed));
+    }
+    steps.clamp(-8.0, 7.0) as i8
+}
+
+/// Encode a clip as a 64-row, 4-channel MOD pattern.
+fn encode_pattern(pattern: &Pattern, num_slots: usize, out: &mut ModExport) -> Vec<u8> {
+    if pattern.ticks_per_row != 0 || pattern.rows_per_beat.is_some() {
+        out.warn("per-pattern speed and rows-per-beat overrides dropped".into());
+    }
+    if pattern.rows > ROWS {
+        out.warn(format!("patterns longer than {} rows truncated", ROWS));
+    }
+    let rows = pattern.rows.min(ROWS);
+    let mut data = alloc::vec![0u8; 1024];
+    for row in 0..rows {
+        for ch in 0..pattern.channels.min(CHANNELS) {
+            let mut cell = *pattern.cell(row, ch);
+            // A short pattern ends early via a break on its last row
+            if row + 1 == rows && rows < ROWS && !has_break(pattern, row) {
+                if cell.effect == Effect::None && ch == 0 {
+                    cell.effect = Effect::PatternBreak(0);
+                } else if ch == 0 {
+                    out.warn("short pattern padded to 64 rows, no free effect slot for a break".into());
+                }
+            }
+            let offset = (row as usize * CHANNELS as usize + ch as usize) * 4;
+            data[offset..offset + 4].copy_from_slice(&encode_cell(&cell, num_slots, out));
+        }
+    }
+    data
+}
+
+fn has_break(pattern: &Pattern, row: u16) -> bool {
+    (0..pattern.channels).any(|ch| {
+        matches!(pattern.cell(row, ch).effect, Effect::PatternBreak(_) | Effect::PositionJump(_))
+    })
+}
+
+/// Pack a cell into MOD's 4 bytes: sample/period, sample/command, parameter.
+fn encode_cell(cell: &Cell, num_slots: usize, out: &mut ModExport) -> [u8; 4] {
+    let period = match cell.note {
+        Note::None => 0,
+        Note::On(n) => note_period(n, out),
+        Note::Off | Note::Fade => {
+            out.warn("note-off and note-fade dropped".into());
+            0
+        }
+    };
+    let mut sample = cell.instrument;
+    if sample as usize > num_slots {
+        out.warn("cells referencing missing instruments cleared".into());
+        sample = 0;
+    }
+
+    let vol_effect = cell.volume.as_effect();
+    let effect = match (cell.effect, vol_effect) {
+        (Effect::None, Some(v)) => v,
+        (e, Some(_)) => {
+            out.warn("volume column dropped where the effect column is in use".into());
+            e
+        }
+        (e, None) => e,
+    };
+    let effect = match (effect, cell.effect2) {
+        (Effect::None, e) | (e, Effect::None) => e,
+        (e, _) => {
+            out.warn("second effect column dropped where the effect column is in use".into());
+            e
+        }
+    };
+    let (cmd, param) = encode_effect(effect).unwrap_or_else(|| {
+        out.warn(format!("{} effects have no MOD equivalent and were dropped", effect.name()));
+        (0, 0)
+    });
+
+    [
+        (sample & 0xF0) | (period >> 8) as u8,
+        period as u8,
+        (sample << 4) | cmd,
+        param,
+    ]
+}
+
+/// Amiga period for a note, transposed by octaves into MOD's 3-octave range.
+fn note_period(note: u8, out: &mut ModExport) -> u16 {
+    let range = FIRST_NOTE..FIRST_NOTE + PERIODS.len() as u8;
+    let mut n = note;
+    while n < range.start {
+        n += 12;
+    }
+    while n >= range.end {
+        n -= 12;
+    }
+    if n != note {
+        out.warn("notes outside the MOD range transposed by octaves".into());
+    }
+    PERIODS[(n - FIRST_NOTE) as usize]
+}
+
+/// MOD command and parameter for an effect, or `None` if it has no equivalent.
+///
+/// Inverse of `effect_parser::parse_effect`.
+pub(crate) fn encode_effect(effect: Effect) -> Option<(u8, u8)> {
+    let nibbles = |hi: u8, lo: u8| (hi.min(15) << 4) | lo.min(15);
+    let ext = |cmd: u8, v: u8| (0xE, (cmd << 4) | v.min(15));
+    Some(match effect {
+        Effect::None => (0, 0),
+        Effect::Arpeggio { x, y } => (0x0, nibbles(x, y)),
+        Effect::PortaUp(v) => (0x1, v),
+        Effect::PortaDown(v) => (0x2, v),
+        Effect::TonePorta(v) => (0x3, v),
+        Effect::Vibrato { speed, depth } => (0x4, nibbles(speed, depth)),
+        Effect::TonePortaVolSlide(s) => (0x5, slide_param(s)),
+        Effect::VibratoVolSlide(s) => (0x6, slide_param(s)),
+        Effect::Tremolo { speed, depth } => (0x7, nibbles(speed, depth)),
+        Effect::SetPan(v) => (0x8, v),
+        Effect::SampleOffset(v) => (0x9, v),
+        Effect::VolumeSlide(s) => (0xA, slide_param(s)),
+        Effect::PositionJump(v) => (0xB, v),
+        Effect::SetVolume(v) => (0xC, v.min(64)),
+        Effect::PatternBreak(r) => (0xD, nibbles(r.min(63) / 10, r.min(63) % 10)),
+        Effect::FinePortaUp(v) => ext(0x1, v),
+        Effect::FinePortaDown(v) => ext(0x2, v),
+        Effect::SetGlissando(v) => ext(0x3, v),
+        Effect::SetVibratoWaveform(v) => ext(0x4, v),
+        Effect::SetFinetune(v) => ext(0x5, v as u8 & 0x0F),
+        Effect::PatternLoop(v) => ext(0x6, v),
+        Effect::SetTremoloWaveform(v) => ext(0x7, v),
+        Effect::SetPanPosition(v) => ext(0x8, v),
+        Effect::RetriggerNote(v) => ext(0x9, v),
+        Effect::FineVolumeSlideUp(v) => ext(0xA, v),
+        Effect::FineVolumeSlideDown(v) => ext(0xB, v),
+        Effect::NoteCut(v) => ext(0xC, v),
+        Effect::NoteDelay(v) => ext(0xD, v),
+        Effect::PatternDelay(v) => ext(0xE, v),
+        Effect::FunkRepeat(v) => ext(0xF, v),
+        Effect::SetSpeed(v) if (1..32).contains(&v) => (0xF, v),
+        Effect::SetTempo(v) if v >= 32 => (0xF, v),
+        _ => return None,
+    })
+}
+
+/// Inverse of `effect_parser::param_to_slide`: up in the high nibble, down in the low.
+fn slide_param(s: i8) -> u8 {
+    if s > 0 {
+        (s as u8).min(15) << 4
+    } else {
+        s.unsigned_abs().min(15)
+    }
+}
+
+/// MOD starts at speed 6 / 125 BPM; put the song's own values on the first row.
+fn set_initial_timing(song: &Song, first: &mut [u8], out: &mut ModExport) {
+    // MOD tempos are whole BPM
+    let tempo = (song.initial_tempo + 50) / 100;
+    if !song.initial_tempo.is_multiple_of(100) {
+        out.warn(format!("initial tempo {} BPM rounded to {}", Bpm(song.initial_tempo), tempo));
+    }
+    let wanted = [(song.initial_speed as u32, 6), (tempo, 125)];
+    for (value, default) in wanted {
+        if value == default {
+            continue;
+        }
+        let param_ok = if default == 6 { (1..32).contains(&value) } else { (32..=255).contains(&value) };
+        let free = (0..CHANNELS as usize).find(|ch| first[ch * 4 + 2] & 0x0F == 0 && first[ch * 4 + 3] == 0);
+        match free.filter(|_| param_ok) {
+            Some(ch) => {
+                first[ch * 4 + 2] |= 0x0F;
+                first[ch * 4 + 3] = value as u8;
+            }
+            None => out.warn(format!("initial speed/tempo {} can't be set on the first row", value)),
+        }
+    }
+}
+
+/// Append `s` as a zero-padded, possibly truncated ASCII field.
+fn put_string(data: &mut Vec<u8>, s: &str, len: usize) {
+    let mut field: Vec<u8> = s.bytes().map(|b| if b.is_ascii() { b } else { b'?' }).take(len).collect();
+    field.resize(len, 0);
+    data.extend_from_slice(&field);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load_mod;
+    use mb_ir::{Instrument, VolumeCommand};
+
+    /// 4-channel song with one looped sample and two patterns.
+    fn small_song() -> Song {
+        let mut song = Song::with_channels("export", 4);
+        song.rows_per_beat = 4;
+        let mut sample = Sample::new("square");
+        sample.set_data(SampleData::Mono8((0..64).map(|i| if i < 32 { 64 } else { -64 }).collect()));
+        sample.loop_start = 16;
+        sample.loop_end = 64;
+        sample.loop_type = LoopType::Forward;
+        sample.default_volume = 48;
+        song.samples.push(sample);
+        let mut inst = Instrument::new("square");
+        inst.set_single_sample(0);
+        song.instruments.push(inst);
+
+        let mut a = Pattern::new(64, 4);
+        *a.cell_mut(0, 0) = Cell { note: Note::On(48), instrument: 1, ..Cell::empty() };
+        a.cell_mut(1, 1).effect = Effect::VolumeSlide(-4);
+        a.cell_mut(2, 2).effect = Effect::NoteCut(3);
+        a.cell_mut(3, 3).effect = Effect::PatternBreak(16);
+        let mut b = Pattern::new(64, 4);
+        b.cell_mut(5, 0).effect = Effect::Vibrato { speed: 4, depth: 7 };
+        mb_ir::build_tracks(&mut song, &[a, b], &[mb_ir::OrderEntry::Pattern(1), mb_ir::OrderEntry::Pattern(0)]);
+        song
+    }
+
+    #[test]
+    fn round_trips_through_load_mod() {
+        let song = small_song();
+        let export = write_mod(&song).unwrap();
+        assert!(export.warnings.is_empty(), "{:?}", export.warnings);
+
+        let loaded = load_mod(&export.data).unwrap();
+        assert_eq!(loaded.title.as_str(), "export");
+        let track = &loaded.tracks[0];
+        assert_eq!(track.sequence.iter().map(|e| e.clip_idx).collect::<Vec<_>>(), [1, 0]);
+        let orig = song.tracks[0].clips[0].pattern().unwrap();
+        let back = track.clips[0].pattern().unwrap();
+        assert_eq!(orig.data, back.data);
+        assert_eq!(track.clips[1].pattern().unwrap().cell(5, 0).effect, Effect::Vibrato { speed: 4, depth: 7 });
+
+        let sample = &loaded.samples[0];
+        assert_eq!(sample.len(), 64);
+        assert_eq!(sample.default_volume, 48);
+        assert_eq!((sample.loop_start, sample.loop_end), (16, 64));
+        assert_eq!(sample.data.get_mono(0), 64 * 256);
+    }
+
+    #[test]
+    fn reports_lossy_conversions() {
+        let mut song = small_song();
+        song.initial_speed = 3;
+        let pat = song.tracks[0].clips[0].pattern_mut().unwrap();
+        pat.cell_mut(8, 0).effect = Effect::SetGlobalVolume(32);
+        pat.cell_mut(9, 0).note = Note::On(90);
+        pat.cell_mut(10, 0).volume = VolumeCommand::Volume(20);
+        pat.cell_mut(11, 0).effect2 = Effect::PortaUp(3);
+        pat.cell_mut(12, 0).effect = Effect::VolumeSlide(2);
+        pat.cell_mut(12, 0).effect2 = Effect::PortaUp(1);
+        let mut sample = Sample::new("hifi");
+        sample.set_data(SampleData::Stereo16(alloc::vec![1000; 9], alloc::vec![3000; 9]));
+        song.samples[0] = sample;
+
+        let export = write_mod(&song).unwrap();
+        let has = |s: &str| export.warnings.iter().any(|w| w.contains(s));
+        assert!(has("SetGlobalVolume"));
+        assert!(has("transposed"));
+        assert!(has("mixed down"));
+        assert!(has("second effect column"));
+
+        let loaded = load_mod(&export.data).unwrap();
+        assert_eq!(loaded.samples[0].len(), 10); // padded to even length
+        let first = loaded.tracks[0].clips[1].pattern().unwrap();
+        assert_eq!(first.cell(0, 0).effect, Effect::SetSpeed(3));
+        let pat = loaded.tracks[0].clips[0].pattern().unwrap();
+        assert_eq!(pat.cell(9, 0).note, Note::On(66));
+        assert_eq!(pat.cell(10, 0).effect, Effect::SetVolume(20));
+        assert_eq!(pat.cell(11, 0).effect, Effect::PortaUp(3));
+    }
+
+    #[test]
+    fn short_patterns_end_with_a_break() {
+        let mut song = small_song();
+        song.tracks[0].clips[1] = mb_ir::Clip::Pattern(Pattern::new(16, 4));
+        let export = write_mod(&song).unwrap();
+        let loaded = load_mod(&export.data).unwrap();
+        let pat = loaded.tracks[0].clips[1].pattern().unwrap();
+        assert_eq!(pat.cell(15, 0).effect, Effect::PatternBreak(0));
+    }
+
+    #[test]
+    fn rejects_songs_that_do_not_fit() {
+        let mut song = Song::with_channels("wide", 8);
+        mb_ir::build_tracks(&mut song, &[Pattern::new(64, 8)], &[mb_ir::OrderEntry::Pattern(0)]);
+        assert!(matches!(write_mod(&song), Err(FormatError::Unsupported(_))));
+
+        let mut song = small_song();
+        song.instruments = (0..32).map(|i| Instrument::new(&format!("{}", i))).collect();
+        assert!(matches!(write_mod(&song), Err(FormatError::Unsupported(_))));
+    }
+
+    #[test]
+    fn effects_survive_parse_round_trip() {
+        use crate::effect_parser::parse_effect;
+        for effect in [
+            Effect::Arpeggio { x: 3, y: 7 },
+            Effect::TonePortaVolSlide(5),
+            Effect::VolumeSlide(-9),
+            Effect::PatternBreak(42),
+            Effect::SetFinetune(-3),
+            Effect::PatternDelay(4),
+            Effect::SetGlissando(1),
+            Effect::FunkRepeat(7),
+            Effect::SetSpeed(31),
+            Effect::SetTempo(32),
+        ] {
+            let (cmd, param) = encode_effect(effect).unwrap();
+            assert_eq!(parse_effect(cmd, param), effect);
+        }
+        assert_eq!(encode_effect(Effect::SetSpeed(40)), None);
+        assert_eq!(encode_effect(Effect::Tremor { on: 1, off: 1 }), None);
+    }
+}
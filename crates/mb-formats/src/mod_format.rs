@@ -2,47 +2,100 @@
 
 use alloc::vec::Vec;
 use mb_ir::{
-    build_tracks, Cell, Instrument, Note, OrderEntry, Pattern, Sample, SampleData, Song,
+    build_tracks, Cell, CompatibilityMode, Instrument, Note, OrderEntry, Pattern, Sample, SampleData, Song,
     VolumeCommand,
 };
 
 use crate::FormatError;
 
+/// Header layout of a MOD variant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ModLayout {
+    /// Sample slots in the header (15 for Ultimate Soundtracker, else 31).
+    num_samples: usize,
+    /// Pattern columns.
+    num_channels: u8,
+}
+
+impl ModLayout {
+    /// Offset of the song length byte (order list follows after the restart byte).
+    fn song_length_offset(&self) -> usize {
+        20 + self.num_samples * 30
+    }
+
+    /// Offset of the first pattern (31-sample files carry a 4-byte signature).
+    fn patterns_offset(&self) -> usize {
+        let sig = if self.num_samples == 31 { 4 } else { 0 };
+        self.song_length_offset() + 2 + 128 + sig
+    }
+}
+
+/// Identify the variant from the signature at offset 1080.
+///
+/// Files without a recognised signature are treated as 15-sample
+/// Soundtracker modules.
+fn detect_layout(data: &[u8]) -> ModLayout {
+    let channels = data.get(1080..1084).and_then(signature_channels);
+    match channels {
+        Some(num_channels) => ModLayout { num_samples: 31, num_channels },
+        None => ModLayout { num_samples: 15, num_channels: 4 },
+    }
+}
+
+/// Channel count for a 31-sample signature, or `None` if unrecognised.
+fn signature_channels(sig: &[u8]) -> Option<u8> {
+    let digit = |b: u8| b.is_ascii_digit().then(|| b - b'0');
+    match sig {
+        b"M.K." | b"M!K!" | b"M&K!" | b"N.T." | b"FLT4" => Some(4),
+        b"FLT8" | b"OCTA" | b"CD81" => Some(8),
+        // xCHN: 1-9 channels (2CHN, 6CHN, 8CHN, ...)
+        [n, b'C', b'H', b'N'] => digit(*n).filter(|&c| c > 0),
+        // xxCH: 10-32 channels
+        [hi, lo, b'C', b'H'] => {
+            let c = digit(*hi)? * 10 + digit(*lo)?;
+            (1..=32).contains(&c).then_some(c)
+        }
+        // TDZx (TakeTracker): 1-3 channels
+        [b'T', b'D', b'Z', n] => digit(*n).filter(|&c| c > 0),
+        _ => None,
+    }
+}
+
+/// Sanity-check a signature-less file before committing to the 15-sample
+/// layout, so arbitrary data isn't loaded as noise.
+fn plausible_soundtracker(data: &[u8], layout: &ModLayout) -> bool {
+    let length_offset = layout.song_length_offset();
+    let song_length = data[length_offset];
+    let orders = &data[length_offset + 2..length_offset + 2 + 128];
+    let volumes_ok = (0..layout.num_samples).all(|i| data[20 + i * 30 + 25] <= 64);
+    (1..=128).contains(&song_length) && orders.iter().all(|&p| p < 128) && volumes_ok
+}
+
 /// Load a MOD file from bytes.
+///
+/// Handles 31-sample ProTracker-style modules (M.K., xCHN, xxCH, ...) and
+/// the original 15-sample Soundtracker format without a signature.
 pub fn load_mod(data: &[u8]) -> Result<Song, FormatError> {
-    if data.len() < 1084 {
+    let layout = detect_layout(data);
+    if data.len() < layout.patterns_offset() {
         return Err(FormatError::UnexpectedEof);
     }
-
-    // Detect format by checking signature at offset 1080
-    let sig = &data[1080..1084];
-    let num_channels = match sig {
-        b"M.K." | b"M!K!" | b"FLT4" => 4,
-        b"6CHN" => 6,
-        b"8CHN" | b"OCTA" => 8,
-        _ => {
-            // Could be 15-sample format (no signature) or unknown
-            // For now, assume 4 channels if no valid signature
-            4
-        }
-    };
+    if layout.num_samples == 15 && !plausible_soundtracker(data, &layout) {
+        return Err(FormatError::InvalidHeader);
+    }
+    let num_channels = layout.num_channels;
 
     // Parse header
     let title = parse_string(&data[0..20]);
     let mut song = Song::with_channels(&title, num_channels);
     song.rows_per_beat = 4; // MOD standard: 4 rows per beat
+    song.compatibility = CompatibilityMode::ProTracker;
 
-    // Parse sample headers (31 samples, starting at offset 20)
-    for i in 0..31 {
+    // Parse sample headers (starting at offset 20)
+    for i in 0..layout.num_samples {
         let header_offset = 20 + i * 30;
-        let sample = parse_sample_header(&data[header_offset..header_offset + 30])?;
-
-        // Track where sample data starts
-        let sample_len = sample.len();
-        if sample_len > 0 {
-            // We'll load sample data after parsing patterns
-        }
-
+        let loop_start_in_bytes = layout.num_samples == 15;
+        let sample = parse_sample_header(&data[header_offset..header_offset + 30], loop_start_in_bytes)?;
         song.samples.push(sample);
 
         // Create a simple instrument that maps to this sample
@@ -52,51 +105,62 @@ pub fn load_mod(data: &[u8]) -> Result<Song, FormatError> {
     }
 
     // Song length (number of positions in order list)
-    let song_length = data[950] as usize;
+    let length_offset = layout.song_length_offset();
+    let mut song_length = data[length_offset] as usize;
+    if song_length == 0 || song_length > 128 {
+        eprintln!("[MOD] WARNING: bogus song length {}, clamping", song_length);
+        song_length = song_length.clamp(1, 128);
+    }
 
     // Parse order list into local vec
     let mut order = Vec::new();
     for i in 0..song_length {
-        let pattern_idx = data[952 + i];
+        let pattern_idx = data[length_offset + 2 + i];
         order.push(OrderEntry::Pattern(pattern_idx));
     }
 
     // Find highest pattern number to know how many patterns to load
-    let max_pattern = data[952..952 + 128].iter().max().copied().unwrap_or(0) as usize;
+    let orders = &data[length_offset + 2..length_offset + 2 + 128];
+    let max_pattern = orders.iter().max().copied().unwrap_or(0) as usize;
 
-    // Parse patterns into local vec
+    // Parse patterns into local vec; missing or truncated ones are padded with empty rows
     let pattern_size = 64 * num_channels as usize * 4; // 64 rows, 4 bytes per cell
     let mut patterns = Vec::new();
     for pat_idx in 0..=max_pattern {
-        let pat_offset = 1084 + pat_idx * pattern_size;
-        if pat_offset + pattern_size > data.len() {
-            break;
+        let pat_offset = layout.patterns_offset() + pat_idx * pattern_size;
+        let available = data.len().saturating_sub(pat_offset).min(pattern_size);
+        if available < pattern_size {
+            eprintln!(
+                "[MOD] WARNING: pattern {} truncated ({} of {} bytes), padding with empty rows",
+                pat_idx, available, pattern_size
+            );
         }
-        let pattern = parse_pattern(&data[pat_offset..pat_offset + pattern_size], num_channels)?;
-        patterns.push(pattern);
+        let pat_data = data.get(pat_offset..pat_offset + available).unwrap_or(&[]);
+        patterns.push(parse_pattern(pat_data, num_channels));
     }
 
-    // Load sample data
-    let mut sample_offset: usize = 1084 + (max_pattern + 1) * pattern_size;
-    for sample in &mut song.samples {
+    // Load sample data, clamping each sample to the bytes actually present
+    let mut sample_offset: usize = layout.patterns_offset() + (max_pattern + 1) * pattern_size;
+    for (i, sample) in song.samples.iter_mut().enumerate() {
         let len = sample.len();
-        if len > 0 && sample_offset + len <= data.len() {
-            let sample_data: Vec<i8> = data[sample_offset..sample_offset + len]
-                .iter()
-                .map(|&b| b as i8)
-                .collect();
-            sample.data = SampleData::Mono8(sample_data);
-            sample_offset += len;
-
-            // Clamp loop bounds to actual sample length (common in real MOD files)
-            if sample.loop_end > len as u32 {
-                sample.loop_end = len as u32;
-            }
+        if len == 0 {
+            continue;
         }
+        let available = data.len().saturating_sub(sample_offset).min(len);
+        if available < len {
+            eprintln!(
+                "[MOD] WARNING: sample {} truncated ({} of {} bytes)",
+                i + 1, available, len
+            );
+        }
+        let start = sample_offset.min(data.len());
+        sample.set_data(SampleData::Mono8(data[start..start + available].iter().map(|&b| b as i8).collect()));
+        sample_offset += len;
+        clamp_loop(sample, available as u32);
     }
 
     // Set initial tempo/speed (MOD defaults)
-    song.initial_tempo = 125;
+    song.initial_tempo = 12500;
     song.initial_speed = 6;
 
     // Build per-track clips + sequences from parsed patterns/order
@@ -112,7 +176,9 @@ fn parse_string(data: &[u8]) -> String {
 }
 
 /// Parse a sample header (30 bytes).
-fn parse_sample_header(data: &[u8]) -> Result<Sample, FormatError> {
+///
+/// Soundtracker stores the loop start in bytes rather than words.
+fn parse_sample_header(data: &[u8], loop_start_in_bytes: bool) -> Result<Sample, FormatError> {
     if data.len() < 30 {
         return Err(FormatError::UnexpectedEof);
     }
@@ -122,7 +188,8 @@ fn parse_sample_header(data: &[u8]) -> Result<Sample, FormatError> {
     let finetune = (data[24] & 0x0F) as i8;
     let finetune = if finetune > 7 { finetune - 16 } else { finetune };
     let volume = data[25].min(64);
-    let loop_start = u16::from_be_bytes([data[26], data[27]]) as u32 * 2;
+    let loop_start = u16::from_be_bytes([data[26], data[27]]) as u32;
+    let loop_start = if loop_start_in_bytes { loop_start } else { loop_start * 2 };
     let loop_length = u16::from_be_bytes([data[28], data[29]]) as u32 * 2;
 
     let mut sample = Sample::new(&name);
@@ -144,28 +211,38 @@ fn parse_sample_header(data: &[u8]) -> Result<Sample, FormatError> {
     }
 
     // Placeholder for sample data (will be filled in later)
-    sample.data = SampleData::Mono8(alloc::vec![0i8; length as usize]);
+    sample.set_data(SampleData::Mono8(alloc::vec![0i8; length as usize]));
 
     Ok(sample)
 }
 
-/// Parse a pattern.
-fn parse_pattern(data: &[u8], num_channels: u8) -> Result<Pattern, FormatError> {
+/// Parse a pattern. Cells past the end of `data` are left empty.
+fn parse_pattern(data: &[u8], num_channels: u8) -> Pattern {
     let mut pattern = Pattern::new(64, num_channels);
 
     for row in 0..64 {
         for ch in 0..num_channels {
             let offset = (row as usize * num_channels as usize + ch as usize) * 4;
-            if offset + 4 > data.len() {
-                return Err(FormatError::UnexpectedEof);
+            if let Some(cell) = data.get(offset..offset + 4) {
+                *pattern.cell_mut(row, ch) = parse_cell(cell);
             }
-
-            let cell = parse_cell(&data[offset..offset + 4]);
-            *pattern.cell_mut(row, ch) = cell;
         }
     }
 
-    Ok(pattern)
+    pattern
+}
+
+/// Clamp loop bounds to the sample's actual length (common in real MOD
+/// files), dropping loops that start past the end.
+fn clamp_loop(sample: &mut Sample, len: u32) {
+    if sample.loop_end > len {
+        sample.loop_end = len;
+    }
+    if sample.loop_start + 2 > sample.loop_end {
+        sample.loop_start = 0;
+        sample.loop_end = 0;
+        sample.loop_type = mb_ir::LoopType::None;
+    }
 }
 
 /// Parse a single pattern cell (4 bytes).
@@ -198,23 +275,26 @@ fn parse_cell(data: &[u8]) -> Cell {
         instrument: sample,
         volume: VolumeCommand::None,
         effect,
+        ..Cell::empty()
     }
 }
 
+/// Standard Amiga period table for octaves 1-3.
+pub(crate) const PERIODS: [u16; 36] = [
+    856, 808, 762, 720, 678, 640, 604, 570, 538, 508, 480, 453, // Octave 1
+    428, 404, 381, 360, 339, 320, 302, 285, 269, 254, 240, 226, // Octave 2
+    214, 202, 190, 180, 170, 160, 151, 143, 135, 127, 120, 113, // Octave 3
+];
+
+/// IR note number of `PERIODS[0]` (C-1 = 36 in our system).
+pub(crate) const FIRST_NOTE: u8 = 36;
+
 /// Convert Amiga period to MIDI note number.
 fn period_to_note(period: u16) -> Note {
     if period == 0 {
         return Note::None;
     }
 
-    // Standard Amiga period table for octaves 1-3
-    // We'll use a simplified lookup
-    const PERIODS: [u16; 36] = [
-        856, 808, 762, 720, 678, 640, 604, 570, 538, 508, 480, 453, // Octave 1
-        428, 404, 381, 360, 339, 320, 302, 285, 269, 254, 240, 226, // Octave 2
-        214, 202, 190, 180, 170, 160, 151, 143, 135, 127, 120, 113, // Octave 3
-    ];
-
     // Find closest period
     let mut best_note = 0;
     let mut best_diff = u16::MAX;
@@ -227,8 +307,7 @@ fn period_to_note(period: u16) -> Note {
         }
     }
 
-    // Convert to MIDI note (C-1 = 24 in our system)
-    Note::On((best_note + 36) as u8)
+    Note::On(best_note as u8 + FIRST_NOTE)
 }
 
 // Effect parsing is shared with BMX format — delegated to effect_parser module.
@@ -244,4 +323,140 @@ mod tests {
         assert_eq!(period_to_note(428), Note::On(48)); // C-4 in MIDI terms
         assert_eq!(period_to_note(0), Note::None);
     }
+
+    /// M.K. header with one 64-byte looped sample and patterns 0..=`max_pattern` in the order list.
+    fn header(max_pattern: u8) -> Vec<u8> {
+        let mut data = alloc::vec![0u8; 1084];
+        data[20 + 22..20 + 24].copy_from_slice(&32u16.to_be_bytes());
+        data[20 + 25] = 64;
+        data[20 + 28..20 + 30].copy_from_slice(&32u16.to_be_bytes());
+        data[950] = max_pattern + 1;
+        for i in 0..=max_pattern {
+            data[952 + i as usize] = i;
+        }
+        data[1080..1084].copy_from_slice(b"M.K.");
+        data
+    }
+
+    #[test]
+    fn truncated_sample_is_clamped() {
+        let mut data = header(0);
+        data.extend(core::iter::repeat_n(0u8, 1024)); // pattern 0
+        data.extend(core::iter::repeat_n(0x40u8, 20)); // 20 of 64 sample bytes
+        let song = load_mod(&data).unwrap();
+        assert_eq!(song.compatibility, CompatibilityMode::ProTracker);
+        assert_eq!(song.samples[0].len(), 20);
+        assert_eq!(song.samples[0].loop_end, 20);
+    }
+
+    #[test]
+    fn missing_sample_data_leaves_sample_empty() {
+        let mut data = header(0);
+        data.extend(core::iter::repeat_n(0u8, 1024));
+        let song = load_mod(&data).unwrap();
+        assert!(song.samples[0].is_empty());
+        assert_eq!(song.samples[0].loop_type, mb_ir::LoopType::None);
+    }
+
+    #[test]
+    fn missing_patterns_are_filled_with_empties() {
+        let mut data = header(2);
+        let mut first = alloc::vec![0u8; 1024];
+        first[..4].copy_from_slice(&[0x11, 0xAC, 0x00, 0x00]); // C-2, sample 17
+        data.extend(first);
+        data.extend(core::iter::repeat_n(0u8, 100)); // pattern 1 cut short, pattern 2 missing
+        let song = load_mod(&data).unwrap();
+        let track = &song.tracks[0];
+        assert_eq!(track.sequence.len(), 3);
+        assert_eq!(track.clips[0].pattern().unwrap().cell(0, 0).note, Note::On(48));
+        assert!(track.clips.iter().all(|c| c.pattern().unwrap().rows == 64));
+    }
+
+    #[test]
+    fn bogus_song_length_is_clamped() {
+        let mut data = header(0);
+        data[950] = 200;
+        data.extend(core::iter::repeat_n(0u8, 1024));
+        let song = load_mod(&data).unwrap();
+        assert_eq!(song.tracks[0].sequence.len(), 128);
+    }
+
+    #[test]
+    fn signatures_map_to_channel_counts() {
+        assert_eq!(signature_channels(b"M.K."), Some(4));
+        assert_eq!(signature_channels(b"FLT8"), Some(8));
+        assert_eq!(signature_channels(b"6CHN"), Some(6));
+        assert_eq!(signature_channels(b"2CHN"), Some(2));
+        assert_eq!(signature_channels(b"12CH"), Some(12));
+        assert_eq!(signature_channels(b"32CH"), Some(32));
+        assert_eq!(signature_channels(b"TDZ3"), Some(3));
+        assert_eq!(signature_channels(b"0CHN"), None);
+        assert_eq!(signature_channels(b"33CH"), None);
+        assert_eq!(signature_channels(b"\0\0\0\0"), None);
+    }
+
+    /// 31-sample module with `sig`, one pattern with a C-2 on the last channel.
+    fn multichannel(sig: &[u8; 4], channels: usize) -> Vec<u8> {
+        let mut data = header(0);
+        data[1080..1084].copy_from_slice(sig);
+        let mut pattern = alloc::vec![0u8; 64 * channels * 4];
+        let last = (channels - 1) * 4;
+        pattern[last..last + 4].copy_from_slice(&[0x01, 0xAC, 0x10, 0x00]);
+        data.extend(pattern);
+        data.extend(core::iter::repeat_n(0x40u8, 64));
+        data
+    }
+
+    #[test]
+    fn xchn_module_loads_all_channels() {
+        let song = load_mod(&multichannel(b"6CHN", 6)).unwrap();
+        assert_eq!(song.tracks[0].num_channels, 6);
+        assert_eq!(song.tracks[0].clips[0].pattern().unwrap().cell(0, 5).note, Note::On(48));
+        assert_eq!(song.samples[0].len(), 64);
+    }
+
+    #[test]
+    fn xxch_module_loads_all_channels() {
+        let song = load_mod(&multichannel(b"12CH", 12)).unwrap();
+        assert_eq!(song.tracks[0].num_channels, 12);
+        assert_eq!(song.tracks[0].clips[0].pattern().unwrap().cell(0, 11).note, Note::On(48));
+        assert_eq!(song.samples[0].len(), 64);
+    }
+
+    /// 15-sample Soundtracker module: 600-byte header, no signature.
+    fn soundtracker() -> Vec<u8> {
+        let mut data = alloc::vec![0u8; 600];
+        data[..5].copy_from_slice(b"st-01");
+        data[20 + 22..20 + 24].copy_from_slice(&32u16.to_be_bytes());
+        data[20 + 25] = 48;
+        data[20 + 26..20 + 28].copy_from_slice(&16u16.to_be_bytes()); // loop start in bytes
+        data[20 + 28..20 + 30].copy_from_slice(&8u16.to_be_bytes());
+        data[470] = 1; // song length
+        data[471] = 120;
+        let mut pattern = alloc::vec![0u8; 1024];
+        pattern[..4].copy_from_slice(&[0x01, 0xAC, 0x10, 0x00]);
+        data.extend(pattern);
+        data.extend(core::iter::repeat_n(0x40u8, 64));
+        data
+    }
+
+    #[test]
+    fn soundtracker_module_loads_15_samples() {
+        let song = load_mod(&soundtracker()).unwrap();
+        assert_eq!(song.title.as_str(), "st-01");
+        assert_eq!(song.samples.len(), 15);
+        assert_eq!(song.instruments.len(), 15);
+        assert_eq!(song.tracks[0].num_channels, 4);
+        assert_eq!(song.tracks[0].clips[0].pattern().unwrap().cell(0, 0).note, Note::On(48));
+        let sample = &song.samples[0];
+        assert_eq!(sample.len(), 64);
+        assert_eq!(sample.default_volume, 48);
+        assert_eq!((sample.loop_start, sample.loop_end), (16, 32));
+    }
+
+    #[test]
+    fn unrecognised_data_is_rejected() {
+        let data = alloc::vec![0xFFu8; 2048];
+        assert!(matches!(load_mod(&data), Err(FormatError::InvalidHeader)));
+    }
 }
@@ -0,0 +1,1203 @@
+//! Native project format: a lossless snapshot of the Song IR.
+//!
+//! Layout: `MBPJ` magic, u16 version, then chunks of `[id: 4 bytes]
+//! [len: u32 LE][payload]`. Each chunk holds one part of the song; readers
+//! skip chunks they don't know, so newer files still open in older builds
+//! as long as the version is unchanged. Fields added later get chunks of
+//! their own rather than growing existing ones. All integers are
+//! little-endian; strings are a u32 byte length then UTF-8.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use mb_ir::{
+    AudioGraph, AutoVibrato, Cell, ChannelSettings, Clip, ClipBoundary, CompatibilityMode, Connection,
+    DuplicateCheck, Effect, Envelope, FrequencyMode, Groove, Instrument, LoopType, Marker, Meter, MusicalTime,
+    NewNoteAction, Node, NodeType, Note, ParamCurve, ParamUnit, Parameter, Pattern, Sample, SampleData,
+    SeqEntry, SeqTermination, Song, Subsong, TempoCurve, TempoEnvelope, TempoPoint, TimeSignature, Track,
+    TrackOffset, VolumeCommand, WireLane, WireValue,
+};
+
+use crate::bmx_format::KNOWN_ENUM_LABELS;
+use crate::FormatError;
+
+const MAGIC: &[u8; 4] = b"MBPJ";
+const VERSION: u16 = 1;
+
+/// Serialize a song to the native project format.
+pub fn save_song(song: &Song) -> Vec<u8> {
+    let mut out = Writer::default();
+    out.bytes(MAGIC);
+    out.u16(VERSION);
+
+    out.chunk(b"SONG", |w| {
+        w.str(&song.title);
+        w.u8(((song.initial_tempo + 50) / 100).clamp(1, 255) as u8);
+        w.u8(song.initial_speed);
+        w.u8(song.rows_per_beat);
+        w.u8(song.global_volume);
+        w.u8(match song.frequency_mode {
+            FrequencyMode::Amiga => 0,
+            FrequencyMode::Linear => 1,
+        });
+        w.u8(match song.compatibility {
+            CompatibilityMode::Native => 0,
+            CompatibilityMode::ProTracker => 1,
+            CompatibilityMode::FastTracker2 => 2,
+            CompatibilityMode::ImpulseTracker => 3,
+        });
+    });
+    out.chunk(b"BPMX", |w| w.u32(song.initial_tempo));
+    out.chunk(b"CHAN", |w| {
+        w.list(&song.channels, |w, ch| {
+            w.i8(ch.initial_pan);
+            w.u8(ch.initial_vol);
+            w.u8(ch.muted as u8 | (ch.solo as u8) << 1);
+        });
+    });
+    out.chunk(b"SMPL", |w| w.list(&song.samples, write_sample));
+    out.chunk(b"INST", |w| w.list(&song.instruments, write_instrument));
+    out.chunk(b"GRPH", |w| write_graph(w, &song.graph));
+    out.chunk(b"WPAN", |w| w.list(&song.graph.connections, |w, c| w.i8(c.pan)));
+    out.chunk(b"NKND", |w| write_node_kinds(w, &song.graph));
+    out.chunk(b"TRAK", |w| w.list(&song.tracks, write_track));
+    out.chunk(b"TNAM", |w| w.list(&song.tracks, |w, t| w.str(&t.name)));
+    out.chunk(b"WIRE", |w| {
+        w.list(&song.tracks, |w, t| {
+            w.list(&t.clips, |w, clip| w.list(clip.pattern().map_or(&[][..], |p| &p.wires), write_wire_lane));
+        });
+    });
+    out.chunk(b"EFX2", |w| {
+        w.list(&song.tracks, |w, t| w.list(&t.clips, |w, clip| w.list(&second_effects(clip.pattern()), write_second_effect)));
+    });
+    out.chunk(b"GROV", |w| {
+        write_groove(w, &song.groove);
+        w.list(&song.tracks, |w, t| {
+            w.bool(t.groove.is_some());
+            write_groove(w, t.groove.as_ref().unwrap_or(&Groove::Straight));
+        });
+    });
+    out.chunk(b"TOFS", |w| w.list(&song.tracks, |w, t| write_offset(w, t.offset)));
+    out.chunk(b"TMPO", |w| w.list(song.tempo_envelope.points(), write_tempo_point));
+    out.chunk(b"METR", |w| {
+        write_signature(w, &song.meter.initial);
+        w.list(song.meter.changes(), |w, (beat, signature)| {
+            w.u64(*beat);
+            write_signature(w, signature);
+        });
+    });
+    out.chunk(b"MARK", |w| {
+        w.list(&song.markers, |w, m| {
+            w.u64(m.time.beat);
+            w.u32(m.time.sub_beat);
+            w.str(&m.name);
+        });
+    });
+    out.chunk(b"SUBS", |w| {
+        w.list(&song.subsongs, |w, sub| {
+            w.str(&sub.name);
+            w.u16(sub.order_start);
+            w.list(&sub.sequence, write_seq_entry);
+        });
+    });
+    out.buf
+}
+
+/// Deserialize a song saved by `save_song`.
+pub fn load_song(data: &[u8]) -> Result<Song, FormatError> {
+    let mut r = Reader { data, pos: 0 };
+    if r.bytes(4)? != MAGIC {
+        return Err(FormatError::InvalidHeader);
+    }
+    if r.u16()? > VERSION {
+        return Err(FormatError::UnsupportedVersion);
+    }
+
+    let mut song = Song::default();
+    while r.pos < data.len() {
+        let id: [u8; 4] = r.bytes(4)?.try_into().unwrap_or_default();
+        let len = r.u32()? as usize;
+        let mut c = Reader { data: r.bytes(len)?, pos: 0 };
+        match &id {
+            b"SONG" => {
+                song = Song::new(&c.str()?);
+                song.initial_tempo = c.u8()? as u32 * 100;
+                song.initial_speed = c.u8()?;
+                song.rows_per_beat = c.u8()?;
+                song.global_volume = c.u8()?;
+                if c.pos < c.data.len() && c.u8()? == 1 {
+                    song.frequency_mode = FrequencyMode::Linear;
+                }
+                if c.pos < c.data.len() {
+                    song.compatibility = match c.u8()? {
+                        1 => CompatibilityMode::ProTracker,
+                        2 => CompatibilityMode::FastTracker2,
+                        3 => CompatibilityMode::ImpulseTracker,
+                        _ => CompatibilityMode::Native,
+                    };
+                }
+            }
+            b"CHAN" => {
+                song.channels = c.list(|c| {
+                    let (initial_pan, initial_vol, flags) = (c.i8()?, c.u8()?, c.u8()?);
+                    Ok(ChannelSettings { initial_pan, initial_vol, muted: flags & 1 != 0, solo: flags & 2 != 0 })
+                })?;
+            }
+            b"SMPL" => song.samples = c.list(read_sample)?,
+            b"INST" => song.instruments = c.list(read_instrument)?,
+            b"GRPH" => song.graph = read_graph(&mut c)?,
+            b"WPAN" => {
+                let pans = c.list(|c| c.i8())?;
+                for (conn, pan) in song.graph.connections.iter_mut().zip(pans) {
+                    conn.pan = pan;
+                }
+            }
+            b"NKND" => {
+                for (id, node_type) in c.list(|c| Ok((c.u16()?, read_node_type(c)?)))? {
+                    if let Some(node) = song.graph.node_mut(id) {
+                        node.node_type = node_type;
+                    }
+                }
+            }
+            b"TRAK" => song.tracks = c.list(read_track)?,
+            b"TNAM" => {
+                let names = c.list(|c| c.str())?;
+                for (track, name) in song.tracks.iter_mut().zip(names) {
+                    track.set_name(&name);
+                }
+            }
+            b"WIRE" => {
+                let tracks = c.list(|c| c.list(|c| c.list(read_wire_lane)))?;
+                for (track, clips) in song.tracks.iter_mut().zip(tracks) {
+                    for (clip, lanes) in track.clips.iter_mut().zip(clips) {
+                        let Some(pattern) = clip.pattern_mut() else { continue };
+                        pattern.wires = lanes;
+                        for lane in &mut pattern.wires {
+                            lane.values.resize(pattern.rows as usize, WireValue::default());
+                        }
+                    }
+                }
+            }
+            b"EFX2" => {
+                let tracks = c.list(|c| c.list(|c| c.list(read_second_effect)))?;
+                for (track, clips) in song.tracks.iter_mut().zip(tracks) {
+                    for (clip, effects) in track.clips.iter_mut().zip(clips) {
+                        let Some(pattern) = clip.pattern_mut() else { continue };
+                        for (index, effect) in effects {
+                            if let Some(cell) = pattern.data.get_mut(index as usize) {
+                                cell.effect2 = effect;
+                            }
+                        }
+                    }
+                }
+            }
+            b"GROV" => {
+                song.groove = read_groove(&mut c)?;
+                let grooves = c.list(|c| {
+                    let present = c.bool()?;
+                    let groove = read_groove(c)?;
+                    Ok(present.then_some(groove))
+                })?;
+                for (track, groove) in song.tracks.iter_mut().zip(grooves) {
+                    track.groove = groove;
+                }
+            }
+            b"TOFS" => {
+                for (track, offset) in song.tracks.iter_mut().zip(c.list(read_offset)?) {
+                    track.offset = offset;
+                }
+            }
+            b"BPMX" => song.initial_tempo = c.u32()?.max(100),
+            b"TMPO" => song.tempo_envelope = TempoEnvelope::new(c.list(read_tempo_point)?),
+            b"METR" => {
+                song.meter = Meter::new(read_signature(&mut c)?);
+                for (beat, signature) in c.list(|c| Ok((c.u64()?, read_signature(c)?)))? {
+                    song.meter.set(beat, Some(signature));
+                }
+            }
+            b"MARK" => {
+                for (time, name) in c.list(|c| Ok((MusicalTime { beat: c.u64()?, sub_beat: c.u32()? }, c.str()?)))? {
+                    song.add_marker(Marker::new(time, &name));
+                }
+            }
+            b"SUBS" => {
+                song.subsongs = c.list(|c| {
+                    let name = c.str()?;
+                    let mut sub = Subsong {
+                        name: Default::default(),
+                        order_start: c.u16()?,
+                        sequence: c.list(read_seq_entry)?,
+                    };
+                    let _ = sub.name.try_push_str(&name);
+                    Ok(sub)
+                })?;
+            }
+            _ => {}
+        }
+    }
+    Ok(song)
+}
+
+// ---------------------------------------------------------------------------
+// Byte-level writer and reader
+// ---------------------------------------------------------------------------
+
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn bytes(&mut self, b: &[u8]) {
+        self.buf.extend_from_slice(b);
+    }
+    fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+    fn i8(&mut self, v: i8) {
+        self.u8(v as u8);
+    }
+    fn bool(&mut self, v: bool) {
+        self.u8(v as u8);
+    }
+    fn u16(&mut self, v: u16) {
+        self.bytes(&v.to_le_bytes());
+    }
+    fn i16(&mut self, v: i16) {
+        self.bytes(&v.to_le_bytes());
+    }
+    fn u32(&mut self, v: u32) {
+        self.bytes(&v.to_le_bytes());
+    }
+    fn i32(&mut self, v: i32) {
+        self.bytes(&v.to_le_bytes());
+    }
+    fn u64(&mut self, v: u64) {
+        self.bytes(&v.to_le_bytes());
+    }
+    fn f32(&mut self, v: f32) {
+        self.bytes(&v.to_le_bytes());
+    }
+    fn str(&mut self, s: &str) {
+        self.u32(s.len() as u32);
+        self.bytes(s.as_bytes());
+    }
+    fn opt_u8(&mut self, v: Option<u8>) {
+        self.bool(v.is_some());
+        self.u8(v.unwrap_or(0));
+    }
+    fn list<T>(&mut self, items: &[T], mut f: impl FnMut(&mut Self, &T)) {
+        self.u32(items.len() as u32);
+        for item in items {
+            f(self, item);
+        }
+    }
+    fn chunk(&mut self, id: &[u8; 4], f: impl FnOnce(&mut Self)) {
+        self.bytes(id);
+        let len_at = self.buf.len();
+        self.u32(0);
+        f(self);
+        let len = (self.buf.len() - len_at - 4) as u32;
+        self.buf[len_at..len_at + 4].copy_from_slice(&len.to_le_bytes());
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], FormatError> {
+        let b = self.data.get(self.pos..self.pos + n).ok_or(FormatError::UnexpectedEof)?;
+        self.pos += n;
+        Ok(b)
+    }
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], FormatError> {
+        let mut a = [0; N];
+        a.copy_from_slice(self.bytes(N)?);
+        Ok(a)
+    }
+    fn u8(&mut self) -> Result<u8, FormatError> {
+        Ok(self.array::<1>()?[0])
+    }
+    fn i8(&mut self) -> Result<i8, FormatError> {
+        Ok(self.u8()? as i8)
+    }
+    fn bool(&mut self) -> Result<bool, FormatError> {
+        Ok(self.u8()? != 0)
+    }
+    fn u16(&mut self) -> Result<u16, FormatError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+    fn i16(&mut self) -> Result<i16, FormatError> {
+        Ok(i16::from_le_bytes(self.array()?))
+    }
+    fn u32(&mut self) -> Result<u32, FormatError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+    fn i32(&mut self) -> Result<i32, FormatError> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+    fn u64(&mut self) -> Result<u64, FormatError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+    fn f32(&mut self) -> Result<f32, FormatError> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+    fn str(&mut self) -> Result<String, FormatError> {
+        let len = self.u32()? as usize;
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+    fn opt_u8(&mut self) -> Result<Option<u8>, FormatError> {
+        let present = self.bool()?;
+        let v = self.u8()?;
+        Ok(present.then_some(v))
+    }
+    fn list<T>(
+        &mut self,
+        mut f: impl FnMut(&mut Self) -> Result<T, FormatError>,
+    ) -> Result<Vec<T>, FormatError> {
+        let count = self.u32()? as usize;
+        // Each item takes at least a byte; don't trust a corrupt count for the allocation
+        let mut items = Vec::with_capacity(count.min(self.data.len() - self.pos));
+        for _ in 0..count {
+            items.push(f(self)?);
+        }
+        Ok(items)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Samples and instruments
+// ---------------------------------------------------------------------------
+
+fn write_sample(w: &mut Writer, s: &Sample) {
+    w.str(&s.name);
+    w.u32(s.loop_start);
+    w.u32(s.loop_end);
+    w.u8(match s.loop_type {
+        LoopType::None => 0,
+        LoopType::Forward => 1,
+        LoopType::PingPong => 2,
+        LoopType::Sustain => 3,
+    });
+    w.u8(s.default_volume);
+    w.i8(s.default_pan);
+    w.u32(s.c4_speed);
+    w.bool(s.vibrato.is_some());
+    let v = s.vibrato.unwrap_or_default();
+    w.bytes(&[v.speed, v.depth, v.sweep, v.waveform]);
+
+    match &*s.data {
+        SampleData::Mono8(d) => {
+            w.u8(0);
+            w.list(d, |w, &v| w.i8(v));
+        }
+        SampleData::Mono16(d) => {
+            w.u8(1);
+            w.list(d, |w, &v| w.i16(v));
+        }
+        SampleData::Stereo8(l, r) => {
+            w.u8(2);
+            w.list(l, |w, &v| w.i8(v));
+            w.list(r, |w, &v| w.i8(v));
+        }
+        SampleData::Stereo16(l, r) => {
+            w.u8(3);
+            w.list(l, |w, &v| w.i16(v));
+            w.list(r, |w, &v| w.i16(v));
+        }
+    }
+}
+
+fn read_sample(r: &mut Reader) -> Result<Sample, FormatError> {
+    let mut s = Sample::new(&r.str()?);
+    s.loop_start = r.u32()?;
+    s.loop_end = r.u32()?;
+    s.loop_type = match r.u8()? {
+        1 => LoopType::Forward,
+        2 => LoopType::PingPong,
+        3 => LoopType::Sustain,
+        _ => LoopType::None,
+    };
+    s.default_volume = r.u8()?;
+    s.default_pan = r.i8()?;
+    s.c4_speed = r.u32()?;
+    let has_vibrato = r.bool()?;
+    let [speed, depth, sweep, waveform] = r.array()?;
+    s.vibrato = has_vibrato.then_some(AutoVibrato { speed, depth, sweep, waveform });
+
+    let data = match r.u8()? {
+        0 => SampleData::Mono8(r.list(Reader::i8)?),
+        1 => SampleData::Mono16(r.list(Reader::i16)?),
+        2 => SampleData::Stereo8(r.list(Reader::i8)?, r.list(Reader::i8)?),
+        3 => SampleData::Stereo16(r.list(Reader::i16)?, r.list(Reader::i16)?),
+        _ => return Err(FormatError::InvalidHeader),
+    };
+    s.set_data(data);
+    Ok(s)
+}
+
+fn write_instrument(w: &mut Writer, inst: &Instrument) {
+    w.str(&inst.name);
+    w.bytes(&inst.sample_map);
+    for env in [&inst.volume_envelope, &inst.panning_envelope, &inst.pitch_envelope] {
+        w.bool(env.is_some());
+        if let Some(env) = env {
+            w.bool(env.enabled);
+            for point in [env.sustain_start, env.sustain_end, env.loop_start, env.loop_end] {
+                w.opt_u8(point);
+            }
+            w.list(&env.points, |w, p| {
+                w.u16(p.tick);
+                w.i8(p.value);
+            });
+        }
+    }
+    w.u16(inst.fadeout);
+    w.u8(match inst.new_note_action {
+        NewNoteAction::Cut => 0,
+        NewNoteAction::Continue => 1,
+        NewNoteAction::Off => 2,
+        NewNoteAction::Fade => 3,
+    });
+    w.u8(match inst.duplicate_check {
+        DuplicateCheck::Off => 0,
+        DuplicateCheck::Note => 1,
+        DuplicateCheck::Sample => 2,
+        DuplicateCheck::Instrument => 3,
+    });
+}
+
+fn read_instrument(r: &mut Reader) -> Result<Instrument, FormatError> {
+    let mut inst = Instrument::new(&r.str()?);
+    inst.sample_map = r.array()?;
+    let envelope = |r: &mut Reader| -> Result<Option<Envelope>, FormatError> {
+        if !r.bool()? {
+            return Ok(None);
+        }
+        let mut env = Envelope::new();
+        env.enabled = r.bool()?;
+        env.sustain_start = r.opt_u8()?;
+        env.sustain_end = r.opt_u8()?;
+        env.loop_start = r.opt_u8()?;
+        env.loop_end = r.opt_u8()?;
+        for (tick, value) in r.list(|r| Ok((r.u16()?, r.i8()?)))? {
+            env.add_point(tick, value);
+        }
+        Ok(Some(env))
+    };
+    inst.volume_envelope = envelope(r)?;
+    inst.panning_envelope = envelope(r)?;
+    inst.pitch_envelope = envelope(r)?;
+    inst.fadeout = r.u16()?;
+    inst.new_note_action = match r.u8()? {
+        1 => NewNoteAction::Continue,
+        2 => NewNoteAction::Off,
+        3 => NewNoteAction::Fade,
+        _ => NewNoteAction::Cut,
+    };
+    inst.duplicate_check = match r.u8()? {
+        1 => DuplicateCheck::Note,
+        2 => DuplicateCheck::Sample,
+        3 => DuplicateCheck::Instrument,
+        _ => DuplicateCheck::Off,
+    };
+    Ok(inst)
+}
+
+// ---------------------------------------------------------------------------
+// Graph
+// ---------------------------------------------------------------------------
+
+fn write_graph(w: &mut Writer, graph: &AudioGraph) {
+    w.list(&graph.nodes, |w, node| {
+        w.u16(node.id);
+        if in_node_kinds_chunk(&node.node_type) {
+            write_node_type(w, &NodeType::Machine { machine_name: node.node_type.label(), is_tracker: false });
+        } else {
+            write_node_type(w, &node.node_type);
+        }
+        w.u8(node.oversample);
+        w.list(&node.parameters, write_parameter);
+    });
+    w.list(&graph.connections, |w, c| {
+        w.u16(c.from);
+        w.u16(c.to);
+        w.u8(c.from_channel);
+        w.u8(c.to_channel);
+        w.i16(c.gain);
+    });
+}
+
+fn read_graph(r: &mut Reader) -> Result<AudioGraph, FormatError> {
+    let nodes = r.list(|r| {
+        let id = r.u16()?;
+        let node_type = read_node_type(r)?;
+        Ok(Node { id, node_type, oversample: r.u8()?, parameters: r.list(read_parameter)? })
+    })?;
+    let connections = r.list(|r| {
+        Ok(Connection {
+            from: r.u16()?,
+            to: r.u16()?,
+            from_channel: r.u8()?,
+            to_channel: r.u8()?,
+            gain: r.i16()?,
+            pan: 0,
+        })
+    })?;
+    Ok(AudioGraph { nodes, connections })
+}
+
+/// Node kinds added after GRPH's layout was set. GRPH stores them as a
+/// machine older builds can't create and NKND stores the real kind, so
+/// those builds still open the file.
+fn in_node_kinds_chunk(node_type: &NodeType) -> bool {
+    matches!(node_type, NodeType::AudioInput | NodeType::Plugin { .. } | NodeType::MidiOut { .. })
+}
+
+fn write_node_kinds(w: &mut Writer, graph: &AudioGraph) {
+    let nodes: Vec<&Node> = graph.nodes.iter().filter(|n| in_node_kinds_chunk(&n.node_type)).collect();
+    w.list(&nodes, |w, node| {
+        w.u16(node.id);
+        write_node_type(w, &node.node_type);
+    });
+}
+
+/// Node kinds as a tag and their fields. Tags are part of the file
+/// format: append new kinds, never renumber.
+fn write_node_type(w: &mut Writer, node_type: &NodeType) {
+    match node_type {
+        NodeType::Master => w.u8(0),
+        NodeType::Machine { machine_name, is_tracker } => {
+            w.u8(1);
+            w.str(machine_name);
+            w.bool(*is_tracker);
+        }
+        NodeType::FeedbackSend => w.u8(2),
+        NodeType::FeedbackReturn { send } => {
+            w.u8(3);
+            w.u16(*send);
+        }
+        NodeType::AudioInput => w.u8(4),
+        NodeType::Plugin { path, plugin_id } => {
+            w.u8(5);
+            w.str(path);
+            w.str(plugin_id);
+        }
+        NodeType::MidiOut { port, channel } => {
+            w.u8(6);
+            w.str(port);
+            w.u8(*channel);
+        }
+    }
+}
+
+fn read_node_type(r: &mut Reader) -> Result<NodeType, FormatError> {
+    Ok(match r.u8()? {
+        0 => NodeType::Master,
+        1 => NodeType::Machine { machine_name: r.str()?, is_tracker: r.bool()? },
+        2 => NodeType::FeedbackSend,
+        3 => NodeType::FeedbackReturn { send: r.u16()? },
+        4 => NodeType::AudioInput,
+        5 => NodeType::Plugin { path: r.str()?, plugin_id: r.str()? },
+        6 => NodeType::MidiOut { port: r.str()?, channel: r.u8()? },
+        tag => return Err(FormatError::UnknownNodeType(tag)),
+    })
+}
+
+fn write_parameter(w: &mut Writer, p: &Parameter) {
+    w.u16(p.id);
+    w.str(&p.name);
+    for v in [p.value, p.min, p.max, p.default] {
+        w.i32(v);
+    }
+    match p.unit {
+        ParamUnit::None => w.u8(0),
+        ParamUnit::Hz => w.u8(1),
+        ParamUnit::Decibels => w.u8(2),
+        ParamUnit::Milliseconds => w.u8(3),
+        ParamUnit::Percent => w.u8(4),
+        ParamUnit::Enum(labels) => {
+            w.u8(5);
+            w.list(labels, |w, l| w.str(l));
+        }
+    }
+    let (tag, lo, hi) = match p.curve {
+        ParamCurve::Raw => (0, 0.0, 0.0),
+        ParamCurve::Linear { lo, hi } => (1, lo, hi),
+        ParamCurve::Exponential { lo, hi } => (2, lo, hi),
+    };
+    w.u8(tag);
+    w.f32(lo);
+    w.f32(hi);
+}
+
+fn read_parameter(r: &mut Reader) -> Result<Parameter, FormatError> {
+    let id = r.u16()?;
+    let name = r.str()?;
+    let [value, min, max, default] = [r.i32()?, r.i32()?, r.i32()?, r.i32()?];
+    let mut p = Parameter::new(id, &name, min, max, default);
+    p.value = value;
+    p.unit = match r.u8()? {
+        1 => ParamUnit::Hz,
+        2 => ParamUnit::Decibels,
+        3 => ParamUnit::Milliseconds,
+        4 => ParamUnit::Percent,
+        5 => static_enum_labels(&r.list(Reader::str)?).map_or(ParamUnit::None, ParamUnit::Enum),
+        _ => ParamUnit::None,
+    };
+    let (tag, lo, hi) = (r.u8()?, r.f32()?, r.f32()?);
+    p.curve = match tag {
+        1 => ParamCurve::Linear { lo, hi },
+        2 => ParamCurve::Exponential { lo, hi },
+        _ => ParamCurve::Raw,
+    };
+    Ok(p)
+}
+
+/// Enum labels are `'static` in the IR, so only label sets known to the
+/// loaders can be restored; others fall back to a plain number display.
+fn static_enum_labels(labels: &[String]) -> Option<&'static [&'static str]> {
+    KNOWN_ENUM_LABELS.iter().copied().find(|known| known.iter().eq(labels.iter()))
+}
+
+// ---------------------------------------------------------------------------
+// Tracks and patterns
+// ---------------------------------------------------------------------------
+
+fn write_track(w: &mut Writer, t: &Track) {
+    w.bool(t.machine_node.is_some());
+    w.u16(t.machine_node.unwrap_or(0));
+    w.u8(t.base_channel);
+    w.u8(t.num_channels);
+    w.bool(t.muted);
+    w.u16(t.order_base);
+    w.list(&t.clips, |w, clip| match clip {
+        Clip::Pattern(p) => {
+            w.u8(0);
+            write_pattern(w, p);
+        }
+    });
+    w.list(&t.sequence, write_seq_entry);
+}
+
+fn read_track(r: &mut Reader) -> Result<Track, FormatError> {
+    let has_node = r.bool()?;
+    let node = r.u16()?;
+    let mut t = Track::new(has_node.then_some(node), r.u8()?, r.u8()?);
+    t.muted = r.bool()?;
+    t.order_base = r.u16()?;
+    t.clips = r.list(|r| match r.u8()? {
+        0 => Ok(Clip::Pattern(read_pattern(r)?)),
+        _ => Err(FormatError::InvalidHeader),
+    })?;
+    t.sequence = r.list(read_seq_entry)?;
+    Ok(t)
+}
+
+fn write_wire_lane(w: &mut Writer, lane: &WireLane) {
+    w.u16(lane.from);
+    w.list(&lane.values, |w, v| {
+        w.u8(v.gain.is_some() as u8 | (v.pan.is_some() as u8) << 1);
+        w.i16(v.gain.unwrap_or(0));
+        w.i8(v.pan.unwrap_or(0));
+    });
+}
+
+fn read_wire_lane(r: &mut Reader) -> Result<WireLane, FormatError> {
+    let from = r.u16()?;
+    let values = r.list(|r| {
+        let (flags, gain, pan) = (r.u8()?, r.i16()?, r.i8()?);
+        Ok(WireValue { gain: (flags & 1 != 0).then_some(gain), pan: (flags & 2 != 0).then_some(pan) })
+    })?;
+    Ok(WireLane { from, values })
+}
+
+/// `(cell index, effect)` of each cell with a second-column effect.
+fn second_effects(pattern: Option<&Pattern>) -> Vec<(u32, Effect)> {
+    let cells = pattern.map_or(&[][..], |p| &p.data);
+    cells.iter().enumerate()
+        .filter(|(_, cell)| cell.effect2 != Effect::None)
+        .map(|(i, cell)| (i as u32, cell.effect2))
+        .collect()
+}
+
+fn write_second_effect(w: &mut Writer, &(index, effect): &(u32, Effect)) {
+    w.u32(index);
+    w.bytes(&encode_effect(effect));
+}
+
+fn read_second_effect(r: &mut Reader) -> Result<(u32, Effect), FormatError> {
+    Ok((r.u32()?, decode_effect(r.array()?)))
+}
+
+fn write_seq_entry(w: &mut Writer, e: &SeqEntry) {
+    w.u64(e.start.beat);
+    w.u32(e.start.sub_beat);
+    w.u16(e.clip_idx);
+    w.u16(e.length);
+    w.u8(match e.termination {
+        SeqTermination::Natural => 0,
+        SeqTermination::Mute => 1,
+        SeqTermination::Break => 2,
+    });
+    w.u8(match e.boundary {
+        ClipBoundary::Ring => 0,
+        ClipBoundary::Cut => 1,
+        ClipBoundary::Release => 2,
+    });
+}
+
+fn read_seq_entry(r: &mut Reader) -> Result<SeqEntry, FormatError> {
+    Ok(SeqEntry {
+        start: MusicalTime { beat: r.u64()?, sub_beat: r.u32()? },
+        clip_idx: r.u16()?,
+        length: r.u16()?,
+        termination: match r.u8()? {
+            1 => SeqTermination::Mute,
+            2 => SeqTermination::Break,
+            _ => SeqTermination::Natural,
+        },
+        boundary: match r.u8()? {
+            1 => ClipBoundary::Cut,
+            2 => ClipBoundary::Release,
+            _ => ClipBoundary::Ring,
+        },
+    })
+}
+
+fn write_signature(w: &mut Writer, s: &TimeSignature) {
+    w.u8(s.beats_per_bar);
+    w.u8(s.beat_unit);
+}
+
+fn read_signature(r: &mut Reader) -> Result<TimeSignature, FormatError> {
+    Ok(TimeSignature::new(r.u8()?, r.u8()?))
+}
+
+fn write_tempo_point(w: &mut Writer, p: &TempoPoint) {
+    w.u64(p.time.beat);
+    w.u32(p.time.sub_beat);
+    w.u32(p.tempo);
+    w.u8(match p.curve {
+        TempoCurve::Step => 0,
+        TempoCurve::Linear => 1,
+        TempoCurve::Exponential => 2,
+    });
+}
+
+fn read_tempo_point(r: &mut Reader) -> Result<TempoPoint, FormatError> {
+    Ok(TempoPoint {
+        time: MusicalTime { beat: r.u64()?, sub_beat: r.u32()? },
+        tempo: r.u32()?,
+        curve: match r.u8()? {
+            0 => TempoCurve::Step,
+            2 => TempoCurve::Exponential,
+            _ => TempoCurve::Linear,
+        },
+    })
+}
+
+fn write_groove(w: &mut Writer, g: &Groove) {
+    match g {
+        Groove::Straight => w.u8(0),
+        Groove::Swing(percent) => {
+            w.u8(1);
+            w.u8(*percent);
+        }
+        Groove::Ticks(ticks) => {
+            w.u8(2);
+            w.list(ticks, |w, t| w.u8(*t));
+        }
+    }
+}
+
+fn read_groove(r: &mut Reader) -> Result<Groove, FormatError> {
+    Ok(match r.u8()? {
+        1 => Groove::Swing(r.u8()?),
+        2 => Groove::Ticks(r.list(|r| r.u8())?),
+        _ => Groove::Straight,
+    })
+}
+
+fn write_offset(w: &mut Writer, offset: TrackOffset) {
+    match offset {
+        TrackOffset::Ticks(ticks) => {
+            w.u8(0);
+            w.i16(ticks as i16);
+        }
+        TrackOffset::Millis(ms) => {
+            w.u8(1);
+            w.i16(ms);
+        }
+    }
+}
+
+fn read_offset(r: &mut Reader) -> Result<TrackOffset, FormatError> {
+    let kind = r.u8()?;
+    let amount = r.i16()?;
+    Ok(match kind {
+        1 => TrackOffset::Millis(amount),
+        _ => TrackOffset::Ticks(amount.clamp(i8::MIN as i16, i8::MAX as i16) as i8),
+    })
+}
+
+fn write_pattern(w: &mut Writer, p: &Pattern) {
+    w.u16(p.rows);
+    w.u8(p.channels);
+    w.u8(p.ticks_per_row);
+    w.opt_u8(p.rows_per_beat);
+    w.list(&p.data, |w, cell| {
+        let (note, n) = match cell.note {
+            Note::None => (0, 0),
+            Note::On(n) => (1, n),
+            Note::Off => (2, 0),
+            Note::Fade => (3, 0),
+        };
+        w.bytes(&[note, n, cell.instrument]);
+        w.bytes(&encode_volume(cell.volume));
+        w.bytes(&encode_effect(cell.effect));
+    });
+}
+
+fn read_pattern(r: &mut Reader) -> Result<Pattern, FormatError> {
+    let mut p = Pattern::new(r.u16()?, r.u8()?);
+    p.ticks_per_row = r.u8()?;
+    p.rows_per_beat = r.opt_u8()?;
+    p.data = r.list(|r| {
+        let [note, n, instrument] = r.array()?;
+        let note = match note {
+            1 => Note::On(n),
+            2 => Note::Off,
+            3 => Note::Fade,
+            _ => Note::None,
+        };
+        Ok(Cell { note, instrument, volume: decode_volume(r.array()?), effect: decode_effect(r.array()?), ..Cell::empty() })
+    })?;
+    if p.data.len() != p.rows as usize * p.channels as usize {
+        return Err(FormatError::InvalidHeader);
+    }
+    Ok(p)
+}
+
+fn encode_volume(v: VolumeCommand) -> [u8; 2] {
+    match v {
+        VolumeCommand::None => [0, 0],
+        VolumeCommand::Volume(x) => [1, x],
+        VolumeCommand::VolumeSlideDown(x) => [2, x],
+        VolumeCommand::VolumeSlideUp(x) => [3, x],
+        VolumeCommand::FineVolSlideDown(x) => [4, x],
+        VolumeCommand::FineVolSlideUp(x) => [5, x],
+        VolumeCommand::Panning(x) => [6, x],
+        VolumeCommand::PortaDown(x) => [7, x],
+        VolumeCommand::PortaUp(x) => [8, x],
+        VolumeCommand::TonePorta(x) => [9, x],
+        VolumeCommand::Vibrato(x) => [10, x],
+    }
+}
+
+fn decode_volume([tag, x]: [u8; 2]) -> VolumeCommand {
+    match tag {
+        1 => VolumeCommand::Volume(x),
+        2 => VolumeCommand::VolumeSlideDown(x),
+        3 => VolumeCommand::VolumeSlideUp(x),
+        4 => VolumeCommand::FineVolSlideDown(x),
+        5 => VolumeCommand::FineVolSlideUp(x),
+        6 => VolumeCommand::Panning(x),
+        7 => VolumeCommand::PortaDown(x),
+        8 => VolumeCommand::PortaUp(x),
+        9 => VolumeCommand::TonePorta(x),
+        10 => VolumeCommand::Vibrato(x),
+        _ => VolumeCommand::None,
+    }
+}
+
+/// Effects as (tag, a, b). Tags are part of the file format: append new
+/// variants, never renumber.
+fn encode_effect(e: Effect) -> [u8; 3] {
+    match e {
+        Effect::None => [0, 0, 0],
+        Effect::Arpeggio { x, y } => [1, x, y],
+        Effect::PortaUp(v) => [2, v, 0],
+        Effect::PortaDown(v) => [3, v, 0],
+        Effect::TonePorta(v) => [4, v, 0],
+        Effect::Vibrato { speed, depth } => [5, speed, depth],
+        Effect::TonePortaVolSlide(v) => [6, v as u8, 0],
+        Effect::VibratoVolSlide(v) => [7, v as u8, 0],
+        Effect::Tremolo { speed, depth } => [8, speed, depth],
+        Effect::SetPan(v) => [9, v, 0],
+        Effect::SampleOffset(v) => [10, v, 0],
+        Effect::FractionalSampleOffset(v) => [11, v, 0],
+        Effect::VolumeSlide(v) => [12, v as u8, 0],
+        Effect::PositionJump(v) => [13, v, 0],
+        Effect::SetVolume(v) => [14, v, 0],
+        Effect::PatternBreak(v) => [15, v, 0],
+        Effect::FinePortaUp(v) => [16, v, 0],
+        Effect::FinePortaDown(v) => [17, v, 0],
+        Effect::SetVibratoWaveform(v) => [18, v, 0],
+        Effect::SetFinetune(v) => [19, v as u8, 0],
+        Effect::PatternLoop(v) => [20, v, 0],
+        Effect::SetTremoloWaveform(v) => [21, v, 0],
+        Effect::SetPanPosition(v) => [22, v, 0],
+        Effect::RetriggerNote(v) => [23, v, 0],
+        Effect::FineVolumeSlideUp(v) => [24, v, 0],
+        Effect::FineVolumeSlideDown(v) => [25, v, 0],
+        Effect::NoteCut(v) => [26, v, 0],
+        Effect::NoteDelay(v) => [27, v, 0],
+        Effect::PatternDelay(v) => [28, v, 0],
+        Effect::SetSpeed(v) => [29, v, 0],
+        Effect::SetTempo(v) => [30, v, 0],
+        Effect::SetGlobalVolume(v) => [31, v, 0],
+        Effect::GlobalVolumeSlide(v) => [32, v as u8, 0],
+        Effect::SetEnvelopePosition(v) => [33, v, 0],
+        Effect::PanningSlide(v) => [34, v as u8, 0],
+        Effect::Retrigger { interval, volume_change } => [35, interval, volume_change as u8],
+        Effect::Tremor { on, off } => [36, on, off],
+        Effect::SetFilterCutoff(v) => [37, v, 0],
+        Effect::SetFilterResonance(v) => [38, v, 0],
+        Effect::ExtraFinePortaUp(v) => [39, v, 0],
+        Effect::ExtraFinePortaDown(v) => [40, v, 0],
+        Effect::SetGlissando(v) => [41, v, 0],
+        Effect::FunkRepeat(v) => [42, v, 0],
+        Effect::SetHighOffset(v) => [43, v, 0],
+    }
+}
+
+fn decode_effect([tag, a, b]: [u8; 3]) -> Effect {
+    match tag {
+        1 => Effect::Arpeggio { x: a, y: b },
+        2 => Effect::PortaUp(a),
+        3 => Effect::PortaDown(a),
+        4 => Effect::TonePorta(a),
+        5 => Effect::Vibrato { speed: a, depth: b },
+        6 => Effect::TonePortaVolSlide(a as i8),
+        7 => Effect::VibratoVolSlide(a as i8),
+        8 => Effect::Tremolo { speed: a, depth: b },
+        9 => Effect::SetPan(a),
+        10 => Effect::SampleOffset(a),
+        11 => Effect::FractionalSampleOffset(a),
+        12 => Effect::VolumeSlide(a as i8),
+        13 => Effect::PositionJump(a),
+        14 => Effect::SetVolume(a),
+        15 => Effect::PatternBreak(a),
+        16 => Effect::FinePortaUp(a),
+        17 => Effect::FinePortaDown(a),
+        18 => Effect::SetVibratoWaveform(a),
+        19 => Effect::SetFinetune(a as i8),
+        20 => Effect::PatternLoop(a),
+        21 => Effect::SetTremoloWaveform(a),
+        22 => Effect::SetPanPosition(a),
+        23 => Effect::RetriggerNote(a),
+        24 => Effect::FineVolumeSlideUp(a),
+        25 => Effect::FineVolumeSlideDown(a),
+        26 => Effect::NoteCut(a),
+        27 => Effect::NoteDelay(a),
+        28 => Effect::PatternDelay(a),
+        29 => Effect::SetSpeed(a),
+        30 => Effect::SetTempo(a),
+        31 => Effect::SetGlobalVolume(a),
+        32 => Effect::GlobalVolumeSlide(a as i8),
+        33 => Effect::SetEnvelopePosition(a),
+        34 => Effect::PanningSlide(a as i8),
+        35 => Effect::Retrigger { interval: a, volume_change: b as i8 },
+        36 => Effect::Tremor { on: a, off: b },
+        37 => Effect::SetFilterCutoff(a),
+        38 => Effect::SetFilterResonance(a),
+        39 => Effect::ExtraFinePortaUp(a),
+        40 => Effect::ExtraFinePortaDown(a),
+        41 => Effect::SetGlissando(a),
+        42 => Effect::FunkRepeat(a),
+        43 => Effect::SetHighOffset(a),
+        _ => Effect::None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mb_ir::SongTemplate;
+
+    /// A song touching every part of the IR.
+    fn rich_song() -> Song {
+        let mut song = SongTemplate {
+            tracks: 2,
+            clips_per_track: 2,
+            effect_density: 50,
+            graph_depth: 2,
+            ..SongTemplate::default()
+        }
+        .build();
+        song.initial_tempo = 14075;
+        song.global_volume = 40;
+        song.frequency_mode = FrequencyMode::Linear;
+        song.compatibility = CompatibilityMode::FastTracker2;
+        song.tracks[1].muted = true;
+        song.tracks[1].set_name("Drums");
+        song.groove = Groove::Swing(62);
+        song.add_marker(Marker::new(MusicalTime::from_beats(8), "Chorus"));
+        song.add_marker(Marker::new(MusicalTime { beat: 2, sub_beat: 5 }, "Pickup"));
+        song.meter.set(0, Some(TimeSignature::new(6, 8)));
+        song.meter.set(12, Some(TimeSignature::new(5, 4)));
+        song.tempo_envelope = TempoEnvelope::new([
+            TempoPoint { time: MusicalTime::from_beats(4), tempo: 14050, curve: TempoCurve::Exponential },
+            TempoPoint { time: MusicalTime { beat: 8, sub_beat: 77 }, tempo: 9000, curve: TempoCurve::Step },
+        ]);
+        song.tracks[1].groove = Some(Groove::Ticks(alloc::vec![0, 2, 1]));
+        song.tracks[1].offset = TrackOffset::Millis(-12);
+        song.channels[0].muted = true;
+        song.channels[1].solo = true;
+        song.tracks[0].sequence[1].boundary = ClipBoundary::Release;
+        song.tracks[0].sequence[1].start.sub_beat = 123;
+
+        let mut stereo = Sample::new("stereo");
+        stereo.set_data(SampleData::Stereo16(alloc::vec![1, -2, 3], alloc::vec![-4, 5, -6]));
+        stereo.loop_type = LoopType::PingPong;
+        stereo.vibrato = Some(AutoVibrato { speed: 1, depth: 2, sweep: 3, waveform: 1 });
+        song.samples.push(stereo);
+
+        let mut inst = Instrument::new("enveloped");
+        let mut env = Envelope::new();
+        env.add_point(0, 64);
+        env.add_point(20, -10);
+        env.enabled = true;
+        env.sustain_start = Some(1);
+        env.sustain_end = Some(1);
+        inst.panning_envelope = Some(env);
+        inst.new_note_action = NewNoteAction::Fade;
+        inst.duplicate_check = DuplicateCheck::Instrument;
+        inst.fadeout = 512;
+        song.instruments.push(inst);
+
+        let node = &mut song.graph.nodes[1];
+        node.oversample = 4;
+        node.parameters.push(
+            Parameter::new(0, "Mode", 0, 1, 1)
+                .with_display(ParamUnit::Enum(crate::bmx_format::SWITCH_LABELS), ParamCurve::Raw),
+        );
+        node.parameters.push(
+            Parameter::new(1, "Cutoff", 0, 127, 64)
+                .with_display(ParamUnit::Hz, ParamCurve::Exponential { lo: 20.0, hi: 20000.0 }),
+        );
+        node.parameters[1].value = 99;
+        let (send, ret) = song.graph.add_feedback_pair();
+        song.graph.connect(send, ret);
+        song.graph.connections.last_mut().unwrap().gain = -600;
+        song.graph.connections.last_mut().unwrap().pan = -40;
+        song.graph.add_node(NodeType::AudioInput);
+        song.graph.add_node(NodeType::Plugin { path: "/usr/lib/clap/Verb.clap".into(), plugin_id: "com.example.verb".into() });
+        song.graph.add_node(NodeType::MidiOut { port: "USB MIDI 1".into(), channel: 9 });
+
+        let pat = song.tracks[0].clips[0].pattern_mut().unwrap();
+        pat.rows_per_beat = Some(8);
+        pat.ticks_per_row = 3;
+        pat.cell_mut(0, 0).note = Note::Fade;
+        pat.cell_mut(1, 0).effect = Effect::Retrigger { interval: 3, volume_change: -8 };
+        pat.cell_mut(2, 0).volume = VolumeCommand::Panning(12);
+        pat.cell_mut(3, 0).effect2 = Effect::VolumeSlide(-3);
+        pat.wire_lane_mut(send).values[1] = WireValue { gain: Some(-30), pan: None };
+        pat.wire_lane_mut(send).values[2] = WireValue { gain: None, pan: Some(-64) };
+        song
+    }
+
+    /// Compare via Debug output: the IR types don't all implement PartialEq.
+    fn assert_same(a: &Song, b: &Song) {
+        assert_eq!(alloc::format!("{:?}", a), alloc::format!("{:?}", b));
+    }
+
+    #[test]
+    fn round_trips_full_song() {
+        let song = rich_song();
+        let loaded = load_song(&save_song(&song)).unwrap();
+        assert_same(&song, &loaded);
+    }
+
+    #[test]
+    fn round_trips_default_and_tracker_songs() {
+        for song in [Song::default(), Song::with_channels("tracker", 8)] {
+            assert_same(&song, &load_song(&save_song(&song)).unwrap());
+        }
+    }
+
+    #[test]
+    fn every_effect_tag_round_trips() {
+        for tag in 0..=43 {
+            let bytes = [tag, 0x12, 0xF4];
+            let effect = decode_effect(bytes);
+            assert_eq!(decode_effect(encode_effect(effect)), effect);
+            if tag > 0 {
+                assert_ne!(effect, Effect::None, "tag {} unmapped", tag);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_bad_magic_and_future_versions() {
+        let mut data = save_song(&Song::default());
+        data[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(load_song(&data), Err(FormatError::UnsupportedVersion)));
+        data[0] = b'X';
+        assert!(matches!(load_song(&data), Err(FormatError::InvalidHeader)));
+    }
+
+    /// `data` with the chunk `id` cut out, as a build that doesn't write it
+    /// would save.
+    fn without_chunk(mut data: Vec<u8>, id: &[u8; 4]) -> Vec<u8> {
+        let mut pos = 6;
+        while pos < data.len() {
+            let len = u32::from_le_bytes(data[pos + 4..pos + 8].try_into().unwrap()) as usize;
+            if &data[pos..pos + 4] == id {
+                data.drain(pos..pos + 8 + len);
+                break;
+            }
+            pos += 8 + len;
+        }
+        data
+    }
+
+    #[test]
+    fn newer_node_kinds_read_as_machines_without_their_chunk() {
+        let song = rich_song();
+        let loaded = load_song(&without_chunk(save_song(&song), b"NKND")).unwrap();
+        assert_eq!(loaded.graph.nodes.len(), song.graph.nodes.len());
+        let kinds: Vec<_> = loaded.graph.nodes.iter().map(|n| &n.node_type).collect();
+        assert!(kinds.iter().any(|k| matches!(k, NodeType::Machine { machine_name, .. } if machine_name == "Audio Input")));
+        assert!(kinds.iter().any(|k| matches!(k, NodeType::Machine { machine_name, .. } if machine_name == "com.example.verb")));
+        assert!(kinds.iter().any(|k| matches!(k, NodeType::Machine { machine_name, .. } if machine_name == "MIDI Out")));
+        assert!(!kinds.iter().any(|k| matches!(k, NodeType::AudioInput | NodeType::Plugin { .. } | NodeType::MidiOut { .. })));
+    }
+
+    #[test]
+    fn unknown_node_kinds_are_reported() {
+        let mut data = save_song(&Song::default());
+        data.extend_from_slice(b"NKND");
+        data.extend_from_slice(&7u32.to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.push(200);
+        assert!(matches!(load_song(&data), Err(FormatError::UnknownNodeType(200))));
+    }
+
+    #[test]
+    fn long_strings_round_trip() {
+        let path = "/x".repeat(40_000);
+        let mut song = Song::default();
+        song.graph.add_node(NodeType::Plugin { path: path.clone(), plugin_id: "id".into() });
+        let loaded = load_song(&save_song(&song)).unwrap();
+        let plugin = loaded.graph.nodes.last().map(|n| &n.node_type);
+        assert!(matches!(plugin, Some(NodeType::Plugin { path: p, .. }) if *p == path));
+    }
+
+    #[test]
+    fn skips_unknown_chunks_and_reports_truncation() {
+        let mut data = save_song(&rich_song());
+        let mut extra = b"XTRA".to_vec();
+        extra.extend_from_slice(&3u32.to_le_bytes());
+        extra.extend_from_slice(&[1, 2, 3]);
+        data.splice(6..6, extra);
+        assert_same(&rich_song(), &load_song(&data).unwrap());
+
+        data.truncate(data.len() - 10);
+        assert!(matches!(load_song(&data), Err(FormatError::UnexpectedEof)));
+    }
+}
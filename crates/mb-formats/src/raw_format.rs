@@ -0,0 +1,93 @@
+//! Headerless PCM sample import.
+
+use crate::FormatError;
+use mb_ir::{Sample, SampleData, SampleFormat};
+
+/// Layout of headerless PCM for `load_raw`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RawFormat {
+    /// Playback rate of C-4
+    pub sample_rate: u32,
+    /// Channels (interleaved when stereo) and bit depth
+    pub format: SampleFormat,
+    /// Samples are unsigned (offset binary) rather than two's complement
+    pub unsigned: bool,
+    /// 16-bit samples are big-endian
+    pub big_endian: bool,
+}
+
+/// Load raw PCM laid out as `raw` into a Sample.
+///
+/// A trailing partial frame is dropped; data without a single whole frame
+/// is rejected.
+pub fn load_raw(data: &[u8], name: &str, raw: &RawFormat) -> Result<Sample, FormatError> {
+    let (stereo, bytes) = match raw.format {
+        SampleFormat::Mono8 => (false, 1),
+        SampleFormat::Mono16 => (false, 2),
+        SampleFormat::Stereo8 => (true, 1),
+        SampleFormat::Stereo16 => (true, 2),
+    };
+    let frame = bytes * (stereo as usize + 1);
+    if data.len() < frame {
+        return Err(FormatError::UnexpectedEof);
+    }
+    let mut sample = Sample::new(name);
+    sample.set_data(decode_pcm(&data[..data.len() / frame * frame], stereo, bytes, raw.unsigned, raw.big_endian));
+    sample.c4_speed = raw.sample_rate;
+    Ok(sample)
+}
+
+/// Decode interleaved PCM with `bytes` bytes per sample, keeping 8-bit
+/// audio as 8-bit and reducing anything deeper to its top 16 bits.
+pub(crate) fn decode_pcm(raw: &[u8], stereo: bool, bytes: usize, unsigned: bool, big_endian: bool) -> SampleData {
+    let channels = stereo as usize + 1;
+    let frames = raw.len() / (bytes * channels);
+    if bytes == 1 {
+        let value = |b: u8| (if unsigned { b ^ 0x80 } else { b }) as i8;
+        let channel = |c: usize| (0..frames).map(|f| value(raw[f * channels + c])).collect::<Vec<_>>();
+        return if stereo { SampleData::Stereo8(channel(0), channel(1)) } else { SampleData::Mono8(channel(0)) };
+    }
+    let value = |at: usize| {
+        let s = &raw[at..at + bytes];
+        let top = if big_endian { [s[0], s[1]] } else { [s[bytes - 1], s[bytes - 2]] };
+        let v = u16::from_be_bytes(top);
+        (if unsigned { v ^ 0x8000 } else { v }) as i16
+    };
+    let channel = |c: usize| (0..frames).map(|f| value((f * channels + c) * bytes)).collect::<Vec<_>>();
+    if stereo { SampleData::Stereo16(channel(0), channel(1)) } else { SampleData::Mono16(channel(0)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(format: SampleFormat, unsigned: bool, big_endian: bool) -> RawFormat {
+        RawFormat { sample_rate: 16000, format, unsigned, big_endian }
+    }
+
+    #[test]
+    fn signed_and_unsigned_8bit() {
+        let sample = load_raw(&[0x00, 0x7F, 0x80, 0xFF], "s8", &raw(SampleFormat::Mono8, false, false)).unwrap();
+        assert_eq!(*sample.data, SampleData::Mono8(vec![0, 127, -128, -1]));
+        assert_eq!(sample.c4_speed, 16000);
+        let sample = load_raw(&[0x00, 0x7F, 0x80, 0xFF], "u8", &raw(SampleFormat::Mono8, true, false)).unwrap();
+        assert_eq!(*sample.data, SampleData::Mono8(vec![-128, -1, 0, 127]));
+    }
+
+    #[test]
+    fn sixteen_bit_byte_orders_and_stereo() {
+        let bytes = [0x01, 0x02, 0xFF, 0xFE, 0x10, 0x00];
+        let sample = load_raw(&bytes, "le", &raw(SampleFormat::Mono16, false, false)).unwrap();
+        assert_eq!(*sample.data, SampleData::Mono16(vec![0x0201, -257, 0x0010]));
+        let sample = load_raw(&bytes, "be", &raw(SampleFormat::Mono16, false, true)).unwrap();
+        assert_eq!(*sample.data, SampleData::Mono16(vec![0x0102, -2, 0x1000]));
+        // The odd third sample pair is a partial frame
+        let sample = load_raw(&bytes, "stereo", &raw(SampleFormat::Stereo16, false, true)).unwrap();
+        assert_eq!(*sample.data, SampleData::Stereo16(vec![0x0102], vec![-2]));
+    }
+
+    #[test]
+    fn too_short_rejected() {
+        assert!(load_raw(&[1, 2, 3], "short", &raw(SampleFormat::Stereo16, false, false)).is_err());
+    }
+}
@@ -0,0 +1,452 @@
+//! Scream Tracker 3 S3M format parser.
+//!
+//! S3M uses the same letter commands as IT (Axx speed, Txx tempo, Sxy
+//! extended), so effects go through the shared IT effect parser with a few
+//! S3M-specific parameter ranges fixed up. AdLib instruments have no IR
+//! equivalent and load as empty samples so instrument numbers stay aligned.
+
+use alloc::vec::Vec;
+use mb_ir::{
+    build_tracks, Cell, ChannelSettings, CompatibilityMode, Effect, Instrument, LoopType, Note, OrderEntry, Pattern,
+    Sample, SampleData, Song, VolumeCommand,
+};
+
+use crate::effect_parser::parse_it_effect;
+use crate::it_format::{bytes_at, u16_at, u8_at};
+use crate::FormatError;
+
+/// Rows per S3M pattern.
+const ROWS: u16 = 64;
+
+/// Master volume flag: stereo playback.
+const STEREO: u8 = 0x80;
+
+/// Default-pan marker: per-channel pan table follows the parapointers.
+const DEFAULT_PAN_PRESENT: u8 = 0xFC;
+
+/// Sample flags.
+const SMP_LOOP: u8 = 0x01;
+const SMP_STEREO: u8 = 0x02;
+const SMP_16BIT: u8 = 0x04;
+
+/// Load an S3M file from bytes.
+pub fn load_s3m(data: &[u8]) -> Result<Song, FormatError> {
+    if data.len() < 0x60 {
+        return Err(FormatError::UnexpectedEof);
+    }
+    if &data[0x2C..0x30] != b"SCRM" {
+        return Err(FormatError::InvalidHeader);
+    }
+
+    let title = parse_string(&data[0..28]);
+    let ord_num = u16_at(data, 0x20)? as usize;
+    let ins_num = u16_at(data, 0x22)? as usize;
+    let pat_num = u16_at(data, 0x24)? as usize;
+    let unsigned_samples = u16_at(data, 0x2A)? == 2;
+    let master_volume = data[0x33];
+    let channel_types = &data[0x40..0x60];
+
+    let orders = bytes_at(data, 0x60, ord_num)?;
+    let ins_table = 0x60 + ord_num;
+    let pat_table = ins_table + ins_num * 2;
+    let pan_table = pat_table + pat_num * 2;
+    let parapointer = |table: usize, i: usize| u16_at(data, table + i * 2).map(|p| p as usize * 16);
+
+    let mut parsed = Vec::with_capacity(pat_num);
+    for i in 0..pat_num {
+        let pattern = parapointer(pat_table, i).and_then(|offset| parse_pattern(data, offset));
+        parsed.push(pattern.unwrap_or_else(|e| {
+            eprintln!("[S3M] WARNING: pattern {} unreadable ({:?}), using an empty pattern", i, e);
+            Vec::new()
+        }));
+    }
+    let num_channels = parsed
+        .iter()
+        .flat_map(|cells| cells.iter().map(|&(_, ch, _)| ch + 1))
+        .max()
+        .unwrap_or(1);
+
+    let mut song = Song::with_channels(&title, num_channels);
+    song.rows_per_beat = 4;
+    // Impulse Tracker plays S3Ms with Scream Tracker's shared effect memory
+    song.compatibility = CompatibilityMode::ImpulseTracker;
+    song.global_volume = data[0x30].min(64);
+    song.initial_speed = data[0x31].max(1);
+    song.initial_tempo = data[0x32].max(32) as u32 * 100;
+    let pans = (data[0x35] == DEFAULT_PAN_PRESENT).then(|| data.get(pan_table..pan_table + 32)).flatten();
+    for (ch, settings) in song.channels.iter_mut().enumerate() {
+        let pan = pans.map_or(0, |p| p[ch]);
+        *settings = parse_channel_settings(channel_types[ch], pan, master_volume & STEREO != 0);
+    }
+
+    for i in 0..ins_num {
+        let sample = parapointer(ins_table, i).and_then(|offset| parse_sample(data, offset, unsigned_samples));
+        let sample = sample.unwrap_or_else(|e| {
+            eprintln!("[S3M] WARNING: instrument {} unreadable ({:?}), leaving it empty", i + 1, e);
+            Sample::new("")
+        });
+        let mut inst = Instrument::new(&sample.name);
+        inst.set_single_sample(i as u8);
+        song.instruments.push(inst);
+        song.samples.push(sample);
+    }
+
+    let patterns: Vec<Pattern> = parsed
+        .iter()
+        .map(|cells| {
+            let mut pattern = Pattern::new(ROWS, num_channels);
+            for &(row, ch, cell) in cells {
+                *pattern.cell_mut(row, ch) = cell;
+            }
+            pattern
+        })
+        .collect();
+    let order: Vec<OrderEntry> = orders
+        .iter()
+        .map(|&o| match o {
+            255 => OrderEntry::End,
+            254 => OrderEntry::Skip,
+            p if (p as usize) < patterns.len() => OrderEntry::Pattern(p),
+            p => {
+                eprintln!("[S3M] WARNING: order references missing pattern {}, skipping", p);
+                OrderEntry::Skip
+            }
+        })
+        .collect();
+    build_tracks(&mut song, &patterns, &order);
+
+    Ok(song)
+}
+
+/// Parse a fixed-size, null-padded string.
+fn parse_string(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).trim().to_string()
+}
+
+/// Channel type (0-7 left PCM, 8-15 right PCM, 16+ AdLib, bit 7 disabled)
+/// and default-pan byte (bit 5 set: low nibble is the pan, 0-15).
+///
+/// Without an explicit pan, stereo songs put left channels at 3 and right
+/// channels at 12, as Scream Tracker does; mono songs are centered.
+fn parse_channel_settings(channel_type: u8, pan: u8, stereo: bool) -> ChannelSettings {
+    let kind = channel_type & 0x7F;
+    let nibble = if pan & 0x20 != 0 {
+        Some(pan & 0x0F)
+    } else if !stereo {
+        None
+    } else if kind < 8 {
+        Some(3)
+    } else {
+        Some(12)
+    };
+    let initial_pan = nibble.map_or(0, |n| (n as i16 * 128 / 15 - 64) as i8);
+    // AdLib channels can't be played; keep them silent
+    let muted = channel_type & 0x80 != 0 || kind >= 16;
+    ChannelSettings { initial_pan, initial_vol: 64, muted, solo: false }
+}
+
+/// Decode a packed pattern: per row, (what, [note, instrument], [volume],
+/// [command, info]) entries terminated by a zero byte. A truncated pattern
+/// keeps the cells decoded before the data ran out.
+fn parse_pattern(data: &[u8], offset: usize) -> Result<Vec<(u16, u8, Cell)>, FormatError> {
+    let mut cells = Vec::new();
+    if offset == 0 {
+        return Ok(cells);
+    }
+    let len = (u16_at(data, offset)? as usize).saturating_sub(2);
+    let packed = data.get(offset + 2..).map(|d| &d[..len.min(d.len())]).unwrap_or(&[]);
+    if decode_rows(packed, &mut cells).is_err() {
+        eprintln!("[S3M] WARNING: pattern data truncated, padding with empty rows");
+    }
+    Ok(cells)
+}
+
+fn decode_rows(packed: &[u8], cells: &mut Vec<(u16, u8, Cell)>) -> Result<(), FormatError> {
+    let mut pos = 0;
+    let mut next = || -> Result<u8, FormatError> {
+        let b = u8_at(packed, pos)?;
+        pos += 1;
+        Ok(b)
+    };
+
+    for row in 0..ROWS {
+        loop {
+            let what = next()?;
+            if what == 0 {
+                break;
+            }
+            let ch = what & 31;
+            let mut cell = Cell::empty();
+            if what & 0x20 != 0 {
+                let note = next()?;
+                cell.note = parse_note(note);
+                cell.instrument = next()?;
+                if note == 254 {
+                    cell.effect = Effect::NoteCut(0);
+                }
+            }
+            if what & 0x40 != 0 {
+                cell.volume = VolumeCommand::Volume(next()?.min(64));
+            }
+            if what & 0x80 != 0 {
+                let command = next()?;
+                let info = next()?;
+                let effect = parse_s3m_effect(command, info);
+                if effect != Effect::None {
+                    cell.effect = effect;
+                }
+            }
+            cells.push((row, ch, cell));
+        }
+    }
+    Ok(())
+}
+
+/// Note byte: high nibble octave, low nibble semitone; 255 empty, 254 cut.
+///
+/// Scream Tracker's C-4 plays a sample at its C2Spd, matching the engine's
+/// reference note 48, so notes map directly.
+fn parse_note(note: u8) -> Note {
+    match note {
+        254 | 255 => Note::None,
+        n if n & 0x0F < 12 => Note::On((n >> 4) * 12 + (n & 0x0F)),
+        _ => Note::None,
+    }
+}
+
+/// S3M letter command. Shares IT's encoding apart from a decimal pattern
+/// break, half-range global volume and panning, and SAx (stereo control
+/// in S3M rather than IT's high sample offset).
+fn parse_s3m_effect(command: u8, info: u8) -> Effect {
+    match parse_it_effect(command, info) {
+        Effect::SetHighOffset(_) => Effect::None,
+        Effect::PatternBreak(_) => Effect::PatternBreak(((info >> 4) * 10 + (info & 0x0F)).min(63)),
+        Effect::SetGlobalVolume(v) => Effect::SetGlobalVolume(v.min(64) * 2),
+        Effect::SetPan(p) => Effect::SetPan(p.min(0x80).saturating_mul(2)),
+        effect => effect,
+    }
+}
+
+/// Parse an instrument header and its sample data. AdLib instruments load
+/// as empty samples.
+fn parse_sample(data: &[u8], offset: usize, unsigned: bool) -> Result<Sample, FormatError> {
+    let hdr = bytes_at(data, offset, 0x50)?;
+    let mut sample = Sample::new(&parse_string(&hdr[0x30..0x4C]));
+    if hdr[0] != 1 {
+        if hdr[0] >= 2 {
+            eprintln!("[S3M] WARNING: skipping AdLib instrument \"{}\"", sample.name);
+        }
+        return Ok(sample);
+    }
+    if &hdr[0x4C..0x50] != b"SCRS" {
+        return Err(FormatError::InvalidHeader);
+    }
+
+    let field = |o: usize| u32::from_le_bytes([hdr[o], hdr[o + 1], hdr[o + 2], hdr[o + 3]]);
+    let pointer = ((hdr[0x0D] as usize) << 16 | u16::from_le_bytes([hdr[0x0E], hdr[0x0F]]) as usize) * 16;
+    let length = field(0x10) as usize;
+    let flags = hdr[0x1F];
+    sample.default_volume = hdr[0x1C].min(64);
+    sample.c4_speed = field(0x20);
+    if hdr[0x1E] != 0 {
+        eprintln!("[S3M] WARNING: packed sample \"{}\" not supported", sample.name);
+        return Ok(sample);
+    }
+
+    let is_16bit = flags & SMP_16BIT != 0;
+    let channels = if flags & SMP_STEREO != 0 { 2 } else { 1 };
+    let width = if is_16bit { 2 } else { 1 };
+    let src = data.get(pointer..).unwrap_or(&[]);
+    if src.len() < length.saturating_mul(width * channels) {
+        eprintln!("[S3M] WARNING: sample \"{}\" truncated, padding with silence", sample.name);
+    }
+    // Pad no further than the bytes left could hold
+    let length = length.min(src.len().div_ceil(width));
+
+    let (loop_start, loop_end) = (field(0x14), field(0x18).min(length as u32));
+    if flags & SMP_LOOP != 0 && loop_start < loop_end {
+        sample.loop_start = loop_start;
+        sample.loop_end = loop_end;
+        sample.loop_type = LoopType::Forward;
+    }
+    let channel = |c: usize| -> Vec<i16> {
+        let start = (c * length * width).min(src.len());
+        let raw = &src[start..(start + length * width).min(src.len())];
+        let mut out: Vec<i16> = if is_16bit {
+            raw.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect()
+        } else {
+            raw.iter().map(|&b| b as i8 as i16).collect()
+        };
+        if unsigned {
+            let flip = if is_16bit { i16::MIN } else { -128 };
+            out.iter_mut().for_each(|s| *s ^= flip);
+        }
+        out.resize(length, 0);
+        out
+    };
+    let narrow = |v: Vec<i16>| v.into_iter().map(|s| s as i8).collect();
+    sample.set_data(match (channels, is_16bit) {
+        (1, false) => SampleData::Mono8(narrow(channel(0))),
+        (1, true) => SampleData::Mono16(channel(0)),
+        (_, false) => SampleData::Stereo8(narrow(channel(0)), narrow(channel(1))),
+        (_, true) => SampleData::Stereo16(channel(0), channel(1)),
+    });
+    Ok(sample)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pad to the next 16-byte boundary and return the parapointer.
+    fn align(data: &mut Vec<u8>) -> u16 {
+        data.resize(data.len().div_ceil(16) * 16, 0);
+        (data.len() / 16) as u16
+    }
+
+    /// SCRM file with one PCM instrument (or AdLib if `adlib`), one pattern
+    /// and a default-pan table.
+    fn s3m(pattern: &[u8], adlib: bool) -> Vec<u8> {
+        let mut data = alloc::vec![0u8; 0x60];
+        data[0..4].copy_from_slice(b"Test");
+        data[0x1C] = 0x1A;
+        data[0x1D] = 16;
+        data[0x20..0x22].copy_from_slice(&2u16.to_le_bytes()); // orders
+        data[0x22..0x24].copy_from_slice(&2u16.to_le_bytes()); // instruments
+        data[0x24..0x26].copy_from_slice(&1u16.to_le_bytes()); // patterns
+        data[0x2A..0x2C].copy_from_slice(&2u16.to_le_bytes()); // unsigned samples
+        data[0x2C..0x30].copy_from_slice(b"SCRM");
+        data[0x30] = 64;
+        data[0x31] = 4;
+        data[0x32] = 150;
+        data[0x33] = 0x80 | 48;
+        data[0x35] = DEFAULT_PAN_PRESENT;
+        data[0x40..0x60].fill(0xFF);
+        data[0x40] = 0;
+        data[0x41] = 8;
+        data[0x42] = 16; // AdLib
+        data.extend_from_slice(&[0, 255]); // orders: pattern 0, end
+        let tables = data.len();
+        data.resize(tables + 6, 0);
+        let mut pans = [0u8; 32];
+        pans[0] = 0x20 | 7;
+        data.extend_from_slice(&pans);
+
+        let pcm = align(&mut data);
+        let mut ins = alloc::vec![0u8; 0x50];
+        ins[0] = 1;
+        ins[0x10..0x14].copy_from_slice(&4u32.to_le_bytes());
+        ins[0x14..0x18].copy_from_slice(&1u32.to_le_bytes());
+        ins[0x18..0x1C].copy_from_slice(&3u32.to_le_bytes());
+        ins[0x1C] = 40;
+        ins[0x1F] = SMP_LOOP;
+        ins[0x20..0x24].copy_from_slice(&8363u32.to_le_bytes());
+        ins[0x30..0x34].copy_from_slice(b"Kick");
+        ins[0x4C..0x50].copy_from_slice(b"SCRS");
+        data.extend_from_slice(&ins);
+        let pcm_data = align(&mut data);
+        data.extend_from_slice(&[0x80, 0x90, 0x70, 0x80]);
+        let memseg = (pcm_data as u32).to_le_bytes();
+        data[pcm as usize * 16 + 0x0D] = memseg[2];
+        data[pcm as usize * 16 + 0x0E..pcm as usize * 16 + 0x10].copy_from_slice(&memseg[0..2]);
+
+        let second = align(&mut data);
+        let mut ins = alloc::vec![0u8; 0x50];
+        ins[0] = if adlib { 2 } else { 0 };
+        ins[0x30..0x35].copy_from_slice(b"Organ");
+        ins[0x4C..0x50].copy_from_slice(b"SCRI");
+        data.extend_from_slice(&ins);
+
+        let pat = align(&mut data);
+        data.extend_from_slice(&(pattern.len() as u16 + 2).to_le_bytes());
+        data.extend_from_slice(pattern);
+
+        data[tables..tables + 2].copy_from_slice(&pcm.to_le_bytes());
+        data[tables + 2..tables + 4].copy_from_slice(&second.to_le_bytes());
+        data[tables + 4..tables + 6].copy_from_slice(&pat.to_le_bytes());
+        data
+    }
+
+    /// Row 0, channel 1: C-5, instrument 1, volume 50, A03 (speed 3).
+    /// Row 1, channel 0: note cut, T7D (tempo 125). Row 2: C12 (break to 12).
+    const PATTERN: [u8; 17] = [
+        0xE1, 0x50, 1, 50, 1, 3, 0,
+        0xA0, 254, 0, 20, 0x7D, 0,
+        0x80, 3, 0x12, 0,
+    ];
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut data = s3m(&PATTERN, false);
+        data[0x2C] = b'X';
+        assert!(matches!(load_s3m(&data), Err(FormatError::InvalidHeader)));
+    }
+
+    #[test]
+    fn loads_header_orders_and_channel_pan() {
+        let song = load_s3m(&s3m(&PATTERN, false)).unwrap();
+        assert_eq!(song.title.as_str(), "Test");
+        assert_eq!((song.initial_speed, song.initial_tempo, song.global_volume), (4, 15000, 64));
+        assert_eq!(song.channels.len(), 2);
+        // Explicit pan 7 on channel 0; channel 1 falls back to the right side
+        assert_eq!(song.channels[0].initial_pan, -5);
+        assert_eq!(song.channels[1].initial_pan, 38);
+        assert_eq!(song.tracks[0].sequence.len(), 1);
+    }
+
+    #[test]
+    fn decodes_packed_pattern_and_speed_tempo() {
+        let song = load_s3m(&s3m(&PATTERN, false)).unwrap();
+        let pat = song.tracks[0].clips[0].pattern().unwrap();
+        let cell = pat.cell(0, 1);
+        assert_eq!(cell.note, Note::On(60));
+        assert_eq!(cell.instrument, 1);
+        assert_eq!(cell.volume, VolumeCommand::Volume(50));
+        assert_eq!(cell.effect, Effect::SetSpeed(3));
+        assert_eq!(pat.cell(1, 0).effect, Effect::SetTempo(125));
+        assert_eq!(pat.cell(1, 0).note, Note::None);
+        assert_eq!(pat.cell(2, 0).effect, Effect::PatternBreak(12));
+    }
+
+    #[test]
+    fn note_cut_without_effect_becomes_cut() {
+        let song = load_s3m(&s3m(&[0x20, 254, 0, 0], false)).unwrap();
+        let pat = song.tracks[0].clips[0].pattern().unwrap();
+        assert_eq!(pat.cell(0, 0).effect, Effect::NoteCut(0));
+    }
+
+    #[test]
+    fn loads_unsigned_pcm_and_skips_adlib() {
+        let song = load_s3m(&s3m(&PATTERN, true)).unwrap();
+        assert_eq!(song.samples.len(), 2);
+        assert_eq!(song.instruments.len(), 2);
+        let kick = &song.samples[0];
+        assert_eq!(kick.name.as_str(), "Kick");
+        assert_eq!(kick.default_volume, 40);
+        assert_eq!((kick.loop_start, kick.loop_end, kick.loop_type), (1, 3, LoopType::Forward));
+        assert_eq!(kick.data.get_mono(1), 0x10 * 256);
+        assert_eq!(kick.data.get_mono(2), -0x10 * 256);
+        let organ = &song.samples[1];
+        assert_eq!(organ.name.as_str(), "Organ");
+        assert!(organ.is_empty());
+    }
+
+    #[test]
+    fn sample_length_is_bounded_by_the_data_left() {
+        let mut data = s3m(&PATTERN, false);
+        let kick = data.windows(4).position(|w| w == b"Kick").unwrap() - 0x30;
+        data[kick + 0x10..kick + 0x14].copy_from_slice(&u32::MAX.to_le_bytes());
+        let song = load_s3m(&data).unwrap();
+        assert!(song.samples[0].data.len() < data.len());
+        assert_eq!(song.samples[0].data.get_mono(1), 0x10 * 256);
+    }
+
+    #[test]
+    fn truncated_pattern_keeps_decoded_rows() {
+        let song = load_s3m(&s3m(&PATTERN[..9], false)).unwrap();
+        let pat = song.tracks[0].clips[0].pattern().unwrap();
+        assert_eq!(pat.cell(0, 1).effect, Effect::SetSpeed(3));
+        assert_eq!(pat.rows, ROWS);
+    }
+}
@@ -1,27 +1,69 @@
 //! WAV encoding and decoding for PCM audio.
 
 use crate::FormatError;
-use mb_ir::{Sample, SampleData};
-use std::io::Write;
+use mb_ir::{LoopType, Sample, SampleData};
+use std::io::{Seek, SeekFrom, Write};
 
 // --- Writing ---
 
-/// Write stereo f32 frames as 16-bit PCM WAV.
-pub fn write_wav(w: &mut impl Write, frames: &[[f32; 2]], sample_rate: u32) -> std::io::Result<()> {
-    let num_channels: u16 = 2;
-    let bits_per_sample: u16 = 16;
-    let block_align = num_channels * (bits_per_sample / 8);
-    let data_size = frames.len() as u32 * block_align as u32;
+/// Sample format of WAV output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WavDepth {
+    /// 16-bit integer PCM
+    #[default]
+    Int16,
+    /// 24-bit integer PCM
+    Int24,
+    /// 32-bit IEEE float, unclipped
+    Float32,
+}
+
+impl WavDepth {
+    fn bits(self) -> u16 {
+        match self {
+            Self::Int16 => 16,
+            Self::Int24 => 24,
+            Self::Float32 => 32,
+        }
+    }
+}
+
+/// How WAV output is encoded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WavSpec {
+    pub depth: WavDepth,
+    /// Add triangular dither before rounding to 16 bits, so quiet passages
+    /// fade into noise instead of distortion (ignored for other depths)
+    pub dither: bool,
+}
+
+impl WavSpec {
+    /// Undithered output at `depth`.
+    pub fn new(depth: WavDepth) -> Self {
+        Self { depth, dither: false }
+    }
+
+    /// Bytes per stereo frame.
+    fn block_align(self) -> u16 {
+        2 * self.depth.bits() / 8
+    }
+}
+
+/// Write stereo f32 frames as a WAV file encoded per `spec`.
+pub fn write_wav(w: &mut impl Write, frames: &[[f32; 2]], sample_rate: u32, spec: WavSpec) -> std::io::Result<()> {
+    let data_size = frames.len() as u32 * spec.block_align() as u32;
 
     write_riff_header(w, data_size)?;
-    write_fmt_chunk(w, num_channels, sample_rate, block_align, bits_per_sample)?;
-    write_data_chunk(w, frames, data_size)
+    write_fmt_chunk(w, sample_rate, spec)?;
+    w.write_all(b"data")?;
+    w.write_all(&data_size.to_le_bytes())?;
+    PcmEncoder::new(spec).write(w, frames)
 }
 
 /// Encode stereo f32 frames to a WAV byte buffer.
-pub fn frames_to_wav(frames: &[[f32; 2]], sample_rate: u32) -> Vec<u8> {
+pub fn frames_to_wav(frames: &[[f32; 2]], sample_rate: u32, spec: WavSpec) -> Vec<u8> {
     let mut buf = Vec::new();
-    write_wav(&mut buf, frames, sample_rate).expect("Vec<u8> write cannot fail");
+    write_wav(&mut buf, frames, sample_rate, spec).expect("Vec<u8> write cannot fail");
     buf
 }
 
@@ -30,41 +72,126 @@ fn f32_to_i16(val: f32) -> i16 {
     (val * 32768.0).clamp(-32768.0, 32767.0) as i16
 }
 
+/// Convert a single f32 sample to 24-bit (clamped).
+fn f32_to_i24(val: f32) -> i32 {
+    (val * 8_388_608.0).clamp(-8_388_608.0, 8_388_607.0) as i32
+}
+
 fn write_riff_header(w: &mut impl Write, data_size: u32) -> std::io::Result<()> {
     w.write_all(b"RIFF")?;
     w.write_all(&(36 + data_size).to_le_bytes())?;
     w.write_all(b"WAVE")
 }
 
-fn write_fmt_chunk(
-    w: &mut impl Write,
-    num_channels: u16,
-    sample_rate: u32,
-    block_align: u16,
-    bits_per_sample: u16,
-) -> std::io::Result<()> {
+fn write_fmt_chunk(w: &mut impl Write, sample_rate: u32, spec: WavSpec) -> std::io::Result<()> {
+    let format: u16 = if spec.depth == WavDepth::Float32 { 3 } else { 1 };
+    let block_align = spec.block_align();
     w.write_all(b"fmt ")?;
     w.write_all(&16u32.to_le_bytes())?;
-    w.write_all(&1u16.to_le_bytes())?;
-    w.write_all(&num_channels.to_le_bytes())?;
+    w.write_all(&format.to_le_bytes())?;
+    w.write_all(&2u16.to_le_bytes())?;
     w.write_all(&sample_rate.to_le_bytes())?;
     w.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
     w.write_all(&block_align.to_le_bytes())?;
-    w.write_all(&bits_per_sample.to_le_bytes())
+    w.write_all(&spec.depth.bits().to_le_bytes())
 }
 
-fn write_data_chunk(
-    w: &mut impl Write,
-    frames: &[[f32; 2]],
-    data_size: u32,
-) -> std::io::Result<()> {
-    w.write_all(b"data")?;
-    w.write_all(&data_size.to_le_bytes())?;
-    for frame in frames {
-        w.write_all(&f32_to_i16(frame[0]).to_le_bytes())?;
-        w.write_all(&f32_to_i16(frame[1]).to_le_bytes())?;
+/// Converts frames to sample bytes, carrying dither state across calls.
+struct PcmEncoder {
+    spec: WavSpec,
+    /// xorshift32 state for dither noise
+    noise: u32,
+}
+
+impl PcmEncoder {
+    fn new(spec: WavSpec) -> Self {
+        Self { spec, noise: 0x9E37_79B9 }
+    }
+
+    fn write(&mut self, w: &mut impl Write, frames: &[[f32; 2]]) -> std::io::Result<()> {
+        for &val in frames.iter().flatten() {
+            match self.spec.depth {
+                WavDepth::Int16 if self.spec.dither => w.write_all(&self.dithered(val).to_le_bytes())?,
+                WavDepth::Int16 => w.write_all(&f32_to_i16(val).to_le_bytes())?,
+                WavDepth::Int24 => w.write_all(&f32_to_i24(val).to_le_bytes()[..3])?,
+                WavDepth::Float32 => w.write_all(&val.to_le_bytes())?,
+            }
+        }
+        Ok(())
+    }
+
+    /// `val` as i16 with triangular (TPDF) dither of +-1 LSB.
+    fn dithered(&mut self, val: f32) -> i16 {
+        let tpdf = self.uniform() - self.uniform();
+        (val * 32768.0 + tpdf).round().clamp(-32768.0, 32767.0) as i16
+    }
+
+    /// Uniform noise in 0..1.
+    fn uniform(&mut self) -> f32 {
+        self.noise ^= self.noise << 13;
+        self.noise ^= self.noise >> 17;
+        self.noise ^= self.noise << 5;
+        (self.noise >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+/// Placeholder data size for streams of unknown length (RIFF size becomes 0xFFFFFFFF).
+const STREAM_DATA_SIZE: u32 = u32::MAX - 36;
+
+/// Stereo WAV writer that encodes frames as they arrive.
+///
+/// The header goes out first with placeholder sizes, the usual convention
+/// for streamed WAV; `finish_seekable` patches in the real sizes when the
+/// destination allows it.
+pub struct WavStreamWriter<W: Write> {
+    inner: W,
+    encoder: PcmEncoder,
+    frames: u64,
+}
+
+impl<W: Write> WavStreamWriter<W> {
+    /// Write the header and start a stream at `sample_rate`, encoded per
+    /// `spec`.
+    pub fn new(mut inner: W, sample_rate: u32, spec: WavSpec) -> std::io::Result<Self> {
+        write_riff_header(&mut inner, STREAM_DATA_SIZE)?;
+        write_fmt_chunk(&mut inner, sample_rate, spec)?;
+        inner.write_all(b"data")?;
+        inner.write_all(&STREAM_DATA_SIZE.to_le_bytes())?;
+        Ok(Self { inner, encoder: PcmEncoder::new(spec), frames: 0 })
+    }
+
+    /// Append frames to the data chunk.
+    pub fn write_frames(&mut self, frames: &[[f32; 2]]) -> std::io::Result<()> {
+        self.encoder.write(&mut self.inner, frames)?;
+        self.frames += frames.len() as u64;
+        Ok(())
+    }
+
+    /// Frames written so far.
+    pub fn frames_written(&self) -> u64 {
+        self.frames
+    }
+
+    /// Flush and return the destination, leaving the placeholder sizes.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write + Seek> WavStreamWriter<W> {
+    /// Patch the real RIFF and data sizes into the header, then flush.
+    pub fn finish_seekable(mut self) -> std::io::Result<W> {
+        let block_align = self.encoder.spec.block_align() as u64;
+        let data_size = (self.frames * block_align).min(STREAM_DATA_SIZE as u64) as u32;
+        let end = self.inner.stream_position()?;
+        self.inner.seek(SeekFrom::Start(4))?;
+        self.inner.write_all(&(36 + data_size).to_le_bytes())?;
+        self.inner.seek(SeekFrom::Start(40))?;
+        self.inner.write_all(&data_size.to_le_bytes())?;
+        self.inner.seek(SeekFrom::Start(end))?;
+        self.finish()
     }
-    Ok(())
 }
 
 /// Extract raw interleaved i16 samples from a WAV file.
@@ -89,8 +216,9 @@ pub fn load_wav(data: &[u8], name: &str) -> Result<Sample, FormatError> {
     let sample_data = read_pcm_data(data, &header)?;
 
     let mut sample = Sample::new(name);
-    sample.data = sample_data;
+    sample.set_data(sample_data);
     sample.c4_speed = header.sample_rate;
+    apply_sampler_metadata(&mut sample, data, &header);
     Ok(sample)
 }
 
@@ -100,6 +228,85 @@ struct WavHeader {
     bits_per_sample: u16,
     data_offset: usize,
     data_size: usize,
+    /// Offset and size of the `smpl` and `cue ` chunk bodies
+    smpl: Option<(usize, usize)>,
+    cue: Option<(usize, usize)>,
+    /// Offset and size of every `LIST` chunk body (files often carry an
+    /// `INFO` list beside the `adtl` one)
+    lists: Vec<(usize, usize)>,
+}
+
+/// MIDI note that plays as C-4.
+const MIDI_C4: f64 = 60.0;
+
+/// Take the loop and root key a sampler stored in the file.
+///
+/// The first `smpl` loop wins; without one, the first cue point with an
+/// `adtl` region length (a labelled region) becomes a forward loop. The
+/// MIDI unity note (plus its pitch fraction) sets `c4_speed` so the sample
+/// plays at its recorded pitch on that note.
+fn apply_sampler_metadata(sample: &mut Sample, data: &[u8], header: &WavHeader) {
+    let body = |range: Option<(usize, usize)>| {
+        range.map(|(offset, size)| &data[offset.min(data.len())..(offset + size).min(data.len())])
+    };
+    let smpl = body(header.smpl).filter(|s| s.len() >= 36);
+    if let Some(smpl) = smpl {
+        let unity = read_u32_le(smpl, 12);
+        let fraction = read_u32_le(smpl, 16) as f64 / 4_294_967_296.0;
+        if unity < 128 {
+            let semitones = MIDI_C4 - unity as f64 - fraction;
+            sample.c4_speed = (header.sample_rate as f64 * 2f64.powf(semitones / 12.0)).round() as u32;
+        }
+    }
+
+    let smpl_loop = smpl.filter(|s| read_u32_le(s, 28) > 0 && s.len() >= 60).map(|s| {
+        // Backward loops have no equivalent here and play forward
+        let kind = if read_u32_le(s, 40) == 1 { LoopType::PingPong } else { LoopType::Forward };
+        // smpl loop ends are inclusive
+        (read_u32_le(s, 44), read_u32_le(s, 48).saturating_add(1), kind)
+    });
+    let region = || {
+        let cue = body(header.cue)?;
+        let (start, len) = header.lists.iter()
+            .filter_map(|&list| body(Some(list)))
+            .flat_map(adtl_regions)
+            .find_map(|(id, len)| Some((cue_offset(cue, id)?, len)))?;
+        Some((start, start.saturating_add(len), LoopType::Forward))
+    };
+    if let Some((start, end, kind)) = smpl_loop.or_else(region) {
+        if start < end && end <= sample.len() as u32 {
+            sample.loop_start = start;
+            sample.loop_end = end;
+            sample.loop_type = kind;
+        }
+    }
+}
+
+/// Sample offset of cue point `id` in a `cue ` chunk body.
+fn cue_offset(cue: &[u8], id: u32) -> Option<u32> {
+    let count = read_u32_le(cue.get(..4)?, 0) as usize;
+    cue.get(4..)?
+        .chunks_exact(24)
+        .take(count)
+        .find(|point| read_u32_le(point, 0) == id)
+        .map(|point| read_u32_le(point, 20))
+}
+
+/// `(cue id, length)` of each labelled region (`ltxt`) in a `LIST` chunk
+/// body of type `adtl`.
+fn adtl_regions(list: &[u8]) -> impl Iterator<Item = (u32, u32)> + '_ {
+    let mut pos = if list.get(..4) == Some(b"adtl") { 4 } else { list.len() };
+    core::iter::from_fn(move || {
+        while pos + 8 <= list.len() {
+            let (id, size) = (&list[pos..pos + 4], read_u32_le(list, pos + 4) as usize);
+            let chunk_body = &list[pos + 8..(pos + 8 + size).min(list.len())];
+            pos += 8 + size + size % 2;
+            if id == b"ltxt" && chunk_body.len() >= 8 && read_u32_le(chunk_body, 4) > 0 {
+                return Some((read_u32_le(chunk_body, 0), read_u32_le(chunk_body, 4)));
+            }
+        }
+        None
+    })
 }
 
 fn parse_header(data: &[u8]) -> Result<WavHeader, FormatError> {
@@ -113,6 +320,7 @@ fn parse_header(data: &[u8]) -> Result<WavHeader, FormatError> {
     let mut pos = 12;
     let mut fmt: Option<(u16, u32, u16)> = None;
     let mut data_chunk: Option<(usize, usize)> = None;
+    let (mut smpl, mut cue, mut lists) = (None, None, Vec::new());
 
     while pos + 8 <= data.len() {
         let chunk_id = &data[pos..pos + 4];
@@ -129,6 +337,12 @@ fn parse_header(data: &[u8]) -> Result<WavHeader, FormatError> {
             fmt = Some((channels, rate, bits));
         } else if chunk_id == b"data" {
             data_chunk = Some((pos + 8, chunk_size));
+        } else if chunk_id == b"smpl" {
+            smpl = Some((pos + 8, chunk_size));
+        } else if chunk_id == b"cue " {
+            cue = Some((pos + 8, chunk_size));
+        } else if chunk_id == b"LIST" {
+            lists.push((pos + 8, chunk_size));
         }
 
         pos += 8 + chunk_size;
@@ -145,7 +359,7 @@ fn parse_header(data: &[u8]) -> Result<WavHeader, FormatError> {
         return Err(FormatError::UnsupportedVersion);
     }
 
-    Ok(WavHeader { num_channels, sample_rate, bits_per_sample, data_offset, data_size })
+    Ok(WavHeader { num_channels, sample_rate, bits_per_sample, data_offset, data_size, smpl, cue, lists })
 }
 
 fn read_pcm_data(data: &[u8], header: &WavHeader) -> Result<SampleData, FormatError> {
@@ -240,7 +454,7 @@ mod tests {
         let wav = make_wav(1, 22050, 8, &[128, 255, 0, 192]);
         let sample = load_wav(&wav, "test").unwrap();
         assert_eq!(sample.c4_speed, 22050);
-        match &sample.data {
+        match &*sample.data {
             SampleData::Mono8(data) => {
                 assert_eq!(data, &[0, 127, -128, 64]);
             }
@@ -256,7 +470,7 @@ mod tests {
             .collect();
         let wav = make_wav(1, 44100, 16, &pcm);
         let sample = load_wav(&wav, "test16").unwrap();
-        match &sample.data {
+        match &*sample.data {
             SampleData::Mono16(data) => {
                 assert_eq!(data, &[0, 1000, -1000, 32767]);
             }
@@ -272,7 +486,7 @@ mod tests {
             .collect();
         let wav = make_wav(2, 44100, 16, &pcm);
         let sample = load_wav(&wav, "stereo").unwrap();
-        match &sample.data {
+        match &*sample.data {
             SampleData::Stereo16(l, r) => {
                 assert_eq!(l, &[100, -100]);
                 assert_eq!(r, &[200, -200]);
@@ -281,6 +495,71 @@ mod tests {
         }
     }
 
+    /// Append a chunk to a WAV built by `make_wav`, fixing the RIFF size.
+    fn with_chunk(mut wav: Vec<u8>, id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        wav.extend(id);
+        wav.extend(&(body.len() as u32).to_le_bytes());
+        wav.extend(body);
+        if !body.len().is_multiple_of(2) {
+            wav.push(0);
+        }
+        let riff = (wav.len() - 8) as u32;
+        wav[4..8].copy_from_slice(&riff.to_le_bytes());
+        wav
+    }
+
+    fn words(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    /// A `smpl` body with the given unity note and loops `(type, start, end)`.
+    fn smpl(unity: u32, fraction: u32, loops: &[(u32, u32, u32)]) -> Vec<u8> {
+        let mut body = words(&[0, 0, 22675, unity, fraction, 0, 0, loops.len() as u32, 0]);
+        for &(kind, start, end) in loops {
+            body.extend(words(&[0, kind, start, end, 0, 0]));
+        }
+        body
+    }
+
+    #[test]
+    fn smpl_chunk_sets_loop_and_root_key() {
+        let wav = with_chunk(make_wav(1, 44100, 8, &[128; 100]), b"smpl", &smpl(72, 0, &[(0, 10, 89)]));
+        let sample = load_wav(&wav, "looped").unwrap();
+        assert_eq!((sample.loop_start, sample.loop_end, sample.loop_type), (10, 90, LoopType::Forward));
+        assert_eq!(sample.c4_speed, 22050, "recorded an octave above C-4");
+
+        let wav = with_chunk(make_wav(1, 44100, 8, &[128; 100]), b"smpl", &smpl(59, 1 << 31, &[(1, 0, 49)]));
+        let sample = load_wav(&wav, "pingpong").unwrap();
+        assert_eq!((sample.loop_end, sample.loop_type), (50, LoopType::PingPong));
+        assert_eq!(sample.c4_speed, 45392, "half a semitone below C-4");
+    }
+
+    #[test]
+    fn out_of_range_smpl_loop_is_ignored() {
+        let wav = with_chunk(make_wav(1, 44100, 8, &[128; 100]), b"smpl", &smpl(60, 0, &[(0, 10, 100)]));
+        let sample = load_wav(&wav, "bad loop").unwrap();
+        assert_eq!((sample.loop_type, sample.c4_speed), (LoopType::None, 44100));
+    }
+
+    #[test]
+    fn cue_region_becomes_loop() {
+        let mut cue = words(&[2]);
+        cue.extend(words(&[1, 0]).iter().chain(b"data").chain(&words(&[0, 0, 5])));
+        cue.extend(words(&[7, 0]).iter().chain(b"data").chain(&words(&[0, 0, 20])));
+        let mut list = b"adtl".to_vec();
+        list.extend(b"labl".iter().chain(&words(&[8, 1])).chain(b"Go\0\0"));
+        list.extend(b"ltxt".iter().chain(&words(&[20, 7, 30])).chain(&[0; 12]));
+        let wav = with_chunk(make_wav(1, 44100, 8, &[128; 100]), b"cue ", &cue);
+        let wav = with_chunk(wav, b"LIST", &list);
+        let sample = load_wav(&wav, "region").unwrap();
+        assert_eq!((sample.loop_start, sample.loop_end, sample.loop_type), (20, 50, LoopType::Forward));
+
+        // An INFO list after the adtl one doesn't hide its regions
+        let info: Vec<u8> = b"INFOINAM".iter().chain(&words(&[4])).chain(b"Loop").copied().collect();
+        let sample = load_wav(&with_chunk(wav, b"LIST", &info), "region").unwrap();
+        assert_eq!((sample.loop_start, sample.loop_end), (20, 50));
+    }
+
     #[test]
     fn invalid_header_rejected() {
         assert!(load_wav(b"not a wav", "bad").is_err());
@@ -294,7 +573,7 @@ mod tests {
     #[test]
     fn f32_roundtrip() {
         let frames = [[0.5f32, -0.5], [1.0, -1.0]];
-        let wav = frames_to_wav(&frames, 44100);
+        let wav = frames_to_wav(&frames, 44100, WavSpec::default());
         // Verify it's a valid WAV
         assert_eq!(&wav[0..4], b"RIFF");
         assert_eq!(&wav[8..12], b"WAVE");
@@ -302,4 +581,58 @@ mod tests {
         let data_size = u32::from_le_bytes([wav[40], wav[41], wav[42], wav[43]]);
         assert_eq!(data_size, 8);
     }
+
+    #[test]
+    fn stream_writer_matches_one_shot_encoding() {
+        let frames = [[0.25f32, -0.25], [0.5, -0.5], [1.0, -1.0]];
+        for depth in [WavDepth::Int16, WavDepth::Int24, WavDepth::Float32] {
+            let spec = WavSpec { depth, dither: true };
+            let mut stream = WavStreamWriter::new(std::io::Cursor::new(Vec::new()), 44100, spec).unwrap();
+            stream.write_frames(&frames[..1]).unwrap();
+            stream.write_frames(&frames[1..]).unwrap();
+            assert_eq!(stream.frames_written(), 3);
+            let wav = stream.finish_seekable().unwrap().into_inner();
+            assert_eq!(wav, frames_to_wav(&frames, 44100, spec), "{:?}", depth);
+        }
+    }
+
+    #[test]
+    fn deeper_formats_keep_precision() {
+        let frames = [[0.5f32, -1.0], [1.5, 1.0 / 8_388_608.0]];
+        let wav = frames_to_wav(&frames, 48000, WavSpec::new(WavDepth::Int24));
+        assert_eq!((read_u16_le(&wav, 20), read_u16_le(&wav, 32), read_u16_le(&wav, 34)), (1, 6, 24));
+        assert_eq!(read_u32_le(&wav, 40), 12);
+        let samples: Vec<i32> = wav[44..].chunks(3).map(|b| i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8).collect();
+        assert_eq!(samples, [0x40_0000, -0x80_0000, 0x7F_FFFF, 1]);
+
+        let wav = frames_to_wav(&frames, 48000, WavSpec::new(WavDepth::Float32));
+        assert_eq!((read_u16_le(&wav, 20), read_u16_le(&wav, 32), read_u16_le(&wav, 34)), (3, 8, 32));
+        let samples: Vec<f32> = wav[44..].chunks(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect();
+        assert_eq!(samples, [0.5, -1.0, 1.5, 1.0 / 8_388_608.0], "float output is not clipped");
+    }
+
+    #[test]
+    fn dither_averages_to_the_true_level() {
+        // A level between two 16-bit steps: truncation always gives 0,
+        // dither hits both neighbours in proportion
+        let frames = vec![[0.3 / 32768.0, -0.3 / 32768.0]; 20_000];
+        let plain = parse_wav_i16_samples(&frames_to_wav(&frames, 44100, WavSpec::default())).unwrap();
+        assert!(plain.iter().all(|&s| s == 0));
+        let spec = WavSpec { depth: WavDepth::Int16, dither: true };
+        let dithered = parse_wav_i16_samples(&frames_to_wav(&frames, 44100, spec)).unwrap();
+        let mean = dithered.iter().step_by(2).map(|&s| s as f32).sum::<f32>() / 20_000.0;
+        assert!((mean - 0.3).abs() < 0.05, "mean {}", mean);
+        assert!(dithered.iter().all(|s| s.abs() <= 2));
+    }
+
+    #[test]
+    fn unpatched_stream_still_loads() {
+        let mut stream = WavStreamWriter::new(Vec::new(), 22050, WavSpec::default()).unwrap();
+        stream.write_frames(&[[0.5, 0.5]; 10]).unwrap();
+        let wav = stream.finish().unwrap();
+        assert_eq!(u32::from_le_bytes([wav[4], wav[5], wav[6], wav[7]]), u32::MAX);
+        let sample = load_wav(&wav, "stream").unwrap();
+        assert_eq!(sample.len(), 10);
+        assert_eq!(sample.c4_speed, 22050);
+    }
 }
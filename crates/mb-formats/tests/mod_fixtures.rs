@@ -23,7 +23,7 @@ fn count_notes(song: &mb_ir::Song) -> usize {
         .filter_map(|c| c.pattern())
         .flat_map(|pat| {
             (0..pat.rows).flat_map(move |row| {
-                (0..pat.channels as u8).map(move |col| pat.cell(row, col))
+                (0..pat.channels).map(move |col| pat.cell(row, col))
             })
         })
         .filter(|cell| matches!(cell.note, Note::On(_)))
@@ -57,7 +57,7 @@ fn assert_mod_invariants(song: &mb_ir::Song) {
     assert_eq!(song.instruments.len(), 31);
 
     // MOD defaults
-    assert_eq!(song.initial_tempo, 125);
+    assert_eq!(song.initial_tempo, 12500);
     assert_eq!(song.initial_speed, 6);
 
     // 4-channel MOD → 1 coalesced track with 4 channels
@@ -4,6 +4,7 @@ use alloc::collections::BTreeSet;
 use core::fmt;
 
 use crate::musical_time::MusicalTime;
+use crate::effects::Effect;
 use crate::pattern::{Cell, Note, Pattern};
 use crate::song::Song;
 
@@ -39,9 +40,10 @@ fn accumulate_cell(cell: &Cell, feat: &mut PatternFeatures) {
         feat.instruments_used.insert(cell.instrument);
     }
 
-    let eff_name = cell.effect.name();
-    if eff_name != "None" {
-        feat.effects.insert(eff_name);
+    for effect in cell.effects() {
+        if effect != Effect::None {
+            feat.effects.insert(effect.name());
+        }
     }
 
     let vol_name = cell.volume.name();
@@ -140,6 +142,30 @@ pub fn time_to_track_position(song: &Song, time: MusicalTime, track_idx: usize)
     None
 }
 
+/// Like `time_to_track_position`, but picks the row whose start is nearest
+/// to `time` rather than the row containing it (for quantizing recorded notes).
+///
+/// A time in the second half of a clip's last row rounds to the next clip's
+/// first row if that clip starts right there.
+pub fn nearest_track_position(song: &Song, time: MusicalTime, track_idx: usize) -> Option<TrackPlaybackPosition> {
+    let pos = time_to_track_position(song, time, track_idx)?;
+    let track = &song.tracks[track_idx];
+    let entry = &track.sequence[pos.seq_index];
+    let pattern = track.clips.get(entry.clip_idx as usize)?.pattern()?;
+    let rpb = pattern.rows_per_beat.map_or(song.rows_per_beat as u32, |r| r as u32);
+    let row_start = entry.start.add_rows(pos.row as u32, rpb);
+    let next_row = row_start.add_rows(1, rpb);
+    let into_row = time.as_sub_beats().saturating_sub(row_start.as_sub_beats());
+    let to_next = next_row.as_sub_beats().saturating_sub(time.as_sub_beats());
+    if into_row < to_next {
+        return Some(pos);
+    }
+    match time_to_track_position(song, next_row, track_idx) {
+        Some(next) if next.seq_index == pos.seq_index || next_row == track.sequence[next.seq_index].start => Some(next),
+        _ => Some(pos),
+    }
+}
+
 /// Find which row contains `time`, given that the pattern starts at `base`.
 fn find_row_at(base: MusicalTime, time: MusicalTime, rpb: u32, max_rows: u16) -> u16 {
     if time < base || rpb == 0 || max_rows == 0 {
@@ -169,6 +195,20 @@ mod tests {
         song
     }
 
+    #[test]
+    fn nearest_track_position_rounds_to_closest_row() {
+        let song = one_track_song(4);
+        let rpb = song.rows_per_beat as u32;
+        let row = |n: u32| MusicalTime::zero().add_rows(n, rpb);
+        let half = row(1).as_sub_beats() / 2;
+        let at = |t: u64| nearest_track_position(&song, MusicalTime::from_sub_beats(t), 0).map(|p| p.row);
+        assert_eq!(at(half - 1), Some(0));
+        assert_eq!(at(half), Some(1));
+        assert_eq!(at(row(2).as_sub_beats() + 1), Some(2));
+        // Past the middle of the last row with nothing after: stays put
+        assert_eq!(at(row(3).as_sub_beats() + half + 1), Some(3));
+    }
+
     // --- Pattern-level tests ---
 
     #[test]
@@ -3,6 +3,8 @@
 use alloc::vec;
 use alloc::vec::Vec;
 
+use crate::mix;
+
 /// Maximum number of audio channels per buffer.
 pub const MAX_CHANNELS: u16 = 8;
 
@@ -106,9 +108,8 @@ impl AudioBuffer {
             let frs = self.channel(ch).len().min(source.channel(ch).len());
             let src_start = ch as usize * source.capacity as usize;
             let dst_start = ch as usize * self.capacity as usize;
-            for i in 0..frs {
-                self.data[dst_start + i] += source.data[src_start + i];
-            }
+            let src = &source.data[src_start..src_start + frs];
+            mix::add_scaled(&mut self.data[dst_start..dst_start + frs], src, 1.0);
         }
     }
 
@@ -120,9 +121,23 @@ impl AudioBuffer {
             let frs = self.channel(ch).len().min(source.channel(ch).len());
             let src_start = ch as usize * source.capacity as usize;
             let dst_start = ch as usize * self.capacity as usize;
-            for i in 0..frs {
-                self.data[dst_start + i] += source.data[src_start + i] * gain;
-            }
+            let src = &source.data[src_start..src_start + frs];
+            mix::add_scaled(&mut self.data[dst_start..dst_start + frs], src, gain);
+        }
+    }
+
+    /// Sum overlapping channels from `source` into this buffer, scaling
+    /// even (left) channels by `gains[0]` and odd (right) ones by `gains[1]`.
+    #[inline(always)]
+    pub fn mix_from_panned(&mut self, source: &AudioBuffer, gains: [f32; 2]) {
+        let chs = self.channels.min(source.channels);
+        for ch in 0..chs {
+            let gain = gains[ch as usize % 2];
+            let frs = self.channel(ch).len().min(source.channel(ch).len());
+            let src_start = ch as usize * source.capacity as usize;
+            let dst_start = ch as usize * self.capacity as usize;
+            let src = &source.data[src_start..src_start + frs];
+            mix::add_scaled(&mut self.data[dst_start..dst_start + frs], src, gain);
         }
     }
 }
@@ -183,6 +198,18 @@ mod tests {
         assert!((dst.channel(0)[1] - -0.5).abs() < 1e-6);
     }
 
+    #[test]
+    fn mix_from_panned_scales_each_side() {
+        let mut dst = AudioBuffer::new(2, 1);
+        let mut src = AudioBuffer::new(2, 1);
+        src.channel_mut(0)[0] = 1.0;
+        src.channel_mut(1)[0] = 1.0;
+
+        dst.mix_from_panned(&src, [0.25, 1.0]);
+        assert!((dst.channel(0)[0] - 0.25).abs() < 1e-6);
+        assert!((dst.channel(1)[0] - 1.0).abs() < 1e-6);
+    }
+
     #[test]
     fn mix_from_mismatched_sizes_uses_minimum() {
         let mut dst = AudioBuffer::new(2, 4);
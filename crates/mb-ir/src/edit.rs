@@ -1,7 +1,15 @@
 //! Edit commands for mutating song data during playback.
 
-use crate::pattern::Cell;
-use crate::song::SeqTermination;
+use arrayvec::ArrayString;
+use crate::graph::{NodeId, NodeType};
+use crate::meter::TimeSignature;
+use crate::groove::{Groove, TrackOffset};
+use crate::marker::Marker;
+use crate::musical_time::MusicalTime;
+use crate::pattern::{Cell, Pattern};
+use crate::sample::{LoopType, Sample, SampleData};
+use crate::song::{ClipBoundary, SeqEntry, SeqTermination};
+use crate::tempo_envelope::TempoEnvelope;
 
 /// Data for placing a sequence entry.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -9,6 +17,42 @@ pub struct SeqEntryData {
     pub clip_idx: u16,
     pub length: u16,
     pub termination: SeqTermination,
+    pub boundary: ClipBoundary,
+}
+
+/// A sample's playback settings (everything but its name and audio data).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SampleFields {
+    pub loop_start: u32,
+    pub loop_end: u32,
+    pub loop_type: LoopType,
+    pub default_volume: u8,
+    pub default_pan: i8,
+    pub c4_speed: u32,
+}
+
+impl SampleFields {
+    /// The current settings of `sample`.
+    pub fn of(sample: &Sample) -> Self {
+        Self {
+            loop_start: sample.loop_start,
+            loop_end: sample.loop_end,
+            loop_type: sample.loop_type,
+            default_volume: sample.default_volume,
+            default_pan: sample.default_pan,
+            c4_speed: sample.c4_speed,
+        }
+    }
+
+    /// Write these settings into `sample`.
+    pub fn apply_to(&self, sample: &mut Sample) {
+        sample.loop_end = self.loop_end.min(sample.len() as u32);
+        sample.loop_start = self.loop_start.min(sample.loop_end);
+        sample.loop_type = self.loop_type;
+        sample.default_volume = self.default_volume.min(64);
+        sample.default_pan = self.default_pan.clamp(-64, 64);
+        sample.c4_speed = self.c4_speed;
+    }
 }
 
 /// An edit command that mutates song data.
@@ -30,4 +74,192 @@ pub enum Edit {
         beat: u32,
         entry: Option<SeqEntryData>,
     },
+    /// Insert a full-length entry for `clip` at sequence position `index`,
+    /// moving later entries back.
+    InsertSeqEntry { track: u16, index: u16, clip: u16 },
+    /// Delete the sequence entry at `index`, pulling later entries in.
+    RemoveSeqEntry { track: u16, index: u16 },
+    /// Move the sequence entry at `from` to position `to`.
+    MoveSeqEntry { track: u16, from: u16, to: u16 },
+    /// Play the sequence entry at `index` twice in a row.
+    DuplicateSeqEntry { track: u16, index: u16 },
+    /// Play `clip` at sequence position `index` instead.
+    SetSeqClip { track: u16, index: u16, clip: u16 },
+    /// Replace a track's whole sequence (how undo restores one).
+    SetSequence { track: u16, sequence: alloc::vec::Vec<SeqEntry> },
+    /// Change a clip's row count; sequence entries playing it follow.
+    ResizeClip { track: u16, clip: u16, rows: u16 },
+    /// Insert empty rows into a clip, pushing later rows down (the clip
+    /// keeps its length).
+    InsertRows { track: u16, clip: u16, row: u16, count: u16 },
+    /// Delete rows from a clip, pulling later rows up (the clip keeps its
+    /// length).
+    DeleteRows { track: u16, clip: u16, row: u16, count: u16 },
+    /// Copy a clip to the end of its track's clip pool.
+    CloneClip { track: u16, clip: u16 },
+    /// Add an empty clip of `rows` rows to the end of a track's clip pool.
+    AddClip { track: u16, rows: u16 },
+    /// Remove the last clip of a track's clip pool, unless the sequence
+    /// plays it.
+    RemoveLastClip { track: u16 },
+    /// Replace a clip's pattern as is, leaving sequence entries alone (how
+    /// undo restores one).
+    SetClip { track: u16, clip: u16, pattern: alloc::boxed::Box<Pattern> },
+    /// Add a track playing `channels` new channels on a new Tracker node.
+    AddTrack { channels: u8, name: ArrayString<32> },
+    /// Add a track playing `channels` new channels on a new MIDI Out node
+    /// sending to `port` on MIDI channel `channel`.
+    AddMidiTrack { channels: u8, name: ArrayString<32>, port: alloc::string::String, channel: u8 },
+    /// Remove a track, the machine node only it plays and its channels.
+    RemoveTrack { track: u16 },
+    /// Move a track to another position in the track list.
+    MoveTrack { from: u16, to: u16 },
+    /// Rename a track.
+    RenameTrack { track: u16, name: ArrayString<32> },
+    /// Mute or unmute a song channel (index into `Song::channels`).
+    SetChannelMute { channel: u8, muted: bool },
+    /// Solo or unsolo a song channel (index into `Song::channels`).
+    SetChannelSolo { channel: u8, solo: bool },
+    /// Set the groove of tracks without their own.
+    SetSongGroove { groove: Groove },
+    /// Give a track its own groove, or follow the song's with `None`.
+    SetTrackGroove { track: u16, groove: Option<Groove> },
+    /// Move all of a track's rows earlier or later.
+    SetTrackOffset { track: u16, offset: TrackOffset },
+    /// Set the song tempo in hundredths of a BPM; applies immediately
+    /// when playing.
+    SetTempo { tempo: u32 },
+    /// Replace the song's tempo envelope (empty to leave tempo to patterns).
+    SetTempoEnvelope { envelope: TempoEnvelope },
+    /// Change the time signature at `beat`, or drop the change there with
+    /// `None` (at beat 0: set or reset the initial signature).
+    SetTimeSignature { beat: u64, signature: Option<TimeSignature> },
+    /// Set the song speed (ticks per row); applies immediately when playing.
+    SetSpeed { speed: u8 },
+    /// Set a song channel's panning (-64 to +64).
+    SetChannelPan { channel: u8, pan: i8 },
+    /// Set a graph node parameter (clamped to its range).
+    SetNodeParam { node: u16, param: u16, value: i32 },
+    /// Replace a sample's playback settings.
+    SetSampleFields { sample: u8, fields: SampleFields },
+    /// Replace a sample's audio and settings (destructive sample edits).
+    SetSample { sample: u8, data: alloc::boxed::Box<Sample> },
+    /// Add a sample and an instrument playing it.
+    AddSample { sample: alloc::boxed::Box<Sample> },
+    /// Remove the last sample and the last instrument (how undo takes back
+    /// `AddSample`).
+    RemoveLastSample,
+    /// Add a marker, keeping the markers in time order.
+    AddMarker { marker: Marker },
+    /// Remove the marker at `index`.
+    RemoveMarker { index: u16 },
+    /// Rename the marker at `index`.
+    RenameMarker { index: u16, name: ArrayString<32> },
+    /// Set or clear the playback loop region `(start, end)`.
+    SetLoop { range: Option<(MusicalTime, MusicalTime)> },
+    /// Turn the master bus limiter on or off.
+    SetMasterLimiter { enabled: bool },
+    /// Turn the metronome click on or off.
+    SetMetronome { enabled: bool },
+    /// Set the metronome click's gain in percent (0..=100).
+    SetMetronomeGain { percent: u8 },
+    /// Click `bars` bars before the song moves on (0 cancels a count-in).
+    CountIn { bars: u8 },
+    /// Play sample audio as it is, after the master bus, going round its
+    /// loop with `looped`; `None` stops the preview.
+    PreviewSample { sample: Option<(alloc::sync::Arc<SampleData>, SampleFields)>, looped: bool },
+    /// Loop `clip` on a track from `at` in place of its sequence, or stop
+    /// the track at `at` with `None` (session mode).
+    LaunchClip { track: u16, clip: Option<u16>, at: MusicalTime },
+    /// Add a graph node; it gets the next free ID (`nodes.len()`).
+    AddNode { node_type: NodeType },
+    /// Remove a graph node and its wires, renumbering the nodes after it.
+    RemoveNode { node: NodeId },
+    /// Wire one node into another (gain as in `Connection::gain`).
+    Connect { from: NodeId, to: NodeId, gain: i16 },
+    /// Remove the wire between two nodes.
+    Disconnect { from: NodeId, to: NodeId },
+    /// Set the gain of the wire between two nodes.
+    SetConnectionGain { from: NodeId, to: NodeId, gain: i16 },
+    /// Set the pan of the wire between two nodes (-64 to +64).
+    SetConnectionPan { from: NodeId, to: NodeId, pan: i8 },
+}
+
+impl Edit {
+    /// Whether applying this edit to a playing engine allocates: graph
+    /// changes (machine instances, node buffers, traversal order) and
+    /// sequence changes (entries), track list changes, clip
+    /// resizes and copies, and sample edits.
+    pub fn allocates(&self) -> bool {
+        self.restructures_graph()
+            || self.is_sequence_edit()
+            || matches!(self, Edit::AddTrack { .. } | Edit::AddMidiTrack { .. } | Edit::RemoveTrack { .. } | Edit::MoveTrack { .. })
+            || matches!(self, Edit::ResizeClip { .. } | Edit::CloneClip { .. } | Edit::AddClip { .. } | Edit::RemoveLastClip { .. } | Edit::SetClip { .. })
+            || self.is_groove_edit()
+            || matches!(self, Edit::SetTempoEnvelope { .. } | Edit::SetTimeSignature { .. })
+            || self.is_sample_edit()
+    }
+
+    /// Whether this edit adds, removes or replaces a sample.
+    pub fn is_sample_edit(&self) -> bool {
+        matches!(self, Edit::SetSample { .. } | Edit::AddSample { .. } | Edit::RemoveLastSample)
+    }
+
+    /// Whether this edit adds, removes or renames a marker.
+    pub fn is_marker_edit(&self) -> bool {
+        matches!(self, Edit::AddMarker { .. } | Edit::RemoveMarker { .. } | Edit::RenameMarker { .. })
+    }
+
+    /// Whether this edit changes the graph's shape.
+    pub fn restructures_graph(&self) -> bool {
+        matches!(
+            self,
+            Edit::AddNode { .. } | Edit::RemoveNode { .. } | Edit::Connect { .. } | Edit::Disconnect { .. }
+        )
+    }
+
+    /// Whether this edit adds, removes, moves or renames a track.
+    pub fn is_track_edit(&self) -> bool {
+        matches!(
+            self,
+            Edit::AddTrack { .. }
+                | Edit::AddMidiTrack { .. }
+                | Edit::RemoveTrack { .. }
+                | Edit::MoveTrack { .. }
+                | Edit::RenameTrack { .. }
+        )
+    }
+
+    /// Whether this edit changes when a track's rows play.
+    pub fn is_groove_edit(&self) -> bool {
+        matches!(self, Edit::SetSongGroove { .. } | Edit::SetTrackGroove { .. } | Edit::SetTrackOffset { .. })
+    }
+
+    /// Whether this edit changes the shape of a clip or the clip pool.
+    pub fn is_clip_edit(&self) -> bool {
+        matches!(
+            self,
+            Edit::ResizeClip { .. }
+                | Edit::InsertRows { .. }
+                | Edit::DeleteRows { .. }
+                | Edit::CloneClip { .. }
+                | Edit::AddClip { .. }
+                | Edit::RemoveLastClip { .. }
+                | Edit::SetClip { .. }
+        )
+    }
+
+    /// Whether this edit changes a track's sequence.
+    pub fn is_sequence_edit(&self) -> bool {
+        matches!(
+            self,
+            Edit::SetSeqEntry { .. }
+                | Edit::InsertSeqEntry { .. }
+                | Edit::RemoveSeqEntry { .. }
+                | Edit::MoveSeqEntry { .. }
+                | Edit::DuplicateSeqEntry { .. }
+                | Edit::SetSeqClip { .. }
+                | Edit::SetSequence { .. }
+        )
+    }
 }
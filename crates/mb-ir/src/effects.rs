@@ -36,6 +36,57 @@ impl VolumeCommand {
             VolumeCommand::Vibrato(_) => "Vibrato",
         }
     }
+
+    /// The command's parameter, if it has one.
+    pub fn param(&self) -> Option<u8> {
+        match *self {
+            VolumeCommand::None => None,
+            VolumeCommand::Volume(v)
+            | VolumeCommand::VolumeSlideDown(v)
+            | VolumeCommand::VolumeSlideUp(v)
+            | VolumeCommand::FineVolSlideDown(v)
+            | VolumeCommand::FineVolSlideUp(v)
+            | VolumeCommand::Panning(v)
+            | VolumeCommand::PortaDown(v)
+            | VolumeCommand::PortaUp(v)
+            | VolumeCommand::TonePorta(v)
+            | VolumeCommand::Vibrato(v) => Some(v),
+        }
+    }
+
+    /// The same command with its parameter replaced (no-op for `None`).
+    pub fn with_param(self, v: u8) -> Self {
+        match self {
+            VolumeCommand::None => self,
+            VolumeCommand::Volume(_) => VolumeCommand::Volume(v),
+            VolumeCommand::VolumeSlideDown(_) => VolumeCommand::VolumeSlideDown(v),
+            VolumeCommand::VolumeSlideUp(_) => VolumeCommand::VolumeSlideUp(v),
+            VolumeCommand::FineVolSlideDown(_) => VolumeCommand::FineVolSlideDown(v),
+            VolumeCommand::FineVolSlideUp(_) => VolumeCommand::FineVolSlideUp(v),
+            VolumeCommand::Panning(_) => VolumeCommand::Panning(v),
+            VolumeCommand::PortaDown(_) => VolumeCommand::PortaDown(v),
+            VolumeCommand::PortaUp(_) => VolumeCommand::PortaUp(v),
+            VolumeCommand::TonePorta(_) => VolumeCommand::TonePorta(v),
+            VolumeCommand::Vibrato(_) => VolumeCommand::Vibrato(v),
+        }
+    }
+
+    /// The effect-column equivalent of this command, if any.
+    pub fn as_effect(&self) -> Option<Effect> {
+        Some(match *self {
+            VolumeCommand::None => return None,
+            VolumeCommand::Volume(v) => Effect::SetVolume(v),
+            VolumeCommand::Panning(p) => Effect::SetPan(p),
+            VolumeCommand::TonePorta(v) => Effect::TonePorta(v),
+            VolumeCommand::Vibrato(v) => Effect::Vibrato { speed: 0, depth: v },
+            VolumeCommand::VolumeSlideDown(v) => Effect::VolumeSlide(-(v as i8)),
+            VolumeCommand::VolumeSlideUp(v) => Effect::VolumeSlide(v as i8),
+            VolumeCommand::FineVolSlideDown(v) => Effect::FineVolumeSlideDown(v),
+            VolumeCommand::FineVolSlideUp(v) => Effect::FineVolumeSlideUp(v),
+            VolumeCommand::PortaDown(v) => Effect::PortaDown(v),
+            VolumeCommand::PortaUp(v) => Effect::PortaUp(v),
+        })
+    }
 }
 
 /// Effect column command.
@@ -85,6 +136,8 @@ pub enum Effect {
     FinePortaUp(u8),
     /// Fine porta down (once per row)
     FinePortaDown(u8),
+    /// Glissando control: tone portamento slides in semitone steps (0=off)
+    SetGlissando(u8),
     /// Set vibrato waveform (0=sine, 1=ramp, 2=square)
     SetVibratoWaveform(u8),
     /// Set finetune (-8 to +7)
@@ -107,6 +160,8 @@ pub enum Effect {
     NoteDelay(u8),
     /// Delay pattern by n rows
     PatternDelay(u8),
+    /// Funk repeat / invert loop: invert the sample loop at speed n (0=off)
+    FunkRepeat(u8),
 
     // === Speed & Tempo ===
     /// Set ticks per row (speed)
@@ -121,6 +176,8 @@ pub enum Effect {
     GlobalVolumeSlide(i8),
     /// Set envelope position
     SetEnvelopePosition(u8),
+    /// High sample offset: later sample offsets add n × 65536 frames
+    SetHighOffset(u8),
     /// Panning slide
     PanningSlide(i8),
     /// Retrigger with volume change
@@ -163,6 +220,7 @@ impl Effect {
             Effect::PatternBreak(_) => "PatternBreak",
             Effect::FinePortaUp(_) => "FinePortaUp",
             Effect::FinePortaDown(_) => "FinePortaDown",
+            Effect::SetGlissando(_) => "SetGlissando",
             Effect::SetVibratoWaveform(_) => "SetVibratoWaveform",
             Effect::SetFinetune(_) => "SetFinetune",
             Effect::PatternLoop(_) => "PatternLoop",
@@ -174,11 +232,13 @@ impl Effect {
             Effect::NoteCut(_) => "NoteCut",
             Effect::NoteDelay(_) => "NoteDelay",
             Effect::PatternDelay(_) => "PatternDelay",
+            Effect::FunkRepeat(_) => "FunkRepeat",
             Effect::SetSpeed(_) => "SetSpeed",
             Effect::SetTempo(_) => "SetTempo",
             Effect::SetGlobalVolume(_) => "SetGlobalVolume",
             Effect::GlobalVolumeSlide(_) => "GlobalVolumeSlide",
             Effect::SetEnvelopePosition(_) => "SetEnvelopePosition",
+            Effect::SetHighOffset(_) => "SetHighOffset",
             Effect::PanningSlide(_) => "PanningSlide",
             Effect::Retrigger { .. } => "Retrigger",
             Effect::Tremor { .. } => "Tremor",
@@ -189,7 +249,112 @@ impl Effect {
         }
     }
 
+    /// The effect's parameter as one number, if it has one.
+    ///
+    /// Nibble pairs (arpeggio, vibrato, tremolo, tremor) are packed as
+    /// `x << 4 | y`, the way trackers display them. `Retrigger` has no
+    /// single parameter.
+    pub fn param(&self) -> Option<i16> {
+        Some(match *self {
+            Effect::None | Effect::Retrigger { .. } => return None,
+            Effect::Arpeggio { x: a, y: b }
+            | Effect::Vibrato { speed: a, depth: b }
+            | Effect::Tremolo { speed: a, depth: b }
+            | Effect::Tremor { on: a, off: b } => ((a.min(15) << 4) | b.min(15)) as i16,
+            Effect::TonePortaVolSlide(v)
+            | Effect::VibratoVolSlide(v)
+            | Effect::VolumeSlide(v)
+            | Effect::SetFinetune(v)
+            | Effect::GlobalVolumeSlide(v)
+            | Effect::PanningSlide(v) => v as i16,
+            Effect::PortaUp(v)
+            | Effect::PortaDown(v)
+            | Effect::TonePorta(v)
+            | Effect::SetPan(v)
+            | Effect::SampleOffset(v)
+            | Effect::FractionalSampleOffset(v)
+            | Effect::PositionJump(v)
+            | Effect::SetVolume(v)
+            | Effect::PatternBreak(v)
+            | Effect::FinePortaUp(v)
+            | Effect::FinePortaDown(v)
+            | Effect::SetGlissando(v)
+            | Effect::SetVibratoWaveform(v)
+            | Effect::PatternLoop(v)
+            | Effect::SetTremoloWaveform(v)
+            | Effect::SetPanPosition(v)
+            | Effect::RetriggerNote(v)
+            | Effect::FineVolumeSlideUp(v)
+            | Effect::FineVolumeSlideDown(v)
+            | Effect::NoteCut(v)
+            | Effect::NoteDelay(v)
+            | Effect::PatternDelay(v)
+            | Effect::FunkRepeat(v)
+            | Effect::SetSpeed(v)
+            | Effect::SetTempo(v)
+            | Effect::SetGlobalVolume(v)
+            | Effect::SetEnvelopePosition(v)
+            | Effect::SetHighOffset(v)
+            | Effect::SetFilterCutoff(v)
+            | Effect::SetFilterResonance(v)
+            | Effect::ExtraFinePortaUp(v)
+            | Effect::ExtraFinePortaDown(v) => v as i16,
+        })
+    }
 
+    /// The same effect with its parameter replaced, packed as in `param`
+    /// and clamped to the parameter's range. Effects without a parameter
+    /// are returned unchanged.
+    pub fn with_param(self, v: i16) -> Self {
+        let u = v.clamp(0, 255) as u8;
+        let i = v.clamp(-128, 127) as i8;
+        let (hi, lo) = (u >> 4, u & 0x0F);
+        match self {
+            Effect::None | Effect::Retrigger { .. } => self,
+            Effect::Arpeggio { .. } => Effect::Arpeggio { x: hi, y: lo },
+            Effect::Vibrato { .. } => Effect::Vibrato { speed: hi, depth: lo },
+            Effect::Tremolo { .. } => Effect::Tremolo { speed: hi, depth: lo },
+            Effect::Tremor { .. } => Effect::Tremor { on: hi, off: lo },
+            Effect::TonePortaVolSlide(_) => Effect::TonePortaVolSlide(i),
+            Effect::VibratoVolSlide(_) => Effect::VibratoVolSlide(i),
+            Effect::VolumeSlide(_) => Effect::VolumeSlide(i),
+            Effect::SetFinetune(_) => Effect::SetFinetune(i),
+            Effect::GlobalVolumeSlide(_) => Effect::GlobalVolumeSlide(i),
+            Effect::PanningSlide(_) => Effect::PanningSlide(i),
+            Effect::PortaUp(_) => Effect::PortaUp(u),
+            Effect::PortaDown(_) => Effect::PortaDown(u),
+            Effect::TonePorta(_) => Effect::TonePorta(u),
+            Effect::SetPan(_) => Effect::SetPan(u),
+            Effect::SampleOffset(_) => Effect::SampleOffset(u),
+            Effect::FractionalSampleOffset(_) => Effect::FractionalSampleOffset(u),
+            Effect::PositionJump(_) => Effect::PositionJump(u),
+            Effect::SetVolume(_) => Effect::SetVolume(u),
+            Effect::PatternBreak(_) => Effect::PatternBreak(u),
+            Effect::FinePortaUp(_) => Effect::FinePortaUp(u),
+            Effect::FinePortaDown(_) => Effect::FinePortaDown(u),
+            Effect::SetGlissando(_) => Effect::SetGlissando(u),
+            Effect::SetVibratoWaveform(_) => Effect::SetVibratoWaveform(u),
+            Effect::PatternLoop(_) => Effect::PatternLoop(u),
+            Effect::SetTremoloWaveform(_) => Effect::SetTremoloWaveform(u),
+            Effect::SetPanPosition(_) => Effect::SetPanPosition(u),
+            Effect::RetriggerNote(_) => Effect::RetriggerNote(u),
+            Effect::FineVolumeSlideUp(_) => Effect::FineVolumeSlideUp(u),
+            Effect::FineVolumeSlideDown(_) => Effect::FineVolumeSlideDown(u),
+            Effect::NoteCut(_) => Effect::NoteCut(u),
+            Effect::NoteDelay(_) => Effect::NoteDelay(u),
+            Effect::PatternDelay(_) => Effect::PatternDelay(u),
+            Effect::FunkRepeat(_) => Effect::FunkRepeat(u),
+            Effect::SetSpeed(_) => Effect::SetSpeed(u),
+            Effect::SetTempo(_) => Effect::SetTempo(u),
+            Effect::SetGlobalVolume(_) => Effect::SetGlobalVolume(u),
+            Effect::SetEnvelopePosition(_) => Effect::SetEnvelopePosition(u),
+            Effect::SetHighOffset(_) => Effect::SetHighOffset(u),
+            Effect::SetFilterCutoff(_) => Effect::SetFilterCutoff(u),
+            Effect::SetFilterResonance(_) => Effect::SetFilterResonance(u),
+            Effect::ExtraFinePortaUp(_) => Effect::ExtraFinePortaUp(u),
+            Effect::ExtraFinePortaDown(_) => Effect::ExtraFinePortaDown(u),
+        }
+    }
 
     /// Returns true if this effect is processed only on tick 0.
     pub fn is_row_effect(&self) -> bool {
@@ -204,12 +369,15 @@ impl Effect {
                     | Effect::SetPan(_)
                     | Effect::SampleOffset(_)
                     | Effect::FractionalSampleOffset(_)
+                    | Effect::SetHighOffset(_)
                     | Effect::FinePortaUp(_)
                     | Effect::FinePortaDown(_)
                     | Effect::FineVolumeSlideUp(_)
                     | Effect::FineVolumeSlideDown(_)
+                    | Effect::SetGlissando(_)
                     | Effect::SetVibratoWaveform(_)
                     | Effect::SetTremoloWaveform(_)
+                    | Effect::FunkRepeat(_)
                     | Effect::ExtraFinePortaUp(_)
                     | Effect::ExtraFinePortaDown(_)
                     | Effect::NoteDelay(_)
@@ -51,8 +51,12 @@ pub enum EventPayload {
     },
     /// Release a note
     NoteOff { note: u8 },
+    /// Start the instrument fadeout without releasing the key (IT note fade)
+    NoteFade,
     /// Set portamento target (TonePorta + note: don't trigger, just set target)
     PortaTarget { note: u8, instrument: u8 },
+    /// An instrument number without a note
+    InstrumentChange { instrument: u8 },
 
     // === Parameter changes ===
     /// Instantly set a parameter value
@@ -64,14 +68,24 @@ pub enum EventPayload {
         duration: u32,
     },
 
+    /// Set the gain and/or pan of the wire from `from` into the target node
+    WireChange { from: NodeId, gain: Option<i16>, pan: Option<i8> },
+
+    // === Machine control ===
+    /// Silence a machine node and reset its state
+    Stop,
+
     // === Transport ===
     /// Set tempo (BPM * 100 for precision, e.g., 12500 = 125.00 BPM)
-    SetTempo(u16),
+    SetTempo(u32),
     /// Set speed (ticks per row)
     SetSpeed(u8),
 
     // === Pattern effects ===
     /// A tracker effect command
     Effect(Effect),
+    /// A second-column effect, layered on the first column's modulation
+    /// rather than replacing it
+    SecondEffect(Effect),
 }
 
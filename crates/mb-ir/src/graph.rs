@@ -24,6 +24,7 @@ impl AudioGraph {
                 id: 0,
                 node_type: NodeType::Master,
                 parameters: Vec::new(),
+                oversample: 1,
             }],
             connections: Vec::new(),
         }
@@ -36,6 +37,7 @@ impl AudioGraph {
             id,
             node_type,
             parameters: Vec::new(),
+            oversample: 1,
         });
         id
     }
@@ -48,9 +50,114 @@ impl AudioGraph {
             from_channel: 0,
             to_channel: 0,
             gain: 0, // 0dB
+            pan: 0,
         });
     }
 
+    /// Wire `from` into `to` at `gain`, refusing wires that would break
+    /// playback: unknown nodes, self-loops, duplicates, and loops that are
+    /// not closed through a feedback send.
+    ///
+    /// Returns whether the wire was added.
+    pub fn try_connect(&mut self, from: NodeId, to: NodeId, gain: i16) -> bool {
+        let valid = self.node(from).is_some() && self.node(to).is_some() && from != to;
+        if !valid || self.connection(from, to).is_some() || self.reaches(to, from) {
+            return false;
+        }
+        self.connect(from, to);
+        if let Some(conn) = self.connections.last_mut() {
+            conn.gain = gain;
+        }
+        true
+    }
+
+    /// Remove the wire from `from` into `to`. Returns whether one existed.
+    pub fn disconnect(&mut self, from: NodeId, to: NodeId) -> bool {
+        let before = self.connections.len();
+        self.connections.retain(|c| c.from != from || c.to != to);
+        self.connections.len() != before
+    }
+
+    /// The wire from `from` into `to`, if any.
+    pub fn connection(&self, from: NodeId, to: NodeId) -> Option<&Connection> {
+        self.connections.iter().find(|c| c.from == from && c.to == to)
+    }
+
+    /// Set the gain of the wire from `from` into `to`. Returns whether it exists.
+    pub fn set_connection_gain(&mut self, from: NodeId, to: NodeId, gain: i16) -> bool {
+        let Some(conn) = self.connections.iter_mut().find(|c| c.from == from && c.to == to) else {
+            return false;
+        };
+        conn.gain = gain;
+        true
+    }
+
+    /// Set the pan of the wire from `from` into `to` (clamped to -64..=64).
+    /// Returns whether it exists.
+    pub fn set_connection_pan(&mut self, from: NodeId, to: NodeId, pan: i8) -> bool {
+        let Some(conn) = self.connections.iter_mut().find(|c| c.from == from && c.to == to) else {
+            return false;
+        };
+        conn.pan = pan.clamp(-64, 64);
+        true
+    }
+
+    /// Whether audio leaving `start` can arrive at `target` within one block,
+    /// i.e. along wires that don't leave a feedback send.
+    fn reaches(&self, start: NodeId, target: NodeId) -> bool {
+        let mut visited = alloc::vec![false; self.nodes.len()];
+        let mut stack = alloc::vec![start];
+        while let Some(id) = stack.pop() {
+            if id == target {
+                return true;
+            }
+            let Some(seen) = visited.get_mut(id as usize) else { continue };
+            let is_send = matches!(self.nodes[id as usize].node_type, NodeType::FeedbackSend);
+            if *seen || is_send {
+                continue;
+            }
+            *seen = true;
+            stack.extend(self.connections.iter().filter(|c| c.from == id).map(|c| c.to));
+        }
+        false
+    }
+
+    /// Remove node `id` and its wires, renumbering the nodes after it.
+    ///
+    /// Node IDs are indices, so every ID above `id` drops by one; callers
+    /// holding IDs must shift them the same way. A feedback return whose
+    /// send is removed is left pointing nowhere and plays silence. The
+    /// Master node can't be removed. Returns whether a node was removed.
+    pub fn remove_node(&mut self, id: NodeId) -> bool {
+        if id == 0 || self.node(id).is_none() {
+            return false;
+        }
+        self.nodes.remove(id as usize);
+        self.connections.retain(|c| c.from != id && c.to != id);
+        let shift = |n: NodeId| if n > id { n - 1 } else { n };
+        for node in &mut self.nodes {
+            node.id = shift(node.id);
+            if let NodeType::FeedbackReturn { send } = &mut node.node_type {
+                *send = if *send == id { NodeId::MAX } else { shift(*send) };
+            }
+        }
+        for conn in &mut self.connections {
+            conn.from = shift(conn.from);
+            conn.to = shift(conn.to);
+        }
+        true
+    }
+
+    /// Add a feedback send/return pair and return `(send, return)`.
+    ///
+    /// Connect the end of the loop into the send and the return into the
+    /// start of the loop; audio reaches the return one block later.
+    pub fn add_feedback_pair(&mut self) -> (NodeId, NodeId) {
+        let send = self.add_node(NodeType::FeedbackSend);
+        let ret = self.add_node(NodeType::FeedbackReturn { send });
+        (send, ret)
+    }
+
     /// Get a node by ID.
     pub fn node(&self, id: NodeId) -> Option<&Node> {
         self.nodes.get(id as usize)
@@ -60,6 +167,15 @@ impl AudioGraph {
     pub fn node_mut(&mut self, id: NodeId) -> Option<&mut Node> {
         self.nodes.get_mut(id as usize)
     }
+
+    /// Set a node parameter, clamped to its range.
+    ///
+    /// Returns the value stored, or `None` if the node or parameter doesn't exist.
+    pub fn set_param(&mut self, node: NodeId, param: u16, value: i32) -> Option<i32> {
+        let p = self.node_mut(node)?.parameters.iter_mut().find(|p| p.id == param)?;
+        p.value = value.clamp(p.min, p.max);
+        Some(p.value)
+    }
 }
 
 /// A node in the audio graph.
@@ -71,6 +187,8 @@ pub struct Node {
     pub node_type: NodeType,
     /// Automatable parameters
     pub parameters: Vec<Parameter>,
+    /// Oversampling factor for this node's machine (1 = off, 2 or 4; ignored for trackers)
+    pub oversample: u8,
 }
 
 /// Type of audio graph node.
@@ -80,6 +198,17 @@ pub enum NodeType {
     Master,
     /// Buzz machine (emulated)
     Machine { machine_name: String, is_tracker: bool },
+    /// Sink half of a feedback pair; its outgoing wires are cut edges
+    FeedbackSend,
+    /// Source half of a feedback pair; replays `send`'s input one block later
+    FeedbackReturn { send: NodeId },
+    /// Live audio from the capture device, for monitoring while sampling
+    AudioInput,
+    /// Externally hosted plugin: the plugin `plugin_id` in the bundle at `path`
+    Plugin { path: String, plugin_id: String },
+    /// Sends the notes played on it to the MIDI output port named `port`,
+    /// on MIDI channel `channel` (0-15); produces no audio
+    MidiOut { port: String, channel: u8 },
 }
 
 impl NodeType {
@@ -88,6 +217,11 @@ impl NodeType {
         match self {
             NodeType::Master => alloc::string::String::from("Master"),
             NodeType::Machine { machine_name, .. } => machine_name.clone(),
+            NodeType::FeedbackSend => alloc::string::String::from("Feedback Send"),
+            NodeType::FeedbackReturn { .. } => alloc::string::String::from("Feedback Return"),
+            NodeType::AudioInput => alloc::string::String::from("Audio Input"),
+            NodeType::Plugin { plugin_id, .. } => plugin_id.clone(),
+            NodeType::MidiOut { .. } => alloc::string::String::from("MIDI Out"),
         }
     }
 }
@@ -105,6 +239,38 @@ pub struct Connection {
     pub to_channel: u8,
     /// Gain in fixed-point dB (0 = unity, positive = boost, negative = cut)
     pub gain: i16,
+    /// Stereo balance (-64 = left only, 0 = centre, +64 = right only)
+    pub pan: i8,
+}
+
+/// Physical unit a parameter's display value is expressed in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParamUnit {
+    /// Plain number, no unit suffix
+    #[default]
+    None,
+    /// Frequency (shown as kHz at 1000 and above)
+    Hz,
+    /// Level in decibels
+    Decibels,
+    /// Time in milliseconds
+    Milliseconds,
+    /// Percentage
+    Percent,
+    /// Discrete choice; label `i` names raw value `min + i`
+    Enum(&'static [&'static str]),
+}
+
+/// Mapping from a parameter's raw range to its display range.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ParamCurve {
+    /// Display value equals the raw value
+    #[default]
+    Raw,
+    /// Raw `min..=max` maps linearly onto `lo..=hi`
+    Linear { lo: f32, hi: f32 },
+    /// Raw `min..=max` maps exponentially onto `lo..=hi` (both > 0)
+    Exponential { lo: f32, hi: f32 },
 }
 
 /// An automatable parameter on a node.
@@ -122,6 +288,10 @@ pub struct Parameter {
     pub max: i32,
     /// Default value
     pub default: i32,
+    /// Unit of the display value
+    pub unit: ParamUnit,
+    /// Raw → display mapping
+    pub curve: ParamCurve,
 }
 
 impl Parameter {
@@ -136,7 +306,188 @@ impl Parameter {
             min,
             max,
             default,
+            unit: ParamUnit::None,
+            curve: ParamCurve::Raw,
+        }
+    }
+
+    /// Attach display metadata.
+    pub fn with_display(mut self, unit: ParamUnit, curve: ParamCurve) -> Self {
+        self.unit = unit;
+        self.curve = curve;
+        self
+    }
+
+    /// Convert a raw value to its display value.
+    pub fn to_display(&self, raw: i32) -> f32 {
+        let t = self.normalized(raw);
+        match self.curve {
+            ParamCurve::Raw => raw as f32,
+            ParamCurve::Linear { lo, hi } => lo + t * (hi - lo),
+            ParamCurve::Exponential { lo, hi } => lo * libm::powf(hi / lo, t),
         }
     }
 
+    /// Convert a display value back to the nearest raw value, clamped to range.
+    pub fn from_display(&self, display: f32) -> i32 {
+        let t = match self.curve {
+            ParamCurve::Raw => return (libm::roundf(display) as i32).clamp(self.min, self.max),
+            ParamCurve::Linear { lo, hi } if hi != lo => (display - lo) / (hi - lo),
+            ParamCurve::Exponential { lo, hi } if hi != lo && lo > 0.0 && display > 0.0 => {
+                libm::logf(display / lo) / libm::logf(hi / lo)
+            }
+            _ => 0.0,
+        };
+        let span = (self.max - self.min) as f32;
+        let raw = self.min + libm::roundf(t.clamp(0.0, 1.0) * span) as i32;
+        raw.clamp(self.min, self.max)
+    }
+
+    /// Format a raw value for display, e.g. `"2.3 kHz"` or `"50%"`.
+    pub fn format_value(&self, raw: i32) -> String {
+        let v = self.to_display(raw);
+        match self.unit {
+            ParamUnit::None => match self.curve {
+                ParamCurve::Raw => alloc::format!("{}", raw),
+                _ => alloc::format!("{:.2}", v),
+            },
+            ParamUnit::Hz if v >= 1000.0 => alloc::format!("{:.1} kHz", v / 1000.0),
+            ParamUnit::Hz => alloc::format!("{:.0} Hz", v),
+            ParamUnit::Decibels => alloc::format!("{:.1} dB", v),
+            ParamUnit::Milliseconds => alloc::format!("{:.0} ms", v),
+            ParamUnit::Percent => alloc::format!("{:.0}%", v),
+            ParamUnit::Enum(labels) => usize::try_from(raw - self.min).ok()
+                .and_then(|i| labels.get(i))
+                .map_or_else(|| alloc::format!("{}", raw), |l| String::from(*l)),
+        }
+    }
+
+    /// Format the current value for display.
+    pub fn display_value(&self) -> String {
+        self.format_value(self.value)
+    }
+
+    /// Position of `raw` within `min..=max` as 0.0–1.0.
+    fn normalized(&self, raw: i32) -> f32 {
+        if self.max <= self.min {
+            return 0.0;
+        }
+        ((raw - self.min) as f32 / (self.max - self.min) as f32).clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_param_clamps_to_range() {
+        let mut graph = AudioGraph::with_master();
+        graph.nodes[0].parameters.push(Parameter::new(3, "Amount", 0, 100, 50));
+        assert_eq!(graph.set_param(0, 3, 250), Some(100));
+        assert_eq!(graph.nodes[0].parameters[0].value, 100);
+        assert_eq!(graph.set_param(0, 4, 10), None);
+        assert_eq!(graph.set_param(9, 3, 10), None);
+    }
+
+    fn machine(name: &str) -> NodeType {
+        NodeType::Machine { machine_name: String::from(name), is_tracker: false }
+    }
+
+    #[test]
+    fn try_connect_rejects_invalid_and_cyclic_wires() {
+        let mut graph = AudioGraph::with_master();
+        let a = graph.add_node(machine("A"));
+        let b = graph.add_node(machine("B"));
+        assert!(graph.try_connect(a, b, 50));
+        assert!(graph.try_connect(b, 0, 0));
+        assert_eq!(graph.connection(a, b).map(|c| c.gain), Some(50));
+        assert!(!graph.try_connect(a, b, 0), "duplicate");
+        assert!(!graph.try_connect(a, a, 0), "self-loop");
+        assert!(!graph.try_connect(a, 9, 0), "unknown node");
+        assert!(!graph.try_connect(b, a, 0), "cycle");
+        // Closing the loop through a feedback pair is fine
+        let (send, ret) = graph.add_feedback_pair();
+        assert!(graph.try_connect(b, send, 0));
+        assert!(graph.try_connect(send, ret, 0));
+        assert!(graph.try_connect(ret, a, 0));
+    }
+
+    #[test]
+    fn disconnect_and_set_gain_need_existing_wire() {
+        let mut graph = AudioGraph::with_master();
+        let a = graph.add_node(machine("A"));
+        assert!(!graph.set_connection_gain(a, 0, 20));
+        graph.connect(a, 0);
+        assert!(graph.set_connection_gain(a, 0, 20));
+        assert!(graph.set_connection_pan(a, 0, 100));
+        assert_eq!(graph.connection(a, 0).map(|c| (c.gain, c.pan)), Some((20, 64)));
+        assert!(graph.disconnect(a, 0));
+        assert!(!graph.disconnect(a, 0));
+    }
+
+    #[test]
+    fn remove_node_renumbers_later_nodes() {
+        let mut graph = AudioGraph::with_master();
+        let a = graph.add_node(machine("A"));
+        let b = graph.add_node(machine("B"));
+        let (send, ret) = graph.add_feedback_pair();
+        graph.connect(a, b);
+        graph.connect(b, 0);
+        graph.connect(send, ret);
+        assert!(!graph.remove_node(0));
+        assert!(graph.remove_node(a));
+        assert_eq!(graph.nodes.len(), 4);
+        assert!(graph.nodes.iter().enumerate().all(|(i, n)| n.id as usize == i));
+        assert_eq!(graph.nodes[1].node_type, machine("B"));
+        assert_eq!(graph.nodes[3].node_type, NodeType::FeedbackReturn { send: send - 1 });
+        let wires: Vec<_> = graph.connections.iter().map(|c| (c.from, c.to)).collect();
+        assert_eq!(wires, [(1, 0), (send - 1, ret - 1)]);
+        assert!(graph.remove_node(send - 1));
+        assert_eq!(graph.nodes[2].node_type, NodeType::FeedbackReturn { send: NodeId::MAX });
+    }
+
+    #[test]
+    fn raw_parameter_formats_as_integer() {
+        let p = Parameter::new(0, "Amount", 0, 255, 137);
+        assert_eq!(p.display_value(), "137");
+        assert_eq!(p.from_display(300.0), 255);
+    }
+
+    #[test]
+    fn hz_switches_to_khz() {
+        let p = Parameter::new(0, "Cutoff", 1000, 22050, 2300).with_display(ParamUnit::Hz, ParamCurve::Raw);
+        assert_eq!(p.display_value(), "2.3 kHz");
+        assert_eq!(p.format_value(999), "999 Hz");
+    }
+
+    #[test]
+    fn linear_percent_round_trips() {
+        let p = Parameter::new(0, "Wet", 0, 254, 127)
+            .with_display(ParamUnit::Percent, ParamCurve::Linear { lo: 0.0, hi: 100.0 });
+        assert_eq!(p.display_value(), "50%");
+        assert_eq!(p.from_display(50.0), 127);
+        assert_eq!(p.from_display(150.0), 254);
+    }
+
+    #[test]
+    fn exponential_curve_round_trips() {
+        let p = Parameter::new(0, "Freq", 0, 100, 0)
+            .with_display(ParamUnit::Hz, ParamCurve::Exponential { lo: 20.0, hi: 20000.0 });
+        assert!((p.to_display(0) - 20.0).abs() < 0.01);
+        assert!((p.to_display(100) - 20000.0).abs() < 1.0);
+        assert!((p.to_display(50) - 632.5).abs() < 1.0);
+        for raw in [0, 17, 50, 99, 100] {
+            assert_eq!(p.from_display(p.to_display(raw)), raw);
+        }
+    }
+
+    #[test]
+    fn enum_labels_by_offset() {
+        const LABELS: &[&str] = &["Low", "High"];
+        let p = Parameter::new(0, "Mode", 1, 2, 2).with_display(ParamUnit::Enum(LABELS), ParamCurve::Raw);
+        assert_eq!(p.display_value(), "High");
+        assert_eq!(p.format_value(1), "Low");
+        assert_eq!(p.format_value(7), "7");
+    }
 }
@@ -0,0 +1,148 @@
+//! Groove templates and track offsets: timing shifts that give rows a
+//! swing or shuffle, or move a whole track off the grid.
+
+use alloc::vec::Vec;
+
+use crate::musical_time::{MusicalTime, SUB_BEAT_UNIT};
+
+/// Largest swing percentage; it puts odd rows halfway into the next row.
+pub const MAX_SWING: u8 = 75;
+
+/// How a track's rows are shifted off the grid.
+///
+/// Grooves only ever delay rows, like an EDx note delay, so a row's events
+/// never fire before the row starts. Rows are counted from the start of
+/// their clip.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Groove {
+    /// Rows play on the grid
+    #[default]
+    Straight,
+    /// Each odd row falls `percent` of the way through its pair of rows
+    /// (50 = straight, 66 = triplet shuffle, at most `MAX_SWING`)
+    Swing(u8),
+    /// Row `r` plays `ticks[r % ticks.len()]` ticks late, at most one tick
+    /// short of the next row
+    Ticks(Vec<u8>),
+}
+
+impl Groove {
+    /// Whether every row stays on the grid.
+    pub fn is_straight(&self) -> bool {
+        match self {
+            Groove::Straight => true,
+            Groove::Swing(percent) => *percent <= 50,
+            Groove::Ticks(ticks) => ticks.iter().all(|&t| t == 0),
+        }
+    }
+
+    /// When clip row `row`, due on the grid at `time`, plays at
+    /// `ticks_per_row` ticks per row and `rows_per_beat` rows per beat.
+    pub fn row_time(&self, time: MusicalTime, row: u16, ticks_per_row: u32, rows_per_beat: u32) -> MusicalTime {
+        match self {
+            Groove::Straight => time,
+            Groove::Swing(percent) => {
+                if row.is_multiple_of(2) || rows_per_beat == 0 {
+                    return time;
+                }
+                let swing = percent.clamp(&50, &MAX_SWING) - 50;
+                let row_len = (SUB_BEAT_UNIT / rows_per_beat) as u64;
+                MusicalTime::from_sub_beats(time.as_sub_beats() + row_len * swing as u64 / 50)
+            }
+            Groove::Ticks(ticks) => {
+                let Some(&delay) = ticks.get(row as usize % ticks.len().max(1)) else { return time };
+                let delay = (delay as u32).min(ticks_per_row.saturating_sub(1));
+                time.add_ticks(delay, ticks_per_row * rows_per_beat)
+            }
+        }
+    }
+}
+
+/// Largest track offset in milliseconds, either way.
+pub const MAX_OFFSET_MS: i16 = 1000;
+
+/// How far all of a track's rows are moved, after its groove: to nudge a
+/// drum track or make up for slow-attack samples without editing cells.
+///
+/// Unlike grooves, offsets may move rows earlier. Rows never move before
+/// the start of the song.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrackOffset {
+    /// Ticks at the playing speed (negative = early)
+    Ticks(i8),
+    /// Milliseconds at the tempo where the row plays (negative = early,
+    /// at most `MAX_OFFSET_MS` either way)
+    Millis(i16),
+}
+
+impl Default for TrackOffset {
+    fn default() -> Self {
+        TrackOffset::Ticks(0)
+    }
+}
+
+impl TrackOffset {
+    /// Whether rows stay where they are.
+    pub fn is_zero(&self) -> bool {
+        matches!(self, TrackOffset::Ticks(0) | TrackOffset::Millis(0))
+    }
+
+    /// The offset in sub-beats (negative = early) at `ticks_per_beat`
+    /// ticks per beat and `tempo` hundredths of a BPM.
+    pub fn sub_beats(&self, ticks_per_beat: u32, tempo: u32) -> i64 {
+        match *self {
+            TrackOffset::Ticks(ticks) => ticks as i64 * SUB_BEAT_UNIT as i64 / ticks_per_beat.max(1) as i64,
+            TrackOffset::Millis(ms) => {
+                let ms = ms.clamp(-MAX_OFFSET_MS, MAX_OFFSET_MS) as i64;
+                ms * tempo as i64 * SUB_BEAT_UNIT as i64 / 6_000_000
+            }
+        }
+    }
+
+    /// `time` moved by the offset, held at zero.
+    pub fn apply(&self, time: MusicalTime, ticks_per_beat: u32, tempo: u32) -> MusicalTime {
+        let offset = self.sub_beats(ticks_per_beat, tempo);
+        let sub_beats = time.as_sub_beats() as i64 + offset;
+        MusicalTime::from_sub_beats(sub_beats.max(0) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn swing_delays_odd_rows_by_a_share_of_the_pair() {
+        let groove = Groove::Swing(MAX_SWING);
+        let row = |n| MusicalTime::zero().add_rows(n, 4);
+        assert_eq!(groove.row_time(row(0), 0, 6, 4), row(0));
+        assert_eq!(groove.row_time(row(1), 1, 6, 4), row(1).add_ticks(3, 24));
+        assert_eq!(Groove::Swing(99).row_time(row(3), 3, 6, 4), row(3).add_ticks(3, 24));
+        assert_eq!(Groove::Swing(20).row_time(row(3), 3, 6, 4), row(3));
+        assert!(Groove::Swing(50).is_straight());
+    }
+
+    #[test]
+    fn tick_offsets_cycle_and_stay_inside_the_row() {
+        let groove = Groove::Ticks(vec![0, 2, 9]);
+        let row = |n| MusicalTime::zero().add_rows(n, 4);
+        assert_eq!(groove.row_time(row(1), 1, 6, 4), row(1).add_ticks(2, 24));
+        assert_eq!(groove.row_time(row(2), 2, 6, 4), row(2).add_ticks(5, 24));
+        assert_eq!(groove.row_time(row(4), 4, 6, 4), row(4).add_ticks(2, 24));
+        assert_eq!(Groove::Ticks(vec![]).row_time(row(1), 1, 6, 4), row(1));
+        assert!(!groove.is_straight());
+    }
+
+    #[test]
+    fn offsets_move_rows_both_ways_but_not_before_zero() {
+        let row = |n| MusicalTime::zero().add_rows(n, 4);
+        assert_eq!(TrackOffset::Ticks(2).apply(row(1), 24, 12_500), row(1).add_ticks(2, 24));
+        assert_eq!(TrackOffset::Ticks(-6).apply(row(1), 24, 12_500), row(0));
+        assert_eq!(TrackOffset::Ticks(-1).apply(row(0), 24, 12_500), row(0));
+        // 120 BPM: a beat lasts 500 ms
+        assert_eq!(TrackOffset::Millis(-125).apply(row(4), 24, 12_000), row(3));
+        assert_eq!(TrackOffset::Millis(i16::MAX).apply(row(0), 24, 12_000), row(8));
+        assert!(TrackOffset::default().is_zero());
+    }
+}
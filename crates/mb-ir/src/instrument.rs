@@ -135,6 +135,35 @@ impl Envelope {
         // Past the last point
         prev.value
     }
+
+    /// Position after advancing one tick from `tick`.
+    ///
+    /// While the key is held the sustain loop wraps (falling back to the
+    /// regular loop); once released only the regular loop does. Without a
+    /// loop the position stops at the last point.
+    pub fn next_tick(&self, tick: u16, released: bool) -> u16 {
+        let Some(last) = self.points.last() else { return 0 };
+        let next = tick.saturating_add(1);
+        let sustain = if released { None } else { self.point_range(self.sustain_start, self.sustain_end) };
+        if let Some((start, end)) = sustain.or_else(|| self.point_range(self.loop_start, self.loop_end)) {
+            if tick <= end && next > end {
+                return start;
+            }
+        }
+        next.min(last.tick)
+    }
+
+    /// Whether `tick` has reached the last point.
+    pub fn at_end(&self, tick: u16) -> bool {
+        self.points.last().is_none_or(|p| tick >= p.tick)
+    }
+
+    /// Ticks of the points at indices `start`..=`end`, if both exist and are ordered.
+    fn point_range(&self, start: Option<u8>, end: Option<u8>) -> Option<(u16, u16)> {
+        let start = self.points.get(start? as usize)?.tick;
+        let end = self.points.get(end? as usize)?.tick;
+        (start <= end).then_some((start, end))
+    }
 }
 
 /// A point in an envelope.
@@ -161,4 +190,42 @@ mod tests {
         assert_eq!(env.value_at(100), 0);
         assert_eq!(env.value_at(200), 0); // Past end
     }
+
+    fn looped_envelope() -> Envelope {
+        let mut env = Envelope::new();
+        env.add_point(0, 0);
+        env.add_point(2, 64);
+        env.add_point(4, 32);
+        env.add_point(8, 0);
+        env.sustain_start = Some(1);
+        env.sustain_end = Some(2);
+        env
+    }
+
+    #[test]
+    fn sustain_loop_holds_until_release() {
+        let env = looped_envelope();
+        let mut tick = 0;
+        let mut held = Vec::new();
+        for _ in 0..8 {
+            tick = env.next_tick(tick, false);
+            held.push(tick);
+        }
+        assert_eq!(held, vec![1, 2, 3, 4, 2, 3, 4, 2]);
+        for _ in 0..10 {
+            tick = env.next_tick(tick, true);
+        }
+        assert_eq!(tick, 8);
+        assert!(env.at_end(tick));
+    }
+
+    #[test]
+    fn regular_loop_applies_after_release() {
+        let mut env = looped_envelope();
+        env.loop_start = Some(0);
+        env.loop_end = Some(3);
+        assert_eq!(env.next_tick(4, false), 2);
+        assert_eq!(env.next_tick(8, true), 0);
+        assert_eq!(env.next_tick(5, true), 6);
+    }
 }
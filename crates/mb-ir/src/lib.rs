@@ -17,21 +17,38 @@ mod edit;
 mod effects;
 mod event;
 mod graph;
+mod groove;
 mod instrument;
+mod loop_finder;
+mod marker;
+mod memory;
+mod meter;
+pub mod mix;
 mod mod_envelope;
 mod modulator;
 mod pattern;
 mod sample;
+mod sample_edit;
+mod selection;
+mod slicing;
 pub mod song;
+mod song_iter;
+mod song_template;
+mod tempo_envelope;
 mod musical_time;
 
-pub use analysis::{analyze_pattern, time_to_track_position, PatternFeatures, PlaybackPosition, TrackPlaybackPosition};
+pub use analysis::{analyze_pattern, nearest_track_position, time_to_track_position, PatternFeatures, PlaybackPosition, TrackPlaybackPosition};
 pub use audio_buffer::{AudioBuffer, BLOCK_SIZE, MAX_CHANNELS};
 pub use audio_traits::{AudioSource, AudioStream, ChannelConfig};
-pub use edit::{Edit, SeqEntryData};
+pub use edit::{Edit, SampleFields, SeqEntryData};
 pub use effects::{Effect, VolumeCommand};
 pub use event::{Event, EventPayload, EventTarget};
-pub use graph::{AudioGraph, Connection, Node, NodeId, NodeType, Parameter};
+pub use graph::{AudioGraph, Connection, Node, NodeId, NodeType, ParamCurve, ParamUnit, Parameter};
+pub use groove::{Groove, TrackOffset, MAX_OFFSET_MS, MAX_SWING};
+pub use loop_finder::find_loop_points;
+pub use marker::Marker;
+pub use memory::{ByteSize, MemoryFootprint};
+pub use meter::{BarPosition, Meter, TimeSignature};
 pub use instrument::{DuplicateCheck, Envelope, EnvelopePoint, Instrument, NewNoteAction};
 pub use mod_envelope::{interpolate, CurveKind, LoopRange, ModBreakPoint, ModEnvelope};
 pub use modulator::{
@@ -40,6 +57,12 @@ pub use modulator::{
     volume_slide_envelope, ChannelParam, GlobalParam, ModMode, ModTarget, Modulator,
 };
 pub use musical_time::{unpack_time, pack_time, MusicalTime, SUB_BEAT_UNIT};
-pub use pattern::{Cell, Note, Pattern};
-pub use sample::{AutoVibrato, LoopType, Sample, SampleData};
-pub use song::{build_tracks, ChannelSettings, Clip, OrderEntry, SeqEntry, SeqTermination, Song, Track, find_machine_node, find_tracker_node};
+pub use pattern::{Cell, Note, Pattern, WireLane, WireValue};
+pub use sample::{AutoVibrato, Interpolation, LoopTaps, LoopType, Sample, SampleData};
+pub use sample_edit::{SampleFormat, SampleOp};
+pub use selection::{interpolate_cells, transpose_cells, CellField, CellSelection};
+pub use slicing::{slice_pattern, slice_points, slice_sample, SliceMode};
+pub use song_iter::{CellPos, ScheduledRow};
+pub use song_template::SongTemplate;
+pub use tempo_envelope::{Bpm, TempoCurve, TempoEnvelope, TempoPoint};
+pub use song::{build_tracks, ChannelSettings, ClipBoundary, Clip, CompatibilityMode, FrequencyMode, OrderEntry, SeqEntry, SeqTermination, Song, Subsong, Track, find_machine_node, find_tracker_node, track_name};
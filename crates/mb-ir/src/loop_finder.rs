@@ -0,0 +1,97 @@
+//! Automatic loop point search for samples.
+//!
+//! Candidate points are rising zero crossings, so the jump back never
+//! clicks on a level step. Among them, the pair whose surroundings match
+//! best (least squared difference over a window on each side) wins, which
+//! keeps the waveform's phase and shape continuous across the seam.
+
+use alloc::vec::Vec;
+
+use crate::sample::Sample;
+
+/// Frames compared on each side of a seam.
+const MATCH_WINDOW: usize = 256;
+
+/// Loop end candidates tried, counted back from the last crossing.
+const END_CANDIDATES: usize = 16;
+
+/// Find a loop `(start, end)` at least `min_len` frames long, or `None`
+/// if the sample has no pair of rising zero crossings that far apart.
+pub fn find_loop_points(sample: &Sample, min_len: u32) -> Option<(u32, u32)> {
+    let x: Vec<f32> = (0..sample.len())
+        .map(|i| (sample.data.get_mono(i) as f32 + sample.data.get_right(i) as f32) / 65536.0)
+        .collect();
+    let crossings: Vec<usize> = (1..x.len()).filter(|&i| x[i - 1] < 0.0 && x[i] >= 0.0).collect();
+    let ends = &crossings[crossings.len().saturating_sub(END_CANDIDATES)..];
+    let mut best: Option<(f32, usize, usize)> = None;
+    for &end in ends {
+        for &start in crossings.iter().take_while(|&&s| s + min_len as usize <= end) {
+            let score = seam_error(&x, start, end);
+            if best.is_none_or(|(b, _, _)| score < b) {
+                best = Some((score, start, end));
+            }
+        }
+    }
+    best.map(|(_, start, end)| (start as u32, end as u32))
+}
+
+/// How different the audio around `start` is from the audio around `end`,
+/// relative to its level (0 = identical).
+fn seam_error(x: &[f32], start: usize, end: usize) -> f32 {
+    let before = MATCH_WINDOW.min(start);
+    let after = MATCH_WINDOW.min(x.len() - end);
+    let (mut diff, mut level) = (0.0f32, 1e-9f32);
+    for k in 1..=before {
+        let (a, b) = (x[start - k], x[end - k]);
+        diff += (a - b) * (a - b);
+        level += a * a + b * b;
+    }
+    for k in 0..after {
+        let (a, b) = (x[start + k], x[end + k]);
+        diff += (a - b) * (a - b);
+        level += a * a + b * b;
+    }
+    diff / level
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::SampleData;
+
+    fn sample(data: Vec<i16>) -> Sample {
+        let mut sample = Sample::new("tone");
+        sample.set_data(SampleData::Mono16(data));
+        sample
+    }
+
+    fn sine(period: f32, frames: usize) -> Vec<i16> {
+        (0..frames)
+            .map(|i| (libm::sinf(core::f32::consts::TAU * (i as f32 + 0.5) / period) * 10000.0) as i16)
+            .collect()
+    }
+
+    #[test]
+    fn loop_spans_whole_periods() {
+        let (start, end) = find_loop_points(&sample(sine(100.0, 3000)), 1000).unwrap();
+        assert!(end - start >= 1000);
+        assert_eq!((end - start) % 100, 0);
+        assert_eq!(start % 100, 0);
+    }
+
+    #[test]
+    fn loop_avoids_mismatched_sections() {
+        // A tone whose second half is an octave up: the loop stays in one half
+        let mut data = sine(100.0, 2000);
+        data.extend(sine(50.0, 2000));
+        let (start, end) = find_loop_points(&sample(data), 500).unwrap();
+        assert!(start >= 2000, "start {} is in the lower octave", start);
+        assert_eq!((end - start) % 50, 0);
+    }
+
+    #[test]
+    fn no_loop_without_crossings_far_enough_apart() {
+        assert_eq!(find_loop_points(&sample(sine(100.0, 300)), 1000), None);
+        assert_eq!(find_loop_points(&sample(alloc::vec![0; 500]), 10), None);
+    }
+}
@@ -0,0 +1,94 @@
+//! Song markers: named cue points for finding your way around long songs.
+
+use arrayvec::ArrayString;
+
+use crate::edit::Edit;
+use crate::musical_time::MusicalTime;
+use crate::song::{track_name, Song};
+
+/// A named position in the song.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Marker {
+    pub time: MusicalTime,
+    pub name: ArrayString<32>,
+}
+
+impl Marker {
+    /// A marker at `time` (names are cut short at 32 bytes).
+    pub fn new(time: MusicalTime, name: &str) -> Self {
+        Self { time, name: track_name(name) }
+    }
+}
+
+impl Song {
+    /// Add a marker, keeping markers in time order; returns its index.
+    /// A marker at the same time as others goes after them.
+    pub fn add_marker(&mut self, marker: Marker) -> usize {
+        let index = self.markers.partition_point(|m| m.time <= marker.time);
+        self.markers.insert(index, marker);
+        index
+    }
+
+    /// Remove the marker at `index`.
+    pub fn remove_marker(&mut self, index: usize) -> Option<Marker> {
+        (index < self.markers.len()).then(|| self.markers.remove(index))
+    }
+
+    /// Apply a marker edit (see `Edit::is_marker_edit`); returns whether
+    /// it applied.
+    pub fn apply_marker_edit(&mut self, edit: &Edit) -> bool {
+        match edit {
+            Edit::AddMarker { marker } => {
+                self.add_marker(marker.clone());
+                true
+            }
+            Edit::RemoveMarker { index } => self.remove_marker(*index as usize).is_some(),
+            Edit::RenameMarker { index, name } => {
+                let Some(marker) = self.markers.get_mut(*index as usize) else { return false };
+                marker.name = *name;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Index of the first marker called `name`.
+    pub fn find_marker(&self, name: &str) -> Option<usize> {
+        self.markers.iter().position(|m| m.name.as_str() == name)
+    }
+
+    /// Index of the first marker after `time`.
+    pub fn marker_after(&self, time: MusicalTime) -> Option<usize> {
+        let index = self.markers.partition_point(|m| m.time <= time);
+        (index < self.markers.len()).then_some(index)
+    }
+
+    /// Index of the last marker before `time`.
+    pub fn marker_before(&self, time: MusicalTime) -> Option<usize> {
+        self.markers.partition_point(|m| m.time < time).checked_sub(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markers_stay_in_time_order_and_can_be_stepped_through() {
+        let mut song = Song::new("markers");
+        let beat = MusicalTime::from_beats;
+        song.add_marker(Marker::new(beat(32), "Chorus"));
+        song.add_marker(Marker::new(beat(0), "Intro"));
+        assert_eq!(song.add_marker(Marker::new(beat(16), "Verse")), 1);
+        assert_eq!(song.find_marker("Chorus"), Some(2));
+
+        assert_eq!(song.marker_after(beat(0)), Some(1));
+        assert_eq!(song.marker_after(beat(32)), None);
+        assert_eq!(song.marker_before(beat(16)), Some(0));
+        assert_eq!(song.marker_before(beat(0)), None);
+
+        assert_eq!(song.remove_marker(0).unwrap().name.as_str(), "Intro");
+        assert!(song.remove_marker(5).is_none());
+        assert_eq!(song.find_marker("Chorus"), Some(1));
+    }
+}
@@ -0,0 +1,195 @@
+//! Approximate heap footprint of a song, by category.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem::size_of;
+
+use crate::graph::{AudioGraph, Connection, Node, NodeType, Parameter};
+use crate::groove::Groove;
+use crate::instrument::{Envelope, EnvelopePoint, Instrument};
+use crate::pattern::{Cell, Pattern, WireLane, WireValue};
+use crate::sample::{Sample, SampleData};
+use crate::song::{SeqEntry, Song, Track};
+
+/// Bytes held by a song, broken down by category.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryFootprint {
+    /// Sample headers and audio data
+    pub samples: usize,
+    /// Pattern cells, clips and sequences
+    pub patterns: usize,
+    /// Instruments and their envelopes
+    pub instruments: usize,
+    /// Graph nodes, parameters and connections
+    pub graph: usize,
+}
+
+impl MemoryFootprint {
+    /// Sum of all categories.
+    pub fn total(&self) -> usize {
+        self.samples + self.patterns + self.instruments + self.graph
+    }
+}
+
+impl fmt::Display for MemoryFootprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (samples {}, patterns {}, instruments {}, graph {})",
+            ByteSize(self.total()),
+            ByteSize(self.samples),
+            ByteSize(self.patterns),
+            ByteSize(self.instruments),
+            ByteSize(self.graph),
+        )
+    }
+}
+
+/// Human-readable byte count (B, KiB, MiB, GiB).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ByteSize(pub usize);
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+        if self.0 < 1024 {
+            return write!(f, "{} B", self.0);
+        }
+        let mut value = self.0 as f64 / 1024.0;
+        let mut unit = 0;
+        while value >= 1024.0 && unit < UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+        write!(f, "{:.1} {}", value, UNITS[unit])
+    }
+}
+
+impl SampleData {
+    /// Bytes of audio data.
+    pub fn memory_size(&self) -> usize {
+        match self {
+            SampleData::Mono8(v) => v.capacity(),
+            SampleData::Mono16(v) => v.capacity() * 2,
+            SampleData::Stereo8(l, r) => l.capacity() + r.capacity(),
+            SampleData::Stereo16(l, r) => (l.capacity() + r.capacity()) * 2,
+        }
+    }
+}
+
+impl Sample {
+    /// Bytes held by this sample, header included.
+    pub fn memory_size(&self) -> usize {
+        size_of::<Sample>() + self.data.memory_size()
+    }
+}
+
+impl Pattern {
+    /// Bytes held by this pattern, header included.
+    pub fn memory_size(&self) -> usize {
+        let wires: usize = self.wires.iter()
+            .map(|l| size_of::<WireLane>() + l.values.capacity() * size_of::<WireValue>())
+            .sum();
+        size_of::<Pattern>() + self.data.capacity() * size_of::<Cell>() + wires
+    }
+}
+
+impl Track {
+    fn memory_size(&self) -> usize {
+        let clips: usize = self.clips.iter().filter_map(|c| c.pattern()).map(Pattern::memory_size).sum();
+        let groove = match &self.groove {
+            Some(Groove::Ticks(ticks)) => ticks.capacity(),
+            _ => 0,
+        };
+        size_of::<Track>() + clips + self.sequence.capacity() * size_of::<SeqEntry>() + groove
+    }
+}
+
+fn envelope_size(env: &Option<Envelope>) -> usize {
+    env.as_ref().map_or(0, |e| e.points.capacity() * size_of::<EnvelopePoint>())
+}
+
+impl Instrument {
+    fn memory_size(&self) -> usize {
+        size_of::<Instrument>()
+            + envelope_size(&self.volume_envelope)
+            + envelope_size(&self.panning_envelope)
+            + envelope_size(&self.pitch_envelope)
+    }
+}
+
+impl AudioGraph {
+    fn memory_size(&self) -> usize {
+        let nodes: usize = self.nodes.iter().map(|n| {
+            let name = match &n.node_type {
+                NodeType::Machine { machine_name, .. } => machine_name.capacity(),
+                NodeType::Plugin { path, plugin_id } => path.capacity() + plugin_id.capacity(),
+                NodeType::MidiOut { port, .. } => port.capacity(),
+                _ => 0,
+            };
+            size_of::<Node>() + name + n.parameters.capacity() * size_of::<Parameter>()
+        }).sum();
+        nodes + self.connections.capacity() * size_of::<Connection>()
+    }
+}
+
+impl Song {
+    /// Approximate heap usage of the song, by category.
+    pub fn memory_footprint(&self) -> MemoryFootprint {
+        MemoryFootprint {
+            samples: self.samples.iter().map(Sample::memory_size).sum(),
+            patterns: self.tracks.iter().map(Track::memory_size).sum(),
+            instruments: self.instruments.iter().map(Instrument::memory_size).sum(),
+            graph: self.graph.memory_size(),
+        }
+    }
+
+    /// Sample indices with their sizes, largest first — candidates for
+    /// downsampling when memory is tight.
+    pub fn samples_by_size(&self) -> Vec<(usize, usize)> {
+        let mut sizes: Vec<(usize, usize)> =
+            self.samples.iter().map(Sample::memory_size).enumerate().collect();
+        sizes.sort_by_key(|&(_, bytes)| core::cmp::Reverse(bytes));
+        sizes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+    use alloc::vec;
+
+    #[test]
+    fn sample_data_size_counts_all_channels() {
+        assert_eq!(SampleData::Mono8(vec![0; 100]).memory_size(), 100);
+        assert_eq!(SampleData::Mono16(vec![0; 100]).memory_size(), 200);
+        assert_eq!(SampleData::Stereo16(vec![0; 100], vec![0; 100]).memory_size(), 400);
+    }
+
+    #[test]
+    fn footprint_breaks_down_by_category() {
+        let mut song = Song::new("mem");
+        let mut sample = Sample::new("big");
+        sample.set_data(SampleData::Mono16(vec![0; 10_000]));
+        song.samples.push(Sample::new("small"));
+        song.samples.push(sample);
+        let mut track = Track::new(None, 0, 4);
+        track.clips.push(crate::song::Clip::Pattern(Pattern::new(64, 4)));
+        song.tracks.push(track);
+
+        let fp = song.memory_footprint();
+        assert!(fp.samples >= 20_000);
+        assert!(fp.patterns >= 64 * 4 * size_of::<Cell>());
+        assert!(fp.graph >= size_of::<Node>());
+        assert_eq!(fp.total(), fp.samples + fp.patterns + fp.instruments + fp.graph);
+        assert_eq!(song.samples_by_size()[0].0, 1);
+    }
+
+    #[test]
+    fn byte_size_formats_units() {
+        assert_eq!(format!("{}", ByteSize(512)), "512 B");
+        assert_eq!(format!("{}", ByteSize(1536)), "1.5 KiB");
+        assert_eq!(format!("{}", ByteSize(3 * 1024 * 1024)), "3.0 MiB");
+    }
+}
@@ -0,0 +1,178 @@
+//! Time signatures: how beats group into bars.
+//!
+//! `MusicalTime` counts beats; the meter counts bars on top of it for
+//! position displays, metronome accents and editor highlighting. A
+//! signature change always starts a new bar, so a change that falls
+//! inside a bar cuts that bar short.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::musical_time::{MusicalTime, SUB_BEAT_UNIT};
+
+/// Beats per bar and the note value of a beat (as in 6/8).
+///
+/// Only `beats_per_bar` affects timing: a beat is always one
+/// `MusicalTime` beat. `beat_unit` is kept for display.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeSignature {
+    /// Beats per bar (0 is treated as 1)
+    pub beats_per_bar: u8,
+    /// Note value of a beat (4 = quarter note)
+    pub beat_unit: u8,
+}
+
+impl TimeSignature {
+    pub const fn new(beats_per_bar: u8, beat_unit: u8) -> Self {
+        Self { beats_per_bar, beat_unit }
+    }
+
+    /// Beats per bar, at least 1.
+    pub fn beats(&self) -> u64 {
+        self.beats_per_bar.max(1) as u64
+    }
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self::new(4, 4)
+    }
+}
+
+impl fmt::Display for TimeSignature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.beats_per_bar, self.beat_unit)
+    }
+}
+
+/// A song position in bars: `bar` and `beat` count from 0, `row` is the
+/// row within the beat.
+///
+/// Displays as `bar:beat:row` with bar and beat counted from 1, as
+/// musicians count them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BarPosition {
+    pub bar: u64,
+    pub beat: u32,
+    pub row: u32,
+}
+
+impl fmt::Display for BarPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.bar + 1, self.beat + 1, self.row)
+    }
+}
+
+/// The song's time signatures over time.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Meter {
+    /// Signature from the start of the song
+    pub initial: TimeSignature,
+    /// Later changes as (beat, signature), sorted by beat, all after beat 0
+    changes: Vec<(u64, TimeSignature)>,
+}
+
+impl Meter {
+    /// A meter with one signature throughout.
+    pub fn new(signature: TimeSignature) -> Self {
+        Self { initial: signature, changes: Vec::new() }
+    }
+
+    /// The signature changes after the start, as (beat, signature).
+    pub fn changes(&self) -> &[(u64, TimeSignature)] {
+        &self.changes
+    }
+
+    /// Change to `signature` at `beat`, or drop the change there with
+    /// `None`. At beat 0 this sets (or resets) the initial signature.
+    pub fn set(&mut self, beat: u64, signature: Option<TimeSignature>) {
+        if beat == 0 {
+            self.initial = signature.unwrap_or_default();
+            return;
+        }
+        match (self.changes.binary_search_by_key(&beat, |&(b, _)| b), signature) {
+            (Ok(i), Some(signature)) => self.changes[i].1 = signature,
+            (Ok(i), None) => {
+                self.changes.remove(i);
+            }
+            (Err(i), Some(signature)) => self.changes.insert(i, (beat, signature)),
+            (Err(_), None) => {}
+        }
+    }
+
+    /// The signature in force at `beat`.
+    pub fn signature_at(&self, beat: u64) -> TimeSignature {
+        let i = self.changes.partition_point(|&(b, _)| b <= beat);
+        i.checked_sub(1).map_or(self.initial, |i| self.changes[i].1)
+    }
+
+    /// Bar containing `beat` and the beat's index within it.
+    pub fn bar_beat(&self, beat: u64) -> (u64, u32) {
+        let (mut bar, mut from, mut signature) = (0, 0, self.initial);
+        for &(at, next) in &self.changes {
+            if at > beat {
+                break;
+            }
+            bar += (at - from).div_ceil(signature.beats());
+            (from, signature) = (at, next);
+        }
+        let beats = beat - from;
+        (bar + beats / signature.beats(), (beats % signature.beats()) as u32)
+    }
+
+    /// The beat bar `bar` starts on.
+    pub fn bar_start(&self, bar: u64) -> u64 {
+        let (mut first_bar, mut from, mut signature) = (0, 0, self.initial);
+        for &(at, next) in &self.changes {
+            let bars = (at - from).div_ceil(signature.beats());
+            if first_bar + bars > bar {
+                break;
+            }
+            first_bar += bars;
+            (from, signature) = (at, next);
+        }
+        from + (bar - first_bar) * signature.beats()
+    }
+
+    /// Where `time` falls in bars, beats and rows.
+    pub fn position(&self, time: MusicalTime, rows_per_beat: u32) -> BarPosition {
+        let (bar, beat) = self.bar_beat(time.beat);
+        let row = (time.sub_beat as u64 * rows_per_beat as u64 / SUB_BEAT_UNIT as u64) as u32;
+        BarPosition { bar, beat, row }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_start_new_bars_and_cut_the_last_one_short() {
+        let mut meter = Meter::default();
+        // Bars 0-1 in 4/4, a 3/4 change mid-bar at beat 6, then 7/8 at beat 12
+        meter.set(6, Some(TimeSignature::new(3, 4)));
+        meter.set(12, Some(TimeSignature::new(7, 8)));
+        assert_eq!(meter.bar_beat(5), (1, 1));
+        assert_eq!(meter.bar_beat(6), (2, 0));
+        assert_eq!(meter.bar_beat(11), (3, 2));
+        assert_eq!(meter.bar_beat(20), (5, 1));
+        assert_eq!(meter.signature_at(13).to_string(), "7/8");
+        for bar in 0..8 {
+            assert_eq!(meter.bar_beat(meter.bar_start(bar)), (bar, 0));
+        }
+
+        meter.set(6, None);
+        assert_eq!(meter.bar_beat(11), (2, 3));
+        meter.set(0, Some(TimeSignature::new(3, 4)));
+        assert_eq!(meter.bar_beat(11), (3, 2));
+    }
+
+    #[test]
+    fn position_counts_rows_within_the_beat() {
+        let meter = Meter::new(TimeSignature::new(3, 4));
+        let time = MusicalTime::from_beats(4).add_rows(3, 4);
+        let position = meter.position(time, 4);
+        assert_eq!(position, BarPosition { bar: 1, beat: 1, row: 3 });
+        assert_eq!(position.to_string(), "2:2:3");
+    }
+}
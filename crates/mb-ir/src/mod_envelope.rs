@@ -102,6 +102,10 @@ impl ModEnvelope {
         self.points.len()
     }
 
+    /// Returns true if the envelope has no breakpoints.
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
 }
 
 impl ModBreakPoint {
@@ -57,6 +57,44 @@ impl MusicalTime {
             sub_beat: remaining,
         }
     }
+
+    /// Total position expressed in sub-beat units.
+    pub fn as_sub_beats(self) -> u64 {
+        self.beat * SUB_BEAT_UNIT as u64 + self.sub_beat as u64
+    }
+
+    /// Build a time from a total count of sub-beat units.
+    pub fn from_sub_beats(total: u64) -> Self {
+        Self {
+            beat: total / SUB_BEAT_UNIT as u64,
+            sub_beat: (total % SUB_BEAT_UNIT as u64) as u32,
+        }
+    }
+
+    /// Move back by `sub_beats` units, saturating at zero.
+    pub fn saturating_sub_sub_beats(self, sub_beats: u64) -> Self {
+        Self::from_sub_beats(self.as_sub_beats().saturating_sub(sub_beats))
+    }
+
+    /// Snap to the nearest multiple of 1/`subdivision` beat (ties round up).
+    /// A `subdivision` of 0 leaves the time unchanged.
+    pub fn quantize(self, subdivision: u32) -> Self {
+        let Some(grid) = Self::grid(subdivision) else { return self };
+        let total = self.as_sub_beats();
+        Self::from_sub_beats((total + grid / 2) / grid * grid)
+    }
+
+    /// Round up to the next multiple of 1/`subdivision` beat (unchanged if
+    /// already on the grid). A `subdivision` of 0 leaves the time unchanged.
+    pub fn quantize_up(self, subdivision: u32) -> Self {
+        let Some(grid) = Self::grid(subdivision) else { return self };
+        Self::from_sub_beats(self.as_sub_beats().div_ceil(grid) * grid)
+    }
+
+    /// Grid spacing in sub-beat units for `subdivision` steps per beat.
+    fn grid(subdivision: u32) -> Option<u64> {
+        (subdivision > 0).then(|| (SUB_BEAT_UNIT / subdivision).max(1) as u64)
+    }
 }
 
 impl PartialOrd for MusicalTime {
@@ -154,6 +192,20 @@ mod tests {
         assert_eq!(t.add_ticks(10, 0), t);
     }
 
+    #[test]
+    fn sub_beats_round_trip() {
+        let t = MusicalTime { beat: 3, sub_beat: 1234 };
+        assert_eq!(t.as_sub_beats(), 3 * SUB_BEAT_UNIT as u64 + 1234);
+        assert_eq!(MusicalTime::from_sub_beats(t.as_sub_beats()), t);
+    }
+
+    #[test]
+    fn saturating_sub_sub_beats_clamps_at_zero() {
+        let t = MusicalTime::from_beats(1);
+        assert_eq!(t.saturating_sub_sub_beats(SUB_BEAT_UNIT as u64 / 2).sub_beat, SUB_BEAT_UNIT / 2);
+        assert_eq!(t.saturating_sub_sub_beats(10 * SUB_BEAT_UNIT as u64), MusicalTime::zero());
+    }
+
     #[test]
     fn sub_beat_unit_divisibility() {
         // SUB_BEAT_UNIT should be evenly divisible by 1..16
@@ -164,4 +216,22 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn quantize_snaps_to_nearest_grid_step() {
+        let sixteenth = SUB_BEAT_UNIT / 4;
+        let t = MusicalTime { beat: 2, sub_beat: sixteenth + 10 };
+        assert_eq!(t.quantize(4), MusicalTime { beat: 2, sub_beat: sixteenth });
+        let t = MusicalTime { beat: 2, sub_beat: SUB_BEAT_UNIT - 10 };
+        assert_eq!(t.quantize(4), MusicalTime::from_beats(3));
+        assert_eq!(t.quantize(0), t);
+    }
+
+    #[test]
+    fn quantize_up_rounds_to_next_grid_step() {
+        let t = MusicalTime { beat: 1, sub_beat: 1 };
+        assert_eq!(t.quantize_up(1), MusicalTime::from_beats(2));
+        assert_eq!(t.quantize_up(2), MusicalTime { beat: 1, sub_beat: SUB_BEAT_UNIT / 2 });
+        assert_eq!(MusicalTime::from_beats(3).quantize_up(4), MusicalTime::from_beats(3));
+    }
 }
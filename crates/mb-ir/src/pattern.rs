@@ -2,6 +2,7 @@
 
 use alloc::vec::Vec;
 use crate::effects::{Effect, VolumeCommand};
+use crate::graph::NodeId;
 
 /// A note value in a pattern cell.
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
@@ -31,6 +32,18 @@ impl Note {
         }
     }
 
+    /// Highest note number.
+    pub const MAX: u8 = 119;
+
+    /// Shift a note on by `semitones`, clamped to the note range; other
+    /// notes are returned unchanged.
+    pub fn transposed(self, semitones: i16) -> Self {
+        match self {
+            Note::On(n) => Note::On((n as i16 + semitones).clamp(0, Self::MAX as i16) as u8),
+            other => other,
+        }
+    }
+
     /// Get the semitone (0-11) if this is a note on.
     pub const fn semitone(self) -> Option<u8> {
         match self {
@@ -51,6 +64,9 @@ pub struct Cell {
     pub volume: VolumeCommand,
     /// Effect column command
     pub effect: Effect,
+    /// Second effect column command (e.g. Matilde Tracker 2), applied
+    /// alongside `effect`
+    pub effect2: Effect,
 }
 
 impl Cell {
@@ -61,20 +77,27 @@ impl Cell {
             instrument: 0,
             volume: VolumeCommand::None,
             effect: Effect::None,
+            effect2: Effect::None,
         }
     }
 
+    /// Both effect columns, first column first.
+    pub fn effects(&self) -> [Effect; 2] {
+        [self.effect, self.effect2]
+    }
+
     /// Returns true if the cell is completely empty.
     pub fn is_empty(&self) -> bool {
         self.note == Note::None
             && self.instrument == 0
             && self.volume == VolumeCommand::None
             && self.effect == Effect::None
+            && self.effect2 == Effect::None
     }
 }
 
 /// A pattern containing rows of cells across channels.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Pattern {
     /// Number of rows (typically 64, can be 1-256)
     pub rows: u16,
@@ -86,6 +109,33 @@ pub struct Pattern {
     pub rows_per_beat: Option<u8>,
     /// Pattern data, stored row-major: data[row * channels + channel]
     pub data: Vec<Cell>,
+    /// Per-row automation of the wires into the track's machine
+    pub wires: Vec<WireLane>,
+}
+
+/// Gain and pan changes for the wire from one node, one value per pattern row.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WireLane {
+    /// Source node of the wire
+    pub from: NodeId,
+    /// Row values; `values.len()` matches the pattern's row count
+    pub values: Vec<WireValue>,
+}
+
+/// A wire change on one row. `None` leaves that setting as it is.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WireValue {
+    /// Gain in fixed-point dB (see `Connection::gain`)
+    pub gain: Option<i16>,
+    /// Stereo balance, -64 to +64
+    pub pan: Option<i8>,
+}
+
+impl WireValue {
+    /// Returns true if the row changes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.gain.is_none() && self.pan.is_none()
+    }
 }
 
 impl Pattern {
@@ -97,9 +147,23 @@ impl Pattern {
             ticks_per_row: 0,
             rows_per_beat: None,
             data: alloc::vec![Cell::empty(); rows as usize * channels as usize],
+            wires: Vec::new(),
         }
     }
 
+    /// The wire lane for the wire from `from`, added empty if missing.
+    pub fn wire_lane_mut(&mut self, from: NodeId) -> &mut WireLane {
+        let idx = match self.wires.iter().position(|l| l.from == from) {
+            Some(idx) => idx,
+            None => {
+                let values = alloc::vec![WireValue::default(); self.rows as usize];
+                self.wires.push(WireLane { from, values });
+                self.wires.len() - 1
+            }
+        };
+        &mut self.wires[idx]
+    }
+
     /// Get a reference to a cell.
     pub fn cell(&self, row: u16, channel: u8) -> &Cell {
         debug_assert!(row < self.rows);
@@ -119,6 +183,58 @@ impl Pattern {
         if self.ticks_per_row > 0 { self.ticks_per_row } else { 6 }
     }
 
+    /// Change the row count (at least 1), dropping rows off the end or
+    /// adding empty ones.
+    pub fn resize(&mut self, rows: u16) {
+        self.rows = rows.max(1);
+        self.data.resize(self.rows as usize * self.channels as usize, Cell::empty());
+        for lane in &mut self.wires {
+            lane.values.resize(self.rows as usize, WireValue::default());
+        }
+    }
+
+    /// Insert `count` empty rows at `row`, pushing the rows below down;
+    /// rows pushed past the end are lost. The row count doesn't change.
+    pub fn insert_rows(&mut self, row: u16, count: u16) {
+        let Some(span) = self.row_span(row, count) else { return };
+        let tail = &mut self.data[span.start..];
+        tail.rotate_right(span.len());
+        tail[..span.len()].fill(Cell::empty());
+        let (start, count) = (row as usize, count.min(self.rows - row) as usize);
+        for lane in &mut self.wires {
+            let tail = &mut lane.values[start..];
+            tail.rotate_right(count);
+            tail[..count].fill(WireValue::default());
+        }
+    }
+
+    /// Delete `count` rows at `row`, pulling the rows below up and leaving
+    /// empty rows at the end. The row count doesn't change.
+    pub fn delete_rows(&mut self, row: u16, count: u16) {
+        let Some(span) = self.row_span(row, count) else { return };
+        let tail = &mut self.data[span.start..];
+        tail.rotate_left(span.len());
+        let end = tail.len() - span.len();
+        tail[end..].fill(Cell::empty());
+        let (start, count) = (row as usize, count.min(self.rows - row) as usize);
+        for lane in &mut self.wires {
+            let tail = &mut lane.values[start..];
+            tail.rotate_left(count);
+            let end = tail.len() - count;
+            tail[end..].fill(WireValue::default());
+        }
+    }
+
+    /// Cell indices of `count` rows from `row`, clipped to the pattern.
+    fn row_span(&self, row: u16, count: u16) -> Option<core::ops::Range<usize>> {
+        if row >= self.rows || count == 0 {
+            return None;
+        }
+        let width = self.channels as usize;
+        let count = count.min(self.rows - row) as usize;
+        let start = row as usize * width;
+        Some(start..start + count * width)
+    }
 }
 
 #[cfg(test)]
@@ -136,6 +252,54 @@ mod tests {
         assert_eq!(a4, Note::On(57));
     }
 
+    fn column(pat: &Pattern, channel: u8) -> Vec<Note> {
+        (0..pat.rows).map(|r| pat.cell(r, channel).note).collect()
+    }
+
+    fn numbered(rows: u16) -> Pattern {
+        let mut pat = Pattern::new(rows, 2);
+        for r in 0..rows {
+            pat.cell_mut(r, 1).note = Note::On(r as u8);
+        }
+        pat
+    }
+
+    #[test]
+    fn resize_keeps_leading_rows() {
+        let mut pat = numbered(4);
+        pat.resize(6);
+        assert_eq!(pat.data.len(), 12);
+        assert_eq!(column(&pat, 1)[3..], [Note::On(3), Note::None, Note::None]);
+        pat.resize(0);
+        assert_eq!((pat.rows, column(&pat, 1)), (1, alloc::vec![Note::On(0)]));
+    }
+
+    #[test]
+    fn insert_and_delete_rows_shift_cells() {
+        let mut pat = numbered(4);
+        pat.insert_rows(1, 2);
+        assert_eq!(column(&pat, 1), [Note::On(0), Note::None, Note::None, Note::On(1)]);
+        pat.delete_rows(0, 3);
+        assert_eq!(column(&pat, 1), [Note::On(1), Note::None, Note::None, Note::None]);
+        pat.delete_rows(9, 1);
+        pat.insert_rows(3, 9);
+        assert_eq!(column(&pat, 1)[0], Note::On(1));
+    }
+
+    #[test]
+    fn wire_lanes_follow_row_edits() {
+        let mut pat = numbered(4);
+        let gain = |g| WireValue { gain: Some(g), pan: None };
+        pat.wire_lane_mut(3).values[1] = gain(-50);
+        pat.insert_rows(0, 1);
+        assert_eq!(pat.wires[0].values[2], gain(-50));
+        pat.delete_rows(0, 2);
+        assert_eq!(pat.wires[0].values, [gain(-50), WireValue::default(), WireValue::default(), WireValue::default()]);
+        pat.resize(2);
+        assert_eq!(pat.wire_lane_mut(3).values.len(), 2);
+        assert_eq!(pat.wires.len(), 1);
+    }
+
     #[test]
     fn pattern_cell_access() {
         let mut pattern = Pattern::new(64, 4);
@@ -1,15 +1,16 @@
 //! Sample data types.
 
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use arrayvec::ArrayString;
 
 /// A sample definition.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Sample {
     /// Sample name
     pub name: ArrayString<26>,
-    /// Audio data
-    pub data: SampleData,
+    /// Audio data, shared between clones of the song (copy-on-write via `data_mut`)
+    pub data: Arc<SampleData>,
     /// Loop start position (in samples)
     pub loop_start: u32,
     /// Loop end position (in samples)
@@ -30,7 +31,7 @@ impl Default for Sample {
     fn default() -> Self {
         Self {
             name: ArrayString::new(),
-            data: SampleData::Mono8(Vec::new()),
+            data: Arc::new(SampleData::Mono8(Vec::new())),
             loop_start: 0,
             loop_end: 0,
             loop_type: LoopType::None,
@@ -50,6 +51,17 @@ impl Sample {
         sample
     }
 
+    /// Replace the audio data.
+    pub fn set_data(&mut self, data: SampleData) {
+        self.data = Arc::new(data);
+    }
+
+    /// Mutable access to the audio data, copying it first if another song
+    /// copy (e.g. a playing engine) still shares it.
+    pub fn data_mut(&mut self) -> &mut SampleData {
+        Arc::make_mut(&mut self.data)
+    }
+
     /// Get the length of the sample in frames.
     pub fn len(&self) -> usize {
         self.data.len()
@@ -64,10 +76,58 @@ impl Sample {
     pub fn has_loop(&self) -> bool {
         self.loop_type != LoopType::None && self.loop_end > self.loop_start
     }
+
+    /// How interpolation taps wrap around the sample's loop, if it has
+    /// one. `wrapped` is whether playback has been round the loop yet.
+    pub fn loop_taps(&self, wrapped: bool) -> Option<LoopTaps> {
+        self.has_loop().then_some(LoopTaps {
+            start: self.loop_start as usize,
+            end: self.loop_end as usize,
+            ping_pong: self.loop_type == LoopType::PingPong,
+            wrapped,
+        })
+    }
+}
+
+/// Where interpolation taps falling outside a sample loop read from, so
+/// filters spanning the loop point hear the frames played next rather
+/// than whatever is stored past the loop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoopTaps {
+    /// First frame of the loop
+    pub start: usize,
+    /// Frame just past the loop
+    pub end: usize,
+    /// Ping-pong loops reflect off their first and last frames
+    pub ping_pong: bool,
+    /// Playback has been round the loop, so frames before `start` are no
+    /// longer the ones heard before the current one
+    pub wrapped: bool,
+}
+
+impl LoopTaps {
+    /// The frame heard at `frame`, which may lie outside the loop (and
+    /// below zero, as a wrapped `usize`).
+    fn frame(&self, frame: usize) -> usize {
+        let offset = frame.wrapping_sub(self.start) as isize;
+        if (offset < 0 && !self.wrapped) || (offset >= 0 && frame < self.end) {
+            return frame;
+        }
+        if self.ping_pong {
+            // Forward to the last frame and back, without repeating either end
+            let span = (self.end - self.start - 1) as isize;
+            if span == 0 {
+                return self.start;
+            }
+            let m = offset.rem_euclid(2 * span);
+            return self.start + if m <= span { m } else { 2 * span - m } as usize;
+        }
+        self.start + offset.rem_euclid((self.end - self.start) as isize) as usize
+    }
 }
 
 /// Sample audio data.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum SampleData {
     /// 8-bit mono samples
     Mono8(Vec<i8>),
@@ -139,6 +199,30 @@ impl SampleData {
         (left, right)
     }
 
+    /// Get stereo sample values as (left, right), reconstructed with `quality`.
+    ///
+    /// `pos` is a 16.16 fixed-point position. Frames past the
+    /// edges of `taps`' loop read from inside it; linear keeps blending
+    /// with the stored frame after the loop, so renders at the default
+    /// quality don't change. Frames outside the sample read as silence.
+    pub fn get_stereo_with(&self, pos_fixed: u64, quality: Interpolation, taps: Option<LoopTaps>) -> (i16, i16) {
+        let at = |i: usize| taps.map_or(i, |t| t.frame(i));
+        let left = |i: usize| self.get_mono(at(i));
+        let right = |i: usize| self.get_right(at(i));
+        match quality {
+            Interpolation::Nearest => {
+                let idx = ((pos_fixed + 0x8000) >> 16) as usize;
+                (left(idx), right(idx))
+            }
+            Interpolation::Linear => self.get_stereo_interpolated(pos_fixed),
+            Interpolation::Cubic => (cubic(left, pos_fixed), cubic(right, pos_fixed)),
+            Interpolation::Sinc => {
+                let (idx, weights) = sinc_weights(pos_fixed);
+                (convolve(left, idx, &weights), convolve(right, idx, &weights))
+            }
+        }
+    }
+
     /// Number of channels in the sample data.
     pub fn num_channels(&self) -> u16 {
         match self {
@@ -172,6 +256,76 @@ impl crate::audio_traits::AudioSource for SampleData {
     }
 }
 
+/// How sample values between stored frames are reconstructed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    /// The nearest frame (cheapest, aliases audibly)
+    Nearest,
+    /// A straight line between the two nearest frames
+    #[default]
+    Linear,
+    /// Cubic Hermite (Catmull-Rom) through the four nearest frames
+    Cubic,
+    /// Lanczos-windowed sinc over the eight nearest frames (slowest; meant
+    /// for offline renders)
+    Sinc,
+}
+
+/// Half the number of frames the windowed sinc reads.
+const SINC_HALF_TAPS: usize = 4;
+
+/// Catmull-Rom spline through the frames around `pos_fixed`.
+fn cubic(frame: impl Fn(usize) -> i16, pos_fixed: u64) -> i16 {
+    let idx = (pos_fixed >> 16) as usize;
+    let t = (pos_fixed & 0xFFFF) as f32 / 65536.0;
+    let p0 = frame(idx.wrapping_sub(1)) as f32;
+    let p1 = frame(idx) as f32;
+    let p2 = frame(idx + 1) as f32;
+    let p3 = frame(idx + 2) as f32;
+    let y = p1 + 0.5 * t * (p2 - p0 + t * (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3 + t * (3.0 * (p1 - p2) + p3 - p0)));
+    libm::roundf(y).clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+/// First frame and normalized Lanczos weights for the frames around `pos_fixed`.
+fn sinc_weights(pos_fixed: u64) -> (usize, [f32; 2 * SINC_HALF_TAPS]) {
+    let idx = (pos_fixed >> 16) as usize;
+    let t = (pos_fixed & 0xFFFF) as f32 / 65536.0;
+    let mut weights = [0.0f32; 2 * SINC_HALF_TAPS];
+    let mut sum = 0.0;
+    for (k, w) in weights.iter_mut().enumerate() {
+        // Distance from the frame at idx + k + 1 - SINC_HALF_TAPS
+        let x = t - (k as f32 + 1.0 - SINC_HALF_TAPS as f32);
+        *w = lanczos(x);
+        sum += *w;
+    }
+    for w in &mut weights {
+        *w /= sum;
+    }
+    (idx.wrapping_sub(SINC_HALF_TAPS - 1), weights)
+}
+
+/// sinc(x) windowed by sinc(x / SINC_HALF_TAPS).
+fn lanczos(x: f32) -> f32 {
+    use core::f32::consts::PI;
+    if x.abs() < 1e-6 {
+        return 1.0;
+    }
+    let a = SINC_HALF_TAPS as f32;
+    if x.abs() >= a {
+        return 0.0;
+    }
+    let px = PI * x;
+    a * libm::sinf(px) * libm::sinf(px / a) / (px * px)
+}
+
+/// Weighted sum of the frames from `first` on.
+fn convolve(frame: impl Fn(usize) -> i16, first: usize, weights: &[f32]) -> i16 {
+    let y: f32 = weights.iter().enumerate()
+        .map(|(k, w)| frame(first.wrapping_add(k)) as f32 * w)
+        .sum();
+    libm::roundf(y).clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
 /// Sample loop type.
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum LoopType {
@@ -187,7 +341,7 @@ pub enum LoopType {
 }
 
 /// Auto-vibrato settings for a sample.
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct AutoVibrato {
     /// Vibrato speed
     pub speed: u8,
@@ -199,6 +353,30 @@ pub struct AutoVibrato {
     pub waveform: u8,
 }
 
+impl AutoVibrato {
+    /// Waveform value (-64..=64) at `phase` (256 steps per cycle).
+    ///
+    /// The random waveform has no fixed shape, so the caller supplies the
+    /// value to use (`random`, -64..=64).
+    pub fn waveform_value(&self, phase: u8, random: i8) -> i32 {
+        match self.waveform {
+            1 => 64 - (phase as i32 * 128 + 128) / 256,
+            2 => if phase < 128 { 64 } else { -64 },
+            3 => random as i32,
+            _ => libm::roundf(libm::sinf(phase as f32 * core::f32::consts::TAU / 256.0) * 64.0) as i32,
+        }
+    }
+
+    /// Depth scale (0-256) after `ticks` ticks: ramps up linearly over
+    /// `sweep` ticks (0 = full depth at once).
+    pub fn sweep_scale(&self, ticks: u32) -> u32 {
+        if self.sweep == 0 {
+            return 256;
+        }
+        (ticks * 256 / self.sweep as u32).min(256)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -207,6 +385,99 @@ mod tests {
         SampleData::Mono8(data.to_vec())
     }
 
+    #[test]
+    fn auto_vibrato_waveforms() {
+        let mut vib = AutoVibrato { speed: 8, depth: 8, sweep: 0, waveform: 0 };
+        assert_eq!(vib.waveform_value(0, 0), 0);
+        assert_eq!(vib.waveform_value(64, 0), 64);
+        assert_eq!(vib.waveform_value(192, 0), -64);
+        vib.waveform = 1;
+        assert_eq!(vib.waveform_value(0, 0), 64);
+        assert_eq!(vib.waveform_value(255, 0), -64);
+        vib.waveform = 2;
+        assert_eq!((vib.waveform_value(0, 0), vib.waveform_value(128, 0)), (64, -64));
+        vib.waveform = 3;
+        assert_eq!(vib.waveform_value(10, -7), -7);
+    }
+
+    #[test]
+    fn auto_vibrato_sweep_ramps_depth() {
+        let mut vib = AutoVibrato { speed: 8, depth: 8, sweep: 0, waveform: 0 };
+        assert_eq!(vib.sweep_scale(0), 256);
+        vib.sweep = 4;
+        assert_eq!(vib.sweep_scale(0), 0);
+        assert_eq!(vib.sweep_scale(2), 128);
+        assert_eq!(vib.sweep_scale(10), 256);
+    }
+
+    #[test]
+    fn clones_share_data_until_written() {
+        let mut a = Sample::new("shared");
+        a.set_data(mono8_sample(&[1, 2, 3]));
+        let mut b = a.clone();
+        assert!(Arc::ptr_eq(&a.data, &b.data));
+
+        if let SampleData::Mono8(v) = b.data_mut() {
+            v[0] = 99;
+        }
+        assert!(!Arc::ptr_eq(&a.data, &b.data));
+        assert_eq!(a.data.get_mono(0), 1 << 8);
+        assert_eq!(b.data.get_mono(0), 99 << 8);
+    }
+
+    #[test]
+    fn nearest_rounds_to_closest_frame() {
+        let data = mono8_sample(&[10, 20, 30]);
+        assert_eq!(data.get_stereo_with(0x7FFF, Interpolation::Nearest, None).0, 10 << 8);
+        assert_eq!(data.get_stereo_with(0x8000, Interpolation::Nearest, None).0, 20 << 8);
+    }
+
+    #[test]
+    fn cubic_and_sinc_hit_stored_frames() {
+        let data = mono8_sample(&[0, 100, -50, 30, 70, -20, 5, 60, -90, 40]);
+        for quality in [Interpolation::Cubic, Interpolation::Sinc] {
+            for i in 0..data.len() {
+                let pos = (i as u64) << 16;
+                assert_eq!(data.get_stereo_with(pos, quality, None).0, data.get_mono(i), "{quality:?} at {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn cubic_follows_a_ramp_exactly() {
+        let data = mono8_sample(&[0, 10, 20, 30, 40]);
+        assert_eq!(data.get_stereo_with((1 << 16) + 0x4000, Interpolation::Cubic, None).0, 12 << 8 | 128);
+    }
+
+    #[test]
+    fn taps_wrap_inside_the_loop() {
+        // A loop over the first four frames, followed by data it never plays
+        let mut sample = Sample::new("loop");
+        sample.data = mono8_sample(&[0, 100, -50, 30, 127, 127, 127, 127, 127]).into();
+        (sample.loop_start, sample.loop_end, sample.loop_type) = (0, 4, LoopType::Forward);
+        let forward = mono8_sample(&[0, 100, -50, 30, 0, 100, -50, 30, 0]);
+        sample.loop_type = LoopType::PingPong;
+        let ping_pong = mono8_sample(&[0, 100, -50, 30, -50, 100, 0, 100, -50]);
+
+        for quality in [Interpolation::Nearest, Interpolation::Cubic, Interpolation::Sinc] {
+            let pos = (3 << 16) + 0x8000;
+            sample.loop_type = LoopType::Forward;
+            let looped = sample.data.get_stereo_with(pos, quality, sample.loop_taps(true));
+            assert_eq!(looped, forward.get_stereo_with(pos, quality, None), "{quality:?}");
+            sample.loop_type = LoopType::PingPong;
+            let looped = sample.data.get_stereo_with(pos, quality, sample.loop_taps(true));
+            assert_eq!(looped, ping_pong.get_stereo_with(pos, quality, None), "{quality:?}");
+        }
+    }
+
+    #[test]
+    fn sinc_keeps_constant_signal_level() {
+        let data = mono8_sample(&[50; 32]);
+        let (left, right) = data.get_stereo_with((12 << 16) + 0x5555, Interpolation::Sinc, None);
+        assert!((left as i32 - (50 << 8)).abs() <= 1);
+        assert_eq!(left, right);
+    }
+
     #[test]
     fn interpolated_at_integer_matches_nearest() {
         let data = mono8_sample(&[0, 100, -50, 30]);
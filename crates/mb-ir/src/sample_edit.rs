@@ -0,0 +1,357 @@
+//! Destructive sample editing: gain, fades, reverse, trim, cleanup,
+//! resampling and format conversion.
+//!
+//! Operations work on any `SampleData` layout. Audio is processed as
+//! floats and written back at the sample's own bit depth (unless the
+//! operation converts it), and loop points follow frames that move.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::sample::{Sample, SampleData};
+
+/// A sample editing operation. Frame ranges are clamped to the sample.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SampleOp {
+    /// Scale so the loudest frame reaches full scale
+    Normalize,
+    /// Multiply by a linear gain, clipping at full scale
+    Gain(f32),
+    /// Ramp frames `start..end` up from silence
+    FadeIn { start: u32, end: u32 },
+    /// Ramp frames `start..end` down to silence
+    FadeOut { start: u32, end: u32 },
+    /// Play frames `start..end` backwards
+    Reverse { start: u32, end: u32 },
+    /// Subtract each channel's average so it centers on zero
+    RemoveDcOffset,
+    /// Keep only frames `start..end`
+    Trim { start: u32, end: u32 },
+    /// Resample from `c4_speed` to `rate` Hz with a windowed-sinc filter,
+    /// keeping the pitch (`c4_speed` becomes `rate`)
+    Resample { rate: u32 },
+    /// Convert to another channel count and bit depth
+    Convert(SampleFormat),
+    /// Blend the last `frames` frames of the loop into the frames leading
+    /// up to the loop start, so the jump back is seamless
+    CrossfadeLoop { frames: u32 },
+}
+
+/// Channel layout and bit depth of `SampleData`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleFormat {
+    Mono8,
+    Mono16,
+    Stereo8,
+    Stereo16,
+}
+
+impl SampleFormat {
+    /// The format of `data`.
+    pub fn of(data: &SampleData) -> Self {
+        match data {
+            SampleData::Mono8(_) => SampleFormat::Mono8,
+            SampleData::Mono16(_) => SampleFormat::Mono16,
+            SampleData::Stereo8(..) => SampleFormat::Stereo8,
+            SampleData::Stereo16(..) => SampleFormat::Stereo16,
+        }
+    }
+
+    fn is_stereo(self) -> bool {
+        matches!(self, SampleFormat::Stereo8 | SampleFormat::Stereo16)
+    }
+}
+
+/// Sinc filter zero crossings on each side of the output frame (at the
+/// lower of the two rates).
+const SINC_ZEROS: f64 = 16.0;
+
+impl Sample {
+    /// Apply `op` to the audio data; returns false if it has no effect
+    /// (empty range, silent sample to normalize).
+    pub fn apply_op(&mut self, op: SampleOp) -> bool {
+        let len = self.len() as u32;
+        let range = |start: u32, end: u32| {
+            let end = end.min(len);
+            (start < end).then_some((start as usize, end as usize))
+        };
+        let mut channels = channels_f32(&self.data);
+        let mut format = SampleFormat::of(&self.data);
+        match op {
+            SampleOp::Normalize => {
+                let peak = channels.iter().flatten().fold(0.0f32, |m, s| m.max(s.abs()));
+                if peak == 0.0 {
+                    return false;
+                }
+                scale(&mut channels, 0..len as usize, |_| 1.0 / peak);
+            }
+            SampleOp::Gain(gain) => scale(&mut channels, 0..len as usize, |_| gain),
+            SampleOp::FadeIn { start, end } => {
+                let Some((start, end)) = range(start, end) else { return false };
+                let steps = (end - start - 1).max(1) as f32;
+                scale(&mut channels, start..end, |i| (i - start) as f32 / steps);
+            }
+            SampleOp::FadeOut { start, end } => {
+                let Some((start, end)) = range(start, end) else { return false };
+                let steps = (end - start - 1).max(1) as f32;
+                scale(&mut channels, start..end, |i| (end - 1 - i) as f32 / steps);
+            }
+            SampleOp::Reverse { start, end } => {
+                let Some((start, end)) = range(start, end) else { return false };
+                for ch in &mut channels {
+                    ch[start..end].reverse();
+                }
+                if (start, end) == (0, len as usize) {
+                    (self.loop_start, self.loop_end) = (len.saturating_sub(self.loop_end), len.saturating_sub(self.loop_start));
+                }
+            }
+            SampleOp::RemoveDcOffset => {
+                for ch in &mut channels {
+                    let mean = ch.iter().sum::<f32>() / ch.len().max(1) as f32;
+                    ch.iter_mut().for_each(|s| *s -= mean);
+                }
+            }
+            SampleOp::Trim { start, end } => {
+                let Some((start, end)) = range(start, end) else { return false };
+                for ch in &mut channels {
+                    ch.truncate(end);
+                    ch.drain(..start);
+                }
+                let new_len = (end - start) as u32;
+                self.loop_end = self.loop_end.saturating_sub(start as u32).min(new_len);
+                self.loop_start = self.loop_start.saturating_sub(start as u32).min(self.loop_end);
+            }
+            SampleOp::Resample { rate } => {
+                if rate == 0 || self.c4_speed == 0 || rate == self.c4_speed || len == 0 {
+                    return false;
+                }
+                let ratio = rate as f64 / self.c4_speed as f64;
+                let to_new = |frame: u32| libm::round(frame as f64 * ratio) as u32;
+                let new_len = to_new(len).max(1) as usize;
+                channels = channels.iter().map(|ch| resample(ch, ratio, new_len)).collect();
+                (self.loop_start, self.loop_end) = (to_new(self.loop_start), to_new(self.loop_end).min(new_len as u32));
+                self.c4_speed = rate;
+            }
+            SampleOp::CrossfadeLoop { frames } => {
+                let (start, end) = (self.loop_start as usize, self.loop_end.min(len) as usize);
+                let n = (frames as usize).min(start).min(end.saturating_sub(start));
+                if !self.has_loop() || n == 0 {
+                    return false;
+                }
+                for ch in &mut channels {
+                    for i in 0..n {
+                        let t = (i + 1) as f32 / n as f32;
+                        ch[end - n + i] = ch[end - n + i] * (1.0 - t) + ch[start - n + i] * t;
+                    }
+                }
+            }
+            SampleOp::Convert(to) => {
+                if to == format {
+                    return false;
+                }
+                match (format.is_stereo(), to.is_stereo()) {
+                    (false, true) => channels.push(channels[0].clone()),
+                    (true, false) => {
+                        let right = channels.pop().unwrap_or_default();
+                        channels[0].iter_mut().zip(right).for_each(|(l, r)| *l = (*l + r) * 0.5);
+                    }
+                    _ => {}
+                }
+                format = to;
+            }
+        }
+        let data = from_f32(format, &channels);
+        self.set_data(data);
+        true
+    }
+}
+
+/// Multiply frames in `range` by `factor(frame)`.
+fn scale(channels: &mut [Vec<f32>], range: Range<usize>, factor: impl Fn(usize) -> f32) {
+    for ch in channels {
+        for i in range.clone() {
+            ch[i] *= factor(i);
+        }
+    }
+}
+
+/// `input` resampled by `ratio` (output rate / input rate) to `out_len`
+/// frames.
+///
+/// Blackman-windowed sinc interpolation; the cutoff follows the lower
+/// rate so downsampling doesn't alias.
+fn resample(input: &[f32], ratio: f64, out_len: usize) -> Vec<f32> {
+    use core::f64::consts::PI;
+    let cutoff = ratio.min(1.0);
+    let width = SINC_ZEROS / cutoff;
+    let sinc = |x: f64| if x == 0.0 { 1.0 } else { libm::sin(PI * x) / (PI * x) };
+    let window = |t: f64| 0.42 + 0.5 * libm::cos(PI * t) + 0.08 * libm::cos(2.0 * PI * t);
+    (0..out_len)
+        .map(|n| {
+            let pos = n as f64 / ratio;
+            let first = libm::ceil(pos - width).max(0.0) as usize;
+            let last = (libm::floor(pos + width) as usize).min(input.len().saturating_sub(1));
+            let acc: f64 = (first..=last)
+                .map(|i| {
+                    let x = pos - i as f64;
+                    input[i] as f64 * cutoff * sinc(cutoff * x) * window(x / width)
+                })
+                .sum();
+            acc as f32
+        })
+        .collect()
+}
+
+/// Each channel of `data` as floats in -1..1.
+fn channels_f32(data: &SampleData) -> Vec<Vec<f32>> {
+    let from8 = |v: &[i8]| v.iter().map(|&s| s as f32 / 128.0).collect::<Vec<_>>();
+    let from16 = |v: &[i16]| v.iter().map(|&s| s as f32 / 32768.0).collect::<Vec<_>>();
+    match data {
+        SampleData::Mono8(v) => alloc::vec![from8(v)],
+        SampleData::Mono16(v) => alloc::vec![from16(v)],
+        SampleData::Stereo8(l, r) => alloc::vec![from8(l), from8(r)],
+        SampleData::Stereo16(l, r) => alloc::vec![from16(l), from16(r)],
+    }
+}
+
+/// `channels` written back as `format` (mono takes the first channel).
+fn from_f32(format: SampleFormat, channels: &[Vec<f32>]) -> SampleData {
+    let to8 = |v: &[f32]| v.iter().map(|&s| libm::roundf(s * 128.0).clamp(-128.0, 127.0) as i8).collect();
+    let to16 = |v: &[f32]| v.iter().map(|&s| libm::roundf(s * 32768.0).clamp(-32768.0, 32767.0) as i16).collect();
+    match format {
+        SampleFormat::Mono8 => SampleData::Mono8(to8(&channels[0])),
+        SampleFormat::Mono16 => SampleData::Mono16(to16(&channels[0])),
+        SampleFormat::Stereo8 => SampleData::Stereo8(to8(&channels[0]), to8(&channels[1])),
+        SampleFormat::Stereo16 => SampleData::Stereo16(to16(&channels[0]), to16(&channels[1])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::LoopType;
+
+    fn mono16(data: &[i16]) -> Sample {
+        let mut sample = Sample::new("edit");
+        sample.set_data(SampleData::Mono16(data.to_vec()));
+        sample
+    }
+
+    fn frames(sample: &Sample) -> Vec<i16> {
+        (0..sample.len()).map(|i| sample.data.get_mono(i)).collect()
+    }
+
+    #[test]
+    fn normalize_and_gain_keep_format() {
+        let mut sample = Sample::new("stereo");
+        sample.set_data(SampleData::Stereo8(alloc::vec![32, -16], alloc::vec![8, 0]));
+        assert!(sample.apply_op(SampleOp::Normalize));
+        match &*sample.data {
+            SampleData::Stereo8(l, r) => assert_eq!((l.as_slice(), r.as_slice()), (&[127, -64][..], &[32, 0][..])),
+            other => panic!("expected Stereo8, got {:?}", other),
+        }
+        assert!(sample.apply_op(SampleOp::Gain(0.5)));
+        assert_eq!(sample.data.get_mono(1), -32 << 8);
+        assert!(!mono16(&[0, 0]).apply_op(SampleOp::Normalize));
+    }
+
+    #[test]
+    fn fades_ramp_to_and_from_silence() {
+        let mut sample = mono16(&[1000; 5]);
+        assert!(sample.apply_op(SampleOp::FadeIn { start: 0, end: 3 }));
+        assert_eq!(frames(&sample), [0, 500, 1000, 1000, 1000]);
+        assert!(sample.apply_op(SampleOp::FadeOut { start: 3, end: 99 }));
+        assert_eq!(frames(&sample), [0, 500, 1000, 1000, 0]);
+        assert!(!sample.apply_op(SampleOp::FadeIn { start: 5, end: 9 }));
+    }
+
+    #[test]
+    fn reverse_mirrors_loop_of_whole_sample() {
+        let mut sample = mono16(&[1, 2, 3, 4, 5]);
+        (sample.loop_start, sample.loop_end, sample.loop_type) = (1, 3, LoopType::Forward);
+        assert!(sample.apply_op(SampleOp::Reverse { start: 1, end: 4 }));
+        assert_eq!(frames(&sample), [1, 4, 3, 2, 5]);
+        assert_eq!((sample.loop_start, sample.loop_end), (1, 3));
+        assert!(sample.apply_op(SampleOp::Reverse { start: 0, end: 5 }));
+        assert_eq!(frames(&sample), [5, 2, 3, 4, 1]);
+        assert_eq!((sample.loop_start, sample.loop_end), (2, 4));
+    }
+
+    #[test]
+    fn dc_offset_is_removed() {
+        let mut sample = mono16(&[100, 300, 200]);
+        assert!(sample.apply_op(SampleOp::RemoveDcOffset));
+        assert_eq!(frames(&sample), [-100, 100, 0]);
+    }
+
+    #[test]
+    fn trim_keeps_range_and_moves_loop() {
+        let mut sample = mono16(&[1, 2, 3, 4, 5, 6]);
+        (sample.loop_start, sample.loop_end) = (2, 6);
+        assert!(sample.apply_op(SampleOp::Trim { start: 1, end: 4 }));
+        assert_eq!(frames(&sample), [2, 3, 4]);
+        assert_eq!((sample.loop_start, sample.loop_end), (1, 3));
+        assert!(!sample.apply_op(SampleOp::Trim { start: 2, end: 2 }));
+    }
+
+    fn sine(hz: f32, rate: u32, frames: usize) -> Sample {
+        let wave = (0..frames)
+            .map(|i| (libm::sinf(core::f32::consts::TAU * hz * i as f32 / rate as f32) * 16000.0) as i16)
+            .collect::<Vec<_>>();
+        let mut sample = mono16(&wave);
+        sample.c4_speed = rate;
+        sample
+    }
+
+    fn crossings(sample: &Sample) -> usize {
+        frames(sample).windows(2).filter(|w| (w[0] < 0) != (w[1] < 0)).count()
+    }
+
+    fn peak(sample: &Sample, range: Range<usize>) -> i16 {
+        frames(sample)[range].iter().map(|s| s.saturating_abs()).max().unwrap_or(0)
+    }
+
+    #[test]
+    fn resample_keeps_pitch_and_level() {
+        let mut sample = sine(1000.0, 48000, 4800);
+        (sample.loop_start, sample.loop_end) = (480, 4800);
+        let before = crossings(&sample);
+        assert!(sample.apply_op(SampleOp::Resample { rate: 44100 }));
+        assert_eq!((sample.len(), sample.c4_speed), (4410, 44100));
+        assert_eq!((sample.loop_start, sample.loop_end), (441, 4410));
+        assert!(crossings(&sample).abs_diff(before) <= 2);
+        assert!((peak(&sample, 500..4000) - 16000).abs() < 300);
+        assert!(!sample.apply_op(SampleOp::Resample { rate: 44100 }));
+    }
+
+    #[test]
+    fn downsampling_filters_out_tones_above_new_nyquist() {
+        let mut sample = sine(15000.0, 44100, 4410);
+        assert!(sample.apply_op(SampleOp::Resample { rate: 22050 }));
+        assert!(peak(&sample, 200..2000) < 1000, "15 kHz aliased into the output");
+    }
+
+    #[test]
+    fn crossfade_loop_blends_into_loop_start() {
+        let mut sample = mono16(&[100, 200, 300, 400, 0, 0, 0, 0]);
+        assert!(!sample.apply_op(SampleOp::CrossfadeLoop { frames: 2 }), "no loop");
+        (sample.loop_start, sample.loop_end, sample.loop_type) = (4, 8, LoopType::Forward);
+        assert!(sample.apply_op(SampleOp::CrossfadeLoop { frames: 2 }));
+        // Ends on the frames just before the loop start
+        assert_eq!(frames(&sample), [100, 200, 300, 400, 0, 0, 150, 400]);
+    }
+
+    #[test]
+    fn convert_changes_channels_and_depth() {
+        let mut sample = Sample::new("stereo");
+        sample.set_data(SampleData::Stereo16(alloc::vec![1000, -256], alloc::vec![3000, 256]));
+        assert!(sample.apply_op(SampleOp::Convert(SampleFormat::Mono8)));
+        match &*sample.data {
+            SampleData::Mono8(v) => assert_eq!(v.as_slice(), &[8, 0]),
+            other => panic!("expected Mono8, got {:?}", other),
+        }
+        assert!(sample.apply_op(SampleOp::Convert(SampleFormat::Stereo16)));
+        assert_eq!(sample.data.get_right(0), 8 << 8);
+        assert!(!sample.apply_op(SampleOp::Convert(SampleFormat::Stereo16)));
+    }
+}
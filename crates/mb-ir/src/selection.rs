@@ -0,0 +1,223 @@
+//! Block operations on a selection of pattern cells.
+//!
+//! Each operation reads the song and returns the `Edit::SetCell` batch that
+//! carries it out, so callers apply it to their own song copy and forward
+//! the same edits to a playing engine.
+
+use alloc::vec::Vec;
+
+use crate::edit::Edit;
+use crate::mod_envelope::{interpolate, CurveKind};
+use crate::pattern::{Cell, Pattern};
+use crate::song::Song;
+
+/// A block of cells in one clip: rows `start_row..=end_row` of columns
+/// `first_column..=last_column`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CellSelection {
+    pub track: u16,
+    pub clip: u16,
+    pub start_row: u16,
+    pub end_row: u16,
+    pub first_column: u8,
+    pub last_column: u8,
+}
+
+impl CellSelection {
+    /// A selection of rows `start_row..=end_row` in a single column.
+    pub fn column(track: u16, clip: u16, column: u8, start_row: u16, end_row: u16) -> Self {
+        Self { track, clip, start_row, end_row, first_column: column, last_column: column }
+    }
+
+    /// Every cell of a clip, if it is a pattern clip.
+    pub fn whole_clip(song: &Song, track: u16, clip: u16) -> Option<Self> {
+        let pattern = song.tracks.get(track as usize)?.clips.get(clip as usize)?.pattern()?;
+        if pattern.rows == 0 || pattern.channels == 0 {
+            return None;
+        }
+        let (end_row, last_column) = (pattern.rows - 1, pattern.channels - 1);
+        Some(Self { track, clip, start_row: 0, end_row, first_column: 0, last_column })
+    }
+
+    /// The selected clip's pattern, if the selection fits inside it.
+    fn pattern<'a>(&self, song: &'a Song) -> Option<&'a Pattern> {
+        let pattern = song.tracks.get(self.track as usize)?.clips.get(self.clip as usize)?.pattern()?;
+        let fits = self.start_row <= self.end_row
+            && self.end_row < pattern.rows
+            && self.first_column <= self.last_column
+            && self.last_column < pattern.channels;
+        fits.then_some(pattern)
+    }
+
+    fn set_cell(&self, row: u16, column: u8, cell: Cell) -> Edit {
+        Edit::SetCell { track: self.track, clip: self.clip, row, column, cell }
+    }
+}
+
+/// Which part of a cell an operation works on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CellField {
+    /// Volume column
+    Volume,
+    /// Effect column parameter
+    Effect,
+    /// Second effect column parameter
+    Effect2,
+}
+
+/// Ramp `field` between the first and last selected row of each column.
+///
+/// A column is ramped only if both end rows hold the same command with a
+/// parameter; the rows between get that command with values along `curve`.
+/// Returns edits for the cells that change.
+pub fn interpolate_cells(song: &Song, sel: &CellSelection, field: CellField, curve: CurveKind) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    let Some(pattern) = sel.pattern(song) else { return edits };
+    let span = (sel.end_row - sel.start_row) as f32;
+    for column in sel.first_column..=sel.last_column {
+        let (first, last) = (pattern.cell(sel.start_row, column), pattern.cell(sel.end_row, column));
+        let Some((from, to)) = field_params(first, last, field) else { continue };
+        for row in sel.start_row + 1..sel.end_row {
+            let t = (row - sel.start_row) as f32 / span;
+            let value = libm::roundf(interpolate(curve, from as f32, to as f32, t)) as i16;
+            let mut cell = *pattern.cell(row, column);
+            match field {
+                CellField::Volume => cell.volume = first.volume.with_param(value.clamp(0, 255) as u8),
+                CellField::Effect => cell.effect = first.effect.with_param(value),
+                CellField::Effect2 => cell.effect2 = first.effect2.with_param(value),
+            }
+            if cell != *pattern.cell(row, column) {
+                edits.push(sel.set_cell(row, column, cell));
+            }
+        }
+    }
+    edits
+}
+
+/// Shift every selected note by `semitones` (12 per octave), clamped to
+/// the note range. Returns edits for the cells that change.
+pub fn transpose_cells(song: &Song, sel: &CellSelection, semitones: i16) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    let Some(pattern) = sel.pattern(song) else { return edits };
+    for row in sel.start_row..=sel.end_row {
+        for column in sel.first_column..=sel.last_column {
+            let mut cell = *pattern.cell(row, column);
+            cell.note = cell.note.transposed(semitones);
+            if cell != *pattern.cell(row, column) {
+                edits.push(sel.set_cell(row, column, cell));
+            }
+        }
+    }
+    edits
+}
+
+/// The end-point values of `field`, if both cells hold the same command
+/// with a parameter.
+fn field_params(first: &Cell, last: &Cell, field: CellField) -> Option<(i16, i16)> {
+    match field {
+        CellField::Volume if first.volume.name() == last.volume.name() => {
+            Some((first.volume.param()? as i16, last.volume.param()? as i16))
+        }
+        CellField::Effect if first.effect.name() == last.effect.name() => {
+            Some((first.effect.param()?, last.effect.param()?))
+        }
+        CellField::Effect2 if first.effect2.name() == last.effect2.name() => {
+            Some((first.effect2.param()?, last.effect2.param()?))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::{Effect, VolumeCommand};
+    use crate::pattern::Note;
+    use crate::song::{build_tracks, OrderEntry};
+
+    fn song_with(cells: &[(u16, u8, Cell)]) -> Song {
+        let mut pattern = Pattern::new(8, 2);
+        for &(row, column, cell) in cells {
+            *pattern.cell_mut(row, column) = cell;
+        }
+        let mut song = Song::with_channels("selection", 2);
+        build_tracks(&mut song, &[pattern], &[OrderEntry::Pattern(0)]);
+        song
+    }
+
+    fn volume(v: u8) -> Cell {
+        Cell { volume: VolumeCommand::Volume(v), ..Cell::empty() }
+    }
+
+    fn volumes(edits: &[Edit]) -> Vec<(u16, u8)> {
+        edits.iter().filter_map(|e| match e {
+            Edit::SetCell { row, cell, .. } => Some((*row, cell.volume.param()?)),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn linear_ramp_fills_rows_between_end_points() {
+        let mut noted = volume(64);
+        noted.note = Note::On(60);
+        let song = song_with(&[(0, 0, volume(0)), (2, 0, noted), (4, 0, volume(64))]);
+        let sel = CellSelection::column(0, 0, 0, 0, 4);
+        let edits = interpolate_cells(&song, &sel, CellField::Volume, CurveKind::Linear);
+        assert_eq!(volumes(&edits), [(1, 16), (2, 32), (3, 48)]);
+        let Edit::SetCell { cell, .. } = edits[1] else { panic!() };
+        assert_eq!(cell.note, Note::On(60), "other fields are kept");
+    }
+
+    #[test]
+    fn exponential_ramp_starts_slow() {
+        let song = song_with(&[(0, 0, volume(0)), (4, 0, volume(64))]);
+        let sel = CellSelection::column(0, 0, 0, 0, 4);
+        let edits = interpolate_cells(&song, &sel, CellField::Volume, CurveKind::Exponential(3.0));
+        let ramp = volumes(&edits);
+        assert_eq!(ramp.len(), 3);
+        assert!(ramp[0].1 < 16 && ramp.windows(2).all(|w| w[0].1 < w[1].1));
+    }
+
+    #[test]
+    fn effect_ramp_needs_matching_commands() {
+        let slide = |v| Cell { effect: Effect::VolumeSlide(v), ..Cell::empty() };
+        let song = song_with(&[
+            (0, 0, slide(-4)),
+            (2, 0, slide(4)),
+            (0, 1, slide(4)),
+            (2, 1, Cell { effect: Effect::SetVolume(4), ..Cell::empty() }),
+        ]);
+        let sel = CellSelection { track: 0, clip: 0, start_row: 0, end_row: 2, first_column: 0, last_column: 1 };
+        let edits = interpolate_cells(&song, &sel, CellField::Effect, CurveKind::Linear);
+        assert_eq!(edits, [Edit::SetCell { track: 0, clip: 0, row: 1, column: 0, cell: slide(0) }]);
+    }
+
+    #[test]
+    fn transpose_shifts_and_clamps_notes() {
+        let note = |n| Cell { note: n, ..Cell::empty() };
+        let song = song_with(&[
+            (0, 0, note(Note::On(60))),
+            (1, 1, note(Note::On(115))),
+            (2, 0, note(Note::Off)),
+            (7, 1, note(Note::On(0))),
+        ]);
+        let sel = CellSelection::whole_clip(&song, 0, 0).unwrap();
+        let shifted: Vec<_> = transpose_cells(&song, &sel, 12).iter().filter_map(|e| match e {
+            Edit::SetCell { row, column, cell, .. } => Some((*row, *column, cell.note)),
+            _ => None,
+        }).collect();
+        assert_eq!(shifted, [(0, 0, Note::On(72)), (1, 1, Note::On(Note::MAX)), (7, 1, Note::On(12))]);
+        let sel = CellSelection::column(0, 0, 1, 7, 7);
+        assert!(transpose_cells(&song, &sel, -1).is_empty(), "already at the bottom");
+        assert!(CellSelection::whole_clip(&song, 0, 9).is_none());
+    }
+
+    #[test]
+    fn out_of_range_selection_is_ignored() {
+        let song = song_with(&[(0, 0, volume(0)), (4, 0, volume(64))]);
+        let sel = CellSelection::column(0, 0, 0, 0, 8);
+        assert!(interpolate_cells(&song, &sel, CellField::Volume, CurveKind::Linear).is_empty());
+        let sel = CellSelection::column(0, 0, 2, 0, 4);
+        assert!(interpolate_cells(&song, &sel, CellField::Volume, CurveKind::Linear).is_empty());
+    }
+}
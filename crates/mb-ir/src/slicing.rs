@@ -0,0 +1,181 @@
+//! Beat slicing: cut a sample at its transients (or evenly) into one
+//! sample per slice, and lay the slices out in a pattern.
+
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use crate::pattern::{Cell, Note, Pattern};
+use crate::sample::{LoopType, Sample};
+use crate::sample_edit::SampleOp;
+
+/// Energy window for transient detection, in milliseconds.
+const WINDOW_MS: u32 = 10;
+
+/// Shortest slice transient detection will produce, in milliseconds.
+const MIN_SLICE_MS: u32 = 50;
+
+/// Windows quieter than this fraction of the loudest one never start a
+/// slice (-30 dB in energy).
+const FLOOR: f32 = 0.001;
+
+/// How to choose slice points.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SliceMode {
+    /// Cut into this many slices of equal length
+    Equal(u16),
+    /// Cut where the level jumps; higher sensitivity (0-100) finds softer hits
+    Transients { sensitivity: u8 },
+}
+
+/// Start frames of the slices of `sample`, beginning with 0.
+pub fn slice_points(sample: &Sample, mode: SliceMode) -> Vec<u32> {
+    let len = sample.len() as u32;
+    if len == 0 {
+        return Vec::new();
+    }
+    match mode {
+        SliceMode::Equal(count) => {
+            let count = (count.max(1) as u32).min(len);
+            (0..count).map(|i| (i as u64 * len as u64 / count as u64) as u32).collect()
+        }
+        SliceMode::Transients { sensitivity } => transients(sample, sensitivity.min(100)),
+    }
+}
+
+/// Frames where the windowed energy jumps by more than the ratio set by
+/// `sensitivity`, at least `MIN_SLICE_MS` apart.
+fn transients(sample: &Sample, sensitivity: u8) -> Vec<u32> {
+    let rate = sample.c4_speed.max(1);
+    let window = (rate * WINDOW_MS / 1000).max(1) as usize;
+    let min_gap = (rate * MIN_SLICE_MS / 1000) as usize;
+    // Energy must grow 6x at sensitivity 0, 1.5x at 100
+    let ratio = 1.5 + (100 - sensitivity) as f32 * 0.045;
+
+    let energy: Vec<f32> = (0..sample.len())
+        .step_by(window)
+        .map(|start| {
+            let end = (start + window).min(sample.len());
+            (start..end).map(|i| {
+                let (l, r) = (sample.data.get_mono(i) as f32, sample.data.get_right(i) as f32);
+                (l * l + r * r) / (2.0 * 32768.0 * 32768.0)
+            }).sum::<f32>() / (end - start) as f32
+        })
+        .collect();
+    let floor = energy.iter().fold(0.0f32, |m, &e| m.max(e)) * FLOOR;
+
+    let mut points = alloc::vec![0u32];
+    for (w, pair) in energy.windows(2).enumerate() {
+        let frame = (w + 1) * window;
+        let last = *points.last().unwrap_or(&0) as usize;
+        if pair[1] > floor && pair[1] > pair[0] * ratio && frame - last >= min_gap {
+            points.push(frame as u32);
+        }
+    }
+    points
+}
+
+/// One sample per slice, named after `sample` with the slice number.
+pub fn slice_sample(sample: &Sample, points: &[u32]) -> Vec<Sample> {
+    let len = sample.len() as u32;
+    points
+        .iter()
+        .enumerate()
+        .map(|(i, &start)| {
+            let end = points.get(i + 1).copied().unwrap_or(len);
+            let mut slice = sample.clone();
+            slice.apply_op(SampleOp::Trim { start, end });
+            slice.loop_type = LoopType::None;
+            slice.name = slice_name(&sample.name, i + 1);
+            slice
+        })
+        .collect()
+}
+
+/// `name` with " <number>" appended, shortening `name` to make room.
+fn slice_name(name: &str, number: usize) -> arrayvec::ArrayString<26> {
+    let mut suffix = arrayvec::ArrayString::<8>::new();
+    let _ = write!(suffix, " {}", number);
+    let mut out = arrayvec::ArrayString::<26>::new();
+    for c in name.chars() {
+        if out.len() + c.len_utf8() + suffix.len() > out.capacity() {
+            break;
+        }
+        out.push(c);
+    }
+    out.push_str(&suffix);
+    out
+}
+
+/// A pattern of `rows` rows that plays each slice at C-4 on column 0, on
+/// the row matching where the slice starts in a sample of `len` frames.
+///
+/// Slices are numbered from instrument `first_instrument` (1-based); a
+/// slice landing on an earlier slice's row replaces it.
+pub fn slice_pattern(points: &[u32], len: u32, first_instrument: u8, rows: u16, channels: u8) -> Pattern {
+    let mut pattern = Pattern::new(rows.max(1), channels.max(1));
+    for (i, &start) in points.iter().enumerate() {
+        let row = (start as u64 * pattern.rows as u64 / len.max(1) as u64) as u16;
+        let instrument = first_instrument.saturating_add(i as u8);
+        let note = Note::from_octave_semitone(4, 0);
+        *pattern.cell_mut(row.min(pattern.rows - 1), 0) = Cell { note, instrument, ..Cell::empty() };
+    }
+    pattern
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::SampleData;
+
+    /// Four hits of decaying noise-like bursts, 1000 frames apart at 10 kHz.
+    fn breakbeat() -> Sample {
+        let data = (0..4000)
+            .map(|i| {
+                let t = i % 1000;
+                let level = 20000.0 * (1.0 - t as f32 / 300.0).max(0.0);
+                (if i % 2 == 0 { level } else { -level }) as i16
+            })
+            .collect();
+        let mut sample = Sample::new("Amen break full length tak");
+        sample.set_data(SampleData::Mono16(data));
+        sample.c4_speed = 10000;
+        sample
+    }
+
+    #[test]
+    fn equal_slices_divide_length() {
+        let sample = breakbeat();
+        assert_eq!(slice_points(&sample, SliceMode::Equal(3)), [0, 1333, 2666]);
+        assert_eq!(slice_points(&sample, SliceMode::Equal(0)), [0]);
+        assert!(slice_points(&Sample::new("empty"), SliceMode::Equal(4)).is_empty());
+    }
+
+    #[test]
+    fn transients_find_each_hit() {
+        let sample = breakbeat();
+        assert_eq!(slice_points(&sample, SliceMode::Transients { sensitivity: 50 }), [0, 1000, 2000, 3000]);
+    }
+
+    #[test]
+    fn slices_split_audio_and_are_numbered() {
+        let mut sample = breakbeat();
+        sample.loop_type = LoopType::Forward;
+        let slices = slice_sample(&sample, &[0, 1000, 2500]);
+        let lens: Vec<_> = slices.iter().map(|s| s.len()).collect();
+        assert_eq!(lens, [1000, 1500, 1500]);
+        assert_eq!(slices[1].data.get_mono(0), sample.data.get_mono(1000));
+        assert_eq!(slices[2].name.as_str(), "Amen break full length t 3");
+        assert!(slices.iter().all(|s| s.loop_type == LoopType::None));
+    }
+
+    #[test]
+    fn pattern_triggers_slices_in_order() {
+        let pattern = slice_pattern(&[0, 1000, 2000, 3000], 4000, 5, 16, 2);
+        let hits: Vec<_> = (0..16)
+            .filter(|&r| pattern.cell(r, 0).note != Note::None)
+            .map(|r| (r, pattern.cell(r, 0).instrument))
+            .collect();
+        assert_eq!(hits, [(0, 5), (4, 6), (8, 7), (12, 8)]);
+        assert_eq!(pattern.cell(0, 0).note, Note::On(48));
+    }
+}
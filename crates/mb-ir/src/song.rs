@@ -3,10 +3,16 @@
 use alloc::vec::Vec;
 use arrayvec::ArrayString;
 
+use crate::edit::{Edit, SeqEntryData};
+use crate::effects::Effect;
 use crate::graph::{AudioGraph, NodeId, NodeType};
+use crate::groove::{Groove, TrackOffset};
+use crate::marker::Marker;
+use crate::meter::Meter;
+use crate::tempo_envelope::TempoEnvelope;
 use crate::instrument::Instrument;
 use crate::musical_time::MusicalTime;
-use crate::pattern::Pattern;
+use crate::pattern::{Cell, Pattern, WireLane};
 use crate::sample::Sample;
 
 /// A complete song.
@@ -14,14 +20,18 @@ use crate::sample::Sample;
 pub struct Song {
     /// Song title
     pub title: ArrayString<32>,
-    /// Initial tempo in BPM (32-255 typical)
-    pub initial_tempo: u8,
+    /// Initial tempo in hundredths of a BPM (12500 = 125 BPM)
+    pub initial_tempo: u32,
     /// Initial speed (ticks per row, 1-31)
     pub initial_speed: u8,
     /// Rows per beat (default 4: 4 rows = 1 beat)
     pub rows_per_beat: u8,
     /// Global volume (0-64)
     pub global_volume: u8,
+    /// Pitch scale used for notes and pitch slides
+    pub frequency_mode: FrequencyMode,
+    /// Which tracker's playback quirks to reproduce
+    pub compatibility: CompatibilityMode,
     /// Instruments
     pub instruments: Vec<Instrument>,
     /// Samples
@@ -32,21 +42,38 @@ pub struct Song {
     pub graph: AudioGraph,
     /// Tracks (per-track sequencing)
     pub tracks: Vec<Track>,
+    /// Subsongs found in the legacy order list (index 0 = main arrangement).
+    pub subsongs: Vec<Subsong>,
+    /// Timing shift for the rows of tracks without a groove of their own
+    pub groove: Groove,
+    /// Gradual tempo changes, overriding tempo commands from its first point
+    pub tempo_envelope: TempoEnvelope,
+    /// Time signatures, grouping beats into bars
+    pub meter: Meter,
+    /// Named cue points, in time order
+    pub markers: Vec<Marker>,
 }
 
 impl Default for Song {
     fn default() -> Self {
         Self {
             title: ArrayString::new(),
-            initial_tempo: 125,
+            initial_tempo: 12500,
             initial_speed: 6,
             rows_per_beat: 4,
             global_volume: 64,
+            frequency_mode: FrequencyMode::Amiga,
+            compatibility: CompatibilityMode::Native,
             instruments: Vec::new(),
             samples: Vec::new(),
             channels: Vec::new(),
             graph: AudioGraph::with_master(),
             tracks: Vec::new(),
+            subsongs: Vec::new(),
+            groove: Groove::Straight,
+            tempo_envelope: TempoEnvelope::default(),
+            meter: Meter::default(),
+            markers: Vec::new(),
         }
     }
 }
@@ -63,7 +90,7 @@ impl Song {
     ///
     /// Graph: Tracker→AmigaFilter→Master
     pub fn with_channels(title: &str, num_channels: u8) -> Self {
-        use crate::graph::{NodeType, Parameter};
+        use crate::graph::{NodeType, ParamCurve, ParamUnit, Parameter};
 
         let mut song = Self::new(title);
 
@@ -72,7 +99,7 @@ impl Song {
             .graph
             .add_node(NodeType::Machine { machine_name: alloc::string::String::from("Amiga Filter"), is_tracker: false });
         song.graph.node_mut(filter_id).unwrap().parameters.push(
-            Parameter::new(0, "Cutoff", 1000, 22050, 4410),
+            Parameter::new(0, "Cutoff", 1000, 22050, 4410).with_display(ParamUnit::Hz, ParamCurve::Raw),
         );
         song.graph.connect(filter_id, 0); // filter → master
 
@@ -88,6 +115,7 @@ impl Song {
                 initial_pan: if i % 4 == 0 || i % 4 == 3 { -64 } else { 64 },
                 initial_vol: 64,
                 muted: false,
+                solo: false,
             });
         }
 
@@ -102,8 +130,311 @@ impl Song {
             .unwrap_or(MusicalTime::zero())
     }
 
+    /// Swap the tracker tracks' sequences for those of subsong `idx`.
+    ///
+    /// Sequence edits made while a subsong is selected are not written back
+    /// to `subsongs`. Returns false if `idx` is out of range.
+    pub fn select_subsong(&mut self, idx: usize) -> bool {
+        let Some(sub) = self.subsongs.get(idx) else { return false };
+        let (sequence, order_base) = (sub.sequence.clone(), sub.order_start);
+        let tracker = find_tracker_node(&self.graph);
+        for track in self.tracks.iter_mut().filter(|t| t.machine_node == tracker) {
+            track.sequence = sequence.clone();
+            track.order_base = order_base;
+        }
+        true
+    }
+
+    /// Remove graph node `id` (see `AudioGraph::remove_node`), renumbering
+    /// the tracks' machine nodes and wire lanes to match.
+    ///
+    /// Tracks that played the removed node are left without one.
+    pub fn remove_node(&mut self, id: NodeId) -> bool {
+        if !self.graph.remove_node(id) {
+            return false;
+        }
+        self.detach_node(id, &mut Vec::new());
+        true
+    }
+
+    /// Renumber the tracks' machine nodes and wire lanes after graph node
+    /// `id` was removed, moving the lanes it drove into `removed`.
+    ///
+    /// Doesn't allocate or free when `removed` has room for the lanes, so
+    /// the engine can do it on the audio thread.
+    pub fn detach_node(&mut self, id: NodeId, removed: &mut Vec<WireLane>) {
+        for track in &mut self.tracks {
+            track.machine_node = match track.machine_node {
+                Some(n) if n == id => None,
+                Some(n) if n > id => Some(n - 1),
+                other => other,
+            };
+            for pattern in track.clips.iter_mut().filter_map(|c| c.pattern_mut()) {
+                let mut i = 0;
+                while i < pattern.wires.len() {
+                    if pattern.wires[i].from == id {
+                        removed.push(pattern.wires.remove(i));
+                        continue;
+                    }
+                    if pattern.wires[i].from > id {
+                        pattern.wires[i].from -= 1;
+                    }
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    /// Wire lanes driven by graph node `id`, across all tracks' patterns.
+    pub fn wire_lanes_from(&self, id: NodeId) -> usize {
+        self.tracks.iter()
+            .flat_map(|t| t.clips.iter().filter_map(|c| c.pattern()))
+            .map(|p| p.wires.iter().filter(|lane| lane.from == id).count())
+            .sum()
+    }
+
+    /// Add a track playing `num_channels` new channels on a new Tracker
+    /// node wired to Master; returns its index.
+    ///
+    /// Returns `None` if the song would pass 255 channels.
+    pub fn add_track(&mut self, num_channels: u8, name: &str) -> Option<usize> {
+        let node_type = NodeType::Machine {
+            machine_name: alloc::string::String::from("Tracker"),
+            is_tracker: true,
+        };
+        self.add_track_on(node_type, num_channels, name)
+    }
+
+    /// Add a track playing `num_channels` new channels on a new MIDI Out
+    /// node (unwired, as it makes no sound) that sends to `port` on MIDI
+    /// channel `channel`; returns its index.
+    ///
+    /// Returns `None` if the song would pass 255 channels.
+    pub fn add_midi_track(&mut self, num_channels: u8, name: &str, port: &str, channel: u8) -> Option<usize> {
+        let node_type = NodeType::MidiOut { port: alloc::string::String::from(port), channel: channel.min(15) };
+        self.add_track_on(node_type, num_channels, name)
+    }
+
+    fn add_track_on(&mut self, node_type: NodeType, num_channels: u8, name: &str) -> Option<usize> {
+        let base = self.channels.len();
+        if base + num_channels as usize > u8::MAX as usize {
+            return None;
+        }
+        let wired = !matches!(node_type, NodeType::MidiOut { .. });
+        let node = self.graph.add_node(node_type);
+        if wired {
+            self.graph.connect(node, 0);
+        }
+        self.channels.extend((0..num_channels).map(|_| ChannelSettings::default()));
+        let mut track = Track::new(Some(node), base as u8, num_channels);
+        track.set_name(name);
+        self.tracks.push(track);
+        Some(self.tracks.len() - 1)
+    }
+
+    /// The machine node only track `idx` plays, which goes with the track
+    /// when it is removed. Master is never exclusive.
+    pub fn exclusive_node(&self, idx: usize) -> Option<NodeId> {
+        let node = self.tracks.get(idx)?.machine_node.filter(|&n| n != 0)?;
+        let shared = self.tracks.iter().enumerate().any(|(i, t)| i != idx && t.machine_node == Some(node));
+        (!shared).then_some(node)
+    }
+
+    /// Remove track `idx` with its exclusive machine node (see
+    /// `exclusive_node`) and the channels no other track plays, moving later
+    /// tracks' channels down to match.
+    pub fn remove_track(&mut self, idx: usize) -> bool {
+        if idx >= self.tracks.len() {
+            return false;
+        }
+        let node = self.exclusive_node(idx);
+        let track = self.tracks.remove(idx);
+        let (start, end) = (track.base_channel, track.base_channel.saturating_add(track.num_channels));
+        let overlaps = |t: &Track| t.base_channel < end && start < t.base_channel.saturating_add(t.num_channels);
+        if track.num_channels > 0 && !self.tracks.iter().any(overlaps) {
+            let end = (end as usize).min(self.channels.len());
+            self.channels.drain((start as usize).min(end)..end);
+            for t in self.tracks.iter_mut().filter(|t| t.base_channel >= start) {
+                t.base_channel -= track.num_channels;
+            }
+        }
+        if let Some(node) = node {
+            self.remove_node(node);
+        }
+        true
+    }
+
+    /// Move track `from` to position `to`.
+    pub fn move_track(&mut self, from: usize, to: usize) -> bool {
+        if from >= self.tracks.len() || to >= self.tracks.len() {
+            return false;
+        }
+        let track = self.tracks.remove(from);
+        self.tracks.insert(to, track);
+        true
+    }
+
+    /// Apply a track edit (see `Edit::is_track_edit`); returns whether it applied.
+    pub fn apply_track_edit(&mut self, edit: &Edit) -> bool {
+        match edit {
+            Edit::AddTrack { channels, name } => self.add_track(*channels, name).is_some(),
+            Edit::AddMidiTrack { channels, name, port, channel } => {
+                self.add_midi_track(*channels, name, port, *channel).is_some()
+            }
+            Edit::RemoveTrack { track } => self.remove_track(*track as usize),
+            Edit::MoveTrack { from, to } => self.move_track(*from as usize, *to as usize),
+            Edit::RenameTrack { track, name } => {
+                let Some(t) = self.tracks.get_mut(*track as usize) else { return false };
+                t.name = *name;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The groove `track` plays with: its own, or else the song's.
+    pub fn track_groove<'a>(&'a self, track: &'a Track) -> &'a Groove {
+        track.groove.as_ref().unwrap_or(&self.groove)
+    }
+
+    /// When clip row `row` of `track`, due on the grid at `time`, plays:
+    /// shifted by the track's groove, then by its offset (milliseconds at
+    /// `tempo`, the tempo in force at `time`).
+    pub fn track_row_time(
+        &self,
+        track: &Track,
+        time: MusicalTime,
+        row: u16,
+        ticks_per_row: u32,
+        rows_per_beat: u32,
+        tempo: u32,
+    ) -> MusicalTime {
+        let time = self.track_groove(track).row_time(time, row, ticks_per_row, rows_per_beat);
+        track.offset.apply(time, ticks_per_row * rows_per_beat, tempo)
+    }
+
+    /// Apply a groove edit (see `Edit::is_groove_edit`).
+    pub fn apply_groove_edit(&mut self, edit: &Edit) -> bool {
+        match edit {
+            Edit::SetSongGroove { groove } => self.groove = groove.clone(),
+            Edit::SetTrackGroove { track, groove } => {
+                let Some(t) = self.tracks.get_mut(*track as usize) else { return false };
+                t.groove = groove.clone();
+            }
+            Edit::SetTrackOffset { track, offset } => {
+                let Some(t) = self.tracks.get_mut(*track as usize) else { return false };
+                t.offset = *offset;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Apply a sequence edit (see `Edit::is_sequence_edit`) to its track.
+    ///
+    /// Returns the track's index if the edit applied.
+    pub fn apply_sequence_edit(&mut self, edit: &Edit) -> Option<usize> {
+        let rpb = self.rows_per_beat;
+        let (track, applied) = match *edit {
+            Edit::SetSeqEntry { track, beat, entry } => {
+                let t = self.tracks.get_mut(track as usize)?;
+                t.set_seq_entry(beat, entry);
+                (track, true)
+            }
+            Edit::InsertSeqEntry { track, index, clip } => {
+                (track, self.tracks.get_mut(track as usize)?.insert_seq_entry(index as usize, clip, rpb))
+            }
+            Edit::RemoveSeqEntry { track, index } => {
+                (track, self.tracks.get_mut(track as usize)?.remove_seq_entry(index as usize, rpb).is_some())
+            }
+            Edit::MoveSeqEntry { track, from, to } => {
+                (track, self.tracks.get_mut(track as usize)?.move_seq_entry(from as usize, to as usize, rpb))
+            }
+            Edit::DuplicateSeqEntry { track, index } => {
+                (track, self.tracks.get_mut(track as usize)?.duplicate_seq_entry(index as usize, rpb))
+            }
+            Edit::SetSeqClip { track, index, clip } => {
+                (track, self.tracks.get_mut(track as usize)?.set_seq_clip(index as usize, clip, rpb))
+            }
+            Edit::SetSequence { track, ref sequence } => {
+                self.tracks.get_mut(track as usize)?.sequence = sequence.clone();
+                (track, true)
+            }
+            _ => return None,
+        };
+        applied.then_some(track as usize)
+    }
+
+    /// Apply a clip edit (see `Edit::is_clip_edit`) to its track's clip pool.
+    ///
+    /// Returns the track's index if the edit applied.
+    pub fn apply_clip_edit(&mut self, edit: &Edit) -> Option<usize> {
+        let rpb = self.rows_per_beat;
+        let (track, applied) = match *edit {
+            Edit::ResizeClip { track, clip, rows } => {
+                (track, self.tracks.get_mut(track as usize)?.resize_clip(clip, rows, rpb))
+            }
+            Edit::InsertRows { track, clip, row, count } => {
+                let pattern = self.tracks.get_mut(track as usize)?.clips.get_mut(clip as usize)?.pattern_mut()?;
+                pattern.insert_rows(row, count);
+                (track, true)
+            }
+            Edit::DeleteRows { track, clip, row, count } => {
+                let pattern = self.tracks.get_mut(track as usize)?.clips.get_mut(clip as usize)?.pattern_mut()?;
+                pattern.delete_rows(row, count);
+                (track, true)
+            }
+            Edit::CloneClip { track, clip } => {
+                (track, self.tracks.get_mut(track as usize)?.clone_clip(clip).is_some())
+            }
+            Edit::AddClip { track, rows } => {
+                self.tracks.get_mut(track as usize)?.add_clip(rows);
+                (track, true)
+            }
+            Edit::RemoveLastClip { track } => {
+                (track, self.tracks.get_mut(track as usize)?.remove_last_clip())
+            }
+            Edit::SetClip { track, clip, ref pattern } => {
+                let c = self.tracks.get_mut(track as usize)?.clips.get_mut(clip as usize)?;
+                *c = Clip::Pattern((**pattern).clone());
+                (track, true)
+            }
+            _ => return None,
+        };
+        applied.then_some(track as usize)
+    }
+
+    /// Apply a sample edit (see `Edit::is_sample_edit`); returns whether it
+    /// applied. Adding fails once samples or instruments run out of 8-bit
+    /// numbers.
+    pub fn apply_sample_edit(&mut self, edit: &Edit) -> bool {
+        match edit {
+            Edit::SetSample { sample, data } => {
+                let Some(slot) = self.samples.get_mut(*sample as usize) else { return false };
+                *slot = (**data).clone();
+            }
+            Edit::AddSample { sample } => {
+                if self.samples.len().max(self.instruments.len()) >= u8::MAX as usize {
+                    return false;
+                }
+                let mut instrument = Instrument::new(&sample.name);
+                instrument.set_single_sample(self.samples.len() as u8);
+                self.samples.push((**sample).clone());
+                self.instruments.push(instrument);
+            }
+            Edit::RemoveLastSample => {
+                if self.samples.pop().is_none() {
+                    return false;
+                }
+                self.instruments.pop();
+            }
+            _ => return false,
+        }
+        true
+    }
+
     pub fn is_tracker(&self, track: &Track) -> bool {
-        return track.machine_node
+        track.machine_node
             .and_then(|id| self.graph.node(id))
             .map(|n| match n.node_type {
                 NodeType::Machine { is_tracker, .. } => {
@@ -113,7 +444,7 @@ impl Song {
                     false
                 }
             })
-            .unwrap_or(false);
+            .unwrap_or(false)
     }
 }
 
@@ -128,6 +459,47 @@ pub enum OrderEntry {
     End,
 }
 
+/// A self-contained song within a legacy order list.
+///
+/// Multi-song MOD/S3M/IT files separate subsongs with End markers, or hide
+/// them behind a backwards PositionJump that loops the preceding song.
+#[derive(Clone, Debug)]
+pub struct Subsong {
+    /// Display name
+    pub name: ArrayString<32>,
+    /// Order list index of the subsong's first entry
+    pub order_start: u16,
+    /// Sequence for the tracker track, starting at time zero
+    pub sequence: Vec<SeqEntry>,
+}
+
+/// How notes map to periods, and so how pitch slides behave.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FrequencyMode {
+    /// Amiga periods: a slide step is a fixed period change, so it bends
+    /// more in high octaves (MOD, S3M, IT without linear slides)
+    #[default]
+    Amiga,
+    /// Linear periods: 64 units per semitone, slides are even in every
+    /// octave (XM and IT linear slides)
+    Linear,
+}
+
+/// Which tracker's playback quirks a song reproduces, so imported files
+/// sound like they did in the tracker that made them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CompatibilityMode {
+    /// masterblaster's own behavior (native projects, BMX, MIDI)
+    #[default]
+    Native,
+    /// ProTracker (MOD)
+    ProTracker,
+    /// FastTracker II (XM)
+    FastTracker2,
+    /// Impulse Tracker (IT, S3M)
+    ImpulseTracker,
+}
+
 /// Per-channel settings.
 #[derive(Clone, Copy, Debug)]
 pub struct ChannelSettings {
@@ -137,6 +509,8 @@ pub struct ChannelSettings {
     pub initial_vol: u8,
     /// Is the channel muted?
     pub muted: bool,
+    /// Is the channel soloed? Any solo silences every unsoloed channel.
+    pub solo: bool,
 }
 
 impl Default for ChannelSettings {
@@ -145,6 +519,7 @@ impl Default for ChannelSettings {
             initial_pan: 0,
             initial_vol: 64,
             muted: false,
+            solo: false,
         }
     }
 }
@@ -168,6 +543,26 @@ pub struct Track {
     pub sequence: Vec<SeqEntry>,
     /// Whether this track is muted (skipped during scheduling).
     pub muted: bool,
+    /// Order index of the first sequence entry. PositionJump targets are
+    /// order indices, so they are rebased by this when a subsong is selected.
+    pub order_base: u16,
+    /// Display name (empty = unnamed)
+    pub name: ArrayString<32>,
+    /// Timing shift for this track's rows (`None` = the song's groove)
+    pub groove: Option<Groove>,
+    /// How far this track's rows move after the groove
+    pub offset: TrackOffset,
+}
+
+/// `name` as a track name, cut short at 32 bytes on a char boundary.
+pub fn track_name(name: &str) -> ArrayString<32> {
+    let mut out = ArrayString::new();
+    for c in name.chars() {
+        if out.try_push(c).is_err() {
+            break;
+        }
+    }
+    out
 }
 
 impl Track {
@@ -180,9 +575,28 @@ impl Track {
             clips: Vec::new(),
             sequence: Vec::new(),
             muted: false,
+            order_base: 0,
+            name: ArrayString::new(),
+            groove: None,
+            offset: TrackOffset::default(),
         }
     }
 
+    /// Rename the track, cutting names longer than 32 bytes short.
+    pub fn set_name(&mut self, name: &str) {
+        self.name = track_name(name);
+    }
+
+    /// Map a PositionJump order index to a sequence index.
+    ///
+    /// Jumps to before `order_base` leave the selected subsong, so they map
+    /// past the end of the sequence.
+    pub fn jump_target(&self, order: u8) -> usize {
+        (order as usize)
+            .checked_sub(self.order_base as usize)
+            .unwrap_or(self.sequence.len())
+    }
+
     /// Get the Pattern from a track's clip pool.
     pub fn get_pattern_at(&self, clip_idx: usize) -> Option<&Pattern> {
         self.clips.get(clip_idx).and_then(|c| c.pattern())
@@ -200,9 +614,182 @@ impl Track {
 
     pub fn get_pattern_at_sequence(&self, seq_idx: usize) -> (Option<&Pattern>, MusicalTime) {
         if seq_idx > self.sequence.len() {
-            return (None, MusicalTime::zero())
+            return (None, MusicalTime::zero());
+        }
+        (self.get_pattern_at(self.sequence[seq_idx].clip_idx as usize), self.sequence[seq_idx].start)
+    }
+
+    // --- Sequence editing ---
+    //
+    // The index-based edits treat the sequence as an order list: entries
+    // after the edited one move in time so none overlap and existing gaps
+    // are kept. `rows_per_beat` is the song's, for clips without their own.
+
+    /// Place an entry at `beat`, replacing any entry starting there, or just
+    /// remove the one there with `None`. Other entries don't move.
+    pub fn set_seq_entry(&mut self, beat: u32, entry: Option<SeqEntryData>) {
+        self.sequence.retain(|e| e.start.beat as u32 != beat);
+        if let Some(data) = entry {
+            let new_entry = SeqEntry {
+                start: MusicalTime::from_beats(beat as u64),
+                clip_idx: data.clip_idx,
+                length: data.length,
+                termination: data.termination,
+                boundary: data.boundary,
+            };
+            // Insert sorted by start time
+            let pos = self.sequence.iter()
+                .position(|e| e.start > new_entry.start)
+                .unwrap_or(self.sequence.len());
+            self.sequence.insert(pos, new_entry);
+        }
+    }
+
+    /// Insert a full-length entry for `clip_idx` at sequence position
+    /// `index` (clamped to the end). Returns false for an unknown clip.
+    pub fn insert_seq_entry(&mut self, index: usize, clip_idx: u16, rows_per_beat: u8) -> bool {
+        let Some(pattern) = self.get_pattern_at(clip_idx as usize) else { return false };
+        let entry = SeqEntry {
+            start: MusicalTime::zero(),
+            clip_idx,
+            length: pattern.rows,
+            termination: SeqTermination::Natural,
+            boundary: ClipBoundary::Ring,
+        };
+        self.insert_seq_entry_at(index, entry, rows_per_beat);
+        true
+    }
+
+    /// Remove the entry at sequence position `index`, pulling later entries
+    /// in to close its span.
+    pub fn remove_seq_entry(&mut self, index: usize, rows_per_beat: u8) -> Option<SeqEntry> {
+        let span = self.seq_entry_span(index, rows_per_beat)?;
+        let entry = self.sequence.remove(index);
+        self.shift_seq_entries(index, -(span as i64));
+        Some(entry)
+    }
+
+    /// Move the entry at position `from` to position `to`.
+    pub fn move_seq_entry(&mut self, from: usize, to: usize, rows_per_beat: u8) -> bool {
+        if to >= self.sequence.len() {
+            return false;
+        }
+        let Some(entry) = self.remove_seq_entry(from, rows_per_beat) else { return false };
+        self.insert_seq_entry_at(to, entry, rows_per_beat);
+        true
+    }
+
+    /// Play the entry at position `index` twice in a row.
+    pub fn duplicate_seq_entry(&mut self, index: usize, rows_per_beat: u8) -> bool {
+        let Some(&entry) = self.sequence.get(index) else { return false };
+        let start = self.seq_entry_end(index, rows_per_beat);
+        self.place_seq_entry(index + 1, SeqEntry { start, ..entry }, rows_per_beat);
+        true
+    }
+
+    /// Play `clip_idx` (at its full length) at sequence position `index`
+    /// instead of the current clip. Returns false for an unknown position or clip.
+    pub fn set_seq_clip(&mut self, index: usize, clip_idx: u16, rows_per_beat: u8) -> bool {
+        let Some(rows) = self.get_pattern_at(clip_idx as usize).map(|p| p.rows) else { return false };
+        if index >= self.sequence.len() {
+            return false;
+        }
+        self.sequence[index].clip_idx = clip_idx;
+        self.set_seq_entry_length(index, rows, rows_per_beat);
+        true
+    }
+
+    /// Change clip `clip_idx`'s row count (at least 1). Sequence entries
+    /// playing it in full follow the new length, shorter ones are cut to
+    /// fit, and later entries move to keep their distance.
+    pub fn resize_clip(&mut self, clip_idx: u16, rows: u16, rows_per_beat: u8) -> bool {
+        let Some(pattern) = self.clips.get_mut(clip_idx as usize).and_then(|c| c.pattern_mut()) else { return false };
+        let old_rows = pattern.rows;
+        pattern.resize(rows);
+        let rows = pattern.rows;
+        for index in 0..self.sequence.len() {
+            let entry = self.sequence[index];
+            if entry.clip_idx == clip_idx {
+                let length = if entry.length >= old_rows { rows } else { entry.length.min(rows) };
+                self.set_seq_entry_length(index, length, rows_per_beat);
+            }
+        }
+        true
+    }
+
+    /// Add a copy of clip `clip_idx` to the pool; returns the copy's index.
+    pub fn clone_clip(&mut self, clip_idx: u16) -> Option<u16> {
+        let clip = self.clips.get(clip_idx as usize)?.clone();
+        self.clips.push(clip);
+        Some(self.clips.len() as u16 - 1)
+    }
+
+    /// Add an empty clip of `rows` rows to the pool; returns its index.
+    pub fn add_clip(&mut self, rows: u16) -> u16 {
+        self.clips.push(Clip::Pattern(Pattern::new(rows, self.num_channels)));
+        self.clips.len() as u16 - 1
+    }
+
+    /// Remove the last clip from the pool. Returns false if the pool is
+    /// empty or the sequence still plays the clip.
+    pub fn remove_last_clip(&mut self) -> bool {
+        let Some(last) = self.clips.len().checked_sub(1) else { return false };
+        if self.sequence.iter().any(|e| e.clip_idx as usize == last) {
+            return false;
+        }
+        self.clips.pop();
+        true
+    }
+
+    /// Set the entry at `index` to play `length` rows, moving later entries
+    /// by the change in its span.
+    fn set_seq_entry_length(&mut self, index: usize, length: u16, rows_per_beat: u8) {
+        let old_span = self.seq_entry_span(index, rows_per_beat).unwrap_or(0);
+        self.sequence[index].length = length;
+        let new_span = self.seq_entry_span(index, rows_per_beat).unwrap_or(0);
+        self.shift_seq_entries(index + 1, new_span as i64 - old_span as i64);
+    }
+
+    /// Insert `entry` at `index`, starting where the entry it displaces
+    /// started (or where the last entry ends), and push later entries back.
+    fn insert_seq_entry_at(&mut self, index: usize, mut entry: SeqEntry, rows_per_beat: u8) {
+        let index = index.min(self.sequence.len());
+        entry.start = match (self.sequence.get(index), index.checked_sub(1)) {
+            (Some(next), _) => next.start,
+            (None, Some(last)) => self.seq_entry_end(last, rows_per_beat),
+            (None, None) => MusicalTime::zero(),
+        };
+        self.place_seq_entry(index, entry, rows_per_beat);
+    }
+
+    /// Insert `entry` at `index` as is and push later entries back by its span.
+    fn place_seq_entry(&mut self, index: usize, entry: SeqEntry, rows_per_beat: u8) {
+        self.sequence.insert(index, entry);
+        let span = self.seq_entry_span(index, rows_per_beat).unwrap_or(0);
+        self.shift_seq_entries(index + 1, span as i64);
+    }
+
+    /// When the entry at `index` finishes playing in full.
+    fn seq_entry_end(&self, index: usize, rows_per_beat: u8) -> MusicalTime {
+        let span = self.seq_entry_span(index, rows_per_beat).unwrap_or(0);
+        MusicalTime::from_sub_beats(self.sequence[index].start.as_sub_beats() + span)
+    }
+
+    /// Sub-beats the entry at `index` plays for.
+    fn seq_entry_span(&self, index: usize, rows_per_beat: u8) -> Option<u64> {
+        let entry = self.sequence.get(index)?;
+        let rpb = self.get_pattern_at(entry.clip_idx as usize)
+            .and_then(|p| p.rows_per_beat)
+            .unwrap_or(rows_per_beat);
+        let end = entry.start.add_rows(entry.length as u32, rpb as u32);
+        Some(end.as_sub_beats() - entry.start.as_sub_beats())
+    }
+
+    /// Move the entries from `index` on by `delta` sub-beats.
+    fn shift_seq_entries(&mut self, index: usize, delta: i64) {
+        for entry in self.sequence.iter_mut().skip(index) {
+            entry.start = MusicalTime::from_sub_beats(entry.start.as_sub_beats().saturating_add_signed(delta));
         }
-        return (self.get_pattern_at(self.sequence[seq_idx].clip_idx as usize), self.sequence[seq_idx].start);
     }
 }
 
@@ -239,6 +826,18 @@ pub enum SeqTermination {
     Break,
 }
 
+/// What happens to notes still sounding when a sequence entry ends.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClipBoundary {
+    /// Notes keep ringing into whatever plays next.
+    #[default]
+    Ring,
+    /// Notes are cut instantly at the boundary.
+    Cut,
+    /// Notes receive a note-off (release) at the boundary.
+    Release,
+}
+
 /// An entry in a track's sequence (playback order).
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct SeqEntry {
@@ -250,6 +849,8 @@ pub struct SeqEntry {
     pub length: u16,
     /// How this entry's playback ends.
     pub termination: SeqTermination,
+    /// What happens to sounding notes when this entry ends.
+    pub boundary: ClipBoundary,
 }
 
 // --- Track building from legacy format data ---
@@ -291,6 +892,69 @@ pub fn build_tracks(
 
     track.sequence = build_sequence_from_order(order, patterns, song.rows_per_beat);
     song.tracks = alloc::vec![track];
+    song.subsongs = build_subsongs(order, patterns, song.rows_per_beat);
+}
+
+/// Split an order list into subsongs.
+///
+/// The first subsong starts at order 0. Each following subsong starts at
+/// the first Pattern entry not reached by playing the earlier ones (taking
+/// PatternBreak/PositionJump into account). A subsong runs until the next
+/// End marker or the end of the order list.
+fn build_subsongs(order: &[OrderEntry], patterns: &[Pattern], song_rpb: u8) -> Vec<Subsong> {
+    let mut visited = alloc::vec![false; order.len()];
+    let mut subsongs = Vec::new();
+    let mut start = Some(0).filter(|_| !order.is_empty());
+
+    while let Some(first) = start {
+        walk_order(order, patterns, first, &mut visited);
+        let end = order[first..].iter()
+            .position(|e| *e == OrderEntry::End)
+            .map_or(order.len(), |i| first + i);
+
+        let mut name = ArrayString::new();
+        let _ = core::fmt::write(&mut name, format_args!("Subsong {}", subsongs.len() + 1));
+        subsongs.push(Subsong {
+            name,
+            order_start: first as u16,
+            sequence: build_sequence_from_order(&order[first..end], patterns, song_rpb),
+        });
+
+        start = (0..order.len())
+            .find(|&i| !visited[i] && matches!(order[i], OrderEntry::Pattern(_)));
+    }
+    subsongs
+}
+
+/// Follow playback through the order list from `first`, marking entries
+/// reached until the walk hits an End marker, falls off the end or loops.
+fn walk_order(order: &[OrderEntry], patterns: &[Pattern], first: usize, visited: &mut [bool]) {
+    let mut cur = first;
+    while cur < order.len() && !visited[cur] {
+        visited[cur] = true;
+        cur = match order[cur] {
+            OrderEntry::End => return,
+            OrderEntry::Skip => cur + 1,
+            OrderEntry::Pattern(p) => patterns.get(p as usize)
+                .and_then(pattern_exit_order)
+                .unwrap_or(cur + 1),
+        };
+    }
+}
+
+/// Order index a pattern jumps to via its first PositionJump, if any.
+///
+/// A PatternBreak before any jump continues with the next order entry,
+/// which is the same as falling off the end of the pattern.
+fn pattern_exit_order(pattern: &Pattern) -> Option<usize> {
+    pattern.data.iter()
+        .flat_map(Cell::effects)
+        .find_map(|effect| match effect {
+            Effect::PositionJump(p) => Some(Some(p as usize)),
+            Effect::PatternBreak(_) => Some(None),
+            _ => None,
+        })
+        .flatten()
 }
 
 /// Build a sequence from a legacy order list, computing start times.
@@ -307,7 +971,13 @@ fn build_sequence_from_order(
         match entry {
             OrderEntry::Pattern(idx) => {
                 let length = patterns.get(*idx as usize).map_or(0, |p| p.rows);
-                sequence.push(SeqEntry { start: time, clip_idx: *idx as u16, length, termination: SeqTermination::Natural });
+                sequence.push(SeqEntry {
+                    start: time,
+                    clip_idx: *idx as u16,
+                    length,
+                    termination: SeqTermination::Natural,
+                    boundary: ClipBoundary::Ring,
+                });
                 if let Some(pattern) = patterns.get(*idx as usize) {
                     let pat_rpb = pattern.rows_per_beat.map_or(rpb, |r| r as u32);
                     time = time.add_rows(pattern.rows as u32, pat_rpb);
@@ -335,6 +1005,147 @@ fn track_end_time(track: &Track, song_rpb: u8) -> Option<MusicalTime> {
 mod tests {
     use super::*;
 
+    /// A track with clips of 4 and 8 rows and the sequence [0 @ beat 0, 1 @ beat 4].
+    fn seq_track() -> Track {
+        let mut track = Track::new(None, 0, 1);
+        track.clips.push(Clip::Pattern(Pattern::new(4, 1)));
+        track.clips.push(Clip::Pattern(Pattern::new(8, 1)));
+        track.insert_seq_entry(0, 0, 4);
+        track.insert_seq_entry(1, 1, 4);
+        track.sequence[1].start = MusicalTime::from_beats(4);
+        track
+    }
+
+    fn layout(track: &Track) -> Vec<(u16, u64)> {
+        track.sequence.iter().map(|e| (e.clip_idx, e.start.beat)).collect()
+    }
+
+    #[test]
+    fn insert_and_remove_seq_entries_shift_later_ones() {
+        let mut track = seq_track();
+        assert!(track.insert_seq_entry(1, 1, 4));
+        // Takes the displaced entry's start, keeping the gap before it
+        assert_eq!(layout(&track), [(0, 0), (1, 4), (1, 6)]);
+        assert!(track.insert_seq_entry(9, 0, 4), "clamped to the end");
+        assert_eq!(layout(&track), [(0, 0), (1, 4), (1, 6), (0, 8)]);
+        assert!(!track.insert_seq_entry(0, 7, 4), "unknown clip");
+        assert_eq!(track.remove_seq_entry(1, 4).map(|e| e.clip_idx), Some(1));
+        assert_eq!(layout(&track), [(0, 0), (1, 4), (0, 6)]);
+        assert!(track.remove_seq_entry(3, 4).is_none());
+    }
+
+    #[test]
+    fn resize_clip_refits_entries_playing_it() {
+        let mut track = seq_track();
+        track.duplicate_seq_entry(0, 4);
+        track.sequence[1].length = 2;
+        assert_eq!(layout(&track), [(0, 0), (0, 1), (1, 5)]);
+        assert!(track.resize_clip(0, 8, 4));
+        // Full-length entries grow, the shortened one keeps its length
+        assert_eq!(track.sequence.iter().map(|e| e.length).collect::<Vec<_>>(), [8, 2, 8]);
+        assert_eq!(layout(&track), [(0, 0), (0, 2), (1, 6)]);
+        assert!(track.resize_clip(0, 1, 4));
+        assert_eq!(track.sequence.iter().map(|e| e.length).collect::<Vec<_>>(), [1, 1, 8]);
+        assert!(!track.resize_clip(5, 4, 4));
+    }
+
+    #[test]
+    fn add_and_remove_tracks_keep_channels_and_nodes_consistent() {
+        let mut song = Song::with_channels("tracks", 2);
+        build_tracks(&mut song, &[Pattern::new(4, 2)], &[OrderEntry::Pattern(0)]);
+        let nodes = song.graph.nodes.len();
+        let a = song.add_track(3, "Lead").unwrap();
+        let b = song.add_track(1, "Bass").unwrap();
+        assert_eq!((song.channels.len(), song.graph.nodes.len()), (6, nodes + 2));
+        assert_eq!((song.tracks[b].base_channel, song.tracks[b].name.as_str()), (5, "Bass"));
+        let bass_node = song.tracks[b].machine_node;
+        assert!(song.graph.connections.iter().any(|c| Some(c.from) == bass_node && c.to == 0));
+
+        assert!(song.remove_track(a));
+        assert_eq!((song.channels.len(), song.graph.nodes.len()), (3, nodes + 1));
+        let bass = &song.tracks[a];
+        assert_eq!((bass.name.as_str(), bass.base_channel), ("Bass", 2));
+        assert_eq!(bass.machine_node, bass_node.map(|n| n - 1));
+        assert!(!song.remove_track(9));
+        assert!(song.add_track(253, "Too wide").is_none());
+    }
+
+    #[test]
+    fn midi_track_plays_an_unwired_midi_out_node() {
+        let mut song = Song::with_channels("tracks", 2);
+        let t = song.add_midi_track(2, "Juno", "USB MIDI", 20).unwrap();
+        let node = song.tracks[t].machine_node.unwrap();
+        let port = String::from("USB MIDI");
+        assert_eq!(song.graph.node(node).unwrap().node_type, NodeType::MidiOut { port, channel: 15 });
+        assert!(!song.graph.connections.iter().any(|c| c.from == node));
+        assert_eq!(song.channels.len(), 4);
+    }
+
+    #[test]
+    fn shared_node_stays_with_remaining_tracks() {
+        let mut song = Song::with_channels("tracks", 2);
+        build_tracks(&mut song, &[Pattern::new(4, 2)], &[OrderEntry::Pattern(0)]);
+        let mut twin = song.tracks[0].clone();
+        twin.set_name("A name well over thirty-two bytes long");
+        song.tracks.push(twin);
+        assert_eq!(song.tracks[1].name.len(), 32);
+        assert_eq!(song.exclusive_node(1), None);
+        let nodes = song.graph.nodes.len();
+        assert!(song.remove_track(1));
+        assert_eq!((song.graph.nodes.len(), song.channels.len()), (nodes, 2));
+        assert!(song.exclusive_node(0).is_some());
+    }
+
+    #[test]
+    fn move_track_reorders() {
+        let mut song = Song::with_channels("tracks", 0);
+        song.add_track(1, "a");
+        song.add_track(1, "b");
+        song.add_track(1, "c");
+        assert!(song.move_track(0, 2));
+        let names: Vec<_> = song.tracks.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["b", "c", "a"]);
+        assert!(!song.move_track(0, 3));
+    }
+
+    #[test]
+    fn clone_clip_appends_a_copy() {
+        let mut track = seq_track();
+        track.clips[1].pattern_mut().unwrap().cell_mut(3, 0).instrument = 7;
+        assert_eq!(track.clone_clip(1), Some(2));
+        assert_eq!(track.get_pattern_at(2).unwrap().cell(3, 0).instrument, 7);
+        assert_eq!(track.clone_clip(9), None);
+
+        assert!(track.remove_last_clip());
+        assert_eq!(track.add_clip(8), 2);
+        assert_eq!(track.get_pattern_at(2).unwrap().rows, 8);
+        // Clip 1 is still in the sequence
+        assert!(track.remove_last_clip());
+        assert!(!track.remove_last_clip());
+    }
+
+    #[test]
+    fn move_duplicate_and_set_clip_keep_entries_apart() {
+        let mut track = seq_track();
+        assert!(track.move_seq_entry(0, 1, 4));
+        // The gap before clip 1 stays put
+        assert_eq!(layout(&track), [(1, 3), (0, 5)]);
+        assert!(track.duplicate_seq_entry(1, 4));
+        assert_eq!(layout(&track), [(1, 3), (0, 5), (0, 6)]);
+        assert!(track.set_seq_clip(1, 1, 4));
+        assert_eq!(layout(&track), [(1, 3), (1, 5), (0, 7)]);
+        assert!(!track.move_seq_entry(0, 3, 4));
+        assert!(!track.set_seq_clip(5, 0, 4));
+    }
+
+    #[test]
+    fn duplicate_plays_right_after_the_original() {
+        let mut track = seq_track();
+        assert!(track.duplicate_seq_entry(0, 4));
+        // The copy fills part of the gap; the entry after it keeps its distance
+        assert_eq!(layout(&track), [(0, 0), (0, 1), (1, 5)]);
+    }
+
     fn make_test_song() -> Song {
         let mut song = Song::with_channels("test", 4);
 
@@ -364,6 +1175,21 @@ mod tests {
         assert_eq!(song.tracks[0].base_channel, 0);
     }
 
+    #[test]
+    fn remove_node_renumbers_track_machines() {
+        let mut song = make_test_song();
+        let tracker = song.tracks[0].machine_node.unwrap();
+        let synth = song.graph.add_node(NodeType::Machine {
+            machine_name: alloc::string::String::from("Synth"),
+            is_tracker: false,
+        });
+        song.tracks.push(Track::new(Some(synth), 0, 1));
+        assert!(song.remove_node(tracker));
+        assert_eq!(song.tracks[0].machine_node, None);
+        assert_eq!(song.tracks[1].machine_node, Some(synth - 1));
+        assert!(!song.remove_node(0));
+    }
+
     #[test]
     fn build_tracks_clips_match_patterns() {
         let song = make_test_song();
@@ -460,6 +1286,85 @@ mod tests {
         assert!(matches!(&node.node_type, NodeType::Machine { is_tracker: true, .. }));
     }
 
+    /// Pattern with a PositionJump to `order` on its last row.
+    fn jump_pattern(rows: u16, order: u8) -> Pattern {
+        let mut pat = Pattern::new(rows, 1);
+        pat.cell_mut(rows - 1, 0).effect = Effect::PositionJump(order);
+        pat
+    }
+
+    #[test]
+    fn build_tracks_single_subsong() {
+        let song = make_test_song();
+        assert_eq!(song.subsongs.len(), 1);
+        assert_eq!(song.subsongs[0].order_start, 0);
+        assert_eq!(song.subsongs[0].sequence, song.tracks[0].sequence);
+    }
+
+    #[test]
+    fn subsongs_split_at_end_markers() {
+        let mut song = Song::with_channels("test", 1);
+        build_tracks(&mut song, &[Pattern::new(4, 1), Pattern::new(8, 1)], &[
+            OrderEntry::Pattern(0), OrderEntry::End, OrderEntry::Pattern(1), OrderEntry::Pattern(0),
+        ]);
+        assert_eq!(song.subsongs.len(), 2);
+        assert_eq!(song.subsongs[0].sequence.len(), 1);
+        let second = &song.subsongs[1];
+        assert_eq!(second.order_start, 2);
+        assert_eq!(second.sequence.len(), 2);
+        assert_eq!(second.sequence[0].start, MusicalTime::zero());
+        assert_eq!(second.sequence[0].clip_idx, 1);
+        assert_eq!(second.sequence[1].start, MusicalTime::from_beats(2));
+    }
+
+    #[test]
+    fn subsongs_detect_hidden_song_after_loop() {
+        let mut song = Song::with_channels("test", 1);
+        // Orders 0-1 loop back to 0; orders 2-3 are never reached from the start.
+        build_tracks(&mut song, &[Pattern::new(4, 1), jump_pattern(4, 0), jump_pattern(4, 2)], &[
+            OrderEntry::Pattern(0), OrderEntry::Pattern(1), OrderEntry::Pattern(0), OrderEntry::Pattern(2),
+        ]);
+        assert_eq!(song.subsongs.len(), 2);
+        assert_eq!(song.subsongs[1].order_start, 2);
+        assert_eq!(song.subsongs[1].sequence.len(), 2);
+    }
+
+    #[test]
+    fn subsongs_follow_forward_jumps() {
+        let mut song = Song::with_channels("test", 1);
+        // Order 0 jumps over order 1, so order 1 starts its own subsong.
+        build_tracks(&mut song, &[jump_pattern(4, 2), Pattern::new(4, 1)], &[
+            OrderEntry::Pattern(0), OrderEntry::Pattern(1), OrderEntry::Pattern(1),
+        ]);
+        assert_eq!(song.subsongs.len(), 2);
+        assert_eq!(song.subsongs[1].order_start, 1);
+    }
+
+    #[test]
+    fn select_subsong_swaps_sequence_and_order_base() {
+        let mut song = Song::with_channels("test", 1);
+        build_tracks(&mut song, &[Pattern::new(4, 1), Pattern::new(8, 1)], &[
+            OrderEntry::Pattern(0), OrderEntry::End, OrderEntry::Pattern(1),
+        ]);
+        assert!(song.select_subsong(1));
+        assert_eq!(song.tracks[0].sequence[0].clip_idx, 1);
+        assert_eq!(song.tracks[0].order_base, 2);
+        assert_eq!(song.total_time(), MusicalTime::from_beats(2));
+        assert!(!song.select_subsong(5));
+    }
+
+    #[test]
+    fn jump_target_rebases_by_order_base() {
+        let mut track = Track::new(None, 0, 1);
+        track.sequence.push(SeqEntry {
+            start: MusicalTime::zero(), clip_idx: 0, length: 4,
+            termination: SeqTermination::Natural, boundary: ClipBoundary::Ring,
+        });
+        track.order_base = 2;
+        assert_eq!(track.jump_target(2), 0);
+        assert_eq!(track.jump_target(1), 1, "jump before the subsong leaves it");
+    }
+
     #[test]
     fn seq_entry_at_beat_found() {
         let song = make_test_song();
@@ -0,0 +1,200 @@
+//! Iterators over song content.
+//!
+//! Walk every cell of every clip, or the rows a track plays in sequence
+//! order, without nested index loops. Mutating walks either borrow cells
+//! mutably or collect `Edit::SetCell` commands for the undo/edit pipeline.
+
+use alloc::vec::Vec;
+
+use crate::edit::Edit;
+use crate::musical_time::MusicalTime;
+use crate::pattern::{Cell, Pattern};
+use crate::song::{Song, Track};
+
+/// Location of a pattern cell within a song.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CellPos {
+    pub track: u16,
+    pub clip: u16,
+    pub row: u16,
+    pub column: u8,
+}
+
+impl CellPos {
+    /// Edit that writes `cell` at this position.
+    pub fn set(self, cell: Cell) -> Edit {
+        Edit::SetCell { track: self.track, clip: self.clip, row: self.row, column: self.column, cell }
+    }
+}
+
+/// One row as played by a track's sequence.
+#[derive(Clone, Copy, Debug)]
+pub struct ScheduledRow<'a> {
+    /// Index into the track's sequence
+    pub seq_index: usize,
+    /// Clip the row belongs to
+    pub clip_idx: u16,
+    /// Row within the clip
+    pub row: u16,
+    /// When the row starts
+    pub time: MusicalTime,
+    /// The row's cells, one per column
+    pub cells: &'a [Cell],
+}
+
+impl Pattern {
+    /// Rows as slices of cells, one per column.
+    pub fn iter_rows(&self) -> impl Iterator<Item = &[Cell]> {
+        self.data.chunks(self.channels.max(1) as usize)
+    }
+
+    /// Every cell with its (row, column).
+    pub fn iter_cells(&self) -> impl Iterator<Item = (u16, u8, &Cell)> {
+        let channels = self.channels.max(1) as usize;
+        self.data.iter().enumerate().map(move |(i, cell)| ((i / channels) as u16, (i % channels) as u8, cell))
+    }
+
+    /// Every cell with its (row, column), mutably.
+    pub fn iter_cells_mut(&mut self) -> impl Iterator<Item = (u16, u8, &mut Cell)> {
+        let channels = self.channels.max(1) as usize;
+        self.data.iter_mut().enumerate().map(move |(i, cell)| ((i / channels) as u16, (i % channels) as u8, cell))
+    }
+}
+
+impl Track {
+    /// Rows in playback order: each sequence entry's clip from row 0, up to
+    /// its length or the next entry's start, whichever comes first.
+    ///
+    /// Jumps and breaks inside the clips are not followed; `length` already
+    /// reflects truncation recorded at load time.
+    pub fn iter_scheduled_rows(&self, rows_per_beat: u8) -> impl Iterator<Item = ScheduledRow<'_>> {
+        self.sequence.iter().enumerate().flat_map(move |(seq_index, entry)| {
+            let pattern = self.get_pattern_at(entry.clip_idx as usize);
+            let rpb = pattern.and_then(|p| p.rows_per_beat).unwrap_or(rows_per_beat).max(1) as u32;
+            let next_start = self.sequence.get(seq_index + 1).map(|e| e.start);
+            pattern
+                .into_iter()
+                .flat_map(|p| p.iter_rows().zip(0..entry.length))
+                .map(move |(cells, row)| ScheduledRow {
+                    seq_index,
+                    clip_idx: entry.clip_idx,
+                    row,
+                    time: entry.start.add_rows(row as u32, rpb),
+                    cells,
+                })
+                .take_while(move |r| next_start.is_none_or(|next| r.time < next))
+        })
+    }
+}
+
+impl Song {
+    /// Every pattern cell in every clip of every track.
+    pub fn iter_cells(&self) -> impl Iterator<Item = (CellPos, &Cell)> {
+        self.tracks.iter().enumerate().flat_map(|(track, t)| {
+            t.clips.iter().enumerate().flat_map(move |(clip, c)| {
+                c.pattern().into_iter().flat_map(move |p| {
+                    p.iter_cells().map(move |(row, column, cell)| {
+                        (CellPos { track: track as u16, clip: clip as u16, row, column }, cell)
+                    })
+                })
+            })
+        })
+    }
+
+    /// Every pattern cell, mutably. Bypasses the edit pipeline; use
+    /// `cell_edits` for changes that must reach a playing engine.
+    pub fn iter_cells_mut(&mut self) -> impl Iterator<Item = (CellPos, &mut Cell)> {
+        self.tracks.iter_mut().enumerate().flat_map(|(track, t)| {
+            t.clips.iter_mut().enumerate().flat_map(move |(clip, c)| {
+                c.pattern_mut().into_iter().flat_map(move |p| {
+                    p.iter_cells_mut().map(move |(row, column, cell)| {
+                        (CellPos { track: track as u16, clip: clip as u16, row, column }, cell)
+                    })
+                })
+            })
+        })
+    }
+
+    /// Collect `SetCell` edits for every cell `f` maps to a different value.
+    pub fn cell_edits(&self, mut f: impl FnMut(CellPos, &Cell) -> Option<Cell>) -> Vec<Edit> {
+        self.iter_cells()
+            .filter_map(|(pos, cell)| f(pos, cell).filter(|new| new != cell).map(|new| pos.set(new)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::Note;
+    use crate::song::{ClipBoundary, Clip, SeqEntry, SeqTermination};
+
+    fn entry(beat: u64, clip_idx: u16, length: u16) -> SeqEntry {
+        SeqEntry {
+            start: MusicalTime::from_beats(beat),
+            clip_idx,
+            length,
+            termination: SeqTermination::Natural,
+            boundary: ClipBoundary::Ring,
+        }
+    }
+
+    /// One track, two 8-row 2-column clips; clip 1 has a note at (3, 1).
+    fn song() -> Song {
+        let mut song = Song::new("iter");
+        let mut track = Track::new(None, 0, 2);
+        track.clips.push(Clip::Pattern(Pattern::new(8, 2)));
+        let mut pat = Pattern::new(8, 2);
+        pat.cell_mut(3, 1).note = Note::On(60);
+        track.clips.push(Clip::Pattern(pat));
+        song.tracks.push(track);
+        song
+    }
+
+    #[test]
+    fn iter_cells_visits_every_cell_with_position() {
+        let song = song();
+        assert_eq!(song.iter_cells().count(), 2 * 8 * 2);
+        let notes: Vec<CellPos> = song.iter_cells().filter(|(_, c)| c.note != Note::None).map(|(p, _)| p).collect();
+        assert_eq!(notes, [CellPos { track: 0, clip: 1, row: 3, column: 1 }]);
+    }
+
+    #[test]
+    fn iter_cells_mut_writes_in_place() {
+        let mut song = song();
+        for (_, cell) in song.iter_cells_mut() {
+            cell.instrument = 2;
+        }
+        assert!(song.iter_cells().all(|(_, c)| c.instrument == 2));
+    }
+
+    #[test]
+    fn cell_edits_skip_unchanged_cells() {
+        let song = song();
+        let edits = song.cell_edits(|_, c| match c.note {
+            Note::On(n) => Some(Cell { note: Note::On(n + 12), ..*c }),
+            _ => Some(*c),
+        });
+        let mut expected = Cell::empty();
+        expected.note = Note::On(72);
+        assert_eq!(edits, [CellPos { track: 0, clip: 1, row: 3, column: 1 }.set(expected)]);
+    }
+
+    #[test]
+    fn scheduled_rows_follow_sequence_and_truncate_at_next_start() {
+        let mut song = song();
+        let track = &mut song.tracks[0];
+        // clip 1 at beat 0 cut off by clip 0 at beat 1 (4 rows per beat)
+        track.sequence = [entry(0, 1, 8), entry(1, 0, 6)].into();
+        let rows: Vec<(usize, u16, u16)> = track.iter_scheduled_rows(4).map(|r| (r.seq_index, r.clip_idx, r.row)).collect();
+        let expected: Vec<(usize, u16, u16)> =
+            (0..4).map(|r| (0, 1, r)).chain((0..6).map(|r| (1, 0, r))).collect();
+        assert_eq!(rows, expected);
+
+        let row = track.iter_scheduled_rows(4).nth(3).unwrap();
+        assert_eq!(row.cells[1].note, Note::On(60));
+        assert_eq!(row.time, MusicalTime::zero().add_rows(3, 4));
+        let last = track.iter_scheduled_rows(4).last().unwrap();
+        assert_eq!(last.time, MusicalTime::from_beats(1).add_rows(5, 4));
+    }
+}
@@ -0,0 +1,230 @@
+//! Synthetic song generators for benchmarks and stress tests.
+//!
+//! `SongTemplate` builds deterministic songs of arbitrary size — many tracks,
+//! dense clips, heavy effect usage, long effect chains — so the scaling of
+//! the scheduler and render loop can be measured directly.
+
+use alloc::format;
+use alloc::vec::Vec;
+
+use crate::effects::{Effect, VolumeCommand};
+use crate::graph::{NodeId, NodeType};
+use crate::instrument::Instrument;
+use crate::musical_time::MusicalTime;
+use crate::pattern::{Cell, Note, Pattern};
+use crate::sample::{Sample, SampleData};
+use crate::song::{ChannelSettings, Clip, ClipBoundary, SeqEntry, SeqTermination, Song, Track};
+
+/// Shape of a synthetic song.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SongTemplate {
+    /// Number of tracks, each driving its own tracker node.
+    pub tracks: u16,
+    /// Pattern columns per track.
+    pub channels_per_track: u8,
+    /// Rows per clip.
+    pub rows: u16,
+    /// Clips per track, played back to back.
+    pub clips_per_track: u16,
+    /// Percentage (0-100) of cells carrying a note.
+    pub note_density: u8,
+    /// Percentage (0-100) of cells carrying a per-tick effect.
+    pub effect_density: u8,
+    /// Passthrough effect nodes chained between the trackers and master.
+    pub graph_depth: u16,
+}
+
+impl Default for SongTemplate {
+    fn default() -> Self {
+        Self {
+            tracks: 1,
+            channels_per_track: 4,
+            rows: 64,
+            clips_per_track: 1,
+            note_density: 25,
+            effect_density: 0,
+            graph_depth: 0,
+        }
+    }
+}
+
+/// Rows per beat used by template songs.
+const ROWS_PER_BEAT: u8 = 4;
+
+/// Effects cycled through on effect cells: every modulator kind the
+/// tracker runs per tick.
+const EFFECTS: [Effect; 6] = [
+    Effect::Vibrato { speed: 4, depth: 8 },
+    Effect::VolumeSlide(-2),
+    Effect::Arpeggio { x: 3, y: 7 },
+    Effect::PortaUp(2),
+    Effect::Tremolo { speed: 6, depth: 4 },
+    Effect::RetriggerNote(3),
+];
+
+impl SongTemplate {
+    /// Worst case the engine is expected to handle: 256 tracks of dense
+    /// 64-row clips with heavy effect usage and a 32-node effect chain.
+    pub fn worst_case() -> Self {
+        Self {
+            tracks: 256,
+            channels_per_track: 4,
+            rows: 64,
+            clips_per_track: 4,
+            note_density: 100,
+            effect_density: 75,
+            graph_depth: 32,
+        }
+    }
+
+    /// Build the song.
+    ///
+    /// Every track gets its own tracker node, so all tracks share one block
+    /// of channel settings at `base_channel` 0.
+    pub fn build(&self) -> Song {
+        let mut song = Song::new("template");
+        song.rows_per_beat = ROWS_PER_BEAT;
+        song.samples.push(sine_sample());
+        let mut inst = Instrument::new("sine");
+        inst.set_single_sample(0);
+        song.instruments.push(inst);
+
+        for i in 0..self.channels_per_track {
+            song.channels.push(ChannelSettings {
+                initial_pan: if i % 2 == 0 { -64 } else { 64 },
+                initial_vol: 64,
+                muted: false,
+                solo: false,
+            });
+        }
+
+        let bus = self.build_effect_chain(&mut song);
+        for t in 0..self.tracks {
+            let node = song.graph.add_node(NodeType::Machine {
+                machine_name: format!("Tracker {}", t),
+                is_tracker: true,
+            });
+            song.graph.connect(node, bus);
+            song.tracks.push(self.build_track(node, t));
+        }
+        song
+    }
+
+    /// Chain `graph_depth` passthrough nodes into master; return the chain input.
+    fn build_effect_chain(&self, song: &mut Song) -> NodeId {
+        let mut next: NodeId = 0;
+        for i in 0..self.graph_depth {
+            let id = song.graph.add_node(NodeType::Machine {
+                machine_name: format!("Passthrough {}", i),
+                is_tracker: false,
+            });
+            song.graph.connect(id, next);
+            next = id;
+        }
+        next
+    }
+
+    fn build_track(&self, node: NodeId, track_idx: u16) -> Track {
+        let mut track = Track::new(Some(node), 0, self.channels_per_track);
+        let beats_per_clip = (self.rows as u64).div_ceil(ROWS_PER_BEAT as u64);
+        for c in 0..self.clips_per_track {
+            track.clips.push(Clip::Pattern(self.build_pattern(track_idx, c)));
+            track.sequence.push(SeqEntry {
+                start: MusicalTime::from_beats(c as u64 * beats_per_clip),
+                clip_idx: c,
+                length: self.rows,
+                termination: SeqTermination::Natural,
+                boundary: ClipBoundary::Ring,
+            });
+        }
+        track
+    }
+
+    fn build_pattern(&self, track_idx: u16, clip_idx: u16) -> Pattern {
+        let mut pat = Pattern::new(self.rows, self.channels_per_track);
+        for row in 0..self.rows {
+            for col in 0..self.channels_per_track {
+                let h = hash(track_idx, clip_idx, row, col);
+                *pat.cell_mut(row, col) = self.cell_for(h);
+            }
+        }
+        pat
+    }
+
+    fn cell_for(&self, h: u32) -> Cell {
+        let mut cell = Cell::empty();
+        if h % 100 < self.note_density as u32 {
+            cell.note = Note::On(36 + (h >> 8) as u8 % 48);
+            cell.instrument = 1;
+            cell.volume = VolumeCommand::Volume(32 + (h >> 16) as u8 % 32);
+        }
+        if (h >> 4) % 100 < self.effect_density as u32 {
+            cell.effect = EFFECTS[(h >> 12) as usize % EFFECTS.len()];
+        }
+        cell
+    }
+}
+
+/// Deterministic cell hash (no RNG dependency).
+fn hash(track: u16, clip: u16, row: u16, col: u8) -> u32 {
+    let mut h = (track as u32).wrapping_mul(0x9E37_79B9)
+        ^ (clip as u32).wrapping_mul(0x85EB_CA6B)
+        ^ (row as u32).wrapping_mul(0xC2B2_AE35)
+        ^ (col as u32).wrapping_mul(0x27D4_EB2F);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2C1B_3C6D);
+    h ^ (h >> 12)
+}
+
+/// A looped single-cycle sine, long enough to keep voices sounding.
+fn sine_sample() -> Sample {
+    const LEN: usize = 2048;
+    let data: Vec<i8> = (0..LEN)
+        .map(|i| (libm::sinf(i as f32 * core::f32::consts::TAU / 64.0) * 127.0) as i8)
+        .collect();
+    let mut sample = Sample::new("sine");
+    sample.set_data(SampleData::Mono8(data));
+    sample.default_volume = 64;
+    sample.c4_speed = 8363;
+    sample.loop_start = 0;
+    sample.loop_end = LEN as u32;
+    sample.loop_type = crate::sample::LoopType::Forward;
+    sample
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn worst_case_shape() {
+        let t = SongTemplate::worst_case();
+        let song = t.build();
+        assert_eq!(song.tracks.len(), 256);
+        // master + chain + one tracker per track
+        assert_eq!(song.graph.nodes.len(), 1 + 32 + 256);
+        let track = &song.tracks[255];
+        assert_eq!(track.sequence.len(), 4);
+        assert_eq!(track.sequence[1].start, MusicalTime::from_beats(16));
+        assert!(song.is_tracker(track));
+    }
+
+    #[test]
+    fn densities_are_respected() {
+        let t = SongTemplate { note_density: 0, effect_density: 100, ..SongTemplate::default() };
+        let song = t.build();
+        let pat = song.tracks[0].clips[0].pattern().unwrap();
+        let cells: Vec<_> = (0..pat.rows).map(|r| pat.cell(r, 0)).collect();
+        assert!(cells.iter().all(|c| c.note == Note::None && c.effect != Effect::None));
+    }
+
+    #[test]
+    fn build_is_deterministic() {
+        let t = SongTemplate { effect_density: 50, ..SongTemplate::default() };
+        let a = t.build();
+        let b = t.build();
+        let pa = a.tracks[0].clips[0].pattern().unwrap();
+        let pb = b.tracks[0].clips[0].pattern().unwrap();
+        assert!((0..pa.rows).all(|r| pa.cell(r, 1) == pb.cell(r, 1)));
+    }
+}
@@ -0,0 +1,174 @@
+//! Tempo envelope: gradual tempo changes (ritardando, accelerando).
+//!
+//! Pattern tempo commands jump from one tempo to the next. The envelope
+//! instead draws the tempo as breakpoints over song time, joined by
+//! curves, and the engine re-reads it on every tick.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::musical_time::MusicalTime;
+
+/// A tempo in hundredths of a BPM, displayed as BPM with only the
+/// decimals it needs ("125", "97.5", "133.33").
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Bpm(pub u32);
+
+impl fmt::Display for Bpm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (whole, hundredths) = (self.0 / 100, self.0 % 100);
+        match hundredths {
+            0 => write!(f, "{}", whole),
+            h if h % 10 == 0 => write!(f, "{}.{}", whole, h / 10),
+            h => write!(f, "{}.{:02}", whole, h),
+        }
+    }
+}
+
+/// How the tempo moves from one point to the next.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TempoCurve {
+    /// Hold the point's tempo until the next point
+    Step,
+    /// Change by the same number of BPM per beat
+    #[default]
+    Linear,
+    /// Change by the same ratio per beat, which sounds even to the ear
+    Exponential,
+}
+
+/// A tempo at a song position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TempoPoint {
+    pub time: MusicalTime,
+    /// Tempo in hundredths of a BPM (below 1 BPM is treated as 1)
+    pub tempo: u32,
+    /// Curve from this point to the next
+    pub curve: TempoCurve,
+}
+
+/// The song's tempo over time, as breakpoints sorted by time.
+///
+/// An empty envelope leaves the tempo to pattern commands. Otherwise the
+/// envelope rules from its first point on, overriding tempo commands, and
+/// the last point's tempo holds after it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TempoEnvelope {
+    points: Vec<TempoPoint>,
+}
+
+impl TempoEnvelope {
+    /// An envelope through `points`, in any order. Of several points at
+    /// the same time, the last one wins.
+    pub fn new(points: impl IntoIterator<Item = TempoPoint>) -> Self {
+        let mut envelope = Self::default();
+        for point in points {
+            envelope.insert(point);
+        }
+        envelope
+    }
+
+    /// The breakpoints, in time order.
+    pub fn points(&self) -> &[TempoPoint] {
+        &self.points
+    }
+
+    /// Whether the envelope has no points.
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// When the envelope takes over, if it has any points.
+    pub fn start(&self) -> Option<MusicalTime> {
+        self.points.first().map(|p| p.time)
+    }
+
+    /// When the envelope settles on its last tempo, if it has any points.
+    pub fn end(&self) -> Option<MusicalTime> {
+        self.points.last().map(|p| p.time)
+    }
+
+    /// Add a point, replacing any at the same time.
+    pub fn insert(&mut self, point: TempoPoint) {
+        match self.points.binary_search_by_key(&point.time, |p| p.time) {
+            Ok(i) => self.points[i] = point,
+            Err(i) => self.points.insert(i, point),
+        }
+    }
+
+    /// Remove the point at `time`; returns whether there was one.
+    pub fn remove(&mut self, time: MusicalTime) -> bool {
+        let Ok(i) = self.points.binary_search_by_key(&time, |p| p.time) else { return false };
+        self.points.remove(i);
+        true
+    }
+
+    /// The tempo at `time` in hundredths of a BPM, or `None` before the
+    /// first point.
+    pub fn tempo_at(&self, time: MusicalTime) -> Option<u32> {
+        let next = self.points.partition_point(|p| p.time <= time);
+        let from = self.points.get(next.checked_sub(1)?)?;
+        let Some(to) = self.points.get(next) else { return Some(from.tempo.max(100)) };
+        let (a, b) = (from.tempo.max(100) as f32, to.tempo.max(100) as f32);
+        let span = (to.time.as_sub_beats() - from.time.as_sub_beats()) as f32;
+        let t = (time.as_sub_beats() - from.time.as_sub_beats()) as f32 / span;
+        let tempo = match from.curve {
+            TempoCurve::Step => a,
+            TempoCurve::Linear => a + (b - a) * t,
+            TempoCurve::Exponential => a * libm::powf(b / a, t),
+        };
+        Some(libm::roundf(tempo).max(100.0) as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(beat: u64, bpm: u32, curve: TempoCurve) -> TempoPoint {
+        TempoPoint { time: MusicalTime::from_beats(beat), tempo: bpm * 100, curve }
+    }
+
+    #[test]
+    fn tempo_follows_the_curves_between_points() {
+        let envelope = TempoEnvelope::new([
+            point(8, 60, TempoCurve::Step),
+            point(4, 120, TempoCurve::Linear),
+            point(12, 240, TempoCurve::Exponential),
+            point(16, 60, TempoCurve::Linear),
+        ]);
+        let at = |beat| envelope.tempo_at(MusicalTime::from_beats(beat));
+        assert_eq!(at(0), None);
+        assert_eq!(at(4), Some(12000));
+        assert_eq!(at(5), Some(10500));
+        assert_eq!(at(10), Some(6000));
+        assert_eq!(at(12), Some(24000));
+        assert_eq!(at(14), Some(12000));
+        assert_eq!(at(99), Some(6000));
+        // Ramps keep fractional tempos between whole BPMs
+        assert_eq!(envelope.tempo_at(MusicalTime::from_beats(4).add_rows(1, 3)), Some(11500));
+        // Points hold fractional and fast tempos too
+        let fast = TempoEnvelope::new([
+            TempoPoint { time: MusicalTime::zero(), tempo: 9750, curve: TempoCurve::Linear },
+            TempoPoint { time: MusicalTime::from_beats(2), tempo: 40000, curve: TempoCurve::Step },
+        ]);
+        assert_eq!(fast.tempo_at(MusicalTime::zero()), Some(9750));
+        assert_eq!(fast.tempo_at(MusicalTime::from_beats(3)), Some(40000));
+    }
+
+    #[test]
+    fn bpm_displays_only_the_decimals_it_needs() {
+        let shown = [12500, 9750, 13333].map(|t| alloc::format!("{}", Bpm(t)));
+        assert_eq!(shown, ["125", "97.5", "133.33"]);
+    }
+
+    #[test]
+    fn points_stay_sorted_and_unique_by_time() {
+        let mut envelope = TempoEnvelope::new([point(4, 100, TempoCurve::Linear), point(0, 80, TempoCurve::Linear)]);
+        envelope.insert(point(4, 140, TempoCurve::Step));
+        assert_eq!(envelope.points().iter().map(|p| p.tempo).collect::<Vec<_>>(), [8000, 14000]);
+        assert!(envelope.remove(MusicalTime::zero()));
+        assert!(!envelope.remove(MusicalTime::zero()));
+        assert_eq!(envelope.start(), Some(MusicalTime::from_beats(4)));
+    }
+}
@@ -0,0 +1,127 @@
+//! Autosave: periodic recovery snapshots of the song in the project format.
+//!
+//! While autosave is on, the controller writes the song to the recovery
+//! directory every so often if it changed since the last snapshot. Each
+//! run names its snapshots after its process ID, keeps only the latest
+//! few, and removes them on a clean exit, so whatever is left in the
+//! directory at startup was written by a run that crashed.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Snapshots kept per run; older ones are removed as new ones are written.
+const KEEP_SNAPSHOTS: usize = 3;
+
+/// File name prefix and extension of recovery snapshots.
+const SNAPSHOT_PREFIX: &str = "autosave-";
+const SNAPSHOT_EXTENSION: &str = "mbp";
+
+/// Autosave settings and the snapshots written by this run.
+pub(crate) struct Autosave {
+    dir: PathBuf,
+    interval: Duration,
+    last_write: Instant,
+    /// Song revision of the last snapshot (see `Controller::revision`)
+    revision: u64,
+    /// Snapshots written by this run, oldest first
+    written: Vec<PathBuf>,
+    /// Numbers this run's snapshots
+    count: u64,
+}
+
+impl Autosave {
+    pub(crate) fn new(dir: PathBuf, interval: Duration, revision: u64) -> Self {
+        Self { dir, interval, last_write: Instant::now(), revision, written: Vec::new(), count: 0 }
+    }
+
+    /// Whether a snapshot of song `revision` is due.
+    pub(crate) fn due(&self, revision: u64) -> bool {
+        revision != self.revision && self.last_write.elapsed() >= self.interval
+    }
+
+    /// Write `data` (song `revision`) as this run's newest snapshot and
+    /// remove any beyond the latest few. Returns the snapshot's path.
+    ///
+    /// A failed write is tried again after the next interval, not at once.
+    pub(crate) fn write(&mut self, data: &[u8], revision: u64) -> io::Result<PathBuf> {
+        self.last_write = Instant::now();
+        fs::create_dir_all(&self.dir)?;
+        let name = format!("{SNAPSHOT_PREFIX}{}-{:04}.{SNAPSHOT_EXTENSION}", std::process::id(), self.count);
+        let path = self.dir.join(name);
+        fs::write(&path, data)?;
+        (self.count, self.revision) = (self.count + 1, revision);
+        self.written.push(path.clone());
+        if self.written.len() > KEEP_SNAPSHOTS {
+            let _ = fs::remove_file(self.written.remove(0));
+        }
+        Ok(path)
+    }
+
+    /// Treat song `revision` as already snapshotted (e.g. just saved).
+    pub(crate) fn skip(&mut self, revision: u64) {
+        self.revision = revision;
+    }
+
+    /// Remove this run's snapshots.
+    pub(crate) fn discard(&mut self) {
+        for path in self.written.drain(..) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// A recovery snapshot found on disk.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecoverySnapshot {
+    pub path: PathBuf,
+    /// When the snapshot was written
+    pub saved: SystemTime,
+}
+
+impl RecoverySnapshot {
+    /// Delete the snapshot file.
+    pub fn remove(&self) -> io::Result<()> {
+        fs::remove_file(&self.path)
+    }
+}
+
+/// Recovery snapshots in `dir`, newest first. A missing directory has none.
+pub fn recovery_snapshots(dir: &Path) -> io::Result<Vec<RecoverySnapshot>> {
+    let entries = match fs::read_dir(dir) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        result => result?,
+    };
+    let mut snapshots = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let is_snapshot = path.extension().is_some_and(|e| e == SNAPSHOT_EXTENSION)
+            && path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with(SNAPSHOT_PREFIX));
+        if is_snapshot {
+            let saved = fs::metadata(&path)?.modified()?;
+            snapshots.push(RecoverySnapshot { path, saved });
+        }
+    }
+    snapshots.sort_by(|a, b| b.saved.cmp(&a.saved).then_with(|| b.path.cmp(&a.path)));
+    Ok(snapshots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_writes_wait_for_the_next_interval() {
+        let file = std::env::temp_dir().join(format!("mb-autosave-blocker-{}", std::process::id()));
+        fs::write(&file, b"").unwrap();
+        let interval = Duration::from_secs(60);
+        // A directory can't be created inside a file
+        let mut autosave = Autosave::new(file.join("recovery"), interval, 0);
+        autosave.last_write -= interval;
+        assert!(autosave.due(1));
+        assert!(autosave.write(b"song", 1).is_err());
+        assert!(!autosave.due(1));
+        let _ = fs::remove_file(&file);
+    }
+}
@@ -3,22 +3,58 @@
 //! Provides a unified API for loading songs, playback, and rendering
 //! that both the GUI and CLI can share.
 
-use mb_audio::{AudioOutput, CpalOutput};
-use mb_engine::Engine;
-use mb_ir::BLOCK_SIZE;
+use mb_audio::{AudioOutput, CpalInput, CpalOutput, MidiIn, MidiMessage, MidiOut};
+pub use mb_audio::{input_device_names, midi_input_names, midi_output_names, AudioError};
+use mb_engine::{machines, Engine, GraphPatch, MeterBank, MidiOutMessage, SyncMessage};
+pub use mb_engine::{LimitHit, Limits, MeterReading, TempoMap, SCOPE_FRAMES};
+pub use mb_engine::machines::plugin::{load_plugin, load_plugin_dir, PluginError, PluginLoad};
+pub use mb_engine::machines::clap_host::{plugin_descriptors as clap_plugins, ClapDescriptor, ClapError};
+use mb_ir::{Event, EventPayload, EventTarget, MusicalTime, NodeType, Note, BLOCK_SIZE};
 use ringbuf::HeapRb;
-use ringbuf::traits::{Consumer, Producer, Split};
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use ringbuf::traits::{Consumer, Observer, Producer, Split};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, AtomicU8, Ordering};
 use std::sync::Arc;
 use std::thread::JoinHandle;
-
+use std::time::Instant;
+
+mod autosave;
+mod preferences;
+mod recording;
+mod render;
+mod spectrum;
+mod undo;
+
+pub use autosave::{recovery_snapshots, RecoverySnapshot};
+pub use preferences::{PanLayout, Preferences};
+pub use render::{ExportFormat, Renderer};
+pub use spectrum::{SpectrumAnalyzer, SPECTRUM_FLOOR_DB, SPECTRUM_SIZE};
 // Re-export common types so callers don't need mb-ir/mb-engine directly.
-pub use mb_formats::{FormatError, frames_to_wav, load_wav, write_wav};
-pub use mb_ir::{pack_time, unpack_time, Edit, PlaybackPosition, Song, TrackPlaybackPosition, time_to_track_position};
+pub use mb_formats::{BmxExport, FormatError, ModExport, RawFormat, FlacStreamWriter, VorbisSpec, VorbisStreamWriter, MAX_VORBIS_QUALITY, WavDepth, WavSpec, WavStreamWriter, frames_to_flac, frames_to_ogg, frames_to_wav, load_wav, write_wav};
+pub use mb_ir::{pack_time, unpack_time, BarPosition, CellField, CellSelection, CurveKind, Edit, Groove, PlaybackPosition, SampleFormat, SampleOp, SliceMode, Song, Subsong, TempoCurve, TempoEnvelope, TempoPoint, TimeSignature, TrackOffset, TrackPlaybackPosition, time_to_track_position};
 
 /// Ring buffer capacity for edit commands sent to the audio thread.
 const EDIT_RING_CAPACITY: usize = 256;
 
+/// Ring buffer capacity for events injected via `schedule_event_at`.
+const EVENT_RING_CAPACITY: usize = 256;
+
+/// Ring buffer capacity (interleaved samples) for monitored input audio.
+const MONITOR_RING_CAPACITY: usize = BLOCK_SIZE * 16;
+
+/// Graph nodes metered during playback (higher node ids go unmetered).
+const METERED_NODES: usize = 256;
+
+/// MIDI Out messages the audio thread can hold back for latency
+/// compensation; more are dropped.
+const MIDI_PENDING_CAPACITY: usize = 4096;
+
+/// Clock and time code messages the audio thread can hold back for
+/// latency compensation; more are dropped.
+const SYNC_PENDING_CAPACITY: usize = 1024;
+
+/// Length of the fade to silence when playback stops or pauses, in milliseconds.
+const STOP_FADE_MS: u32 = 50;
+
 // ---------------------------------------------------------------------------
 // Allocation guards — no-ops without the `alloc_check` feature.
 // ---------------------------------------------------------------------------
@@ -44,142 +80,588 @@ fn alloc_permit<R>(f: impl FnOnce() -> R) -> R { f() }
 /// Headless tracker controller — owns a song and manages playback.
 pub struct Controller {
     song: Song,
+    /// New-song and export defaults.
+    preferences: Preferences,
     playback: Option<PlaybackHandle>,
+    /// Playback told to stop whose audio thread may still be fading out.
+    stopping: Option<PlaybackHandle>,
+    /// Extra record offset in milliseconds, added to the backend's reported latency.
+    record_offset_ms: i32,
+    /// Loop region applied to song playback (`None` plays straight through).
+    loop_range: Option<(MusicalTime, MusicalTime)>,
+    /// Whether live playback runs through the master bus limiter.
+    master_limiter: bool,
+    /// Whether live playback clicks on every beat.
+    metronome: bool,
+    /// Metronome click gain in percent.
+    metronome_gain: u8,
+    /// Worker threads for rendering independent graph branches (0 = none).
+    render_threads: usize,
+    /// Node levels and scopes published by the audio thread.
+    meters: Arc<MeterBank>,
+    /// FFT state for `master_spectrum`.
+    spectrum: SpectrumAnalyzer,
+    /// Whether `record_note` writes notes into the playing clips.
+    note_recording: bool,
+    /// Input capture in progress (see `record_sample`).
+    recording: Option<recording::Recording>,
+    /// Monitor ring ends, lent to the capture and audio threads while they run.
+    monitor_producer: Option<ringbuf::HeapProd<f32>>,
+    monitor_consumer: Option<ringbuf::HeapCons<f32>>,
+    /// Bounds on how much playback and renders may schedule.
+    limits: Limits,
+    /// Output rate of the latest playback, which the capture thread
+    /// resamples the monitor copy to (0 = none yet).
+    monitor_rate: Arc<AtomicU32>,
+    /// Whether playback sends MIDI clock / MIDI time code on `sync_output`.
+    midi_clock_out: bool,
+    mtc_out: bool,
+    /// Port clock and time code go out on, lent to the audio thread.
+    sync_output: Option<MidiOut>,
+    /// Port whose clock and time code playback follows.
+    sync_input: Option<MidiIn>,
+    /// Messages received on `sync_input`, lent to the audio thread.
+    sync_messages: Option<ringbuf::HeapCons<MidiMessage>>,
+    /// Whether the audio thread holds the current `sync_output` / `sync_messages`.
+    sync_output_lent: bool,
+    sync_input_lent: bool,
+    /// Time base for received MIDI timestamps.
+    sync_epoch: Instant,
+    /// Ports MIDI Out tracks play on, by name, lent to the audio thread.
+    midi_ports: Vec<(String, MidiOut)>,
+    /// Extra MIDI Out offset in milliseconds (positive = earlier).
+    midi_out_offset_ms: i32,
+    /// Clips launched per track during this playback.
+    launches: Vec<TrackLaunch>,
+    /// Note `preview_note` is holding.
+    previewing: Option<u8>,
+    /// Sample audio sent to `preview_sample`, held until the audio thread
+    /// lets go of it so it is never freed there
+    previewed_samples: Vec<Arc<mb_ir::SampleData>>,
+    /// Song edits that can be undone and redone.
+    undo: undo::UndoStack,
+    /// Counts changes to the song; compared against `saved_revision` for
+    /// unsaved changes and by autosave.
+    revision: u64,
+    saved_revision: u64,
+    /// Recovery snapshots, when turned on.
+    autosave: Option<autosave::Autosave>,
+}
+
+/// A track's launched clip and the launch or stop waiting for its time.
+#[derive(Clone, Copy, Debug, Default)]
+struct TrackLaunch {
+    /// Clip looping since the given time
+    playing: Option<(u16, MusicalTime)>,
+    queued: Option<(MusicalTime, Option<u16>)>,
+}
+
+impl TrackLaunch {
+    /// The launch state once playback has reached `now`.
+    fn at(mut self, now: MusicalTime) -> Self {
+        if let Some((at, clip)) = self.queued.filter(|&(at, _)| at <= now) {
+            self.playing = clip.map(|c| (c, at));
+            self.queued = None;
+        }
+        self
+    }
 }
 
 struct PlaybackHandle {
     stop_signal: Arc<AtomicBool>,
+    /// Set while playback is paused
+    pause_signal: Arc<AtomicBool>,
     /// Packed MusicalTime: (beat as u32) << 32 | sub_beat
     current_time: Arc<AtomicU64>,
+    /// Packed latency-compensated position for recording input.
+    record_time: Arc<AtomicU64>,
+    /// Shared copy of `Controller::record_offset_ms` read by the audio thread.
+    record_offset_ms: Arc<AtomicI32>,
     finished: Arc<AtomicBool>,
-    thread: Option<JoinHandle<()>>,
-    edit_producer: ringbuf::HeapProd<Edit>,
+    /// `LimitHit::code` of the safety limit that cut playback short (0 = none).
+    limit_hit: Arc<AtomicU8>,
+    /// Returns the inbox, with what it was lent.
+    thread: Option<JoinHandle<Inbox>>,
+    edit_producer: ringbuf::HeapProd<Command>,
+    /// Graph patches the audio thread swapped in, holding what they
+    /// replaced, to be dropped here
+    retired: ringbuf::HeapCons<Box<GraphPatch>>,
+    event_producer: ringbuf::HeapProd<Event>,
+    /// Output rate of the played engine
+    sample_rate: u32,
+    /// Started only to play previews (see `PlayMode::Preview`)
+    preview: bool,
+}
+
+/// What a playback plays.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PlayMode {
+    /// The song's sequences
+    Song,
+    /// Launched clips only (see `play_session`)
+    Session,
+    /// Nothing but previews, started by `preview_note` or `preview_sample`
+    /// when stopped; it doesn't count as playing
+    Preview,
 }
 
 impl Controller {
     pub fn new() -> Self {
+        Self::with_preferences(Preferences::default())
+    }
+
+    /// Create a controller whose new songs and exports follow `preferences`.
+    pub fn with_preferences(preferences: Preferences) -> Self {
+        let (monitor_producer, monitor_consumer) = HeapRb::<f32>::new(MONITOR_RING_CAPACITY).split();
         Self {
             song: Song::with_channels("Untitled", 4),
+            preferences,
             playback: None,
+            stopping: None,
+            record_offset_ms: 0,
+            loop_range: None,
+            master_limiter: false,
+            metronome: false,
+            metronome_gain: 100,
+            render_threads: 0,
+            meters: Arc::new(MeterBank::new(METERED_NODES)),
+            spectrum: SpectrumAnalyzer::new(),
+            note_recording: false,
+            recording: None,
+            monitor_producer: Some(monitor_producer),
+            monitor_consumer: Some(monitor_consumer),
+            limits: Limits::default(),
+            monitor_rate: Arc::new(AtomicU32::new(0)),
+            midi_clock_out: false,
+            mtc_out: false,
+            sync_output: None,
+            sync_input: None,
+            sync_messages: None,
+            sync_output_lent: false,
+            sync_input_lent: false,
+            sync_epoch: Instant::now(),
+            midi_ports: Vec::new(),
+            midi_out_offset_ms: 0,
+            launches: Vec::new(),
+            previewing: None,
+            previewed_samples: Vec::new(),
+            undo: undo::UndoStack::default(),
+            revision: 0,
+            saved_revision: 0,
+            autosave: None,
         }
     }
 
+    pub fn preferences(&self) -> &Preferences {
+        &self.preferences
+    }
+
+    /// Replace the preferences; applies to the next `new_song`.
+    pub fn set_preferences(&mut self, preferences: Preferences) {
+        self.preferences = preferences;
+    }
+
     // --- Song management ---
 
     pub fn song(&self) -> &Song {
         &self.song
     }
 
+    /// Replace the song, stopping playback and clearing the undo history.
+    /// The new song counts as saved.
     pub fn set_song(&mut self, song: Song) {
         self.stop();
         self.song = song;
+        self.undo.clear();
+        self.revision += 1;
+        self.saved_revision = self.revision;
     }
 
     pub fn load_mod(&mut self, data: &[u8]) -> Result<(), FormatError> {
         self.stop();
-        self.song = mb_formats::load_mod(data)?;
+        self.set_song(mb_formats::load_mod(data)?);
         Ok(())
     }
 
     pub fn load_bmx(&mut self, data: &[u8]) -> Result<(), FormatError> {
         self.stop();
-        self.song = mb_formats::load_bmx(data)?;
+        self.set_song(mb_formats::load_bmx(data)?);
         Ok(())
     }
 
-    /// Create a new empty song with default settings.
-    pub fn new_song(&mut self, channels: u8) {
+    pub fn load_it(&mut self, data: &[u8]) -> Result<(), FormatError> {
         self.stop();
-        let mut song = Song::with_channels("Untitled", channels);
-        let patterns = vec![mb_ir::Pattern::new(64, channels)];
-        let order = vec![mb_ir::OrderEntry::Pattern(0)];
-        mb_ir::build_tracks(&mut song, &patterns, &order);
-        self.song = song;
+        self.set_song(mb_formats::load_it(data)?);
+        Ok(())
+    }
+
+    pub fn load_s3m(&mut self, data: &[u8]) -> Result<(), FormatError> {
+        self.stop();
+        self.set_song(mb_formats::load_s3m(data)?);
+        Ok(())
+    }
+
+    /// Import a Standard MIDI File, quantized to the current song's rows per beat.
+    pub fn load_midi(&mut self, data: &[u8]) -> Result<(), FormatError> {
+        self.stop();
+        self.set_song(mb_formats::load_midi(data, self.song.rows_per_beat)?);
+        Ok(())
+    }
+
+    /// Serialize the current song in the native project format.
+    pub fn save_project(&self) -> Vec<u8> {
+        mb_formats::save_song(&self.song)
+    }
+
+    /// Convert the current song to a ProTracker MOD, with a report of lossy conversions.
+    pub fn export_mod(&self) -> Result<ModExport, FormatError> {
+        mb_formats::write_mod(&self.song)
+    }
+
+    /// Convert the current song to a Buzz BMX, with a report of lossy conversions.
+    pub fn export_bmx(&self) -> Result<BmxExport, FormatError> {
+        mb_formats::write_bmx(&self.song)
+    }
+
+    /// Load a song file in `format`.
+    pub fn load_song(&mut self, data: &[u8], format: SongFileFormat) -> Result<(), FormatError> {
+        match format {
+            SongFileFormat::Project => self.load_project(data),
+            SongFileFormat::Mod => self.load_mod(data),
+            SongFileFormat::Bmx => self.load_bmx(data),
+            SongFileFormat::It => self.load_it(data),
+            SongFileFormat::S3m => self.load_s3m(data),
+            SongFileFormat::Midi => self.load_midi(data),
+        }
+    }
+
+    /// Serialize the current song in `format`, with a report of lossy
+    /// conversions. Only the project, MOD and BMX formats can be written.
+    pub fn export_song(&self, format: SongFileFormat) -> Result<SongExport, FormatError> {
+        match format {
+            SongFileFormat::Project => Ok(SongExport { data: self.save_project(), warnings: Vec::new() }),
+            SongFileFormat::Mod => self.export_mod().map(|e| SongExport { data: e.data, warnings: e.warnings }),
+            SongFileFormat::Bmx => self.export_bmx().map(|e| SongExport { data: e.data, warnings: e.warnings }),
+            _ => Err(FormatError::Unsupported(format!("can't write {} files", format.extension()))),
+        }
+    }
+
+    /// Load a song saved by `save_project`.
+    pub fn load_project(&mut self, data: &[u8]) -> Result<(), FormatError> {
+        self.stop();
+        self.set_song(mb_formats::load_song(data)?);
+        Ok(())
+    }
+
+    /// Subsongs detected in the loaded song (index 0 = main arrangement).
+    pub fn subsongs(&self) -> &[Subsong] {
+        &self.song.subsongs
+    }
+
+    /// Select which subsong `play()` and the render methods use.
+    /// Stops playback and, since it rebuilds the sequences, clears the
+    /// undo history. Returns false if `idx` is out of range.
+    pub fn select_subsong(&mut self, idx: usize) -> bool {
+        self.stop();
+        let selected = self.song.select_subsong(idx);
+        if selected {
+            self.undo.clear();
+            self.mark_modified();
+        }
+        selected
+    }
+
+    /// Create a new empty song from the current preferences.
+    pub fn new_song(&mut self) {
+        self.set_song(self.preferences.new_song());
     }
 
     /// Load a WAV file as a sample and add it to the song.
     /// Returns the 1-based instrument number on success.
     pub fn load_wav_sample(&mut self, data: &[u8], name: &str) -> Result<u8, FormatError> {
-        let sample = mb_formats::load_wav(data, name)?;
-        let sample_idx = self.song.samples.len() as u8;
-        self.song.samples.push(sample);
+        self.load_sample(data, name, SampleFileFormat::Wav)
+    }
+
+    /// Load a sample file in `format` and add it to the song.
+    /// Returns the 1-based instrument number on success.
+    pub fn load_sample(&mut self, data: &[u8], name: &str, format: SampleFileFormat) -> Result<u8, FormatError> {
+        let sample = match format {
+            SampleFileFormat::Wav => mb_formats::load_wav(data, name)?,
+            SampleFileFormat::Aiff => mb_formats::load_aiff(data, name)?,
+            SampleFileFormat::Raw(raw) => mb_formats::load_raw(data, name, &raw)?,
+        };
+        self.add_sample_instrument(sample)
+            .ok_or_else(|| FormatError::Unsupported("no room for another instrument".into()))
+    }
+
+    /// Apply a destructive edit to sample `idx` (0-based), live if playing;
+    /// returns false if there is no such sample or the edit changed nothing.
+    pub fn edit_sample(&mut self, idx: u8, op: SampleOp) -> bool {
+        let Some(mut sample) = self.song.samples.get(idx as usize).cloned() else { return false };
+        sample.apply_op(op) && self.commit_edit(Edit::SetSample { sample: idx, data: Box::new(sample) })
+    }
+
+    /// Give sample `idx` (0-based) a forward loop at least `min_len` frames
+    /// long at the best-matching zero crossings, blending the last
+    /// `crossfade` frames of the loop into its start (0 = no crossfade).
+    ///
+    /// Returns the loop points, or `None` if no loop was found.
+    pub fn auto_loop(&mut self, idx: u8, min_len: u32, crossfade: u32) -> Option<(u32, u32)> {
+        let mut sample = self.song.samples.get(idx as usize)?.clone();
+        let (start, end) = mb_ir::find_loop_points(&sample, min_len)?;
+        (sample.loop_start, sample.loop_end, sample.loop_type) = (start, end, mb_ir::LoopType::Forward);
+        sample.apply_op(SampleOp::CrossfadeLoop { frames: crossfade });
+        self.commit_edit(Edit::SetSample { sample: idx, data: Box::new(sample) });
+        Some((start, end))
+    }
 
-        let mut inst = mb_ir::Instrument::new(name);
-        inst.set_single_sample(sample_idx);
-        self.song.instruments.push(inst);
+    /// Cut sample `idx` (0-based) into slices, each added as a new sample
+    /// and instrument; returns their instrument numbers in order.
+    ///
+    /// With `clip = Some((track, rows))`, also adds a clip of `rows` rows to
+    /// that track triggering the slices where they fall in the original.
+    /// Returns `None` if there is no such sample or the song has no room
+    /// for the instruments. Live if playing, and undone in one step.
+    pub fn slice_sample(&mut self, idx: u8, mode: SliceMode, clip: Option<(usize, u16)>) -> Option<Slices> {
+        let sample = self.song.samples.get(idx as usize)?;
+        let points = mb_ir::slice_points(sample, mode);
+        let len = sample.len() as u32;
+        let slices = mb_ir::slice_sample(sample, &points);
+        let room = u8::MAX as usize - self.song.instruments.len().max(self.song.samples.len());
+        if slices.is_empty() || slices.len() > room {
+            return None;
+        }
+        self.undo.begin_group();
+        let instruments: Vec<u8> = slices.into_iter().filter_map(|s| self.add_sample_instrument(s)).collect();
+        let clip = clip.and_then(|(track_idx, rows)| {
+            let track = self.song.tracks.get(track_idx)?;
+            let pattern = mb_ir::slice_pattern(&points, len, instruments[0], rows, track.num_channels);
+            let (track, clip) = (track_idx as u16, track.clips.len() as u16);
+            let added = self.commit_edit(Edit::AddClip { track, rows })
+                && self.commit_edit(Edit::SetClip { track, clip, pattern: Box::new(pattern) });
+            added.then_some(clip)
+        });
+        self.undo.end_group();
+        Some(Slices { instruments, clip })
+    }
 
-        Ok(self.song.instruments.len() as u8) // 1-based
+    /// Add a sample and an instrument playing it, live if playing; returns
+    /// the 1-based instrument number, or `None` if the song has no room.
+    fn add_sample_instrument(&mut self, sample: mb_ir::Sample) -> Option<u8> {
+        if !self.commit_edit(Edit::AddSample { sample: Box::new(sample) }) {
+            return None;
+        }
+        Some(self.song.instruments.len() as u8) // 1-based
     }
 
-    /// Add a new empty clip to the given track.
+    /// Add a new empty clip to the given track, live if playing.
     /// Returns the clip index.
     pub fn add_clip(&mut self, track_idx: usize, rows: u16) -> u16 {
-        let Some(track) = self.song.tracks.get_mut(track_idx) else { return 0 };
+        let Some(track) = self.song.tracks.get(track_idx) else { return 0 };
         let clip_idx = track.clips.len() as u16;
-        let channels = track.num_channels;
-        track.clips.push(mb_ir::Clip::Pattern(mb_ir::Pattern::new(rows, channels)));
+        self.commit_edit(Edit::AddClip { track: track_idx as u16, rows });
         clip_idx
     }
 
-    /// Add a sequence entry to the given track.
+    /// Add a sequence entry to the end of the given track, live if playing.
     pub fn add_seq_entry(&mut self, track_idx: usize, clip_idx: u16) {
         let start = track_end_time(&self.song, track_idx);
-        let length = self.song.tracks.get(track_idx)
-            .and_then(|t| t.get_pattern_at(clip_idx as usize))
-            .map_or(0, |p| p.rows);
-        let entry = mb_ir::SeqEntry { start, clip_idx, length, termination: mb_ir::SeqTermination::Natural };
-        if let Some(track) = self.song.tracks.get_mut(track_idx) {
-            track.sequence.push(entry);
-        }
+        let Some(track) = self.song.tracks.get(track_idx) else { return };
+        let length = track.get_pattern_at(clip_idx as usize).map_or(0, |p| p.rows);
+        let entry = mb_ir::SeqEntry { start, clip_idx, length, termination: mb_ir::SeqTermination::Natural, boundary: mb_ir::ClipBoundary::Ring };
+        let mut sequence = track.sequence.clone();
+        sequence.push(entry);
+        self.commit_edit(Edit::SetSequence { track: track_idx as u16, sequence });
     }
 
-    /// Remove the last sequence entry from the given track.
+    /// Remove the last sequence entry from the given track, live if playing.
     pub fn remove_last_seq_entry(&mut self, track_idx: usize) {
-        if let Some(track) = self.song.tracks.get_mut(track_idx) {
-            track.sequence.pop();
-        }
+        let Some(track) = self.song.tracks.get(track_idx) else { return };
+        let Some((_, rest)) = track.sequence.split_last() else { return };
+        self.commit_edit(Edit::SetSequence { track: track_idx as u16, sequence: rest.to_vec() });
     }
 
-    /// Place a clip at a specific beat in a track's sequence.
-    /// Returns the forward and reverse edits, or None if overlap detected.
-    pub fn set_seq_entry(&mut self, track_idx: usize, beat: u32, clip_idx: u16) -> Option<(Edit, Edit)> {
-        let track = self.song.tracks.get(track_idx)?;
+    /// Place a clip at a specific beat in a track's sequence, live if
+    /// playing. Returns false if it would overlap another entry.
+    pub fn set_seq_entry(&mut self, track_idx: usize, beat: u32, clip_idx: u16) -> bool {
+        let Some(track) = self.song.tracks.get(track_idx) else { return false };
         let length = track.get_pattern_at(clip_idx as usize).map_or(16, |p| p.rows);
         let rpb = self.song.rows_per_beat;
         if would_overlap(track, beat, length, rpb) {
-            return None;
+            return false;
         }
         let data = mb_ir::SeqEntryData {
             clip_idx,
             length,
             termination: mb_ir::SeqTermination::Natural,
+            boundary: mb_ir::ClipBoundary::Ring,
         };
-        // Build reverse: restore whatever was at this beat before
-        let old_entry = track.seq_entry_at_beat(beat).map(|e| mb_ir::SeqEntryData {
-            clip_idx: e.clip_idx,
-            length: e.length,
-            termination: e.termination,
-        });
-        let forward = Edit::SetSeqEntry { track: track_idx as u16, beat, entry: Some(data) };
-        let reverse = Edit::SetSeqEntry { track: track_idx as u16, beat, entry: old_entry };
-        self.apply_edit(forward.clone());
-        Some((forward, reverse))
+        self.commit_edit(Edit::SetSeqEntry { track: track_idx as u16, beat, entry: Some(data) })
     }
 
-    /// Remove a sequence entry at a specific beat.
-    /// Returns the forward and reverse edits, or None if nothing there.
-    pub fn remove_seq_entry(&mut self, track_idx: usize, beat: u32) -> Option<(Edit, Edit)> {
-        let track = self.song.tracks.get(track_idx)?;
-        let old = track.seq_entry_at_beat(beat)?;
-        let old_data = mb_ir::SeqEntryData {
+    /// Remove the sequence entry at a specific beat, live if playing.
+    /// Returns false if nothing is there.
+    pub fn remove_seq_entry(&mut self, track_idx: usize, beat: u32) -> bool {
+        if self.seq_entry_at(track_idx, beat).is_none() {
+            return false;
+        }
+        self.commit_edit(Edit::SetSeqEntry { track: track_idx as u16, beat, entry: None })
+    }
+
+    /// Set what happens to sounding notes when the entry at `beat` ends.
+    /// Returns false if nothing is there.
+    pub fn set_clip_boundary(&mut self, track_idx: usize, beat: u32, boundary: mb_ir::ClipBoundary) -> bool {
+        let Some(old) = self.seq_entry_at(track_idx, beat) else { return false };
+        let data = mb_ir::SeqEntryData {
             clip_idx: old.clip_idx,
             length: old.length,
             termination: old.termination,
+            boundary,
         };
-        let forward = Edit::SetSeqEntry { track: track_idx as u16, beat, entry: None };
-        let reverse = Edit::SetSeqEntry { track: track_idx as u16, beat, entry: Some(old_data) };
-        self.apply_edit(forward.clone());
-        Some((forward, reverse))
+        self.commit_edit(Edit::SetSeqEntry { track: track_idx as u16, beat, entry: Some(data) })
+    }
+
+    /// Insert `clip_idx` at position `index` of a track's sequence (clamped
+    /// to the end), live if playing. Later entries move back to make room.
+    pub fn insert_seq_entry(&mut self, track_idx: usize, index: usize, clip_idx: u16) -> bool {
+        self.commit_edit(Edit::InsertSeqEntry { track: track_idx as u16, index: index as u16, clip: clip_idx })
+    }
+
+    /// Delete the entry at position `index` of a track's sequence, live if
+    /// playing. Later entries move in to close the gap.
+    pub fn delete_seq_entry(&mut self, track_idx: usize, index: usize) -> bool {
+        self.commit_edit(Edit::RemoveSeqEntry { track: track_idx as u16, index: index as u16 })
+    }
+
+    /// Move a track's sequence entry from position `from` to `to`, live if playing.
+    pub fn move_seq_entry(&mut self, track_idx: usize, from: usize, to: usize) -> bool {
+        self.commit_edit(Edit::MoveSeqEntry { track: track_idx as u16, from: from as u16, to: to as u16 })
+    }
+
+    /// Repeat the entry at position `index` of a track's sequence right
+    /// after itself, live if playing.
+    pub fn duplicate_seq_entry(&mut self, track_idx: usize, index: usize) -> bool {
+        self.commit_edit(Edit::DuplicateSeqEntry { track: track_idx as u16, index: index as u16 })
+    }
+
+    /// Play `clip_idx` at position `index` of a track's sequence instead of
+    /// its current clip, live if playing.
+    pub fn set_seq_clip(&mut self, track_idx: usize, index: usize, clip_idx: u16) -> bool {
+        self.commit_edit(Edit::SetSeqClip { track: track_idx as u16, index: index as u16, clip: clip_idx })
+    }
+
+    /// Change a clip's row count (at least 1), live if playing. Sequence
+    /// entries playing the whole clip follow its new length.
+    pub fn resize_clip(&mut self, track_idx: usize, clip_idx: u16, rows: u16) -> bool {
+        self.commit_edit(Edit::ResizeClip { track: track_idx as u16, clip: clip_idx, rows })
+    }
+
+    /// Insert `count` empty rows at `row` of a clip, live if playing. Rows
+    /// pushed past the end of the clip are lost.
+    pub fn insert_rows(&mut self, track_idx: usize, clip_idx: u16, row: u16, count: u16) -> bool {
+        self.commit_edit(Edit::InsertRows { track: track_idx as u16, clip: clip_idx, row, count })
+    }
+
+    /// Delete `count` rows at `row` of a clip, live if playing. The clip
+    /// keeps its length, gaining empty rows at the end.
+    pub fn delete_rows(&mut self, track_idx: usize, clip_idx: u16, row: u16, count: u16) -> bool {
+        self.commit_edit(Edit::DeleteRows { track: track_idx as u16, clip: clip_idx, row, count })
+    }
+
+    /// Copy a clip into a new one on the same track; returns the copy's index.
+    pub fn clone_clip(&mut self, track_idx: usize, clip_idx: u16) -> Option<u16> {
+        let applied = self.commit_edit(Edit::CloneClip { track: track_idx as u16, clip: clip_idx });
+        applied.then(|| self.song.tracks[track_idx].clips.len() as u16 - 1)
+    }
+
+    /// Replace the song's tempo envelope, live if playing. From its first
+    /// point on it overrides the patterns' tempo commands.
+    pub fn set_tempo_envelope(&mut self, envelope: TempoEnvelope) {
+        self.apply_edit(Edit::SetTempoEnvelope { envelope });
+    }
+
+    /// Change the time signature from `beat` on, or drop the change there
+    /// with `None`; at beat 0 this sets the song's opening signature.
+    pub fn set_time_signature(&mut self, beat: u64, signature: Option<TimeSignature>) {
+        self.apply_edit(Edit::SetTimeSignature { beat, signature });
+    }
+
+    /// Set the groove of every track without its own, live if playing.
+    pub fn set_groove(&mut self, groove: Groove) {
+        self.commit_edit(Edit::SetSongGroove { groove });
+    }
+
+    /// Give a track its own groove, or `None` to follow the song's, live if
+    /// playing.
+    pub fn set_track_groove(&mut self, track_idx: usize, groove: Option<Groove>) -> bool {
+        self.commit_edit(Edit::SetTrackGroove { track: track_idx as u16, groove })
+    }
+
+    /// Move all of a track's rows earlier or later, live if playing.
+    pub fn set_track_offset(&mut self, track_idx: usize, offset: TrackOffset) -> bool {
+        self.commit_edit(Edit::SetTrackOffset { track: track_idx as u16, offset })
+    }
+
+    /// Add a track playing `channels` new channels on a new Tracker node
+    /// wired to Master, live if playing; returns the track's index.
+    pub fn add_track(&mut self, channels: u8, name: &str) -> Option<usize> {
+        let edit = Edit::AddTrack { channels, name: mb_ir::track_name(name) };
+        let applied = self.commit_edit(edit);
+        applied.then(|| self.song.tracks.len() - 1)
+    }
+
+    /// Add a track playing `channels` new channels on external gear: its
+    /// notes go to the MIDI output port `port` (see `midi_output_names`) on
+    /// MIDI channel `channel` (0-15), live if playing; returns the track's
+    /// index. A port first used while playing is connected when playback
+    /// next starts.
+    pub fn add_midi_track(&mut self, channels: u8, name: &str, port: &str, channel: u8) -> Option<usize> {
+        let edit = Edit::AddMidiTrack { channels, name: mb_ir::track_name(name), port: port.into(), channel };
+        let applied = self.commit_edit(edit);
+        applied.then(|| self.song.tracks.len() - 1)
+    }
+
+    /// Delete a track, live if playing. Its machine node and channels go
+    /// with it unless another track still plays them.
+    pub fn delete_track(&mut self, track_idx: usize) -> bool {
+        self.commit_edit(Edit::RemoveTrack { track: track_idx as u16 })
+    }
+
+    /// Move a track, with its sequence, to position `to`.
+    pub fn move_track(&mut self, from: usize, to: usize) -> bool {
+        self.commit_edit(Edit::MoveTrack { from: from as u16, to: to as u16 })
+    }
+
+    /// Rename a track (names are cut short at 32 bytes).
+    pub fn rename_track(&mut self, track_idx: usize, name: &str) -> bool {
+        self.commit_edit(Edit::RenameTrack { track: track_idx as u16, name: mb_ir::track_name(name) })
+    }
+
+    /// Ramp the volume column or effect parameter of each selected column
+    /// between its first and last row (see `mb_ir::interpolate_cells`);
+    /// returns the edits applied.
+    pub fn interpolate(&mut self, selection: &CellSelection, field: CellField, curve: CurveKind) -> Vec<Edit> {
+        self.apply_cell_edits(mb_ir::interpolate_cells(&self.song, selection, field, curve))
+    }
+
+    /// Shift the selected notes by `semitones`, clamped to the note range;
+    /// returns the edits applied, which `undo` takes back as one step.
+    pub fn transpose(&mut self, selection: &CellSelection, semitones: i16) -> Vec<Edit> {
+        self.apply_cell_edits(mb_ir::transpose_cells(&self.song, selection, semitones))
+    }
+
+    /// Shift every note of a clip by `semitones`; returns the edits
+    /// applied, which `undo` takes back as one step.
+    pub fn transpose_clip(&mut self, track_idx: usize, clip_idx: u16, semitones: i16) -> Vec<Edit> {
+        let Some(selection) = CellSelection::whole_clip(&self.song, track_idx as u16, clip_idx) else { return Vec::new() };
+        self.transpose(&selection, semitones)
+    }
+
+    /// Apply a batch of cell edits as one undo step and hand them back as
+    /// applied (not their inverses).
+    fn apply_cell_edits(&mut self, edits: Vec<Edit>) -> Vec<Edit> {
+        self.apply_edits(edits.iter().cloned());
+        edits
     }
 
     /// Read helper: get the sequence entry at a specific beat.
@@ -193,38 +675,555 @@ impl Controller {
         track.muted = !track.muted;
         let muted = track.muted;
         let node_id = track.machine_node;
+        self.mark_modified();
         if let Some(node_id) = node_id {
             self.push_edit(Edit::SetNodeBypass { node: node_id, bypassed: muted });
         }
     }
 
+    /// Mute or unmute a song channel, live if playing.
+    pub fn set_channel_muted(&mut self, channel: u8, muted: bool) {
+        self.apply_edit(Edit::SetChannelMute { channel, muted });
+    }
+
+    /// Solo or unsolo a song channel, live if playing.
+    ///
+    /// While any channel is soloed, only soloed channels are heard.
+    pub fn set_channel_solo(&mut self, channel: u8, solo: bool) {
+        self.apply_edit(Edit::SetChannelSolo { channel, solo });
+    }
+
+    // --- Audio graph ---
+
+    /// Add a graph node, live if playing, and return its ID.
+    ///
+    /// Native machines start with their default parameters. The node is
+    /// silent until wired in with `connect`.
+    pub fn add_node(&mut self, node_type: NodeType) -> u16 {
+        let id = self.song.graph.nodes.len() as u16;
+        self.commit_edit(Edit::AddNode { node_type });
+        id
+    }
+
+    /// Remove a graph node and its wires, live if playing.
+    ///
+    /// The nodes after it are renumbered down by one. Returns false for
+    /// the Master node or an unknown ID.
+    pub fn remove_node(&mut self, node: u16) -> bool {
+        self.commit_edit(Edit::RemoveNode { node })
+    }
+
+    /// Wire `from` into `to`, live if playing.
+    ///
+    /// Returns false for unknown nodes, existing wires, and wires that
+    /// would close a loop outside a feedback send/return pair.
+    pub fn connect(&mut self, from: u16, to: u16, gain: i16) -> bool {
+        self.commit_edit(Edit::Connect { from, to, gain })
+    }
+
+    /// Remove the wire from `from` into `to`, live if playing.
+    pub fn disconnect(&mut self, from: u16, to: u16) -> bool {
+        self.commit_edit(Edit::Disconnect { from, to })
+    }
+
+    /// Set the gain of the wire from `from` into `to`, live if playing.
+    pub fn set_connection_gain(&mut self, from: u16, to: u16, gain: i16) -> bool {
+        self.commit_edit(Edit::SetConnectionGain { from, to, gain })
+    }
+
+    /// Set the pan of the wire from `from` into `to` (-64 to +64), live if playing.
+    pub fn set_connection_pan(&mut self, from: u16, to: u16, pan: i8) -> bool {
+        self.commit_edit(Edit::SetConnectionPan { from, to, pan })
+    }
+
     // --- Edit dispatch ---
 
-    /// Apply an edit to the local song and push it to the audio thread if playing.
+    /// Apply an edit to the local song and push it to the audio thread if
+    /// playing. Song edits can be undone (see `undo`).
     pub fn apply_edit(&mut self, edit: Edit) {
-        apply_edit_to_song(&mut self.song, &edit);
-        self.push_edit(edit);
+        self.commit_edit(edit);
+    }
+
+    /// Apply several edits as a single undo step.
+    pub fn apply_edits(&mut self, edits: impl IntoIterator<Item = Edit>) {
+        self.undo.begin_group();
+        for edit in edits {
+            self.commit_edit(edit);
+        }
+        self.undo.end_group();
+    }
+
+    /// Apply an edit locally and, if it took, record how to undo it and
+    /// push it to the engine. Returns whether it took.
+    fn commit_edit(&mut self, edit: Edit) -> bool {
+        let reverse = undo::reverse(&self.song, &edit);
+        let patch = self.graph_patch(&edit);
+        let applied = apply_edit_to_song(&mut self.song, &edit);
+        if applied {
+            if let Some(reverse) = reverse {
+                self.undo.record(edit.clone(), reverse);
+                self.mark_modified();
+            }
+            self.push_patched_edit(edit, patch);
+        }
+        applied
+    }
+
+    /// Apply an edit locally and to the engine without recording it.
+    fn replay_edit(&mut self, edit: Edit) {
+        let patch = self.graph_patch(&edit);
+        if apply_edit_to_song(&mut self.song, &edit) {
+            self.mark_modified();
+            self.push_patched_edit(edit, patch);
+        }
+    }
+
+    /// Build the playing engine's side of a graph edit here, so the audio
+    /// thread only swaps it in. Call before applying `edit` to the song.
+    fn graph_patch(&mut self, edit: &Edit) -> Option<Box<GraphPatch>> {
+        let pb = self.playback.as_mut().filter(|_| edit.restructures_graph())?;
+        // Drop the patches the audio thread is done with
+        while pb.retired.try_pop().is_some() {}
+        GraphPatch::new(&self.song, edit, pb.sample_rate)
+    }
+
+    /// Push an edit to the audio thread (if playing), as its graph patch
+    /// if it has one.
+    fn push_patched_edit(&mut self, edit: Edit, patch: Option<Box<GraphPatch>>) {
+        match (patch, &mut self.playback) {
+            (Some(patch), Some(pb)) => {
+                let _ = pb.edit_producer.try_push(Command::Graph(patch));
+            }
+            _ => self.push_edit(edit),
+        }
+    }
+
+    // --- Undo ---
+
+    /// Take back the last undo step, live if playing. Undoing a track or
+    /// node removal puts the whole song back, which stops playback.
+    /// Returns false if there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let Some(reverse) = self.undo.undo() else { return false };
+        match reverse {
+            undo::Reverse::Edits(edits) => edits.into_iter().for_each(|edit| self.replay_edit(edit)),
+            undo::Reverse::Song(song) => {
+                self.stop();
+                self.song = *song;
+                self.mark_modified();
+            }
+        }
+        true
+    }
+
+    /// Make the last undone step again, live if playing. Returns false if
+    /// there is nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let Some(edits) = self.undo.redo() else { return false };
+        edits.into_iter().for_each(|edit| self.replay_edit(edit));
+        true
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.undo.can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.undo.can_redo()
+    }
+
+    // --- Unsaved changes and autosave ---
+
+    /// Whether the song changed since it was loaded, created or last
+    /// marked saved.
+    pub fn is_modified(&self) -> bool {
+        self.revision != self.saved_revision
+    }
+
+    /// Note that the song was just saved (e.g. `save_project` written to
+    /// disk). This run's recovery snapshots are no longer needed and go.
+    pub fn mark_saved(&mut self) {
+        self.saved_revision = self.revision;
+        if let Some(autosave) = &mut self.autosave {
+            autosave.skip(self.revision);
+            autosave.discard();
+        }
+    }
+
+    fn mark_modified(&mut self) {
+        self.revision += 1;
+    }
+
+    /// Write recovery snapshots of unsaved changes to `dir` (see
+    /// `Preferences::recovery_dir`) at most once per `interval`, or stop
+    /// with `None`, removing this run's snapshots. Snapshots are written
+    /// by `autosave`.
+    pub fn set_autosave(&mut self, dir: Option<std::path::PathBuf>, interval: std::time::Duration) {
+        self.discard_autosaves();
+        self.autosave = dir.map(|dir| autosave::Autosave::new(dir, interval, self.saved_revision));
+    }
+
+    /// Write a recovery snapshot if autosave is on, the song has unsaved
+    /// changes since the last snapshot and the interval has passed. Call
+    /// regularly, e.g. once per frame. Returns the snapshot's path.
+    pub fn autosave(&mut self) -> std::io::Result<Option<std::path::PathBuf>> {
+        let revision = self.revision;
+        match &mut self.autosave {
+            Some(autosave) if self.saved_revision != revision && autosave.due(revision) => {
+                autosave.write(&mb_formats::save_song(&self.song), revision).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Remove this run's recovery snapshots, e.g. on a clean exit.
+    pub fn discard_autosaves(&mut self) {
+        if let Some(autosave) = &mut self.autosave {
+            autosave.discard();
+        }
+    }
+
+    /// Load a recovery snapshot (see `recovery_snapshots`). The recovered
+    /// song counts as unsaved.
+    pub fn restore_snapshot(&mut self, snapshot: &RecoverySnapshot) -> Result<(), FormatError> {
+        let data = std::fs::read(&snapshot.path).map_err(|e| FormatError::Io(e.to_string()))?;
+        self.load_project(&data)?;
+        self.mark_modified();
+        Ok(())
     }
 
     /// Push an edit to the audio thread (if playing).
     fn push_edit(&mut self, edit: Edit) {
         if let Some(pb) = &mut self.playback {
-            let _ = pb.edit_producer.try_push(edit);
+            let _ = pb.edit_producer.try_push(Command::Edit(edit));
         }
     }
 
+    /// Inject an event into the playing engine, dispatched when playback
+    /// reaches `time` (immediately if `time` has already passed).
+    ///
+    /// Returns false if nothing is playing or the event queue is full.
+    pub fn schedule_event_at(&mut self, time: MusicalTime, target: EventTarget, payload: EventPayload) -> bool {
+        let Some(pb) = &mut self.playback else { return false };
+        pb.event_producer.try_push(Event::new(time, target, payload)).is_ok()
+    }
+
+    /// The first point on a 1/`subdivision`-beat grid at or after the current
+    /// playback position, e.g. `next_grid_time(1)` for the next beat.
+    pub fn next_grid_time(&self, subdivision: u32) -> Option<MusicalTime> {
+        let pb = self.song_playback()?;
+        Some(unpack_time(pb.current_time.load(Ordering::Relaxed)).quantize_up(subdivision))
+    }
+
     // --- Real-time playback ---
 
     pub fn play(&mut self) {
-        self.play_song(self.song.clone());
+        // Sample data is Arc-shared, so this copies only the song structure.
+        self.play_song(self.song.clone(), MusicalTime::zero(), self.loop_range, PlayMode::Song);
+    }
+
+    /// Start playback after `bars` bars of metronome clicks, so recording
+    /// can begin on the first downbeat.
+    ///
+    /// The count-in clicks even with the metronome off; playback position
+    /// stays at the start of the song until it ends. The engine is built
+    /// counting in, so no song audio plays before the clicks.
+    pub fn play_with_count_in(&mut self, bars: u8) {
+        self.start_playback(self.song.clone(), MusicalTime::zero(), self.loop_range, PlayMode::Song, bars);
+    }
+
+    /// Start playback at `start` instead of the beginning of the song.
+    pub fn play_from(&mut self, start: MusicalTime) {
+        self.play_song(self.song.clone(), start, self.loop_range, PlayMode::Song);
+    }
+
+    /// Start playback in session mode: tracks stay silent until clips are
+    /// launched on them (see `launch_clip`), and play on until stopped.
+    pub fn play_session(&mut self) {
+        self.play_song(self.song.clone(), MusicalTime::zero(), self.loop_range, PlayMode::Session);
+    }
+
+    /// Loop `clip_idx` on a track from the next `quantize` step, in place
+    /// of whatever it plays, or stop the track there with `None`. Works
+    /// during song playback too; returns false if nothing is playing.
+    pub fn launch_clip(&mut self, track_idx: usize, clip_idx: Option<u16>, quantize: LaunchQuantize) -> bool {
+        let Some(now) = self.song_playback().map(|pb| unpack_time(pb.current_time.load(Ordering::Relaxed))) else {
+            return false;
+        };
+        if track_idx >= self.song.tracks.len() {
+            return false;
+        }
+        let at = match quantize {
+            LaunchQuantize::Now => now,
+            LaunchQuantize::Beat => now.quantize_up(1),
+            LaunchQuantize::Bar => match self.song.meter.bar_beat(now.beat) {
+                (_, 0) if now.sub_beat == 0 => now,
+                (bar, _) => MusicalTime::from_beats(self.song.meter.bar_start(bar + 1)),
+            },
+        };
+        if self.launches.len() <= track_idx {
+            self.launches.resize(self.song.tracks.len(), TrackLaunch::default());
+        }
+        let launch = &mut self.launches[track_idx];
+        *launch = launch.at(now);
+        launch.queued = Some((at, clip_idx));
+        self.push_edit(Edit::LaunchClip { track: track_idx as u16, clip: clip_idx, at });
+        true
+    }
+
+    /// The clip a track loops since it was launched, and the row playing.
+    pub fn launched_position(&self, track_idx: usize) -> Option<(u16, u16)> {
+        let pb = self.song_playback()?;
+        let now = unpack_time(pb.current_time.load(Ordering::Relaxed));
+        let (clip_idx, start) = self.launches.get(track_idx)?.at(now).playing?;
+        let clip = self.song.tracks[track_idx].get_pattern_at(clip_idx as usize)?;
+        let rpb = clip.rows_per_beat.unwrap_or(self.song.rows_per_beat) as u64;
+        let rows = (now.as_sub_beats() - start.as_sub_beats()) * rpb / mb_ir::SUB_BEAT_UNIT as u64;
+        Some((clip_idx, (rows % clip.rows.max(1) as u64) as u16))
     }
 
     pub fn play_pattern(&mut self, track_idx: usize, clip_idx: usize) {
-        self.play_song(self.single_clip_song(track_idx, clip_idx as u16));
+        self.play_song(self.single_clip_song(track_idx, clip_idx as u16), MusicalTime::zero(), None, PlayMode::Song);
+    }
+
+    /// Play a single clip over and over until stopped.
+    ///
+    /// Edits to the clip are heard on the next pass, as in classic trackers.
+    pub fn loop_pattern(&mut self, track_idx: usize, clip_idx: usize) {
+        let song = self.single_clip_song(track_idx, clip_idx as u16);
+        let end = track_end_time(&song, track_idx);
+        self.play_song(song, MusicalTime::zero(), Some((MusicalTime::zero(), end)), PlayMode::Song);
+    }
+
+    /// Loop song playback over `(start, end)`, or play straight through with `None`.
+    ///
+    /// Takes effect immediately if playing. Empty ranges clear the loop.
+    pub fn set_loop(&mut self, range: Option<(MusicalTime, MusicalTime)>) {
+        self.loop_range = range.filter(|(start, end)| start < end);
+        self.push_edit(Edit::SetLoop { range: self.loop_range });
+    }
+
+    /// The loop region used for song playback, if any.
+    pub fn loop_range(&self) -> Option<(MusicalTime, MusicalTime)> {
+        self.loop_range
+    }
+
+    // --- Markers ---
+
+    /// Add a marker called `name` at `time`; returns its index in the
+    /// song's time-ordered markers.
+    pub fn add_marker(&mut self, time: MusicalTime, name: &str) -> usize {
+        let index = self.song.markers.partition_point(|m| m.time <= time);
+        self.commit_edit(Edit::AddMarker { marker: mb_ir::Marker::new(time, name) });
+        index
+    }
+
+    pub fn remove_marker(&mut self, index: usize) -> bool {
+        let Ok(index) = u16::try_from(index) else { return false };
+        self.commit_edit(Edit::RemoveMarker { index })
+    }
+
+    /// Rename a marker (names are cut short at 32 bytes).
+    pub fn rename_marker(&mut self, index: usize, name: &str) -> bool {
+        let Ok(index) = u16::try_from(index) else { return false };
+        self.commit_edit(Edit::RenameMarker { index, name: mb_ir::track_name(name) })
+    }
+
+    /// Start playback at marker `index`, jumping there if already playing.
+    pub fn play_from_marker(&mut self, index: usize) -> bool {
+        let Some(marker) = self.song.markers.get(index) else { return false };
+        self.play_from(marker.time);
+        true
+    }
+
+    /// Loop song playback from marker `from` up to marker `to`, live if
+    /// playing. Returns false unless `to` comes after `from`.
+    pub fn loop_markers(&mut self, from: usize, to: usize) -> bool {
+        let (Some(start), Some(end)) = (self.song.markers.get(from), self.song.markers.get(to)) else { return false };
+        if start.time >= end.time {
+            return false;
+        }
+        self.set_loop(Some((start.time, end.time)));
+        true
+    }
+
+    /// Put a limiter on the master bus during playback, live if playing.
+    pub fn set_master_limiter(&mut self, enabled: bool) {
+        self.master_limiter = enabled;
+        self.push_edit(Edit::SetMasterLimiter { enabled });
+    }
+
+    /// Whether playback runs through the master bus limiter.
+    pub fn master_limiter(&self) -> bool {
+        self.master_limiter
+    }
+
+    /// Click on every beat during playback (accented on the first beat of
+    /// the bar), live if playing. The click bypasses the master limiter.
+    pub fn set_metronome(&mut self, enabled: bool) {
+        self.metronome = enabled;
+        self.push_edit(Edit::SetMetronome { enabled });
+    }
+
+    /// Whether playback clicks on every beat.
+    pub fn metronome(&self) -> bool {
+        self.metronome
+    }
+
+    /// Set the metronome click's gain in percent (clamped to 0..=100).
+    pub fn set_metronome_gain(&mut self, percent: u8) {
+        self.metronome_gain = percent.min(100);
+        self.push_edit(Edit::SetMetronomeGain { percent: self.metronome_gain });
+    }
+
+    /// The metronome click's gain in percent.
+    pub fn metronome_gain(&self) -> u8 {
+        self.metronome_gain
+    }
+
+    /// Render independent graph branches on `threads` worker threads
+    /// (0 = all on the audio thread). Takes effect the next time playback
+    /// starts.
+    pub fn set_render_threads(&mut self, threads: usize) {
+        self.render_threads = threads;
+    }
+
+    /// Worker threads used for graph rendering during playback.
+    pub fn render_threads(&self) -> usize {
+        self.render_threads
+    }
+
+    /// Send MIDI clock and time code on the output port called `name` (see
+    /// `midi_output_names`), or on no port with `None`. Takes effect the
+    /// next time playback starts.
+    pub fn set_sync_output(&mut self, name: Option<&str>) -> Result<(), AudioError> {
+        self.sync_output = name.map(MidiOut::open).transpose()?;
+        self.sync_output_lent = false;
+        Ok(())
+    }
+
+    /// Send MIDI clock (with start, stop and song position) on the sync
+    /// output during playback. Messages go out as blocks are rendered, so
+    /// they lead the audio by the output latency. Takes effect the next time
+    /// playback starts.
+    pub fn set_midi_clock_out(&mut self, enabled: bool) {
+        self.midi_clock_out = enabled;
+    }
+
+    /// Whether playback sends MIDI clock.
+    pub fn midi_clock_out(&self) -> bool {
+        self.midi_clock_out
+    }
+
+    /// Send 25 fps MIDI time code on the sync output during playback. Takes
+    /// effect the next time playback starts.
+    pub fn set_mtc_out(&mut self, enabled: bool) {
+        self.mtc_out = enabled;
+    }
+
+    /// Whether playback sends MIDI time code.
+    pub fn mtc_out(&self) -> bool {
+        self.mtc_out
+    }
+
+    /// Follow MIDI clock and time code from the input port called `name`
+    /// (see `midi_input_names`), or stop following with `None`. While
+    /// following, playback waits for the source to start and then tracks its
+    /// position and tempo. Takes effect the next time playback starts.
+    pub fn set_sync_input(&mut self, name: Option<&str>) -> Result<(), AudioError> {
+        self.sync_input = None;
+        self.sync_messages = None;
+        self.sync_input_lent = false;
+        if let Some(name) = name {
+            let (input, messages) = MidiIn::open(name, self.sync_epoch)?;
+            self.sync_input = Some(input);
+            self.sync_messages = Some(messages);
+        }
+        Ok(())
     }
 
-    fn play_song(&mut self, song: Song) {
+    /// Whether playback follows an external MIDI clock or time code.
+    pub fn sync_following(&self) -> bool {
+        self.sync_input.is_some()
+    }
+
+    /// Connect the MIDI output ports `song`'s MIDI Out nodes name that
+    /// aren't connected yet. Ports that can't be opened are skipped; their
+    /// tracks stay silent.
+    fn open_midi_ports(&mut self, song: &Song) {
+        for node in &song.graph.nodes {
+            let NodeType::MidiOut { port, .. } = &node.node_type else { continue };
+            if self.midi_ports.iter().any(|(name, _)| name == port) {
+                continue;
+            }
+            if let Ok(out) = MidiOut::open(port) {
+                self.midi_ports.push((port.clone(), out));
+            }
+        }
+    }
+
+    /// Set an extra MIDI Out offset in milliseconds (positive = earlier).
+    ///
+    /// MIDI Out tracks are already delayed by the audio backend's reported
+    /// output latency so they line up with the samples; this covers what
+    /// the backend can't see, such as a hardware synth's own latency or
+    /// the trip back through a mixer. Takes effect the next time playback
+    /// starts.
+    pub fn set_midi_out_offset_ms(&mut self, ms: i32) {
+        self.midi_out_offset_ms = ms;
+    }
+
+    pub fn midi_out_offset_ms(&self) -> i32 {
+        self.midi_out_offset_ms
+    }
+
+    /// A node's output levels during playback: the peak since the previous
+    /// call for that node, and the latest RMS. `None` for unmetered nodes.
+    pub fn node_meter(&self, node: u16) -> Option<MeterReading> {
+        self.meters.take_reading(node)
+    }
+
+    /// Copy a node's most recent output waveform (mono, oldest first) into
+    /// `out`; returns the frames written (at most `SCOPE_FRAMES`).
+    pub fn node_scope(&self, node: u16, out: &mut [f32]) -> usize {
+        self.meters.scope(node, out)
+    }
+
+    /// Spectrum of the latest `SPECTRUM_SIZE` frames of master output, in
+    /// dBFS per bin (see `SpectrumAnalyzer::analyze`). Computed on the
+    /// calling thread.
+    pub fn master_spectrum(&self) -> Vec<f32> {
+        let mut frames = vec![0.0; SPECTRUM_SIZE];
+        self.meters.master_history(&mut frames);
+        self.spectrum.analyze(&frames)
+    }
+
+    /// Song time of `row` within a track's sequence entry, for starting
+    /// playback at the editor cursor.
+    pub fn row_time(&self, track_idx: usize, seq_idx: usize, row: u16) -> Option<MusicalTime> {
+        let track = self.song.tracks.get(track_idx)?;
+        let entry = track.sequence.get(seq_idx)?;
+        let rpb = track.get_pattern_at(entry.clip_idx as usize)
+            .and_then(|p| p.rows_per_beat)
+            .unwrap_or(self.song.rows_per_beat);
+        Some(entry.start.add_rows(row as u32, rpb as u32))
+    }
+
+    fn play_song(&mut self, song: Song, start: MusicalTime, loop_range: Option<(MusicalTime, MusicalTime)>, mode: PlayMode) {
+        self.start_playback(song, start, loop_range, mode, 0);
+    }
+
+    /// Like `play_song`, after `count_in` bars of clicks.
+    fn start_playback(
+        &mut self,
+        song: Song,
+        start: MusicalTime,
+        loop_range: Option<(MusicalTime, MusicalTime)>,
+        mode: PlayMode,
+        count_in: u8,
+    ) {
         self.stop();
+        self.launches.clear();
 
         // Collect initial mute state before song is moved to audio thread
         let initial_bypasses: Vec<_> = song.tracks.iter()
@@ -233,60 +1232,273 @@ impl Controller {
             .collect();
 
         let stop_signal = Arc::new(AtomicBool::new(false));
-        let current_time = Arc::new(AtomicU64::new(0));
+        let pause_signal = Arc::new(AtomicBool::new(false));
+        // The audio thread seeks to the initial value of the position
+        let current_time = Arc::new(AtomicU64::new(pack_time(start)));
+        let record_time = Arc::new(AtomicU64::new(0));
+        let record_offset_ms = Arc::new(AtomicI32::new(self.record_offset_ms));
         let finished = Arc::new(AtomicBool::new(false));
 
-        let rb = HeapRb::<Edit>::new(EDIT_RING_CAPACITY);
+        let rb = HeapRb::<Command>::new(EDIT_RING_CAPACITY);
         let (edit_producer, edit_consumer) = rb.split();
+        // Room for every patch the edit ring can hold
+        let (retired_producer, retired) = HeapRb::<Box<GraphPatch>>::new(EDIT_RING_CAPACITY).split();
+        let (event_producer, event_consumer) = HeapRb::<Event>::new(EVENT_RING_CAPACITY).split();
 
         let stop = stop_signal.clone();
+        let pause = pause_signal.clone();
         let time = current_time.clone();
+        let record = RecordClock { time: record_time.clone(), offset_ms: record_offset_ms.clone() };
         let done = finished.clone();
+        let limit_hit = Arc::new(AtomicU8::new(0));
+        let limit = limit_hit.clone();
+        // Previews keep the sync ports: no clock out, no waiting for a source
+        let preview = mode == PlayMode::Preview;
+        let sync_out = if preview { None } else { self.sync_output.take() };
+        let sync_in = if preview { None } else { self.sync_messages.take() };
+        let setup = EngineSetup {
+            render_threads: self.render_threads,
+            meters: self.meters.clone(),
+            midi_clock_out: self.midi_clock_out && sync_out.is_some(),
+            mtc_out: self.mtc_out && sync_out.is_some(),
+            sync_follow: sync_in.is_some(),
+            session: mode != PlayMode::Song,
+            count_in,
+            limits: self.limits,
+        };
+        let midi_out_offset_ms = self.midi_out_offset_ms;
+        let monitor = self.monitor_consumer.take().map(|mut m| {
+            // Input captured while stopped is stale
+            m.clear();
+            m
+        });
+        self.sync_output_lent = sync_out.is_some();
+        self.sync_input_lent = sync_in.is_some();
+        let sync_in = sync_in.map(|mut m| {
+            m.clear();
+            m
+        });
+        let epoch = self.sync_epoch;
+        self.open_midi_ports(&song);
+        let midi_ports = core::mem::take(&mut self.midi_ports);
 
+        let (rate_sender, rate_receiver) = std::sync::mpsc::sync_channel(1);
+        let (engine_sender, engine_receiver) = std::sync::mpsc::sync_channel(1);
         let thread = std::thread::spawn(move || {
-            audio_thread(song, stop, time, done, edit_consumer);
+            let mut inbox = Inbox {
+                edits: edit_consumer,
+                retired: retired_producer,
+                events: event_consumer,
+                monitor,
+                sync_out,
+                sync_in,
+                epoch,
+                midi_ports,
+                engine: None,
+            };
+            let handoff = EngineHandoff { rate: rate_sender, engine: engine_receiver };
+            audio_thread(handoff, stop, pause, time, record, done, limit, midi_out_offset_ms, &mut inbox);
+            inbox
         });
+        // The engine is built here, so plugins are created and activated on
+        // this thread, at the rate the audio thread opened its output with
+        let sample_rate = rate_receiver.recv().unwrap_or_default();
+        if sample_rate > 0 {
+            let _ = engine_sender.send(build_engine(song, setup, start, sample_rate));
+            self.monitor_rate.store(sample_rate, Ordering::Relaxed);
+        }
 
         let mut pb = PlaybackHandle {
             stop_signal,
+            pause_signal,
             current_time,
+            record_time,
+            record_offset_ms,
             finished,
+            limit_hit,
             thread: Some(thread),
             edit_producer,
+            retired,
+            event_producer,
+            sample_rate,
+            preview,
         };
 
         // Send initial bypass state for tracks muted before play
         for node_id in initial_bypasses {
-            let _ = pb.edit_producer.try_push(Edit::SetNodeBypass { node: node_id, bypassed: true });
+            let _ = pb.edit_producer.try_push(Command::Edit(Edit::SetNodeBypass { node: node_id, bypassed: true }));
+        }
+        if loop_range.is_some() {
+            let _ = pb.edit_producer.try_push(Command::Edit(Edit::SetLoop { range: loop_range }));
+        }
+        if self.master_limiter {
+            let _ = pb.edit_producer.try_push(Command::Edit(Edit::SetMasterLimiter { enabled: true }));
+        }
+        if self.metronome && !preview {
+            let _ = pb.edit_producer.try_push(Command::Edit(Edit::SetMetronome { enabled: true }));
+        }
+        if self.metronome_gain != 100 {
+            let _ = pb.edit_producer.try_push(Command::Edit(Edit::SetMetronomeGain { percent: self.metronome_gain }));
         }
 
         self.playback = Some(pb);
     }
 
+    /// Stop playback and wait for the audio thread to fade out and close
+    /// the output.
     pub fn stop(&mut self) {
-        if let Some(mut pb) = self.playback.take() {
+        self.stop_async();
+        self.finish_stop(true);
+    }
+
+    /// Stop playback without waiting: the audio thread fades out and
+    /// closes the output on its own. `is_stopped` tells when it is done.
+    pub fn stop_async(&mut self) {
+        self.previewing = None;
+        if let Some(pb) = self.playback.take() {
             pb.stop_signal.store(true, Ordering::Relaxed);
-            if let Some(handle) = pb.thread.take() {
-                let _ = handle.join();
+            // Only one playback winds down at a time
+            self.finish_stop(true);
+            self.stopping = Some(pb);
+        }
+    }
+
+    /// Whether playback has stopped and its audio thread has finished.
+    pub fn is_stopped(&mut self) -> bool {
+        self.finish_stop(false);
+        self.playback.is_none() && self.stopping.is_none()
+    }
+
+    /// Take back what the stopping audio thread was lent, once it has
+    /// finished, or waiting for it to with `wait`.
+    fn finish_stop(&mut self, wait: bool) {
+        let Some(pb) = &mut self.stopping else { return };
+        if !wait && pb.thread.as_ref().is_some_and(|t| !t.is_finished()) {
+            return;
+        }
+        if let Some(inbox) = pb.thread.take().and_then(|t| t.join().ok()) {
+            if inbox.monitor.is_some() {
+                self.monitor_consumer = inbox.monitor;
+            }
+            if self.sync_output_lent {
+                self.sync_output = inbox.sync_out;
             }
+            if self.sync_input_lent {
+                self.sync_messages = inbox.sync_in;
+            }
+            self.midi_ports = inbox.midi_ports;
+            // Plugins are deactivated and destroyed here, off the audio thread
+            drop(inbox.engine);
+        }
+        self.stopping = None;
+        self.sync_output_lent = false;
+        self.sync_input_lent = false;
+        self.meters.clear();
+    }
+
+    /// The running playback, unless it only plays previews.
+    fn song_playback(&self) -> Option<&PlaybackHandle> {
+        self.playback.as_ref().filter(|p| !p.preview)
+    }
+
+    /// Audition `instrument` (1-based) playing `note` on the preview
+    /// channel, starting a silent playback for it if nothing is playing.
+    ///
+    /// Returns false if the song has no tracker track to play it on or the
+    /// event queue is full.
+    pub fn preview_note(&mut self, instrument: u8, note: u8) -> bool {
+        let Some(target) = mb_engine::preview_target(&self.song) else { return false };
+        let Some(pb) = self.preview_playback() else { return false };
+        let payload = EventPayload::NoteOn { note, velocity: 64, instrument };
+        if pb.event_producer.try_push(Event::new(MusicalTime::zero(), target, payload)).is_err() {
+            return false;
+        }
+        self.previewing = Some(note);
+        true
+    }
+
+    /// Release the note `preview_note` started. Returns false if there
+    /// was none.
+    pub fn preview_release(&mut self) -> bool {
+        let (Some(note), Some(target)) = (self.previewing, mb_engine::preview_target(&self.song)) else { return false };
+        let Some(pb) = &mut self.playback else { return false };
+        self.previewing = None;
+        pb.event_producer.try_push(Event::new(MusicalTime::zero(), target, EventPayload::NoteOff { note })).is_ok()
+    }
+
+    /// Play sample `idx` (0-based) as it is, at its C-4 speed and default
+    /// volume, on top of whatever is playing; starts a silent playback for
+    /// it if nothing is. With `looped` it goes round its loop until
+    /// `stop_sample_preview`. Returns false if there is no such sample.
+    pub fn preview_sample(&mut self, idx: u8, looped: bool) -> bool {
+        let Some(sample) = self.song.samples.get(idx as usize).filter(|s| !s.is_empty()) else { return false };
+        let (data, fields) = (sample.data.clone(), mb_ir::SampleFields::of(sample));
+        self.release_previewed_samples();
+        self.previewed_samples.push(data.clone());
+        let Some(pb) = self.preview_playback() else { return false };
+        pb.edit_producer.try_push(Command::Edit(Edit::PreviewSample { sample: Some((data, fields)), looped })).is_ok()
+    }
+
+    /// Stop the sample `preview_sample` is playing.
+    pub fn stop_sample_preview(&mut self) {
+        self.push_edit(Edit::PreviewSample { sample: None, looped: false });
+        self.release_previewed_samples();
+    }
+
+    /// Drop the previewed sample audio nothing else holds any more; the
+    /// audio thread only ever lets go of references, so once ours is the
+    /// last it is safe to free here.
+    fn release_previewed_samples(&mut self) {
+        self.previewed_samples.retain(|data| Arc::strong_count(data) > 1);
+    }
+
+    /// The running playback, started for previews if nothing is playing.
+    fn preview_playback(&mut self) -> Option<&mut PlaybackHandle> {
+        if self.playback.is_none() {
+            self.play_song(self.song.clone(), MusicalTime::zero(), None, PlayMode::Preview);
         }
+        self.playback.as_mut()
+    }
+
+    /// Pause playback, keeping the audio thread and the engine as they are
+    /// (the output fades to silence). `resume` carries on from there.
+    pub fn pause(&mut self) {
+        if let Some(pb) = self.song_playback() {
+            pb.pause_signal.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Carry on with paused playback where it left off.
+    pub fn resume(&mut self) {
+        if let Some(pb) = self.song_playback() {
+            pb.pause_signal.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether playback is paused (it still counts as playing).
+    pub fn is_paused(&self) -> bool {
+        self.song_playback().is_some_and(|p| p.pause_signal.load(Ordering::Relaxed))
     }
 
     pub fn is_playing(&self) -> bool {
-        self.playback
-            .as_ref()
+        self.song_playback()
             .is_some_and(|p| !p.finished.load(Ordering::Relaxed))
     }
 
+    /// The safety limit that truncated or halted the current playback, if any.
+    pub fn limit_hit(&self) -> Option<LimitHit> {
+        let pb = self.song_playback()?;
+        LimitHit::from_code(pb.limit_hit.load(Ordering::Relaxed))
+    }
+
     pub fn is_finished(&self) -> bool {
-        self.playback
-            .as_ref()
+        self.song_playback()
             .is_some_and(|p| p.finished.load(Ordering::Relaxed))
     }
 
     /// Get the current playback position in per-track coordinates.
     pub fn track_position(&self, track_idx: usize) -> Option<TrackPlaybackPosition> {
-        let pb = self.playback.as_ref()?;
+        let pb = self.song_playback()?;
         if pb.finished.load(Ordering::Relaxed) {
             return None;
         }
@@ -295,18 +1507,245 @@ impl Controller {
         time_to_track_position(&self.song, time, track_idx)
     }
 
+    /// Get the current playback position in bars, beats and rows.
+    pub fn bar_position(&self) -> Option<BarPosition> {
+        let pb = self.song_playback()?;
+        if pb.finished.load(Ordering::Relaxed) {
+            return None;
+        }
+        let time = unpack_time(pb.current_time.load(Ordering::Relaxed));
+        Some(self.song.meter.position(time, self.song.rows_per_beat as u32))
+    }
+
+    // --- Live sampling ---
+
+    /// Start recording input `device` (`None` = the default; see
+    /// `input_device_names`) into a new sample called `name`.
+    ///
+    /// While recording, playback feeds the input through the graph's
+    /// AudioInput nodes, so wiring one to Master monitors it.
+    /// `stop_recording` adds the sample to the song.
+    pub fn record_sample(&mut self, device: Option<&str>, name: &str) -> Result<(), AudioError> {
+        if self.recording.is_some() {
+            return Err(AudioError::Busy);
+        }
+        let (input, ring) = CpalInput::open(device)?;
+        let monitor = self.take_monitor_producer();
+        self.recording = Some(recording::Recording::start(input, ring, monitor, self.monitor_rate.clone(), name));
+        Ok(())
+    }
+
+    /// Whether `record_sample` is capturing.
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Finish the recording and add it to the song as a new sample and
+    /// instrument.
+    ///
+    /// Returns the 1-based instrument number, or `None` if nothing was
+    /// recording or no audio arrived.
+    pub fn stop_recording(&mut self) -> Option<u8> {
+        let (sample, monitor) = self.recording.take()?.finish();
+        if monitor.is_some() {
+            self.monitor_producer = monitor;
+        }
+        self.add_sample_instrument(sample?)
+    }
+
+    /// The monitor ring's producer, or a fresh ring's if a panicked thread
+    /// lost it (the new consumer reaches the audio thread on the next play).
+    fn take_monitor_producer(&mut self) -> ringbuf::HeapProd<f32> {
+        if let Some(producer) = self.monitor_producer.take() {
+            return producer;
+        }
+        let (producer, consumer) = HeapRb::<f32>::new(MONITOR_RING_CAPACITY).split();
+        self.monitor_consumer = Some(consumer);
+        producer
+    }
+
+    // --- Recording latency compensation ---
+
+    /// Set an extra record offset in milliseconds (positive = earlier).
+    ///
+    /// Added on top of the audio backend's reported output latency, to
+    /// account for input latency (MIDI interface, keyboard scan) the
+    /// backend can't see.
+    pub fn set_record_offset_ms(&mut self, ms: i32) {
+        self.record_offset_ms = ms;
+        if let Some(pb) = &self.playback {
+            pb.record_offset_ms.store(ms, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_offset_ms(&self) -> i32 {
+        self.record_offset_ms
+    }
+
+    /// Turn live note recording on or off (see `record_note`).
+    pub fn set_note_recording(&mut self, enabled: bool) {
+        self.note_recording = enabled;
+    }
+
+    /// Whether live note recording is on.
+    pub fn note_recording(&self) -> bool {
+        self.note_recording
+    }
+
+    /// Record a played note (from the GUI keyboard or MIDI) into channel
+    /// `column` of the clip playing on track `track_idx`.
+    ///
+    /// The note lands on the row nearest `record_position`, replacing that
+    /// cell's note and instrument and keeping its volume and effect. The
+    /// edit reaches both the local song and the running engine. Returns
+    /// where it was written, or `None` if not recording, not playing, or
+    /// no clip is playing on the track.
+    pub fn record_note(&mut self, track_idx: usize, column: u8, note: Note, instrument: u8) -> Option<TrackPlaybackPosition> {
+        if !self.note_recording {
+            return None;
+        }
+        let time = self.record_position()?;
+        self.record_note_at(time, track_idx, column, note, instrument)
+    }
+
+    /// Write a recorded note at the row nearest `time`.
+    fn record_note_at(&mut self, time: MusicalTime, track_idx: usize, column: u8, note: Note, instrument: u8) -> Option<TrackPlaybackPosition> {
+        let pos = mb_ir::nearest_track_position(&self.song, time, track_idx)?;
+        let pattern = self.song.tracks[track_idx].clips.get(pos.clip_idx as usize)?.pattern()?;
+        if column >= pattern.channels {
+            return None;
+        }
+        let mut cell = *pattern.cell(pos.row, column);
+        cell.note = note;
+        if instrument > 0 {
+            cell.instrument = instrument;
+        }
+        self.apply_edit(Edit::SetCell { track: track_idx as u16, clip: pos.clip_idx, row: pos.row, column, cell });
+        Some(pos)
+    }
+
+    /// Position of what the player is hearing right now, for stamping
+    /// recorded input. Lags `track_position` by the output latency plus
+    /// the configured record offset.
+    pub fn record_position(&self) -> Option<mb_ir::MusicalTime> {
+        let pb = self.song_playback()?;
+        if pb.finished.load(Ordering::Relaxed) {
+            return None;
+        }
+        Some(unpack_time(pb.record_time.load(Ordering::Relaxed)))
+    }
+
+    // --- Song timing ---
+
+    /// The song's tempo map and length, for time displays and seek bars.
+    ///
+    /// Schedules the whole song, so cache the result instead of calling
+    /// it every frame.
+    pub fn tempo_map(&self) -> (TempoMap, MusicalTime) {
+        let result = mb_engine::schedule_song(&self.song, self.preferences.export_sample_rate);
+        (result.tempo_map, result.total_time)
+    }
+
+    /// Song length in seconds, following its tempo and speed changes.
+    pub fn song_length_seconds(&self) -> f64 {
+        let (map, end) = self.tempo_map();
+        map.seconds_at(end)
+    }
+
+    /// Seconds from the start of the song to `time`.
+    pub fn time_at(&self, time: MusicalTime) -> f64 {
+        self.tempo_map().0.seconds_at(time)
+    }
+
     // --- Offline rendering ---
 
+    /// Set the safety limits on how much a song may schedule, for
+    /// playback started from now on and for renders.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// The limits `set_limits` set (defaults until then).
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Incremental renderer for the song, yielding `chunk_size` frames at a time.
+    pub fn renderer(&self, sample_rate: u32, chunk_size: usize, max_frames: usize) -> Renderer {
+        Renderer::with_limits(self.song.clone(), sample_rate, chunk_size, max_frames, self.limits)
+    }
+
+    /// Incremental renderer for a single clip (see `render_pattern_to_wav`).
+    pub fn pattern_renderer(&self, track_idx: usize, clip_idx: usize, sample_rate: u32, chunk_size: usize, max_frames: usize) -> Renderer {
+        Renderer::with_limits(self.single_clip_song(track_idx, clip_idx as u16), sample_rate, chunk_size, max_frames, self.limits)
+    }
+
+    /// Stream the song through `f` in chunks instead of collecting it.
+    pub fn render_chunks(&self, sample_rate: u32, chunk_size: usize, max_frames: usize, f: impl FnMut(&[[f32; 2]])) {
+        self.renderer(sample_rate, chunk_size, max_frames).for_each_chunk(f);
+    }
+
     pub fn render_frames(&self, sample_rate: u32, max_frames: usize) -> Vec<[f32; 2]> {
-        render_song_frames(self.song.clone(), sample_rate, max_frames)
+        render_song_frames(self.song.clone(), sample_rate, max_frames, self.limits)
     }
 
-    pub fn render_to_wav(&self, sample_rate: u32, max_seconds: u32) -> Vec<u8> {
-        render_song_to_wav(self.song.clone(), sample_rate, max_seconds)
+    pub fn render_to_wav(&self, sample_rate: u32, max_seconds: u32, spec: WavSpec) -> Vec<u8> {
+        render_song_to_wav(self.song.clone(), sample_rate, max_seconds, spec, self.limits)
+    }
+
+    /// Render the song into a new `format` file at `path`, streaming so
+    /// long renders run in constant memory. Returns the frames written.
+    pub fn render_to_file(&self, path: impl AsRef<std::path::Path>, format: ExportFormat, sample_rate: u32, max_seconds: u32) -> std::io::Result<u64> {
+        self.renderer(sample_rate, BLOCK_SIZE, frame_limit(sample_rate, max_seconds)).write_file(path.as_ref(), format)
     }
 
     pub fn render_pattern_to_wav(&self, track_idx: usize, clip_idx: usize, sample_rate: u32, max_seconds: u32) -> Vec<u8> {
-        render_song_to_wav(self.single_clip_song(track_idx, clip_idx as u16), sample_rate, max_seconds)
+        let song = self.single_clip_song(track_idx, clip_idx as u16);
+        render_song_to_wav(song, sample_rate, max_seconds, WavSpec::default(), self.limits)
+    }
+
+    /// Render the master mix and one stem per track as WAVs of equal
+    /// length, master first.
+    ///
+    /// Each stem bypasses the other tracks' machines, so tempo changes and
+    /// other global effects from the rest of the song still apply; the
+    /// graph after the track (effects, sends) is rendered as in the mix.
+    /// Tracks sharing a machine are told apart by soloing channels.
+    pub fn render_stems(&self, sample_rate: u32, max_seconds: u32) -> Vec<Stem> {
+        let max_frames = frame_limit(sample_rate, max_seconds);
+        let master = render_song_frames(self.song.clone(), sample_rate, max_frames, self.limits);
+        let len = master.len();
+        let mut stems = vec![Stem { name: "Master".to_string(), wav: frames_to_wav(&master, sample_rate, WavSpec::default()) }];
+        for (i, track) in self.song.tracks.iter().enumerate() {
+            let mut frames = self.render_stem(i, sample_rate, len);
+            frames.resize(len, [0.0, 0.0]);
+            let name = if track.name.is_empty() { format!("Track {}", i + 1) } else { track.name.to_string() };
+            stems.push(Stem { name, wav: frames_to_wav(&frames, sample_rate, WavSpec::default()) });
+        }
+        stems
+    }
+
+    /// Up to `max_frames` of the song with only track `track_idx` audible.
+    fn render_stem(&self, track_idx: usize, sample_rate: u32, max_frames: usize) -> Vec<[f32; 2]> {
+        let track = &self.song.tracks[track_idx];
+        let Some(node) = track.machine_node else { return Vec::new() };
+        let mut song = self.song.clone();
+        let others = self.song.tracks.iter().enumerate().filter(|&(i, _)| i != track_idx);
+        let bypass: Vec<Edit> = others.clone()
+            .filter_map(|(_, t)| t.machine_node.filter(|&n| n != node))
+            .map(|n| Edit::SetNodeBypass { node: n, bypassed: true })
+            .collect();
+        if others.clone().any(|(_, t)| t.machine_node == Some(node)) {
+            let solo = track.base_channel as usize..track.base_channel as usize + track.num_channels as usize;
+            for (ch, settings) in song.channels.iter_mut().enumerate() {
+                settings.solo = solo.contains(&ch);
+            }
+        }
+        let mut frames = Vec::new();
+        Renderer::with_limits(song, sample_rate, BLOCK_SIZE, max_frames, self.limits)
+            .with_edits(&bypass)
+            .for_each_chunk(|chunk| frames.extend_from_slice(chunk));
+        frames
     }
 
     // --- Helpers ---
@@ -325,43 +1764,186 @@ impl Default for Controller {
     }
 }
 
+/// Where `Controller::launch_clip` starts or stops a clip.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LaunchQuantize {
+    /// Right away
+    Now,
+    /// On the next beat
+    Beat,
+    /// On the next bar, following the song's time signatures
+    #[default]
+    Bar,
+}
+
+/// One rendered stem from `Controller::render_stems`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stem {
+    /// Track name ("Master" for the mix)
+    pub name: String,
+    /// The stem as a WAV file
+    pub wav: Vec<u8>,
+}
+
+/// Song file formats `Controller::load_song` reads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SongFileFormat {
+    /// Native project (`save_project`)
+    Project,
+    Mod,
+    Bmx,
+    It,
+    S3m,
+    /// Standard MIDI File
+    Midi,
+}
+
+impl SongFileFormat {
+    /// The format a file name's extension names.
+    pub fn from_path(path: &std::path::Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "mbp" => Some(Self::Project),
+            "mod" => Some(Self::Mod),
+            "bmx" => Some(Self::Bmx),
+            "it" => Some(Self::It),
+            "s3m" => Some(Self::S3m),
+            "mid" | "midi" => Some(Self::Midi),
+            _ => None,
+        }
+    }
+
+    /// Usual file extension.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Project => "mbp",
+            Self::Mod => "mod",
+            Self::Bmx => "bmx",
+            Self::It => "it",
+            Self::S3m => "s3m",
+            Self::Midi => "mid",
+        }
+    }
+}
+
+/// A song serialized by `Controller::export_song`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SongExport {
+    /// The file bytes
+    pub data: Vec<u8>,
+    /// One line per kind of loss (empty if the export is exact)
+    pub warnings: Vec<String>,
+}
+
+/// Sample file formats `Controller::load_sample` reads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleFileFormat {
+    Wav,
+    /// AIFF or uncompressed AIFF-C
+    Aiff,
+    /// Headerless PCM laid out as described
+    Raw(RawFormat),
+}
+
+impl SampleFileFormat {
+    /// The format a file name's extension names. Raw PCM is never
+    /// guessed, since its layout has to be given.
+    pub fn from_path(path: &std::path::Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "wav" => Some(Self::Wav),
+            "aif" | "aiff" | "aifc" => Some(Self::Aiff),
+            _ => None,
+        }
+    }
+}
+
+/// What `Controller::slice_sample` added.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Slices {
+    /// 1-based instrument numbers of the slices, in order
+    pub instruments: Vec<u8>,
+    /// The clip triggering the slices, if one was asked for
+    pub clip: Option<u16>,
+}
+
 /// Apply an edit directly to song data (no event queue update).
-fn apply_edit_to_song(song: &mut Song, edit: &Edit) {
+fn apply_edit_to_song(song: &mut Song, edit: &Edit) -> bool {
     match edit {
         Edit::SetCell { track, clip, row, column, cell } => {
-            let Some(t) = song.tracks.get_mut(*track as usize) else { return };
-            let Some(c) = t.clips.get_mut(*clip as usize) else { return };
-            let Some(pat) = c.pattern_mut() else { return };
-            if *row < pat.rows && *column < pat.channels {
-                *pat.cell_mut(*row, *column) = *cell;
+            let Some(t) = song.tracks.get_mut(*track as usize) else { return false };
+            let Some(c) = t.clips.get_mut(*clip as usize) else { return false };
+            let Some(pat) = c.pattern_mut() else { return false };
+            if *row >= pat.rows || *column >= pat.channels {
+                return false;
             }
+            *pat.cell_mut(*row, *column) = *cell;
         }
         Edit::SetNodeBypass { .. } => {} // Handled by engine directly
-        Edit::SetSeqEntry { track, beat, entry } => {
-            apply_set_seq_entry(song, *track, *beat, entry);
+        Edit::SetLoop { .. } => {} // Playback state, not song data
+        Edit::SetMasterLimiter { .. } => {} // Playback state, not song data
+        Edit::SetMetronome { .. } | Edit::SetMetronomeGain { .. } | Edit::CountIn { .. } | Edit::PreviewSample { .. } => {} // Playback state
+        Edit::LaunchClip { .. } => {} // Playback state
+        Edit::SetChannelMute { channel, muted } => {
+            let Some(ch) = song.channels.get_mut(*channel as usize) else { return false };
+            ch.muted = *muted;
         }
+        Edit::SetChannelSolo { channel, solo } => {
+            let Some(ch) = song.channels.get_mut(*channel as usize) else { return false };
+            ch.solo = *solo;
+        }
+        Edit::SetTempo { tempo } if *tempo > 0 => song.initial_tempo = *tempo,
+        Edit::SetSpeed { speed } if *speed > 0 => song.initial_speed = *speed,
+        Edit::SetTempo { .. } | Edit::SetSpeed { .. } => return false,
+        Edit::SetTempoEnvelope { envelope } => song.tempo_envelope = envelope.clone(),
+        Edit::SetTimeSignature { beat, signature } => song.meter.set(*beat, *signature),
+        Edit::SetChannelPan { channel, pan } => {
+            let Some(ch) = song.channels.get_mut(*channel as usize) else { return false };
+            ch.initial_pan = (*pan).clamp(-64, 64);
+        }
+        Edit::SetNodeParam { node, param, value } => {
+            return song.graph.set_param(*node, *param, *value).is_some();
+        }
+        Edit::SetSampleFields { sample, fields } => {
+            let Some(s) = song.samples.get_mut(*sample as usize) else { return false };
+            fields.apply_to(s);
+        }
+        Edit::SetSample { .. } | Edit::AddSample { .. } | Edit::RemoveLastSample => return song.apply_sample_edit(edit),
+        Edit::AddMarker { .. } | Edit::RemoveMarker { .. } | Edit::RenameMarker { .. } => {
+            return song.apply_marker_edit(edit)
+        }
+        Edit::SetSeqEntry { .. }
+        | Edit::InsertSeqEntry { .. }
+        | Edit::RemoveSeqEntry { .. }
+        | Edit::MoveSeqEntry { .. }
+        | Edit::DuplicateSeqEntry { .. }
+        | Edit::SetSeqClip { .. }
+        | Edit::SetSequence { .. } => return song.apply_sequence_edit(edit).is_some(),
+        Edit::ResizeClip { .. }
+        | Edit::InsertRows { .. }
+        | Edit::DeleteRows { .. }
+        | Edit::CloneClip { .. }
+        | Edit::AddClip { .. }
+        | Edit::RemoveLastClip { .. }
+        | Edit::SetClip { .. } => return song.apply_clip_edit(edit).is_some(),
+        Edit::SetSongGroove { .. } | Edit::SetTrackGroove { .. } | Edit::SetTrackOffset { .. } => {
+            return song.apply_groove_edit(edit)
+        }
+        Edit::AddTrack { .. }
+        | Edit::AddMidiTrack { .. }
+        | Edit::RemoveTrack { .. }
+        | Edit::MoveTrack { .. }
+        | Edit::RenameTrack { .. } => return song.apply_track_edit(edit),
+        Edit::AddNode { node_type } => {
+            machines::add_node(&mut song.graph, node_type.clone());
+        }
+        Edit::RemoveNode { node } => return song.remove_node(*node),
+        Edit::Connect { from, to, gain } => return song.graph.try_connect(*from, *to, *gain),
+        Edit::Disconnect { from, to } => return song.graph.disconnect(*from, *to),
+        Edit::SetConnectionGain { from, to, gain } => return song.graph.set_connection_gain(*from, *to, *gain),
+        Edit::SetConnectionPan { from, to, pan } => return song.graph.set_connection_pan(*from, *to, *pan),
     }
-}
-
-/// Apply a SetSeqEntry edit: remove any entry at beat, optionally insert new one.
-fn apply_set_seq_entry(song: &mut Song, track_idx: u16, beat: u32, entry: &Option<mb_ir::SeqEntryData>) {
-    let Some(track) = song.tracks.get_mut(track_idx as usize) else { return };
-    // Remove existing entry at this beat
-    track.sequence.retain(|e| e.start.beat as u32 != beat);
-    // Insert new entry if provided
-    if let Some(data) = entry {
-        let new_entry = mb_ir::SeqEntry {
-            start: mb_ir::MusicalTime::from_beats(beat as u64),
-            clip_idx: data.clip_idx,
-            length: data.length,
-            termination: data.termination,
-        };
-        // Insert sorted by start time
-        let pos = track.sequence.iter()
-            .position(|e| e.start > new_entry.start)
-            .unwrap_or(track.sequence.len());
-        track.sequence.insert(pos, new_entry);
-    }
+    true
 }
 
 /// Rebuild track sequences to play only a single clip on a single track.
@@ -370,7 +1952,7 @@ fn rebuild_track_sequences(song: &mut Song, track_idx: usize, clip_idx: u16) {
     let length = song.tracks.get(track_idx)
         .and_then(|t| t.get_pattern_at(clip_idx as usize))
         .map_or(0, |p| p.rows);
-    let entry = SeqEntry { start: mb_ir::MusicalTime::zero(), clip_idx, length, termination: mb_ir::SeqTermination::Natural };
+    let entry = SeqEntry { start: mb_ir::MusicalTime::zero(), clip_idx, length, termination: mb_ir::SeqTermination::Natural, boundary: mb_ir::ClipBoundary::Ring };
     for (i, track) in song.tracks.iter_mut().enumerate() {
         track.sequence = if i == track_idx && (clip_idx as usize) < track.clips.len() {
             vec![entry]
@@ -380,30 +1962,120 @@ fn rebuild_track_sequences(song: &mut Song, track_idx: usize, clip_idx: u16) {
     }
 }
 
-fn render_song_frames(song: Song, sample_rate: u32, max_frames: usize) -> Vec<[f32; 2]> {
-    let mut engine = Engine::new(song, sample_rate);
-    engine.schedule_song();
-    engine.play();
+fn render_song_frames(song: Song, sample_rate: u32, max_frames: usize, limits: Limits) -> Vec<[f32; 2]> {
+    let mut frames = Vec::new();
+    Renderer::with_limits(song, sample_rate, BLOCK_SIZE, max_frames, limits).for_each_chunk(|chunk| frames.extend_from_slice(chunk));
+    frames
+}
+
+/// Frames in `max_seconds` at `sample_rate`, in u64 as a long limit at a
+/// high rate overflows u32.
+fn frame_limit(sample_rate: u32, max_seconds: u32) -> usize {
+    (sample_rate as u64 * max_seconds as u64).try_into().unwrap_or(usize::MAX)
+}
+
+fn render_song_to_wav(song: Song, sample_rate: u32, max_seconds: u32, spec: WavSpec, limits: Limits) -> Vec<u8> {
+    let max_frames = frame_limit(sample_rate, max_seconds);
+    let frames = render_song_frames(song, sample_rate, max_frames, limits);
+    frames_to_wav(&frames, sample_rate, spec)
+}
+
+/// Shared state for publishing the latency-compensated record position.
+struct RecordClock {
+    time: Arc<AtomicU64>,
+    offset_ms: Arc<AtomicI32>,
+}
 
-    let mut frames = Vec::with_capacity(max_frames);
-    while !engine.is_finished() && frames.len() < max_frames {
-        frames.push(engine.render_frame());
+impl RecordClock {
+    /// Publish the engine position rewound by latency + configured offset.
+    fn publish(&self, engine: &Engine, latency_frames: u32, sample_rate: u32) {
+        let offset = self.offset_ms.load(Ordering::Relaxed) as i64 * sample_rate as i64 / 1000;
+        let frames = (latency_frames as i64 + offset).clamp(0, u32::MAX as i64) as u32;
+        self.time.store(pack_time(engine.time_before(frames)), Ordering::Relaxed);
     }
-    frames
 }
 
-fn render_song_to_wav(song: Song, sample_rate: u32, max_seconds: u32) -> Vec<u8> {
-    let max_frames = (sample_rate * max_seconds) as usize;
-    let frames = render_song_frames(song, sample_rate, max_frames);
-    frames_to_wav(&frames, sample_rate)
+/// What the Controller sends the audio thread, applied in order.
+enum Command {
+    Edit(Edit),
+    /// A graph edit prepared off the audio thread
+    Graph(Box<GraphPatch>),
+}
+
+/// Audio-thread ends of the controller's command rings.
+struct Inbox {
+    edits: ringbuf::HeapCons<Command>,
+    /// Applied graph patches on their way back to be dropped
+    retired: ringbuf::HeapProd<Box<GraphPatch>>,
+    events: ringbuf::HeapCons<Event>,
+    /// Captured input for AudioInput nodes, lent by the Controller
+    monitor: Option<ringbuf::HeapCons<f32>>,
+    /// MIDI sync ports, lent by the Controller
+    sync_out: Option<MidiOut>,
+    sync_in: Option<ringbuf::HeapCons<MidiMessage>>,
+    /// Time base of `sync_in` timestamps
+    epoch: Instant,
+    /// Ports for MIDI Out tracks, lent by the Controller
+    midi_ports: Vec<(String, MidiOut)>,
+    /// The played engine, handed back to be dropped by the Controller
+    engine: Option<Engine>,
+}
+
+/// How the audio thread gets its engine from the Controller: it reports
+/// its output's sample rate and receives the engine built for it.
+struct EngineHandoff {
+    rate: std::sync::mpsc::SyncSender<u32>,
+    engine: std::sync::mpsc::Receiver<Engine>,
+}
+
+/// Engine options the audio thread applies before playback starts.
+struct EngineSetup {
+    render_threads: usize,
+    meters: Arc<MeterBank>,
+    midi_clock_out: bool,
+    mtc_out: bool,
+    /// Follow the lent sync input instead of starting on its own
+    sync_follow: bool,
+    /// Play launched clips instead of the sequences
+    session: bool,
+    /// Bars of clicks before the song (0 = none)
+    count_in: u8,
+    limits: Limits,
+}
+
+/// The engine for a playback from `start`, built on the Controller's
+/// thread with the options in `setup`.
+fn build_engine(song: Song, setup: EngineSetup, start: MusicalTime, sample_rate: u32) -> Engine {
+    let mut engine = Engine::new(song, sample_rate);
+    engine.set_render_threads(setup.render_threads);
+    engine.set_meters(Some(setup.meters));
+    engine.set_session(setup.session);
+    engine.set_limits(setup.limits);
+    if start > MusicalTime::zero() {
+        engine.seek(start);
+    } else {
+        engine.schedule_song();
+    }
+    engine.set_midi_clock_out(setup.midi_clock_out);
+    engine.set_mtc_out(setup.mtc_out);
+    engine.set_sync_follow(setup.sync_follow);
+    if setup.count_in > 0 {
+        engine.count_in(setup.count_in);
+    }
+    engine
 }
 
+#[allow(clippy::too_many_arguments)]
 fn audio_thread(
-    song: Song,
+    handoff: EngineHandoff,
     stop_signal: Arc<AtomicBool>,
+    pause_signal: Arc<AtomicBool>,
     current_time: Arc<AtomicU64>,
+    record: RecordClock,
     finished: Arc<AtomicBool>,
-    mut edit_consumer: ringbuf::HeapCons<Edit>,
+    limit_hit: Arc<AtomicU8>,
+    midi_out_offset_ms: i32,
+    inbox: &mut Inbox,
 ) {
     let Ok((mut output, consumer)) = CpalOutput::new() else {
         finished.store(true, Ordering::Relaxed);
@@ -411,11 +2083,17 @@ fn audio_thread(
     };
 
     let sample_rate = output.sample_rate();
-    let mut engine = Engine::new(song, sample_rate);
-    engine.schedule_song();
+    let _ = handoff.rate.send(sample_rate);
+    let Ok(mut engine) = handoff.engine.recv() else {
+        finished.store(true, Ordering::Relaxed);
+        return;
+    };
 
     alloc_guard(|| {
-        engine.play();
+        // A followed source starts playback itself
+        if !engine.sync_follow() {
+            engine.play();
+        }
 
         alloc_permit(|| {
             if output.build_stream(consumer, std::thread::current()).is_err() {
@@ -425,38 +2103,82 @@ fn audio_thread(
         });
 
         run_audio_loop(
-            &mut engine, &mut output, &stop_signal, &current_time,
-            &mut edit_consumer, sample_rate,
+            &mut engine, &mut output, &stop_signal, &pause_signal, &current_time, &record,
+            inbox, sample_rate, midi_out_offset_ms,
         );
     });
 
+    if let Some(hit) = engine.limit_hit() {
+        limit_hit.store(hit.code(), Ordering::Relaxed);
+    }
+    inbox.engine = Some(engine);
     finished.store(true, Ordering::Relaxed);
 }
 
 /// Main audio render loop. Must be called inside `alloc_guard`.
+#[allow(clippy::too_many_arguments)]
 fn run_audio_loop(
     engine: &mut Engine,
     output: &mut CpalOutput,
     stop_signal: &AtomicBool,
+    pause_signal: &AtomicBool,
     current_time: &AtomicU64,
-    edit_consumer: &mut ringbuf::HeapCons<Edit>,
+    record: &RecordClock,
+    inbox: &mut Inbox,
     sample_rate: u32,
+    midi_out_offset_ms: i32,
 ) {
     let report_interval = (sample_rate / 100) as u64;
+    let latency_frames = output.latency_frames();
+    // MIDI Out messages wait until the audio rendered with them is heard
+    let midi_lead = sample_rate as i64 * midi_out_offset_ms as i64 / 1000;
+    let midi_delay = (latency_frames as i64 - midi_lead).max(0) as u64;
+    let mut midi_pending: Vec<(u64, MidiOutMessage)> = alloc_permit(|| Vec::with_capacity(MIDI_PENDING_CAPACITY));
+    let mut sync_pending: Vec<(u64, SyncMessage)> = alloc_permit(|| Vec::with_capacity(SYNC_PENDING_CAPACITY));
     let mut frame_count: u64 = 0;
     let mut edit_buf: Vec<Edit> = alloc_permit(Vec::new);
     let mut batch = [[0.0f32; 2]; BLOCK_SIZE];
     let mut interleaved = [0.0f32; BLOCK_SIZE * 2];
-
-    while !engine.is_finished() && !stop_signal.load(Ordering::Relaxed) {
-        alloc_permit(|| drain_edits(edit_consumer, &mut edit_buf));
-        if !edit_buf.is_empty() {
-            engine.apply_edits(&edit_buf);
-            edit_buf.clear();
+    let mut input = [[0.0f32; 2]; BLOCK_SIZE];
+
+    let fade_frames = sample_rate * STOP_FADE_MS / 1000;
+    let mut stopping = false;
+    let mut paused = false;
+
+    // Bail out if the engine's watchdog halted a stalled clock
+    while !engine.is_finished() && engine.limit_hit() != Some(LimitHit::Stalled) {
+        // Fade out before stopping rather than cutting the sound off
+        if !stopping && stop_signal.load(Ordering::Relaxed) {
+            stopping = true;
+            engine.fade_out(fade_frames);
+        }
+        if stopping && !engine.fading_out() {
+            break;
+        }
+        // Pausing fades out the same way but keeps the engine where it is;
+        // a followed source starts playback itself
+        if !stopping && paused != pause_signal.load(Ordering::Relaxed) {
+            paused = !paused;
+            if paused {
+                engine.fade_out(fade_frames);
+            } else if !engine.sync_follow() {
+                engine.play();
+            }
+        }
+        drain_commands(inbox, engine, &mut edit_buf);
+        drain_events(&mut inbox.events, engine);
+        if let Some(messages) = inbox.sync_in.as_mut() {
+            receive_sync(messages, engine);
+            engine.poll_sync(inbox.epoch.elapsed().as_micros() as u64);
         }
 
         let n = frames_until_report(frame_count, report_interval, BLOCK_SIZE);
+        let captured = inbox.monitor.as_mut()
+            .map_or(0, |m| pop_input(m, &mut interleaved[..n * 2], &mut input));
+        engine.set_input(&input[..captured]);
         engine.render_block(&mut batch[..n]);
+        queue_sync(engine, frame_count + midi_delay, &mut sync_pending);
+        queue_midi(engine, frame_count + midi_delay, &mut midi_pending);
 
         // Interleave for output
         for i in 0..n {
@@ -466,26 +2188,140 @@ fn run_audio_loop(
         output.write(&interleaved[..n * 2]);
 
         frame_count += n as u64;
+        send_sync(&mut inbox.sync_out, &mut sync_pending, frame_count);
+        send_midi(engine, &mut inbox.midi_ports, &mut midi_pending, frame_count);
         if frame_count.is_multiple_of(report_interval) {
             current_time.store(pack_time(engine.position()), Ordering::Relaxed);
+            record.publish(engine, latency_frames, sample_rate);
         }
     }
 
-    let silence = [0.0f32; BLOCK_SIZE * 2];
-    let tail_frames = sample_rate as usize;
-    let mut written = 0;
-    while written < tail_frames {
-        let n = (tail_frames - written).min(BLOCK_SIZE);
-        output.write(&silence[..n * 2]);
-        written += n;
+    // Release held notes now; notes not yet heard never start
+    engine.stop();
+    queue_sync(engine, 0, &mut sync_pending);
+    send_sync(&mut inbox.sync_out, &mut sync_pending, u64::MAX);
+    midi_pending.retain(|(_, m)| m.bytes[0] & 0xF0 != 0x90);
+    queue_midi(engine, 0, &mut midi_pending);
+    send_midi(engine, &mut inbox.midi_ports, &mut midi_pending, u64::MAX);
+
+    // Let the device play what is queued, then close the stream
+    output.drain();
+    let _ = output.stop();
+}
+
+/// Pop whole interleaved frames from the monitor ring into `frames`,
+/// using `scratch` as staging; returns the number of frames.
+fn pop_input(monitor: &mut ringbuf::HeapCons<f32>, scratch: &mut [f32], frames: &mut [[f32; 2]]) -> usize {
+    let len = monitor.occupied_len().min(scratch.len()) & !1;
+    monitor.pop_slice(&mut scratch[..len]);
+    for (frame, s) in frames.iter_mut().zip(scratch[..len].chunks_exact(2)) {
+        *frame = [s[0], s[1]];
     }
+    len / 2
 }
 
 /// Drain all available edits from the consumer into the buffer.
-fn drain_edits(consumer: &mut ringbuf::HeapCons<Edit>, buf: &mut Vec<Edit>) {
-    while let Some(edit) = consumer.try_pop() {
-        buf.push(edit);
+/// Apply the Controller's edits and graph patches in the order they were
+/// sent. Patches go back holding what they replaced.
+fn drain_commands(inbox: &mut Inbox, engine: &mut Engine, edit_buf: &mut Vec<Edit>) {
+    while let Some(command) = inbox.edits.try_pop() {
+        match command {
+            Command::Edit(edit) => alloc_permit(|| edit_buf.push(edit)),
+            Command::Graph(mut patch) => {
+                apply_edits(engine, edit_buf);
+                engine.apply_graph_patch(&mut patch);
+                let _ = inbox.retired.try_push(patch);
+            }
+        }
+    }
+    apply_edits(engine, edit_buf);
+}
+
+/// Apply and clear `edits`.
+fn apply_edits(engine: &mut Engine, edits: &mut Vec<Edit>) {
+    if edits.iter().any(Edit::allocates) {
+        // Track and sequence changes rebuild event sources and node buffers
+        alloc_permit(|| {
+            engine.apply_edits(edits);
+            edits.clear();
+        });
+    } else if !edits.is_empty() {
+        engine.apply_edits(edits);
+        edits.clear();
+    }
+}
+
+/// Move injected events into the engine while it has preallocated room;
+/// the rest wait in the ring for the next block.
+fn drain_events(consumer: &mut ringbuf::HeapCons<Event>, engine: &mut Engine) {
+    while engine.has_schedule_room() {
+        let Some(event) = consumer.try_pop() else { break };
+        engine.schedule(event);
+    }
+}
+
+/// Feed received clock and time code messages to the engine.
+fn receive_sync(messages: &mut ringbuf::HeapCons<MidiMessage>, engine: &mut Engine) {
+    while let Some(message) = messages.try_pop() {
+        let Some(sync) = SyncMessage::parse(message.bytes()) else { continue };
+        if sync.may_relocate() {
+            // Relocating rebuilds event sources
+            alloc_permit(|| engine.receive_sync(sync, message.micros));
+        } else {
+            engine.receive_sync(sync, message.micros);
+        }
+    }
+}
+
+/// Hold back the clock and time code produced by the last block, due
+/// `block_due` frames (plus their offset) into the output stream.
+fn queue_sync(engine: &mut Engine, block_due: u64, pending: &mut Vec<(u64, SyncMessage)>) {
+    engine.drain_sync_output(|offset, message| {
+        if pending.len() < pending.capacity() {
+            pending.push((block_due + offset as u64, message));
+        }
+    });
+}
+
+/// Send the held-back clock and time code due by frame `now`.
+fn send_sync(port: &mut Option<MidiOut>, pending: &mut Vec<(u64, SyncMessage)>, now: u64) {
+    let mut bytes = [0u8; 10];
+    for (due, message) in pending.iter() {
+        if *due > now {
+            continue;
+        }
+        let len = message.encode(&mut bytes);
+        if let Some(port) = port.as_mut() {
+            let _ = alloc_permit(|| port.send(&bytes[..len]));
+        }
+    }
+    pending.retain(|(due, _)| *due > now);
+}
+
+/// Hold back the messages MIDI Out nodes produced in the last block, due
+/// `block_due` frames (plus their offset) into the output stream.
+fn queue_midi(engine: &mut Engine, block_due: u64, pending: &mut Vec<(u64, MidiOutMessage)>) {
+    engine.drain_midi_output(|message| {
+        if pending.len() < pending.capacity() {
+            pending.push((block_due + message.offset as u64, message));
+        }
+    });
+}
+
+/// Send the held-back MIDI Out messages due by frame `now` to their nodes' ports.
+fn send_midi(engine: &Engine, ports: &mut [(String, MidiOut)], pending: &mut Vec<(u64, MidiOutMessage)>, now: u64) {
+    for (due, message) in pending.iter() {
+        if *due > now {
+            continue;
+        }
+        let Some(NodeType::MidiOut { port, .. }) = engine.song().graph.node(message.node).map(|n| &n.node_type) else {
+            continue;
+        };
+        if let Some((_, out)) = ports.iter_mut().find(|(name, _)| name == port) {
+            let _ = alloc_permit(|| out.send(&message.bytes));
+        }
     }
+    pending.retain(|(due, _)| *due > now);
 }
 
 /// Frames to render before the next position report, clamped to batch_size.
@@ -533,17 +2369,39 @@ mod tests {
     /// Build a controller with a simple song: 1 track, 2 clips (16 rows each), rpb=4.
     fn test_controller() -> Controller {
         let mut ctrl = Controller::new();
-        ctrl.new_song(4);
+        ctrl.new_song();
         ctrl.add_clip(0, 16); // clip 1 (clip 0 already created by new_song)
         ctrl
     }
 
+    #[test]
+    fn sync_messages_wait_for_their_frame() {
+        let mut engine = Engine::new(Song::with_channels("sync", 1), 44100);
+        engine.set_midi_clock_out(true);
+        engine.play();
+        engine.render_frames(4096);
+        let mut pending = Vec::with_capacity(SYNC_PENDING_CAPACITY);
+        queue_sync(&mut engine, 1000, &mut pending);
+        assert!(pending.windows(2).all(|w| w[0].0 <= w[1].0));
+        assert!(pending.last().unwrap().0 > 1000, "later pulses keep their offset");
+        send_sync(&mut None, &mut pending, 1000);
+        assert!(!pending.is_empty());
+        assert!(pending.iter().all(|&(due, _)| due > 1000));
+    }
+
+    #[test]
+    fn schedule_event_at_requires_playback() {
+        let mut ctrl = test_controller();
+        let payload = EventPayload::NoteOn { note: 48, velocity: 64, instrument: 1 };
+        assert!(!ctrl.schedule_event_at(MusicalTime::from_beats(1), EventTarget::Channel(0), payload));
+        assert_eq!(ctrl.next_grid_time(1), None);
+    }
+
     #[test]
     fn set_seq_entry_inserts_sorted() {
         let mut ctrl = test_controller();
         // Clip 0 is 64 rows at rpb=4 = 16 beats. Place clip 1 at beat 16 (after clip 0).
-        let result = ctrl.set_seq_entry(0, 16, 1);
-        assert!(result.is_some());
+        assert!(ctrl.set_seq_entry(0, 16, 1));
         let track = &ctrl.song().tracks[0];
         assert_eq!(track.sequence.len(), 2);
         assert_eq!(track.sequence[0].start.beat, 0);
@@ -555,8 +2413,7 @@ mod tests {
     fn remove_seq_entry_at_beat() {
         let mut ctrl = test_controller();
         // Remove the default entry at beat 0
-        let result = ctrl.remove_seq_entry(0, 0);
-        assert!(result.is_some());
+        assert!(ctrl.remove_seq_entry(0, 0));
         assert!(ctrl.song().tracks[0].sequence.is_empty());
     }
 
@@ -572,16 +2429,14 @@ mod tests {
         let mut ctrl = test_controller();
         // Clip 0 is 64 rows at beat 0 with rpb=4 = 16 beats.
         // Placing at beat 4 overlaps.
-        let result = ctrl.set_seq_entry(0, 4, 0);
-        assert!(result.is_none(), "Should reject overlapping placement");
+        assert!(!ctrl.set_seq_entry(0, 4, 0), "Should reject overlapping placement");
     }
 
     #[test]
     fn overlap_allows_adjacent() {
         let mut ctrl = test_controller();
         // Clip 1 is 16 rows = 4 beats. Placing right after clip 0 (beat 16) should work.
-        let result = ctrl.set_seq_entry(0, 16, 1);
-        assert!(result.is_some(), "Adjacent placement should succeed");
+        assert!(ctrl.set_seq_entry(0, 16, 1), "Adjacent placement should succeed");
     }
 
     #[test]
@@ -592,18 +2447,67 @@ mod tests {
         assert!(ctrl.song().tracks[0].sequence.is_empty());
 
         // Place clip 0 at beat 0
-        let (fwd, rev) = ctrl.set_seq_entry(0, 0, 0).unwrap();
+        assert!(ctrl.set_seq_entry(0, 0, 0));
         assert_eq!(ctrl.song().tracks[0].sequence.len(), 1);
 
-        // Undo (apply reverse)
-        ctrl.apply_edit(rev);
+        assert!(ctrl.undo());
         assert!(ctrl.song().tracks[0].sequence.is_empty());
 
-        // Redo (apply forward)
-        ctrl.apply_edit(fwd);
+        assert!(ctrl.redo());
         assert_eq!(ctrl.song().tracks[0].sequence.len(), 1);
     }
 
+    #[test]
+    fn set_clip_boundary_round_trip() {
+        let mut ctrl = test_controller();
+        assert!(ctrl.set_clip_boundary(0, 0, mb_ir::ClipBoundary::Cut));
+        assert_eq!(ctrl.seq_entry_at(0, 0).unwrap().boundary, mb_ir::ClipBoundary::Cut);
+
+        ctrl.undo();
+        assert_eq!(ctrl.seq_entry_at(0, 0).unwrap().boundary, mb_ir::ClipBoundary::Ring);
+
+        ctrl.redo();
+        assert_eq!(ctrl.seq_entry_at(0, 0).unwrap().boundary, mb_ir::ClipBoundary::Cut);
+        assert!(!ctrl.set_clip_boundary(0, 99, mb_ir::ClipBoundary::Release));
+    }
+
+    #[test]
+    fn undo_takes_back_structural_edits_step_by_step() {
+        let mut ctrl = test_controller();
+        let cell = mb_ir::Cell { note: Note::On(60), ..mb_ir::Cell::empty() };
+        ctrl.apply_edit(Edit::SetCell { track: 0, clip: 0, row: 1, column: 0, cell });
+        let state = |song: &Song| {
+            let clips: Vec<_> = song.tracks[0].clips.iter().map(|c| c.pattern().cloned()).collect();
+            (song.tracks.len(), song.graph.nodes.len(), clips, song.tracks[0].sequence.clone())
+        };
+        let before = state(ctrl.song());
+
+        ctrl.apply_edits([
+            Edit::DeleteRows { track: 0, clip: 0, row: 0, count: 2 },
+            Edit::ResizeClip { track: 0, clip: 0, rows: 8 },
+        ]);
+        ctrl.add_seq_entry(0, 1);
+        assert_eq!(ctrl.clone_clip(0, 1), Some(2));
+        let lead = ctrl.add_track(2, "Lead").unwrap();
+        assert!(ctrl.delete_track(lead));
+        // Playback settings aren't undo steps
+        ctrl.apply_edit(Edit::SetLoop { range: None });
+        let after = state(ctrl.song());
+
+        for _ in 0..5 {
+            assert!(ctrl.undo());
+        }
+        assert_eq!(state(ctrl.song()), before);
+        for _ in 0..5 {
+            assert!(ctrl.redo());
+        }
+        assert!(!ctrl.redo());
+        assert_eq!(state(ctrl.song()), after);
+
+        ctrl.new_song();
+        assert!(!ctrl.can_undo());
+    }
+
     #[test]
     fn seq_entry_at_beat_lookup() {
         let ctrl = test_controller();
@@ -613,6 +2517,525 @@ mod tests {
         assert!(ctrl.seq_entry_at(0, 99).is_none());
     }
 
+    #[test]
+    fn select_subsong_renders_only_that_subsong() {
+        let mut ctrl = Controller::new();
+        let mut song = Song::with_channels("test", 1);
+        let patterns = vec![mb_ir::Pattern::new(4, 1), mb_ir::Pattern::new(16, 1)];
+        let order = vec![
+            mb_ir::OrderEntry::Pattern(0), mb_ir::OrderEntry::End, mb_ir::OrderEntry::Pattern(1),
+        ];
+        mb_ir::build_tracks(&mut song, &patterns, &order);
+        ctrl.set_song(song);
+        assert_eq!(ctrl.subsongs().len(), 2);
+
+        let main_len = ctrl.render_frames(44100, 1_000_000).len();
+        assert!(ctrl.select_subsong(1));
+        let sub_len = ctrl.render_frames(44100, 1_000_000).len();
+        // 16-row subsong vs 4-row main song (within a few frames of end detection)
+        assert!(sub_len.abs_diff(main_len * 4) < 8, "main={} sub={}", main_len, sub_len);
+        assert!(!ctrl.select_subsong(2));
+    }
+
+    #[test]
+    fn row_time_offsets_from_seq_entry_start() {
+        let mut ctrl = test_controller();
+        ctrl.set_seq_entry(0, 16, 1);
+        assert_eq!(ctrl.row_time(0, 0, 8), Some(MusicalTime::from_beats(2)));
+        assert_eq!(ctrl.row_time(0, 1, 6), Some(MusicalTime::from_beats(17).add_rows(2, 4)));
+        assert_eq!(ctrl.row_time(0, 2, 0), None);
+    }
+
+    #[test]
+    fn set_loop_keeps_range_and_ignores_empty() {
+        let mut ctrl = test_controller();
+        let range = (MusicalTime::from_beats(1), MusicalTime::from_beats(3));
+        ctrl.set_loop(Some(range));
+        assert_eq!(ctrl.loop_range(), Some(range));
+        ctrl.set_loop(Some((range.1, range.0)));
+        assert_eq!(ctrl.loop_range(), None);
+    }
+
+    #[test]
+    fn autosave_snapshots_unsaved_changes_for_recovery() {
+        let dir = std::env::temp_dir().join(format!("mb-recovery-{}", std::process::id()));
+        let mut ctrl = test_controller();
+        ctrl.mark_saved();
+        ctrl.set_autosave(Some(dir.clone()), std::time::Duration::ZERO);
+        assert!(!ctrl.is_modified());
+        assert_eq!(ctrl.autosave().unwrap(), None);
+
+        ctrl.apply_edit(Edit::SetTempo { tempo: 9000 });
+        assert!(ctrl.is_modified());
+        let first = ctrl.autosave().unwrap().unwrap();
+        // Nothing new since the last snapshot
+        assert_eq!(ctrl.autosave().unwrap(), None);
+        for bpm in 91..95 {
+            ctrl.apply_edit(Edit::SetTempo { tempo: bpm * 100 });
+            ctrl.autosave().unwrap();
+        }
+        assert!(!first.exists());
+        let snapshots = recovery_snapshots(&dir).unwrap();
+        assert_eq!(snapshots.len(), 3);
+
+        let mut recovered = Controller::new();
+        recovered.restore_snapshot(&snapshots[0]).unwrap();
+        assert_eq!(recovered.song().initial_tempo, 9400);
+        assert!(recovered.is_modified());
+
+        ctrl.mark_saved();
+        assert!(!ctrl.is_modified());
+        assert!(recovery_snapshots(&dir).unwrap().is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn markers_set_playback_loops() {
+        let mut ctrl = test_controller();
+        let (verse, chorus) = (MusicalTime::from_beats(4), MusicalTime::from_beats(12));
+        ctrl.add_marker(chorus, "Chorus");
+        assert_eq!(ctrl.add_marker(verse, "Verse"), 0);
+        assert!(ctrl.rename_marker(1, "Hook"));
+        assert_eq!(ctrl.song().find_marker("Hook"), Some(1));
+
+        assert!(!ctrl.loop_markers(1, 0));
+        assert!(ctrl.loop_markers(0, 1));
+        assert_eq!(ctrl.loop_range(), Some((verse, chorus)));
+        assert!(ctrl.remove_marker(0));
+        assert!(!ctrl.play_from_marker(1));
+
+        assert!(ctrl.undo());
+        assert!(ctrl.undo());
+        assert_eq!(ctrl.song().find_marker("Chorus"), Some(1));
+        assert!(ctrl.undo());
+        assert!(ctrl.undo());
+        assert!(ctrl.song().markers.is_empty());
+    }
+
+    #[test]
+    fn stop_async_without_playback_is_already_stopped() {
+        let mut ctrl = test_controller();
+        ctrl.stop_async();
+        assert!(ctrl.is_stopped());
+        assert!(!ctrl.is_playing());
+    }
+
+    #[test]
+    fn preview_needs_a_tracker_track_and_a_note_to_release() {
+        let mut ctrl = test_controller();
+        assert!(!ctrl.preview_release());
+        assert!(!ctrl.preview_sample(200, false), "no such sample");
+        ctrl.song.tracks.clear();
+        assert!(!ctrl.preview_note(1, 48));
+        assert!(!ctrl.is_playing());
+    }
+
+    #[test]
+    fn previewed_samples_are_held_until_the_audio_thread_lets_go() {
+        let mut ctrl = test_controller();
+        let playing = Arc::new(mb_ir::SampleData::Mono16(vec![100, -200, 50]));
+        ctrl.previewed_samples.push(playing.clone());
+        ctrl.stop_sample_preview();
+        assert_eq!(ctrl.previewed_samples.len(), 1, "held for the audio thread");
+        drop(playing);
+        ctrl.stop_sample_preview();
+        assert!(ctrl.previewed_samples.is_empty());
+    }
+
+    #[test]
+    fn engine_is_built_counting_in() {
+        let ctrl = test_controller();
+        let setup = |count_in| EngineSetup {
+            render_threads: 0,
+            meters: ctrl.meters.clone(),
+            midi_clock_out: false,
+            mtc_out: false,
+            sync_follow: false,
+            session: false,
+            count_in,
+            limits: Limits::default(),
+        };
+        let mut engine = build_engine(ctrl.song.clone(), setup(1), MusicalTime::zero(), 44100);
+        assert!(engine.counting_in(), "counting in before the first block");
+        engine.render_block(&mut [[0.0; 2]; BLOCK_SIZE]);
+        assert_eq!(engine.position(), MusicalTime::zero());
+        assert!(!build_engine(ctrl.song.clone(), setup(0), MusicalTime::zero(), 44100).counting_in());
+    }
+
+    #[test]
+    fn pause_needs_playback() {
+        let mut ctrl = test_controller();
+        ctrl.pause();
+        assert!(!ctrl.is_paused());
+        ctrl.resume();
+        assert!(!ctrl.is_playing());
+    }
+
+    #[test]
+    fn launching_clips_needs_playback() {
+        let mut ctrl = test_controller();
+        assert!(!ctrl.launch_clip(0, Some(0), LaunchQuantize::Bar));
+        assert_eq!(ctrl.launched_position(0), None);
+    }
+
+    #[test]
+    fn channel_mute_and_solo_update_song() {
+        let mut ctrl = test_controller();
+        ctrl.set_channel_muted(1, true);
+        ctrl.set_channel_solo(2, true);
+        assert!(ctrl.song().channels[1].muted);
+        assert!(ctrl.song().channels[2].solo);
+        ctrl.set_channel_solo(2, false);
+        assert!(!ctrl.song().channels[2].solo);
+    }
+
+    #[test]
+    fn realtime_edits_update_song() {
+        let mut ctrl = test_controller();
+        ctrl.apply_edit(Edit::SetTempo { tempo: 14050 });
+        ctrl.apply_edit(Edit::SetChannelPan { channel: 0, pan: 100 });
+        ctrl.apply_edit(Edit::SetNodeParam { node: 1, param: 0, value: 2000 });
+        assert_eq!(ctrl.song().initial_tempo, 14050);
+        assert_eq!(ctrl.song().channels[0].initial_pan, 64);
+        assert_eq!(ctrl.song().graph.nodes[1].parameters[0].value, 2000);
+    }
+
+    #[test]
+    fn graph_edits_update_song() {
+        let mut ctrl = test_controller();
+        let synth = NodeType::Machine { machine_name: "Synth".into(), is_tracker: false };
+        let id = ctrl.add_node(synth);
+        assert_eq!(id as usize, ctrl.song().graph.nodes.len() - 1);
+        assert!(!ctrl.song().graph.nodes[id as usize].parameters.is_empty());
+        assert!(ctrl.connect(id, 0, 0));
+        assert!(!ctrl.connect(0, id, 0), "would close a loop");
+        assert!(ctrl.set_connection_gain(id, 0, 50));
+        assert!(ctrl.set_connection_pan(id, 0, -20));
+        assert_eq!(ctrl.song().graph.connection(id, 0).map(|c| (c.gain, c.pan)), Some((50, -20)));
+        assert!(ctrl.disconnect(id, 0));
+        assert!(!ctrl.disconnect(id, 0));
+        let tracker = ctrl.song().tracks[0].machine_node.unwrap();
+        assert!(ctrl.remove_node(tracker));
+        assert_eq!(ctrl.song().tracks[0].machine_node, None);
+        assert!(!ctrl.remove_node(0));
+    }
+
+    #[test]
+    fn stop_recording_without_recording_adds_nothing() {
+        let mut ctrl = test_controller();
+        let instruments = ctrl.song().instruments.len();
+        assert!(!ctrl.is_recording());
+        assert_eq!(ctrl.stop_recording(), None);
+        assert_eq!(ctrl.song().instruments.len(), instruments);
+    }
+
+    #[test]
+    fn record_note_needs_recording_and_playback() {
+        let mut ctrl = test_controller();
+        assert_eq!(ctrl.record_note(0, 0, Note::On(60), 1), None);
+        ctrl.set_note_recording(true);
+        assert!(ctrl.note_recording());
+        assert_eq!(ctrl.record_note(0, 0, Note::On(60), 1), None, "not playing");
+    }
+
+    #[test]
+    fn recorded_note_lands_on_nearest_row_and_keeps_effects() {
+        let mut ctrl = test_controller();
+        let rpb = ctrl.song().rows_per_beat as u32;
+        let clip = ctrl.song().tracks[0].sequence[0].clip_idx as usize;
+        let effect = mb_ir::Effect::SetSpeed(3);
+        ctrl.song.tracks[0].clips[clip].pattern_mut().unwrap().cell_mut(2, 1).effect = effect;
+        // Just before row 2: rounds forward
+        let time = MusicalTime::from_sub_beats(MusicalTime::zero().add_rows(2, rpb).as_sub_beats() - 1);
+        let pos = ctrl.record_note_at(time, 0, 1, Note::On(60), 3).unwrap();
+        assert_eq!((pos.clip_idx as usize, pos.row), (clip, 2));
+        let cell = ctrl.song().tracks[0].clips[clip].pattern().unwrap().cell(2, 1);
+        assert_eq!((cell.note, cell.instrument, cell.effect), (Note::On(60), 3, effect));
+        assert!(ctrl.record_note_at(time, 0, 200, Note::Off, 0).is_none(), "no such column");
+    }
+
+    #[test]
+    fn song_length_follows_tempo() {
+        let mut ctrl = test_controller();
+        let length = ctrl.song_length_seconds();
+        assert!(length > 0.0);
+        let clip = ctrl.song().tracks[0].sequence[0].clip_idx as usize;
+        let tempo = ctrl.song().initial_tempo;
+        let pattern = ctrl.song.tracks[0].clips[clip].pattern_mut().unwrap();
+        pattern.cell_mut(0, 0).effect = mb_ir::Effect::SetTempo((tempo * 2 / 100) as u8);
+        assert!((ctrl.song_length_seconds() * 2.0 - length).abs() < 1e-9);
+        assert_eq!(ctrl.time_at(MusicalTime::zero()), 0.0);
+    }
+
+    #[test]
+    fn sequence_edits_by_position() {
+        let mut ctrl = test_controller();
+        let clips = |c: &Controller| c.song().tracks[0].sequence.iter().map(|e| e.clip_idx).collect::<Vec<_>>();
+        assert_eq!(clips(&ctrl), [0]);
+        assert!(ctrl.insert_seq_entry(0, 0, 1));
+        assert!(ctrl.duplicate_seq_entry(0, 1));
+        assert_eq!(clips(&ctrl), [1, 0, 0]);
+        assert!(ctrl.move_seq_entry(0, 0, 2));
+        assert!(ctrl.set_seq_clip(0, 0, 1));
+        assert_eq!(clips(&ctrl), [1, 0, 1]);
+        assert!(ctrl.delete_seq_entry(0, 1));
+        assert_eq!(clips(&ctrl), [1, 1]);
+        let seq = &ctrl.song().tracks[0].sequence;
+        assert!(seq[0].start < seq[1].start);
+        assert!(!ctrl.set_seq_clip(0, 0, 9));
+        assert!(!ctrl.delete_seq_entry(3, 0));
+    }
+
+    #[test]
+    fn clip_edits_reshape_clips() {
+        let mut ctrl = test_controller();
+        ctrl.song.tracks[0].clips[1].pattern_mut().unwrap().cell_mut(0, 0).note = Note::On(60);
+        assert!(ctrl.insert_rows(0, 1, 0, 2));
+        assert!(ctrl.delete_rows(0, 1, 0, 1));
+        let copy = ctrl.clone_clip(0, 1).unwrap();
+        assert!(ctrl.resize_clip(0, copy, 4));
+        let pattern = ctrl.song().tracks[0].get_pattern_at(copy as usize).unwrap();
+        assert_eq!((pattern.rows, pattern.cell(1, 0).note), (4, Note::On(60)));
+        assert_eq!(ctrl.song().tracks[0].get_pattern_at(1).unwrap().rows, 16);
+        assert_eq!(ctrl.clone_clip(0, 99), None);
+        assert!(!ctrl.insert_rows(0, 99, 0, 1));
+    }
+
+    #[test]
+    fn track_edits_manage_tracks() {
+        let mut ctrl = test_controller();
+        let tracks = ctrl.song().tracks.len();
+        let nodes = ctrl.song().graph.nodes.len();
+        let pad = ctrl.add_track(2, "Pad").unwrap();
+        assert_eq!(pad, tracks);
+        assert!(ctrl.rename_track(pad, "Strings"));
+        assert!(ctrl.move_track(pad, 0));
+        assert_eq!(ctrl.song().tracks[0].name.as_str(), "Strings");
+        assert_eq!(ctrl.song().graph.nodes.len(), nodes + 1);
+        assert!(ctrl.delete_track(0));
+        assert_eq!((ctrl.song().tracks.len(), ctrl.song().graph.nodes.len()), (tracks, nodes));
+        assert!(!ctrl.delete_track(tracks));
+        assert!(!ctrl.rename_track(tracks, "x"));
+
+        let midi = ctrl.add_midi_track(1, "Juno", "USB MIDI", 2).unwrap();
+        let node = ctrl.song().tracks[midi].machine_node.unwrap();
+        let node_type = &ctrl.song().graph.node(node).unwrap().node_type;
+        assert_eq!(*node_type, NodeType::MidiOut { port: "USB MIDI".into(), channel: 2 });
+    }
+
+    #[test]
+    fn interpolate_ramps_selected_volumes() {
+        let mut ctrl = test_controller();
+        let volume = |v| mb_ir::Cell { volume: mb_ir::VolumeCommand::Volume(v), ..mb_ir::Cell::empty() };
+        ctrl.apply_edit(Edit::SetCell { track: 0, clip: 1, row: 0, column: 0, cell: volume(10) });
+        ctrl.apply_edit(Edit::SetCell { track: 0, clip: 1, row: 3, column: 0, cell: volume(40) });
+        let sel = CellSelection::column(0, 1, 0, 0, 3);
+        assert_eq!(ctrl.interpolate(&sel, CellField::Volume, CurveKind::Linear).len(), 2);
+        let pattern = ctrl.song().tracks[0].get_pattern_at(1).unwrap();
+        assert_eq!(pattern.cell(2, 0).volume, mb_ir::VolumeCommand::Volume(30));
+        assert!(ctrl.interpolate(&sel, CellField::Effect, CurveKind::Linear).is_empty());
+    }
+
+    #[test]
+    fn transpose_clip_shifts_notes_by_octave() {
+        let mut ctrl = test_controller();
+        let cell = mb_ir::Cell { note: Note::On(60), ..mb_ir::Cell::empty() };
+        ctrl.apply_edit(Edit::SetCell { track: 0, clip: 1, row: 5, column: 0, cell });
+        let edits = ctrl.transpose_clip(0, 1, -12);
+        assert_eq!(edits.len(), 1);
+        let pattern = ctrl.song().tracks[0].get_pattern_at(1).unwrap();
+        assert_eq!(pattern.cell(5, 0).note, Note::On(48));
+        assert!(matches!(edits[0], Edit::SetCell { row: 5, cell: mb_ir::Cell { note: Note::On(48), .. }, .. }));
+        assert!(ctrl.undo());
+        assert_eq!(ctrl.song().tracks[0].get_pattern_at(1).unwrap().cell(5, 0).note, Note::On(60));
+        assert!(ctrl.transpose_clip(0, 99, 1).is_empty());
+    }
+
+    #[test]
+    fn load_sample_picks_the_format_loader() {
+        let mut ctrl = test_controller();
+        let raw = RawFormat { sample_rate: 11025, format: SampleFormat::Mono16, unsigned: false, big_endian: true };
+        let inst = ctrl.load_sample(&[0x01, 0x00, 0xFF, 0xFF], "raw", SampleFileFormat::Raw(raw)).unwrap();
+        let sample = &ctrl.song().samples[inst as usize - 1];
+        assert_eq!((sample.len(), sample.c4_speed, sample.data.get_mono(0)), (2, 11025, 256));
+        assert!(ctrl.load_sample(&[0; 16], "junk", SampleFileFormat::Aiff).is_err());
+        let path = std::path::Path::new("kit/Snare.AIF");
+        assert_eq!(SampleFileFormat::from_path(path), Some(SampleFileFormat::Aiff));
+        assert_eq!(SampleFileFormat::from_path(std::path::Path::new("snare.raw")), None);
+    }
+
+    #[test]
+    fn export_song_round_trips_through_load_song() {
+        let ctrl = test_controller();
+        let project = ctrl.export_song(SongFileFormat::Project).unwrap();
+        assert!(project.warnings.is_empty());
+        let mut other = Controller::new();
+        other.load_song(&project.data, SongFileFormat::Project).unwrap();
+        assert_eq!(other.song().tracks[0].clips.len(), 2);
+        assert!(matches!(ctrl.export_song(SongFileFormat::It), Err(FormatError::Unsupported(_))));
+        let path = std::path::Path::new("tunes/Intro.MIDI");
+        assert_eq!(SongFileFormat::from_path(path), Some(SongFileFormat::Midi));
+        assert_eq!(SongFileFormat::from_path(std::path::Path::new("intro.xm")), None);
+    }
+
+    #[test]
+    fn edit_sample_changes_song_sample() {
+        let mut ctrl = test_controller();
+        let mut sample = mb_ir::Sample::new("hit");
+        sample.set_data(mb_ir::SampleData::Mono16(vec![100, -200, 50]));
+        ctrl.add_sample_instrument(sample).unwrap();
+        assert!(ctrl.edit_sample(0, SampleOp::Reverse { start: 0, end: 3 }));
+        assert!(ctrl.edit_sample(0, SampleOp::Normalize));
+        assert_eq!(ctrl.song().samples[0].data.get_mono(1), -32768);
+        assert!(!ctrl.edit_sample(1, SampleOp::Normalize));
+    }
+
+    #[test]
+    fn slice_sample_adds_instruments_and_clip() {
+        let mut ctrl = test_controller();
+        let mut sample = mb_ir::Sample::new("break");
+        sample.set_data(mb_ir::SampleData::Mono8(vec![64; 800]));
+        let first = ctrl.add_sample_instrument(sample).unwrap();
+        let slices = ctrl.slice_sample(0, SliceMode::Equal(4), Some((0, 8))).unwrap();
+        assert_eq!(slices.instruments, [first + 1, first + 2, first + 3, first + 4]);
+        assert_eq!(ctrl.song().samples[1].len(), 200);
+        let clip = slices.clip.unwrap();
+        let pattern = ctrl.song().tracks[0].get_pattern_at(clip as usize).unwrap();
+        assert_eq!((pattern.cell(2, 0).instrument, pattern.cell(6, 0).instrument), (first + 2, first + 4));
+        assert_eq!(ctrl.slice_sample(0, SliceMode::Equal(2), None).unwrap().clip, None);
+        assert!(ctrl.slice_sample(99, SliceMode::Equal(2), None).is_none());
+    }
+
+    #[test]
+    fn sample_edits_undo_one_step_at_a_time() {
+        let mut ctrl = test_controller();
+        let clips = ctrl.song().tracks[0].clips.len();
+        let mut sample = mb_ir::Sample::new("break");
+        sample.set_data(mb_ir::SampleData::Mono8((0..800).map(|i| (i % 100) as i8).collect()));
+        ctrl.add_sample_instrument(sample).unwrap();
+        let loaded = ctrl.song().samples[0].clone();
+        ctrl.slice_sample(0, SliceMode::Equal(4), Some((0, 8))).unwrap();
+        assert!(ctrl.edit_sample(0, SampleOp::Reverse { start: 0, end: 800 }));
+
+        assert!(ctrl.undo());
+        assert_eq!(ctrl.song().samples[0], loaded);
+        assert!(ctrl.undo());
+        assert_eq!((ctrl.song().samples.len(), ctrl.song().instruments.len()), (1, 1));
+        assert_eq!(ctrl.song().tracks[0].clips.len(), clips);
+        assert!(ctrl.undo());
+        assert!(ctrl.song().samples.is_empty());
+        assert!(ctrl.redo());
+        assert_eq!(ctrl.song().samples[0], loaded);
+    }
+
+    #[test]
+    fn auto_loop_sets_forward_loop() {
+        let mut ctrl = test_controller();
+        let mut sample = mb_ir::Sample::new("pad");
+        let wave = (0..4000).map(|i| if (i / 50) % 2 == 0 { 1000 } else { -1000 }).collect();
+        sample.set_data(mb_ir::SampleData::Mono16(wave));
+        ctrl.add_sample_instrument(sample).unwrap();
+        let (start, end) = ctrl.auto_loop(0, 1000, 32).unwrap();
+        assert_eq!((end - start) % 100, 0);
+        let sample = &ctrl.song().samples[0];
+        assert_eq!((sample.loop_start, sample.loop_end, sample.loop_type), (start, end, mb_ir::LoopType::Forward));
+        assert_eq!(ctrl.auto_loop(0, 10_000, 0), None);
+    }
+
+    #[test]
+    fn stems_match_master_length_and_solo_tracks() {
+        let mut ctrl = Controller::new();
+        ctrl.set_song(mb_ir::SongTemplate { tracks: 2, rows: 8, ..mb_ir::SongTemplate::default() }.build());
+        ctrl.rename_track(1, "Bass");
+        let stems = ctrl.render_stems(8000, 10);
+        let names: Vec<_> = stems.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["Master", "Track 1", "Bass"]);
+        assert!(stems.iter().all(|s| s.wav.len() == stems[0].wav.len()));
+        assert_ne!(stems[1].wav, stems[0].wav, "stem is not the full mix");
+        // A limit past u32::MAX frames still renders the song
+        let long = ctrl.render_stems(192_000, 30_000);
+        assert!(long[0].wav.len() > stems[0].wav.len());
+    }
+
+    #[test]
+    fn render_to_file_takes_limits_past_u32_frames() {
+        let mut ctrl = Controller::new();
+        ctrl.set_song(mb_ir::SongTemplate { rows: 8, ..mb_ir::SongTemplate::default() }.build());
+        let dir = std::env::temp_dir().join(format!("mb-render-to-file-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("long.wav");
+        let frames = ctrl.render_to_file(&path, ExportFormat::Wav(WavSpec::default()), 192_000, 12 * 3600).unwrap();
+        assert!(frames > 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn renders_stop_at_the_controller_limits() {
+        let mut ctrl = Controller::new();
+        ctrl.set_song(mb_ir::SongTemplate { rows: 64, ..mb_ir::SongTemplate::default() }.build());
+        let full = ctrl.render_frames(8000, usize::MAX).len();
+        ctrl.set_limits(Limits { max_beats: 2, ..Limits::default() });
+        assert_eq!(ctrl.limits().max_beats, 2);
+        assert!(ctrl.render_frames(8000, usize::MAX).len() < full);
+        let mut renderer = ctrl.renderer(8000, 512, usize::MAX);
+        while renderer.next_chunk().is_some() {}
+        assert_eq!(renderer.limit_hit(), Some(LimitHit::Length));
+    }
+
+    #[test]
+    fn metronome_settings_persist_without_playback() {
+        let mut ctrl = test_controller();
+        assert!(!ctrl.metronome());
+        ctrl.set_metronome(true);
+        ctrl.set_metronome_gain(150);
+        assert!(ctrl.metronome());
+        assert_eq!(ctrl.metronome_gain(), 100);
+    }
+
+    #[test]
+    fn meters_read_silence_when_stopped() {
+        let mut ctrl = test_controller();
+        ctrl.set_render_threads(2);
+        assert_eq!(ctrl.render_threads(), 2);
+        assert_eq!(ctrl.node_meter(0), Some(MeterReading::default()));
+        assert_eq!(ctrl.node_meter(METERED_NODES as u16), None);
+        let mut scope = [1.0f32; SCOPE_FRAMES + 8];
+        assert_eq!(ctrl.node_scope(0, &mut scope), SCOPE_FRAMES);
+        assert!(scope[..SCOPE_FRAMES].iter().all(|&s| s == 0.0));
+        let spectrum = ctrl.master_spectrum();
+        assert_eq!(spectrum.len(), SPECTRUM_SIZE / 2);
+        assert!(spectrum.iter().all(|&db| db == SPECTRUM_FLOOR_DB));
+    }
+
+    #[test]
+    fn record_offset_persists_without_playback() {
+        let mut ctrl = test_controller();
+        ctrl.set_record_offset_ms(25);
+        assert_eq!(ctrl.record_offset_ms(), 25);
+        assert!(ctrl.record_position().is_none());
+    }
+
+    #[test]
+    fn record_clock_rewinds_by_latency_and_offset() {
+        let mut engine = Engine::new(test_controller().song().clone(), 44100);
+        engine.schedule_song();
+        engine.play();
+        // 125 BPM, speed 6, rpb 4: one beat = 21168 frames
+        engine.render_frames(21168 * 2);
+
+        let clock = RecordClock {
+            time: Arc::new(AtomicU64::new(0)),
+            offset_ms: Arc::new(AtomicI32::new(0)),
+        };
+        clock.publish(&engine, 21168, 44100);
+        assert_eq!(unpack_time(clock.time.load(Ordering::Relaxed)), mb_ir::MusicalTime::from_beats(1));
+
+        // 480 ms = one beat at 125 BPM on top of one beat of latency
+        clock.offset_ms.store(480, Ordering::Relaxed);
+        clock.publish(&engine, 21168, 44100);
+        assert_eq!(unpack_time(clock.time.load(Ordering::Relaxed)), mb_ir::MusicalTime::zero());
+    }
+
     #[test]
     fn would_overlap_no_conflict() {
         let mut track = mb_ir::Track::new(None, 0, 4);
@@ -622,6 +3045,7 @@ mod tests {
             clip_idx: 0,
             length: 16,
             termination: mb_ir::SeqTermination::Natural,
+            boundary: mb_ir::ClipBoundary::Ring,
         });
         // Place after the first clip ends (beat 4 with rpb=4)
         assert!(!would_overlap(&track, 4, 16, 4));
@@ -636,6 +3060,7 @@ mod tests {
             clip_idx: 0,
             length: 16,
             termination: mb_ir::SeqTermination::Natural,
+            boundary: mb_ir::ClipBoundary::Ring,
         });
         // Place overlapping the first clip
         assert!(would_overlap(&track, 2, 16, 4));
@@ -0,0 +1,245 @@
+//! New-song preferences, persisted as a small `key = value` config file.
+//!
+//! Unknown keys and unparsable values are skipped with a warning, so a
+//! config written by a newer version still loads.
+
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+use mb_ir::{build_tracks, ChannelSettings, OrderEntry, Pattern, Song};
+
+/// Initial channel panning for new songs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PanLayout {
+    /// Hard L R R L, as on the Amiga.
+    #[default]
+    Amiga,
+    /// Every channel centered.
+    Center,
+    /// Spread evenly from hard left to hard right.
+    Spread,
+}
+
+impl PanLayout {
+    fn name(self) -> &'static str {
+        match self {
+            PanLayout::Amiga => "amiga",
+            PanLayout::Center => "center",
+            PanLayout::Spread => "spread",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "amiga" => Some(PanLayout::Amiga),
+            "center" => Some(PanLayout::Center),
+            "spread" => Some(PanLayout::Spread),
+            _ => None,
+        }
+    }
+
+    /// Pan (-64..=64) of channel `i` out of `count`.
+    pub fn pan(self, i: u8, count: u8) -> i8 {
+        match self {
+            PanLayout::Amiga => match i % 4 { 0 | 3 => -64, _ => 64 },
+            PanLayout::Center => 0,
+            PanLayout::Spread if count < 2 => 0,
+            PanLayout::Spread => (-64 + 128 * i as i32 / (count as i32 - 1)) as i8,
+        }
+    }
+}
+
+/// Defaults applied to new songs and exports.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Preferences {
+    /// Tracker channels (1-32).
+    pub channels: u8,
+    /// Rows in the initial pattern (1-256).
+    pub rows_per_pattern: u16,
+    /// Initial tempo in BPM (32-255).
+    pub tempo: u8,
+    /// Initial speed in ticks per row (1-31).
+    pub speed: u8,
+    /// Initial channel panning.
+    pub pan_layout: PanLayout,
+    /// Sample rate for WAV export.
+    pub export_sample_rate: u32,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            channels: 4,
+            rows_per_pattern: 64,
+            tempo: 125,
+            speed: 6,
+            pan_layout: PanLayout::Amiga,
+            export_sample_rate: 44100,
+        }
+    }
+}
+
+impl Preferences {
+    /// Default config location: `$XDG_CONFIG_HOME/masterblaster/preferences.conf`,
+    /// falling back to `~/.config`.
+    pub fn default_path() -> Option<PathBuf> {
+        let base = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+        Some(base.join("masterblaster").join("preferences.conf"))
+    }
+
+    /// Directory machine plugins are loaded from: `machines` next to the
+    /// default config file.
+    pub fn plugin_dir() -> Option<PathBuf> {
+        Some(Self::default_path()?.with_file_name("machines"))
+    }
+
+    /// Directory autosave writes recovery snapshots to: `recovery` next to
+    /// the default config file.
+    pub fn recovery_dir() -> Option<PathBuf> {
+        Some(Self::default_path()?.with_file_name("recovery"))
+    }
+
+    /// Load from the default path, or defaults if there is no readable config.
+    pub fn load_or_default() -> Self {
+        Self::default_path()
+            .and_then(|p| Self::load(&p).ok())
+            .unwrap_or_default()
+    }
+
+    /// Load from `path`.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Write to `path`, creating parent directories as needed.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_config_string())
+    }
+
+    /// Parse config text. Missing keys keep their defaults.
+    pub fn parse(text: &str) -> Self {
+        let mut prefs = Self::default();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                eprintln!("[prefs] WARNING: ignoring malformed line \"{}\"", line);
+                continue;
+            };
+            if !prefs.set(key.trim(), value.trim()) {
+                eprintln!("[prefs] WARNING: ignoring {} = {}", key.trim(), value.trim());
+            }
+        }
+        prefs
+    }
+
+    /// Set one key from its config-file value. Returns false if the key is
+    /// unknown or the value is out of range.
+    pub fn set(&mut self, key: &str, value: &str) -> bool {
+        fn ranged<T: std::str::FromStr + PartialOrd>(v: &str, lo: T, hi: T) -> Option<T> {
+            v.parse().ok().filter(|n| *n >= lo && *n <= hi)
+        }
+        match key {
+            "channels" => ranged(value, 1, 32).map(|v| self.channels = v),
+            "rows_per_pattern" => ranged(value, 1, 256).map(|v| self.rows_per_pattern = v),
+            "tempo" => ranged(value, 32, 255).map(|v| self.tempo = v),
+            "speed" => ranged(value, 1, 31).map(|v| self.speed = v),
+            "pan_layout" => PanLayout::from_name(value).map(|v| self.pan_layout = v),
+            "export_sample_rate" => ranged(value, 8000, 192_000).map(|v| self.export_sample_rate = v),
+            _ => None,
+        }
+        .is_some()
+    }
+
+    /// Serialize in the format read by `parse`.
+    pub fn to_config_string(&self) -> String {
+        format!(
+            "# masterblaster new-song preferences\n\
+             channels = {}\n\
+             rows_per_pattern = {}\n\
+             tempo = {}\n\
+             speed = {}\n\
+             pan_layout = {}\n\
+             export_sample_rate = {}\n",
+            self.channels,
+            self.rows_per_pattern,
+            self.tempo,
+            self.speed,
+            self.pan_layout.name(),
+            self.export_sample_rate,
+        )
+    }
+
+    /// Build an empty song with one pattern, using these settings.
+    pub fn new_song(&self) -> Song {
+        let mut song = Song::with_channels("Untitled", self.channels);
+        song.initial_tempo = self.tempo as u32 * 100;
+        song.initial_speed = self.speed;
+        for (i, ch) in song.channels.iter_mut().enumerate() {
+            *ch = ChannelSettings {
+                initial_pan: self.pan_layout.pan(i as u8, self.channels),
+                ..*ch
+            };
+        }
+        let patterns = vec![Pattern::new(self.rows_per_pattern, self.channels)];
+        build_tracks(&mut song, &patterns, &[OrderEntry::Pattern(0)]);
+        song
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_round_trips() {
+        let prefs = Preferences {
+            channels: 8,
+            rows_per_pattern: 128,
+            tempo: 140,
+            speed: 3,
+            pan_layout: PanLayout::Spread,
+            export_sample_rate: 48000,
+        };
+        assert_eq!(Preferences::parse(&prefs.to_config_string()), prefs);
+    }
+
+    #[test]
+    fn invalid_and_unknown_keys_keep_defaults() {
+        let prefs = Preferences::parse("channels = 99\ntempo = fast\ncolor = red\nno equals sign\nspeed=4");
+        assert_eq!(prefs, Preferences { speed: 4, ..Preferences::default() });
+    }
+
+    #[test]
+    fn new_song_applies_settings() {
+        let prefs = Preferences {
+            channels: 3,
+            rows_per_pattern: 32,
+            tempo: 150,
+            speed: 4,
+            pan_layout: PanLayout::Spread,
+            ..Preferences::default()
+        };
+        let song = prefs.new_song();
+        assert_eq!((song.initial_tempo, song.initial_speed), (15000, 4));
+        let pans: Vec<i8> = song.channels.iter().map(|c| c.initial_pan).collect();
+        assert_eq!(pans, [-64, 0, 64]);
+        assert_eq!(song.tracks[0].clips[0].pattern().unwrap().rows, 32);
+        assert_eq!(song.tracks[0].num_channels, 3);
+    }
+
+    #[test]
+    fn save_and_load_from_disk() {
+        let path = env::temp_dir().join(format!("mb-prefs-{}", std::process::id())).join("prefs.conf");
+        let prefs = Preferences { tempo: 99, ..Preferences::default() };
+        prefs.save(&path).unwrap();
+        assert_eq!(Preferences::load(&path).unwrap(), prefs);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,209 @@
+//! Live sampling: capture an input device into a new song sample.
+//!
+//! The device callback fills a ring buffer; a capture thread drains it
+//! into memory and forwards a copy to the monitor ring, which the audio
+//! thread plays through the graph's AudioInput nodes. The copy is
+//! resampled to the playback's rate when the input device runs at another.
+
+use mb_audio::{AudioInput, CpalInput};
+use mb_ir::{Sample, SampleData};
+use ringbuf::traits::{Consumer, Observer, Producer};
+use ringbuf::{HeapCons, HeapProd};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Interleaved samples moved per capture-thread pass.
+const CHUNK: usize = 1024;
+
+/// How long the capture thread sleeps when the input ring is empty.
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+/// What the capture thread hands back when it stops.
+struct Captured {
+    frames: Vec<[f32; 2]>,
+    monitor: HeapProd<f32>,
+}
+
+/// A recording in progress.
+pub(crate) struct Recording {
+    input: CpalInput,
+    name: String,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Captured>>,
+}
+
+impl Recording {
+    /// Start capturing from an already opened input, monitoring at the
+    /// rate `output_rate` holds.
+    pub(crate) fn start(
+        input: CpalInput,
+        ring: HeapCons<f32>,
+        monitor: HeapProd<f32>,
+        output_rate: Arc<AtomicU32>,
+        name: &str,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = stop.clone();
+        let resampler = MonitorResampler::new(input.sample_rate());
+        let thread = std::thread::spawn(move || capture_loop(ring, monitor, resampler, &output_rate, &flag));
+        Self { input, name: name.to_string(), stop, thread: Some(thread) }
+    }
+
+    /// Stop capturing and return the recorded sample (if any audio arrived)
+    /// along with the monitor ring's producer.
+    ///
+    /// Returns `None` for the producer only if the capture thread panicked.
+    pub(crate) fn finish(mut self) -> (Option<Sample>, Option<HeapProd<f32>>) {
+        let _ = self.input.stop();
+        self.stop.store(true, Ordering::Relaxed);
+        let Some(Ok(captured)) = self.thread.take().map(JoinHandle::join) else { return (None, None) };
+        let sample = captured_sample(&self.name, &captured.frames, self.input.sample_rate());
+        (sample, Some(captured.monitor))
+    }
+}
+
+impl Drop for Recording {
+    /// Let an abandoned capture thread exit.
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Drain `ring` into memory until `stop` is set, copying to `monitor` at
+/// the rate `output_rate` holds.
+///
+/// Monitor frames that don't fit are dropped; nothing plays them while
+/// playback is stopped.
+fn capture_loop(
+    mut ring: HeapCons<f32>,
+    mut monitor: HeapProd<f32>,
+    mut resampler: MonitorResampler,
+    output_rate: &AtomicU32,
+    stop: &AtomicBool,
+) -> Captured {
+    let mut frames = Vec::new();
+    let mut buf = [0.0f32; CHUNK];
+    let mut resampled = Vec::new();
+    loop {
+        let stopping = stop.load(Ordering::Relaxed);
+        // Whole frames only, so the sides never swap
+        let n = ring.occupied_len().min(CHUNK) & !1;
+        if n == 0 {
+            if stopping {
+                break;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+            continue;
+        }
+        ring.pop_slice(&mut buf[..n]);
+        frames.extend(buf[..n].chunks_exact(2).map(|f| [f[0], f[1]]));
+        resampler.process(&buf[..n], output_rate.load(Ordering::Relaxed), &mut resampled);
+        let room = monitor.vacant_len().min(resampled.len()) & !1;
+        monitor.push_slice(&resampled[..room]);
+    }
+    Captured { frames, monitor }
+}
+
+/// Streaming linear resampler for the monitor copy, so the input keeps its
+/// pitch when the device and the playback run at different rates.
+struct MonitorResampler {
+    /// Input device rate
+    from: u32,
+    /// Where the next output frame falls after `prev`, in input frames
+    pos: f64,
+    /// Last input frame seen
+    prev: [f32; 2],
+}
+
+impl MonitorResampler {
+    fn new(from: u32) -> Self {
+        Self { from, pos: 0.0, prev: [0.0; 2] }
+    }
+
+    /// Replace `out` with the interleaved `input` at `to` Hz; copies it
+    /// as it is when the rates match or no playback has set one (0).
+    fn process(&mut self, input: &[f32], to: u32, out: &mut Vec<f32>) {
+        out.clear();
+        if to == 0 || to == self.from {
+            out.extend_from_slice(input);
+        } else {
+            let step = self.from as f64 / to as f64;
+            for frame in input.chunks_exact(2) {
+                while self.pos < 1.0 {
+                    let t = self.pos as f32;
+                    out.extend([0, 1].map(|i| self.prev[i] + (frame[i] - self.prev[i]) * t));
+                    self.pos += step;
+                }
+                self.pos -= 1.0;
+                self.prev = [frame[0], frame[1]];
+            }
+        }
+        if let Some(last) = input.rchunks_exact(2).next() {
+            self.prev = [last[0], last[1]];
+        }
+    }
+}
+
+/// A 16-bit stereo sample playing `frames` at their recorded rate.
+fn captured_sample(name: &str, frames: &[[f32; 2]], sample_rate: u32) -> Option<Sample> {
+    if frames.is_empty() {
+        return None;
+    }
+    let to_i16 = |v: f32| (v.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+    let left = frames.iter().map(|f| to_i16(f[0])).collect();
+    let right = frames.iter().map(|f| to_i16(f[1])).collect();
+    let mut sample = Sample::new(name);
+    sample.set_data(SampleData::Stereo16(left, right));
+    sample.c4_speed = sample_rate;
+    Some(sample)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ringbuf::traits::Split;
+    use ringbuf::HeapRb;
+
+    #[test]
+    fn capture_loop_keeps_everything_and_monitors_what_fits() {
+        let (mut input_tx, input_rx) = HeapRb::<f32>::new(64).split();
+        let (monitor_tx, mut monitor_rx) = HeapRb::<f32>::new(4).split();
+        input_tx.push_slice(&[0.1, 0.2, 0.3, 0.4, 0.5, 0.6]);
+        let stop = AtomicBool::new(true);
+        let captured = capture_loop(input_rx, monitor_tx, MonitorResampler::new(44100), &AtomicU32::new(44100), &stop);
+        assert_eq!(captured.frames, [[0.1, 0.2], [0.3, 0.4], [0.5, 0.6]]);
+        let mut monitored = [0.0; 4];
+        assert_eq!(monitor_rx.pop_slice(&mut monitored), 4);
+        assert_eq!(monitored, [0.1, 0.2, 0.3, 0.4]);
+    }
+
+    #[test]
+    fn monitor_follows_the_playback_rate() {
+        let mut resampler = MonitorResampler::new(22050);
+        let mut out = Vec::new();
+        resampler.process(&[0.5, -0.5, 1.0, -1.0], 44100, &mut out);
+        // Twice the frames, halfway ones interpolated (from silence at first)
+        assert_eq!(out, [0.0, 0.0, 0.25, -0.25, 0.5, -0.5, 0.75, -0.75]);
+        resampler.process(&[0.0, 0.0, 0.0, 0.0], 11025, &mut out);
+        assert_eq!(out, [1.0, -1.0], "half the frames, carrying on from the last chunk");
+        resampler.process(&[0.1, 0.2], 0, &mut out);
+        assert_eq!(out, [0.1, 0.2], "copied before any playback");
+    }
+
+    #[test]
+    fn captured_sample_is_stereo_at_input_rate() {
+        let sample = captured_sample("mic", &[[1.0, -2.0], [0.0, 0.5]], 48000).unwrap();
+        assert_eq!(sample.c4_speed, 48000);
+        assert_eq!(sample.name.as_str(), "mic");
+        match &*sample.data {
+            SampleData::Stereo16(l, r) => {
+                assert_eq!(l, &[i16::MAX, 0]);
+                assert_eq!(r, &[-i16::MAX, i16::MAX / 2]);
+            }
+            other => panic!("expected Stereo16, got {:?}", other),
+        }
+        assert!(captured_sample("empty", &[], 44100).is_none());
+    }
+}
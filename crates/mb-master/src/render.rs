@@ -0,0 +1,286 @@
+//! Incremental offline rendering.
+//!
+//! `Renderer` plays a song through its own engine and hands out fixed-size
+//! chunks, so long renders run in constant memory and can be written out
+//! (as WAV, FLAC or Ogg Vorbis) while they progress.
+
+use mb_engine::{Engine, LimitHit, Limits};
+use mb_formats::{FlacStreamWriter, VorbisSpec, VorbisStreamWriter, WavSpec, WavStreamWriter};
+use mb_ir::{Edit, Interpolation, Song};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Audio file formats a render can be written as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// WAV at the given depth
+    Wav(WavSpec),
+    /// 16-bit lossless FLAC
+    Flac,
+    /// Lossy Ogg Vorbis at the given quality
+    Ogg(VorbisSpec),
+}
+
+impl ExportFormat {
+    /// The format a file name's extension asks for, if any.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "wav" => Some(Self::Wav(WavSpec::default())),
+            "flac" => Some(Self::Flac),
+            "ogg" | "oga" => Some(Self::Ogg(VorbisSpec::default())),
+            _ => None,
+        }
+    }
+
+    /// Usual file extension.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Wav(_) => "wav",
+            Self::Flac => "flac",
+            Self::Ogg(_) => "ogg",
+        }
+    }
+}
+
+/// Offline renderer yielding a song's audio in chunks.
+pub struct Renderer {
+    engine: Engine,
+    chunk: Vec<[f32; 2]>,
+    chunk_size: usize,
+    remaining: usize,
+    sample_rate: u32,
+    reported: bool,
+}
+
+impl Renderer {
+    /// Start rendering `song`, stopping at its end or after `max_frames`.
+    pub fn new(song: Song, sample_rate: u32, chunk_size: usize, max_frames: usize) -> Self {
+        Self::with_limits(song, sample_rate, chunk_size, max_frames, Limits::default())
+    }
+
+    /// Like `new`, scheduling the song within `limits`.
+    pub fn with_limits(song: Song, sample_rate: u32, chunk_size: usize, max_frames: usize, limits: Limits) -> Self {
+        let mut engine = Engine::new(song, sample_rate);
+        engine.set_limits(limits);
+        engine.schedule_song();
+        engine.play();
+        let chunk_size = chunk_size.max(1);
+        Self {
+            engine,
+            chunk: Vec::with_capacity(chunk_size),
+            chunk_size,
+            remaining: max_frames,
+            sample_rate,
+            reported: false,
+        }
+    }
+
+    /// Apply edits (e.g. bypasses) to the engine before rendering starts.
+    pub fn with_edits(mut self, edits: &[Edit]) -> Self {
+        self.engine.apply_edits(edits);
+        self
+    }
+
+    /// Resample with `quality` instead of the realtime default (linear).
+    pub fn with_interpolation(mut self, quality: Interpolation) -> Self {
+        self.engine.set_interpolation(quality);
+        self
+    }
+
+    /// Render independent graph branches on `threads` worker threads.
+    pub fn with_render_threads(mut self, threads: usize) -> Self {
+        self.engine.set_render_threads(threads);
+        self
+    }
+
+    /// Output sample rate.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Render the next chunk; `None` once the song has ended.
+    ///
+    /// Chunks are `chunk_size` frames except for the last one.
+    pub fn next_chunk(&mut self) -> Option<&[[f32; 2]]> {
+        self.chunk.clear();
+        while self.chunk.len() < self.chunk_size && self.remaining > 0 && !self.done() {
+            self.chunk.push(self.engine.render_frame());
+            self.remaining -= 1;
+        }
+        if self.chunk.is_empty() {
+            self.report_limit();
+            return None;
+        }
+        Some(&self.chunk)
+    }
+
+    /// Render everything, passing each chunk to `f`.
+    pub fn for_each_chunk(mut self, mut f: impl FnMut(&[[f32; 2]])) {
+        while let Some(chunk) = self.next_chunk() {
+            f(chunk);
+        }
+    }
+
+    /// Render everything into a WAV stream over `w`, encoded per `spec`.
+    ///
+    /// Returns the stream unfinished so the caller can pick `finish` or,
+    /// for files, `finish_seekable`.
+    pub fn write_wav<W: Write>(mut self, w: W, spec: WavSpec) -> std::io::Result<WavStreamWriter<W>> {
+        let mut stream = WavStreamWriter::new(w, self.sample_rate, spec)?;
+        while let Some(chunk) = self.next_chunk() {
+            stream.write_frames(chunk)?;
+        }
+        Ok(stream)
+    }
+
+    /// Render everything into a new `format` file at `path`, returning the
+    /// number of frames written.
+    pub fn write_file(mut self, path: &Path, format: ExportFormat) -> std::io::Result<u64> {
+        let file = BufWriter::new(File::create(path)?);
+        let sample_rate = self.sample_rate;
+        match format {
+            ExportFormat::Wav(spec) => {
+                let stream = self.write_wav(file, spec)?;
+                let frames = stream.frames_written();
+                stream.finish_seekable()?;
+                Ok(frames)
+            }
+            ExportFormat::Flac => {
+                let mut stream = FlacStreamWriter::new(file, sample_rate)?;
+                while let Some(chunk) = self.next_chunk() {
+                    stream.write_frames(chunk)?;
+                }
+                let frames = stream.frames_written();
+                stream.finish_seekable()?;
+                Ok(frames)
+            }
+            ExportFormat::Ogg(spec) => {
+                let mut stream = VorbisStreamWriter::new(file, sample_rate, spec)?;
+                while let Some(chunk) = self.next_chunk() {
+                    stream.write_frames(chunk)?;
+                }
+                let frames = stream.frames_written();
+                stream.finish()?;
+                Ok(frames)
+            }
+        }
+    }
+
+    /// Safety limit that cut the render short, if any.
+    pub fn limit_hit(&self) -> Option<LimitHit> {
+        self.engine.limit_hit()
+    }
+
+    fn done(&self) -> bool {
+        self.engine.is_finished() || self.engine.limit_hit() == Some(LimitHit::Stalled)
+    }
+
+    fn report_limit(&mut self) {
+        if self.reported {
+            return;
+        }
+        self.reported = true;
+        if let Some(hit) = self.engine.limit_hit() {
+            eprintln!("[render] WARNING: {}", hit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mb_ir::SongTemplate;
+
+    fn song() -> Song {
+        SongTemplate { rows: 16, ..SongTemplate::default() }.build()
+    }
+
+    /// Reference: the whole song one frame at a time.
+    fn render_all(song: Song) -> Vec<[f32; 2]> {
+        let mut engine = Engine::new(song, 8000);
+        engine.schedule_song();
+        engine.play();
+        let mut frames = Vec::new();
+        while !engine.is_finished() {
+            frames.push(engine.render_frame());
+        }
+        frames
+    }
+
+    #[test]
+    fn chunks_concatenate_to_full_render() {
+        let expected = render_all(song());
+        let mut sizes = Vec::new();
+        let mut frames = Vec::new();
+        Renderer::new(song(), 8000, 1000, usize::MAX).for_each_chunk(|chunk| {
+            sizes.push(chunk.len());
+            frames.extend_from_slice(chunk);
+        });
+        assert_eq!(frames, expected);
+        assert!(sizes[..sizes.len() - 1].iter().all(|&n| n == 1000));
+    }
+
+    #[test]
+    fn interpolation_applies_to_the_render() {
+        let collect = |renderer: Renderer| {
+            let mut frames = Vec::new();
+            renderer.for_each_chunk(|chunk| frames.extend_from_slice(chunk));
+            frames
+        };
+        let linear = collect(Renderer::new(song(), 8000, 512, usize::MAX));
+        let sinc = collect(Renderer::new(song(), 8000, 512, usize::MAX).with_interpolation(Interpolation::Sinc));
+        assert_eq!(linear, render_all(song()));
+        assert_eq!(linear.len(), sinc.len());
+        assert_ne!(linear, sinc);
+    }
+
+    #[test]
+    fn render_threads_leave_output_unchanged() {
+        let mut frames = Vec::new();
+        Renderer::new(song(), 8000, 512, usize::MAX)
+            .with_render_threads(2)
+            .for_each_chunk(|chunk| frames.extend_from_slice(chunk));
+        assert_eq!(frames, render_all(song()));
+    }
+
+    #[test]
+    fn max_frames_caps_output() {
+        let mut total = 0;
+        Renderer::new(song(), 8000, 300, 1000).for_each_chunk(|chunk| total += chunk.len());
+        assert_eq!(total, 1000);
+    }
+
+    #[test]
+    fn wav_stream_matches_buffered_encoding() {
+        let spec = WavSpec { depth: mb_formats::WavDepth::Int16, dither: true };
+        let expected = mb_formats::frames_to_wav(&render_all(song()), 8000, spec);
+        let stream = Renderer::new(song(), 8000, 512, usize::MAX)
+            .write_wav(std::io::Cursor::new(Vec::new()), spec)
+            .unwrap();
+        assert_eq!(stream.finish_seekable().unwrap().into_inner(), expected);
+    }
+
+    #[test]
+    fn files_match_buffered_encoders() {
+        let frames = render_all(song());
+        let dir = std::env::temp_dir().join(format!("mb-render-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let expected = [
+            (ExportFormat::Wav(WavSpec::default()), mb_formats::frames_to_wav(&frames, 8000, WavSpec::default())),
+            (ExportFormat::Flac, mb_formats::frames_to_flac(&frames, 8000)),
+            (ExportFormat::Ogg(VorbisSpec::default()), mb_formats::frames_to_ogg(&frames, 8000, VorbisSpec::default())),
+        ];
+        for (format, bytes) in expected {
+            let path = dir.join(format!("song.{}", format.extension()));
+            assert_eq!(ExportFormat::from_path(&path), Some(format));
+            let written = Renderer::new(song(), 8000, 512, usize::MAX).write_file(&path, format).unwrap();
+            assert_eq!(written, frames.len() as u64);
+            assert_eq!(std::fs::read(&path).unwrap(), bytes, "{:?}", format);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(ExportFormat::from_path(Path::new("song.OGG")), Some(ExportFormat::Ogg(VorbisSpec::default())));
+        assert_eq!(ExportFormat::from_path(Path::new("song.mod")), None);
+    }
+}
@@ -0,0 +1,120 @@
+//! Spectrum analysis of the master output for analyzer displays.
+//!
+//! Runs on the caller's thread over the master history the audio thread
+//! publishes (see `MeterBank::master_history`), so the audio thread never
+//! does more than copy samples into a ring.
+
+use std::f32::consts::PI;
+
+/// FFT length; also the number of master frames analyzed.
+pub const SPECTRUM_SIZE: usize = mb_engine::MASTER_HISTORY_FRAMES;
+
+/// Lowest level reported, in dBFS (silence and below).
+pub const SPECTRUM_FLOOR_DB: f32 = -120.0;
+
+/// Hann-windowed FFT analyzer producing magnitude spectra in dBFS.
+pub struct SpectrumAnalyzer {
+    window: Vec<f32>,
+    /// FFT twiddles
+    roots: Vec<(f32, f32)>,
+}
+
+impl Default for SpectrumAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpectrumAnalyzer {
+    pub fn new() -> Self {
+        let n = SPECTRUM_SIZE as f32;
+        let window = (0..SPECTRUM_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / n).cos())
+            .collect();
+        let roots = (0..SPECTRUM_SIZE / 2)
+            .map(|k| {
+                let angle = 2.0 * PI * k as f32 / n;
+                (angle.cos(), -angle.sin())
+            })
+            .collect();
+        Self { window, roots }
+    }
+
+    /// Level of each frequency bin of `samples` (the last `SPECTRUM_SIZE`
+    /// frames; shorter input is zero-padded at the front) in dBFS, where a
+    /// full-scale sine reads 0 dB.
+    ///
+    /// Returns `SPECTRUM_SIZE / 2` bins; bin `k` is centred on
+    /// `k * sample_rate / SPECTRUM_SIZE` Hz.
+    pub fn analyze(&self, samples: &[f32]) -> Vec<f32> {
+        let tail = &samples[samples.len().saturating_sub(SPECTRUM_SIZE)..];
+        let pad = SPECTRUM_SIZE - tail.len();
+        let mut z: Vec<(f32, f32)> = (0..SPECTRUM_SIZE)
+            .map(|i| (if i < pad { 0.0 } else { tail[i - pad] * self.window[i] }, 0.0))
+            .collect();
+        self.fft(&mut z);
+        // A Hann window halves a sine's amplitude; one-sided bins halve it again
+        let scale = 4.0 / SPECTRUM_SIZE as f32;
+        z[..SPECTRUM_SIZE / 2]
+            .iter()
+            .map(|&(re, im)| {
+                let magnitude = (re * re + im * im).sqrt() * scale;
+                (20.0 * magnitude.log10()).max(SPECTRUM_FLOOR_DB)
+            })
+            .collect()
+    }
+
+    /// In-place radix-2 forward FFT.
+    fn fft(&self, z: &mut [(f32, f32)]) {
+        let n = z.len();
+        let bits = n.trailing_zeros();
+        for i in 0..n {
+            let j = i.reverse_bits() >> (usize::BITS - bits);
+            if i < j {
+                z.swap(i, j);
+            }
+        }
+        let mut len = 2;
+        while len <= n {
+            let stride = n / len;
+            for start in (0..n).step_by(len) {
+                for k in 0..len / 2 {
+                    let t = complex_mul(z[start + k + len / 2], self.roots[k * stride]);
+                    let a = z[start + k];
+                    z[start + k] = (a.0 + t.0, a.1 + t.1);
+                    z[start + k + len / 2] = (a.0 - t.0, a.1 - t.1);
+                }
+            }
+            len *= 2;
+        }
+    }
+}
+
+fn complex_mul(a: (f32, f32), b: (f32, f32)) -> (f32, f32) {
+    (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_scale_sine_peaks_at_its_bin() {
+        let bin = 64;
+        let samples: Vec<f32> = (0..SPECTRUM_SIZE)
+            .map(|i| (2.0 * PI * bin as f32 * i as f32 / SPECTRUM_SIZE as f32).sin())
+            .collect();
+        let spectrum = SpectrumAnalyzer::new().analyze(&samples);
+        assert_eq!(spectrum.len(), SPECTRUM_SIZE / 2);
+        let loudest = (0..spectrum.len()).max_by(|&a, &b| spectrum[a].total_cmp(&spectrum[b])).unwrap();
+        assert_eq!(loudest, bin);
+        assert!(spectrum[bin].abs() < 0.1, "{} dB", spectrum[bin]);
+        assert!(spectrum[bin + 10] < -60.0);
+    }
+
+    #[test]
+    fn silence_reads_the_floor() {
+        let spectrum = SpectrumAnalyzer::new().analyze(&[]);
+        assert!(spectrum.iter().all(|&db| db == SPECTRUM_FLOOR_DB));
+    }
+}
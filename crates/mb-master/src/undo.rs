@@ -0,0 +1,299 @@
+//! Undo history for song edits.
+//!
+//! Every edit the controller applies to the song is recorded with what it
+//! takes to reverse it, worked out from the song just before the edit.
+//! Most edits reverse with other edits, so undo works live during
+//! playback. Removing a track or a graph node loses more than an edit can
+//! carry; those keep a copy of the whole song instead.
+
+use mb_ir::{Edit, SampleFields, Song};
+
+/// Undo steps kept before the oldest is dropped.
+const UNDO_LIMIT: usize = 500;
+
+/// How to take back an undo step.
+#[derive(Clone, Debug)]
+pub(crate) enum Reverse {
+    /// Edits that restore the song, applied in order
+    Edits(Vec<Edit>),
+    /// The song as it was before the step
+    Song(Box<Song>),
+}
+
+/// One undo step: the edits made and how to take them back.
+#[derive(Clone, Debug)]
+struct UndoEntry {
+    forward: Vec<Edit>,
+    reverse: Reverse,
+}
+
+/// Undo/redo history.
+#[derive(Default)]
+pub(crate) struct UndoStack {
+    entries: Vec<UndoEntry>,
+    position: usize,
+    /// Nesting depth of open groups (see `begin_group`)
+    group_depth: usize,
+    /// Whether the open group already has an entry on the stack
+    group_started: bool,
+}
+
+impl UndoStack {
+    /// Record an applied edit with its reverse (see `reverse`).
+    ///
+    /// Inside a group the edit joins the group's step; otherwise it is a
+    /// step of its own. Either way, steps that were undone can no longer
+    /// be redone.
+    pub(crate) fn record(&mut self, edit: Edit, reverse: Reverse) {
+        if self.group_depth > 0 && self.group_started {
+            if let Some(entry) = self.entries.last_mut() {
+                entry.forward.push(edit);
+                entry.reverse = chain(reverse, core::mem::replace(&mut entry.reverse, Reverse::Edits(Vec::new())));
+                return;
+            }
+        }
+        self.entries.truncate(self.position);
+        if self.entries.len() == UNDO_LIMIT {
+            self.entries.remove(0);
+        }
+        self.entries.push(UndoEntry { forward: vec![edit], reverse });
+        self.position = self.entries.len();
+        self.group_started = self.group_depth > 0;
+    }
+
+    /// Start collecting edits into one undo step. Groups nest; the step
+    /// closes with the outermost `end_group`.
+    pub(crate) fn begin_group(&mut self) {
+        if self.group_depth == 0 {
+            self.group_started = false;
+        }
+        self.group_depth += 1;
+    }
+
+    pub(crate) fn end_group(&mut self) {
+        self.group_depth = self.group_depth.saturating_sub(1);
+    }
+
+    /// Step back: returns how to reverse the step, or None at the bottom.
+    pub(crate) fn undo(&mut self) -> Option<Reverse> {
+        self.position = self.position.checked_sub(1)?;
+        self.group_started = false;
+        Some(self.entries[self.position].reverse.clone())
+    }
+
+    /// Step forward: returns the edits to apply again, or None at the top.
+    pub(crate) fn redo(&mut self) -> Option<Vec<Edit>> {
+        let entry = self.entries.get(self.position)?;
+        self.position += 1;
+        self.group_started = false;
+        Some(entry.forward.clone())
+    }
+
+    pub(crate) fn can_undo(&self) -> bool {
+        self.position > 0
+    }
+
+    pub(crate) fn can_redo(&self) -> bool {
+        self.position < self.entries.len()
+    }
+
+    /// Forget all history (for a new or reloaded song).
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.position = 0;
+        self.group_started = false;
+    }
+}
+
+/// The reverse of `later` followed by the reverse of `earlier`.
+fn chain(later: Reverse, earlier: Reverse) -> Reverse {
+    match (later, earlier) {
+        (_, Reverse::Song(song)) => Reverse::Song(song),
+        (Reverse::Edits(mut edits), Reverse::Edits(earlier)) => {
+            edits.extend(earlier);
+            Reverse::Edits(edits)
+        }
+        // Wind the copy back to before the earlier edits too
+        (Reverse::Song(mut song), Reverse::Edits(earlier)) => {
+            for edit in &earlier {
+                crate::apply_edit_to_song(&mut song, edit);
+            }
+            Reverse::Song(song)
+        }
+    }
+}
+
+/// How to reverse `edit` on `song` as it is now, before the edit.
+///
+/// Returns None for edits that only change playback (loop, metronome,
+/// clip launches, bypass), which aren't undone, and for edits that can't
+/// apply to `song`.
+pub(crate) fn reverse(song: &Song, edit: &Edit) -> Option<Reverse> {
+    let edits = match *edit {
+        Edit::SetCell { track, clip, row, column, .. } => {
+            let pattern = song.tracks.get(track as usize)?.get_pattern_at(clip as usize)?;
+            if row >= pattern.rows || column >= pattern.channels {
+                return None;
+            }
+            vec![Edit::SetCell { track, clip, row, column, cell: *pattern.cell(row, column) }]
+        }
+        Edit::SetNodeBypass { .. }
+        | Edit::SetLoop { .. }
+        | Edit::SetMasterLimiter { .. }
+        | Edit::SetMetronome { .. }
+        | Edit::SetMetronomeGain { .. }
+        | Edit::CountIn { .. }
+        | Edit::PreviewSample { .. }
+        | Edit::LaunchClip { .. } => return None,
+        Edit::SetChannelMute { channel, .. } => {
+            vec![Edit::SetChannelMute { channel, muted: song.channels.get(channel as usize)?.muted }]
+        }
+        Edit::SetChannelSolo { channel, .. } => {
+            vec![Edit::SetChannelSolo { channel, solo: song.channels.get(channel as usize)?.solo }]
+        }
+        Edit::SetChannelPan { channel, .. } => {
+            vec![Edit::SetChannelPan { channel, pan: song.channels.get(channel as usize)?.initial_pan }]
+        }
+        Edit::SetTempo { .. } => vec![Edit::SetTempo { tempo: song.initial_tempo }],
+        Edit::SetSpeed { .. } => vec![Edit::SetSpeed { speed: song.initial_speed }],
+        Edit::SetTempoEnvelope { .. } => vec![Edit::SetTempoEnvelope { envelope: song.tempo_envelope.clone() }],
+        Edit::SetTimeSignature { beat, .. } => {
+            let signature = match beat {
+                0 => Some(song.meter.initial),
+                _ => song.meter.changes().iter().find(|&&(b, _)| b == beat).map(|&(_, s)| s),
+            };
+            vec![Edit::SetTimeSignature { beat, signature }]
+        }
+        Edit::SetNodeParam { node, param, .. } => {
+            let old = song.graph.node(node)?.parameters.iter().find(|p| p.id == param)?;
+            vec![Edit::SetNodeParam { node, param, value: old.value }]
+        }
+        Edit::SetSampleFields { sample, .. } => {
+            vec![Edit::SetSampleFields { sample, fields: SampleFields::of(song.samples.get(sample as usize)?) }]
+        }
+        Edit::SetSample { sample, .. } => {
+            vec![Edit::SetSample { sample, data: Box::new(song.samples.get(sample as usize)?.clone()) }]
+        }
+        Edit::AddSample { .. } => vec![Edit::RemoveLastSample],
+        Edit::RemoveLastSample => vec![Edit::AddSample { sample: Box::new(song.samples.last()?.clone()) }],
+        Edit::AddMarker { ref marker } => {
+            vec![Edit::RemoveMarker { index: song.markers.partition_point(|m| m.time <= marker.time) as u16 }]
+        }
+        Edit::RemoveMarker { index } => vec![Edit::AddMarker { marker: song.markers.get(index as usize)?.clone() }],
+        Edit::RenameMarker { index, .. } => {
+            vec![Edit::RenameMarker { index, name: song.markers.get(index as usize)?.name }]
+        }
+        Edit::SetSeqEntry { track, .. }
+        | Edit::InsertSeqEntry { track, .. }
+        | Edit::RemoveSeqEntry { track, .. }
+        | Edit::MoveSeqEntry { track, .. }
+        | Edit::DuplicateSeqEntry { track, .. }
+        | Edit::SetSeqClip { track, .. }
+        | Edit::SetSequence { track, .. } => vec![restore_sequence(song, track)?],
+        // Resizing can cut entries short, so put the sequence back as well
+        Edit::ResizeClip { track, clip, .. } => vec![restore_clip(song, track, clip)?, restore_sequence(song, track)?],
+        Edit::InsertRows { track, clip, .. } | Edit::DeleteRows { track, clip, .. } | Edit::SetClip { track, clip, .. } => {
+            vec![restore_clip(song, track, clip)?]
+        }
+        Edit::CloneClip { track, .. } | Edit::AddClip { track, .. } => vec![Edit::RemoveLastClip { track }],
+        Edit::RemoveLastClip { track } => {
+            let t = song.tracks.get(track as usize)?;
+            let clip = t.clips.len().checked_sub(1)? as u16;
+            let rows = t.get_pattern_at(clip as usize)?.rows;
+            vec![Edit::AddClip { track, rows }, restore_clip(song, track, clip)?]
+        }
+        Edit::SetSongGroove { .. } => vec![Edit::SetSongGroove { groove: song.groove.clone() }],
+        Edit::SetTrackGroove { track, .. } => {
+            vec![Edit::SetTrackGroove { track, groove: song.tracks.get(track as usize)?.groove.clone() }]
+        }
+        Edit::SetTrackOffset { track, .. } => {
+            vec![Edit::SetTrackOffset { track, offset: song.tracks.get(track as usize)?.offset }]
+        }
+        Edit::AddTrack { .. } | Edit::AddMidiTrack { .. } => vec![Edit::RemoveTrack { track: song.tracks.len() as u16 }],
+        Edit::MoveTrack { from, to } => vec![Edit::MoveTrack { from: to, to: from }],
+        Edit::RenameTrack { track, .. } => {
+            vec![Edit::RenameTrack { track, name: song.tracks.get(track as usize)?.name }]
+        }
+        Edit::AddNode { .. } => vec![Edit::RemoveNode { node: song.graph.nodes.len() as u16 }],
+        Edit::RemoveTrack { .. } | Edit::RemoveNode { .. } => return Some(Reverse::Song(Box::new(song.clone()))),
+        Edit::Connect { from, to, .. } => {
+            // Connecting an existing wire changes nothing
+            if song.graph.connection(from, to).is_some() {
+                return None;
+            }
+            vec![Edit::Disconnect { from, to }]
+        }
+        Edit::Disconnect { from, to } => {
+            let wire = song.graph.connection(from, to)?;
+            vec![Edit::Connect { from, to, gain: wire.gain }, Edit::SetConnectionPan { from, to, pan: wire.pan }]
+        }
+        Edit::SetConnectionGain { from, to, .. } => {
+            vec![Edit::SetConnectionGain { from, to, gain: song.graph.connection(from, to)?.gain }]
+        }
+        Edit::SetConnectionPan { from, to, .. } => {
+            vec![Edit::SetConnectionPan { from, to, pan: song.graph.connection(from, to)?.pan }]
+        }
+    };
+    Some(Reverse::Edits(edits))
+}
+
+/// An edit putting a track's sequence back as it is now.
+fn restore_sequence(song: &Song, track: u16) -> Option<Edit> {
+    Some(Edit::SetSequence { track, sequence: song.tracks.get(track as usize)?.sequence.clone() })
+}
+
+/// An edit putting a clip back as it is now.
+fn restore_clip(song: &Song, track: u16, clip: u16) -> Option<Edit> {
+    let pattern = song.tracks.get(track as usize)?.get_pattern_at(clip as usize)?;
+    Some(Edit::SetClip { track, clip, pattern: Box::new(pattern.clone()) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mb_ir::{Cell, Note};
+
+    fn set_cell(row: u16, note: Note) -> Edit {
+        Edit::SetCell { track: 0, clip: 0, row, column: 0, cell: Cell { note, ..Cell::empty() } }
+    }
+
+    fn edits(reverse: Option<Reverse>) -> Vec<Edit> {
+        match reverse {
+            Some(Reverse::Edits(edits)) => edits,
+            other => panic!("expected edits, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn new_steps_after_undo_drop_the_redo_history() {
+        let mut stack = UndoStack::default();
+        stack.record(set_cell(0, Note::On(60)), Reverse::Edits(vec![set_cell(0, Note::None)]));
+        stack.record(set_cell(1, Note::On(62)), Reverse::Edits(vec![set_cell(1, Note::None)]));
+        assert_eq!(edits(stack.undo()), [set_cell(1, Note::None)]);
+        assert!(stack.can_redo());
+
+        stack.record(set_cell(2, Note::On(64)), Reverse::Edits(vec![set_cell(2, Note::None)]));
+        assert!(!stack.can_redo());
+        assert!(stack.redo().is_none());
+        stack.undo();
+        stack.undo();
+        assert!(!stack.can_undo());
+        assert!(stack.undo().is_none());
+    }
+
+    #[test]
+    fn groups_undo_as_one_step_in_reverse_order() {
+        let mut stack = UndoStack::default();
+        stack.begin_group();
+        stack.record(set_cell(0, Note::On(60)), Reverse::Edits(vec![set_cell(0, Note::None)]));
+        stack.begin_group();
+        stack.record(set_cell(1, Note::On(62)), Reverse::Edits(vec![set_cell(1, Note::None)]));
+        stack.end_group();
+        stack.end_group();
+        stack.record(set_cell(2, Note::On(64)), Reverse::Edits(vec![set_cell(2, Note::None)]));
+
+        stack.undo();
+        assert_eq!(edits(stack.undo()), [set_cell(1, Note::None), set_cell(0, Note::None)]);
+        assert_eq!(stack.redo().unwrap(), [set_cell(0, Note::On(60)), set_cell(1, Note::On(62))]);
+    }
+}
@@ -0,0 +1,67 @@
+//! JavaScript bindings for `Player`.
+//!
+//! From an `AudioWorkletProcessor`, construct a `WebPlayer` with the
+//! context's `sampleRate`, `load` the song bytes, `play`, then call
+//! `renderPlanar(outputs[0][0], outputs[0][1])` in every `process` call.
+
+use mb_ir::Interpolation;
+use wasm_bindgen::prelude::*;
+
+use crate::Player;
+
+/// A song player for the browser.
+#[wasm_bindgen]
+pub struct WebPlayer {
+    player: Player,
+}
+
+#[wasm_bindgen]
+impl WebPlayer {
+    #[wasm_bindgen(constructor)]
+    pub fn new(sample_rate: u32) -> WebPlayer {
+        WebPlayer { player: Player::new(sample_rate) }
+    }
+
+    /// Load song bytes; `extension` picks the format ("mod", "it", ...).
+    pub fn load(&mut self, data: &[u8], extension: &str) -> Result<(), JsError> {
+        self.player
+            .load(data, extension)
+            .map_err(|e| JsError::new(&format!("can't load {} file: {:?}", extension, e)))
+    }
+
+    pub fn play(&mut self) {
+        self.player.play();
+    }
+
+    pub fn stop(&mut self) {
+        self.player.stop();
+    }
+
+    #[wasm_bindgen(js_name = isFinished)]
+    pub fn is_finished(&self) -> bool {
+        self.player.is_finished()
+    }
+
+    /// 0 = nearest, 1 = linear, 2 = cubic, 3 = sinc.
+    #[wasm_bindgen(js_name = setInterpolation)]
+    pub fn set_interpolation(&mut self, quality: u8) {
+        self.player.set_interpolation(match quality {
+            0 => Interpolation::Nearest,
+            2 => Interpolation::Cubic,
+            3 => Interpolation::Sinc,
+            _ => Interpolation::Linear,
+        });
+    }
+
+    /// Fill interleaved stereo frames.
+    #[wasm_bindgen(js_name = renderBlock)]
+    pub fn render_block(&mut self, out: &mut [f32]) {
+        self.player.render_block(out);
+    }
+
+    /// Fill separate left and right channel buffers.
+    #[wasm_bindgen(js_name = renderPlanar)]
+    pub fn render_planar(&mut self, left: &mut [f32], right: &mut [f32]) {
+        self.player.render_planar(left, right);
+    }
+}
@@ -0,0 +1,181 @@
+//! Browser playback for masterblaster songs.
+//!
+//! `Player` wraps the engine for hosts that pull audio themselves, such as
+//! a Web Audio `AudioWorkletProcessor`: no threads, no audio device and
+//! no ring buffers, just an explicit sample rate and render calls that
+//! fill the caller's buffers. On wasm32 the `bindings` module exports it
+//! to JavaScript through wasm-bindgen.
+
+#[cfg(target_arch = "wasm32")]
+mod bindings;
+
+use mb_engine::Engine;
+use mb_formats::FormatError;
+use mb_ir::{Interpolation, Song, BLOCK_SIZE};
+
+/// A song and the engine playing it, rendered on demand.
+pub struct Player {
+    engine: Engine,
+    sample_rate: u32,
+    /// Stereo frames for one engine block, reused by every render call
+    block: Vec<[f32; 2]>,
+}
+
+impl Player {
+    /// A player with an empty song, rendering at `sample_rate`.
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            engine: Engine::new(Song::with_channels("Untitled", 4), sample_rate),
+            sample_rate,
+            block: vec![[0.0; 2]; BLOCK_SIZE],
+        }
+    }
+
+    /// Output sample rate.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Load a song file, picking the parser by `extension` (e.g. "it";
+    /// anything unrecognised is tried as a MOD). Playback stops.
+    pub fn load(&mut self, data: &[u8], extension: &str) -> Result<(), FormatError> {
+        let song = match extension.to_ascii_lowercase().as_str() {
+            "mbp" => mb_formats::load_song(data)?,
+            "bmx" => mb_formats::load_bmx(data)?,
+            "it" => mb_formats::load_it(data)?,
+            "s3m" => mb_formats::load_s3m(data)?,
+            "mid" | "midi" => mb_formats::load_midi(data, self.engine.song().rows_per_beat)?,
+            _ => mb_formats::load_mod(data)?,
+        };
+        self.set_song(song);
+        Ok(())
+    }
+
+    /// Replace the song. Playback stops and restarts from the top.
+    pub fn set_song(&mut self, song: Song) {
+        let interpolation = self.engine.interpolation();
+        self.engine = Engine::new(song, self.sample_rate);
+        self.engine.set_interpolation(interpolation);
+        self.engine.schedule_song();
+    }
+
+    /// The loaded song.
+    pub fn song(&self) -> &Song {
+        self.engine.song()
+    }
+
+    /// Start or resume playback.
+    pub fn play(&mut self) {
+        self.engine.play();
+    }
+
+    /// Pause playback; rendering outputs silence until `play`.
+    pub fn stop(&mut self) {
+        self.engine.stop();
+    }
+
+    /// Whether the song has played to its end.
+    pub fn is_finished(&self) -> bool {
+        self.engine.is_finished()
+    }
+
+    /// Resample with `quality` (linear by default).
+    pub fn set_interpolation(&mut self, quality: Interpolation) {
+        self.engine.set_interpolation(quality);
+    }
+
+    /// Fill `out` with interleaved stereo frames (L, R, L, R, ...).
+    /// A trailing odd sample is zeroed, as is everything once the song
+    /// has finished. Doesn't allocate.
+    pub fn render_block(&mut self, out: &mut [f32]) {
+        let frames = out.len() / 2;
+        let mut done = 0;
+        while done < frames {
+            let n = self.render_next(frames - done);
+            for (dst, frame) in out[done * 2..].chunks_exact_mut(2).zip(&self.block[..n]) {
+                dst.copy_from_slice(frame);
+            }
+            done += n;
+        }
+        out[frames * 2..].fill(0.0);
+    }
+
+    /// Fill separate left and right buffers, as an `AudioWorkletProcessor`
+    /// receives them. Renders the shorter of the two lengths and zeroes
+    /// the rest. Doesn't allocate.
+    pub fn render_planar(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let frames = left.len().min(right.len());
+        let mut done = 0;
+        while done < frames {
+            let n = self.render_next(frames - done);
+            for (i, frame) in self.block[..n].iter().enumerate() {
+                left[done + i] = frame[0];
+                right[done + i] = frame[1];
+            }
+            done += n;
+        }
+        left[frames..].fill(0.0);
+        right[frames..].fill(0.0);
+    }
+
+    /// Render up to one block into `self.block`, silent once finished.
+    /// Returns the frames rendered.
+    fn render_next(&mut self, wanted: usize) -> usize {
+        let n = wanted.min(BLOCK_SIZE);
+        if self.engine.is_finished() {
+            self.block[..n].fill([0.0; 2]);
+        } else {
+            self.engine.render_block(&mut self.block[..n]);
+        }
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mb_ir::SongTemplate;
+
+    fn player() -> Player {
+        let mut player = Player::new(8000);
+        player.set_song(SongTemplate { rows: 16, ..SongTemplate::default() }.build());
+        player
+    }
+
+    /// Reference: the engine driven directly, as the desktop renderer does.
+    fn engine_frames(frames: usize) -> Vec<[f32; 2]> {
+        let mut engine = Engine::new(SongTemplate { rows: 16, ..SongTemplate::default() }.build(), 8000);
+        engine.schedule_song();
+        engine.play();
+        engine.render_frames(frames)
+    }
+
+    #[test]
+    fn interleaved_and_planar_renders_match_the_engine() {
+        let expected = engine_frames(300);
+
+        let mut interleaved = player();
+        interleaved.play();
+        let mut out = vec![1.0f32; 600];
+        interleaved.render_block(&mut out[..200]);
+        interleaved.render_block(&mut out[200..]);
+        let frames: Vec<[f32; 2]> = out.chunks_exact(2).map(|f| [f[0], f[1]]).collect();
+        assert_eq!(frames, expected);
+
+        let mut planar = player();
+        planar.play();
+        let (mut left, mut right) = (vec![1.0f32; 300], vec![1.0f32; 300]);
+        planar.render_planar(&mut left, &mut right);
+        assert_eq!(left, expected.iter().map(|f| f[0]).collect::<Vec<_>>());
+        assert_eq!(right, expected.iter().map(|f| f[1]).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn stopped_player_renders_silence() {
+        let mut player = player();
+        let mut out = [1.0f32; 7];
+        player.render_block(&mut out);
+        assert_eq!(out, [0.0; 7]);
+        assert!(player.load(&[0; 4], "it").is_err());
+    }
+}
@@ -1,61 +1,255 @@
-//! masterblaster CLI — headless playback and WAV export.
+//! masterblaster CLI — headless playback, inspection, conversion and audio export.
 //!
 //! Usage:
+//!   cargo cli render path/to/file.mod -o output.wav [--subsong N] [--pattern N] [--quality N]
+//!   cargo cli inspect path/to/file.it
+//!   cargo cli convert path/to/file.it output.mod
+//!   cargo cli new
+//!   cargo cli prefs [key=value ...]
+//!
+//! Playing and the older flag forms still work:
 //!   cargo cli path/to/file.mod
-//!   cargo cli path/to/file.mod --wav output.wav
 //!   cargo cli path/to/file.mod --pattern 0
-//!   cargo cli path/to/file.mod --pattern 0 --wav output.wav
+//!   cargo cli path/to/file.mod --subsong 1 --wav output.flac
+//!   cargo cli path/to/file.it --mod output.mod
+//!   cargo cli path/to/file.bmx --bmx output.bmx
+//!   cargo cli path/to/file.mod --clock-out "Port name" --clock-in "Port name"
+//!
+//! Rendering writes FLAC or Ogg Vorbis instead of WAV when the output ends
+//! in `.flac` or `.ogg` (`--quality`, 0-10, sets the Vorbis quality);
+//! `convert` picks the song format (`.mbp`, `.mod` or `.bmx`) from the
+//! output's extension. While playing, `--clock-out` sends
+//! MIDI clock to a MIDI output port and `--clock-in` follows the clock or
+//! time code arriving on an input port. New songs and audio export use the
+//! preferences file (see `Preferences::default_path`), which `prefs`
+//! prints, after saving any `key=value` changes. Machine plugins in
+//! `Preferences::plugin_dir` are loaded first, so songs can use them.
+//! Errors exit with status 1, so the subcommands suit scripts and CI.
 
-use mb_master::Controller;
+use mb_master::{Controller, ExportFormat, Preferences, SongFileFormat, WavSpec};
 use std::io::Write;
 use std::{env, fs};
 
+const USAGE: &str = "Usage: mb-cli render <song> -o <out.wav|.flac|.ogg> [--subsong N] [--pattern N] [--quality 0-10]
+       mb-cli inspect <song>
+       mb-cli convert <in> <out.mbp|.mod|.bmx>
+       mb-cli new
+       mb-cli prefs [key=value ...]
+       mb-cli <song> [--wav output.wav] [--mod output.mod] [--bmx output.bmx] [--pattern N] [--subsong N]
+              [--clock-out <midi port>] [--clock-in <midi port>]";
+
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let path = args.get(1).unwrap_or_else(|| {
-        eprintln!("Usage: mb-cli <file.mod> [--wav output.wav] [--pattern N]");
+    let command = args.get(1).unwrap_or_else(|| usage());
+    let mut ctrl = Controller::with_preferences(Preferences::load_or_default());
+    load_machine_plugins();
+
+    match command.as_str() {
+        "new" => {
+            ctrl.new_song();
+            print_song_info(&ctrl);
+        }
+        "render" => render_command(&mut ctrl, &args[2..]),
+        "inspect" => inspect_command(&mut ctrl, &args[2..]),
+        "convert" => convert_command(&mut ctrl, &args[2..]),
+        "prefs" => prefs_command(ctrl.preferences().clone(), &args[2..]),
+        _ => play_command(&mut ctrl, &args[1..]),
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(1);
+}
+
+/// `render <song> -o <out>`: write the song (or one clip) to an audio file.
+fn render_command(ctrl: &mut Controller, args: &[String]) {
+    let path = args.first().unwrap_or_else(|| usage());
+    let out = flag_value(args, "-o")
+        .or_else(|| flag_value(args, "--output"))
+        .unwrap_or_else(|| usage());
+    let quality = numeric_flag(args, "--quality").map(|q| q.min(mb_master::MAX_VORBIS_QUALITY as usize) as u8);
+    load_song_file(ctrl, path);
+    select_subsong(ctrl, args);
+    match numeric_flag(args, "--pattern") {
+        Some(p) => {
+            check_clip(ctrl, p);
+            render_to_wav_pattern(ctrl, &out, p, quality);
+        }
+        None => render_to_wav(ctrl, &out, quality),
+    }
+}
+
+/// `inspect <song>`: print the song's structure without playing it.
+fn inspect_command(ctrl: &mut Controller, args: &[String]) {
+    let path = args.first().unwrap_or_else(|| usage());
+    load_song_file(ctrl, path);
+    print_song_info(ctrl);
+    print_clips(ctrl.song());
+    print_samples(ctrl.song());
+    print_graph(ctrl.song());
+}
+
+/// `convert <in> <out>`: rewrite a song in the format `out` names.
+fn convert_command(ctrl: &mut Controller, args: &[String]) {
+    let (Some(path), Some(out)) = (args.first(), args.get(1)) else { usage() };
+    let format = SongFileFormat::from_path(std::path::Path::new(out)).unwrap_or_else(|| {
+        eprintln!("Can't tell the output format of {} (use .mbp, .mod or .bmx)", out);
         std::process::exit(1);
     });
+    load_song_file(ctrl, path);
+    let export = ctrl.export_song(format).map(|e| (e.data, e.warnings));
+    write_export(&format.extension().to_uppercase(), export, out);
+}
 
-    let wav_path = args
-        .iter()
-        .position(|a| a == "--wav")
-        .and_then(|i| args.get(i + 1))
-        .cloned();
-
-    let pattern_idx: Option<usize> = args
-        .iter()
-        .position(|a| a == "--pattern")
-        .map(|i| {
-            args.get(i + 1)
-                .and_then(|s| s.parse().ok())
-                .unwrap_or_else(|| {
-                    eprintln!("--pattern requires a numeric argument");
-                    std::process::exit(1);
-                })
+/// `prefs [key=value ...]`: save the given preferences, then print them all.
+fn prefs_command(mut prefs: Preferences, args: &[String]) {
+    for arg in args {
+        let Some((key, value)) = arg.split_once('=') else { usage() };
+        if !prefs.set(key.trim(), value.trim()) {
+            eprintln!("Unknown preference or value out of range: {}", arg);
+            std::process::exit(1);
+        }
+    }
+    if !args.is_empty() {
+        let Some(path) = Preferences::default_path() else {
+            eprintln!("No config directory to save preferences in (set HOME or XDG_CONFIG_HOME)");
+            std::process::exit(1);
+        };
+        prefs.save(&path).unwrap_or_else(|e| {
+            eprintln!("Failed to write {}: {}", path.display(), e);
+            std::process::exit(1);
         });
+        println!("Saved {}", path.display());
+    }
+    print!("{}", prefs.to_config_string());
+}
+
+/// The original flag-driven mode: play, or export with `--wav`, `--mod` or `--bmx`.
+fn play_command(ctrl: &mut Controller, args: &[String]) {
+    let path = &args[0];
+    let wav_path = flag_value(args, "--wav");
+    let mod_path = flag_value(args, "--mod");
+    let bmx_path = flag_value(args, "--bmx");
+    let pattern_idx = numeric_flag(args, "--pattern");
+
+    load_song_file(ctrl, path);
+    select_subsong(ctrl, args);
+    set_up_midi_sync(ctrl, args);
+
+    print_song_info(ctrl);
+    if let Some(out) = mod_path {
+        let export = ctrl.export_mod().map(|e| (e.data, e.warnings));
+        write_export("MOD", export, &out);
+        return;
+    }
+    if let Some(out) = bmx_path {
+        let export = ctrl.export_bmx().map(|e| (e.data, e.warnings));
+        write_export("BMX", export, &out);
+        return;
+    }
+
+    if let Some(p) = pattern_idx {
+        check_clip(ctrl, p);
+        println!("\nClip: {}", p);
+
+        if let Some(track) = ctrl.song().tracks.first() {
+            if let Some(pat) = track.clips[p].pattern() {
+                let pf = mb_ir::analyze_pattern(pat);
+                print!("{}", pf);
+            }
+        }
+    }
+
+    match (wav_path, pattern_idx) {
+        (Some(wav), Some(p)) => render_to_wav_pattern(ctrl, &wav, p, None),
+        (Some(wav), None) => render_to_wav(ctrl, &wav, None),
+        (None, Some(p)) => play_pattern(ctrl, p),
+        (None, None) => play_audio(ctrl),
+    }
+}
+
+/// Register the machine plugins in the plugin directory, if there is one.
+fn load_machine_plugins() {
+    let Some(loads) = Preferences::plugin_dir().and_then(|dir| mb_master::load_plugin_dir(&dir).ok()) else { return };
+    for (path, result) in loads {
+        if let Err(e) = result {
+            eprintln!("Skipping plugin {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Apply `--clock-out` and `--clock-in`; exits if a port can't be opened.
+fn set_up_midi_sync(ctrl: &mut Controller, args: &[String]) {
+    if let Some(port) = flag_value(args, "--clock-out") {
+        if let Err(e) = ctrl.set_sync_output(Some(&port)) {
+            eprintln!("Failed to open MIDI output {}: {} (ports: {:?})", port, e, mb_master::midi_output_names());
+            std::process::exit(1);
+        }
+        ctrl.set_midi_clock_out(true);
+    }
+    if let Some(port) = flag_value(args, "--clock-in") {
+        if let Err(e) = ctrl.set_sync_input(Some(&port)) {
+            eprintln!("Failed to open MIDI input {}: {} (ports: {:?})", port, e, mb_master::midi_input_names());
+            std::process::exit(1);
+        }
+        println!("Waiting for MIDI clock on {}", port);
+    }
+}
+
+/// The argument following `flag`, if present.
+fn flag_value(args: &[String], flag: &str) -> Option<String> {
+    args.iter()
+        .position(|a| a == flag)
+        .and_then(|i| args.get(i + 1))
+        .cloned()
+}
 
+/// The numeric argument following `flag`; exits if it isn't a number.
+fn numeric_flag(args: &[String], flag: &str) -> Option<usize> {
+    args.iter().position(|a| a == flag).map(|i| {
+        args.get(i + 1)
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_else(|| {
+                eprintln!("{} requires a numeric argument", flag);
+                std::process::exit(1);
+            })
+    })
+}
+
+/// Load a song, choosing the loader by extension (MOD when unknown).
+fn load_song_file(ctrl: &mut Controller, path: &str) {
     let data = fs::read(path).unwrap_or_else(|e| {
         eprintln!("Failed to read {}: {}", path, e);
         std::process::exit(1);
     });
-
-    let ext = std::path::Path::new(path)
-        .extension()
-        .and_then(|e| e.to_str())
-        .unwrap_or("")
-        .to_ascii_lowercase();
-
-    let mut ctrl = Controller::new();
-    let load_result = match ext.as_str() {
-        "bmx" => ctrl.load_bmx(&data),
-        _ => ctrl.load_mod(&data),
-    };
-    load_result.unwrap_or_else(|e| {
-        eprintln!("Failed to parse {}: {:?}", ext.to_uppercase(), e);
+    let format = SongFileFormat::from_path(std::path::Path::new(path)).unwrap_or(SongFileFormat::Mod);
+    ctrl.load_song(&data, format).unwrap_or_else(|e| {
+        eprintln!("Failed to parse {}: {:?}", format.extension().to_uppercase(), e);
         std::process::exit(1);
     });
+}
 
+/// Apply `--subsong N`, if given.
+fn select_subsong(ctrl: &mut Controller, args: &[String]) {
+    if let Some(s) = numeric_flag(args, "--subsong") {
+        if !ctrl.select_subsong(s) {
+            eprintln!("Subsong {} out of range (song has {})", s, ctrl.subsongs().len());
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Exit unless the first track has clip `p`.
+fn check_clip(ctrl: &Controller, p: usize) {
+    let clip_count = ctrl.song().tracks.first().map(|t| t.clips.len()).unwrap_or(0);
+    if p >= clip_count {
+        eprintln!("Clip {} out of range (song has {})", p, clip_count);
+        std::process::exit(1);
+    }
+}
+
+fn print_song_info(ctrl: &Controller) {
     let song = ctrl.song();
     println!("Title:    {}", song.title);
     println!("Channels: {}", song.channels.len());
@@ -65,45 +259,99 @@ fn main() {
     let seq_len = song.tracks.first().map(|t| t.sequence.len()).unwrap_or(0);
     println!("Clips:    {}", clip_count);
     println!("Sequence: {} entries", seq_len);
-    println!("Tempo:    {} BPM, Speed: {}", song.initial_tempo, song.initial_speed);
+    println!("Subsongs: {}", song.subsongs.len());
+    println!("Tempo:    {} BPM, Speed: {}", mb_ir::Bpm(song.initial_tempo), song.initial_speed);
 
     let samples_with_data = song.samples.iter().filter(|s| !s.is_empty()).count();
     println!("Samples:  {} (with data)", samples_with_data);
+    println!("Memory:   {}", song.memory_footprint());
+    print_largest_samples(song);
+    print_machine_params(song);
     println!();
+}
 
-    if let Some(p) = pattern_idx {
-        if p >= clip_count {
-            eprintln!("Clip {} out of range (song has {})", p, clip_count);
-            std::process::exit(1);
-        }
-        println!("\nClip: {}", p);
+/// List the biggest samples — the first candidates for downsampling.
+fn print_largest_samples(song: &mb_ir::Song) {
+    for (idx, bytes) in song.samples_by_size().into_iter().take(3).filter(|&(i, _)| !song.samples[i].is_empty()) {
+        println!("  #{:02} {:<22} {}", idx + 1, song.samples[idx].name, mb_ir::ByteSize(bytes));
+    }
+}
 
-        if let Some(track) = song.tracks.first() {
-            if let Some(pat) = track.clips[p].pattern() {
-                let pf = mb_ir::analyze_pattern(pat);
-                print!("{}", pf);
+/// Every track's clips with their sizes, and the sequence that plays them.
+fn print_clips(song: &mb_ir::Song) {
+    for (t, track) in song.tracks.iter().enumerate() {
+        println!("Track {}: {} channel(s) from {}", t, track.num_channels, track.base_channel);
+        for (c, clip) in track.clips.iter().enumerate() {
+            if let Some(pat) = clip.pattern() {
+                let notes = pat.data.iter().filter(|cell| !cell.is_empty()).count();
+                println!("  Clip {:02X}: {} rows x {} ch, {} cells used", c, pat.rows, pat.channels, notes);
             }
         }
+        let order: Vec<String> = track.sequence.iter().map(|e| format!("{:02X}", e.clip_idx)).collect();
+        println!("  Sequence: {}", order.join(" "));
     }
+    println!();
+}
 
-    match (wav_path, pattern_idx) {
-        (Some(wav), Some(p)) => render_to_wav_pattern(&ctrl, &wav, p),
-        (Some(wav), None) => render_to_wav(&ctrl, &wav),
-        (None, Some(p)) => play_pattern(&mut ctrl, p),
-        (None, None) => play_audio(&mut ctrl),
+/// Every sample with data: length, loop and base rate.
+fn print_samples(song: &mb_ir::Song) {
+    for (idx, sample) in song.samples.iter().enumerate().filter(|(_, s)| !s.is_empty()) {
+        let looped = if sample.has_loop() {
+            format!("loop {}..{}", sample.loop_start, sample.loop_end)
+        } else {
+            "no loop".to_string()
+        };
+        println!("  #{:02} {:<22} {} frames, {} Hz, {}", idx + 1, sample.name, sample.len(), sample.c4_speed, looped);
+    }
+    println!();
+}
+
+/// Graph nodes and the connections between them.
+fn print_graph(song: &mb_ir::Song) {
+    println!("Graph:    {} nodes, {} connections", song.graph.nodes.len(), song.graph.connections.len());
+    for node in &song.graph.nodes {
+        println!("  {:>3} {}", node.id, node.node_type.label());
+    }
+    for conn in &song.graph.connections {
+        println!("  {} -> {} (gain {:+})", conn.from, conn.to, conn.gain);
+    }
+}
+
+fn print_machine_params(song: &mb_ir::Song) {
+    for node in song.graph.nodes.iter().filter(|n| !n.parameters.is_empty()) {
+        println!("Machine:  {}", node.node_type.label());
+        for param in &node.parameters {
+            println!("  {}: {}", param.name, param.display_value());
+        }
     }
 }
 
+/// Write an exported song, printing its lossy-conversion warnings.
+fn write_export(format: &str, export: Result<(Vec<u8>, Vec<String>), mb_master::FormatError>, path: &str) {
+    let (data, warnings) = export.unwrap_or_else(|e| {
+        eprintln!("Can't export as {}: {:?}", format, e);
+        std::process::exit(1);
+    });
+    for warning in &warnings {
+        println!("Warning: {}", warning);
+    }
+    fs::write(path, &data).unwrap_or_else(|e| {
+        eprintln!("Failed to write {}: {}", path, e);
+        std::process::exit(1);
+    });
+    println!("Wrote {}", path);
+}
+
 fn play_audio(ctrl: &mut Controller) {
     ctrl.play();
     println!("Playing...");
     println!();
 
     while ctrl.is_playing() {
-        if let Some(pos) = ctrl.track_position(0) {
+        if let (Some(pos), Some(bar)) = (ctrl.track_position(0), ctrl.bar_position()) {
             print!(
-                "\rSeq: {:02X} | Clip: {:02X} | Row: {:02X}",
-                pos.seq_index, pos.clip_idx, pos.row
+                "\rBar: {} | Seq: {:02X} | Clip: {:02X} | Row: {:02X}",
+                bar, pos.seq_index, pos.clip_idx, pos.row
             );
             let _ = std::io::stdout().flush();
         }
@@ -111,6 +359,9 @@ fn play_audio(ctrl: &mut Controller) {
     }
 
     println!("\rDone.          ");
+    if let Some(hit) = ctrl.limit_hit() {
+        println!("Warning: {}", hit);
+    }
 }
 
 fn play_pattern(ctrl: &mut Controller, pattern: usize) {
@@ -119,10 +370,10 @@ fn play_pattern(ctrl: &mut Controller, pattern: usize) {
     println!();
 
     while ctrl.is_playing() {
-        if let Some(pos) = ctrl.track_position(0) {
+        if let (Some(pos), Some(bar)) = (ctrl.track_position(0), ctrl.bar_position()) {
             print!(
-                "\rSeq: {:02X} | Clip: {:02X} | Row: {:02X}",
-                pos.seq_index, pos.clip_idx, pos.row
+                "\rBar: {} | Seq: {:02X} | Clip: {:02X} | Row: {:02X}",
+                bar, pos.seq_index, pos.clip_idx, pos.row
             );
             let _ = std::io::stdout().flush();
         }
@@ -130,36 +381,43 @@ fn play_pattern(ctrl: &mut Controller, pattern: usize) {
     }
 
     println!("\rDone.          ");
+    if let Some(hit) = ctrl.limit_hit() {
+        println!("Warning: {}", hit);
+    }
 }
 
-fn render_to_wav_pattern(ctrl: &Controller, path: &str, pattern: usize) {
-    let sample_rate: u32 = 44100;
-    let max_seconds: u32 = 1200;
+fn render_to_wav_pattern(ctrl: &Controller, path: &str, pattern: usize, quality: Option<u8>) {
+    let sample_rate = ctrl.preferences().export_sample_rate;
     println!("Rendering clip {} to {} at {} Hz...", pattern, path, sample_rate);
-
-    let wav = ctrl.render_pattern_to_wav(0, pattern, sample_rate, max_seconds);
-    println!("Rendered {} bytes", wav.len());
-
-    fs::write(path, &wav).unwrap_or_else(|e| {
-        eprintln!("Failed to write {}: {}", path, e);
-        std::process::exit(1);
-    });
-
-    println!("Done.");
+    write_wav_file(ctrl.pattern_renderer(0, pattern, sample_rate, RENDER_CHUNK, max_render_frames(sample_rate)), path, quality);
 }
 
-fn render_to_wav(ctrl: &Controller, path: &str) {
-    let sample_rate: u32 = 44100;
-    let max_seconds: u32 = 1200;
+fn render_to_wav(ctrl: &Controller, path: &str, quality: Option<u8>) {
+    let sample_rate = ctrl.preferences().export_sample_rate;
     println!("Rendering to {} at {} Hz...", path, sample_rate);
+    write_wav_file(ctrl.renderer(sample_rate, RENDER_CHUNK, max_render_frames(sample_rate)), path, quality);
+}
 
-    let wav = ctrl.render_to_wav(sample_rate, max_seconds);
-    println!("Rendered {} bytes", wav.len());
+/// Frames per chunk when streaming a render to disk.
+const RENDER_CHUNK: usize = 4096;
 
-    fs::write(path, &wav).unwrap_or_else(|e| {
-        eprintln!("Failed to write {}: {}", path, e);
+/// Renders stop after 20 minutes.
+fn max_render_frames(sample_rate: u32) -> usize {
+    sample_rate as usize * 1200
+}
+
+/// Stream a render straight into an audio file, WAV unless the extension
+/// names another format. `quality` overrides the default Vorbis quality.
+fn write_wav_file(renderer: mb_master::Renderer, path: &str, quality: Option<u8>) {
+    let path = std::path::Path::new(path);
+    let mut format = ExportFormat::from_path(path).unwrap_or(ExportFormat::Wav(WavSpec::default()));
+    if let (ExportFormat::Ogg(spec), Some(quality)) = (&mut format, quality) {
+        spec.quality = quality;
+    }
+    let frames = renderer.write_file(path, format).unwrap_or_else(|e| {
+        eprintln!("Failed to write {}: {}", path.display(), e);
         std::process::exit(1);
     });
-
+    println!("Rendered {} frames", frames);
     println!("Done.");
 }
@@ -57,6 +57,7 @@ impl ApplicationHandler for AppHandler {
         match event {
             WindowEvent::CloseRequested => {
                 app.gui.controller.stop();
+                app.gui.controller.discard_autosaves();
                 event_loop.exit();
             }
             WindowEvent::Resized(size) => {
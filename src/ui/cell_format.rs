@@ -10,6 +10,8 @@ pub fn format_cell_into(cell: &mb_ir::Cell, buf: &mut String) {
     write_instrument(buf, cell.instrument);
     buf.push(' ');
     write_effect(buf, &cell.effect);
+    buf.push(' ');
+    write_effect(buf, &cell.effect2);
 }
 
 pub fn format_note(note: mb_ir::Note) -> &'static str {
@@ -69,6 +71,7 @@ fn write_effect(buf: &mut String, effect: &mb_ir::Effect) {
         PatternBreak(v) => { let _ = write!(buf, "D{:02X}", v); }
         FinePortaUp(v) => { let _ = write!(buf, "E1{:X}", v); }
         FinePortaDown(v) => { let _ = write!(buf, "E2{:X}", v); }
+        SetGlissando(v) => { let _ = write!(buf, "E3{:X}", v); }
         SetVibratoWaveform(v) => { let _ = write!(buf, "E4{:X}", v); }
         SetFinetune(v) => { let _ = write!(buf, "E5{:X}", *v as u8 & 0xF); }
         PatternLoop(v) => { let _ = write!(buf, "E6{:X}", v); }
@@ -80,6 +83,7 @@ fn write_effect(buf: &mut String, effect: &mb_ir::Effect) {
         NoteCut(v) => { let _ = write!(buf, "EC{:X}", v); }
         NoteDelay(v) => { let _ = write!(buf, "ED{:X}", v); }
         PatternDelay(v) => { let _ = write!(buf, "EE{:X}", v); }
+        FunkRepeat(v) => { let _ = write!(buf, "EF{:X}", v); }
         SetSpeed(v) => { let _ = write!(buf, "F{:02X}", v); }
         SetTempo(v) => { let _ = write!(buf, "F{:02X}", v); }
         other => {
@@ -113,7 +117,7 @@ mod tests {
     #[test]
     fn empty_cell() {
         let cell = mb_ir::Cell::empty();
-        assert_eq!(format_cell(&cell), "--- .. ...");
+        assert_eq!(format_cell(&cell), "--- .. ... ...");
     }
 
     fn cell(note: mb_ir::Note, instrument: u8, effect: mb_ir::Effect) -> mb_ir::Cell {
@@ -122,22 +126,22 @@ mod tests {
 
     #[test]
     fn note_on_with_instrument_and_effect() {
-        assert_eq!(format_cell(&cell(mb_ir::Note::On(48), 1, mb_ir::Effect::SetVolume(64))), "C-4 01 C40");
+        assert_eq!(format_cell(&cell(mb_ir::Note::On(48), 1, mb_ir::Effect::SetVolume(64))), "C-4 01 C40 ...");
     }
 
     #[test]
     fn note_off() {
-        assert_eq!(format_cell(&cell(mb_ir::Note::Off, 0, mb_ir::Effect::None)), "=== .. ...");
+        assert_eq!(format_cell(&cell(mb_ir::Note::Off, 0, mb_ir::Effect::None)), "=== .. ... ...");
     }
 
     #[test]
     fn volume_slide_up() {
-        assert_eq!(format_cell(&cell(mb_ir::Note::None, 0, mb_ir::Effect::VolumeSlide(3))), "--- .. A30");
+        assert_eq!(format_cell(&cell(mb_ir::Note::None, 0, mb_ir::Effect::VolumeSlide(3))), "--- .. A30 ...");
     }
 
     #[test]
     fn volume_slide_down() {
-        assert_eq!(format_cell(&cell(mb_ir::Note::None, 0, mb_ir::Effect::VolumeSlide(-5))), "--- .. A05");
+        assert_eq!(format_cell(&cell(mb_ir::Note::None, 0, mb_ir::Effect::VolumeSlide(-5))), "--- .. A05 ...");
     }
 
     #[test]
@@ -146,24 +150,30 @@ mod tests {
         let cell1 = cell(mb_ir::Note::On(48), 1, mb_ir::Effect::SetVolume(64));
 
         format_cell_into(&cell1, &mut buf);
-        assert_eq!(buf, "C-4 01 C40");
+        assert_eq!(buf, "C-4 01 C40 ...");
 
         format_cell_into(&mb_ir::Cell::empty(), &mut buf);
-        assert_eq!(buf, "--- .. ...");
+        assert_eq!(buf, "--- .. ... ...");
+    }
+
+    #[test]
+    fn second_effect_column() {
+        let cell = mb_ir::Cell { effect2: mb_ir::Effect::PortaDown(2), ..cell(mb_ir::Note::On(48), 1, mb_ir::Effect::SetVolume(64)) };
+        assert_eq!(format_cell(&cell), "C-4 01 C40 202");
     }
 
     #[test]
     fn arpeggio_format() {
-        assert_eq!(format_cell(&cell(mb_ir::Note::None, 0, mb_ir::Effect::Arpeggio { x: 3, y: 7 })), "--- .. 037");
+        assert_eq!(format_cell(&cell(mb_ir::Note::None, 0, mb_ir::Effect::Arpeggio { x: 3, y: 7 })), "--- .. 037 ...");
     }
 
     #[test]
     fn porta_up_format() {
-        assert_eq!(format_cell(&cell(mb_ir::Note::None, 0, mb_ir::Effect::PortaUp(15))), "--- .. 10F");
+        assert_eq!(format_cell(&cell(mb_ir::Note::None, 0, mb_ir::Effect::PortaUp(15))), "--- .. 10F ...");
     }
 
     #[test]
     fn sample_offset_format() {
-        assert_eq!(format_cell(&cell(mb_ir::Note::On(60), 2, mb_ir::Effect::SampleOffset(128))), "C-5 02 980");
+        assert_eq!(format_cell(&cell(mb_ir::Note::On(60), 2, mb_ir::Effect::SampleOffset(128))), "C-5 02 980 ...");
     }
 }
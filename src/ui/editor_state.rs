@@ -9,10 +9,13 @@ pub enum CellColumn {
     EffectType,
     EffectParam0,
     EffectParam1,
+    Effect2Type,
+    Effect2Param0,
+    Effect2Param1,
 }
 
 /// Total number of CellColumn variants (for left/right wrapping).
-const COLUMN_COUNT: usize = 6;
+const COLUMN_COUNT: usize = 9;
 
 impl CellColumn {
     /// All columns in display order.
@@ -23,6 +26,9 @@ impl CellColumn {
         CellColumn::EffectType,
         CellColumn::EffectParam0,
         CellColumn::EffectParam1,
+        CellColumn::Effect2Type,
+        CellColumn::Effect2Param0,
+        CellColumn::Effect2Param1,
     ];
 
     /// Index of this column in the ALL array.
@@ -34,6 +40,9 @@ impl CellColumn {
             CellColumn::EffectType => 3,
             CellColumn::EffectParam0 => 4,
             CellColumn::EffectParam1 => 5,
+            CellColumn::Effect2Type => 6,
+            CellColumn::Effect2Param0 => 7,
+            CellColumn::Effect2Param1 => 8,
         }
     }
 
@@ -288,6 +297,9 @@ mod tests {
     #[test]
     fn column_move_right_wraps() {
         let (col, wrapped) = CellColumn::EffectParam1.move_right();
+        assert_eq!(col, CellColumn::Effect2Type);
+        assert!(!wrapped);
+        let (col, wrapped) = CellColumn::Effect2Param1.move_right();
         assert_eq!(col, CellColumn::Note);
         assert!(wrapped);
     }
@@ -302,7 +314,7 @@ mod tests {
     #[test]
     fn column_move_left_wraps() {
         let (col, wrapped) = CellColumn::Note.move_left();
-        assert_eq!(col, CellColumn::EffectParam1);
+        assert_eq!(col, CellColumn::Effect2Param1);
         assert!(wrapped);
     }
 
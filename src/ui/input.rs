@@ -19,6 +19,7 @@ pub enum EditorAction {
     ToggleEditMode,
     TogglePlayStop,
     TogglePlayPatternStop,
+    TogglePlayFromCursor,
     SwitchToGraph,
     SwitchToPattern,
     SwitchToSequencer,
@@ -30,6 +31,8 @@ pub enum EditorAction {
     Undo,
     Redo,
     MuteSelectedTrack,
+    ToggleChannelMute,
+    ToggleChannelSolo,
     EnterOnCell,
 }
 
@@ -90,6 +93,9 @@ fn poll_global_shortcuts(
     if is_pressed(ui, imgui::Key::Space) && ctrl {
         actions.push(EditorAction::TogglePlayPatternStop);
     }
+    if is_pressed(ui, imgui::Key::Space) && shift && !ctrl && !cmd {
+        actions.push(EditorAction::TogglePlayFromCursor);
+    }
     if cmd && is_pressed(ui, imgui::Key::G) {
         actions.push(EditorAction::SwitchToGraph);
     }
@@ -134,6 +140,13 @@ fn poll_global_shortcuts(
     if ctrl && is_pressed(ui, imgui::Key::M) {
         actions.push(EditorAction::MuteSelectedTrack);
     }
+    // Mute/solo the cursor channel: F9 / F10
+    if is_pressed(ui, imgui::Key::F9) {
+        actions.push(EditorAction::ToggleChannelMute);
+    }
+    if is_pressed(ui, imgui::Key::F10) {
+        actions.push(EditorAction::ToggleChannelSolo);
+    }
 }
 
 fn poll_navigation(ui: &imgui::Ui, shift: bool, actions: &mut Vec<EditorAction>) {
@@ -10,15 +10,17 @@ mod patterns;
 mod samples;
 mod sequencer;
 mod transport;
-mod undo;
 
 use std::collections::HashMap;
+use std::time::Duration;
 
 use editor_state::{Clipboard, EditorState};
 use input::EditorAction;
-use mb_master::Controller;
+use mb_master::{recovery_snapshots, Controller, Preferences, RecoverySnapshot};
 use sequencer::SeqCellContent;
-use undo::UndoStack;
+
+/// How often unsaved changes are snapshotted for crash recovery.
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
 
 /// Toggle between center panel views.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -43,7 +45,8 @@ pub struct GuiState {
     pub center_view: CenterView,
     pub status: String,
     pub editor: EditorState,
-    pub undo_stack: UndoStack,
+    /// Newest snapshot left behind by a run that crashed, offered for recovery.
+    pub recovery: Option<RecoverySnapshot>,
     // --- Performance caches (invalidated by invalidate_caches) ---
     /// A2: Cached sequencer beat lookups per track.
     pub(crate) seq_lookups: Option<Vec<HashMap<u32, SeqCellContent>>>,
@@ -55,8 +58,14 @@ pub struct GuiState {
 
 impl Default for GuiState {
     fn default() -> Self {
+        let recovery_dir = Preferences::recovery_dir();
+        let recovery = recovery_dir.as_deref()
+            .and_then(|dir| recovery_snapshots(dir).ok())
+            .and_then(|snapshots| snapshots.into_iter().next());
+        let mut controller = Controller::with_preferences(Preferences::load_or_default());
+        controller.set_autosave(recovery_dir, AUTOSAVE_INTERVAL);
         Self {
-            controller: Controller::new(),
+            controller,
             selected_track: 0,
             selected_seq_index: 0,
             seq_cursor_row: 0,
@@ -64,7 +73,7 @@ impl Default for GuiState {
             center_view: CenterView::Pattern,
             status: String::new(),
             editor: EditorState::default(),
-            undo_stack: UndoStack::new(),
+            recovery,
             seq_lookups: None,
             modeline_cache: None,
             cached_clip_info: None,
@@ -94,6 +103,14 @@ fn track_channel_count(gui: &GuiState) -> u8 {
         .unwrap_or(0)
 }
 
+/// Song channel index under the pattern cursor.
+fn cursor_song_channel(gui: &GuiState) -> Option<u8> {
+    let track = gui.controller.song().tracks.get(gui.selected_track)?;
+    let ch = gui.editor.cursor.channel;
+    let idx = track.base_channel.checked_add(ch).filter(|_| ch < track.num_channels)?;
+    ((idx as usize) < gui.controller.song().channels.len()).then_some(idx)
+}
+
 pub fn build_ui(ui: &imgui::Ui, gui: &mut GuiState) {
     let pos = gui.controller.track_position(gui.selected_track);
 
@@ -262,7 +279,7 @@ pub fn process_actions(gui: &mut GuiState, actions: &[EditorAction]) {
             }
             EditorAction::TogglePlayStop => {
                 if gui.controller.is_playing() {
-                    gui.controller.stop();
+                    gui.controller.stop_async();
                     gui.status = "Stopped".to_string();
                 } else {
                     gui.controller.play();
@@ -271,11 +288,22 @@ pub fn process_actions(gui: &mut GuiState, actions: &[EditorAction]) {
             }
             EditorAction::TogglePlayPatternStop => {
                 if gui.controller.is_playing() {
-                    gui.controller.stop();
+                    gui.controller.stop_async();
                     gui.status = "Stopped".to_string();
                 } else if let Some(clip_idx) = selected_clip_idx(gui) {
-                    gui.controller.play_pattern(gui.selected_track, clip_idx as usize);
-                    gui.status = "Playing pattern...".to_string();
+                    gui.controller.loop_pattern(gui.selected_track, clip_idx as usize);
+                    gui.status = "Looping pattern...".to_string();
+                }
+            }
+            EditorAction::TogglePlayFromCursor => {
+                if gui.controller.is_playing() {
+                    gui.controller.stop_async();
+                    gui.status = "Stopped".to_string();
+                } else if let Some(start) = gui.controller.row_time(
+                    gui.selected_track, gui.selected_seq_index, gui.editor.cursor.row,
+                ) {
+                    gui.controller.play_from(start);
+                    gui.status = "Playing...".to_string();
                 }
             }
             EditorAction::SwitchToGraph => gui.center_view = CenterView::Graph,
@@ -288,9 +316,11 @@ pub fn process_actions(gui: &mut GuiState, actions: &[EditorAction]) {
                 gui.editor.step_size = (gui.editor.step_size as i8 + d).clamp(0, 16) as u8;
             }
             EditorAction::EnterNote(note) => {
+                gui.controller.preview_note(gui.editor.selected_instrument, *note);
                 enter_note(gui, *note, max_rows);
             }
             EditorAction::NoteOff => {
+                gui.controller.preview_release();
                 enter_note_off(gui, max_rows);
             }
             EditorAction::DeleteCell => {
@@ -328,6 +358,18 @@ pub fn process_actions(gui: &mut GuiState, actions: &[EditorAction]) {
                 gui.controller.toggle_track_mute(gui.selected_track);
                 gui.invalidate_caches();
             }
+            EditorAction::ToggleChannelMute => {
+                if let Some(ch) = cursor_song_channel(gui) {
+                    let muted = gui.controller.song().channels[ch as usize].muted;
+                    gui.controller.set_channel_muted(ch, !muted);
+                }
+            }
+            EditorAction::ToggleChannelSolo => {
+                if let Some(ch) = cursor_song_channel(gui) {
+                    let solo = gui.controller.song().channels[ch as usize].solo;
+                    gui.controller.set_channel_solo(ch, !solo);
+                }
+            }
             EditorAction::EnterOnCell => {
                 if gui.center_view == CenterView::Sequencer {
                     seq_enter_on_cell(gui);
@@ -404,14 +446,10 @@ fn pattern_bounds(gui: &GuiState) -> (u16, u8) {
     (rows, channels)
 }
 
-/// Apply an edit with undo recording: reads old cell, records undo, applies edit.
-fn apply_edit_with_undo(gui: &mut GuiState, clip_idx: u16, row: u16, channel: u8, cell: mb_ir::Cell) {
+/// Set a cell of the selected track's clip (undoable through the controller).
+fn set_cell(gui: &mut GuiState, clip_idx: u16, row: u16, channel: u8, cell: mb_ir::Cell) {
     let track = gui.selected_track as u16;
-    let old_cell = read_cell(gui, clip_idx, row, channel);
-    let forward = mb_ir::Edit::SetCell { track, clip: clip_idx, row, column: channel, cell };
-    let reverse = mb_ir::Edit::SetCell { track, clip: clip_idx, row, column: channel, cell: old_cell };
-    gui.undo_stack.push(forward.clone(), reverse);
-    gui.controller.apply_edit(forward);
+    gui.controller.apply_edit(mb_ir::Edit::SetCell { track, clip: clip_idx, row, column: channel, cell });
     gui.invalidate_caches();
 }
 
@@ -437,9 +475,10 @@ fn enter_note(gui: &mut GuiState, note: u8, max_rows: u16) {
         instrument: inst,
         volume: old_cell.volume,
         effect: old_cell.effect,
+        effect2: old_cell.effect2,
     };
 
-    apply_edit_with_undo(gui, clip_idx, cursor.row, cursor.channel, cell);
+    set_cell(gui, clip_idx, cursor.row, cursor.channel, cell);
     gui.editor.advance_by_step(max_rows);
 }
 
@@ -453,16 +492,17 @@ fn enter_note_off(gui: &mut GuiState, max_rows: u16) {
         instrument: 0,
         volume: old_cell.volume,
         effect: old_cell.effect,
+        effect2: old_cell.effect2,
     };
 
-    apply_edit_with_undo(gui, clip_idx, cursor.row, cursor.channel, cell);
+    set_cell(gui, clip_idx, cursor.row, cursor.channel, cell);
     gui.editor.advance_by_step(max_rows);
 }
 
 fn delete_cell(gui: &mut GuiState, max_rows: u16) {
     let Some(clip_idx) = selected_clip_idx(gui) else { return };
     let cursor = gui.editor.cursor;
-    apply_edit_with_undo(gui, clip_idx, cursor.row, cursor.channel, mb_ir::Cell::empty());
+    set_cell(gui, clip_idx, cursor.row, cursor.channel, mb_ir::Cell::empty());
     gui.editor.advance_by_step(max_rows);
 }
 
@@ -492,10 +532,20 @@ fn enter_hex_digit(gui: &mut GuiState, digit: u8, max_rows: u16) {
             };
             mb_ir::Cell { effect: parse_effect(new_etype, new_param), ..old_cell }
         }
+        CellColumn::Effect2Type | CellColumn::Effect2Param0 | CellColumn::Effect2Param1 => {
+            let (etype, param) = effect_to_raw(&old_cell.effect2);
+            let (new_etype, new_param) = match cursor.column {
+                CellColumn::Effect2Type => (digit, param),
+                CellColumn::Effect2Param0 => (etype, (digit << 4) | (param & 0x0F)),
+                CellColumn::Effect2Param1 => (etype, (param & 0xF0) | digit),
+                _ => unreachable!(),
+            };
+            mb_ir::Cell { effect2: parse_effect(new_etype, new_param), ..old_cell }
+        }
         CellColumn::Note => return,
     };
 
-    apply_edit_with_undo(gui, clip_idx, cursor.row, cursor.channel, cell);
+    set_cell(gui, clip_idx, cursor.row, cursor.channel, cell);
 
     let (new_col, wrapped) = cursor.column.move_right();
     gui.editor.cursor.column = new_col;
@@ -519,8 +569,7 @@ fn seq_enter_hex_digit(gui: &mut GuiState, digit: u8) {
             let clip_idx = (high << 4) | digit;
             gui.seq_hex_nibble = None;
             let beat = seq_row_to_beat(gui, gui.seq_cursor_row);
-            if let Some((fwd, rev)) = gui.controller.set_seq_entry(gui.selected_track, beat, clip_idx as u16) {
-                gui.undo_stack.push(fwd, rev);
+            if gui.controller.set_seq_entry(gui.selected_track, beat, clip_idx as u16) {
                 gui.invalidate_caches();
                 gui.status = format!("Placed clip {:02X}", clip_idx);
                 // Auto-advance cursor down
@@ -548,8 +597,7 @@ fn seq_enter_on_cell(gui: &mut GuiState) {
 /// Delete the sequence entry at the cursor position.
 fn seq_delete_entry(gui: &mut GuiState) {
     let beat = seq_row_to_beat(gui, gui.seq_cursor_row);
-    if let Some((fwd, rev)) = gui.controller.remove_seq_entry(gui.selected_track, beat) {
-        gui.undo_stack.push(fwd, rev);
+    if gui.controller.remove_seq_entry(gui.selected_track, beat) {
         gui.invalidate_caches();
         gui.status = "Removed seq entry".to_string();
     }
@@ -598,8 +646,7 @@ fn paste_clipboard(gui: &mut GuiState, max_rows: u16, max_channels: u8) {
     let Some(clip_idx) = selected_clip_idx(gui) else { return };
 
     let cursor = gui.editor.cursor;
-    let mut forward_edits = Vec::new();
-    let mut reverse_edits = Vec::new();
+    let mut edits = Vec::new();
 
     for r in 0..clipboard.rows {
         let dest_row = cursor.row + r;
@@ -612,22 +659,14 @@ fn paste_clipboard(gui: &mut GuiState, max_rows: u16, max_channels: u8) {
                 break;
             }
             let new_cell = *clipboard.cell(r, ch);
-            let old_cell = read_cell(gui, clip_idx, dest_row, dest_ch);
-
             let track = gui.selected_track as u16;
-            forward_edits.push(mb_ir::Edit::SetCell {
+            edits.push(mb_ir::Edit::SetCell {
                 track, clip: clip_idx, row: dest_row, column: dest_ch, cell: new_cell,
             });
-            reverse_edits.push(mb_ir::Edit::SetCell {
-                track, clip: clip_idx, row: dest_row, column: dest_ch, cell: old_cell,
-            });
         }
     }
 
-    gui.undo_stack.push_batch(forward_edits.clone(), reverse_edits);
-    for edit in forward_edits {
-        gui.controller.apply_edit(edit);
-    }
+    gui.controller.apply_edits(edits);
     gui.invalidate_caches();
 
     gui.editor.clear_selection();
@@ -642,26 +681,18 @@ fn delete_selection(gui: &mut GuiState) {
     let Some(clip_idx) = selected_clip_idx(gui) else { return };
 
     let (min_row, min_ch, max_row, max_ch) = sel.bounds();
-    let mut forward_edits = Vec::new();
-    let mut reverse_edits = Vec::new();
+    let mut edits = Vec::new();
 
     let track = gui.selected_track as u16;
     for r in min_row..=max_row {
         for ch in min_ch..=max_ch {
-            let old_cell = read_cell(gui, clip_idx, r, ch);
-            forward_edits.push(mb_ir::Edit::SetCell {
+            edits.push(mb_ir::Edit::SetCell {
                 track, clip: clip_idx, row: r, column: ch, cell: mb_ir::Cell::empty(),
             });
-            reverse_edits.push(mb_ir::Edit::SetCell {
-                track, clip: clip_idx, row: r, column: ch, cell: old_cell,
-            });
         }
     }
 
-    gui.undo_stack.push_batch(forward_edits.clone(), reverse_edits);
-    for edit in forward_edits {
-        gui.controller.apply_edit(edit);
-    }
+    gui.controller.apply_edits(edits);
     gui.invalidate_caches();
 
     gui.editor.clear_selection();
@@ -671,30 +702,18 @@ fn delete_selection(gui: &mut GuiState) {
 // --- Undo / Redo ---
 
 fn apply_undo(gui: &mut GuiState) {
-    let edits = match gui.undo_stack.undo() {
-        Some(e) => e.to_vec(),
-        None => {
-            gui.status = "Nothing to undo".to_string();
-            return;
-        }
-    };
-    for edit in edits {
-        gui.controller.apply_edit(edit);
+    if !gui.controller.undo() {
+        gui.status = "Nothing to undo".to_string();
+        return;
     }
     gui.invalidate_caches();
     gui.status = "Undo".to_string();
 }
 
 fn apply_redo(gui: &mut GuiState) {
-    let edits = match gui.undo_stack.redo() {
-        Some(e) => e.to_vec(),
-        None => {
-            gui.status = "Nothing to redo".to_string();
-            return;
-        }
-    };
-    for edit in edits {
-        gui.controller.apply_edit(edit);
+    if !gui.controller.redo() {
+        gui.status = "Nothing to redo".to_string();
+        return;
     }
     gui.invalidate_caches();
     gui.status = "Redo".to_string();
@@ -724,6 +743,7 @@ fn effect_to_raw(effect: &mb_ir::Effect) -> (u8, u8) {
         // E-class effects
         FinePortaUp(v) => (0xE, 0x10 | v),
         FinePortaDown(v) => (0xE, 0x20 | v),
+        SetGlissando(v) => (0xE, 0x30 | v),
         SetVibratoWaveform(v) => (0xE, 0x40 | v),
         SetFinetune(v) => (0xE, 0x50 | (*v as u8 & 0xF)),
         PatternLoop(v) => (0xE, 0x60 | v),
@@ -735,6 +755,7 @@ fn effect_to_raw(effect: &mb_ir::Effect) -> (u8, u8) {
         NoteCut(v) => (0xE, 0xC0 | v),
         NoteDelay(v) => (0xE, 0xD0 | v),
         PatternDelay(v) => (0xE, 0xE0 | v),
+        FunkRepeat(v) => (0xE, 0xF0 | v),
         _ => (0, 0),
     }
 }
@@ -776,6 +797,7 @@ fn parse_e_effect(param: u8) -> mb_ir::Effect {
     match sub {
         0x1 => Effect::FinePortaUp(val),
         0x2 => Effect::FinePortaDown(val),
+        0x3 => Effect::SetGlissando(val),
         0x4 => Effect::SetVibratoWaveform(val),
         0x5 => Effect::SetFinetune(val as i8),
         0x6 => Effect::PatternLoop(val),
@@ -787,6 +809,7 @@ fn parse_e_effect(param: u8) -> mb_ir::Effect {
         0xC => Effect::NoteCut(val),
         0xD => Effect::NoteDelay(val),
         0xE => Effect::PatternDelay(val),
+        0xF => Effect::FunkRepeat(val),
         _ => Effect::None,
     }
 }
@@ -843,6 +866,8 @@ mod tests {
         round_trip(Effect::NoteCut(3));
         round_trip(Effect::NoteDelay(2));
         round_trip(Effect::PatternDelay(4));
+        round_trip(Effect::SetGlissando(1));
+        round_trip(Effect::FunkRepeat(7));
         round_trip(Effect::RetriggerNote(3));
         round_trip(Effect::PatternLoop(2));
         round_trip(Effect::SetPanPosition(8));
@@ -23,8 +23,8 @@ pub fn pattern_editor(
         }
     };
 
-    let clip_idx = match track.sequence.get(gui.selected_seq_index) {
-        Some(e) => e.clip_idx,
+    let (clip_idx, clip_start) = match track.sequence.get(gui.selected_seq_index) {
+        Some(e) => (e.clip_idx, e.start),
         None => {
             ui.text("No clips at this sequence position.");
             return None;
@@ -40,6 +40,7 @@ pub fn pattern_editor(
     };
     let rows = clip.rows;
     let num_channels = clip.channels;
+    let rows_per_beat = clip.rows_per_beat.unwrap_or(song.rows_per_beat).max(1) as u32;
 
     let playing_row = pos
         .filter(|p| p.clip_idx == clip_idx)
@@ -79,10 +80,16 @@ pub fn pattern_editor(
         });
 
         for ch in 0..num_channels {
+            let settings = song.channels.get(track.base_channel as usize + ch as usize);
+            let flag = match settings {
+                Some(c) if c.solo => " S",
+                Some(c) if c.muted => " M",
+                _ => "",
+            };
             ui.table_setup_column_with(imgui::TableColumnSetup {
-                name: format!("Ch {:02}", ch),
+                name: format!("Ch {:02}{}", ch, flag),
                 flags: imgui::TableColumnFlags::WIDTH_FIXED,
-                init_width_or_weight: char_width * 11.0,
+                init_width_or_weight: char_width * 15.0,
                 user_id: imgui::Id::default(),
             });
         }
@@ -117,7 +124,7 @@ pub fn pattern_editor(
                     cursor_screen_y = ui.cursor_screen_pos()[1];
                     ui.set_scroll_here_y_with_ratio(0.85);
                 }
-                render_row(ui, gui, song, clip_idx, rows, num_channels, row, playing_row, char_width, line_height, &mut click_target, &mut cell_buf);
+                render_row(ui, gui, song, clip_idx, (clip_start, rows_per_beat), num_channels, row, playing_row, char_width, line_height, &mut click_target, &mut cell_buf);
             }
         }
 
@@ -138,7 +145,7 @@ fn render_row(
     gui: &GuiState,
     song: &mb_ir::Song,
     clip_idx: u16,
-    _rows: u16,
+    grid: (mb_ir::MusicalTime, u32),
     num_channels: u8,
     row: u16,
     playing_row: Option<u16>,
@@ -149,7 +156,7 @@ fn render_row(
 ) {
     let is_playing = playing_row == Some(row);
     let is_cursor_row = gui.editor.cursor.row == row;
-    let cell_width = char_width * 11.0;
+    let cell_width = char_width * 15.0;
 
     ui.table_next_row();
 
@@ -163,7 +170,7 @@ fn render_row(
     } else if is_cursor_row {
         [0.55, 0.55, 0.75, 1.0]
     } else {
-        row_label_color(row)
+        row_label_color(song, grid, row)
     };
     let _token = ui.push_style_color(imgui::StyleColor::Text, row_color);
     ui.text(format!("{:02X}", row));
@@ -237,7 +244,7 @@ fn draw_cursor(ui: &imgui::Ui, gui: &GuiState, char_width: f32, line_height: f32
 
 /// Get the character offset and width for a CellColumn within a cell.
 ///
-/// Cell format: "C#4 01 3FF" — positions:
+/// Cell format: "C#4 01 3FF 4A8" — positions:
 ///   Note:        chars 0-2 (3 chars)
 ///   space:       char 3
 ///   Instrument0: char 4
@@ -246,6 +253,10 @@ fn draw_cursor(ui: &imgui::Ui, gui: &GuiState, char_width: f32, line_height: f32
 ///   EffectType:  char 7
 ///   EffectParam0: char 8
 ///   EffectParam1: char 9
+///   space:       char 10
+///   Effect2Type:  char 11
+///   Effect2Param0: char 12
+///   Effect2Param1: char 13
 fn column_geometry(column: CellColumn, cw: f32) -> (f32, f32) {
     match column {
         CellColumn::Note => (0.0, cw * 3.0),
@@ -254,6 +265,9 @@ fn column_geometry(column: CellColumn, cw: f32) -> (f32, f32) {
         CellColumn::EffectType => (cw * 7.0, cw),
         CellColumn::EffectParam0 => (cw * 8.0, cw),
         CellColumn::EffectParam1 => (cw * 9.0, cw),
+        CellColumn::Effect2Type => (cw * 11.0, cw),
+        CellColumn::Effect2Param0 => (cw * 12.0, cw),
+        CellColumn::Effect2Param1 => (cw * 13.0, cw),
     }
 }
 
@@ -294,12 +308,15 @@ fn x_to_cell_column(x: f32, cw: f32) -> CellColumn {
         5..=6 => CellColumn::Instrument1,
         7 => CellColumn::EffectType,
         8 => CellColumn::EffectParam0,
-        _ => CellColumn::EffectParam1,
+        9..=10 => CellColumn::EffectParam1,
+        11 => CellColumn::Effect2Type,
+        12 => CellColumn::Effect2Param0,
+        _ => CellColumn::Effect2Param1,
     }
 }
 
 /// Human-readable label for the current cursor column.
-/// For effect columns, shows the effect name from the cell under the cursor.
+/// For effect columns, shows the column's effect name from the cell under the cursor.
 fn cursor_column_label(gui: &GuiState, clip_idx: u16) -> &'static str {
     let cursor = &gui.editor.cursor;
     match cursor.column {
@@ -309,15 +326,22 @@ fn cursor_column_label(gui: &GuiState, clip_idx: u16) -> &'static str {
             let cell = crate::ui::read_cell(gui, clip_idx, cursor.row, cursor.channel);
             cell.effect.name()
         }
+        CellColumn::Effect2Type | CellColumn::Effect2Param0 | CellColumn::Effect2Param1 => {
+            let cell = crate::ui::read_cell(gui, clip_idx, cursor.row, cursor.channel);
+            cell.effect2.name()
+        }
     }
 }
 
-fn row_label_color(row: u16) -> [f32; 4] {
-    if row.is_multiple_of(16) {
+/// Row number color: brightest on bar starts, then beats, following the
+/// song's time signatures from where the clip sits in the sequence.
+fn row_label_color(song: &mb_ir::Song, (start, rows_per_beat): (mb_ir::MusicalTime, u32), row: u16) -> [f32; 4] {
+    let time = start.add_rows(row as u32, rows_per_beat);
+    if time.sub_beat != 0 {
+        [0.24, 0.24, 0.27, 1.0]
+    } else if song.meter.bar_beat(time.beat).1 == 0 {
         [0.39, 0.39, 0.59, 1.0]
-    } else if row.is_multiple_of(4) {
-        [0.31, 0.31, 0.39, 1.0]
     } else {
-        [0.24, 0.24, 0.27, 1.0]
+        [0.31, 0.31, 0.39, 1.0]
     }
 }
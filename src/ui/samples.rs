@@ -1,16 +1,34 @@
 //! Samples browser panel.
 
 use super::GuiState;
+use mb_master::SampleFileFormat;
+
+/// Note clicked instruments are auditioned at (C-4).
+const PREVIEW_NOTE: u8 = 48;
 
 pub fn samples_panel(ui: &imgui::Ui, gui: &mut GuiState) {
     ui.text("Samples");
     ui.separator();
 
-    if ui.button("Load WAV") {
-        load_wav_dialog(gui);
+    if ui.button("Load Sample") {
+        load_sample_dialog(gui);
+    }
+    // Audition the selected sample as it is
+    let sample_idx = gui.editor.selected_instrument.saturating_sub(1);
+    if ui.button("Play") {
+        gui.controller.preview_sample(sample_idx, false);
+    }
+    ui.same_line();
+    if ui.button("Loop") {
+        gui.controller.preview_sample(sample_idx, true);
+    }
+    ui.same_line();
+    if ui.button("Stop") {
+        gui.controller.stop_sample_preview();
     }
     ui.separator();
 
+    let mut clicked = None;
     let samples = &gui.controller.song().samples;
     for (i, sample) in samples.iter().enumerate() {
         if sample.is_empty() {
@@ -26,23 +44,31 @@ pub fn samples_panel(ui: &imgui::Ui, gui: &mut GuiState) {
 
         if ui.selectable_config(&label).selected(selected).build() {
             gui.editor.selected_instrument = inst_num as u8;
+            clicked = Some(inst_num as u8);
         }
     }
+    if let Some(inst_num) = clicked {
+        gui.controller.preview_note(inst_num, PREVIEW_NOTE);
+    }
 }
 
-fn load_wav_dialog(gui: &mut GuiState) {
+fn load_sample_dialog(gui: &mut GuiState) {
     let file = rfd::FileDialog::new()
-        .add_filter("WAV files", &["wav", "WAV"])
+        .add_filter("Sample files", &["wav", "WAV", "aif", "AIF", "aiff", "AIFF", "aifc", "AIFC"])
         .pick_file();
 
     let Some(path) = file else { return };
+    let Some(format) = SampleFileFormat::from_path(&path) else {
+        gui.status = format!("Unknown sample format: {}", path.display());
+        return;
+    };
 
     match std::fs::read(&path) {
         Err(e) => gui.status = format!("Read error: {}", e),
         Ok(data) => {
             let name = path.file_stem().unwrap_or_default().to_string_lossy();
-            match gui.controller.load_wav_sample(&data, &name) {
-                Err(e) => gui.status = format!("WAV error: {:?}", e),
+            match gui.controller.load_sample(&data, &name, format) {
+                Err(e) => gui.status = format!("Sample error: {:?}", e),
                 Ok(inst_num) => {
                     gui.editor.selected_instrument = inst_num;
                     gui.status = format!("Loaded sample {}", name);
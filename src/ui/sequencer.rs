@@ -132,7 +132,7 @@ pub fn sequencer_panel(ui: &imgui::Ui, gui: &mut GuiState) {
             if is_cursor_row {
                 draw_row_bg(ui, ui.calc_text_size("000")[0], SEQ_CURSOR_ROW_BG);
             }
-            let row_color = row_beat_color(beat);
+            let row_color = row_beat_color(&song.meter, beat, beats_per_seq_row);
             let _token = ui.push_style_color(imgui::StyleColor::Text, row_color);
             ui.text(format!("{:03}", beat));
             drop(_token);
@@ -266,12 +266,18 @@ fn draw_selected_col_bg(ui: &imgui::Ui, width: f32) {
     draw_row_bg(ui, width, SELECTED_BG);
 }
 
-fn row_beat_color(beat: u32) -> [f32; 4] {
-    if beat % 16 == 0 {
+/// Highlight rows where a bar starts, brighter every fourth bar.
+fn row_beat_color(meter: &mb_ir::Meter, beat: u32, beats_per_row: u32) -> [f32; 4] {
+    let first_bar = match meter.bar_beat(beat as u64) {
+        (bar, 0) => bar,
+        (bar, _) => bar + 1,
+    };
+    let last_bar = meter.bar_beat((beat + beats_per_row.max(1) - 1) as u64).0;
+    if first_bar > last_bar {
+        EMPTY_COLOR
+    } else if first_bar.div_ceil(4) <= last_bar / 4 {
         [0.39, 0.39, 0.59, 1.0]
-    } else if beat % 4 == 0 {
-        [0.31, 0.31, 0.39, 1.0]
     } else {
-        EMPTY_COLOR
+        [0.31, 0.31, 0.39, 1.0]
     }
 }
@@ -1,11 +1,11 @@
-//! Transport bar: New, Load, Play/Stop, view toggle, song info, playback position.
+//! Transport bar: New, Load, Save, Play/Stop, view toggle, song info, playback position.
 
 use super::CenterView;
 use super::GuiState;
 
 pub fn transport_panel(ui: &imgui::Ui, gui: &mut GuiState) {
     if ui.button("New") {
-        gui.controller.new_song(4);
+        gui.controller.new_song();
         gui.selected_seq_index = 0;
         gui.editor.cursor = Default::default();
         gui.invalidate_caches();
@@ -16,6 +16,14 @@ pub fn transport_panel(ui: &imgui::Ui, gui: &mut GuiState) {
         load_mod_dialog(gui);
     }
     ui.same_line();
+    if ui.button("Save") {
+        save_project_dialog(gui);
+    }
+    ui.same_line();
+    if gui.recovery.is_some() && ui.button("Recover") {
+        recover_snapshot(gui);
+    }
+    ui.same_line();
     ui.separator();
     ui.same_line();
 
@@ -28,9 +36,21 @@ pub fn transport_panel(ui: &imgui::Ui, gui: &mut GuiState) {
         }
     });
     ui.same_line();
+    ui.disabled(!playing, || {
+        if gui.controller.is_paused() {
+            if ui.button("Resume") {
+                gui.controller.resume();
+                gui.status = "Playing...".to_string();
+            }
+        } else if ui.button("Pause") {
+            gui.controller.pause();
+            gui.status = "Paused".to_string();
+        }
+    });
+    ui.same_line();
     ui.disabled(!playing, || {
         if ui.button("Stop") {
-            gui.controller.stop();
+            gui.controller.stop_async();
             gui.status = "Stopped".to_string();
         }
     });
@@ -43,19 +63,32 @@ pub fn transport_panel(ui: &imgui::Ui, gui: &mut GuiState) {
     ui.separator();
     ui.same_line();
 
+    let modified = if gui.controller.is_modified() { "*" } else { "" };
     let song = gui.controller.song();
-    ui.text(song.title);
+    ui.text(format!("{}{}", song.title, modified));
     ui.same_line();
     ui.text(format!(
-        "BPM: {} | Speed: {}",
-        song.initial_tempo, song.initial_speed
+        "BPM: {} | Speed: {} | {} | Mem: {}",
+        mb_ir::Bpm(song.initial_tempo), song.initial_speed, song.meter.initial,
+        mb_ir::ByteSize(song.memory_footprint().total())
     ));
+    if ui.is_item_hovered() {
+        ui.tooltip_text(song.memory_footprint().to_string());
+    }
+    if let Some(bar) = gui.controller.bar_position() {
+        ui.same_line();
+        ui.text(format!("Bar {}", bar));
+    }
 
     if !gui.status.is_empty() {
         ui.same_line();
         ui.text(&gui.status);
     }
 
+    if let Err(e) = gui.controller.autosave() {
+        gui.status = format!("Autosave error: {}", e);
+    }
+
     // Auto-detect when playback finishes naturally
     if gui.controller.is_finished() && gui.status == "Playing..." {
         gui.status = "Finished".to_string();
@@ -64,9 +97,13 @@ pub fn transport_panel(ui: &imgui::Ui, gui: &mut GuiState) {
 
 fn load_mod_dialog(gui: &mut GuiState) {
     let file = rfd::FileDialog::new()
-        .add_filter("Tracker files", &["mod", "MOD", "bmx", "BMX"])
+        .add_filter("Song files", &["mbp", "MBP", "mod", "MOD", "s3m", "S3M", "it", "IT", "bmx", "BMX", "mid", "MID"])
+        .add_filter("Projects", &["mbp", "MBP"])
         .add_filter("MOD files", &["mod", "MOD"])
+        .add_filter("S3M files", &["s3m", "S3M"])
+        .add_filter("IT files", &["it", "IT"])
         .add_filter("BMX files", &["bmx", "BMX"])
+        .add_filter("MIDI files", &["mid", "MID", "midi", "MIDI"])
         .pick_file();
 
     let Some(path) = file else { return };
@@ -82,7 +119,11 @@ fn load_mod_dialog(gui: &mut GuiState) {
         Err(e) => gui.status = format!("Read error: {}", e),
         Ok(data) => {
             let result = match ext.as_str() {
+                "mbp" => gui.controller.load_project(&data),
                 "bmx" => gui.controller.load_bmx(&data),
+                "it" => gui.controller.load_it(&data),
+                "s3m" => gui.controller.load_s3m(&data),
+                "mid" | "midi" => gui.controller.load_midi(&data),
                 _ => gui.controller.load_mod(&data),
             };
             match result {
@@ -99,6 +140,45 @@ fn load_mod_dialog(gui: &mut GuiState) {
     }
 }
 
+fn save_project_dialog(gui: &mut GuiState) {
+    let file = rfd::FileDialog::new()
+        .add_filter("Projects", &["mbp"])
+        .set_file_name("song.mbp")
+        .save_file();
+
+    let Some(path) = file else { return };
+
+    match std::fs::write(&path, gui.controller.save_project()) {
+        Err(e) => gui.status = format!("Write error: {}", e),
+        Ok(()) => {
+            gui.controller.mark_saved();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            gui.status = format!("Saved {}", name);
+        }
+    }
+}
+
+/// Load the snapshot a crashed run left behind, then clear out that run's
+/// leftovers (this run autosaves the recovered song itself).
+fn recover_snapshot(gui: &mut GuiState) {
+    let Some(snapshot) = gui.recovery.take() else { return };
+    match gui.controller.restore_snapshot(&snapshot) {
+        Err(e) => gui.status = format!("Recovery failed: {:?}", e),
+        Ok(()) => {
+            let stale = mb_master::Preferences::recovery_dir()
+                .and_then(|dir| mb_master::recovery_snapshots(&dir).ok())
+                .unwrap_or_default();
+            for old in stale.iter().filter(|s| s.saved <= snapshot.saved) {
+                let _ = old.remove();
+            }
+            gui.selected_track = 0;
+            gui.selected_seq_index = 0;
+            gui.invalidate_caches();
+            gui.status = "Recovered unsaved song".to_string();
+        }
+    }
+}
+
 fn view_toggle_buttons(ui: &imgui::Ui, gui: &mut GuiState) {
     const VIEWS: &[(CenterView, &str)] = &[
         (CenterView::Pattern, "Pattern"),
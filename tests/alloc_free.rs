@@ -13,7 +13,7 @@ use assert_no_alloc::{assert_no_alloc, AllocDisabler};
 #[global_allocator]
 static A: AllocDisabler = AllocDisabler;
 
-use mb_engine::Engine;
+use mb_engine::{Engine, GraphPatch};
 use std::fs;
 use std::path::PathBuf;
 
@@ -72,3 +72,46 @@ fn bmx_acousticelectro_alloc_free() {
     let song = load_bmx("acousticelectro-drumloop-100.bmx");
     assert_render_alloc_free(song, 44100 * 5);
 }
+
+#[test]
+fn parallel_render_alloc_free() {
+    let mut engine = Engine::new(load_bmx("tribal-60.bmx"), 44100);
+    engine.set_render_threads(2);
+    engine.schedule_song();
+    engine.play();
+    let mut block = vec![[0.0f32; 2]; 256];
+
+    assert_no_alloc(|| {
+        for _ in 0..44100 * 2 / 256 {
+            engine.render_block(&mut block);
+        }
+    });
+}
+
+#[test]
+fn graph_patches_swap_in_alloc_free() {
+    let mut song = load_mod("ELYSIUM.MOD");
+    let mut engine = Engine::new(song.clone(), 44100);
+    engine.schedule_song();
+    engine.play();
+
+    let synth = mb_ir::NodeType::Machine { machine_name: "Synth".into(), is_tracker: false };
+    let nodes = song.graph.nodes.len();
+    let edits = [
+        mb_ir::Edit::AddNode { node_type: synth },
+        mb_ir::Edit::Connect { from: nodes as u16, to: 0, gain: 0 },
+        mb_ir::Edit::RemoveNode { node: 1 },
+    ];
+    for edit in edits {
+        let mut patch = GraphPatch::new(&song, &edit, 44100).unwrap();
+        assert_no_alloc(|| {
+            engine.apply_graph_patch(&mut patch);
+            for _ in 0..4410 {
+                engine.render_frame();
+            }
+        });
+        song = engine.song().clone();
+    }
+    assert_eq!(engine.song().graph.nodes.len(), nodes);
+    assert!(engine.song().graph.connection(nodes as u16 - 1, 0).is_some(), "synth renumbered");
+}
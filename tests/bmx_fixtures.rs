@@ -124,15 +124,16 @@ fn tribal_60_renders_without_panic() {
 fn tribal_60_has_correct_pt_tempo() {
     let song = load_fixture("tribal-60.bmx");
     // Buzz BPM 60, speed 1, rpb 8 → PT tempo = 60 * 1 * 8 / 24 = 20
-    assert_eq!(song.initial_tempo, 20, "PT tempo should be 20 for BPM=60, rpb=8");
+    assert_eq!(song.initial_tempo, 20 * 100, "PT tempo should be 20 for BPM=60, rpb=8");
 }
 
 #[test]
 fn acousticelectro_100_has_correct_pt_tempo() {
     let song = load_fixture("acousticelectro-drumloop-100.bmx");
-    // Buzz BPM 100, speed 1, rpb 4 → PT tempo = 100 * 1 * 4 / 24 ≈ 16
-    let expected = (100u32 * 1 * 4) / 24;
-    assert_eq!(song.initial_tempo, expected as u8);
+    // Buzz BPM 100, speed 1, rpb 4 → PT tempo = 100 * 1 * 4 / 24 ≈ 16.66,
+    // in hundredths of a BPM
+    let expected = (100u32 * 1 * 4 * 100) / 24;
+    assert_eq!(song.initial_tempo, expected);
 }
 
 #[test]
@@ -7,7 +7,7 @@
 //!   cargo test --test snapshot_tests
 
 use mb_formats::parse_wav_i16_samples;
-use mb_master::Controller;
+use mb_master::{Controller, WavSpec};
 use std::path::PathBuf;
 use std::{env, fs};
 
@@ -85,7 +85,7 @@ fn snapshot_test(fixture_name: &str) {
     let mut ctrl = Controller::new();
     ctrl.load_mod(&fs::read(fixtures_dir().join(fixture_name)).unwrap())
         .unwrap();
-    let wav = ctrl.render_to_wav(SAMPLE_RATE, MAX_SECONDS);
+    let wav = ctrl.render_to_wav(SAMPLE_RATE, MAX_SECONDS, WavSpec::default());
     assert_snapshot(&snapshot_stem(fixture_name), &wav);
 }
 